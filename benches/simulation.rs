@@ -0,0 +1,59 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use pollster::FutureExt as _;
+use sploosh::{fluid_simulation::FluidSimulationConfig, FluidSimulation, WgpuDevice};
+
+const PARTICLE_CNTS: [usize; 3] = [10_000, 50_000, 100_000];
+const DT: f32 = 1.0 / 120.0;
+
+/// Times every stage of a step on its own, each iteration submits the stage and waits for it.
+fn step_stages(c: &mut Criterion) {
+    let wgpu_device = match WgpuDevice::new_compute_device().block_on() {
+        Ok(wgpu_device) => wgpu_device,
+        Err(err) => {
+            eprintln!("Skipping the simulation benchmarks: {err}");
+            return;
+        }
+    };
+
+    let mut group = c.benchmark_group("step");
+    group.sample_size(20);
+
+    for particle_cnt in PARTICLE_CNTS {
+        let fluid_sim = FluidSimulation::new(
+            FluidSimulationConfig {
+                particle_cnt,
+                ..Default::default()
+            },
+            &wgpu_device,
+        );
+
+        let submit = |stage: &dyn Fn(&mut wgpu::CommandEncoder, &wgpu::Queue)| {
+            let mut encoder = wgpu_device
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            stage(&mut encoder, &wgpu_device.queue);
+            wgpu_device.submit(encoder);
+            wgpu_device.device.poll(wgpu::Maintain::Wait);
+        };
+
+        // settle the particles so the neighborhoods look like a running simulation
+        let step = fluid_sim.step_fn(DT);
+        for _ in 0..50 {
+            submit(&step);
+        }
+
+        for (name, stage) in fluid_sim.step_stages(DT) {
+            group.bench_function(BenchmarkId::new(name, particle_cnt), |b| {
+                b.iter(|| submit(&stage))
+            });
+        }
+        group.bench_function(BenchmarkId::new("full", particle_cnt), |b| {
+            b.iter(|| submit(&step))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, step_stages);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+/* C interface of sploosh, built with `cargo rustc --release --lib --features sploosh-ffi
+ * --crate-type cdylib` (or `staticlib`). Functions returning int return -1 on failure,
+ * sploosh_last_error() then describes the failure. */
+
+#ifndef SPLOOSH_H
+#define SPLOOSH_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SplooshSimulation SplooshSimulation;
+
+/* Default parameters with particle_cnt fluid particles, the boundary particles are added on
+ * top. Earlier builds counted the boundary particles in particle_cnt, callers passing the
+ * total now get that many fluid particles more. two_d runs the simulation in the xy plane.
+ * Null on failure. */
+SplooshSimulation *sploosh_create(uint32_t particle_cnt, bool two_d);
+/* TOML or RON file with the fields of the [simulation] section. Null on failure. */
+SplooshSimulation *sploosh_create_from_file(const char *path);
+void sploosh_destroy(SplooshSimulation *sim);
+
+/* Advances by steps steps of dt seconds and waits for the GPU. */
+int32_t sploosh_step(SplooshSimulation *sim, float dt, uint32_t steps);
+
+/* Including the static boundary particles, which come first. */
+uint32_t sploosh_particle_count(const SplooshSimulation *sim);
+uint32_t sploosh_ghost_particle_count(const SplooshSimulation *sim);
+/* Simulated time in seconds. */
+float sploosh_time(const SplooshSimulation *sim);
+
+/* Positions and velocities as 4 floats per particle, densities as one. Any array may be
+ * null. Returns the number of particles written, at most capacity. */
+int32_t sploosh_read_particles(const SplooshSimulation *sim, float *positions,
+                               float *velocities, float *densities, uint32_t capacity);
+
+/* Last failure on this thread, null if there was none. */
+const char *sploosh_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,260 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{graphics::render_engine::GenericRequest, ComputeTask, SpatialLookup, WgpuDevice};
+
+pub const PARTICLE_SHAPE_SHADER: &str = include_str!("shaders/particle_shape.wgsl");
+
+/// Symmetric transform from the sphere of a particle to its ellipsoid, matches `ParticleShape`
+/// in `PARTICLE_SHAPE_SHADER`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ParticleShape {
+    /// xx, yy and zz
+    pub diagonal: [f32; 4],
+    /// xy, xz and yz
+    pub off_diagonal: [f32; 4],
+}
+
+impl ParticleShape {
+    pub const SPHERE: ParticleShape = ParticleShape {
+        diagonal: [1.0, 1.0, 1.0, 0.0],
+        off_diagonal: [0.0; 4],
+    };
+}
+
+/// Draws the particles as ellipsoids stretched along the spread of their neighbors, after
+/// the anisotropic kernels of Yu and Turk. Surfaces look smoother without meshing them.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnisotropyConfig {
+    /// Largest ratio between the longest and the shortest axis of an ellipsoid
+    pub max_ratio: f32,
+    /// Particles with fewer neighbors, like the spray, stay spheres
+    pub min_neighbors: u32,
+}
+
+impl Default for AnisotropyConfig {
+    fn default() -> Self {
+        Self {
+            max_ratio: 4.0,
+            min_neighbors: 20,
+        }
+    }
+}
+
+impl AnisotropyConfig {
+    /// Longest axis of an ellipsoid relative to the sphere it replaces, the ellipsoids keep
+    /// the volume of the spheres.
+    pub fn max_stretch(&self) -> f32 {
+        self.max_ratio.max(1.0).powf(2.0 / 3.0)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct AnisotropyConstants {
+    max_ratio: f32,
+    min_neighbors: u32,
+}
+
+/// Computes the `ParticleShape` of every fluid particle from the weighted covariance of its
+/// neighbors, the display pass copies them next to the displayed particles.
+pub struct Anisotropy {
+    shape_buffer: Arc<wgpu::Buffer>,
+    display_shape_buffer: Arc<wgpu::Buffer>,
+    shape_task: Arc<ComputeTask>,
+}
+
+impl Anisotropy {
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+    ) -> Self {
+        // the ghost particles are never updated and stay spheres
+        let shape_buffer = wgpu_device.create_buffer_init(
+            &vec![ParticleShape::SPHERE; particle_cnt],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+        let display_shape_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Display shape buffer"),
+            size: (particle_cnt * std::mem::size_of::<ParticleShape>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let workgroup_cnt = ((particle_cnt - ghost_particle_cnt) as u32).div_ceil(256);
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             {}
+             {}
+             {}",
+            spatial_lookup.shader_source(),
+            PARTICLE_SHAPE_SHADER,
+            include_str!("shaders/anisotropy.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let shape_task = Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Particle anisotropy",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, false),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: shape_buffer.as_entire_binding(),
+                },
+            ],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..std::mem::size_of::<AnisotropyConstants>() as u32,
+            }],
+            shader_source.into(),
+            (workgroup_cnt.max(1), 1, 1),
+        ));
+
+        Self {
+            shape_buffer,
+            display_shape_buffer,
+            shape_task,
+        }
+    }
+
+    /// `ParticleShape` of every particle, indexed like the particle buffers.
+    pub fn shapes(&self) -> &Arc<wgpu::Buffer> {
+        &self.shape_buffer
+    }
+
+    /// Shapes of the displayed particles, in the order of the display buffer.
+    pub fn display_shapes(&self) -> &Arc<wgpu::Buffer> {
+        &self.display_shape_buffer
+    }
+
+    pub fn update_fn(&self, config: AnisotropyConfig) -> GenericRequest {
+        let shape_task = self.shape_task.clone();
+        let constants = AnisotropyConstants {
+            max_ratio: config.max_ratio.max(1.0),
+            min_neighbors: config.min_neighbors,
+        };
+
+        Box::new(move |encoder, _| {
+            shape_task.execute(encoder, bytemuck::bytes_of(&constants));
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point4, Vector3};
+    use pollster::FutureExt as _;
+
+    use crate::{
+        spatial_lookup::SpatialGrid,
+        test_utils::{read_buffer, submit},
+    };
+
+    use super::*;
+
+    #[test]
+    fn particles_in_a_sheet_flatten() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+
+        // a single layer of particles in the y = 1 plane
+        let smoothing_radius = 0.5;
+        let spacing = 0.1;
+        let positions: Vec<Point4<f32>> = (0..20)
+            .flat_map(|x| (0..20).map(move |z| (x, z)))
+            .map(|(x, z)| Point4::new(x as f32 * spacing, 1.0, z as f32 * spacing, 1.0))
+            .collect();
+        let particle_cnt = positions.len();
+
+        let position_buffer = wgpu_device.create_buffer_init(
+            &positions,
+            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+        );
+        let spatial_lookup = SpatialLookup::new(
+            &wgpu_device,
+            particle_cnt,
+            smoothing_radius,
+            smoothing_radius,
+            SpatialGrid::Dense {
+                cell_cnt: Vector3::new(5, 5, 5),
+            },
+            &position_buffer,
+        );
+        let anisotropy = Anisotropy::new(
+            &wgpu_device,
+            particle_cnt,
+            0,
+            &spatial_lookup,
+            &position_buffer,
+        );
+
+        let staging_buffer = wgpu_device.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: anisotropy.shapes().size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let config = AnisotropyConfig::default();
+        submit(&wgpu_device, |encoder| {
+            spatial_lookup.update_fn()(encoder, &wgpu_device.queue);
+            anisotropy.update_fn(config)(encoder, &wgpu_device.queue);
+            encoder.copy_buffer_to_buffer(
+                anisotropy.shapes(),
+                0,
+                &staging_buffer,
+                0,
+                staging_buffer.size(),
+            );
+        });
+
+        let shapes = read_buffer::<ParticleShape>(&wgpu_device, &staging_buffer);
+        let center = shapes[10 * 20 + 10];
+        let [xx, yy, zz, _] = center.diagonal;
+
+        // squashed across the sheet to the largest ratio, the volume is kept
+        assert!((xx - zz).abs() < 1e-3);
+        assert!((xx / yy - config.max_ratio).abs() < 1e-2);
+        assert!((xx * yy * zz - 1.0).abs() < 1e-2);
+        assert!(center.off_diagonal.iter().all(|v| v.abs() < 1e-3));
+    }
+}
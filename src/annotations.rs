@@ -0,0 +1,56 @@
+use nalgebra::{Matrix4, Point2, Point3};
+use serde::{Deserialize, Serialize};
+
+/// Text label at a point of the scene, drawn over the main viewport and always facing the
+/// camera.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub position: Point3<f32>,
+    pub text: String,
+    /// sRGB
+    pub color: [u8; 3],
+}
+
+impl Annotation {
+    pub fn new(position: Point3<f32>, text: impl Into<String>) -> Self {
+        Self {
+            position,
+            text: text.into(),
+            color: [255, 255, 255],
+        }
+    }
+}
+
+/// Position of `point` in the viewport seen through `view_proj`, from (0, 0) in the top left
+/// corner to (1, 1) in the bottom right. `None` behind the camera or outside the view.
+pub fn project_to_viewport(view_proj: &Matrix4<f32>, point: &Point3<f32>) -> Option<Point2<f32>> {
+    let clip = view_proj * point.to_homogeneous();
+    if clip.w <= 0.0 {
+        return None;
+    }
+
+    let ndc = clip.xyz() / clip.w;
+    if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || !(0.0..=1.0).contains(&ndc.z) {
+        return None;
+    }
+
+    Some(Point2::new((ndc.x + 1.0) / 2.0, (1.0 - ndc.y) / 2.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::Camera;
+
+    #[test]
+    fn points_behind_the_camera_are_hidden() {
+        let camera = Camera::new();
+        let view_proj = camera.get_projection_matrix(1.0) * camera.get_view_matrix();
+
+        let center = project_to_viewport(&view_proj, &camera.target).unwrap();
+        assert!((center - Point2::new(0.5, 0.5)).norm() < 1e-4);
+
+        let behind = camera.position + (camera.position - camera.target);
+        assert!(project_to_viewport(&view_proj, &behind).is_none());
+    }
+}
@@ -3,34 +3,151 @@ use std::sync::Arc;
 use pollster::FutureExt;
 use winit::{
     application::ApplicationHandler,
+    dpi::PhysicalSize,
     event::{DeviceEvent, MouseScrollDelta, WindowEvent},
-    window::Window,
+    window::{Fullscreen, Icon, Window},
 };
 
-use crate::{input_helper::InputHelper, ApplicationState};
+use crate::{
+    config::{AppConfig, WindowConfig},
+    input_helper::InputHelper,
+    offline_render::OfflineOptions,
+    scene::Scene,
+    settings::{Settings, SETTINGS_PATH},
+    ApplicationState, SplooshError,
+};
 
 pub struct Application {
     window: Option<Arc<Window>>,
     state: Option<ApplicationState>,
     input_helper: InputHelper,
+    config: AppConfig,
+    frame_limit: Option<u64>,
+    frame_cnt: u64,
+    offline_options: Option<OfflineOptions>,
+    scene: Option<Box<dyn Scene>>,
 }
 
 impl Application {
     pub fn new() -> Self {
+        Self::with_config(AppConfig::default())
+    }
+
+    pub fn with_config(config: AppConfig) -> Self {
         Self {
             window: None,
             state: None,
             input_helper: InputHelper::new(),
+            config,
+            frame_limit: None,
+            frame_cnt: 0,
+            offline_options: None,
+            scene: None,
         }
     }
+
+    /// Opens a window and runs the application with the given scene hooked in.
+    pub fn run_scene(scene: impl Scene + 'static) -> Result<(), SplooshError> {
+        Application::new().with_scene(scene).run()
+    }
+
+    pub fn run(mut self) -> Result<(), SplooshError> {
+        let event_loop = winit::event_loop::EventLoop::new()?;
+        event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
+        event_loop.run_app(&mut self)?;
+
+        Ok(())
+    }
+
+    pub fn with_scene(mut self, scene: impl Scene + 'static) -> Self {
+        self.scene = Some(Box::new(scene));
+        self
+    }
+
+    pub fn with_offline_options(mut self, offline_options: Option<OfflineOptions>) -> Self {
+        self.offline_options = offline_options;
+        self
+    }
+
+    pub fn with_frame_limit(mut self, frame_limit: Option<u64>) -> Self {
+        self.frame_limit = frame_limit;
+        self
+    }
+}
+
+impl Default for Application {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ApplicationHandler for Application {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        if let Ok(window) = event_loop.create_window(Window::default_attributes()) {
+        let mut settings = Settings::load(SETTINGS_PATH).unwrap_or_default();
+        if let Some(simulation) = &self.config.simulation {
+            settings.simulation = simulation.clone();
+        }
+
+        let window_config = &self.config.window;
+        let mut window_attributes = Window::default_attributes()
+            .with_title(window_config.title.clone())
+            .with_window_icon(
+                window_icon(window_config)
+                    .map_err(|err| tracing::error!("Failed to load the window icon: {err}"))
+                    .ok(),
+            );
+        if let Some((width, height)) = window_config.size.or(settings.window_size) {
+            window_attributes = window_attributes.with_inner_size(PhysicalSize::new(width, height));
+        }
+        if let Some((width, height)) = window_config.min_size {
+            window_attributes =
+                window_attributes.with_min_inner_size(PhysicalSize::new(width, height));
+        }
+        if window_config.fullscreen.unwrap_or(settings.fullscreen) {
+            window_attributes =
+                window_attributes.with_fullscreen(Some(Fullscreen::Borderless(None)));
+        }
+
+        if let Ok(window) = event_loop.create_window(window_attributes) {
             let window_arc = Arc::new(window);
 
-            self.state = ApplicationState::new(window_arc.clone()).block_on().ok();
+            self.state = ApplicationState::new(
+                window_arc.clone(),
+                &self.config.window,
+                &self.config.adapter,
+                settings,
+            )
+            .block_on()
+            .map_err(|err| tracing::error!("Failed to initialize the renderer: {err}"))
+            .ok();
+
+            if let (Some(state), Some(scene)) = (&mut self.state, self.scene.take()) {
+                state.set_scene(scene);
+            }
+
+            if let (Some(state), Some(animation)) =
+                (&mut self.state, self.config.camera_animation.clone())
+            {
+                state.play_camera_animation(animation);
+            }
+
+            if let Some(state) = &mut self.state {
+                if !self.config.timeline.is_empty() {
+                    state.play_timeline(self.config.timeline.clone());
+                }
+                if let Some(remote) = &self.config.remote {
+                    if let Err(err) = state.start_remote(remote) {
+                        tracing::error!("Failed to start the remote control server: {err}");
+                    }
+                }
+            }
+
+            if let (Some(state), Some(options)) = (&mut self.state, self.offline_options.take()) {
+                if let Err(err) = state.start_offline_render(options) {
+                    tracing::error!("Failed to start offline rendering: {err}");
+                    event_loop.exit();
+                }
+            }
             self.window = Some(window_arc);
         }
     }
@@ -70,12 +187,73 @@ impl ApplicationHandler for Application {
                     } => {
                         self.input_helper.mouse_key_event(&state, button);
                     }
+                    WindowEvent::DroppedFile(path) => {
+                        if let Some(state) = &mut self.state {
+                            state.open_file(&path);
+                        }
+                    }
+                    WindowEvent::Focused(false) => {
+                        self.input_helper.focus_lost();
+                    }
+                    WindowEvent::CursorMoved {
+                        device_id: _,
+                        position,
+                    } => {
+                        self.input_helper
+                            .cursor_moved((position.x as f32, position.y as f32));
+                    }
+                    WindowEvent::Touch(touch) => {
+                        self.input_helper.touch_event(
+                            touch.id,
+                            touch.phase,
+                            (touch.location.x as f32, touch.location.y as f32),
+                        );
+                    }
                     WindowEvent::RedrawRequested => {
+                        if self
+                            .state
+                            .as_ref()
+                            .is_some_and(|state| state.is_device_lost())
+                        {
+                            self.state = self.state.take().and_then(|state| {
+                                tracing::warn!("Recreating the lost device");
+                                state
+                                    .recreate(&self.config.window, &self.config.adapter)
+                                    .block_on()
+                                    .map_err(|err| {
+                                        tracing::error!(
+                                            "Failed to recover from the device loss: {err}"
+                                        );
+                                        event_loop.exit();
+                                    })
+                                    .ok()
+                            });
+                        }
+
                         if let Some(state) = &mut self.state {
-                            state.update(&self.input_helper);
-                            state.redraw();
+                            state.update(&mut self.input_helper);
+                            match state.redraw() {
+                                Ok(()) => {}
+                                // recovered at the start of the next frame
+                                Err(_) if state.is_device_lost() => {}
+                                Err(err) if err.is_recoverable() => {
+                                    tracing::warn!("Skipped a frame: {err}");
+                                }
+                                Err(err) => {
+                                    tracing::error!("Rendering failed: {err}");
+                                    event_loop.exit();
+                                }
+                            }
                         }
                         self.input_helper.reset();
+
+                        self.frame_cnt += 1;
+                        if self
+                            .frame_limit
+                            .is_some_and(|limit| self.frame_cnt >= limit)
+                        {
+                            event_loop.exit();
+                        }
                     }
                     _ => {}
                 }
@@ -94,18 +272,80 @@ impl ApplicationHandler for Application {
                 self.input_helper
                     .mouse_moved((delta.0 as f32, delta.1 as f32));
             }
-            DeviceEvent::MouseWheel { delta } => {
-                if let MouseScrollDelta::LineDelta(_, dy) = delta {
-                    self.input_helper.mouse_wheel_moved(dy);
-                }
+            DeviceEvent::MouseWheel {
+                delta: MouseScrollDelta::LineDelta(_, dy),
+            } => {
+                self.input_helper.mouse_wheel_moved(dy);
             }
             _ => {}
         }
     }
 
+    fn exiting(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        if let Some(state) = &self.state {
+            if let Err(err) = state.settings().save(SETTINGS_PATH) {
+                tracing::error!("Failed to save settings: {err}");
+            }
+        }
+    }
+
     fn about_to_wait(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
         if let Some(window) = self.window.as_ref() {
             window.request_redraw();
         }
     }
 }
+
+/// Loads the configured icon, or draws a water droplet.
+fn window_icon(window_config: &WindowConfig) -> Result<Icon, SplooshError> {
+    let (rgba, width, height) = match &window_config.icon {
+        Some(path) => {
+            let image = image::open(path)?.into_rgba8();
+            let (width, height) = image.dimensions();
+            (image.into_raw(), width, height)
+        }
+        None => (droplet_icon(ICON_SIZE), ICON_SIZE, ICON_SIZE),
+    };
+
+    Icon::from_rgba(rgba, width, height).map_err(|err| SplooshError::Config(err.to_string()))
+}
+
+const ICON_SIZE: u32 = 64;
+
+/// Circle with a pointed top, shaded lighter towards the upper left.
+fn droplet_icon(size: u32) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity((size * size * 4) as usize);
+    let radius = size as f32 * 0.32;
+    let center = (size as f32 / 2.0, size as f32 * 0.62);
+    let tip = size as f32 * 0.06;
+
+    for y in 0..size {
+        for x in 0..size {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let (dx, dy) = (px - center.0, py - center.1);
+            // a cone from the tip to the widest point of the circle gives the droplet shape
+            let circle = (radius * radius - dy * dy).max(0.0).sqrt();
+            let half_width = if py < center.1 {
+                let t = ((py - tip) / (center.1 - tip)).clamp(0.0, 1.0);
+                circle.max(radius * t)
+            } else {
+                circle
+            };
+
+            if py < tip || dx.abs() > half_width {
+                rgba.extend_from_slice(&[0, 0, 0, 0]);
+                continue;
+            }
+
+            let light = 1.0 - ((dx + radius) / (2.0 * radius)).clamp(0.0, 1.0) * 0.5;
+            rgba.extend_from_slice(&[
+                (40.0 * light) as u8,
+                (140.0 * light) as u8,
+                (255.0 * light) as u8,
+                255,
+            ]);
+        }
+    }
+
+    rgba
+}
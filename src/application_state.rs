@@ -1,141 +1,3168 @@
-use std::{cell::RefCell, collections::VecDeque, error::Error, rc::Rc, sync::Arc, time::Instant};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock, Weak},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use egui::Slider;
-use egui_plot::{Line, Plot, PlotPoints};
-use winit::{dpi::PhysicalSize, event::WindowEvent, window::Window};
+use egui_plot::{Bar, BarChart, Line, Plot, PlotPoints};
+use image::RgbaImage;
+use nalgebra::{Point3, Vector3, Vector4};
+use winit::{
+    dpi::PhysicalSize,
+    event::{MouseButton, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+    window::{Fullscreen, Window},
+};
 
 use crate::{
-    fluid_simulation::FluidSimulationConfig,
-    graphics::{Camera, RenderEngine},
-    gui::Egui,
+    annotations::{self, Annotation},
+    camera_animation::{CameraAnimation, CameraKeyframe, CameraKeyframes, Easing},
+    camera_controller::{CameraMode, OrbitState},
+    clip_recorder::ClipRecorder,
+    colormap::{ColorRange, Colormap},
+    config::{AdapterConfig, WindowConfig},
+    console::Console,
+    debris::MAX_DEBRIS,
+    density_filter::RenormalizationMethod,
+    density_slice::SliceAxis,
+    diagnostics::{DiagnosticsSummary, Histogram},
+    dye::DyeConfig,
+    edit_history::EditHistory,
+    fluid_simulation::{
+        DomainBoundary, FluidPreset, FluidSimulationConfig, Integrator, Material,
+        ParticleColorMode, ParticleSnapshot, SimDim,
+    },
+    graphics::{
+        background::Background,
+        camera::Projection,
+        materials::{ColoredVertex, MaterialType},
+        post_process::Tonemapping,
+        render_engine::{FrameStage, RenderRequest, Viewport, ViewportRect},
+        scene_objects::ObjectHandle,
+        Camera, RenderEngine,
+    },
+    gui::{DockLayout, Egui, GuiPanel},
     input_helper::InputHelper,
-    CameraController, FluidSimulation, WgpuRenderDevice,
+    input_map::{Action, InputMap},
+    kernel_registry::Kernel,
+    offline_render::{OfflineOptions, OfflineRenderer},
+    particle_inspector::ParticleSample,
+    particle_trails::{MAX_TRAILS, MAX_TRAIL_LENGTH},
+    playback::{PlaybackConfig, PlaybackHistory},
+    probes::{export_probes, ProbeConfig, ProbeHistory, ProbeQuantity, ProbeShape, MAX_PROBES},
+    project::{Project, PROJECT_EXTENSION, PROJECT_VERSION},
+    remote::{RemoteCommand, RemoteConfig, RemoteServer, Telemetry},
+    resource_registry::ResourceKind,
+    scene::{Scene, SceneContext},
+    settings::Settings,
+    simulation_worker::SimulationWorker,
+    soft_body::SoftBodyConfig,
+    timeline::{Timeline, TimelineAction, TimelinePlayer},
+    velocity_lines::MAX_STREAMLINE_STEPS,
+    wave_gauges::{export_wave_gauges, WaveGaugeConfig, WaveGaugeHistory, MAX_WAVE_GAUGES},
+    CameraController, FluidSimulation, SplooshError, WgpuRenderDevice,
 };
 
+const SCREENSHOT_DIR: &str = "screenshots";
+const PARTICLE_TRAIL_LENGTH: usize = 200;
+/// How often the particle state is copied to the CPU to survive a device loss.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5);
+/// Simulated time of a step triggered by hand while paused.
+const MANUAL_STEP_DT: f32 = 1.0 / 60.0;
+const TITLE_UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+
+/// Arrangement of the main camera and the fixed orthographic views next to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ViewLayout {
+    Single,
+    /// Top view on the right half
+    SideBySide,
+    /// Front view and top view stacked on the right half
+    ThreeViews,
+}
+
+impl ViewLayout {
+    const ALL: [ViewLayout; 3] = [
+        ViewLayout::Single,
+        ViewLayout::SideBySide,
+        ViewLayout::ThreeViews,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ViewLayout::Single => "Single",
+            ViewLayout::SideBySide => "Side by side",
+            ViewLayout::ThreeViews => "Three views",
+        }
+    }
+}
+
+struct PlayingAnimation {
+    animation: CameraAnimation,
+    start: OrbitState,
+    time: f32,
+}
+
+/// Kernel open in the kernel editor, the source isn't used until it's compiled.
+struct KernelEdit {
+    kernel: Weak<Kernel>,
+    source: String,
+    error: Option<String>,
+}
+
 pub struct ApplicationState {
     window: Arc<Window>,
-    render_device: Rc<RefCell<WgpuRenderDevice>>,
+    title: String,
+    last_title_update: Instant,
+    /// Saved instead of the fullscreen size
+    windowed_size: Option<(u32, u32)>,
+    render_device: Arc<RwLock<WgpuRenderDevice>>,
     render_engine: RenderEngine,
+    /// Drawn while the domain has walls, until it's hidden in the scene panel
+    bbox_object: Option<ObjectHandle>,
     gui: Egui,
+    gui_layout: DockLayout,
+    /// Where the embedded viewport was drawn in the last frame, in points
+    viewport_rect: Option<egui::Rect>,
+    viewport_hovered: bool,
+    /// Gui texture of the minimap and the view it was registered with, the view changes when
+    /// the simulation is rebuilt
+    minimap_texture: Option<(egui::TextureId, wgpu::Id<wgpu::TextureView>)>,
+    /// Same for the density heatmap
+    screen_density_texture: Option<(egui::TextureId, wgpu::Id<wgpu::TextureView>)>,
+    input_map: InputMap,
+    /// Action whose key is replaced by the next key press
+    rebinding: Option<Action>,
     camera: Camera,
     camera_controller: CameraController,
+    camera_keyframes: CameraKeyframes,
+    /// Simulation configs before the edits made in the gui
+    edit_history: EditHistory<FluidSimulationConfig>,
+    keyframe_easing: Easing,
+    turntable_speed: f32,
+    camera_animation: Option<PlayingAnimation>,
+    view_layout: ViewLayout,
+    /// Labels placed by the user, drawn with the emitters and the selected particle
+    annotations: Vec<Annotation>,
+    show_annotations: bool,
 
     fluid_sim: FluidSimulation,
+    simulation_worker: Option<SimulationWorker>,
     frame_times: VecDeque<f32>,
+    /// Empty without timestamp queries
+    gpu_frame_times: VecDeque<f32>,
+    fps: f32,
+    start_time: Instant,
+    running_time: f32,
+    neighbor_histogram: Vec<u32>,
+    diagnostics: Option<DiagnosticsSummary>,
+    probe_history: ProbeHistory,
+    probe_quantity: ProbeQuantity,
+    wave_gauge_history: WaveGaugeHistory,
+    playback_history: PlaybackHistory,
+    /// Frame of the playback history shown while scrubbing through it, the later frames are
+    /// dropped once the simulation continues
+    playback_cursor: Option<usize>,
+    /// Edited in the gui, becomes the simulation material once a slider is released
+    material: Material,
+    pick_pending: bool,
+    selected_sample: Option<ParticleSample>,
+    particle_trail: VecDeque<Point3<f32>>,
+    show_particle_trail: bool,
+    particle_snapshot: Option<ParticleSnapshot>,
+    last_snapshot: Instant,
 
     simulation_paused: bool,
     particle_display_size: f32,
+    environment_path: String,
     prev_time: Instant,
+
+    offline_renderer: Option<OfflineRenderer>,
+    clip_recorder: ClipRecorder,
+
+    scene: Option<Box<dyn Scene>>,
+    timeline: Option<TimelinePlayer>,
+    /// Config from before the first timeline event, a reset starts from it again
+    timeline_start_config: Option<FluidSimulationConfig>,
+    remote: Option<RemoteServer>,
+    /// Message shown at the top of the window since the given time
+    toast: Option<(String, Instant)>,
+    kernel_edit: Option<KernelEdit>,
+    telemetry_interval: Duration,
+    last_telemetry: Instant,
 }
 
 impl ApplicationState {
-    pub async fn new(window: Arc<Window>) -> Result<Self, Box<dyn Error>> {
-        let render_device = Rc::new(RefCell::new(WgpuRenderDevice::new(window.clone()).await?));
-        let render_engine = RenderEngine::new(render_device.clone());
-
-        let config = FluidSimulationConfig {
-            particle_cnt: 100_000,
-            smoothing_radius: 0.15,
-            mass: 0.12,
-            damping: -0.7,
-            gas_const: 350.0,
-            rest_density: 200.0,
-            viscosity: 1.15,
-            gravity: nalgebra::Vector3::new(0.0, -1.0, 0.0),
-            bbox_dimensions: nalgebra::Vector3::new(14.0, 6.0, 4.0),
-        };
-
-        let fluid_sim =
-            FluidSimulation::new(config, &render_engine, &render_device.borrow().wgpu_device);
+    pub async fn new(
+        window: Arc<Window>,
+        window_config: &WindowConfig,
+        adapter_config: &AdapterConfig,
+        settings: Settings,
+    ) -> Result<Self, SplooshError> {
+        let render_device = Arc::new(RwLock::new(
+            WgpuRenderDevice::new(window.clone(), window_config, adapter_config).await?,
+        ));
+        let mut render_engine = RenderEngine::new(render_device.clone());
+        render_engine.set_post_process_settings(settings.post_process);
+        let environment_path = match &settings.render.background {
+            Background::Environment { path } => path.display().to_string(),
+            _ => String::new(),
+        };
+        if let Err(err) = render_engine.set_render_settings(settings.render) {
+            tracing::error!("Failed to load the background: {err}");
+        }
+
+        let limits = render_device.read().unwrap().wgpu_device.device.limits();
+        settings.simulation.max_particle_cnt(&limits)?;
+        let fluid_sim = FluidSimulation::new(
+            settings.simulation,
+            &render_device.read().unwrap().wgpu_device,
+        );
+        let bbox_object = ApplicationState::add_bbox_object(&mut render_engine, &fluid_sim);
         let gui = Egui::new(&window);
+        let windowed_size = match window.fullscreen() {
+            Some(_) => settings.window_size,
+            None => Some(window.inner_size().into()),
+        };
+
+        let mut camera = Camera::new();
+        let mut camera_controller = CameraController::from_orbit_state(settings.camera);
+        if fluid_sim.config().dimensions == SimDim::Two {
+            // look straight at the particle plane
+            camera.projection = Projection::Orthographic;
+            camera_controller.set_orbit_state(OrbitState {
+                phi: std::f32::consts::FRAC_PI_2,
+                theta: std::f32::consts::FRAC_PI_2,
+                ..OrbitState::default()
+            });
+            camera_controller.frame_bbox(
+                nalgebra::Point3::origin(),
+                fluid_sim.bbox_dimensions(),
+                &camera,
+            );
+        }
 
+        let material = fluid_sim.config().material;
         Ok(Self {
             window,
+            title: window_config.title.clone(),
+            last_title_update: Instant::now(),
+            windowed_size,
             render_device,
             render_engine,
+            bbox_object,
             gui,
-            camera: Camera::new(),
-            camera_controller: CameraController::new(),
+            gui_layout: settings.gui_layout,
+            viewport_rect: None,
+            viewport_hovered: false,
+            minimap_texture: None,
+            screen_density_texture: None,
+            input_map: settings.input_map,
+            rebinding: None,
+            camera,
+            camera_controller,
+            camera_keyframes: CameraKeyframes::default(),
+            keyframe_easing: Easing::default(),
+            edit_history: EditHistory::new(),
+            turntable_speed: 0.5,
+            camera_animation: None,
+            view_layout: ViewLayout::Single,
+            annotations: Vec::new(),
+            show_annotations: true,
             fluid_sim,
+            simulation_worker: None,
             frame_times: VecDeque::new(),
+            gpu_frame_times: VecDeque::new(),
+            fps: 0.0,
+            start_time: Instant::now(),
+            running_time: 0.0,
+            neighbor_histogram: Vec::new(),
+            diagnostics: None,
+            probe_history: ProbeHistory::default(),
+            probe_quantity: ProbeQuantity::Density,
+            wave_gauge_history: WaveGaugeHistory::default(),
+            playback_history: PlaybackHistory::default(),
+            playback_cursor: None,
+            material,
+            pick_pending: false,
+            selected_sample: None,
+            particle_trail: VecDeque::new(),
+            show_particle_trail: true,
+            particle_snapshot: None,
+            last_snapshot: Instant::now(),
 
             simulation_paused: true,
             particle_display_size: 0.01,
+            environment_path,
             prev_time: Instant::now(),
+
+            offline_renderer: None,
+            clip_recorder: ClipRecorder::new(15, 640),
+
+            scene: None,
+            timeline: None,
+            timeline_start_config: None,
+            remote: None,
+            toast: None,
+            kernel_edit: None,
+            telemetry_interval: Duration::ZERO,
+            last_telemetry: Instant::now(),
         })
     }
 
     pub fn on_window_event(&mut self, event: &WindowEvent) {
-        self.gui.handle_input(&self.window, &event);
+        self.gui.handle_input(&self.window, event);
+    }
+
+    pub fn settings(&self) -> Settings {
+        Settings {
+            version: PROJECT_VERSION,
+            window_size: self.windowed_size,
+            fullscreen: self.window.fullscreen().is_some(),
+            camera: self.camera_controller.orbit_state(),
+            gui_layout: self.gui_layout.clone(),
+            simulation: self.fluid_sim.config().clone(),
+            post_process: self.render_engine.post_process_settings(),
+            render: self.render_engine.render_settings().clone(),
+            input_map: self.input_map.clone(),
+        }
+    }
+
+    /// The authored scene. A timeline that already ran is saved with the config from before
+    /// its first event.
+    pub fn project(&self) -> Project {
+        Project {
+            version: PROJECT_VERSION,
+            simulation: self
+                .timeline_start_config
+                .clone()
+                .unwrap_or_else(|| self.fluid_sim.config().clone()),
+            camera: self.camera_controller.orbit_state(),
+            camera_keyframes: self.camera_keyframes.clone(),
+            timeline: self
+                .timeline
+                .as_ref()
+                .map(|player| player.timeline().clone())
+                .unwrap_or_default(),
+            post_process: self.render_engine.post_process_settings(),
+            render: self.render_engine.render_settings().clone(),
+            annotations: self.annotations.clone(),
+        }
+    }
+
+    /// Replaces the authored scene, the simulation and the timeline start over.
+    pub fn open_project(&mut self, project: Project) -> Result<(), SplooshError> {
+        self.render_engine.set_render_settings(project.render)?;
+        self.render_engine
+            .set_post_process_settings(project.post_process);
+
+        self.camera_animation = None;
+        self.camera_controller.set_orbit_state(project.camera);
+        self.camera_keyframes = project.camera_keyframes;
+        self.annotations = project.annotations;
+
+        self.timeline = None;
+        self.timeline_start_config = None;
+        self.rebuild_simulation(project.simulation);
+        if !project.timeline.is_empty() {
+            self.play_timeline(project.timeline);
+        }
+
+        Ok(())
+    }
+
+    /// Opens a project, or a simulation config in TOML or RON, and confirms it with a toast.
+    /// Used for files dropped onto the window.
+    pub fn open_file(&mut self, path: &Path) {
+        let opened = match path.extension().and_then(|ext| ext.to_str()) {
+            Some(PROJECT_EXTENSION) => {
+                Project::load(path).and_then(|project| self.open_project(project))
+            }
+            Some("toml" | "ron") => {
+                FluidSimulationConfig::from_file(path).map(|config| self.rebuild_simulation(config))
+            }
+            _ => Err(SplooshError::Config(
+                "Only projects and simulation configs can be opened".to_string(),
+            )),
+        };
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match opened {
+            Ok(()) => self.show_toast(format!("Opened {name}")),
+            Err(err) => {
+                tracing::error!("Failed to open {}: {err}", path.display());
+                self.show_toast(format!("Failed to open {name}: {err}"));
+            }
+        }
+    }
+
+    fn show_toast(&mut self, message: String) {
+        self.toast = Some((message, Instant::now()));
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.render_device.read().unwrap().wgpu_device.is_lost()
+    }
+
+    /// Builds the state again on a new device after the old one was lost. The particles continue
+    /// from the last snapshot, or from the initial layout if none was taken yet.
+    pub async fn recreate(
+        mut self,
+        window_config: &WindowConfig,
+        adapter_config: &AdapterConfig,
+    ) -> Result<Self, SplooshError> {
+        let window = self.window.clone();
+        let settings = self.settings();
+        let snapshot = self.particle_snapshot.take();
+        let scene = self.scene.take();
+        let timeline = self.timeline.take();
+        let timeline_start_config = self.timeline_start_config.take();
+        let remote = self.remote.take();
+        let telemetry_interval = self.telemetry_interval;
+        let offline_renderer = self.offline_renderer.take();
+        let simulation_paused = self.simulation_paused;
+        // everything on the old device has to be gone before the new one is created
+        drop(self);
+
+        let mut state =
+            ApplicationState::new(window, window_config, adapter_config, settings).await?;
+        state.simulation_paused = simulation_paused;
+
+        match &snapshot {
+            Some(snapshot) => {
+                state
+                    .fluid_sim
+                    .restore_snapshot(state.render_device.read().unwrap().queue(), snapshot)?;
+                tracing::info!(
+                    "Restored the simulation from the snapshot at {:.2} s",
+                    snapshot.time
+                );
+            }
+            None => tracing::info!("No snapshot was taken yet, the simulation restarts"),
+        }
+        state.particle_snapshot = snapshot;
+
+        if let Some(scene) = scene {
+            state.set_scene(scene);
+        }
+        state.timeline = timeline;
+        state.timeline_start_config = timeline_start_config;
+        state.remote = remote;
+        state.telemetry_interval = telemetry_interval;
+        if let Some(offline_renderer) = offline_renderer {
+            let options = offline_renderer.options();
+            state
+                .render_engine
+                .set_offscreen_target(options.width, options.height);
+            state.offline_renderer = Some(offline_renderer);
+        }
+
+        Ok(state)
+    }
+
+    pub fn set_scene(&mut self, mut scene: Box<dyn Scene>) {
+        scene.setup(&mut SceneContext {
+            render_device: &self.render_device,
+            render_engine: &mut self.render_engine,
+            fluid_sim: &mut self.fluid_sim,
+            camera: &mut self.camera,
+            camera_controller: &mut self.camera_controller,
+            input_helper: None,
+        });
+        self.scene = Some(scene);
+    }
+
+    pub fn start_offline_render(&mut self, options: OfflineOptions) -> Result<(), SplooshError> {
+        self.render_engine
+            .set_offscreen_target(options.width, options.height);
+        self.offline_renderer = Some(OfflineRenderer::new(options)?);
+        self.simulation_paused = false;
+
+        Ok(())
+    }
+
+    /// Starts driving the camera from the animation. Time advances with the frame timestep, so
+    /// offline renders with a fixed timestep always produce the same camera path.
+    pub fn play_camera_animation(&mut self, animation: CameraAnimation) {
+        self.camera_animation = Some(PlayingAnimation {
+            animation,
+            start: self.camera_controller.orbit_state(),
+            time: 0.0,
+        });
+    }
+
+    pub fn stop_camera_animation(&mut self) {
+        self.camera_animation = None;
+    }
+
+    /// Listens for remote control clients, which are sent telemetry every
+    /// `telemetry_interval` seconds.
+    pub fn start_remote(&mut self, config: &RemoteConfig) -> Result<(), SplooshError> {
+        let remote = RemoteServer::start(config)?;
+        tracing::info!("Remote control listening on {}", remote.address());
+        self.remote = Some(remote);
+        self.telemetry_interval = Duration::from_secs_f32(config.telemetry_interval.max(0.0));
+
+        Ok(())
+    }
+
+    fn update_remote(&mut self) {
+        let Some(remote) = &self.remote else {
+            return;
+        };
+        let requests = remote.poll();
+        let send_telemetry =
+            remote.client_cnt() > 0 && self.last_telemetry.elapsed() >= self.telemetry_interval;
+
+        for request in requests {
+            let result = match &request.command {
+                RemoteCommand::Pause | RemoteCommand::Resume => {
+                    let pause = request.command == RemoteCommand::Pause;
+                    if self.simulation_paused != pause {
+                        self.toggle_pause();
+                    }
+                    Ok(())
+                }
+                RemoteCommand::Step => {
+                    self.step_simulation();
+                    Ok(())
+                }
+                RemoteCommand::Reset => {
+                    self.reset_simulation();
+                    Ok(())
+                }
+                RemoteCommand::Set { parameters } => {
+                    RemoteCommand::apply_parameters(self.fluid_sim.config(), parameters)
+                        .map(|config| self.rebuild_keeping_particles(config))
+                }
+            };
+            request.respond(result);
+        }
+
+        if send_telemetry {
+            self.last_telemetry = Instant::now();
+            let render_device = self.render_device.read().unwrap();
+            let telemetry = self
+                .fluid_sim
+                .read_snapshot(&render_device.wgpu_device)
+                .map(|snapshot| Telemetry {
+                    fps: self.fps,
+                    paused: self.simulation_paused,
+                    ..Telemetry::from_snapshot(
+                        &snapshot,
+                        self.fluid_sim.config(),
+                        self.fluid_sim.ghost_particle_cnt(),
+                    )
+                });
+            let sent = telemetry.and_then(|telemetry| {
+                self.remote
+                    .as_ref()
+                    .map_or(Ok(()), |remote| remote.broadcast(&telemetry))
+            });
+            if let Err(err) = sent {
+                tracing::error!("Failed to send telemetry: {err}");
+            }
+        }
+    }
+
+    /// Runs the events of the timeline as the simulation time passes them.
+    /// Adds a label that stays until it's removed in the scene panel or
+    /// another project is opened.
+    pub fn add_annotation(&mut self, annotation: Annotation) {
+        self.annotations.push(annotation);
+    }
+
+    pub fn clear_annotations(&mut self) {
+        self.annotations.clear();
+    }
+
+    pub fn play_timeline(&mut self, timeline: Timeline) {
+        self.timeline = Some(TimelinePlayer::new(timeline));
+        self.timeline_start_config = None;
+    }
+
+    fn update_timeline(&mut self) {
+        let actions = match &mut self.timeline {
+            Some(player) => player.advance(self.fluid_sim.sim_time()),
+            None => return,
+        };
+        if !actions.is_empty() {
+            self.timeline_start_config
+                .get_or_insert_with(|| self.fluid_sim.config().clone());
+        }
+
+        for action in actions {
+            self.run_timeline_action(action);
+        }
+    }
+
+    fn run_timeline_action(&mut self, action: TimelineAction) {
+        let config = self.fluid_sim.config().clone();
+        match action {
+            TimelineAction::DyeEmitter { emitter } => {
+                let dye = match emitter {
+                    Some(emitter) => Some(DyeConfig {
+                        emitter: Some(emitter),
+                        ..config.dye.unwrap_or_default()
+                    }),
+                    None => config.dye.map(|dye| DyeConfig {
+                        emitter: None,
+                        ..dye
+                    }),
+                };
+                self.fluid_sim.set_dye(dye);
+            }
+            TimelineAction::Debris { debris } => self.fluid_sim.set_debris(debris),
+            TimelineAction::Gravity { gravity } => {
+                self.rebuild_keeping_particles(FluidSimulationConfig { gravity, ..config });
+            }
+            TimelineAction::MoveObstacle { center } => {
+                let soft_body = SoftBodyConfig {
+                    center,
+                    ..config.soft_body.unwrap_or_default()
+                };
+                self.rebuild_keeping_particles(FluidSimulationConfig {
+                    soft_body: Some(soft_body),
+                    ..config
+                });
+            }
+            TimelineAction::Record { duration } => {
+                if !self.clip_recorder.is_recording() && !self.clip_recorder.is_encoding() {
+                    self.clip_recorder
+                        .start(Duration::from_secs_f32(duration.max(0.0)));
+                }
+            }
+            TimelineAction::Pause => {
+                if !self.simulation_paused {
+                    self.toggle_pause();
+                }
+            }
+        }
+    }
+
+    fn update_camera_animation(&mut self, dt: f32) {
+        let Some(playing) = &mut self.camera_animation else {
+            return;
+        };
+
+        playing.time += dt;
+        let orbit = playing.animation.sample(playing.time, &playing.start);
+        self.camera_controller.set_orbit_state(orbit);
+
+        // the last offline frames hold the final keyframe instead of stopping early
+        if self.offline_renderer.is_none()
+            && playing
+                .animation
+                .duration()
+                .is_some_and(|duration| playing.time >= duration)
+        {
+            self.camera_animation = None;
+        }
     }
 
     pub fn resize(&mut self, size: PhysicalSize<u32>) {
-        self.render_device.borrow_mut().resize(size);
+        if self.window.fullscreen().is_none() {
+            self.windowed_size = Some(size.into());
+        }
+        self.render_device.write().unwrap().resize(size);
     }
 
-    pub fn update(&mut self, input_helper: &InputHelper) {
+    pub fn update(&mut self, input_helper: &mut InputHelper) {
+        let _span = tracing::debug_span!("update").entered();
+        // the camera, particle picking, actions and the scene only see input the gui didn't take
+        let mut gui_focus = self.gui.input_focus();
+        // the embedded viewport belongs to the gui, but the pointer over it controls the scene
+        if self.viewport_hovered {
+            gui_focus.pointer = false;
+        }
+        input_helper.set_gui_focus(gui_focus);
+        let input_helper = &*input_helper;
+
         let time = Instant::now();
-        let dt = (time - self.prev_time).as_secs_f32();
+        let dt = match &self.offline_renderer {
+            Some(offline_renderer) => offline_renderer.options().dt,
+            None => (time - self.prev_time).as_secs_f32(),
+        };
         self.prev_time = time;
 
+        if dt > 0.0 {
+            // smoothed so the overlay stays readable
+            self.fps += 0.1 * (1.0 / dt - self.fps);
+        }
+        if !self.simulation_paused {
+            self.running_time += dt;
+        }
+
+        // while rebinding, keys don't trigger actions
+        let triggered =
+            |action| self.rebinding.is_none() && self.input_map.is_triggered(input_helper, action);
+        let toggle_camera = triggered(Action::ToggleCamera);
+        let frame_bounds = triggered(Action::FrameBounds);
+        let pause = triggered(Action::Pause);
+        let reset = triggered(Action::Reset);
+        let step = triggered(Action::Step);
+        let screenshot = triggered(Action::Screenshot);
+        let fullscreen = triggered(Action::Fullscreen);
+        let ctrl = input_helper.is_key_held(PhysicalKey::Code(KeyCode::ControlLeft))
+            || input_helper.is_key_held(PhysicalKey::Code(KeyCode::ControlRight));
+        let undo = ctrl && triggered(Action::Undo);
+        let redo = ctrl && triggered(Action::Redo);
+
+        if let Some(action) = self.rebinding {
+            if let Some(PhysicalKey::Code(key)) = input_helper.just_pressed_keys().next() {
+                if key != KeyCode::Escape {
+                    self.input_map.bind(action, key);
+                }
+                self.rebinding = None;
+            }
+        }
+
+        if toggle_camera {
+            self.camera_controller.toggle_mode(&self.camera);
+        }
+
+        if frame_bounds {
+            self.frame_bbox();
+        }
+
+        self.update_camera_animation(dt);
         self.camera_controller
-            .update_camera(input_helper, &mut self.camera);
+            .update_camera(input_helper, &mut self.camera, dt);
+        self.update_viewports();
+
+        if pause {
+            self.toggle_pause();
+        }
+
+        if reset {
+            self.reset_simulation();
+        }
 
-        if input_helper.is_key_pressed(winit::keyboard::PhysicalKey::Code(
-            winit::keyboard::KeyCode::Space,
-        )) {
-            if self.simulation_paused {
-                self.prev_time = Instant::now();
+        if step {
+            self.step_simulation();
+        }
+
+        if screenshot {
+            self.render_engine.request_screenshot();
+        }
+
+        if fullscreen {
+            self.toggle_fullscreen();
+        }
+
+        if undo {
+            if let Some(config) = self.edit_history.undo(self.fluid_sim.config()) {
+                self.apply_config(config);
+            }
+        }
+
+        if redo {
+            if let Some(config) = self.edit_history.redo(self.fluid_sim.config()) {
+                self.apply_config(config);
+            }
+        }
+
+        if self.last_title_update.elapsed() >= TITLE_UPDATE_INTERVAL {
+            self.window
+                .set_title(&format!("{} - {:.0} FPS", self.title, self.fps));
+            self.last_title_update = Instant::now();
+        }
+
+        if input_helper.is_mouse_button_clicked(MouseButton::Left) {
+            self.pick_particle(input_helper);
+        }
+        self.paint_dye(input_helper);
+
+        if let Some(scene) = &mut self.scene {
+            scene.update(
+                &mut SceneContext {
+                    render_device: &self.render_device,
+                    render_engine: &mut self.render_engine,
+                    fluid_sim: &mut self.fluid_sim,
+                    camera: &mut self.camera,
+                    camera_controller: &mut self.camera_controller,
+                    input_helper: Some(input_helper),
+                },
+                dt,
+            );
+        }
+
+        self.update_remote();
+        self.update_timeline();
+        self.update_simulation_worker();
+        // submitted before the gui is built, which keeps the GPU busy in the meantime
+        self.fluid_sim.update(
+            &self.render_device.read().unwrap().wgpu_device,
+            &mut self.render_engine,
+            &self.camera,
+            dt,
+            self.simulation_paused || self.simulation_worker.is_some(),
+        );
+
+        if self.show_particle_trail && self.particle_trail.len() > 1 {
+            self.submit_particle_trail();
+        }
+    }
+
+    /// The walls of a floor boundary only set the layout, so their edges are dashed.
+    fn add_bbox_object(
+        render_engine: &mut RenderEngine,
+        fluid_sim: &FluidSimulation,
+    ) -> Option<ObjectHandle> {
+        let boundary = fluid_sim.config().boundary;
+        let material_type = match boundary {
+            DomainBoundary::Box => MaterialType::Line,
+            DomainBoundary::Floor | DomainBoundary::Open => MaterialType::DashedLine,
+        };
+        let bbox_object = render_engine.add_object(
+            "Bounding box",
+            RenderRequest {
+                material_type,
+                geometry: fluid_sim.bbox_geometry(),
+            },
+        )?;
+        render_engine.set_object_visible(bbox_object, boundary != DomainBoundary::Open);
+        Some(bbox_object)
+    }
+
+    /// Recreates the simulation from its current config with the particles at their start
+    /// positions. Changes made by the timeline are undone, so its events can run again.
+    fn reset_simulation(&mut self) {
+        let config = self
+            .timeline_start_config
+            .take()
+            .unwrap_or_else(|| self.fluid_sim.config().clone());
+        self.rebuild_simulation(config);
+    }
+
+    /// The scene is set up again, since its passes belonged to the old simulation.
+    fn rebuild_simulation(&mut self, config: FluidSimulationConfig) {
+        let limits = self
+            .render_device
+            .read()
+            .unwrap()
+            .wgpu_device
+            .device
+            .limits();
+        if let Err(err) = config.max_particle_cnt(&limits) {
+            tracing::error!("Failed to rebuild the simulation: {err}");
+            self.show_toast(err.to_string());
+            return;
+        }
+
+        let boundary = self.fluid_sim.config().boundary;
+        self.fluid_sim =
+            FluidSimulation::new(config, &self.render_device.read().unwrap().wgpu_device);
+        if self.fluid_sim.config().boundary != boundary {
+            if let Some(bbox_object) = self.bbox_object {
+                self.render_engine.remove_object(bbox_object);
+            }
+            self.bbox_object =
+                ApplicationState::add_bbox_object(&mut self.render_engine, &self.fluid_sim);
+        } else if let Some(bbox_object) = self.bbox_object {
+            self.render_engine
+                .set_object_geometry(bbox_object, self.fluid_sim.bbox_geometry());
+        }
+        self.particle_snapshot = None;
+        self.neighbor_histogram.clear();
+        self.diagnostics = None;
+        self.probe_history.clear();
+        self.wave_gauge_history.clear();
+        self.playback_history.clear();
+        self.playback_cursor = None;
+        self.select_particle(None);
+        self.running_time = 0.0;
+
+        if let Some(scene) = self.scene.take() {
+            self.set_scene(scene);
+        }
+    }
+
+    /// Rebuilds the simulation for a config with the same particle layout and continues from
+    /// the current particles.
+    fn rebuild_keeping_particles(&mut self, config: FluidSimulationConfig) {
+        let snapshot = self
+            .fluid_sim
+            .read_snapshot(&self.render_device.read().unwrap().wgpu_device);
+        self.rebuild_simulation(config);
+
+        let restored = snapshot.and_then(|snapshot| {
+            self.fluid_sim
+                .restore_snapshot(self.render_device.read().unwrap().queue(), &snapshot)
+        });
+        if let Err(err) = restored {
+            tracing::error!("Failed to keep the particles across the rebuild: {err}");
+        }
+    }
+
+    /// Switches to `config` with as little disruption as possible, rebuilding the simulation
+    /// only for baked in fields and keeping the particles if their number stays the same.
+    fn apply_config(&mut self, config: FluidSimulationConfig) {
+        if self.fluid_sim.apply_runtime_config(&config) {
+            return;
+        }
+
+        // the snapshot holds the ghost particles too, which have to land in the same slots
+        if config.particle_cnt == self.fluid_sim.fluid_particle_cnt()
+            && config.ghost_particle_cnt() == self.fluid_sim.ghost_particle_cnt()
+        {
+            self.rebuild_keeping_particles(config);
+        } else {
+            self.rebuild_simulation(config);
+        }
+    }
+
+    /// Advances a paused simulation by a single step.
+    fn step_simulation(&mut self) {
+        if self.simulation_paused {
+            self.leave_playback();
+            self.render_engine.submit_generic_request(
+                FrameStage::Simulation,
+                self.fluid_sim.step_fn(MANUAL_STEP_DT),
+            );
+        }
+    }
+
+    /// Starts or stops the simulation thread to match the config and hands it the current step.
+    /// Offline renders step once per frame, so every frame advances by the same time.
+    fn update_simulation_worker(&mut self) {
+        let step_rate = self
+            .fluid_sim
+            .config()
+            .background_step_rate
+            .filter(|_| self.offline_renderer.is_none());
+
+        if self
+            .simulation_worker
+            .as_ref()
+            .map(|worker| worker.step_rate())
+            != step_rate
+        {
+            self.simulation_worker =
+                step_rate.map(|rate| SimulationWorker::new(self.render_device.clone(), rate));
+        }
+
+        match &self.simulation_worker {
+            Some(worker) => {
+                let step = (!self.simulation_paused)
+                    .then(|| self.fluid_sim.background_step_fn(worker.dt()));
+                worker.set_step(step);
+                self.fluid_sim.set_interpolation(worker.interpolation());
+            }
+            None => self.fluid_sim.set_interpolation(1.0),
+        }
+    }
+
+    /// Places the orthographic views of the layout around the main camera.
+    fn update_viewports(&mut self) {
+        let half = |y, height| ViewportRect::new(0.5, y, 0.5, height);
+        let (main, extra) = match self.view_layout {
+            ViewLayout::Single => (ViewportRect::FULL, Vec::new()),
+            ViewLayout::SideBySide => (
+                ViewportRect::new(0.0, 0.0, 0.5, 1.0),
+                vec![(half(0.0, 1.0), -Vector3::y())],
+            ),
+            ViewLayout::ThreeViews => (
+                ViewportRect::new(0.0, 0.0, 0.5, 1.0),
+                vec![
+                    (half(0.0, 0.5), -Vector3::z()),
+                    (half(0.5, 0.5), -Vector3::y()),
+                ],
+            ),
+        };
+
+        let extra = extra
+            .into_iter()
+            .map(|(rect, direction)| Viewport {
+                rect,
+                camera: self.axis_camera(direction),
+            })
+            .collect();
+        self.render_engine.set_viewports(main, extra);
+    }
+
+    /// Orthographic camera looking at the whole domain along `direction`.
+    fn axis_camera(&self, direction: Vector3<f32>) -> Camera {
+        // the bounding box is rendered centered around the origin
+        let center = Point3::origin();
+        let radius = self.fluid_sim.bbox_dimensions().norm() / 2.0;
+
+        let mut camera = Camera::new();
+        camera.projection = Projection::Orthographic;
+        let distance = radius / (camera.fov / 2.0).tan();
+        // looking straight down would be parallel to the up vector of the view matrix
+        let direction = (direction - Vector3::z() * 1e-3).normalize();
+        camera.position = center - direction * distance;
+        camera.target = center;
+        camera.z_far = distance + 2.0 * radius;
+
+        camera
+    }
+
+    /// Part of the window the main camera is drawn to, in physical pixels.
+    fn viewport_region(&self) -> egui::Rect {
+        let region = match self.viewport_rect {
+            Some(rect) => rect * self.gui.ppp(),
+            None => {
+                let size = self.window.inner_size();
+                egui::Rect::from_min_size(
+                    egui::Pos2::ZERO,
+                    egui::vec2(size.width as f32, size.height as f32),
+                )
             }
-            self.simulation_paused = !self.simulation_paused;
+        };
+
+        let main = self.render_engine.main_viewport();
+        egui::Rect::from_min_size(
+            region.min + egui::vec2(main.x, main.y) * region.size(),
+            egui::vec2(main.width, main.height) * region.size(),
+        )
+    }
+
+    /// World space ray through the cursor.
+    fn cursor_ray(&self, input_helper: &InputHelper) -> Option<(Point3<f32>, Vector3<f32>)> {
+        let (x, y) = input_helper.cursor_position()?;
+        let region = self.viewport_region();
+        let ndc = (
+            2.0 * (x - region.min.x) / region.width().max(1.0) - 1.0,
+            1.0 - 2.0 * (y - region.min.y) / region.height().max(1.0),
+        );
+
+        Some(self.camera.view_ray(ndc, self.render_engine.aspect_ratio()))
+    }
+
+    fn pick_particle(&mut self, input_helper: &InputHelper) {
+        let Some((origin, direction)) = self.cursor_ray(input_helper) else {
+            return;
+        };
+        self.render_engine.submit_generic_request(
+            FrameStage::Simulation,
+            self.fluid_sim.pick_fn(origin, direction),
+        );
+        self.pick_pending = true;
+    }
+
+    /// The right mouse button looks around in fly mode, so painting only works while orbiting.
+    fn paint_dye(&mut self, input_helper: &InputHelper) {
+        if self.camera_controller.mode() != CameraMode::Orbit
+            || !input_helper.is_mouse_button_pressed(MouseButton::Right)
+        {
+            return;
+        }
+        let Some((origin, direction)) = self.cursor_ray(input_helper) else {
+            return;
+        };
+        if let Some(paint) = self.fluid_sim.paint_dye_fn(origin, direction) {
+            self.render_engine
+                .submit_generic_request(FrameStage::Simulation, paint);
         }
+    }
 
-        self.fluid_sim
-            .update(&mut self.render_engine, dt, self.simulation_paused);
+    fn select_particle(&mut self, particle: Option<u32>) {
+        self.fluid_sim.select_particle(particle);
+        self.selected_sample = None;
+        self.particle_trail.clear();
     }
 
-    pub fn redraw(&mut self) {
+    fn submit_particle_trail(&mut self) {
+        // fades from transparent at the oldest point to opaque at the particle
+        let len = self.particle_trail.len() as f32;
+        let vertex = |i: usize, position: Point3<f32>| {
+            ColoredVertex::new(position, Vector4::new(1.0, 1.0, 0.3, i as f32 / len))
+        };
+        let vertices: Vec<ColoredVertex> = self
+            .particle_trail
+            .iter()
+            .zip(self.particle_trail.iter().skip(1))
+            .enumerate()
+            .flat_map(|(i, (&start, &end))| [vertex(i, start), vertex(i + 1, end)])
+            .collect();
+
+        let geometry = self.render_engine.create_geometry_array(&vertices);
+        self.render_engine.submit_render_request(RenderRequest {
+            material_type: MaterialType::Line,
+            geometry,
+        });
+    }
+
+    pub fn redraw(&mut self) -> Result<(), SplooshError> {
+        let _span = tracing::debug_span!("redraw").entered();
+        if self.is_device_lost() {
+            return Err(SplooshError::DeviceLost);
+        }
+
         if self.frame_times.len() > 1000 {
             self.frame_times.pop_front();
         }
+        if self.gpu_frame_times.len() > 1000 {
+            self.gpu_frame_times.pop_front();
+        }
 
         self.frame_times
             .push_back(self.render_engine.last_frame_time());
+        if let Some(gpu_frame_time) = self.render_engine.last_gpu_frame_time() {
+            self.gpu_frame_times.push_back(gpu_frame_time);
+        }
 
-        self.gui.render(
-            &self.window,
-            &mut self.render_engine,
-            "Fluid simulation",
-            |ui| {
-                let points: PlotPoints = self
-                    .frame_times
-                    .iter()
-                    .enumerate()
-                    .map(|(i, &time)| [i as f64, time as f64])
-                    .collect();
-
-                let line = Line::new(points)
-                    .color(egui::Color32::LIGHT_BLUE)
-                    .name("Frame Time (ms)");
-
-                Plot::new("frame_time_plot")
-                    .view_aspect(2.0)
-                    .show(ui, |plot_ui| {
-                        plot_ui.line(line);
-                    });
-
-                ui.label(if self.simulation_paused {
-                    "Simulation paused"
-                } else {
-                    "Simulation running"
-                });
-                ui.label("Particle display size:");
-                ui.add(Slider::new(&mut self.particle_display_size, 0.001..=0.5).text("Size"));
-            },
-        );
+        // offline frames are captured without the gui
+        if self.offline_renderer.is_none() {
+            let ctx = self.gui.begin_pass(&self.window);
+            let config = self.fluid_sim.config().clone();
+            let mut gui_layout = std::mem::take(&mut self.gui_layout);
+            gui_layout.show(&ctx, |panel, ui| match panel {
+                GuiPanel::Stats => self.stats_panel(ui),
+                GuiPanel::Parameters => self.parameters_panel(ui),
+                GuiPanel::Scene => self.scene_panel(ui),
+                GuiPanel::Profiler => self.profiler_panel(ui),
+                GuiPanel::Inspector => self.inspector_panel(ui),
+                GuiPanel::System => self.system_panel(ui),
+                GuiPanel::Console => Self::console_panel(ui),
+                GuiPanel::KernelEditor => self.kernel_editor_panel(ui),
+                GuiPanel::Probes => self.probes_panel(ui),
+            });
+            self.gui_layout = gui_layout;
+            let scene_rect = ctx.available_rect();
+            if self.gui_layout.embedded_viewport() {
+                egui::CentralPanel::default()
+                    .frame(egui::Frame::none())
+                    .show(&ctx, |ui| self.viewport_ui(ui));
+            } else {
+                self.render_engine.clear_viewport_texture();
+                self.viewport_rect = None;
+                self.viewport_hovered = false;
+            }
+            self.screen_density_overlay(&ctx, self.viewport_rect.unwrap_or(scene_rect));
+            self.stats_overlay(&ctx);
+            self.legend_overlay(&ctx);
+            self.toast_overlay(&ctx);
+            self.minimap_overlay(&ctx, self.viewport_rect.unwrap_or(scene_rect));
+            self.annotation_overlay(&ctx, self.viewport_rect.unwrap_or(scene_rect));
+            self.axis_gizmo(&ctx, self.viewport_rect.unwrap_or(scene_rect));
+            // a drag or a text field in progress keeps the edit open
+            let editing = ctx.input(|input| input.pointer.any_down()) || ctx.wants_keyboard_input();
+            self.edit_history
+                .observe(&config, self.fluid_sim.config(), editing);
+            self.gui.end_pass(&self.window, &mut self.render_engine);
+        }
 
-        self.render_engine
-            .render(&self.camera)
-            .expect("Render engine failed");
-    }
+        let wants_clip_frame = self.clip_recorder.wants_frame();
+        if wants_clip_frame {
+            self.render_engine.request_scene_capture();
+        }
+
+        self.render_engine.render(&self.camera)?;
+        if wants_clip_frame && self.render_engine.scene_capture_queued() {
+            self.clip_recorder.frame_queued();
+        }
+
+        let histogram = self
+            .fluid_sim
+            .neighbor_histogram(self.render_device.read().unwrap().device());
+        match histogram {
+            Some(Ok(histogram)) => self.neighbor_histogram = histogram,
+            Some(Err(err)) => tracing::error!("Failed to read the neighbor histogram: {err}"),
+            None => {}
+        }
+
+        let diagnostics = self
+            .fluid_sim
+            .poll_diagnostics(self.render_device.read().unwrap().device());
+        match diagnostics {
+            Some(Ok(diagnostics)) => {
+                self.diagnostics = Some(DiagnosticsSummary::new(&diagnostics));
+            }
+            Some(Err(err)) => tracing::error!("Failed to read the diagnostics: {err}"),
+            None => {}
+        }
+        if !self.fluid_sim.config().diagnostics {
+            self.diagnostics = None;
+        }
+
+        let probe_samples = self
+            .fluid_sim
+            .poll_probes(self.render_device.read().unwrap().device());
+        match probe_samples {
+            Some(Ok(samples)) => self.probe_history.push(&samples),
+            Some(Err(err)) => tracing::error!("Failed to read the probes: {err}"),
+            None => {}
+        }
+
+        let wave_gauge_samples = self
+            .fluid_sim
+            .poll_wave_gauges(self.render_device.read().unwrap().device());
+        match wave_gauge_samples {
+            Some(Ok(samples)) => self.wave_gauge_history.push(&samples),
+            Some(Err(err)) => tracing::error!("Failed to read the wave gauges: {err}"),
+            None => {}
+        }
+
+        let playback_frame = self
+            .fluid_sim
+            .poll_playback(self.render_device.read().unwrap().device());
+        match playback_frame {
+            Some(Ok(frame)) => {
+                let capacity = self
+                    .fluid_sim
+                    .config()
+                    .playback
+                    .unwrap_or_default()
+                    .capacity;
+                self.playback_history.push(frame, capacity);
+            }
+            Some(Err(err)) => tracing::error!("Failed to read the playback frame: {err}"),
+            None => {}
+        }
+
+        let sample = self
+            .fluid_sim
+            .poll_selected_particle(self.render_device.read().unwrap().device());
+        match sample {
+            Some(Ok(sample)) => {
+                if self.particle_trail.back() != Some(&sample.position) {
+                    if self.particle_trail.len() >= PARTICLE_TRAIL_LENGTH {
+                        self.particle_trail.pop_front();
+                    }
+                    self.particle_trail.push_back(sample.position);
+                }
+                self.selected_sample = Some(sample);
+            }
+            Some(Err(err)) => tracing::error!("Failed to read the selected particle: {err}"),
+            None => {}
+        }
+
+        if self.pick_pending {
+            let pick = self
+                .fluid_sim
+                .poll_pick(self.render_device.read().unwrap().device());
+            self.pick_pending = pick.is_none();
+            match pick {
+                Some(Ok(particle)) => self.select_particle(particle),
+                Some(Err(err)) => tracing::error!("Failed to pick a particle: {err}"),
+                None => {}
+            }
+        }
+
+        let snapshot_outdated = self
+            .particle_snapshot
+            .as_ref()
+            .is_none_or(|snapshot| snapshot.step_cnt != self.fluid_sim.step_cnt());
+        if snapshot_outdated && self.last_snapshot.elapsed() >= SNAPSHOT_INTERVAL {
+            self.last_snapshot = Instant::now();
+            let snapshot = self
+                .fluid_sim
+                .read_snapshot(&self.render_device.read().unwrap().wgpu_device);
+            match snapshot {
+                Ok(snapshot) => self.particle_snapshot = Some(snapshot),
+                Err(err) => tracing::error!("Failed to snapshot the particles: {err}"),
+            }
+        }
+
+        for frame in self.render_engine.take_scene_captures() {
+            match frame {
+                Ok(frame) => self.clip_recorder.add_frame(frame),
+                Err(err) => tracing::error!("Failed to capture clip frame: {err}"),
+            }
+        }
+
+        match self.clip_recorder.poll_encoder() {
+            Some(Ok(path)) => tracing::info!("Saved clip to {}", path.display()),
+            Some(Err(err)) => tracing::error!("Failed to encode clip: {err}"),
+            None => {}
+        }
+
+        if let Some(screenshot) = self.render_engine.take_screenshot() {
+            match screenshot.and_then(|image| save_screenshot(&image)) {
+                Ok(path) => tracing::info!("Saved screenshot to {}", path.display()),
+                Err(err) => tracing::error!("Failed to save screenshot: {err}"),
+            }
+        }
+
+        if let Some(offline_renderer) = &mut self.offline_renderer {
+            let frame = self
+                .render_engine
+                .read_offscreen_frame()
+                .ok_or_else(|| {
+                    SplooshError::Capture(
+                        "Offline rendering requires an offscreen target".to_string(),
+                    )
+                })?
+                .and_then(|frame| offline_renderer.write_frame(&frame));
+
+            if let Err(err) = frame {
+                tracing::error!("Failed to write offline frame: {err}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The scene as an image filling the space left by the docked panels.
+    fn viewport_ui(&mut self, ui: &mut egui::Ui) {
+        let size = ui.available_size();
+        let ppp = ui.ctx().pixels_per_point();
+        let texture_id = self
+            .render_engine
+            .set_viewport_texture((size.x * ppp).round() as u32, (size.y * ppp).round() as u32);
+
+        let image = egui::Image::new(egui::load::SizedTexture::new(texture_id, size))
+            .sense(egui::Sense::click_and_drag());
+        let response = ui.add(image);
+        self.viewport_rect = Some(response.rect);
+        self.viewport_hovered = response.hovered() || response.dragged();
+    }
+
+    fn stats_panel(&mut self, ui: &mut egui::Ui) {
+        let frame_time = self.render_engine.last_frame_time();
+
+        ui.label(if self.simulation_paused {
+            "Simulation paused"
+        } else {
+            "Simulation running"
+        });
+        ui.label(format!(
+            "Particles: {} + {} boundary",
+            self.fluid_sim.fluid_particle_cnt(),
+            self.fluid_sim.ghost_particle_cnt()
+        ));
+        ui.label(format!("Frame time: {frame_time:.2} ms"));
+        if let Some(gpu_frame_time) = self.render_engine.last_gpu_frame_time() {
+            ui.label(format!("GPU time: {gpu_frame_time:.2} ms"));
+        }
+    }
+
+    fn toast_overlay(&mut self, ctx: &egui::Context) {
+        if self
+            .toast
+            .as_ref()
+            .is_some_and(|(_, shown)| shown.elapsed() >= TOAST_DURATION)
+        {
+            self.toast = None;
+        }
+        let Some((message, _)) = &self.toast else {
+            return;
+        };
+
+        egui::Area::new(egui::Id::new("toast_overlay"))
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| ui.label(message));
+            });
+    }
+
+    /// Compact statistics in the corner of the viewport, shown regardless of the dock layout.
+    fn stats_overlay(&self, ctx: &egui::Context) {
+        let sim_time = self.fluid_sim.sim_time();
+        let wall_time = self.start_time.elapsed().as_secs_f32();
+        let ratio = if self.running_time > 0.0 {
+            sim_time / self.running_time
+        } else {
+            0.0
+        };
+        let memory = self.fluid_sim.buffer_memory();
+        let total_memory: u64 = memory.iter().map(|(_, size)| size).sum();
+        let mib = |bytes: u64| bytes as f32 / (1024.0 * 1024.0);
+
+        egui::Area::new(egui::Id::new("stats_overlay"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-8.0, -8.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(format!("FPS: {:.0}", self.fps));
+                    ui.label(format!(
+                        "Particles: {}",
+                        self.fluid_sim.fluid_particle_cnt()
+                    ));
+                    ui.label(format!("Sim time: {sim_time:.2} s ({ratio:.2}x real time)"));
+                    ui.label(format!("Wall time: {wall_time:.1} s"));
+                    ui.label(format!("Steps: {}", self.fluid_sim.step_cnt()));
+                    ui.label(format!("GPU memory: {:.1} MiB", mib(total_memory)))
+                        .on_hover_ui(|ui| {
+                            for (name, size) in &memory {
+                                ui.label(format!("{name}: {:.1} MiB", mib(*size)));
+                            }
+                        });
+                });
+            });
+    }
+
+    /// Color bars for the color mapped quantities on screen, in the opposite corner of the
+    /// statistics.
+    fn legend_overlay(&self, ctx: &egui::Context) {
+        let config = self.fluid_sim.config();
+        let mut legends = Vec::new();
+        if config.color_mode != ParticleColorMode::Dye {
+            let range = config
+                .color_range
+                .unwrap_or_else(|| config.color_mode.default_range());
+            legends.push((config.color_mode.name(), config.colormap, range));
+        }
+        if let Some(slice) = config.density_slice {
+            let range = ColorRange {
+                min: slice.min_density,
+                max: slice.max_density,
+            };
+            legends.push(("Density slice", slice.color_map, range));
+        }
+        if legends.is_empty() {
+            return;
+        }
+
+        egui::Area::new(egui::Id::new("legend_overlay"))
+            .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(8.0, -8.0))
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    for (title, colormap, range) in legends {
+                        Self::color_bar_ui(ui, title, colormap, range);
+                    }
+                });
+            });
+    }
+
+    /// Top-down density map in the top right corner of `scene_rect`, with the camera drawn as
+    /// a line from its position towards its target.
+    fn minimap_overlay(&mut self, ctx: &egui::Context, scene_rect: egui::Rect) {
+        let Some(config) = self.fluid_sim.config().minimap else {
+            if let Some((texture_id, _)) = self.minimap_texture.take() {
+                self.render_engine.free_gui_texture(texture_id);
+            }
+            return;
+        };
+
+        let minimap = self.fluid_sim.minimap();
+        let view_id = minimap.view().global_id();
+        let texture_id = match self.minimap_texture {
+            Some((texture_id, id)) if id == view_id => texture_id,
+            previous => {
+                let texture_id = self
+                    .render_engine
+                    .register_gui_texture(minimap.view(), previous.map(|(id, _)| id));
+                self.minimap_texture = Some((texture_id, view_id));
+                texture_id
+            }
+        };
+
+        let (width, height) = minimap.size();
+        let size = egui::vec2(config.width, config.width * height as f32 / width as f32);
+        let bbox = self.fluid_sim.config().simulation_bbox();
+        let camera = &self.camera;
+
+        egui::Area::new(egui::Id::new("minimap_overlay"))
+            .pivot(egui::Align2::RIGHT_TOP)
+            .fixed_pos(scene_rect.right_top() + egui::vec2(-8.0, 8.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    let image = egui::Image::new(egui::load::SizedTexture::new(texture_id, size));
+                    let rect = ui.add(image).rect;
+
+                    // the domain is centered at the origin, x runs right and z down the map
+                    let to_map = |point: Point3<f32>| {
+                        rect.min
+                            + egui::vec2(
+                                (point.x / bbox.x + 0.5) * rect.width(),
+                                (point.z / bbox.z + 0.5) * rect.height(),
+                            )
+                    };
+                    let position = to_map(camera.position);
+                    let target = to_map(camera.target);
+                    let painter = ui.painter().with_clip_rect(rect);
+                    let stroke = egui::Stroke::new(2.0, egui::Color32::WHITE);
+                    painter.line_segment([position, target], stroke);
+                    painter.circle_filled(position, 4.0, egui::Color32::WHITE);
+                });
+            });
+    }
+
+    /// Density heatmap stretched over the main viewport in `scene_rect`, behind the other
+    /// overlays.
+    fn screen_density_overlay(&mut self, ctx: &egui::Context, scene_rect: egui::Rect) {
+        let Some(config) = self.fluid_sim.config().screen_density else {
+            if let Some((texture_id, _)) = self.screen_density_texture.take() {
+                self.render_engine.free_gui_texture(texture_id);
+            }
+            return;
+        };
+
+        let screen_density = self.fluid_sim.screen_density();
+        let view_id = screen_density.view().global_id();
+        let texture_id = match self.screen_density_texture {
+            Some((texture_id, id)) if id == view_id => texture_id,
+            previous => {
+                let texture_id = self
+                    .render_engine
+                    .register_gui_texture(screen_density.view(), previous.map(|(id, _)| id));
+                self.screen_density_texture = Some((texture_id, view_id));
+                texture_id
+            }
+        };
+
+        let main = self.render_engine.main_viewport();
+        let rect = egui::Rect::from_min_size(
+            scene_rect.min + egui::vec2(main.x, main.y) * scene_rect.size(),
+            egui::vec2(main.width, main.height) * scene_rect.size(),
+        );
+
+        egui::Area::new(egui::Id::new("screen_density_overlay"))
+            .fixed_pos(rect.min)
+            .order(egui::Order::Background)
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.painter_at(rect).image(
+                    texture_id,
+                    rect,
+                    egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                    egui::Color32::WHITE.gamma_multiply(config.opacity),
+                );
+            });
+    }
+
+    /// Labels of the emitters, the selected particle and the user's annotations, placed where
+    /// their positions are seen by the main camera.
+    fn annotation_overlay(&mut self, ctx: &egui::Context, scene_rect: egui::Rect) {
+        if !self.show_annotations {
+            return;
+        }
+
+        let config = self.fluid_sim.config();
+        let mut labels = Vec::new();
+        if let Some(emitter) = config.dye.and_then(|dye| dye.emitter) {
+            labels.push(Annotation::new(emitter.center, "Dye emitter"));
+        }
+        if let Some(debris) = config.debris {
+            labels.push(Annotation::new(debris.spawn_center, "Debris spawn"));
+        }
+        for probe in config.probes.iter().take(MAX_PROBES) {
+            let mut label = Annotation::new(probe.position, probe.name.clone());
+            label.color = [120, 220, 255];
+            labels.push(label);
+        }
+        if let (Some(particle), Some(sample)) =
+            (self.fluid_sim.selected_particle(), &self.selected_sample)
+        {
+            labels.push(Annotation::new(
+                sample.position,
+                format!("Particle {particle}"),
+            ));
+        }
+        labels.extend(self.annotations.iter().cloned());
+        if labels.is_empty() {
+            return;
+        }
+
+        let main = self.render_engine.main_viewport();
+        let rect = egui::Rect::from_min_size(
+            scene_rect.min + egui::vec2(main.x, main.y) * scene_rect.size(),
+            egui::vec2(main.width, main.height) * scene_rect.size(),
+        );
+        let view_proj = self
+            .camera
+            .get_projection_matrix(self.render_engine.aspect_ratio())
+            * self.camera.get_view_matrix();
+
+        egui::Area::new(egui::Id::new("annotations"))
+            .fixed_pos(rect.min)
+            .order(egui::Order::Background)
+            .interactable(false)
+            .show(ctx, |ui| {
+                let painter = ui.painter_at(rect);
+                let font = egui::FontId::proportional(13.0);
+                for label in &labels {
+                    let Some(point) = annotations::project_to_viewport(&view_proj, &label.position)
+                    else {
+                        continue;
+                    };
+                    let [r, g, b] = label.color;
+                    let color = egui::Color32::from_rgb(r, g, b);
+                    let anchor = rect.min + egui::vec2(point.x, point.y) * rect.size();
+
+                    painter.circle_filled(anchor, 3.0, color);
+                    let text = painter.layout_no_wrap(label.text.clone(), font.clone(), color);
+                    let text_rect = egui::Align2::LEFT_BOTTOM
+                        .anchor_size(anchor + egui::vec2(6.0, -4.0), text.size());
+                    painter.rect_filled(
+                        text_rect.expand(3.0),
+                        3.0,
+                        egui::Color32::from_black_alpha(160),
+                    );
+                    painter.galley(text_rect.min, text, color);
+                }
+            });
+    }
+
+    /// Axes of the world as seen by the camera in the top left corner of `scene_rect`.
+    /// Clicking the end of an axis orbits to the view from that side.
+    fn axis_gizmo(&mut self, ctx: &egui::Context, scene_rect: egui::Rect) {
+        const SIZE: f32 = 90.0;
+        const HANDLE_RADIUS: f32 = 9.0;
+
+        let view = self.camera.get_view_matrix();
+        let axes = [
+            (Vector3::x(), "X", egui::Color32::from_rgb(230, 70, 70)),
+            (Vector3::y(), "Y", egui::Color32::from_rgb(90, 200, 90)),
+            (Vector3::z(), "Z", egui::Color32::from_rgb(80, 130, 240)),
+        ];
+        // both ends of every axis, positive ends carry the label
+        let mut handles: Vec<_> = axes
+            .iter()
+            .flat_map(|&(axis, label, color)| [(axis, Some(label), color), (-axis, None, color)])
+            .map(|(axis, label, color)| {
+                let projected = view.transform_vector(&axis);
+                (axis, label, color, projected)
+            })
+            .collect();
+        // the view looks down -z, so the handles closest to the camera are drawn last
+        handles.sort_by(|a, b| a.3.z.total_cmp(&b.3.z));
+
+        let mut snap = None;
+        egui::Area::new(egui::Id::new("axis_gizmo"))
+            .fixed_pos(scene_rect.left_top() + egui::vec2(8.0, 8.0))
+            .show(ctx, |ui| {
+                let (rect, response) =
+                    ui.allocate_exact_size(egui::vec2(SIZE, SIZE), egui::Sense::click());
+                let center = rect.center();
+                let length = SIZE / 2.0 - HANDLE_RADIUS;
+                let screen = |projected: Vector3<f32>| {
+                    center + egui::vec2(projected.x, -projected.y) * length
+                };
+
+                let painter = ui.painter();
+                if response.hovered() {
+                    painter.circle_filled(center, SIZE / 2.0, egui::Color32::from_white_alpha(20));
+                }
+                for &(_, label, color, projected) in &handles {
+                    let end = screen(projected);
+                    match label {
+                        Some(label) => {
+                            painter.line_segment([center, end], egui::Stroke::new(2.0, color));
+                            painter.circle_filled(end, HANDLE_RADIUS, color);
+                            painter.text(
+                                end,
+                                egui::Align2::CENTER_CENTER,
+                                label,
+                                egui::FontId::proportional(11.0),
+                                egui::Color32::BLACK,
+                            );
+                        }
+                        None => {
+                            painter.circle_filled(end, HANDLE_RADIUS, color.gamma_multiply(0.3));
+                            painter.circle_stroke(
+                                end,
+                                HANDLE_RADIUS,
+                                egui::Stroke::new(1.5, color),
+                            );
+                        }
+                    }
+                }
+
+                if let Some(pointer) = response
+                    .interact_pointer_pos()
+                    .filter(|_| response.clicked())
+                {
+                    // handles in front win when they overlap
+                    snap = handles
+                        .iter()
+                        .rev()
+                        .find(|handle| screen(handle.3).distance(pointer) <= HANDLE_RADIUS)
+                        .map(|handle| handle.0);
+                }
+            });
+
+        if let Some(direction) = snap {
+            self.camera_animation = None;
+            self.camera_controller.snap_to_axis(direction);
+        }
+    }
+
+    fn color_bar_ui(ui: &mut egui::Ui, title: &str, colormap: Colormap, range: ColorRange) {
+        const SEGMENTS: usize = 64;
+
+        ui.label(title);
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(160.0, 12.0), egui::Sense::hover());
+        let width = rect.width() / SEGMENTS as f32;
+        for i in 0..SEGMENTS {
+            let [r, g, b] = colormap.sample((i as f32 + 0.5) / SEGMENTS as f32);
+            let color = egui::Color32::from_rgb(
+                (r * 255.0).round() as u8,
+                (g * 255.0).round() as u8,
+                (b * 255.0).round() as u8,
+            );
+            let min = rect.left_top() + egui::vec2(i as f32 * width, 0.0);
+            // overlapping by a pixel hides the seams between the segments
+            let segment = egui::Rect::from_min_size(min, egui::vec2(width + 1.0, rect.height()));
+            ui.painter()
+                .rect_filled(segment.intersect(rect), 0.0, color);
+        }
+        ui.horizontal(|ui| {
+            ui.set_width(rect.width());
+            ui.label(format!("{:.1}", range.min));
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                ui.label(format!("{:.1}", range.max));
+            });
+        });
+    }
+
+    fn parameters_panel(&mut self, ui: &mut egui::Ui) {
+        ui.label("Particle display size:");
+        ui.add(Slider::new(&mut self.particle_display_size, 0.001..=0.5).text("Size"));
+
+        let mut integrator = self.fluid_sim.config().integrator;
+        egui::ComboBox::from_label("Integrator")
+            .selected_text(integrator.name())
+            .show_ui(ui, |ui| {
+                for option in Integrator::ALL {
+                    ui.selectable_value(&mut integrator, option, option.name());
+                }
+            });
+        if integrator != self.fluid_sim.config().integrator {
+            self.fluid_sim.set_integrator(integrator);
+        }
+
+        self.material_ui(ui);
+        self.fluid_preset_ui(ui);
+
+        let mut free_surface_correction = self.fluid_sim.config().free_surface_correction;
+        if ui
+            .checkbox(&mut free_surface_correction, "Free surface correction")
+            .on_hover_text("Corrects the densities of particles with missing neighbors")
+            .changed()
+        {
+            self.fluid_sim
+                .set_free_surface_correction(free_surface_correction);
+        }
+
+        let mut renormalization = self.fluid_sim.config().density_renormalization;
+        let mut enabled = renormalization.is_some();
+        ui.checkbox(&mut enabled, "Density renormalization");
+        renormalization = enabled.then(|| renormalization.unwrap_or_default());
+        if let Some(renormalization) = &mut renormalization {
+            egui::ComboBox::from_label("Renormalization")
+                .selected_text(renormalization.method.name())
+                .show_ui(ui, |ui| {
+                    for option in RenormalizationMethod::ALL {
+                        ui.selectable_value(&mut renormalization.method, option, option.name());
+                    }
+                });
+            ui.add(Slider::new(&mut renormalization.interval, 1..=100).text("Every n steps"));
+        }
+        if renormalization != self.fluid_sim.config().density_renormalization {
+            self.fluid_sim.set_density_renormalization(renormalization);
+        }
+
+        let mut step_rate = self.fluid_sim.config().background_step_rate;
+        let mut enabled = step_rate.is_some();
+        ui.checkbox(&mut enabled, "Background stepping")
+            .on_hover_text("Steps the simulation on its own thread at a fixed rate");
+        step_rate = enabled.then(|| step_rate.unwrap_or(120.0));
+        if let Some(rate) = &mut step_rate {
+            ui.add(Slider::new(rate, 30.0..=480.0).text("Steps per second"));
+        }
+        if step_rate != self.fluid_sim.config().background_step_rate {
+            self.fluid_sim.set_background_step_rate(step_rate);
+        }
+
+        let mut translucent = self.fluid_sim.config().translucent_particles;
+        if ui
+            .checkbox(&mut translucent, "Translucent particles")
+            .changed()
+        {
+            self.fluid_sim.set_translucent_particles(translucent);
+        }
+
+        let mut anisotropy = self.fluid_sim.config().anisotropy;
+        let mut enabled = anisotropy.is_some();
+        ui.checkbox(&mut enabled, "Anisotropic particles")
+            .on_hover_text(
+                "Stretches opaque particles along their neighbors for smoother surfaces",
+            );
+        anisotropy = enabled.then(|| anisotropy.unwrap_or_default());
+        if let Some(anisotropy) = &mut anisotropy {
+            ui.add(Slider::new(&mut anisotropy.max_ratio, 1.0..=8.0).text("Max axis ratio"));
+            ui.add(Slider::new(&mut anisotropy.min_neighbors, 0..=64).text("Min neighbors"));
+        }
+        if anisotropy != self.fluid_sim.config().anisotropy {
+            self.fluid_sim.set_anisotropy(anisotropy);
+        }
+
+        let mut show_ghosts = self.fluid_sim.config().show_ghost_particles;
+        if ui
+            .checkbox(&mut show_ghosts, "Show ghost particles")
+            .on_hover_text("Draws the static boundary particles on the floor")
+            .changed()
+        {
+            self.fluid_sim.set_show_ghost_particles(show_ghosts);
+        }
+
+        let mut culling = self.fluid_sim.config().culling;
+        ui.checkbox(&mut culling.frustum, "Frustum culling");
+        Self::optional_distance_ui(ui, "Max distance", &mut culling.max_distance);
+        Self::optional_distance_ui(ui, "LOD distance", &mut culling.lod_distance);
+        Self::optional_distance_ui(ui, "Merge distance", &mut culling.merge_distance);
+        if culling != self.fluid_sim.config().culling {
+            self.fluid_sim.set_culling(culling);
+        }
+
+        let mut color_mode = self.fluid_sim.config().color_mode;
+        egui::ComboBox::from_label("Color")
+            .selected_text(color_mode.name())
+            .show_ui(ui, |ui| {
+                for option in ParticleColorMode::ALL {
+                    ui.selectable_value(&mut color_mode, option, option.name());
+                }
+            });
+        if color_mode != self.fluid_sim.config().color_mode {
+            self.fluid_sim.set_color_mode(color_mode);
+        }
+
+        if color_mode != ParticleColorMode::Dye {
+            let mut colormap = self.fluid_sim.config().colormap;
+            Self::colormap_ui(ui, "Colormap", &mut colormap);
+            if colormap != self.fluid_sim.config().colormap {
+                self.fluid_sim.set_colormap(colormap);
+            }
+        }
+
+        let mut color_range = self.fluid_sim.config().color_range;
+        let mut custom = color_range.is_some();
+        ui.checkbox(&mut custom, "Custom color range");
+        color_range = custom.then(|| color_range.unwrap_or_else(|| color_mode.default_range()));
+        if let Some(range) = &mut color_range {
+            ui.horizontal(|ui| {
+                ui.label("Range:");
+                ui.add(egui::DragValue::new(&mut range.min).speed(0.5));
+                ui.add(egui::DragValue::new(&mut range.max).speed(0.5));
+            });
+        }
+        if color_range != self.fluid_sim.config().color_range {
+            self.fluid_sim.set_color_range(color_range);
+        }
+        if color_mode == ParticleColorMode::NeighborCount {
+            self.neighbor_histogram_ui(ui);
+        }
+
+        let mut diagnostics = self.fluid_sim.config().diagnostics;
+        if ui
+            .checkbox(&mut diagnostics, "Divergence and pressure")
+            .changed()
+        {
+            self.fluid_sim.set_diagnostics(diagnostics);
+        }
+        if let Some(summary) = &self.diagnostics {
+            Self::diagnostics_ui(ui, summary);
+        }
+
+        let mut velocity_lines = self.fluid_sim.config().velocity_lines;
+        let mut enabled = velocity_lines.is_some();
+        ui.checkbox(&mut enabled, "Velocity lines");
+        velocity_lines = enabled.then(|| velocity_lines.unwrap_or_default());
+        if let Some(lines) = &mut velocity_lines {
+            ui.add(Slider::new(&mut lines.scale, 0.01..=1.0).text("Line scale"));
+            ui.add(Slider::new(&mut lines.subsampling, 1..=64).text("Subsampling"));
+            ui.add(
+                Slider::new(&mut lines.streamline_steps, 1..=MAX_STREAMLINE_STEPS)
+                    .text("Streamline steps"),
+            );
+        }
+        if velocity_lines != self.fluid_sim.config().velocity_lines {
+            self.fluid_sim.set_velocity_lines(velocity_lines);
+        }
+
+        let mut particle_trails = self.fluid_sim.config().particle_trails;
+        let mut enabled = particle_trails.is_some();
+        ui.checkbox(&mut enabled, "Particle trails");
+        particle_trails = enabled.then(|| particle_trails.unwrap_or_default());
+        if let Some(trails) = &mut particle_trails {
+            ui.add(
+                Slider::new(&mut trails.trail_cnt, 1..=MAX_TRAILS)
+                    .logarithmic(true)
+                    .text("Trails"),
+            );
+            ui.add(Slider::new(&mut trails.length, 2..=MAX_TRAIL_LENGTH).text("Trail length"));
+        }
+        if particle_trails != self.fluid_sim.config().particle_trails {
+            self.fluid_sim.set_particle_trails(particle_trails);
+        }
+
+        let mut minimap = self.fluid_sim.config().minimap;
+        let mut enabled = minimap.is_some();
+        ui.checkbox(&mut enabled, "Minimap");
+        minimap = enabled.then(|| minimap.unwrap_or_default());
+        if let Some(minimap) = &mut minimap {
+            Self::colormap_ui(ui, "Minimap colormap", &mut minimap.colormap);
+            ui.add(Slider::new(&mut minimap.width, 80.0..=480.0).text("Minimap width"));
+        }
+        if minimap != self.fluid_sim.config().minimap {
+            self.fluid_sim.set_minimap(minimap);
+        }
+
+        let mut screen_density = self.fluid_sim.config().screen_density;
+        let mut enabled = screen_density.is_some();
+        ui.checkbox(&mut enabled, "Density heatmap");
+        screen_density = enabled.then(|| screen_density.unwrap_or_default());
+        if let Some(screen_density) = &mut screen_density {
+            Self::colormap_ui(ui, "Heatmap colormap", &mut screen_density.colormap);
+            ui.add(Slider::new(&mut screen_density.opacity, 0.1..=1.0).text("Heatmap opacity"));
+        }
+        if screen_density != self.fluid_sim.config().screen_density {
+            self.fluid_sim.set_screen_density(screen_density);
+        }
+
+        let mut dye = self.fluid_sim.config().dye;
+        let mut enabled = dye.is_some();
+        if ui.checkbox(&mut enabled, "Dye").changed() && enabled {
+            self.fluid_sim.set_color_mode(ParticleColorMode::Dye);
+        }
+        dye = enabled.then(|| dye.unwrap_or_default());
+        if let Some(dye) = &mut dye {
+            ui.label("Hold the right mouse button to paint dye");
+            ui.add(Slider::new(&mut dye.brush_radius, 0.05..=2.0).text("Brush radius"));
+            ui.add(Slider::new(&mut dye.diffusion, 0.0..=5.0).text("Diffusion"));
+
+            let mut emitter = dye.emitter.is_some();
+            ui.checkbox(&mut emitter, "Dye emitter");
+            dye.emitter = emitter.then(|| dye.emitter.unwrap_or_default());
+            if let Some(emitter) = &mut dye.emitter {
+                ui.horizontal(|ui| {
+                    ui.label("Center");
+                    ui.add(egui::DragValue::new(&mut emitter.center.x).speed(0.05));
+                    ui.add(egui::DragValue::new(&mut emitter.center.y).speed(0.05));
+                    ui.add(egui::DragValue::new(&mut emitter.center.z).speed(0.05));
+                });
+                ui.add(Slider::new(&mut emitter.radius, 0.05..=2.0).text("Emitter radius"));
+            }
+
+            if ui.button("Clear dye").clicked() {
+                self.render_engine
+                    .submit_generic_request(FrameStage::Simulation, self.fluid_sim.clear_dye_fn());
+            }
+        }
+        if dye != self.fluid_sim.config().dye {
+            self.fluid_sim.set_dye(dye);
+        }
+
+        let mut debris = self.fluid_sim.config().debris;
+        let mut enabled = debris.is_some();
+        ui.checkbox(&mut enabled, "Debris");
+        debris = enabled.then(|| debris.unwrap_or_default());
+        if let Some(debris) = &mut debris {
+            ui.add(
+                Slider::new(&mut debris.count, 1..=MAX_DEBRIS)
+                    .logarithmic(true)
+                    .text("Pieces"),
+            );
+            ui.add(Slider::new(&mut debris.spawn_rate, 0.0..=2000.0).text("Spawn rate"));
+            ui.horizontal(|ui| {
+                ui.label("Spawn center");
+                ui.add(egui::DragValue::new(&mut debris.spawn_center.x).speed(0.05));
+                ui.add(egui::DragValue::new(&mut debris.spawn_center.y).speed(0.05));
+                ui.add(egui::DragValue::new(&mut debris.spawn_center.z).speed(0.05));
+            });
+            ui.add(Slider::new(&mut debris.spawn_radius, 0.05..=3.0).text("Spawn radius"));
+            ui.add(Slider::new(&mut debris.drag, 0.0..=20.0).text("Drag"));
+            ui.add(Slider::new(&mut debris.buoyancy, 0.0..=3.0).text("Buoyancy"));
+            ui.add(Slider::new(&mut debris.size, 0.1..=2.0).text("Debris size"));
+
+            if ui.button("Clear debris").clicked() {
+                self.render_engine.submit_generic_request(
+                    FrameStage::Simulation,
+                    self.fluid_sim.clear_debris_fn(),
+                );
+            }
+        }
+        if debris != self.fluid_sim.config().debris {
+            self.fluid_sim.set_debris(debris);
+        }
+
+        let mut density_slice = self.fluid_sim.config().density_slice;
+        let mut enabled = density_slice.is_some();
+        ui.checkbox(&mut enabled, "Density slice");
+        density_slice = enabled.then(|| density_slice.unwrap_or_default());
+        if let Some(slice) = &mut density_slice {
+            ui.horizontal(|ui| {
+                ui.label("Axis:");
+                for axis in SliceAxis::ALL {
+                    ui.radio_value(&mut slice.axis, axis, axis.name());
+                }
+            });
+            ui.add(Slider::new(&mut slice.position, 0.0..=1.0).text("Slice position"));
+            ui.add(Slider::new(&mut slice.min_density, 0.0..=1000.0).text("Min density"));
+            ui.add(Slider::new(&mut slice.max_density, 0.0..=1000.0).text("Max density"));
+            Self::colormap_ui(ui, "Slice colormap", &mut slice.color_map);
+        }
+        if density_slice != self.fluid_sim.config().density_slice {
+            self.fluid_sim.set_density_slice(density_slice);
+        }
+    }
+
+    fn colormap_ui(ui: &mut egui::Ui, label: &str, colormap: &mut Colormap) {
+        egui::ComboBox::from_label(label)
+            .selected_text(colormap.name())
+            .show_ui(ui, |ui| {
+                for option in Colormap::ALL {
+                    ui.selectable_value(colormap, option, option.name());
+                }
+            });
+    }
+
+    fn neighbor_histogram_ui(&self, ui: &mut egui::Ui) {
+        let total: u32 = self.neighbor_histogram.iter().sum();
+        let mean = self
+            .neighbor_histogram
+            .iter()
+            .enumerate()
+            .map(|(count, &particles)| count as f64 * particles as f64)
+            .sum::<f64>()
+            / total.max(1) as f64;
+        ui.label(format!("Mean neighbor count: {mean:.1}"));
+
+        let bars = self
+            .neighbor_histogram
+            .iter()
+            .enumerate()
+            .map(|(count, &particles)| Bar::new(count as f64, particles as f64).width(1.0))
+            .collect();
+
+        Plot::new("neighbor_histogram")
+            .view_aspect(2.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(BarChart::new(bars).name("Particles"));
+            });
+    }
+
+    /// The material is part of the force pass, so changing it restarts the simulation.
+    fn material_ui(&mut self, ui: &mut egui::Ui) {
+        let material = &mut self.material;
+        egui::ComboBox::from_label("Material")
+            .selected_text(material.name())
+            .show_ui(ui, |ui| {
+                for option in Material::ALL {
+                    if ui
+                        .selectable_label(material.name() == option.name(), option.name())
+                        .clicked()
+                        && material.name() != option.name()
+                    {
+                        *material = option;
+                    }
+                }
+            });
+
+        // restarting while a slider is dragged would restart every frame
+        let mut released = false;
+        if let Material::Granular {
+            friction_angle,
+            cohesion,
+            shear_viscosity,
+        } = material
+        {
+            let mut slider = |ui: &mut egui::Ui, slider: Slider| {
+                let response = ui.add(slider);
+                released |= response.drag_stopped() || (response.changed() && !response.dragged());
+            };
+            slider(
+                ui,
+                Slider::new(friction_angle, 0.0..=60.0).text("Friction angle"),
+            );
+            slider(ui, Slider::new(cohesion, 0.0..=50.0).text("Cohesion"));
+            slider(
+                ui,
+                Slider::new(shear_viscosity, 1.0..=100.0)
+                    .logarithmic(true)
+                    .text("Shear viscosity"),
+            );
+        }
+
+        let material = *material;
+        let switched = material.name() != self.fluid_sim.config().material.name();
+        if switched || (released && material != self.fluid_sim.config().material) {
+            self.rebuild_simulation(FluidSimulationConfig {
+                material,
+                ..self.fluid_sim.config().clone()
+            });
+        }
+    }
+
+    /// Presets are baked into the simulation, switching rebuilds it but keeps the particles
+    /// moving where they are.
+    fn fluid_preset_ui(&mut self, ui: &mut egui::Ui) {
+        let current = self.fluid_sim.config().preset;
+        let mut preset = current;
+        egui::ComboBox::from_label("Fluid preset")
+            .selected_text(preset.map_or("Custom", |preset| preset.name()))
+            .show_ui(ui, |ui| {
+                for option in FluidPreset::ALL {
+                    ui.selectable_value(&mut preset, Some(option), option.name());
+                }
+            });
+
+        if preset != current {
+            self.rebuild_keeping_particles(FluidSimulationConfig {
+                preset,
+                ..self.fluid_sim.config().clone()
+            });
+        }
+    }
+
+    fn diagnostics_ui(ui: &mut egui::Ui, summary: &DiagnosticsSummary) {
+        ui.label(format!(
+            "Mean |divergence|: {:.3} 1/s",
+            summary.mean_abs_divergence
+        ));
+        Self::value_histogram_ui(ui, "divergence_histogram", &summary.divergence);
+        ui.label(format!("Mean pressure: {:.2}", summary.mean_pressure));
+        Self::value_histogram_ui(ui, "pressure_histogram", &summary.pressure);
+    }
+
+    fn value_histogram_ui(ui: &mut egui::Ui, id: &str, histogram: &Histogram) {
+        let width = histogram.bin_width() as f64;
+        let bars = histogram
+            .bins
+            .iter()
+            .enumerate()
+            .map(|(i, &particles)| {
+                let center = histogram.min as f64 + (i as f64 + 0.5) * width;
+                Bar::new(center, particles as f64).width(width)
+            })
+            .collect();
+
+        Plot::new(id)
+            .view_aspect(2.0)
+            .allow_drag(false)
+            .allow_zoom(false)
+            .allow_scroll(false)
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(BarChart::new(bars).name("Particles"));
+            });
+    }
+
+    fn optional_distance_ui(ui: &mut egui::Ui, label: &str, distance: &mut Option<f32>) {
+        ui.horizontal(|ui| {
+            let mut enabled = distance.is_some();
+            ui.checkbox(&mut enabled, label);
+            let mut value = distance.unwrap_or(20.0);
+            ui.add_enabled(enabled, Slider::new(&mut value, 1.0..=100.0));
+            *distance = enabled.then_some(value);
+        });
+    }
+
+    fn frame_bbox(&mut self) {
+        // the bounding box is rendered centered around the origin
+        self.camera_controller.frame_bbox(
+            nalgebra::Point3::origin(),
+            self.fluid_sim.bbox_dimensions(),
+            &self.camera,
+        );
+    }
+
+    fn camera_animation_ui(&mut self, ui: &mut egui::Ui) {
+        if let Some(playing) = &self.camera_animation {
+            ui.label(format!("Animation time: {:.2} s", playing.time));
+            if ui.button("Stop animation").clicked() {
+                self.stop_camera_animation();
+            }
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(Slider::new(&mut self.turntable_speed, -2.0..=2.0).text("rad/s"));
+            if ui.button("Turntable").clicked() {
+                self.play_camera_animation(CameraAnimation::Turntable {
+                    speed: self.turntable_speed,
+                });
+            }
+        });
+
+        ui.label(format!("Keyframes: {}", self.camera_keyframes.len()));
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("keyframe_easing")
+                .selected_text(self.keyframe_easing.name())
+                .show_ui(ui, |ui| {
+                    for easing in Easing::ALL {
+                        ui.selectable_value(&mut self.keyframe_easing, easing, easing.name());
+                    }
+                });
+
+            if ui.button("Add keyframe").clicked() {
+                let time = self
+                    .camera_keyframes
+                    .last()
+                    .map_or(0.0, |keyframe| keyframe.time + 2.0);
+                self.camera_keyframes.insert(CameraKeyframe {
+                    time,
+                    orbit: self.camera_controller.orbit_state(),
+                    easing: self.keyframe_easing,
+                });
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let enabled = self.camera_keyframes.len() > 1;
+            if ui
+                .add_enabled(enabled, egui::Button::new("Play keyframes"))
+                .clicked()
+            {
+                self.play_camera_animation(CameraAnimation::Keyframes {
+                    keyframes: self.camera_keyframes.clone(),
+                });
+            }
+            if ui.button("Clear keyframes").clicked() {
+                self.camera_keyframes.clear();
+            }
+        });
+    }
+
+    fn scene_panel(&mut self, ui: &mut egui::Ui) {
+        self.project_ui(ui);
+        ui.separator();
+
+        let bbox = self.fluid_sim.bbox_dimensions();
+        ui.label(format!(
+            "Bounding box: {:.2} x {:.2} x {:.2}",
+            bbox.x, bbox.y, bbox.z
+        ));
+
+        egui::CollapsingHeader::new("Objects").show(ui, |ui| {
+            let objects: Vec<_> = self
+                .render_engine
+                .scene_objects()
+                .iter()
+                .map(|(handle, object)| (handle, object.name.clone(), object.visible))
+                .collect();
+            for (handle, name, mut visible) in objects {
+                if ui.checkbox(&mut visible, name).changed() {
+                    self.render_engine.set_object_visible(handle, visible);
+                }
+            }
+        });
+
+        egui::CollapsingHeader::new("Labels").show(ui, |ui| {
+            ui.checkbox(&mut self.show_annotations, "Show labels");
+            let mut removed = None;
+            for (i, annotation) in self.annotations.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut annotation.text).desired_width(100.0));
+                    ui.add(egui::DragValue::new(&mut annotation.position.x).speed(0.05));
+                    ui.add(egui::DragValue::new(&mut annotation.position.y).speed(0.05));
+                    ui.add(egui::DragValue::new(&mut annotation.position.z).speed(0.05));
+                    if ui.small_button("x").clicked() {
+                        removed = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = removed {
+                self.annotations.remove(i);
+            }
+            if ui.button("Add label at the camera target").clicked() {
+                let text = format!("Label {}", self.annotations.len() + 1);
+                self.annotations
+                    .push(Annotation::new(self.camera.target, text));
+            }
+        });
+
+        let mut mode = self.camera_controller.mode();
+        ui.horizontal(|ui| {
+            ui.label("Camera (F):");
+            ui.radio_value(&mut mode, CameraMode::Orbit, "Orbit");
+            ui.radio_value(&mut mode, CameraMode::Fly, "Fly");
+        });
+        if mode != self.camera_controller.mode() {
+            self.camera_controller.set_mode(mode, &self.camera);
+        }
+        if mode == CameraMode::Fly {
+            ui.label("WASD/QE to move, right drag to look, shift to sprint");
+        }
+
+        if mode == CameraMode::Orbit {
+            let mut target = self.camera_controller.target_goal();
+            ui.horizontal(|ui| {
+                ui.label("Target:");
+                let x = ui.add(egui::DragValue::new(&mut target.x).speed(0.05));
+                let y = ui.add(egui::DragValue::new(&mut target.y).speed(0.05));
+                let z = ui.add(egui::DragValue::new(&mut target.z).speed(0.05));
+                if x.changed() || y.changed() || z.changed() {
+                    self.camera_controller.set_target(target);
+                }
+            });
+        }
+
+        let label = format!(
+            "Frame bounds ({})",
+            self.input_map.key_label(Action::FrameBounds)
+        );
+        if ui.button(label).clicked() {
+            self.frame_bbox();
+        }
+
+        egui::ComboBox::from_label("View layout")
+            .selected_text(self.view_layout.name())
+            .show_ui(ui, |ui| {
+                for layout in ViewLayout::ALL {
+                    ui.selectable_value(&mut self.view_layout, layout, layout.name());
+                }
+            });
+
+        ui.separator();
+        self.camera_animation_ui(ui);
+        if self.timeline.is_some() {
+            ui.collapsing("Timeline", |ui| self.timeline_ui(ui));
+        }
+        ui.collapsing("Playback", |ui| self.playback_ui(ui));
+        ui.separator();
+        ui.collapsing("Background", |ui| self.background_ui(ui));
+        ui.collapsing("Post processing", |ui| self.post_process_ui(ui));
+        ui.collapsing("Controls", |ui| self.controls_ui(ui));
+        ui.separator();
+
+        let label = if self.simulation_paused {
+            "Resume"
+        } else {
+            "Pause"
+        };
+        ui.horizontal(|ui| {
+            let label = format!("{label} ({})", self.input_map.key_label(Action::Pause));
+            if ui.button(label).clicked() {
+                self.toggle_pause();
+            }
+            let label = format!("Step ({})", self.input_map.key_label(Action::Step));
+            if ui
+                .add_enabled(self.simulation_paused, egui::Button::new(label))
+                .clicked()
+            {
+                self.step_simulation();
+            }
+            let label = format!("Reset ({})", self.input_map.key_label(Action::Reset));
+            if ui.button(label).clicked() {
+                self.reset_simulation();
+            }
+        });
+
+        let label = format!(
+            "Screenshot ({})",
+            self.input_map.key_label(Action::Screenshot)
+        );
+        if ui.button(label).clicked() {
+            self.render_engine.request_screenshot();
+        }
+
+        if self.clip_recorder.is_recording() {
+            ui.add(egui::ProgressBar::new(self.clip_recorder.progress()).text("Recording"));
+        } else if self.clip_recorder.is_encoding() {
+            ui.label("Encoding clip...");
+        } else if ui.button("Record 5 seconds").clicked() {
+            self.clip_recorder.start(Duration::from_secs(5));
+        }
+
+        if let Some(scene) = &mut self.scene {
+            ui.separator();
+            scene.gui(
+                &mut SceneContext {
+                    render_device: &self.render_device,
+                    render_engine: &mut self.render_engine,
+                    fluid_sim: &mut self.fluid_sim,
+                    camera: &mut self.camera,
+                    camera_controller: &mut self.camera_controller,
+                    input_helper: None,
+                },
+                ui,
+            );
+        }
+    }
+
+    fn project_ui(&mut self, ui: &mut egui::Ui) {
+        let dialog = || rfd::FileDialog::new().add_filter("Sploosh project", &[PROJECT_EXTENSION]);
+
+        ui.horizontal(|ui| {
+            if ui.button("Open project...").clicked() {
+                if let Some(path) = dialog().pick_file() {
+                    self.open_file(&path);
+                }
+            }
+
+            if ui.button("Save project...").clicked() {
+                let path = dialog()
+                    .set_file_name(format!("scene.{PROJECT_EXTENSION}"))
+                    .save_file();
+                if let Some(path) = path {
+                    match self.project().save(&path) {
+                        Ok(()) => tracing::info!("Saved project to {}", path.display()),
+                        Err(err) => tracing::error!("Failed to save {}: {err}", path.display()),
+                    }
+                }
+            }
+
+            let has_rest_state = self.fluid_sim.config().rest_state.is_some();
+            let save_rest_state = ui
+                .add_enabled(has_rest_state, egui::Button::new("Save rest state"))
+                .on_hover_text(
+                    "The simulation starts from the current particles until the config changes",
+                )
+                .on_disabled_hover_text("Needs a rest state file in the simulation config");
+            if save_rest_state.clicked() {
+                let saved = self
+                    .fluid_sim
+                    .save_rest_state(&self.render_device.read().unwrap().wgpu_device);
+                if let Err(err) = saved {
+                    tracing::error!("Failed to save the rest state: {err}");
+                }
+            }
+        });
+    }
+
+    /// Recording toggle and a slider over the recorded frames, moving it pauses the
+    /// simulation on the chosen frame.
+    fn playback_ui(&mut self, ui: &mut egui::Ui) {
+        let mut record = self.fluid_sim.config().playback.is_some();
+        if ui.checkbox(&mut record, "Record").changed() {
+            self.fluid_sim
+                .set_playback(record.then(PlaybackConfig::default));
+        }
+        if let Some(mut playback) = self.fluid_sim.config().playback {
+            ui.horizontal(|ui| {
+                let interval = ui.add(
+                    egui::DragValue::new(&mut playback.interval)
+                        .range(1..=120)
+                        .prefix("every ")
+                        .suffix(" steps"),
+                );
+                let capacity = ui.add(
+                    egui::DragValue::new(&mut playback.capacity)
+                        .range(1..=2000)
+                        .suffix(" frames"),
+                );
+                if interval.changed() || capacity.changed() {
+                    self.fluid_sim.set_playback(Some(playback));
+                }
+            });
+        }
+
+        if self.playback_history.is_empty() {
+            ui.label("Nothing recorded yet");
+            return;
+        }
+        ui.label(format!(
+            "{} frames over {:.1} s, {:.1} MiB",
+            self.playback_history.len(),
+            self.playback_history.duration(),
+            self.playback_history.memory() as f32 / (1024.0 * 1024.0)
+        ));
+
+        let last = self.playback_history.len() - 1;
+        let mut cursor = self.playback_cursor.unwrap_or(last).min(last);
+        let time = self
+            .playback_history
+            .get(cursor)
+            .map_or(0.0, |frame| frame.time);
+        let slider = ui.add(
+            egui::Slider::new(&mut cursor, 0..=last)
+                .show_value(false)
+                .text(format!("{time:.2} s")),
+        );
+        if slider.changed() {
+            self.show_playback_frame(cursor);
+        }
+
+        ui.horizontal(|ui| {
+            let scrubbing = self.playback_cursor.is_some();
+            if ui
+                .add_enabled(scrubbing, egui::Button::new("Resume from here"))
+                .clicked()
+            {
+                self.toggle_pause();
+            }
+            if ui.button("Clear").clicked() {
+                self.playback_history.clear();
+                self.playback_cursor = None;
+            }
+        });
+    }
+
+    /// Pauses the simulation on frame `index` of the playback history.
+    fn show_playback_frame(&mut self, index: usize) {
+        let Some(frame) = self.playback_history.get(index) else {
+            return;
+        };
+        let restored = self
+            .fluid_sim
+            .restore_playback_frame(self.render_device.read().unwrap().queue(), frame);
+        match restored {
+            Ok(()) => {
+                self.simulation_paused = true;
+                self.playback_cursor = Some(index);
+            }
+            Err(err) => tracing::error!("Failed to show the recorded frame: {err}"),
+        }
+    }
+
+    /// Drops the recorded frames after the one shown, the simulation continues from it.
+    fn leave_playback(&mut self) {
+        if let Some(index) = self.playback_cursor.take() {
+            self.playback_history.truncate_after(index);
+        }
+    }
+
+    /// Events as dots on a bar up to the last one, with the current simulation time as a
+    /// cursor, and listed below.
+    fn timeline_ui(&self, ui: &mut egui::Ui) {
+        let Some(player) = &self.timeline else {
+            return;
+        };
+        let timeline = player.timeline();
+        let time = self.fluid_sim.sim_time();
+        let duration = timeline.duration().max(time).max(f32::EPSILON);
+
+        let (rect, _) =
+            ui.allocate_exact_size(egui::vec2(ui.available_width(), 20.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        let x = |t: f32| rect.left() + rect.width() * t / duration;
+        painter.hline(
+            rect.x_range(),
+            rect.center().y,
+            ui.visuals().widgets.noninteractive.fg_stroke,
+        );
+        for (i, event) in timeline.events.iter().enumerate() {
+            let color = match player.is_done(i) {
+                true => ui.visuals().weak_text_color(),
+                false => ui.visuals().strong_text_color(),
+            };
+            painter.circle_filled(egui::pos2(x(event.time), rect.center().y), 4.0, color);
+        }
+        painter.vline(
+            x(time),
+            rect.y_range(),
+            egui::Stroke::new(2.0, ui.visuals().selection.bg_fill),
+        );
+
+        egui::Grid::new("timeline_events")
+            .striped(true)
+            .show(ui, |ui| {
+                for (i, event) in timeline.events.iter().enumerate() {
+                    ui.label(format!("{:.2} s", event.time));
+                    ui.label(event.action.name());
+                    ui.label(if player.is_done(i) { "done" } else { "" });
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn background_ui(&mut self, ui: &mut egui::Ui) {
+        let mut settings = self.render_engine.render_settings().clone();
+
+        let options = [
+            Background::default(),
+            Background::Gradient {
+                top: [0.35, 0.55, 0.85],
+                bottom: [0.85, 0.85, 0.9],
+            },
+            Background::Environment {
+                path: self.environment_path.clone().into(),
+            },
+        ];
+        egui::ComboBox::from_label("Background")
+            .selected_text(settings.background.name())
+            .show_ui(ui, |ui| {
+                for option in options {
+                    let selected = option.name() == settings.background.name();
+                    if ui.selectable_label(selected, option.name()).clicked() && !selected {
+                        settings.background = option;
+                    }
+                }
+            });
+
+        match &mut settings.background {
+            Background::Solid { color } => {
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(color);
+                    ui.label("Color");
+                });
+            }
+            Background::Gradient { top, bottom } => {
+                ui.horizontal(|ui| {
+                    ui.color_edit_button_rgb(top);
+                    ui.label("Top");
+                    ui.color_edit_button_rgb(bottom);
+                    ui.label("Bottom");
+                });
+            }
+            Background::Environment { .. } => {}
+        }
+
+        ui.add(Slider::new(&mut settings.lines.width, 0.5..=8.0).text("Line width"));
+        ui.add(Slider::new(&mut settings.lines.dash_length, 1.0..=32.0).text("Dash length"));
+        ui.add(Slider::new(&mut settings.lines.gap_length, 1.0..=32.0).text("Dash gap"));
+
+        // the path can be edited before the map is loaded for the first time
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.environment_path);
+            if ui.button("Load").clicked() {
+                settings.background = Background::Environment {
+                    path: self.environment_path.clone().into(),
+                };
+            }
+        });
+
+        if settings != *self.render_engine.render_settings() {
+            if let Err(err) = self.render_engine.set_render_settings(settings) {
+                tracing::error!("Failed to load the background: {err}");
+            }
+        }
+    }
+
+    fn controls_ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("input_map").striped(true).show(ui, |ui| {
+            for action in Action::ALL {
+                ui.label(action.name());
+                if self.rebinding == Some(action) {
+                    ui.label("Press a key, Escape cancels");
+                } else {
+                    ui.label(self.input_map.key_label(action));
+                }
+                if ui.button("Rebind").clicked() {
+                    self.rebinding = Some(action);
+                }
+                if ui.button("Clear").clicked() {
+                    self.input_map.unbind(action);
+                }
+                ui.end_row();
+            }
+        });
+
+        if ui.button("Restore defaults").clicked() {
+            self.input_map = InputMap::default();
+        }
+    }
+
+    fn post_process_ui(&mut self, ui: &mut egui::Ui) {
+        let mut settings = self.render_engine.post_process_settings();
+
+        ui.add(Slider::new(&mut settings.exposure, 0.1..=4.0).text("Exposure"));
+        egui::ComboBox::from_label("Tonemapping")
+            .selected_text(settings.tonemapping.name())
+            .show_ui(ui, |ui| {
+                for tonemapping in Tonemapping::ALL {
+                    ui.selectable_value(&mut settings.tonemapping, tonemapping, tonemapping.name());
+                }
+            });
+
+        let mut bloom_enabled = settings.bloom.is_some();
+        ui.checkbox(&mut bloom_enabled, "Bloom");
+        settings.bloom = bloom_enabled.then(|| settings.bloom.unwrap_or_default());
+        if let Some(bloom) = &mut settings.bloom {
+            ui.add(Slider::new(&mut bloom.threshold, 0.0..=2.0).text("Threshold"));
+            ui.add(Slider::new(&mut bloom.intensity, 0.0..=2.0).text("Intensity"));
+        }
+
+        let mut ssao_enabled = settings.ssao.is_some();
+        ui.checkbox(&mut ssao_enabled, "Ambient occlusion");
+        settings.ssao = ssao_enabled.then(|| settings.ssao.unwrap_or_default());
+        if let Some(ssao) = &mut settings.ssao {
+            ui.add(Slider::new(&mut ssao.radius, 0.01..=0.5).text("Radius"));
+            ui.add(Slider::new(&mut ssao.intensity, 0.0..=1.0).text("Intensity"));
+        }
+
+        ui.add(Slider::new(&mut settings.vignette, 0.0..=1.0).text("Vignette"));
+
+        if settings != self.render_engine.post_process_settings() {
+            self.render_engine.set_post_process_settings(settings);
+        }
+    }
+
+    fn profiler_panel(&mut self, ui: &mut egui::Ui) {
+        let plot_points = |times: &VecDeque<f32>| -> PlotPoints {
+            times
+                .iter()
+                .enumerate()
+                .map(|(i, &time)| [i as f64, time as f64])
+                .collect()
+        };
+
+        let line = Line::new(plot_points(&self.frame_times))
+            .color(egui::Color32::LIGHT_BLUE)
+            .name("CPU Frame Time (ms)");
+        let gpu_line = Line::new(plot_points(&self.gpu_frame_times))
+            .color(egui::Color32::LIGHT_GREEN)
+            .name("GPU Frame Time (ms)");
+
+        Plot::new("frame_time_plot")
+            .view_aspect(2.0)
+            .legend(egui_plot::Legend::default())
+            .show(ui, |plot_ui| {
+                plot_ui.line(line);
+                plot_ui.line(gpu_line);
+            });
+    }
+
+    fn inspector_panel(&mut self, ui: &mut egui::Ui) {
+        let Some(particle) = self.fluid_sim.selected_particle() else {
+            ui.label("Click a particle to inspect it");
+            return;
+        };
+
+        ui.label(format!("Particle: {particle}"));
+        if let Some(sample) = &self.selected_sample {
+            let p = sample.position;
+            let v = sample.velocity;
+            ui.label(format!("Position: {:.3}, {:.3}, {:.3}", p.x, p.y, p.z));
+            ui.label(format!(
+                "Velocity: {:.3}, {:.3}, {:.3} ({:.3})",
+                v.x,
+                v.y,
+                v.z,
+                v.norm()
+            ));
+            ui.label(format!("Density: {:.2}", sample.density));
+            ui.label(format!("Pressure: {:.2}", sample.pressure));
+            ui.label(format!("Neighbors: {}", sample.neighbor_count));
+        }
+
+        if ui
+            .checkbox(&mut self.show_particle_trail, "Trail")
+            .changed()
+        {
+            self.particle_trail.clear();
+        }
+        if ui.button("Deselect").clicked() {
+            self.select_particle(None);
+        }
+    }
+
+    fn console_panel(ui: &mut egui::Ui) {
+        let console = Console::global();
+        let entries = console.entries();
+        if entries.is_empty() {
+            ui.label("No warnings or errors");
+            return;
+        }
+        if ui.button("Clear").clicked() {
+            console.clear();
+        }
+
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for entry in &entries {
+                    let color = if entry.level == tracing::Level::ERROR {
+                        ui.visuals().error_fg_color
+                    } else {
+                        ui.visuals().warn_fg_color
+                    };
+                    let mut text = format!("[{}] {}", entry.target, entry.message);
+                    if entry.count > 1 {
+                        text.push_str(&format!(" (x{})", entry.count));
+                    }
+                    ui.label(egui::RichText::new(text).color(color).monospace());
+                }
+            });
+    }
+
+    /// Probe list and the time series of the selected quantity, one line per probe.
+    fn probes_panel(&mut self, ui: &mut egui::Ui) {
+        // the newest samples of each probe in the plot
+        const PLOTTED_SAMPLES: usize = 2000;
+
+        let mut probes = self.fluid_sim.config().probes.clone();
+        let mut removed = None;
+        for (i, probe) in probes.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut probe.name).desired_width(80.0));
+                ui.add(egui::DragValue::new(&mut probe.position.x).speed(0.05));
+                ui.add(egui::DragValue::new(&mut probe.position.y).speed(0.05));
+                ui.add(egui::DragValue::new(&mut probe.position.z).speed(0.05));
+
+                let mut plane = matches!(probe.shape, ProbeShape::Plane { .. });
+                if ui.checkbox(&mut plane, "Plane").changed() {
+                    probe.shape = if plane {
+                        ProbeShape::Plane {
+                            axis: SliceAxis::Y,
+                            size: 1.0,
+                        }
+                    } else {
+                        ProbeShape::Point
+                    };
+                }
+                if let ProbeShape::Plane { axis, size } = &mut probe.shape {
+                    egui::ComboBox::from_id_salt(("probe_axis", i))
+                        .selected_text(axis.name())
+                        .width(40.0)
+                        .show_ui(ui, |ui| {
+                            for a in SliceAxis::ALL {
+                                ui.selectable_value(axis, a, a.name());
+                            }
+                        });
+                    ui.add(
+                        egui::DragValue::new(size)
+                            .speed(0.05)
+                            .range(0.01..=100.0)
+                            .prefix("size "),
+                    );
+                }
+                if ui.small_button("x").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            probes.remove(i);
+        }
+        if probes.len() > MAX_PROBES {
+            ui.label(format!("Only the first {MAX_PROBES} probes are sampled"));
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Add probe at the camera target").clicked() {
+                probes.push(ProbeConfig {
+                    name: format!("Probe {}", probes.len() + 1),
+                    position: self.camera.target,
+                    shape: ProbeShape::Point,
+                });
+            }
+            if ui.button("Clear samples").clicked() {
+                self.probe_history.clear();
+            }
+            let export = ui.add_enabled(
+                !self.probe_history.is_empty(),
+                egui::Button::new("Export CSV..."),
+            );
+            if export.clicked() {
+                let path = rfd::FileDialog::new()
+                    .add_filter("CSV", &["csv"])
+                    .set_file_name("probes.csv")
+                    .save_file();
+                if let Some(path) = path {
+                    match export_probes(&path, &probes, &self.probe_history) {
+                        Ok(()) => tracing::info!("Saved probe samples to {}", path.display()),
+                        Err(err) => tracing::error!("Failed to save {}: {err}", path.display()),
+                    }
+                }
+            }
+        });
+        if probes != self.fluid_sim.config().probes {
+            self.fluid_sim.set_probes(probes);
+        }
+
+        ui.horizontal(|ui| {
+            for quantity in ProbeQuantity::ALL {
+                ui.radio_value(&mut self.probe_quantity, quantity, quantity.name());
+            }
+        });
+
+        let quantity = self.probe_quantity;
+        let probes = &self.fluid_sim.config().probes;
+        Plot::new("probe_plot")
+            .view_aspect(3.0)
+            .legend(egui_plot::Legend::default())
+            .x_axis_label("Time (s)")
+            .show(ui, |plot_ui| {
+                for (i, probe) in probes.iter().take(MAX_PROBES).enumerate() {
+                    let Some(series) = self.probe_history.series(i) else {
+                        continue;
+                    };
+                    let points: PlotPoints = series
+                        .iter()
+                        .skip(series.len().saturating_sub(PLOTTED_SAMPLES))
+                        .map(|sample| [sample.time as f64, quantity.value(sample) as f64])
+                        .collect();
+                    plot_ui.line(Line::new(points).name(&probe.name));
+                }
+            });
+
+        egui::CollapsingHeader::new("Wave gauges").show(ui, |ui| self.wave_gauges_ui(ui));
+    }
+
+    /// Gauge list and the surface height at each gauge over time, one line per gauge.
+    fn wave_gauges_ui(&mut self, ui: &mut egui::Ui) {
+        const PLOTTED_SAMPLES: usize = 2000;
+
+        let mut gauges = self.fluid_sim.config().wave_gauges.clone();
+        let mut removed = None;
+        for (i, gauge) in gauges.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut gauge.name).desired_width(80.0));
+                ui.add(
+                    egui::DragValue::new(&mut gauge.position.x)
+                        .speed(0.05)
+                        .prefix("x "),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut gauge.position.y)
+                        .speed(0.05)
+                        .prefix("z "),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut gauge.radius)
+                        .speed(0.01)
+                        .range(0.01..=10.0)
+                        .prefix("radius "),
+                );
+                if ui.small_button("x").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            gauges.remove(i);
+        }
+        if gauges.len() > MAX_WAVE_GAUGES {
+            ui.label(format!(
+                "Only the first {MAX_WAVE_GAUGES} gauges are measured"
+            ));
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Add gauge at the camera target").clicked() {
+                gauges.push(WaveGaugeConfig {
+                    name: format!("Gauge {}", gauges.len() + 1),
+                    position: self.camera.target.xz(),
+                    ..Default::default()
+                });
+            }
+            if ui.button("Clear samples").clicked() {
+                self.wave_gauge_history.clear();
+            }
+            let export = ui.add_enabled(
+                !self.wave_gauge_history.is_empty(),
+                egui::Button::new("Export CSV..."),
+            );
+            if export.clicked() {
+                let path = rfd::FileDialog::new()
+                    .add_filter("CSV", &["csv"])
+                    .set_file_name("wave_gauges.csv")
+                    .save_file();
+                if let Some(path) = path {
+                    match export_wave_gauges(&path, &gauges, &self.wave_gauge_history) {
+                        Ok(()) => tracing::info!("Saved wave gauge samples to {}", path.display()),
+                        Err(err) => tracing::error!("Failed to save {}: {err}", path.display()),
+                    }
+                }
+            }
+        });
+        if gauges != self.fluid_sim.config().wave_gauges {
+            self.fluid_sim.set_wave_gauges(gauges);
+        }
+
+        let gauges = &self.fluid_sim.config().wave_gauges;
+        Plot::new("wave_gauge_plot")
+            .view_aspect(3.0)
+            .legend(egui_plot::Legend::default())
+            .x_axis_label("Time (s)")
+            .y_axis_label("Height")
+            .show(ui, |plot_ui| {
+                for (i, gauge) in gauges.iter().take(MAX_WAVE_GAUGES).enumerate() {
+                    let Some(series) = self.wave_gauge_history.series(i) else {
+                        continue;
+                    };
+                    let points: PlotPoints = series
+                        .iter()
+                        .skip(series.len().saturating_sub(PLOTTED_SAMPLES))
+                        .filter_map(|sample| Some([sample.time as f64, sample.height? as f64]))
+                        .collect();
+                    plot_ui.line(Line::new(points).name(&gauge.name));
+                }
+            });
+    }
+
+    fn kernel_editor_panel(&mut self, ui: &mut egui::Ui) {
+        let render_device = self.render_device.read().unwrap();
+        let device = &render_device.wgpu_device.device;
+        let kernels = render_device.wgpu_device.kernels.kernels();
+
+        // the task was dropped, e.g. because the simulation was rebuilt
+        if self
+            .kernel_edit
+            .as_ref()
+            .is_some_and(|edit| edit.kernel.strong_count() == 0)
+        {
+            self.kernel_edit = None;
+        }
+
+        let selected = self
+            .kernel_edit
+            .as_ref()
+            .and_then(|edit| edit.kernel.upgrade());
+        egui::ComboBox::from_label("Kernel")
+            .selected_text(selected.as_ref().map_or("None", |kernel| kernel.name()))
+            .show_ui(ui, |ui| {
+                for kernel in &kernels {
+                    let is_selected = selected
+                        .as_ref()
+                        .is_some_and(|selected| Arc::ptr_eq(selected, kernel));
+                    let label = if kernel.is_edited() {
+                        format!("{} (edited)", kernel.name())
+                    } else {
+                        kernel.name().to_string()
+                    };
+                    if ui.selectable_label(is_selected, label).clicked() {
+                        self.kernel_edit = Some(KernelEdit {
+                            kernel: Arc::downgrade(kernel),
+                            source: kernel.source(),
+                            error: None,
+                        });
+                    }
+                }
+            });
+
+        let Some(edit) = &mut self.kernel_edit else {
+            return;
+        };
+        let Some(kernel) = edit.kernel.upgrade() else {
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            if ui.button("Compile").clicked() {
+                edit.error = kernel.recompile(device, &edit.source).err();
+            }
+            if ui
+                .add_enabled(kernel.is_edited(), egui::Button::new("Revert"))
+                .clicked()
+            {
+                edit.source = kernel.generated_source().to_string();
+                edit.error = kernel.recompile(device, &edit.source).err();
+            }
+        });
+        if let Some(error) = &edit.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        egui::ScrollArea::both().max_height(400.0).show(ui, |ui| {
+            ui.add(
+                egui::TextEdit::multiline(&mut edit.source)
+                    .code_editor()
+                    .desired_width(f32::INFINITY)
+                    .desired_rows(30),
+            );
+        });
+    }
+
+    fn system_panel(&mut self, ui: &mut egui::Ui) {
+        let render_device = self.render_device.read().unwrap();
+        let wgpu_device = &render_device.wgpu_device;
+        let info = wgpu_device.adapter.get_info();
+
+        ui.label(format!("Adapter: {}", info.name));
+        ui.label(format!("Backend: {:?}", info.backend));
+        ui.label(format!("Device type: {:?}", info.device_type));
+        if !info.driver.is_empty() {
+            ui.label(format!("Driver: {} {}", info.driver, info.driver_info));
+        }
+
+        ui.collapsing("Limits", |ui| {
+            let limits = wgpu_device.device.limits();
+            egui::Grid::new("adapter_limits")
+                .striped(true)
+                .show(ui, |ui| {
+                    let rows = [
+                        ("Max buffer size", limits.max_buffer_size),
+                        (
+                            "Max storage binding size",
+                            limits.max_storage_buffer_binding_size as u64,
+                        ),
+                        (
+                            "Max storage buffers per stage",
+                            limits.max_storage_buffers_per_shader_stage as u64,
+                        ),
+                        (
+                            "Max workgroup invocations",
+                            limits.max_compute_invocations_per_workgroup as u64,
+                        ),
+                        (
+                            "Max workgroups per dimension",
+                            limits.max_compute_workgroups_per_dimension as u64,
+                        ),
+                        ("Max texture size", limits.max_texture_dimension_2d as u64),
+                        (
+                            "Max push constant size",
+                            limits.max_push_constant_size as u64,
+                        ),
+                    ];
+                    for (name, value) in rows {
+                        ui.label(name);
+                        ui.label(value.to_string());
+                        ui.end_row();
+                    }
+                });
+        });
+
+        let max_particle_cnt = self
+            .fluid_sim
+            .config()
+            .max_particle_cnt(&wgpu_device.device.limits())
+            .map_or_else(|err| err.to_string(), |cnt| cnt.to_string());
+        ui.label(format!("Max particles: {max_particle_cnt}"))
+            .on_hover_text("Fluid particles that fit into the limits and the memory budget");
+
+        ui.collapsing("Features", |ui| {
+            let enabled = wgpu_device.device.features();
+            for (name, feature) in wgpu_device.adapter.features().iter_names() {
+                let mut is_enabled = enabled.contains(feature);
+                ui.add_enabled(false, egui::Checkbox::new(&mut is_enabled, name));
+            }
+        });
+
+        let resources = wgpu_device.resources.resources();
+        let total_size: u64 = resources.iter().map(|resource| resource.size).sum();
+        let mib = |bytes: u64| bytes as f32 / (1024.0 * 1024.0);
+        ui.collapsing(format!("GPU memory: {:.1} MiB", mib(total_size)), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("gpu_resources")
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for resource in &resources {
+                                ui.label(&resource.label);
+                                ui.label(format!("{:.2} MiB", mib(resource.size)));
+                                ui.label(match resource.kind {
+                                    ResourceKind::Buffer(usage) => format!("{usage:?}"),
+                                    ResourceKind::Texture(usage) => format!("{usage:?}"),
+                                });
+                                ui.end_row();
+                            }
+                        });
+                });
+        });
+
+        ui.separator();
+        // recovery is otherwise hard to trigger on purpose
+        if ui
+            .button("Simulate device loss")
+            .on_hover_text("Destroys the device, the application recreates it and continues from the last particle snapshot")
+            .clicked()
+        {
+            wgpu_device.device.destroy();
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        if self.simulation_paused {
+            self.prev_time = Instant::now();
+            self.leave_playback();
+        }
+        self.simulation_paused = !self.simulation_paused;
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = match self.window.fullscreen() {
+            Some(_) => None,
+            None => Some(Fullscreen::Borderless(None)),
+        };
+        self.window.set_fullscreen(fullscreen);
+    }
+}
+
+fn save_screenshot(image: &RgbaImage) -> Result<PathBuf, SplooshError> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = PathBuf::from(SCREENSHOT_DIR).join(format!("screenshot_{timestamp}.png"));
+
+    std::fs::create_dir_all(SCREENSHOT_DIR)?;
+    image.save(&path)?;
+
+    Ok(path)
 }
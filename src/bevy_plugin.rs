@@ -0,0 +1,376 @@
+use bevy::{
+    app::{App, Plugin},
+    asset::{load_internal_asset, Handle},
+    core_pipeline::core_3d::{Transparent3d, CORE_3D_DEPTH_FORMAT},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::With,
+        schedule::IntoSystemConfigs,
+        system::{lifetimeless::SRes, Query, Res, ResMut, Resource, SystemParamItem},
+        world::{FromWorld, World},
+    },
+    pbr::{MeshPipeline, MeshPipelineKey},
+    render::{
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        graph::CameraDriverLabel,
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext, RenderLabel},
+        render_phase::{
+            AddRenderCommand, DrawFunctions, PhaseItem, PhaseItemExtraIndex, RenderCommand,
+            RenderCommandResult, SetItemPipeline, TrackedRenderPass, ViewSortedRenderPhases,
+        },
+        render_resource::{
+            BlendState, ColorTargetState, ColorWrites, CompareFunction, DepthStencilState,
+            FragmentState, MultisampleState, PipelineCache, PrimitiveState, PrimitiveTopology,
+            RenderPipelineDescriptor, Shader, SpecializedRenderPipeline,
+            SpecializedRenderPipelines, TextureFormat, VertexAttribute, VertexBufferLayout,
+            VertexFormat, VertexState, VertexStepMode,
+        },
+        renderer::{RenderAdapter, RenderContext, RenderDevice, RenderQueue},
+        settings::{WgpuFeatures, WgpuLimits, WgpuSettings},
+        sync_world::MainEntity,
+        texture::BevyDefault,
+        view::{ExtractedView, Msaa, ViewTarget},
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    fluid_simulation::{FluidSimulationConfig, ParticleCulling},
+    graphics::{camera::Camera, materials::ColoredVertex},
+    wgpu_device::{Shared, MAX_STORAGE_BUFFERS},
+    FluidSimulation, WgpuDevice,
+};
+
+const PARTICLE_SHADER_HANDLE: Handle<Shader> =
+    Handle::weak_from_u128(0x5a1e_63c0_9b2f_4d17_8e45_c1f0_2d3b_7a96);
+
+/// Runs a `FluidSimulation` on the render device of Bevy. The simulation steps once per frame
+/// before the cameras render, and every entity with `FluidParticles` draws the particles in
+/// world space. The device has to support push constants, see `SplooshPlugin::wgpu_settings`.
+pub struct SplooshPlugin {
+    pub config: FluidSimulationConfig,
+}
+
+impl SplooshPlugin {
+    /// Settings for `RenderPlugin` with the features and limits the simulation needs.
+    pub fn wgpu_settings() -> WgpuSettings {
+        WgpuSettings {
+            features: WgpuFeatures::PUSH_CONSTANTS,
+            limits: WgpuLimits {
+                max_push_constant_size: 16,
+                max_storage_buffers_per_shader_stage: MAX_STORAGE_BUFFERS,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl Plugin for SplooshPlugin {
+    fn build(&self, app: &mut App) {
+        load_internal_asset!(
+            app,
+            PARTICLE_SHADER_HANDLE,
+            "shaders/bevy_particles.wgsl",
+            Shader::from_wgsl
+        );
+
+        app.init_resource::<SplooshControl>().add_plugins((
+            ExtractComponentPlugin::<FluidParticles>::default(),
+            ExtractResourcePlugin::<SplooshControl>::default(),
+        ));
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .add_render_command::<Transparent3d, DrawParticles>()
+            .init_resource::<SpecializedRenderPipelines<ParticlePipeline>>()
+            .add_systems(Render, queue_particles.in_set(RenderSet::Queue));
+
+        let mut graph = render_app.world_mut().resource_mut::<RenderGraph>();
+        graph.add_node(SplooshStepLabel, SplooshStepNode);
+        graph.add_node_edge(SplooshStepLabel, CameraDriverLabel);
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+
+        let world = render_app.world();
+        let wgpu_device = WgpuDevice::from_shared(
+            Shared::new(BevyAdapter(world.resource::<RenderAdapter>().clone())),
+            Shared::new(BevyDevice(world.resource::<RenderDevice>().clone())),
+            Shared::new(BevyQueue(world.resource::<RenderQueue>().clone())),
+        );
+
+        // the display pass has no view of the Bevy cameras, so every particle is kept
+        let config = FluidSimulationConfig {
+            culling: ParticleCulling::DISABLED,
+            ..self.config.clone()
+        };
+        let fluid_sim = FluidSimulation::new(config, &wgpu_device);
+
+        render_app
+            .insert_resource(SplooshSimulation {
+                wgpu_device,
+                fluid_sim,
+            })
+            .init_resource::<ParticlePipeline>();
+    }
+}
+
+struct BevyDevice(RenderDevice);
+
+impl std::ops::Deref for BevyDevice {
+    type Target = wgpu::Device;
+
+    fn deref(&self) -> &wgpu::Device {
+        self.0.wgpu_device()
+    }
+}
+
+struct BevyQueue(RenderQueue);
+
+impl std::ops::Deref for BevyQueue {
+    type Target = wgpu::Queue;
+
+    fn deref(&self) -> &wgpu::Queue {
+        &self.0
+    }
+}
+
+struct BevyAdapter(RenderAdapter);
+
+impl std::ops::Deref for BevyAdapter {
+    type Target = wgpu::Adapter;
+
+    fn deref(&self) -> &wgpu::Adapter {
+        &self.0
+    }
+}
+
+/// Stepping of the simulation, set from the main world.
+#[derive(Resource, ExtractResource, Clone, Debug)]
+pub struct SplooshControl {
+    pub paused: bool,
+    /// Simulated seconds per frame
+    pub dt: f32,
+}
+
+impl Default for SplooshControl {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            dt: 1.0 / 60.0,
+        }
+    }
+}
+
+/// Marks an entity that draws the particles. Its transform is ignored, the particles are
+/// placed at their simulation coordinates.
+#[derive(Component, ExtractComponent, Clone, Copy, Debug, Default)]
+pub struct FluidParticles;
+
+/// The simulation in the render world.
+#[derive(Resource)]
+pub struct SplooshSimulation {
+    pub wgpu_device: WgpuDevice,
+    pub fluid_sim: FluidSimulation,
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+struct SplooshStepLabel;
+
+/// Steps the simulation and fills the display buffer read by `DrawParticles`.
+struct SplooshStepNode;
+
+impl Node for SplooshStepNode {
+    fn run<'w>(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext<'w>,
+        world: &'w World,
+    ) -> Result<(), NodeRunError> {
+        let Some(sim) = world.get_resource::<SplooshSimulation>() else {
+            return Ok(());
+        };
+        let control = world
+            .get_resource::<SplooshControl>()
+            .cloned()
+            .unwrap_or_default();
+
+        // bevy submits the encoder itself, the uploads of the previous frame were submitted
+        // by now
+        let uploader = &sim.wgpu_device.uploader;
+        uploader.recall();
+
+        let queue = &sim.wgpu_device.queue;
+        let encoder = render_context.command_encoder();
+        if !control.paused {
+            sim.fluid_sim.step_fn(control.dt)(encoder, queue);
+        }
+        sim.fluid_sim.display_fn(&Camera::new(), 1.0, false)(encoder, queue);
+        uploader.finish();
+
+        Ok(())
+    }
+}
+
+#[derive(Resource)]
+struct ParticlePipeline {
+    mesh_pipeline: MeshPipeline,
+}
+
+impl FromWorld for ParticlePipeline {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            mesh_pipeline: world.resource::<MeshPipeline>().clone(),
+        }
+    }
+}
+
+impl SpecializedRenderPipeline for ParticlePipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let format = if key.contains(MeshPipelineKey::HDR) {
+            ViewTarget::TEXTURE_FORMAT_HDR
+        } else {
+            TextureFormat::bevy_default()
+        };
+
+        // the particle position with the sprite scale in w, then the color
+        let instance_layout = VertexBufferLayout {
+            array_stride: std::mem::size_of::<ColoredVertex>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: vec![
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 16,
+                    shader_location: 1,
+                },
+            ],
+        };
+
+        RenderPipelineDescriptor {
+            label: Some("Sploosh particle pipeline".into()),
+            layout: vec![self.mesh_pipeline.get_view_layout(key.into()).clone()],
+            push_constant_ranges: Vec::new(),
+            vertex: VertexState {
+                shader: PARTICLE_SHADER_HANDLE,
+                shader_defs: Vec::new(),
+                entry_point: "vs_main".into(),
+                buffers: vec![instance_layout],
+            },
+            fragment: Some(FragmentState {
+                shader: PARTICLE_SHADER_HANDLE,
+                shader_defs: Vec::new(),
+                entry_point: "fs_main".into(),
+                targets: vec![Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            // Bevy uses a reversed depth buffer
+            depth_stencil: Some(DepthStencilState {
+                format: CORE_3D_DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::GreaterEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: MultisampleState {
+                count: key.msaa_samples(),
+                ..Default::default()
+            },
+            zero_initialize_workgroup_memory: false,
+        }
+    }
+}
+
+fn queue_particles(
+    draw_functions: Res<DrawFunctions<Transparent3d>>,
+    particle_pipeline: Res<ParticlePipeline>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<ParticlePipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    sim: Option<Res<SplooshSimulation>>,
+    particles: Query<(Entity, &MainEntity), With<FluidParticles>>,
+    mut phases: ResMut<ViewSortedRenderPhases<Transparent3d>>,
+    views: Query<(Entity, &ExtractedView, &Msaa)>,
+) {
+    // the simulation is only created once the render device exists
+    if sim.is_none() {
+        return;
+    }
+    let draw_particles = draw_functions.read().id::<DrawParticles>();
+
+    for (view_entity, view, msaa) in &views {
+        let Some(phase) = phases.get_mut(&view_entity) else {
+            continue;
+        };
+        let key = MeshPipelineKey::from_msaa_samples(msaa.samples())
+            | MeshPipelineKey::from_hdr(view.hdr);
+        let pipeline = pipelines.specialize(&pipeline_cache, &particle_pipeline, key);
+        // the domain is centered around the origin
+        let distance = view
+            .rangefinder3d()
+            .distance_translation(&bevy::math::Vec3::ZERO);
+
+        for (entity, main_entity) in &particles {
+            phase.add(Transparent3d {
+                entity: (entity, *main_entity),
+                pipeline,
+                draw_function: draw_particles,
+                distance,
+                batch_range: 0..1,
+                extra_index: PhaseItemExtraIndex::NONE,
+            });
+        }
+    }
+}
+
+type DrawParticles = (
+    SetItemPipeline,
+    bevy::pbr::SetMeshViewBindGroup<0>,
+    DrawParticleInstances,
+);
+
+struct DrawParticleInstances;
+
+impl<P: PhaseItem> RenderCommand<P> for DrawParticleInstances {
+    type Param = SRes<SplooshSimulation>;
+    type ViewQuery = ();
+    type ItemQuery = ();
+
+    fn render<'w>(
+        _item: &P,
+        _view: (),
+        _entity: Option<()>,
+        sim: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let fluid_sim = &sim.into_inner().fluid_sim;
+
+        // the buffers belong to the simulation, not to Bevy, so they are bound on the raw pass
+        // and the tracked state doesn't know about them
+        let pass = pass.wgpu_pass();
+        pass.set_vertex_buffer(0, fluid_sim.display_buffer().slice(..));
+        pass.draw_indirect(fluid_sim.draw_args(), 0);
+
+        RenderCommandResult::Success
+    }
+}
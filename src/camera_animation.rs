@@ -0,0 +1,181 @@
+use std::ops::Deref;
+
+use serde::{Deserialize, Serialize};
+
+use crate::camera_controller::OrbitState;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    pub const ALL: [Easing; 4] = [
+        Easing::Linear,
+        Easing::EaseIn,
+        Easing::EaseOut,
+        Easing::EaseInOut,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Easing::Linear => "Linear",
+            Easing::EaseIn => "Ease in",
+            Easing::EaseOut => "Ease out",
+            Easing::EaseInOut => "Ease in-out",
+        }
+    }
+
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub orbit: OrbitState,
+    /// Easing used when moving from the previous keyframe to this one
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+/// Keyframes sorted by time, so an animation can look up the current pair with a binary
+/// search. Lists read from a file are sorted once, `insert` keeps them sorted.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<CameraKeyframe>", into = "Vec<CameraKeyframe>")]
+pub struct CameraKeyframes(Vec<CameraKeyframe>);
+
+impl CameraKeyframes {
+    /// Adds the keyframe after the ones with the same or an earlier time.
+    pub fn insert(&mut self, keyframe: CameraKeyframe) {
+        let i = self.0.partition_point(|k| k.time <= keyframe.time);
+        self.0.insert(i, keyframe);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+impl Deref for CameraKeyframes {
+    type Target = [CameraKeyframe];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Vec<CameraKeyframe>> for CameraKeyframes {
+    fn from(mut keyframes: Vec<CameraKeyframe>) -> Self {
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self(keyframes)
+    }
+}
+
+impl From<CameraKeyframes> for Vec<CameraKeyframe> {
+    fn from(keyframes: CameraKeyframes) -> Self {
+        keyframes.0
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CameraAnimation {
+    /// Orbits around the target at a constant angular speed in radians per second
+    Turntable {
+        speed: f32,
+    },
+    Keyframes {
+        keyframes: CameraKeyframes,
+    },
+}
+
+impl CameraAnimation {
+    /// Length of the animation in seconds, the turntable never ends.
+    pub fn duration(&self) -> Option<f32> {
+        match self {
+            CameraAnimation::Turntable { .. } => None,
+            CameraAnimation::Keyframes { keyframes } => {
+                Some(keyframes.last().map_or(0.0, |k| k.time.max(0.0)))
+            }
+        }
+    }
+
+    pub fn sample(&self, time: f32, start: &OrbitState) -> OrbitState {
+        match self {
+            CameraAnimation::Turntable { speed } => OrbitState {
+                phi: start.phi + speed * time,
+                ..*start
+            },
+            CameraAnimation::Keyframes { keyframes } => sample_keyframes(keyframes, time, start),
+        }
+    }
+}
+
+fn sample_keyframes(keyframes: &[CameraKeyframe], time: f32, start: &OrbitState) -> OrbitState {
+    let (Some(first), Some(last)) = (keyframes.first(), keyframes.last()) else {
+        return *start;
+    };
+
+    if time <= first.time {
+        return first.orbit;
+    }
+    if time >= last.time {
+        return last.orbit;
+    }
+
+    let i = keyframes.partition_point(|k| k.time <= time);
+    let (from, to) = (&keyframes[i - 1], &keyframes[i]);
+
+    let t = to.easing.apply((time - from.time) / (to.time - from.time));
+    let lerp = |a: f32, b: f32| a + (b - a) * t;
+
+    OrbitState {
+        radius: lerp(from.orbit.radius, to.orbit.radius),
+        phi: lerp(from.orbit.phi, to.orbit.phi),
+        theta: lerp(from.orbit.theta, to.orbit.theta),
+        target: from.orbit.target + (to.orbit.target - from.orbit.target) * t,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f32, radius: f32) -> CameraKeyframe {
+        CameraKeyframe {
+            time,
+            orbit: OrbitState {
+                radius,
+                ..Default::default()
+            },
+            easing: Easing::Linear,
+        }
+    }
+
+    #[test]
+    fn keyframes_stay_sorted() {
+        let mut keyframes = CameraKeyframes::from(vec![keyframe(2.0, 3.0), keyframe(0.0, 1.0)]);
+        keyframes.insert(keyframe(1.0, 2.0));
+
+        let times: Vec<f32> = keyframes.iter().map(|k| k.time).collect();
+        assert_eq!(times, [0.0, 1.0, 2.0]);
+
+        let animation = CameraAnimation::Keyframes { keyframes };
+        assert_eq!(animation.duration(), Some(2.0));
+        let orbit = animation.sample(1.5, &OrbitState::default());
+        assert!((orbit.radius - 2.5).abs() < 1e-5);
+    }
+}
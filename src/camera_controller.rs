@@ -1,40 +1,277 @@
 use core::f32;
 
+use nalgebra::{Point3, Vector3};
+use serde::{Deserialize, Serialize};
+use winit::{
+    event::MouseButton,
+    keyboard::{KeyCode, PhysicalKey},
+};
+
 use crate::{graphics::Camera, input_helper::InputHelper};
 
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OrbitState {
+    pub radius: f32,
+    pub phi: f32,
+    pub theta: f32,
+    pub target: Point3<f32>,
+}
+
+impl Default for OrbitState {
+    fn default() -> Self {
+        Self {
+            radius: 10.0,
+            phi: 0.0,
+            theta: f32::consts::FRAC_2_PI,
+            target: Point3::origin(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+    Orbit,
+    Fly,
+}
+
 pub struct CameraController {
+    mode: CameraMode,
+
     radius: f32,
     phi: f32,
     theta: f32,
+    target: Point3<f32>,
     zoom_sensitivity: f32,
     orbit_sensitivity: f32,
+    pan_sensitivity: f32,
+
+    // orbiting keeps its momentum after the mouse is released, zoom and target changes are
+    // eased towards their goals
+    radius_goal: f32,
+    target_goal: Point3<f32>,
+    phi_velocity: f32,
+    theta_velocity: f32,
+    damping: f32,
+    smoothing: f32,
+
+    yaw: f32,
+    pitch: f32,
+    fly_speed: f32,
+    sprint_multiplier: f32,
+    look_sensitivity: f32,
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CameraController {
     pub fn new() -> Self {
+        Self::from_orbit_state(OrbitState::default())
+    }
+
+    pub fn from_orbit_state(orbit: OrbitState) -> Self {
         Self {
-            radius: 10.0,
-            phi: 0.0,
-            theta: f32::consts::FRAC_2_PI,
+            mode: CameraMode::Orbit,
+            radius: orbit.radius,
+            phi: orbit.phi,
+            theta: orbit.theta,
+            target: orbit.target,
             zoom_sensitivity: 0.01,
             orbit_sensitivity: 0.003,
+            pan_sensitivity: 0.002,
+            radius_goal: orbit.radius,
+            target_goal: orbit.target,
+            phi_velocity: 0.0,
+            theta_velocity: 0.0,
+            damping: 8.0,
+            smoothing: 10.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            fly_speed: 3.0,
+            sprint_multiplier: 4.0,
+            look_sensitivity: 0.003,
         }
     }
 
-    pub fn update_camera(&mut self, input_helper: &InputHelper, camera: &mut Camera) {
-        self.radius += input_helper.mouse_wheel_delta() * self.zoom_sensitivity;
-        self.radius = f32::max(self.radius, camera.z_near);
+    pub fn orbit_state(&self) -> OrbitState {
+        OrbitState {
+            radius: self.radius_goal,
+            phi: self.phi,
+            theta: self.theta,
+            target: self.target_goal,
+        }
+    }
 
-        if input_helper.is_mouse_button_pressed(winit::event::MouseButton::Left) {
-            let (dx, dy) = input_helper.mouse_delta();
+    /// Jumps straight to the given orbit without any easing.
+    pub fn set_orbit_state(&mut self, orbit: OrbitState) {
+        self.mode = CameraMode::Orbit;
+        self.radius = orbit.radius;
+        self.radius_goal = orbit.radius;
+        self.phi = orbit.phi;
+        self.theta = orbit.theta;
+        self.target = orbit.target;
+        self.target_goal = orbit.target;
+        self.phi_velocity = 0.0;
+        self.theta_velocity = 0.0;
+    }
+
+    /// Orbits to the view from `direction`, e.g. `Vector3::x()` looks at the target from the
+    /// +x side. Views from straight above or below keep the current heading.
+    pub fn snap_to_axis(&mut self, direction: Vector3<f32>) {
+        let direction = direction.normalize();
+        if direction.x != 0.0 || direction.z != 0.0 {
+            self.phi = direction.z.atan2(direction.x);
+        }
+        self.theta = direction.y.clamp(-1.0, 1.0).acos();
+
+        self.mode = CameraMode::Orbit;
+        self.phi_velocity = 0.0;
+        self.theta_velocity = 0.0;
+    }
+
+    pub fn target_goal(&self) -> Point3<f32> {
+        self.target_goal
+    }
+
+    pub fn set_target(&mut self, target: Point3<f32>) {
+        self.target_goal = target;
+    }
+
+    /// Moves the orbit target to the center of the box and zooms out until the whole box is
+    /// in view.
+    pub fn frame_bbox(&mut self, center: Point3<f32>, dimensions: Vector3<f32>, camera: &Camera) {
+        let bounding_radius = dimensions.norm() / 2.0;
+
+        self.mode = CameraMode::Orbit;
+        self.target_goal = center;
+        self.radius_goal = bounding_radius / (camera.fov / 2.0).sin();
+        self.phi_velocity = 0.0;
+        self.theta_velocity = 0.0;
+    }
+
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: CameraMode, camera: &Camera) {
+        if mode == CameraMode::Fly && self.mode != CameraMode::Fly {
+            // start flying from the current view so the switch is seamless
+            let forward = (camera.target - camera.position).normalize();
+            self.yaw = forward.z.atan2(forward.x);
+            self.pitch = forward.y.asin();
+        }
+
+        self.mode = mode;
+    }
+
+    pub fn toggle_mode(&mut self, camera: &Camera) {
+        let mode = match self.mode {
+            CameraMode::Orbit => CameraMode::Fly,
+            CameraMode::Fly => CameraMode::Orbit,
+        };
+        self.set_mode(mode, camera);
+    }
+
+    pub fn update_camera(&mut self, input_helper: &InputHelper, camera: &mut Camera, dt: f32) {
+        match self.mode {
+            CameraMode::Orbit => self.update_orbit(input_helper, camera, dt),
+            CameraMode::Fly => self.update_fly(input_helper, camera, dt),
+        }
+    }
+
+    fn update_orbit(&mut self, input_helper: &InputHelper, camera: &mut Camera, dt: f32) {
+        self.radius_goal += input_helper.mouse_wheel_delta() * self.zoom_sensitivity;
+        // spreading the fingers zooms in
+        self.radius_goal /= input_helper.pinch_scale();
+        self.radius_goal = f32::max(self.radius_goal, camera.z_near);
+
+        let (pan_x, pan_y) = input_helper.touch_pan();
+        if pan_x != 0.0 || pan_y != 0.0 {
+            // the scene follows the fingers
+            let forward = (camera.target - camera.position).normalize();
+            let right = forward.cross(&Vector3::y()).normalize();
+            let up = right.cross(&forward);
+            let scale = self.radius * self.pan_sensitivity;
+            self.target_goal += (up * pan_y - right * pan_x) * scale;
+        }
+
+        let drag = if input_helper.is_mouse_button_pressed(MouseButton::Left) {
+            Some(input_helper.mouse_delta())
+        } else if input_helper.touch_cnt() == 1 {
+            Some(input_helper.touch_drag())
+        } else {
+            None
+        };
+
+        if let Some((dx, dy)) = drag {
             self.phi += dx * self.orbit_sensitivity;
             self.theta -= dy * self.orbit_sensitivity;
 
-            self.theta = self.theta.clamp(0.01, f32::consts::PI - 0.01);
+            if dt > 0.0 {
+                self.phi_velocity = dx * self.orbit_sensitivity / dt;
+                self.theta_velocity = -dy * self.orbit_sensitivity / dt;
+            }
+        } else {
+            self.phi += self.phi_velocity * dt;
+            self.theta += self.theta_velocity * dt;
+
+            let decay = (-self.damping * dt).exp();
+            self.phi_velocity *= decay;
+            self.theta_velocity *= decay;
+        }
+
+        self.theta = self.theta.clamp(0.01, f32::consts::PI - 0.01);
+
+        let blend = 1.0 - (-self.smoothing * dt).exp();
+        self.radius += (self.radius_goal - self.radius) * blend;
+        self.target += (self.target_goal - self.target) * blend;
+
+        camera.target = self.target;
+        camera.position = self.target
+            + Vector3::new(
+                self.theta.sin() * self.phi.cos(),
+                self.theta.cos(),
+                self.theta.sin() * self.phi.sin(),
+            ) * self.radius;
+    }
+
+    fn update_fly(&mut self, input_helper: &InputHelper, camera: &mut Camera, dt: f32) {
+        if input_helper.is_mouse_button_pressed(MouseButton::Right) {
+            let (dx, dy) = input_helper.mouse_delta();
+            self.yaw += dx * self.look_sensitivity;
+            self.pitch -= dy * self.look_sensitivity;
+
+            let limit = f32::consts::FRAC_PI_2 - 0.01;
+            self.pitch = self.pitch.clamp(-limit, limit);
+        }
+
+        let forward = Vector3::new(
+            self.pitch.cos() * self.yaw.cos(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.sin(),
+        );
+        let right = forward.cross(&Vector3::y()).normalize();
+
+        let held = |key| input_helper.is_key_held(PhysicalKey::Code(key));
+        let axis = |positive, negative| held(positive) as i32 as f32 - held(negative) as i32 as f32;
+
+        let direction = forward * axis(KeyCode::KeyW, KeyCode::KeyS)
+            + right * axis(KeyCode::KeyD, KeyCode::KeyA)
+            + Vector3::y() * axis(KeyCode::KeyE, KeyCode::KeyQ);
+
+        if direction.norm_squared() > 0.0 {
+            let mut speed = self.fly_speed;
+            if held(KeyCode::ShiftLeft) || held(KeyCode::ShiftRight) {
+                speed *= self.sprint_multiplier;
+            }
+
+            camera.position += direction.normalize() * speed * dt;
         }
 
-        camera.position.x = self.radius * self.theta.sin() * self.phi.cos();
-        camera.position.y = self.radius * self.theta.cos();
-        camera.position.z = self.radius * self.theta.sin() * self.phi.sin();
+        camera.target = camera.position + forward;
     }
 }
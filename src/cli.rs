@@ -0,0 +1,313 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+
+use crate::{
+    camera_animation::CameraAnimation,
+    config::{AppConfig, Backend, PowerPreference},
+    fluid_simulation::{FluidSimulationConfig, InitialLayout, SimDim},
+    headless::HeadlessOptions,
+    logging::LogLevel,
+    offline_render::OfflineOptions,
+    remote::RemoteConfig,
+    soak::SoakOptions,
+    validation::{ValidationCase, ValidationOptions},
+    SplooshError,
+};
+
+const SOAK_PARTICLES: usize = 20_000;
+const DEFAULT_BENCHMARK_PARTICLES: [usize; 4] = [10_000, 50_000, 100_000, 200_000];
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SceneArg {
+    Cube,
+    DamBreak,
+}
+
+impl From<SceneArg> for InitialLayout {
+    fn from(scene: SceneArg) -> Self {
+        match scene {
+            SceneArg::Cube => InitialLayout::Cube,
+            SceneArg::DamBreak => InitialLayout::DamBreak,
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about = "GPU SPH fluid simulator")]
+pub struct Cli {
+    /// Number of simulated particles, overrides the config file
+    #[arg(long)]
+    pub particles: Option<usize>,
+
+    /// Initial particle layout
+    #[arg(long, value_enum)]
+    pub scene: Option<SceneArg>,
+
+    /// Run the simulation in 2D
+    #[arg(long = "2d")]
+    pub two_d: bool,
+
+    /// Path to a TOML or RON config file
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Run the simulation without opening a window
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Exit after simulating this many frames
+    #[arg(long)]
+    pub frames: Option<u64>,
+
+    /// Timestep used in headless and offline mode
+    #[arg(long, default_value_t = 1.0 / 120.0)]
+    pub dt: f32,
+
+    /// Directory where headless mode writes per-frame particle positions as CSV and offline
+    /// mode writes rendered frames
+    #[arg(long)]
+    pub export_dir: Option<PathBuf>,
+
+    /// Also write the velocity divergence and pressure of every fluid particle per frame to
+    /// `--export-dir` in headless mode
+    #[arg(long, requires = "export_dir")]
+    pub export_diagnostics: bool,
+
+    /// Run headless and print frame and per-pass GPU timings as JSON
+    #[arg(long)]
+    pub benchmark: bool,
+
+    /// Step a mid-sized simulation for a long time, checking it for NaNs and density
+    /// outliers, and report the first diverged frame. The exit code is 1 after a divergence
+    #[arg(long)]
+    pub soak: bool,
+
+    /// Frames between two checks of the soak run
+    #[arg(long, default_value_t = 100)]
+    pub check_interval: u64,
+
+    /// Run the validation cases in 2D, compare them with their analytic or experimental
+    /// references and print the errors as JSON. The exit code is 1 if a case misses its
+    /// tolerance
+    #[arg(long)]
+    pub validate: bool,
+
+    /// Comma separated cases run by `--validate`, defaults to all of them
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "CASES")]
+    pub validation_cases: Vec<ValidationCase>,
+
+    /// Particles across the water depth of the validation cases
+    #[arg(long, default_value_t = 30)]
+    pub validation_resolution: u32,
+
+    /// Comma separated particle counts run by `--benchmark`, defaults to `--particles` or a
+    /// range of sizes
+    #[arg(long, value_delimiter = ',', value_name = "COUNTS")]
+    pub benchmark_particles: Vec<usize>,
+
+    /// Run headless with the domain split between two adapters, experimental. The value is the
+    /// skin in smoothing radii the particles may move before the domain is split again
+    #[arg(long, value_name = "SKIN", num_args = 0..=1, default_missing_value = "1.0")]
+    pub split_domain: Option<f32>,
+
+    /// Render frames offscreen at a fixed resolution and timestep and write them to
+    /// `--export-dir` as PNGs, or to `--video` through ffmpeg
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Offline render resolution
+    #[arg(long, default_value = "1920x1080", value_parser = parse_resolution)]
+    pub resolution: (u32, u32),
+
+    /// Frame rate of the offline video
+    #[arg(long, default_value_t = 60)]
+    pub fps: u32,
+
+    /// Encode offline frames into this video file with ffmpeg
+    #[arg(long)]
+    pub video: Option<PathBuf>,
+
+    /// Orbit the camera around the scene at this many radians per second
+    #[arg(long, value_name = "SPEED")]
+    pub turntable: Option<f32>,
+
+    /// Graphics API, overrides the config file
+    #[arg(long, value_enum)]
+    pub backend: Option<Backend>,
+
+    /// Prefer discrete or integrated GPUs, overrides the config file
+    #[arg(long, value_enum)]
+    pub power_preference: Option<PowerPreference>,
+
+    /// Use the adapter whose name contains this, overrides the config file
+    #[arg(long, value_name = "NAME")]
+    pub adapter: Option<String>,
+
+    /// Accept remote control connections on this address, e.g. 127.0.0.1:7878
+    #[arg(long, value_name = "ADDRESS")]
+    pub remote: Option<String>,
+
+    /// Least severe messages logged to stderr, `RUST_LOG` takes precedence
+    #[arg(long, value_enum, default_value_t = LogLevel::Warn)]
+    pub log_level: LogLevel,
+
+    /// Write the spans of the run to this file as a Chrome trace, for chrome://tracing or
+    /// Perfetto
+    #[arg(long, value_name = "FILE")]
+    pub trace: Option<PathBuf>,
+
+    /// Rhai script that sets up the scene and schedules events
+    #[cfg(feature = "scripting")]
+    #[arg(long, value_name = "FILE")]
+    pub script: Option<PathBuf>,
+}
+
+fn parse_resolution(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {value}"))?;
+
+    let width = width
+        .parse()
+        .map_err(|_| format!("invalid width {width}"))?;
+    let height = height
+        .parse()
+        .map_err(|_| format!("invalid height {height}"))?;
+
+    Ok((width, height))
+}
+
+impl Cli {
+    pub fn load_config(&self) -> Result<AppConfig, SplooshError> {
+        let mut config = match &self.config {
+            Some(path) => AppConfig::from_file(path)?,
+            None => AppConfig::load()?,
+        };
+
+        if self.particles.is_some() || self.scene.is_some() || self.two_d || self.soak {
+            let simulation = config
+                .simulation
+                .get_or_insert_with(|| FluidSimulationConfig {
+                    particle_cnt: if self.soak {
+                        SOAK_PARTICLES
+                    } else {
+                        FluidSimulationConfig::default().particle_cnt
+                    },
+                    ..Default::default()
+                });
+
+            if let Some(particles) = self.particles {
+                simulation.particle_cnt = particles;
+            }
+            if let Some(scene) = self.scene {
+                simulation.initial_layout = scene.into();
+            }
+            if self.two_d {
+                simulation.dimensions = SimDim::Two;
+            }
+        }
+
+        if let Some(backend) = self.backend {
+            config.adapter.backend = backend;
+        }
+        if let Some(power_preference) = self.power_preference {
+            config.adapter.power_preference = power_preference;
+        }
+        if let Some(name) = &self.adapter {
+            config.adapter.name = Some(name.clone());
+        }
+
+        if let Some(address) = &self.remote {
+            config.remote = Some(RemoteConfig {
+                address: address.clone(),
+                ..config.remote.take().unwrap_or_default()
+            });
+        }
+
+        if let Some(speed) = self.turntable {
+            config.camera_animation = Some(CameraAnimation::Turntable { speed });
+        }
+
+        Ok(config)
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.headless || self.benchmark || self.split_domain.is_some()
+    }
+
+    pub fn offline_options(&self) -> Option<OfflineOptions> {
+        if !self.offline {
+            return None;
+        }
+
+        Some(OfflineOptions {
+            width: self.resolution.0,
+            height: self.resolution.1,
+            dt: self.dt,
+            fps: self.fps,
+            export_dir: self
+                .export_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("frames")),
+            video: self.video.clone(),
+        })
+    }
+
+    pub fn frame_limit(&self) -> Option<u64> {
+        match self.offline {
+            true => Some(self.frames.unwrap_or(600)),
+            false => self.frames,
+        }
+    }
+
+    pub fn soak_options(&self) -> Option<SoakOptions> {
+        if !self.soak {
+            return None;
+        }
+
+        let defaults = SoakOptions::default();
+        Some(SoakOptions {
+            frames: self.frames.unwrap_or(defaults.frames),
+            dt: self.dt,
+            check_interval: self.check_interval,
+            ..defaults
+        })
+    }
+
+    pub fn validation_options(&self) -> Option<ValidationOptions> {
+        if !self.validate {
+            return None;
+        }
+
+        let defaults = ValidationOptions::default();
+        Some(ValidationOptions {
+            cases: match self.validation_cases.is_empty() {
+                true => defaults.cases,
+                false => self.validation_cases.clone(),
+            },
+            resolution: self.validation_resolution,
+            export_dir: self.export_dir.clone(),
+        })
+    }
+
+    pub fn headless_options(&self) -> HeadlessOptions {
+        let benchmark_particles = match (self.benchmark, self.particles) {
+            (false, _) => Vec::new(),
+            (true, _) if !self.benchmark_particles.is_empty() => self.benchmark_particles.clone(),
+            (true, Some(particles)) => vec![particles],
+            (true, None) => DEFAULT_BENCHMARK_PARTICLES.to_vec(),
+        };
+
+        HeadlessOptions {
+            frames: self
+                .frames
+                .unwrap_or(if self.benchmark { 500 } else { 1000 }),
+            dt: self.dt,
+            export_dir: self.export_dir.clone(),
+            export_diagnostics: self.export_diagnostics,
+            benchmark_particles,
+            split_skin: self.split_domain,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    path::PathBuf,
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops::FilterType,
+    Delay, Frame, RgbaImage,
+};
+
+use crate::SplooshError;
+
+const RECORDING_DIR: &str = "recordings";
+
+/// Collects downscaled frames for a short clip and encodes them into an animated GIF on a
+/// background thread once the clip is complete.
+pub struct ClipRecorder {
+    frames: VecDeque<RgbaImage>,
+    max_frames: usize,
+    frame_interval: Duration,
+    max_width: u32,
+
+    recording_start: Option<Instant>,
+    last_capture: Option<Instant>,
+    clip_duration: Duration,
+    encoder_thread: Option<JoinHandle<Result<PathBuf, String>>>,
+}
+
+impl ClipRecorder {
+    pub fn new(fps: u32, max_width: u32) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            max_frames: 0,
+            frame_interval: Duration::from_secs_f32(1.0 / fps as f32),
+            max_width,
+            recording_start: None,
+            last_capture: None,
+            clip_duration: Duration::ZERO,
+            encoder_thread: None,
+        }
+    }
+
+    pub fn start(&mut self, duration: Duration) {
+        self.frames.clear();
+        self.max_frames =
+            (duration.as_secs_f32() / self.frame_interval.as_secs_f32()).ceil() as usize;
+        self.clip_duration = duration;
+        self.recording_start = Some(Instant::now());
+        self.last_capture = None;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording_start.is_some()
+    }
+
+    pub fn is_encoding(&self) -> bool {
+        self.encoder_thread.is_some()
+    }
+
+    pub fn progress(&self) -> f32 {
+        match self.recording_start {
+            Some(start) => {
+                (start.elapsed().as_secs_f32() / self.clip_duration.as_secs_f32()).min(1.0)
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Returns true when the next rendered frame should be captured. Captured frames arrive
+    /// through `add_frame` once they are read back, usually a frame or two later.
+    pub fn wants_frame(&self) -> bool {
+        self.is_recording()
+            && self
+                .last_capture
+                .is_none_or(|last| last.elapsed() >= self.frame_interval)
+    }
+
+    /// Starts the next frame interval once the copy of a wanted frame was queued. A frame
+    /// whose copy was skipped is asked for again.
+    pub fn frame_queued(&mut self) {
+        self.last_capture = Some(Instant::now());
+    }
+
+    pub fn add_frame(&mut self, frame: RgbaImage) {
+        // frames still read back after the clip is complete are dropped
+        if !self.is_recording() {
+            return;
+        }
+
+        let frame = if frame.width() > self.max_width {
+            let height = frame.height() * self.max_width / frame.width();
+            image::imageops::resize(&frame, self.max_width, height, FilterType::Triangle)
+        } else {
+            frame
+        };
+
+        if self.frames.len() >= self.max_frames {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+
+        if self
+            .recording_start
+            .is_some_and(|start| start.elapsed() >= self.clip_duration)
+        {
+            self.finish();
+        }
+    }
+
+    /// Returns the result of a finished encode, if there is one.
+    pub fn poll_encoder(&mut self) -> Option<Result<PathBuf, String>> {
+        if !self.encoder_thread.as_ref()?.is_finished() {
+            return None;
+        }
+
+        let result = self
+            .encoder_thread
+            .take()?
+            .join()
+            .unwrap_or_else(|_| Err("The gif encoder panicked".to_string()));
+
+        Some(result)
+    }
+
+    fn finish(&mut self) {
+        self.recording_start = None;
+
+        let frames: Vec<RgbaImage> = self.frames.drain(..).collect();
+        let delay = Delay::from_saturating_duration(self.frame_interval);
+
+        self.encoder_thread = Some(std::thread::spawn(move || {
+            encode_gif(frames, delay).map_err(|err| err.to_string())
+        }));
+    }
+}
+
+fn encode_gif(frames: Vec<RgbaImage>, delay: Delay) -> Result<PathBuf, SplooshError> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = PathBuf::from(RECORDING_DIR).join(format!("clip_{timestamp}.gif"));
+    std::fs::create_dir_all(RECORDING_DIR)?;
+
+    let mut encoder = GifEncoder::new(File::create(&path)?);
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder.encode_frames(
+        frames
+            .into_iter()
+            .map(|frame| Frame::from_parts(frame, 0, 0, delay)),
+    )?;
+
+    Ok(path)
+}
@@ -0,0 +1,277 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{graphics::render_engine::GenericRequest, WgpuDevice};
+
+/// Texels in a colormap texture.
+pub const COLORMAP_SIZE: u32 = 256;
+
+/// Defines `colormap(t)` for shaders that bind a `ColormapTexture` as `colormap_texture`.
+pub const COLORMAP_SHADER: &str = include_str!("shaders/colormap.wgsl");
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Colormap {
+    /// Perceptually uniform from dark blue over green to yellow
+    Viridis,
+    /// Perceptually uniform from dark blue over magenta to yellow
+    Plasma,
+    /// Diverging from blue over gray to red, for values around a reference
+    Coolwarm,
+    /// Rainbow with a smooth lightness profile
+    Turbo,
+    /// Blue, cyan, green, yellow and red
+    #[default]
+    Heatmap,
+    Grayscale,
+}
+
+const VIRIDIS: [[f32; 3]; 11] = [
+    [0.267, 0.005, 0.329],
+    [0.282, 0.141, 0.459],
+    [0.255, 0.267, 0.529],
+    [0.208, 0.373, 0.553],
+    [0.165, 0.471, 0.557],
+    [0.129, 0.569, 0.549],
+    [0.133, 0.659, 0.518],
+    [0.267, 0.749, 0.439],
+    [0.478, 0.820, 0.318],
+    [0.741, 0.875, 0.149],
+    [0.992, 0.906, 0.145],
+];
+
+const PLASMA: [[f32; 3]; 11] = [
+    [0.051, 0.031, 0.529],
+    [0.255, 0.016, 0.616],
+    [0.416, 0.000, 0.659],
+    [0.561, 0.051, 0.643],
+    [0.694, 0.165, 0.565],
+    [0.800, 0.278, 0.471],
+    [0.882, 0.392, 0.384],
+    [0.949, 0.518, 0.294],
+    [0.988, 0.651, 0.212],
+    [0.988, 0.808, 0.145],
+    [0.941, 0.976, 0.129],
+];
+
+const COOLWARM: [[f32; 3]; 5] = [
+    [0.230, 0.299, 0.754],
+    [0.552, 0.690, 0.996],
+    [0.865, 0.865, 0.865],
+    [0.958, 0.604, 0.482],
+    [0.706, 0.016, 0.150],
+];
+
+const TURBO: [[f32; 3]; 11] = [
+    [0.190, 0.072, 0.232],
+    [0.288, 0.346, 0.867],
+    [0.184, 0.618, 0.960],
+    [0.154, 0.844, 0.765],
+    [0.304, 0.975, 0.512],
+    [0.589, 0.982, 0.313],
+    [0.876, 0.862, 0.196],
+    [1.000, 0.637, 0.136],
+    [0.958, 0.363, 0.091],
+    [0.721, 0.125, 0.031],
+    [0.478, 0.016, 0.011],
+];
+
+const HEATMAP: [[f32; 3]; 5] = [
+    [0.0, 0.0, 1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, 1.0, 0.0],
+    [1.0, 1.0, 0.0],
+    [1.0, 0.0, 0.0],
+];
+
+const GRAYSCALE: [[f32; 3]; 2] = [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0]];
+
+impl Colormap {
+    pub const ALL: [Colormap; 6] = [
+        Colormap::Viridis,
+        Colormap::Plasma,
+        Colormap::Coolwarm,
+        Colormap::Turbo,
+        Colormap::Heatmap,
+        Colormap::Grayscale,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Colormap::Viridis => "Viridis",
+            Colormap::Plasma => "Plasma",
+            Colormap::Coolwarm => "Coolwarm",
+            Colormap::Turbo => "Turbo",
+            Colormap::Heatmap => "Heatmap",
+            Colormap::Grayscale => "Grayscale",
+        }
+    }
+
+    /// Evenly spaced colors from 0 to 1.
+    fn stops(&self) -> &'static [[f32; 3]] {
+        match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Plasma => &PLASMA,
+            Colormap::Coolwarm => &COOLWARM,
+            Colormap::Turbo => &TURBO,
+            Colormap::Heatmap => &HEATMAP,
+            Colormap::Grayscale => &GRAYSCALE,
+        }
+    }
+
+    /// Linear interpolation between the stops, `t` is clamped to [0, 1].
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let stops = self.stops();
+        let x = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (x as usize).min(stops.len() - 2);
+        let f = x - i as f32;
+        [0, 1, 2].map(|c| stops[i][c] + (stops[i + 1][c] - stops[i][c]) * f)
+    }
+
+    fn texels(&self) -> Vec<[u8; 4]> {
+        (0..COLORMAP_SIZE)
+            .map(|i| {
+                let [r, g, b] = self.sample(i as f32 / (COLORMAP_SIZE - 1) as f32);
+                [r, g, b, 1.0].map(|c| (c * 255.0).round() as u8)
+            })
+            .collect()
+    }
+}
+
+/// Values mapped to the ends of a colormap.
+#[repr(C)]
+#[derive(
+    Clone, Copy, Debug, PartialEq, Serialize, Deserialize, bytemuck::Pod, bytemuck::Zeroable,
+)]
+pub struct ColorRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ColorRange {
+    /// Keeps the range from collapsing, the shaders divide by its width.
+    pub fn validated(self) -> Self {
+        Self {
+            min: self.min,
+            max: self.max.max(self.min + f32::EPSILON),
+        }
+    }
+}
+
+/// A colormap uploaded as a 1D texture, shaders read it with `colormap` from
+/// `COLORMAP_SHADER`.
+pub struct ColormapTexture {
+    texture: Arc<wgpu::Texture>,
+    view: wgpu::TextureView,
+    /// Colormap in the texture, `u32::MAX` before the first upload
+    uploaded: Arc<AtomicU32>,
+}
+
+impl ColormapTexture {
+    pub fn new(wgpu_device: &WgpuDevice) -> Self {
+        let texture = wgpu_device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Colormap texture"),
+            size: wgpu::Extent3d {
+                width: COLORMAP_SIZE,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D1,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            uploaded: Arc::new(AtomicU32::new(u32::MAX)),
+        }
+    }
+
+    pub const fn layout_entry(
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D1,
+                multisampled: false,
+            },
+            count: None,
+        }
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Uploads `colormap` unless it is already in the texture, cheap enough to run every
+    /// frame.
+    pub fn upload_fn(&self, colormap: Colormap) -> GenericRequest {
+        let texture = self.texture.clone();
+        let uploaded = self.uploaded.clone();
+
+        Box::new(move |_, queue| {
+            if uploaded.swap(colormap as u32, Ordering::Relaxed) == colormap as u32 {
+                return;
+            }
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                bytemuck::cast_slice(&colormap.texels()),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * COLORMAP_SIZE),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width: COLORMAP_SIZE,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+            );
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_hit_the_stops() {
+        for colormap in Colormap::ALL {
+            let stops = colormap.stops();
+            assert_eq!(colormap.sample(0.0), stops[0]);
+            assert_eq!(colormap.sample(1.0), stops[stops.len() - 1]);
+            assert_eq!(colormap.sample(-1.0), stops[0]);
+        }
+
+        let [r, g, b] = Colormap::Grayscale.sample(0.25);
+        assert!((r - 0.25).abs() < 1e-6 && r == g && g == b);
+    }
+
+    #[test]
+    fn texels_cover_the_texture() {
+        let texels = Colormap::Heatmap.texels();
+        assert_eq!(texels.len(), COLORMAP_SIZE as usize);
+        assert_eq!(texels[0], [0, 0, 255, 255]);
+        assert_eq!(texels[COLORMAP_SIZE as usize - 1], [255, 0, 0, 255]);
+    }
+}
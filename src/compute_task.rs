@@ -1,11 +1,23 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, sync::Arc, time::Instant};
 
-use crate::WgpuDevice;
+use crate::{
+    gpu_timer::GpuTimer,
+    kernel_registry::Kernel,
+    pass_validation::{self, PassAccesses},
+    WgpuDevice,
+};
+
+/// Workgroup sizes `tune_workgroup_size` chooses from, all within the default device limits.
+pub const WORKGROUP_SIZES: [u32; 3] = [64, 128, 256];
+
+/// Dispatches of every task timed per workgroup size
+const TUNING_RUNS: usize = 10;
 
 pub struct ComputeTask {
     bind_group: wgpu::BindGroup,
-    pipeline: wgpu::ComputePipeline,
+    kernel: Arc<Kernel>,
     workgroups: (u32, u32, u32),
+    accesses: PassAccesses,
 }
 
 impl ComputeTask {
@@ -18,13 +30,8 @@ impl ComputeTask {
         shader_source: Cow<'_, str>,
         workgroups: (u32, u32, u32),
     ) -> Self {
-        let bind_group_layout =
-            wgpu_device
-                .device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: Some(&format!("{name} bind group layout")),
-                    entries,
-                });
+        let _span = tracing::debug_span!("compile_compute_task", name).entered();
+        let bind_group_layout = wgpu_device.bind_group_layout(entries);
 
         let bind_group = wgpu_device
             .device
@@ -34,50 +41,130 @@ impl ComputeTask {
                 entries: resources,
             });
 
-        let layout = wgpu_device
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some(&format!("{name} pipeline layout")),
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges,
-            });
-
-        let shader = wgpu_device
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some(&format!("{name} shader")),
-                source: wgpu::ShaderSource::Wgsl(shader_source),
-            });
-
-        let pipeline =
-            wgpu_device
-                .device
-                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                    label: Some(&format!("{name} pipeline")),
-                    layout: Some(&layout),
-                    module: &shader,
-                    entry_point: Some("main"),
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                    cache: None,
-                });
+        let layout = wgpu_device.pipeline_layout(entries, push_constant_ranges);
+        let kernel = Arc::new(Kernel::new(
+            &wgpu_device.device,
+            name,
+            layout,
+            shader_source.into_owned(),
+        ));
+        wgpu_device.kernels.track(&kernel);
 
         Self {
             bind_group,
-            pipeline,
+            kernel,
             workgroups,
+            accesses: PassAccesses::new(name, entries, resources),
         }
     }
 
+    /// Like `new` for a kernel over `invocation_cnt` threads in x, whose shader declares
+    /// `@workgroup_size(WORKGROUP_SIZE)`. The constant is prepended to `shader_source`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn with_workgroup_size(
+        wgpu_device: &WgpuDevice,
+        name: &str,
+        entries: &[wgpu::BindGroupLayoutEntry],
+        resources: &[wgpu::BindGroupEntry],
+        push_constant_ranges: &[wgpu::PushConstantRange],
+        shader_source: &str,
+        workgroup_size: u32,
+        invocation_cnt: u32,
+    ) -> Self {
+        let shader_source =
+            format!("const WORKGROUP_SIZE: u32 = {workgroup_size};\n{shader_source}");
+
+        Self::new(
+            wgpu_device,
+            name,
+            entries,
+            resources,
+            push_constant_ranges,
+            shader_source.into(),
+            (invocation_cnt.div_ceil(workgroup_size), 1, 1),
+        )
+    }
+
     pub fn execute(&self, encoder: &mut wgpu::CommandEncoder, push_constants: &[u8]) {
+        pass_validation::record(&self.accesses);
+
         let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
             label: Some("Compute Pass"),
             timestamp_writes: None,
         });
-        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_pipeline(&self.kernel.pipeline());
         compute_pass.set_bind_group(0, &self.bind_group, &[]);
-        if push_constants.len() > 0 {
+        if !push_constants.is_empty() {
             compute_pass.set_push_constants(0, push_constants);
         }
         compute_pass.dispatch_workgroups(self.workgroups.0, self.workgroups.1, self.workgroups.2);
     }
 }
+
+/// Returns the size of `WORKGROUP_SIZES` whose tasks, built by `create`, run the fastest on the
+/// state `prepare` sets up. The tasks are timed with timestamp queries if the device supports
+/// them and by the wall time until the GPU is done otherwise.
+pub fn tune_workgroup_size(
+    wgpu_device: &WgpuDevice,
+    prepare: impl Fn(&mut wgpu::CommandEncoder, &wgpu::Queue),
+    create: impl Fn(u32) -> Vec<Arc<ComputeTask>>,
+) -> u32 {
+    let _span = tracing::info_span!("tune_workgroup_size").entered();
+    let timer = GpuTimer::new(wgpu_device, 2);
+    let create_encoder = || {
+        wgpu_device
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Workgroup tuning encoder"),
+            })
+    };
+
+    let mut fastest = (WORKGROUP_SIZES[WORKGROUP_SIZES.len() - 1], f64::INFINITY);
+    for workgroup_size in WORKGROUP_SIZES {
+        let tasks = create(workgroup_size);
+
+        // the first dispatch also waits for the pipelines to be ready
+        let mut encoder = create_encoder();
+        prepare(&mut encoder, &wgpu_device.queue);
+        for task in &tasks {
+            task.execute(&mut encoder, &[]);
+        }
+        wgpu_device.submit(encoder);
+        wgpu_device.device.poll(wgpu::Maintain::Wait);
+
+        let start = Instant::now();
+        let mut encoder = create_encoder();
+        if let Some(timer) = &timer {
+            timer.write_timestamp(&mut encoder, 0);
+        }
+        for _ in 0..TUNING_RUNS {
+            for task in &tasks {
+                task.execute(&mut encoder, &[]);
+            }
+        }
+        if let Some(timer) = &timer {
+            timer.write_timestamp(&mut encoder, 1);
+            timer.resolve(&mut encoder, 2);
+        }
+        wgpu_device.submit(encoder);
+
+        let gpu_time = timer.as_ref().and_then(|timer| {
+            timer
+                .read_intervals(&wgpu_device.device, 2)
+                .ok()
+                .and_then(|intervals| intervals.first().copied())
+        });
+        let time = gpu_time.unwrap_or_else(|| {
+            wgpu_device.device.poll(wgpu::Maintain::Wait);
+            start.elapsed().as_secs_f64() * 1000.0
+        });
+
+        tracing::debug!("Workgroup size {workgroup_size}: {time:.3} ms");
+        if time < fastest.1 {
+            fastest = (workgroup_size, time);
+        }
+    }
+
+    tracing::info!("Tuned the workgroup size to {}", fastest.0);
+    fastest.0
+}
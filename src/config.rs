@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    camera_animation::CameraAnimation,
+    fluid_simulation::FluidSimulationConfig,
+    project::{check_version, migrate_simulation, unversioned, WrittenSimulation, PROJECT_VERSION},
+    timeline::Timeline,
+    SplooshError,
+};
+
+pub const CONFIG_ENV_VAR: &str = "SPLOOSH_CONFIG";
+pub const DEFAULT_CONFIG_PATH: &str = "sploosh.toml";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Primary,
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl Backend {
+    pub fn wgpu_backends(&self) -> wgpu::Backends {
+        match self {
+            Backend::Primary => wgpu::Backends::PRIMARY,
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+/// Kind of adapter preferred when several are available.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerPreference {
+    /// Discrete GPUs first
+    #[default]
+    HighPerformance,
+    /// Integrated GPUs first
+    LowPower,
+}
+
+/// Selects the GPU used for both windowed and headless runs.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdapterConfig {
+    pub backend: Backend,
+    pub power_preference: PowerPreference,
+    /// Only adapters whose name contains this, ignoring case
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub size: Option<(u32, u32)>,
+    pub min_size: Option<(u32, u32)>,
+    pub vsync: bool,
+    /// The frame rate is appended while the app runs
+    pub title: String,
+    /// Image file for the window icon, a generated droplet is used without one
+    pub icon: Option<PathBuf>,
+    /// Starts in borderless fullscreen, overrides the state saved in the settings
+    pub fullscreen: Option<bool>,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            size: None,
+            min_size: Some((640, 360)),
+            vsync: false,
+            title: "Sploosh".to_string(),
+            icon: None,
+            fullscreen: None,
+        }
+    }
+}
+
+impl WindowConfig {
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        if self.vsync {
+            wgpu::PresentMode::AutoVsync
+        } else {
+            wgpu::PresentMode::Immediate
+        }
+    }
+}
+
+/// Startup configuration. Values present in the config file take precedence over the
+/// settings persisted from the previous session.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    /// Project version of the simulation config, files without one are from version 1
+    #[serde(default = "unversioned")]
+    pub version: u32,
+    pub window: WindowConfig,
+    pub adapter: AdapterConfig,
+    pub simulation: Option<FluidSimulationConfig>,
+    pub camera_animation: Option<CameraAnimation>,
+    /// Timed events, run from the start of the simulation
+    pub timeline: Timeline,
+    /// Serves remote control and telemetry over TCP
+    pub remote: Option<RemoteConfig>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            version: PROJECT_VERSION,
+            window: WindowConfig::default(),
+            adapter: AdapterConfig::default(),
+            simulation: None,
+            camera_animation: None,
+            timeline: Timeline::default(),
+            remote: None,
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename = "AppConfig")]
+struct WrittenAppConfig {
+    simulation: Option<WrittenSimulation>,
+}
+
+impl AppConfig {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SplooshError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        let mut config: AppConfig = parse_contents(path, &contents)?;
+        let written: WrittenAppConfig = parse_contents(path, &contents)?;
+
+        check_version(config.version, "config")?;
+        if let (Some(simulation), Some(written)) = (&mut config.simulation, &written.simulation) {
+            migrate_simulation(simulation, config.version, written);
+        }
+        config.version = PROJECT_VERSION;
+
+        Ok(config)
+    }
+
+    /// Loads the file named by `SPLOOSH_CONFIG`, or `sploosh.toml` in the working directory
+    /// if it exists. Falls back to the default configuration when neither is present.
+    pub fn load() -> Result<Self, SplooshError> {
+        if let Ok(path) = std::env::var(CONFIG_ENV_VAR) {
+            return AppConfig::from_file(path);
+        }
+
+        if Path::new(DEFAULT_CONFIG_PATH).exists() {
+            return AppConfig::from_file(DEFAULT_CONFIG_PATH);
+        }
+
+        Ok(AppConfig::default())
+    }
+}
+
+pub fn parse_file<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, SplooshError> {
+    let path = path.as_ref();
+    parse_contents(path, &std::fs::read_to_string(path)?)
+}
+
+/// Parses `contents` in the format given by the extension of `path`.
+pub fn parse_contents<T: DeserializeOwned>(path: &Path, contents: &str) -> Result<T, SplooshError> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => Ok(toml::from_str(contents)?),
+        Some("ron") => Ok(ron::from_str(contents)?),
+        _ => Err(SplooshError::Config(format!(
+            "Unsupported config file format: {}",
+            path.display()
+        ))),
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    collections::VecDeque,
+    fmt::{Debug, Write as _},
+    sync::{LazyLock, Mutex},
+};
+
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+/// Messages kept by the console, the oldest ones are dropped first.
+pub const MAX_CONSOLE_ENTRIES: usize = 500;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConsoleEntry {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    /// Times the message was logged in a row, e.g. by a broken pipeline every frame
+    pub count: u32,
+}
+
+/// Warnings and errors shown in the console panel of the gui, wgpu validation errors among
+/// them.
+#[derive(Default)]
+pub struct Console {
+    entries: Mutex<VecDeque<ConsoleEntry>>,
+}
+
+static CONSOLE: LazyLock<Console> = LazyLock::new(Console::default);
+
+impl Console {
+    /// The console filled by `ConsoleLayer`.
+    pub fn global() -> &'static Console {
+        &CONSOLE
+    }
+
+    pub fn push(&self, level: Level, target: &str, message: String) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(last) = entries.back_mut() {
+            if last.level == level && last.target == target && last.message == message {
+                last.count += 1;
+                return;
+            }
+        }
+
+        if entries.len() >= MAX_CONSOLE_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(ConsoleEntry {
+            level,
+            target: target.to_string(),
+            message,
+            count: 1,
+        });
+    }
+
+    pub fn entries(&self) -> Vec<ConsoleEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Collects the warnings and errors logged through `tracing` into the global console.
+pub struct ConsoleLayer;
+
+impl<S: Subscriber> Layer<S> for ConsoleLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        // more verbose levels compare greater
+        if *metadata.level() > Level::WARN {
+            return;
+        }
+
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+        Console::global().push(*metadata.level(), metadata.target(), message.0);
+    }
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_messages_are_counted() {
+        let console = Console::default();
+        console.push(Level::ERROR, "wgpu", "Invalid pipeline".to_string());
+        console.push(Level::ERROR, "wgpu", "Invalid pipeline".to_string());
+        console.push(Level::WARN, "sploosh", "Skipped a frame".to_string());
+
+        let entries = console.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].count, 2);
+        assert_eq!(entries[1].message, "Skipped a frame");
+    }
+}
@@ -0,0 +1,326 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
+
+use nalgebra::{Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fluid_simulation::SimDim,
+    graphics::{
+        geometry::{Geometry, Mesh},
+        materials::MeshInstance,
+        render_engine::GenericRequest,
+    },
+    wgpu_device::Uploader,
+    ComputeTask, SpatialLookup, WgpuDevice,
+};
+
+pub const MAX_DEBRIS: u32 = 16384;
+
+/// Passive pieces of debris, like leaves or foam flecks, carried along by the fluid without
+/// acting back on it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebrisConfig {
+    /// Pieces alive at once, the oldest are respawned first
+    pub count: u32,
+    /// Pieces spawned per simulated second
+    pub spawn_rate: f32,
+    /// In world space, the bounding box is centered at the origin
+    pub spawn_center: Point3<f32>,
+    pub spawn_radius: f32,
+    /// Rate at which a submerged piece takes on the velocity of the fluid around it, per
+    /// second
+    pub drag: f32,
+    /// Density of the fluid over the density of the debris, above one it floats
+    pub buoyancy: f32,
+    /// Size relative to the fluid particles
+    pub size: f32,
+}
+
+impl Default for DebrisConfig {
+    fn default() -> Self {
+        Self {
+            count: 2048,
+            spawn_rate: 200.0,
+            spawn_center: Point3::new(0.0, 2.5, 0.0),
+            spawn_radius: 1.0,
+            drag: 5.0,
+            buoyancy: 1.5,
+            size: 0.6,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct DebrisUniform {
+    spawn_center: [f32; 3],
+    spawn_radius: f32,
+    count: u32,
+    drag: f32,
+    buoyancy: f32,
+    size: f32,
+}
+
+/// Changes with every step, so it is pushed with the pass instead of written to the uniform.
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct DebrisStep {
+    first_spawn: u32,
+    spawn_cnt: u32,
+    seed: u32,
+    dt: f32,
+}
+
+/// Ring of debris pieces advected through the interpolated fluid velocity, drawn as spheres.
+/// Pieces that were never spawned or left the domain are drawn with a zero size.
+pub struct Debris {
+    bbox_dimensions: Vector3<f32>,
+    position_buffer: Arc<wgpu::Buffer>,
+    display_buffer: Arc<wgpu::Buffer>,
+    sphere: Arc<Mesh>,
+    uniform_buffer: Arc<wgpu::Buffer>,
+    step_task: Arc<ComputeTask>,
+    uploader: Uploader,
+    /// Index of the next piece to spawn
+    next_spawn: Arc<AtomicU32>,
+    /// Bits of the fraction of a piece left over from the last step
+    spawn_remainder: Arc<AtomicU32>,
+}
+
+impl Debris {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        ghost_particle_cnt: usize,
+        mass: f32,
+        rest_density: f32,
+        poly6: f32,
+        gravity: Vector3<f32>,
+        bbox_dimensions: Vector3<f32>,
+        dimensions: SimDim,
+        walls: bool,
+        floor: bool,
+        kill_radius: f32,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        velocities: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+    ) -> Self {
+        // a zero w marks pieces that are not alive
+        let state_buffer = |label| {
+            wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: MAX_DEBRIS as u64 * 4 * std::mem::size_of::<f32>() as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        let position_buffer = state_buffer("Debris position buffer");
+        let velocity_buffer = state_buffer("Debris velocity buffer");
+        let display_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debris display buffer"),
+            size: (MAX_DEBRIS as usize * std::mem::size_of::<MeshInstance>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_buffer = wgpu_device.create_buffer_init(
+            &[DebrisUniform::default()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             const MASS: f32 = {mass};\n
+             const REST_DENSITY: f32 = {rest_density};\n
+             const POLY6: f32 = {poly6};\n
+             const G: vec3<f32> = vec3<f32>({}, {}, {});\n
+             const BBOX: vec3<f32> = vec3<f32>({}, {}, {});\n
+             const OFFSET: vec3<f32> = vec3<f32>({}, {}, {});\n
+             const FLAT: bool = {};\n
+             const WALLS: bool = {walls};\n
+             const FLOOR: bool = {floor};\n
+             const KILL_RADIUS: f32 = {kill_radius};\n
+             {}
+             {}",
+            gravity.x,
+            gravity.y,
+            gravity.z,
+            bbox_dimensions.x,
+            bbox_dimensions.y,
+            bbox_dimensions.z,
+            -bbox_dimensions.x / 2.0,
+            -bbox_dimensions.y / 2.0,
+            -bbox_dimensions.z / 2.0,
+            dimensions == SimDim::Two,
+            spatial_lookup.shader_source(),
+            include_str!("shaders/debris.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let step_task = Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Debris",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, true),
+                storage_entry(5, true),
+                storage_entry(6, false),
+                storage_entry(7, false),
+                storage_entry(8, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: velocities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: densities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: position_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: velocity_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: display_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..16,
+            }],
+            shader_source.into(),
+            (MAX_DEBRIS.div_ceil(256), 1, 1),
+        ));
+
+        Self {
+            uploader: wgpu_device.uploader.clone(),
+            bbox_dimensions,
+            position_buffer,
+            display_buffer,
+            sphere: Arc::new(Mesh::sphere(wgpu_device, 6, 10)),
+            uniform_buffer,
+            step_task,
+            next_spawn: Arc::new(AtomicU32::new(0)),
+            spawn_remainder: Arc::new(AtomicU32::new(0.0f32.to_bits())),
+        }
+    }
+
+    /// Spawns the pieces due over `dt` and advects all of them, run after the densities of a
+    /// step have been computed.
+    pub fn step_fn(&self, config: DebrisConfig, dt: f32) -> GenericRequest {
+        let count = config.count.clamp(1, MAX_DEBRIS);
+        let spawn_center = config.spawn_center + self.bbox_dimensions / 2.0;
+        let uniform = DebrisUniform {
+            spawn_center: spawn_center.into(),
+            spawn_radius: config.spawn_radius,
+            count,
+            drag: config.drag,
+            buoyancy: config.buoyancy,
+            size: config.size,
+        };
+        let spawn_rate = config.spawn_rate.max(0.0);
+
+        let uploader = self.uploader.clone();
+        let uniform_buffer = self.uniform_buffer.clone();
+        let step_task = self.step_task.clone();
+        let next_spawn = self.next_spawn.clone();
+        let spawn_remainder = self.spawn_remainder.clone();
+
+        Box::new(move |encoder, _| {
+            let due = f32::from_bits(spawn_remainder.load(Ordering::Relaxed)) + spawn_rate * dt;
+            let spawn_cnt = (due.floor() as u32).min(count);
+            spawn_remainder.store(
+                (due - spawn_cnt as f32).min(1.0).to_bits(),
+                Ordering::Relaxed,
+            );
+            let first_spawn = next_spawn.fetch_add(spawn_cnt, Ordering::Relaxed) % count;
+
+            let step = DebrisStep {
+                first_spawn,
+                spawn_cnt,
+                seed: first_spawn.wrapping_mul(2654435761),
+                dt,
+            };
+            uploader.write(encoder, &uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+            step_task.execute(encoder, bytemuck::bytes_of(&step));
+        })
+    }
+
+    /// Removes all pieces, spawning starts over.
+    pub fn clear_fn(&self) -> GenericRequest {
+        let position_buffer = self.position_buffer.clone();
+        let display_buffer = self.display_buffer.clone();
+        let next_spawn = self.next_spawn.clone();
+
+        Box::new(move |encoder, _| {
+            encoder.clear_buffer(&position_buffer, 0, None);
+            encoder.clear_buffer(&display_buffer, 0, None);
+            next_spawn.store(0, Ordering::Relaxed);
+        })
+    }
+
+    /// Spheres placed by the last step, drawn with the instanced mesh material.
+    pub fn geometry(&self, config: DebrisConfig) -> Geometry {
+        Geometry::InstancedMesh {
+            mesh: self.sphere.clone(),
+            instance_buffer: self.display_buffer.clone(),
+            instance_cnt: config.count.clamp(1, MAX_DEBRIS) as usize,
+        }
+    }
+}
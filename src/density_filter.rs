@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fluid_simulation::SimDim, graphics::render_engine::GenericRequest, ComputeTask, SpatialLookup,
+    WgpuDevice,
+};
+
+/// Filter modes pushed to the shader.
+const FREE_SURFACE: u32 = 0;
+const SHEPARD: u32 = 1;
+const MOVING_LEAST_SQUARES: u32 = 2;
+
+/// Replaces the densities with a filtered estimate every few steps, which removes noise and
+/// the slow drift of the density field.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DensityRenormalization {
+    pub method: RenormalizationMethod,
+    /// Steps between two passes
+    pub interval: u32,
+}
+
+impl Default for DensityRenormalization {
+    fn default() -> Self {
+        Self {
+            method: RenormalizationMethod::Shepard,
+            interval: 20,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenormalizationMethod {
+    /// Zeroth order, divides by the kernel sum over the particle volumes
+    #[default]
+    Shepard,
+    /// First order, also reproduces a linear density field exactly. Falls back to Shepard
+    /// where the neighborhood is too sparse to fit the gradient.
+    MovingLeastSquares,
+}
+
+impl RenormalizationMethod {
+    pub const ALL: [RenormalizationMethod; 2] = [
+        RenormalizationMethod::Shepard,
+        RenormalizationMethod::MovingLeastSquares,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenormalizationMethod::Shepard => "Shepard",
+            RenormalizationMethod::MovingLeastSquares => "Moving least squares",
+        }
+    }
+
+    fn shader_id(&self) -> u32 {
+        match self {
+            RenormalizationMethod::Shepard => SHEPARD,
+            RenormalizationMethod::MovingLeastSquares => MOVING_LEAST_SQUARES,
+        }
+    }
+}
+
+/// Corrects the SPH densities with the kernel sum over the particle volumes, which is one
+/// inside the fluid and falls off where the neighborhood is incomplete.
+pub struct DensityFilter {
+    ghost_particle_cnt: usize,
+    density_buffer: Arc<wgpu::Buffer>,
+    filtered_buffer: Arc<wgpu::Buffer>,
+    filter_task: Arc<ComputeTask>,
+}
+
+impl DensityFilter {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        mass: f32,
+        poly6: f32,
+        dimensions: SimDim,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        densities: &Arc<wgpu::Buffer>,
+    ) -> Self {
+        let filtered_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Filtered density buffer"),
+            size: densities.size(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let filter_task = DensityFilter::create_filter_task(
+            wgpu_device,
+            particle_cnt - ghost_particle_cnt,
+            ghost_particle_cnt,
+            mass,
+            poly6,
+            dimensions,
+            spatial_lookup,
+            positions,
+            densities,
+            &filtered_buffer,
+        );
+
+        Self {
+            ghost_particle_cnt,
+            density_buffer: densities.clone(),
+            filtered_buffer,
+            filter_task,
+        }
+    }
+
+    /// Raises the densities of the particles near the free surface, where the missing
+    /// neighbors make the SPH sum too small and the resulting negative pressure clumps the
+    /// particles together. Inside the fluid the densities are left as they are.
+    pub fn free_surface_fn(&self) -> GenericRequest {
+        self.filter_fn(FREE_SURFACE)
+    }
+
+    /// Filters the densities of all fluid particles.
+    pub fn renormalize_fn(&self, method: RenormalizationMethod) -> GenericRequest {
+        self.filter_fn(method.shader_id())
+    }
+
+    fn filter_fn(&self, mode: u32) -> GenericRequest {
+        let filter_task = self.filter_task.clone();
+        let density_buffer = self.density_buffer.clone();
+        let filtered_buffer = self.filtered_buffer.clone();
+        // the ghost particles keep their rest density
+        let offset = (self.ghost_particle_cnt * std::mem::size_of::<f32>()) as u64;
+
+        Box::new(move |encoder, _| {
+            filter_task.execute(encoder, bytemuck::bytes_of(&mode));
+            encoder.copy_buffer_to_buffer(
+                &filtered_buffer,
+                offset,
+                &density_buffer,
+                offset,
+                density_buffer.size() - offset,
+            );
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_filter_task(
+        wgpu_device: &WgpuDevice,
+        fluid_particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        mass: f32,
+        poly6: f32,
+        dimensions: SimDim,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+        filtered_buffer: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = (fluid_particle_cnt as u32).div_ceil(256);
+
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             const MASS: f32 = {mass};\n
+             const POLY6: f32 = {poly6};\n
+             const FLAT: bool = {};\n
+             const FREE_SURFACE: u32 = {FREE_SURFACE}u;\n
+             const SHEPARD: u32 = {SHEPARD}u;\n
+             const MOVING_LEAST_SQUARES: u32 = {MOVING_LEAST_SQUARES}u;\n
+             {}
+             {}",
+            dimensions == SimDim::Two,
+            spatial_lookup.shader_source(),
+            include_str!("shaders/density_filter.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Density filter",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, true),
+                storage_entry(5, false),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: densities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: filtered_buffer.as_entire_binding(),
+                },
+            ],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..4,
+            }],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+}
@@ -0,0 +1,344 @@
+use std::sync::Arc;
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    colormap::{Colormap, ColormapTexture},
+    graphics::{
+        geometry::Geometry,
+        materials::{TexturedVertex, DENSITY_SLICE_LAYOUT_ENTRIES},
+        render_engine::GenericRequest,
+    },
+    wgpu_device::Uploader,
+    ComputeTask, SpatialLookup, WgpuDevice,
+};
+
+/// Texels along each side of the slice texture.
+pub const SLICE_RESOLUTION: u32 = 256;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SliceAxis {
+    X,
+    Y,
+    #[default]
+    Z,
+}
+
+impl SliceAxis {
+    pub const ALL: [SliceAxis; 3] = [SliceAxis::X, SliceAxis::Y, SliceAxis::Z];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SliceAxis::X => "X",
+            SliceAxis::Y => "Y",
+            SliceAxis::Z => "Z",
+        }
+    }
+
+    fn shader_id(&self) -> u32 {
+        match self {
+            SliceAxis::X => 0,
+            SliceAxis::Y => 1,
+            SliceAxis::Z => 2,
+        }
+    }
+}
+
+/// Axis aligned plane through the bounding box showing the SPH density of the particles.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DensitySliceConfig {
+    pub axis: SliceAxis,
+    /// Position of the plane along the axis, from 0 to 1 across the bounding box
+    pub position: f32,
+    /// Densities mapped to the ends of the color map
+    pub min_density: f32,
+    pub max_density: f32,
+    pub color_map: Colormap,
+}
+
+impl Default for DensitySliceConfig {
+    fn default() -> Self {
+        Self {
+            axis: SliceAxis::Z,
+            position: 0.5,
+            min_density: 0.0,
+            max_density: 400.0,
+            color_map: Colormap::Heatmap,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct SliceUniform {
+    axis: u32,
+    position: f32,
+    min_density: f32,
+    max_density: f32,
+}
+
+/// Evaluates the density on a grid of points in the slice plane and draws it as a textured
+/// quad.
+pub struct DensitySlice {
+    bbox_dimensions: Vector3<f32>,
+    uniform_buffer: Arc<wgpu::Buffer>,
+    quad_buffer: Arc<wgpu::Buffer>,
+    colormap_texture: ColormapTexture,
+    bind_group: Arc<wgpu::BindGroup>,
+    slice_task: Arc<ComputeTask>,
+    uploader: Uploader,
+}
+
+impl DensitySlice {
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        bbox_dimensions: Vector3<f32>,
+        mass: f32,
+        poly6: f32,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+    ) -> Self {
+        let device = &wgpu_device.device;
+
+        let uniform_buffer = wgpu_device.create_buffer_init(
+            &[SliceUniform::default()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        let quad_buffer = wgpu_device.create_buffer_init(
+            &[TexturedVertex::default(); 6],
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Density slice texture"),
+            size: wgpu::Extent3d {
+                width: SLICE_RESOLUTION,
+                height: SLICE_RESOLUTION,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Density slice sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let colormap_texture = ColormapTexture::new(wgpu_device);
+
+        // the same layout the density slice material was created with
+        let bind_group_layout = wgpu_device.bind_group_layout(&DENSITY_SLICE_LAYOUT_ENTRIES);
+        let bind_group = Arc::new(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Density slice bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(colormap_texture.view()),
+                },
+            ],
+        }));
+
+        let slice_task = DensitySlice::create_slice_task(
+            wgpu_device,
+            bbox_dimensions,
+            mass,
+            poly6,
+            spatial_lookup,
+            positions,
+            &uniform_buffer,
+            &view,
+        );
+
+        Self {
+            uploader: wgpu_device.uploader.clone(),
+            bbox_dimensions,
+            uniform_buffer,
+            quad_buffer,
+            colormap_texture,
+            bind_group,
+            slice_task,
+        }
+    }
+
+    pub fn update_fn(&self, config: DensitySliceConfig) -> GenericRequest {
+        let axis = config.axis.shader_id();
+        let position = config.position.clamp(0.0, 1.0) * self.bbox_dimensions[axis as usize];
+
+        let uniform = SliceUniform {
+            axis,
+            position,
+            min_density: config.min_density,
+            max_density: config.max_density.max(config.min_density + f32::EPSILON),
+        };
+        let quad = self.quad(config.axis, position);
+        let uploader = self.uploader.clone();
+        let uniform_buffer = self.uniform_buffer.clone();
+        let quad_buffer = self.quad_buffer.clone();
+        let slice_task = self.slice_task.clone();
+        let upload_colormap = self.colormap_texture.upload_fn(config.color_map);
+
+        Box::new(move |encoder, queue| {
+            upload_colormap(encoder, queue);
+            uploader.write(encoder, &uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+            uploader.write(encoder, &quad_buffer, 0, bytemuck::cast_slice(&quad));
+            slice_task.execute(encoder, &[]);
+        })
+    }
+
+    /// Quad written by the last pass of `update_fn`, for the density slice material.
+    pub fn geometry(&self) -> Geometry {
+        Geometry::Textured {
+            vertex_buffer: self.quad_buffer.clone(),
+            vertex_cnt: 6,
+            bind_group: self.bind_group.clone(),
+        }
+    }
+
+    /// Two triangles spanning the bounding box in world space, the uv mapping matches the
+    /// sample points of the slice shader.
+    fn quad(&self, axis: SliceAxis, position: f32) -> [TexturedVertex; 6] {
+        let bbox = self.bbox_dimensions;
+        let vertex = |u: f32, v: f32| {
+            let point = match axis {
+                SliceAxis::X => Vector3::new(position, v * bbox.y, u * bbox.z),
+                SliceAxis::Y => Vector3::new(u * bbox.x, position, v * bbox.z),
+                SliceAxis::Z => Vector3::new(u * bbox.x, v * bbox.y, position),
+            } - bbox / 2.0;
+
+            TexturedVertex {
+                position: point.into(),
+                uv: [u, v],
+            }
+        };
+
+        [
+            vertex(0.0, 0.0),
+            vertex(1.0, 0.0),
+            vertex(1.0, 1.0),
+            vertex(0.0, 0.0),
+            vertex(1.0, 1.0),
+            vertex(0.0, 1.0),
+        ]
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_slice_task(
+        wgpu_device: &WgpuDevice,
+        bbox_dimensions: Vector3<f32>,
+        mass: f32,
+        poly6: f32,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        uniform_buffer: &wgpu::Buffer,
+        slice_view: &wgpu::TextureView,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = SLICE_RESOLUTION.div_ceil(16);
+
+        let shader_source = format!(
+            "
+             const BBOX: vec3<f32> = vec3<f32>({}, {}, {});\n
+             const POLY6: f32 = {poly6};\n
+             const MASS: f32 = {mass};\n
+             {}
+             {}",
+            bbox_dimensions.x,
+            bbox_dimensions.y,
+            bbox_dimensions.z,
+            spatial_lookup.shader_source(),
+            include_str!("shaders/density_slice.wgsl")
+        );
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Density slice",
+            &[
+                storage_entry(0),
+                storage_entry(1),
+                storage_entry(2),
+                storage_entry(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(slice_view),
+                },
+            ],
+            &[],
+            shader_source.into(),
+            (workgroup_cnt, workgroup_cnt, 1),
+        ))
+    }
+}
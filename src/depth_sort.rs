@@ -0,0 +1,278 @@
+use std::{num::NonZeroU32, sync::Arc};
+
+use wgpu_sort::{GPUSorter, SortBuffers};
+
+use crate::{graphics::render_engine::GenericRequest, ComputeTask, SplooshError, WgpuDevice};
+
+/// Sorts the display buffer back-to-front by view depth into a second buffer, for materials
+/// that blend the particles.
+pub struct DepthSort {
+    sort: Arc<GPUSorter>,
+    sort_buffers: Arc<SortBuffers>,
+    depth_key_task: Arc<ComputeTask>,
+    gather_task: Arc<ComputeTask>,
+    sorted_display_buffer: Arc<wgpu::Buffer>,
+}
+
+impl DepthSort {
+    /// `draw_args` limits the sort to the particles written by the display pass, `cull` holds
+    /// the camera the depth is measured from. Fails for no particles or more than `u32::MAX`.
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        sort: Arc<GPUSorter>,
+        particle_cnt: usize,
+        display_buffer: &wgpu::Buffer,
+        draw_args: &wgpu::Buffer,
+        cull: &wgpu::Buffer,
+    ) -> Result<Self, SplooshError> {
+        let sort_cnt = u32::try_from(particle_cnt)
+            .ok()
+            .and_then(NonZeroU32::new)
+            .ok_or_else(|| {
+                SplooshError::Config(format!(
+                    "The depth sort needs between 1 and {} particles, not {particle_cnt}",
+                    u32::MAX
+                ))
+            })?;
+        let sort_buffers = Arc::new(sort.create_sort_buffers(&wgpu_device.device, sort_cnt));
+
+        let sorted_display_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sorted display buffer"),
+            size: display_buffer.size(),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let depth_key_task = DepthSort::create_depth_key_task(
+            wgpu_device,
+            particle_cnt,
+            display_buffer,
+            draw_args,
+            cull,
+            sort_buffers.keys(),
+            sort_buffers.values(),
+        );
+
+        let gather_task = DepthSort::create_gather_task(
+            wgpu_device,
+            particle_cnt,
+            display_buffer,
+            draw_args,
+            sort_buffers.values(),
+            &sorted_display_buffer,
+        );
+
+        Ok(Self {
+            sort,
+            sort_buffers,
+            depth_key_task,
+            gather_task,
+            sorted_display_buffer,
+        })
+    }
+
+    pub fn sorted_display(&self) -> Arc<wgpu::Buffer> {
+        self.sorted_display_buffer.clone()
+    }
+
+    pub fn sort_fn(&self) -> GenericRequest {
+        let sort = self.sort.clone();
+        let sort_buffers = self.sort_buffers.clone();
+        let depth_key_task = self.depth_key_task.clone();
+        let gather_task = self.gather_task.clone();
+
+        Box::new(move |encoder, queue| {
+            depth_key_task.execute(encoder, &[]);
+            sort.sort(encoder, queue, &sort_buffers, None);
+            gather_task.execute(encoder, &[]);
+        })
+    }
+
+    fn create_depth_key_task(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        display_buffer: &wgpu::Buffer,
+        draw_args: &wgpu::Buffer,
+        cull: &wgpu::Buffer,
+        depth_keys: &wgpu::Buffer,
+        depth_vals: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = (particle_cnt as u32).div_ceil(256);
+
+        let shader_source = format!(
+            "const PARTICLE_CNT: u32 = {particle_cnt};\n
+             {}
+             {}",
+            include_str!("shaders/display_particle.wgsl"),
+            include_str!("shaders/depth_sort_keys.wgsl")
+        );
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Depth sort keys",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: display_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: draw_args.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: cull.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: depth_keys.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: depth_vals.as_entire_binding(),
+                },
+            ],
+            &[],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+
+    fn create_gather_task(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        display_buffer: &wgpu::Buffer,
+        draw_args: &wgpu::Buffer,
+        depth_vals: &wgpu::Buffer,
+        sorted_display_buffer: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = (particle_cnt as u32).div_ceil(256);
+
+        let shader_source = format!(
+            "{}
+             {}",
+            include_str!("shaders/display_particle.wgsl"),
+            include_str!("shaders/depth_sort_gather.wgsl")
+        );
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Depth sort gather",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: display_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: draw_args.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: depth_vals.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: sorted_display_buffer.as_entire_binding(),
+                },
+            ],
+            &[],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+}
@@ -0,0 +1,297 @@
+use std::{fs::File, io::Write, path::Path, sync::Arc};
+
+use crate::{
+    graphics::render_engine::GenericRequest, readback::Readback, ComputeTask, SpatialLookup,
+    SplooshError, WgpuDevice,
+};
+
+pub const DIAGNOSTICS_BINS: usize = 32;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ParticleDiagnostics {
+    /// SPH estimate of the velocity divergence, zero for an incompressible flow
+    pub divergence: f32,
+    /// Pressure from the equation of state
+    pub pressure: f32,
+}
+
+/// Particle counts over evenly sized bins between the smallest and the largest value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    pub bins: Vec<u32>,
+}
+
+impl Histogram {
+    /// Non-finite values are left out.
+    pub fn new(values: &[f32], bin_cnt: usize) -> Self {
+        let finite = || values.iter().copied().filter(|x| x.is_finite());
+        let min = finite().fold(f32::INFINITY, f32::min);
+        let max = finite().fold(f32::NEG_INFINITY, f32::max);
+        let mut bins = vec![0; bin_cnt];
+        if min > max {
+            return Self {
+                min: 0.0,
+                max: 0.0,
+                bins,
+            };
+        }
+
+        let width = (max - min).max(f32::EPSILON) / bin_cnt as f32;
+        for x in finite() {
+            let bin = ((x - min) / width) as usize;
+            bins[bin.min(bin_cnt - 1)] += 1;
+        }
+
+        Self { min, max, bins }
+    }
+
+    pub fn bin_width(&self) -> f32 {
+        (self.max - self.min) / self.bins.len() as f32
+    }
+}
+
+/// Distribution of the diagnostics over the fluid particles, for comparing solver settings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiagnosticsSummary {
+    pub divergence: Histogram,
+    pub pressure: Histogram,
+    pub mean_abs_divergence: f32,
+    pub mean_pressure: f32,
+}
+
+impl DiagnosticsSummary {
+    pub fn new(diagnostics: &[ParticleDiagnostics]) -> Self {
+        let divergence: Vec<f32> = diagnostics.iter().map(|d| d.divergence).collect();
+        let pressure: Vec<f32> = diagnostics.iter().map(|d| d.pressure).collect();
+        let cnt = diagnostics.len().max(1) as f32;
+
+        Self {
+            divergence: Histogram::new(&divergence, DIAGNOSTICS_BINS),
+            pressure: Histogram::new(&pressure, DIAGNOSTICS_BINS),
+            mean_abs_divergence: divergence.iter().map(|x| x.abs()).sum::<f32>() / cnt,
+            mean_pressure: pressure.iter().sum::<f32>() / cnt,
+        }
+    }
+}
+
+/// Writes a row per fluid particle, numbered like the particle buffers.
+pub fn export_diagnostics(
+    path: &Path,
+    ghost_particle_cnt: usize,
+    diagnostics: &[ParticleDiagnostics],
+) -> Result<(), SplooshError> {
+    let mut file = std::io::BufWriter::new(File::create(path)?);
+    writeln!(file, "particle,divergence,pressure")?;
+    for (i, d) in diagnostics.iter().enumerate() {
+        writeln!(
+            file,
+            "{},{},{}",
+            ghost_particle_cnt + i,
+            d.divergence,
+            d.pressure
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Computes the velocity divergence and pressure of every fluid particle from the current
+/// state.
+pub struct Diagnostics {
+    diagnostics_buffer: Arc<wgpu::Buffer>,
+    readback: Readback<ParticleDiagnostics>,
+    diagnostics_task: Arc<ComputeTask>,
+}
+
+impl Diagnostics {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        mass: f32,
+        gas_const: f32,
+        rest_density: f32,
+        spiky_grad: f32,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        velocities: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+    ) -> Self {
+        let fluid_particle_cnt = particle_cnt - ghost_particle_cnt;
+        let size = (fluid_particle_cnt.max(1) * std::mem::size_of::<ParticleDiagnostics>()) as u64;
+
+        let diagnostics_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Diagnostics buffer"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = Readback::new(wgpu_device, "Diagnostics readback", size);
+
+        let diagnostics_task = Diagnostics::create_diagnostics_task(
+            wgpu_device,
+            fluid_particle_cnt,
+            ghost_particle_cnt,
+            mass,
+            gas_const,
+            rest_density,
+            spiky_grad,
+            spatial_lookup,
+            positions,
+            velocities,
+            densities,
+            &diagnostics_buffer,
+        );
+
+        Self {
+            diagnostics_buffer,
+            readback,
+            diagnostics_task,
+        }
+    }
+
+    /// Needs an up to date spatial lookup and densities, so it runs after a step.
+    pub fn update_fn(&self) -> GenericRequest {
+        let diagnostics_task = self.diagnostics_task.clone();
+        let diagnostics_buffer = self.diagnostics_buffer.clone();
+        let readback = self.readback.clone();
+
+        Box::new(move |encoder, _| {
+            diagnostics_task.execute(encoder, &[]);
+            readback.copy(encoder, |encoder, staging_buffer| {
+                encoder.copy_buffer_to_buffer(
+                    &diagnostics_buffer,
+                    0,
+                    staging_buffer,
+                    0,
+                    diagnostics_buffer.size(),
+                );
+            });
+        })
+    }
+
+    /// Blocks until the submitted passes of `update_fn` have finished. The first entry belongs
+    /// to the first fluid particle.
+    pub fn read(&self, device: &wgpu::Device) -> Result<Vec<ParticleDiagnostics>, SplooshError> {
+        self.readback.wait(device)
+    }
+
+    /// Like `read` without blocking, `None` until newer diagnostics arrive.
+    pub fn poll(
+        &self,
+        device: &wgpu::Device,
+    ) -> Option<Result<Vec<ParticleDiagnostics>, SplooshError>> {
+        self.readback.poll(device)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_diagnostics_task(
+        wgpu_device: &WgpuDevice,
+        fluid_particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        mass: f32,
+        gas_const: f32,
+        rest_density: f32,
+        spiky_grad: f32,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        velocities: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+        diagnostics_buffer: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = (fluid_particle_cnt as u32).div_ceil(256);
+
+        // the force pass scales the spiky kernel by its normalization, the cubic falloff
+        // contributes the factor of three to the derivative
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             const MASS: f32 = {mass};\n
+             const GAS_CONST: f32 = {gas_const};\n
+             const REST_DENSITY: f32 = {rest_density};\n
+             const SPIKY_DERIVATIVE: f32 = {};\n
+             {}
+             {}",
+            3.0 * spiky_grad,
+            spatial_lookup.shader_source(),
+            include_str!("shaders/diagnostics.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Diagnostics",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, true),
+                storage_entry(5, true),
+                storage_entry(6, false),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: velocities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: densities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: diagnostics_buffer.as_entire_binding(),
+                },
+            ],
+            &[],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_spans_finite_values() {
+        let histogram = Histogram::new(&[-1.0, 0.0, 0.5, 1.0, f32::NAN], 4);
+
+        assert_eq!(histogram.min, -1.0);
+        assert_eq!(histogram.max, 1.0);
+        assert_eq!(histogram.bins, vec![1, 0, 1, 2]);
+        assert_eq!(histogram.bin_width(), 0.5);
+    }
+}
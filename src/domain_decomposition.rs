@@ -0,0 +1,489 @@
+//! Experimental mode splitting a large simulation between two GPUs. The domain is cut by a
+//! plane along one axis, every device owns the particles on its side and receives copies of the
+//! particles of the other side near the plane, its halo, exchanged through CPU staging buffers
+//! after every step.
+//!
+//! The simulation buffers have a fixed particle count, so particles can't move between the
+//! devices from one step to the next. Each device keeps stepping the particles it owns, also
+//! once they cross the plane, and the halo is widened by a skin on both sides to cover them.
+//! Before any particle could have moved further than the skin, the plane is placed at the
+//! median again and both simulations are rebuilt around their new particles.
+
+use std::ops::Range;
+
+use nalgebra::{Point4, Vector3, Vector4};
+
+use crate::{
+    config::AdapterConfig,
+    density_slice::SliceAxis,
+    fluid_simulation::{FluidSimulation, FluidSimulationConfig, ParticleSnapshot},
+    wgpu_device::{create_instance, select_adapter},
+    SplooshError, WgpuDevice,
+};
+
+/// Index of the device owning a particle, 0 below the split plane and 1 above it.
+pub type Side = usize;
+
+/// Plane cutting the domain between the two devices.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DomainSplit {
+    pub axis: SliceAxis,
+    /// Coordinate of the plane along `axis` in m
+    pub position: f32,
+    /// Particles closer than this to the plane are needed by both devices, at least the
+    /// smoothing radius
+    pub halo_width: f32,
+}
+
+/// Particles each device owns and the particles of the other device it needs as its halo.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Partition {
+    pub owned: [Vec<u32>; 2],
+    pub halos: [Vec<u32>; 2],
+}
+
+impl DomainSplit {
+    /// Cuts the longest axis of `bbox_dimensions` at the median of `positions`, which gives
+    /// both devices the same number of particles.
+    pub fn balanced(
+        positions: &[Point4<f32>],
+        bbox_dimensions: Vector3<f32>,
+        halo_width: f32,
+    ) -> Self {
+        let axis = SliceAxis::ALL
+            .into_iter()
+            .max_by(|a, b| {
+                bbox_dimensions[axis_index(*a)].total_cmp(&bbox_dimensions[axis_index(*b)])
+            })
+            .unwrap();
+
+        let mut coordinates: Vec<f32> = positions.iter().map(|p| p[axis_index(axis)]).collect();
+        let position = if coordinates.is_empty() {
+            0.5 * bbox_dimensions[axis_index(axis)]
+        } else {
+            let median = coordinates.len() / 2;
+            *coordinates
+                .select_nth_unstable_by(median, |a, b| a.total_cmp(b))
+                .1
+        };
+
+        Self {
+            axis,
+            position,
+            halo_width,
+        }
+    }
+
+    pub fn side(&self, position: &Point4<f32>) -> Side {
+        (position[axis_index(self.axis)] >= self.position) as Side
+    }
+
+    /// Sorts the particles into the owned sets and the halos the devices send each other.
+    pub fn partition(&self, positions: &[Point4<f32>]) -> Partition {
+        let mut partition = Partition::default();
+        for (i, position) in positions.iter().enumerate() {
+            let side = self.side(position);
+            partition.owned[side].push(i as u32);
+
+            let distance = (position[axis_index(self.axis)] - self.position).abs();
+            if distance < self.halo_width {
+                partition.halos[1 - side].push(i as u32);
+            }
+        }
+
+        partition
+    }
+}
+
+/// Opens a device on two different adapters matching `config`, the first of them the one
+/// `select_adapter` would pick on its own.
+pub async fn adapter_pair(config: &AdapterConfig) -> Result<[WgpuDevice; 2], SplooshError> {
+    let instance = create_instance(config);
+    let first = select_adapter(&instance, config, None).await?;
+    let first_info = first.get_info();
+
+    let second = instance
+        .enumerate_adapters(config.backend.wgpu_backends())
+        .into_iter()
+        .filter(|adapter| {
+            config.name.as_ref().is_none_or(|name| {
+                adapter
+                    .get_info()
+                    .name
+                    .to_lowercase()
+                    .contains(&name.to_lowercase())
+            })
+        })
+        .find(|adapter| {
+            let info = adapter.get_info();
+            (info.vendor, info.device, info.backend)
+                != (first_info.vendor, first_info.device, first_info.backend)
+                && info.device_type != wgpu::DeviceType::Cpu
+        })
+        .ok_or_else(|| {
+            SplooshError::Adapter(format!(
+                "Splitting the domain needs a second adapter next to {}",
+                first_info.name
+            ))
+        })?;
+
+    Ok([
+        WgpuDevice::from_adapter(first).await?,
+        WgpuDevice::from_adapter(second).await?,
+    ])
+}
+
+/// The simulation of one device. Its buffers hold the ghost particles, the owned particles
+/// the other device doesn't need, the owned particles it does need and the halo, in this order.
+struct Subdomain {
+    fluid_sim: FluidSimulation,
+    /// Owned particles sent to the other device after every step
+    border: Range<usize>,
+    /// Copies of the border particles of the other device, stepped but overwritten before
+    /// they are used again
+    halo: Range<usize>,
+}
+
+impl Subdomain {
+    fn owned(&self) -> Range<usize> {
+        self.fluid_sim.ghost_particle_cnt()..self.border.end
+    }
+}
+
+/// A simulation stepped on two devices, each owning the fluid particles on one side of a
+/// `DomainSplit`. The ghost particles are laid out on both devices.
+pub struct SplitSimulation {
+    config: FluidSimulationConfig,
+    devices: [WgpuDevice; 2],
+    split: DomainSplit,
+    subdomains: [Subdomain; 2],
+    /// Distance in m the particles may move before the domain is split again
+    skin: f32,
+    steps_since_split: u64,
+}
+
+impl SplitSimulation {
+    /// Lays out the particles of `config` and splits them at the median between `devices`.
+    /// `skin` is relative to the smoothing radius like the skin of the neighbor caching, a
+    /// wider skin rebuilds the simulations less often but copies more halo particles.
+    pub fn new(
+        config: FluidSimulationConfig,
+        devices: [WgpuDevice; 2],
+        skin: f32,
+    ) -> Result<Self, SplooshError> {
+        // the river teleports particles past the skin, springs and soft bodies hold particle
+        // indices that change with every split
+        if config.river.is_some() || config.elastic_springs.is_some() || config.soft_body.is_some()
+        {
+            return Err(SplooshError::Config(
+                "Splitting the domain supports neither the river, elastic springs nor soft bodies"
+                    .to_string(),
+            ));
+        }
+        let config = FluidSimulationConfig {
+            rest_state: None,
+            playback: None,
+            ..config
+        };
+
+        let (positions, ghost_particle_cnt) = FluidSimulation::particle_start_positions(
+            config.particle_cnt,
+            config.smoothing_radius,
+            config.simulation_bbox(),
+            config.initial_layout,
+            config.dimensions,
+            config.boundary,
+        );
+        let positions = positions[ghost_particle_cnt..].to_vec();
+        let particles = ParticleSnapshot {
+            velocities: vec![Vector4::zeros(); positions.len()],
+            densities: vec![config.rest_density; positions.len()],
+            positions,
+            time: 0.0,
+            step_cnt: 0,
+        };
+
+        let skin = skin.max(0.0) * config.smoothing_radius;
+        let (split, subdomains) = split_particles(&config, &devices, skin, &particles)?;
+
+        Ok(Self {
+            config,
+            devices,
+            split,
+            subdomains,
+            skin,
+            steps_since_split: 0,
+        })
+    }
+
+    pub fn split(&self) -> &DomainSplit {
+        &self.split
+    }
+
+    /// Fluid particles owned by each device.
+    pub fn owned_particle_cnts(&self) -> [usize; 2] {
+        self.subdomains
+            .each_ref()
+            .map(|subdomain| subdomain.owned().len())
+    }
+
+    /// Simulated seconds since the start.
+    pub fn sim_time(&self) -> f32 {
+        self.subdomains[0].fluid_sim.sim_time()
+    }
+
+    /// Steps both devices by `dt` and exchanges the halos, blocking until both are done. The
+    /// domain is split again first if a particle could have moved further than the skin.
+    pub fn step(&mut self, dt: f32) -> Result<(), SplooshError> {
+        // a particle at the speed of sound bounds the flow speed like in the CFL condition
+        let max_travel = (self.steps_since_split + 1) as f32 * self.config.speed_of_sound() * dt;
+        if self.steps_since_split > 0 && max_travel > self.skin {
+            let particles = self.read_fluid_particles()?;
+            (self.split, self.subdomains) =
+                split_particles(&self.config, &self.devices, self.skin, &particles)?;
+            self.steps_since_split = 0;
+        }
+
+        for (wgpu_device, subdomain) in self.devices.iter().zip(&self.subdomains) {
+            let mut encoder =
+                wgpu_device
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("Split step encoder"),
+                    });
+            subdomain.fluid_sim.step_fn(dt)(&mut encoder, &wgpu_device.queue);
+            wgpu_device.submit(encoder);
+        }
+
+        let borders = [0, 1].map(|side| {
+            let subdomain = &self.subdomains[side];
+            subdomain
+                .fluid_sim
+                .read_particles(&self.devices[side], subdomain.border.clone())
+        });
+        for (side, border) in borders.into_iter().enumerate() {
+            let other = &self.subdomains[1 - side];
+            other.fluid_sim.write_particles(
+                &self.devices[1 - side].queue,
+                other.halo.start,
+                &border?,
+            )?;
+        }
+        self.steps_since_split += 1;
+
+        Ok(())
+    }
+
+    /// Blocks until the submitted steps have finished and gathers the fluid particles of both
+    /// devices, without the ghosts and the halos. The order changes whenever the domain is
+    /// split again.
+    pub fn read_fluid_particles(&self) -> Result<ParticleSnapshot, SplooshError> {
+        let mut particles = ParticleSnapshot {
+            positions: Vec::new(),
+            velocities: Vec::new(),
+            densities: Vec::new(),
+            time: self.sim_time(),
+            step_cnt: self.subdomains[0].fluid_sim.step_cnt(),
+        };
+        for (wgpu_device, subdomain) in self.devices.iter().zip(&self.subdomains) {
+            let owned = subdomain
+                .fluid_sim
+                .read_particles(wgpu_device, subdomain.owned())?;
+            particles.positions.extend(owned.positions);
+            particles.velocities.extend(owned.velocities);
+            particles.densities.extend(owned.densities);
+        }
+
+        Ok(particles)
+    }
+}
+
+/// Splits the fluid `particles` at their median and builds a simulation for each side. The
+/// halo reaches two smoothing radii and the skin on either side of the plane, so the particles
+/// within a smoothing radius of an owned particle are there with their neighbors, which their
+/// density depends on.
+fn split_particles(
+    config: &FluidSimulationConfig,
+    devices: &[WgpuDevice; 2],
+    skin: f32,
+    particles: &ParticleSnapshot,
+) -> Result<(DomainSplit, [Subdomain; 2]), SplooshError> {
+    let halo_width = 2.0 * (config.smoothing_radius + skin);
+    let split = DomainSplit::balanced(&particles.positions, config.simulation_bbox(), halo_width);
+    let partition = split.partition(&particles.positions);
+    let subdomains = [
+        build_subdomain(config, &devices[0], 0, &partition, particles)?,
+        build_subdomain(config, &devices[1], 1, &partition, particles)?,
+    ];
+
+    Ok((split, subdomains))
+}
+
+/// Simulation of the particles `partition` gives to `side`, see `Subdomain` for their order.
+fn build_subdomain(
+    config: &FluidSimulationConfig,
+    wgpu_device: &WgpuDevice,
+    side: Side,
+    partition: &Partition,
+    particles: &ParticleSnapshot,
+) -> Result<Subdomain, SplooshError> {
+    let border = &partition.halos[1 - side];
+    let mut is_border = vec![false; particles.positions.len()];
+    for &i in border {
+        is_border[i as usize] = true;
+    }
+    let interior = partition.owned[side]
+        .iter()
+        .filter(|&&i| !is_border[i as usize]);
+    let order: Vec<usize> = interior
+        .chain(border)
+        .chain(&partition.halos[side])
+        .map(|&i| i as usize)
+        .collect();
+
+    let side_config = FluidSimulationConfig {
+        particle_cnt: order.len(),
+        ..config.clone()
+    };
+    let max_particle_cnt = side_config.max_particle_cnt(&wgpu_device.device.limits())?;
+    if order.len() > max_particle_cnt {
+        return Err(SplooshError::Config(format!(
+            "{} particles of side {side} don't fit into its device, at most {max_particle_cnt} do",
+            order.len()
+        )));
+    }
+    let fluid_sim = FluidSimulation::new(side_config, wgpu_device);
+
+    // the ghost particles come from the new simulation, the fluid particles after them
+    let ghost_particle_cnt = fluid_sim.ghost_particle_cnt();
+    let mut snapshot = fluid_sim.read_particles(wgpu_device, 0..ghost_particle_cnt)?;
+    snapshot
+        .positions
+        .extend(order.iter().map(|&i| particles.positions[i]));
+    snapshot
+        .velocities
+        .extend(order.iter().map(|&i| particles.velocities[i]));
+    snapshot
+        .densities
+        .extend(order.iter().map(|&i| particles.densities[i]));
+    snapshot.time = particles.time;
+    snapshot.step_cnt = particles.step_cnt;
+    fluid_sim.restore_snapshot(&wgpu_device.queue, &snapshot)?;
+
+    let halo_start = ghost_particle_cnt + order.len() - partition.halos[side].len();
+    Ok(Subdomain {
+        fluid_sim,
+        border: halo_start - border.len()..halo_start,
+        halo: halo_start..ghost_particle_cnt + order.len(),
+    })
+}
+
+fn axis_index(axis: SliceAxis) -> usize {
+    match axis {
+        SliceAxis::X => 0,
+        SliceAxis::Y => 1,
+        SliceAxis::Z => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::FutureExt as _;
+
+    use crate::test_utils::step;
+
+    use super::*;
+
+    fn split_config() -> FluidSimulationConfig {
+        FluidSimulationConfig {
+            particle_cnt: 4096,
+            bbox_dimensions: Vector3::new(2.0, 2.0, 2.0),
+            // a slow speed of sound leaves room for several steps between splits
+            gas_const: 10.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn split_step_matches_single_device() {
+        let devices = [0, 1].map(|_| WgpuDevice::new_compute_device().block_on().unwrap());
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let config = split_config();
+        let dt = 0.001;
+
+        let mut split_sim = SplitSimulation::new(config.clone(), devices, 0.5).unwrap();
+        let [below, above] = split_sim.owned_particle_cnts();
+        assert_eq!(below + above, config.particle_cnt);
+        assert!(below.abs_diff(above) <= 1);
+
+        // the jitter of the start positions differs between simulations
+        let fluid_sim = FluidSimulation::new(config, &wgpu_device);
+        let ghost_particle_cnt = fluid_sim.ghost_particle_cnt();
+        let mut snapshot = fluid_sim.read_snapshot(&wgpu_device).unwrap();
+        let start = split_sim.read_fluid_particles().unwrap();
+        snapshot.positions[ghost_particle_cnt..].copy_from_slice(&start.positions);
+        snapshot.velocities[ghost_particle_cnt..].copy_from_slice(&start.velocities);
+        snapshot.densities[ghost_particle_cnt..].copy_from_slice(&start.densities);
+        fluid_sim
+            .restore_snapshot(&wgpu_device.queue, &snapshot)
+            .unwrap();
+
+        // fewer steps than fit into the skin, so the particles keep their order
+        for _ in 0..10 {
+            split_sim.step(dt).unwrap();
+            step(&wgpu_device, &fluid_sim, dt, 1);
+        }
+
+        let split = split_sim.read_fluid_particles().unwrap();
+        let single = fluid_sim.read_snapshot(&wgpu_device).unwrap();
+        for (split, single) in split
+            .positions
+            .iter()
+            .zip(&single.positions[ghost_particle_cnt..])
+        {
+            assert!(
+                (split - single).norm() < 1e-4,
+                "{split:?} split between devices, {single:?} on one"
+            );
+        }
+    }
+
+    #[test]
+    fn particles_survive_splitting_again() {
+        let devices = [0, 1].map(|_| WgpuDevice::new_compute_device().block_on().unwrap());
+        let config = split_config();
+
+        let mut split_sim = SplitSimulation::new(config.clone(), devices, 0.1).unwrap();
+        // every step moves the particles further than the skin
+        for _ in 0..10 {
+            split_sim.step(0.005).unwrap();
+        }
+
+        let particles = split_sim.read_fluid_particles().unwrap();
+        assert_eq!(particles.positions.len(), config.particle_cnt);
+        assert_eq!(
+            split_sim.owned_particle_cnts().iter().sum::<usize>(),
+            config.particle_cnt
+        );
+        assert!(particles
+            .positions
+            .iter()
+            .all(|position| position.coords.iter().all(|x| x.is_finite())));
+    }
+
+    #[test]
+    fn balanced_split_halves_the_particles() {
+        let positions: Vec<Point4<f32>> = (0..101)
+            .map(|i| Point4::new(i as f32 * 0.1, 1.0, 0.5, 1.0))
+            .collect();
+        let split = DomainSplit::balanced(&positions, Vector3::new(10.0, 2.0, 1.0), 0.25);
+        let partition = split.partition(&positions);
+
+        assert_eq!(split.axis, SliceAxis::X);
+        assert_eq!(partition.owned[0].len(), 50);
+        assert_eq!(partition.owned[1].len(), 51);
+        // the halo of each side holds the particles of the other side near the plane
+        assert_eq!(partition.halos[0], vec![50, 51, 52]);
+        assert_eq!(partition.halos[1], vec![48, 49]);
+    }
+}
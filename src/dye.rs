@@ -0,0 +1,365 @@
+use std::sync::Arc;
+
+use nalgebra::{Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graphics::render_engine::GenericRequest, wgpu_device::Uploader, ComputeTask, SpatialLookup,
+    WgpuDevice,
+};
+
+/// A passive scalar carried by the particles, used to visualize mixing.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DyeConfig {
+    /// Rate at which the dye of a particle approaches the average of its neighborhood, per
+    /// second
+    pub diffusion: f32,
+    /// Radius of the cylinder along the cursor ray that is painted with the brush
+    pub brush_radius: f32,
+    /// Keeps the particles in a sphere fully dyed
+    pub emitter: Option<DyeEmitter>,
+}
+
+impl Default for DyeConfig {
+    fn default() -> Self {
+        Self {
+            diffusion: 0.5,
+            brush_radius: 0.3,
+            emitter: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DyeEmitter {
+    /// In world space, the bounding box is centered at the origin
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl Default for DyeEmitter {
+    fn default() -> Self {
+        Self {
+            center: Point3::origin(),
+            radius: 0.5,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct RegionUniform {
+    start: [f32; 3],
+    radius: f32,
+    end: [f32; 3],
+    _padding: f32,
+}
+
+/// Dye concentration from 0 to 1 for every particle. The dye moves with the particles, so it
+/// only has to be injected and diffused.
+pub struct Dye {
+    bbox_dimensions: Vector3<f32>,
+    dye_buffer: Arc<wgpu::Buffer>,
+    next_buffer: Arc<wgpu::Buffer>,
+    diffuse_task: Arc<ComputeTask>,
+    emitter_buffer: Arc<wgpu::Buffer>,
+    emitter_task: Arc<ComputeTask>,
+    brush_buffer: Arc<wgpu::Buffer>,
+    brush_task: Arc<ComputeTask>,
+    uploader: Uploader,
+}
+
+impl Dye {
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        bbox_dimensions: Vector3<f32>,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+    ) -> Self {
+        let dye_buffer = wgpu_device.create_buffer_init(
+            &vec![0.0f32; particle_cnt],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let next_buffer = wgpu_device.create_buffer_init(
+            &vec![0.0f32; particle_cnt],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+
+        let fluid_particle_cnt = particle_cnt - ghost_particle_cnt;
+        let diffuse_task = Dye::create_diffuse_task(
+            wgpu_device,
+            fluid_particle_cnt,
+            ghost_particle_cnt,
+            spatial_lookup,
+            positions,
+            densities,
+            &dye_buffer,
+            &next_buffer,
+        );
+
+        // the emitter and the brush can both inject in the same submission, so they need their
+        // own uniforms
+        let region_buffer = || {
+            wgpu_device.create_buffer_init(
+                &[RegionUniform::default()],
+                wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            )
+        };
+        let emitter_buffer = region_buffer();
+        let brush_buffer = region_buffer();
+        let inject_task = |region: &wgpu::Buffer| {
+            Dye::create_inject_task(
+                wgpu_device,
+                fluid_particle_cnt,
+                ghost_particle_cnt,
+                positions,
+                &dye_buffer,
+                region,
+            )
+        };
+        let emitter_task = inject_task(&emitter_buffer);
+        let brush_task = inject_task(&brush_buffer);
+
+        Self {
+            uploader: wgpu_device.uploader.clone(),
+            bbox_dimensions,
+            dye_buffer,
+            next_buffer,
+            diffuse_task,
+            emitter_buffer,
+            emitter_task,
+            brush_buffer,
+            brush_task,
+        }
+    }
+
+    pub fn concentrations(&self) -> &Arc<wgpu::Buffer> {
+        &self.dye_buffer
+    }
+
+    /// Refills the emitter and diffuses the dye over `dt`, run after every simulation step.
+    pub fn step_fn(&self, config: DyeConfig, dt: f32) -> GenericRequest {
+        let emitter = config.emitter.map(|emitter| {
+            let center = self.simulation_position(emitter.center);
+            RegionUniform {
+                start: center.into(),
+                radius: emitter.radius,
+                end: center.into(),
+                _padding: 0.0,
+            }
+        });
+        let amount = (config.diffusion * dt).clamp(0.0, 1.0);
+
+        let uploader = self.uploader.clone();
+        let dye_buffer = self.dye_buffer.clone();
+        let next_buffer = self.next_buffer.clone();
+        let diffuse_task = self.diffuse_task.clone();
+        let emitter_buffer = self.emitter_buffer.clone();
+        let emitter_task = self.emitter_task.clone();
+
+        Box::new(move |encoder, _| {
+            if let Some(emitter) = &emitter {
+                uploader.write(encoder, &emitter_buffer, 0, bytemuck::bytes_of(emitter));
+                emitter_task.execute(encoder, &[]);
+            }
+            diffuse_task.execute(encoder, bytemuck::bytes_of(&amount));
+            encoder.copy_buffer_to_buffer(&next_buffer, 0, &dye_buffer, 0, dye_buffer.size());
+        })
+    }
+
+    /// Dyes the particles close to a ray through the bounding box, for example from the
+    /// cursor.
+    pub fn paint_fn(
+        &self,
+        config: DyeConfig,
+        origin: Point3<f32>,
+        direction: Vector3<f32>,
+    ) -> GenericRequest {
+        let start = self.simulation_position(origin);
+        // long enough to cross the whole box from wherever the ray starts
+        let length = origin.coords.norm() + self.bbox_dimensions.norm();
+        let end = start + direction.normalize() * length;
+        let region = RegionUniform {
+            start: start.into(),
+            radius: config.brush_radius,
+            end: end.into(),
+            _padding: 0.0,
+        };
+
+        let uploader = self.uploader.clone();
+        let brush_buffer = self.brush_buffer.clone();
+        let brush_task = self.brush_task.clone();
+
+        Box::new(move |encoder, _| {
+            uploader.write(encoder, &brush_buffer, 0, bytemuck::bytes_of(&region));
+            brush_task.execute(encoder, &[]);
+        })
+    }
+
+    pub fn clear_fn(&self) -> GenericRequest {
+        let dye_buffer = self.dye_buffer.clone();
+
+        Box::new(move |encoder, _| {
+            encoder.clear_buffer(&dye_buffer, 0, None);
+        })
+    }
+
+    /// The particle buffers have the corner of the bounding box at the origin.
+    fn simulation_position(&self, world: Point3<f32>) -> Point3<f32> {
+        world + self.bbox_dimensions / 2.0
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_diffuse_task(
+        wgpu_device: &WgpuDevice,
+        fluid_particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+        dye_buffer: &wgpu::Buffer,
+        next_buffer: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = (fluid_particle_cnt as u32).div_ceil(256);
+
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             {}
+             {}",
+            spatial_lookup.shader_source(),
+            include_str!("shaders/dye_diffuse.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Dye diffusion",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, true),
+                storage_entry(5, true),
+                storage_entry(6, false),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: densities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: dye_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: next_buffer.as_entire_binding(),
+                },
+            ],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..4,
+            }],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+
+    fn create_inject_task(
+        wgpu_device: &WgpuDevice,
+        fluid_particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        positions: &wgpu::Buffer,
+        dye_buffer: &wgpu::Buffer,
+        region_buffer: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = (fluid_particle_cnt as u32).div_ceil(256);
+
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             {}",
+            include_str!("shaders/dye_inject.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Dye injection",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: dye_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: region_buffer.as_entire_binding(),
+                },
+            ],
+            &[],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+}
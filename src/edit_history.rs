@@ -0,0 +1,120 @@
+/// Edits kept for undo, the oldest ones are dropped first.
+pub const MAX_EDITS: usize = 100;
+
+/// Undo and redo stacks of a value edited in the gui, e.g. the simulation config. The history
+/// is shown the value before and after every gui pass. An edit starts with the first change
+/// and is only recorded once the user stops interacting, so dragging a slider becomes a single
+/// step.
+#[derive(Clone, Debug)]
+pub struct EditHistory<T> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+    /// Value before the edit in progress
+    pending: Option<T>,
+}
+
+impl<T> Default for EditHistory<T> {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            pending: None,
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> EditHistory<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `editing` holds the edit open, e.g. while a mouse button is down or a text field has
+    /// focus.
+    pub fn observe(&mut self, before: &T, after: &T, editing: bool) {
+        if before != after && self.pending.is_none() {
+            self.pending = Some(before.clone());
+        }
+        if !editing {
+            self.commit(after);
+        }
+    }
+
+    /// The value to go back to, `current` becomes available to `redo`.
+    pub fn undo(&mut self, current: &T) -> Option<T> {
+        self.commit(current);
+        let previous = self.undo.pop()?;
+        self.redo.push(current.clone());
+        Some(previous)
+    }
+
+    pub fn redo(&mut self, current: &T) -> Option<T> {
+        self.commit(current);
+        let next = self.redo.pop()?;
+        self.undo.push(current.clone());
+        Some(next)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.pending.is_some()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    fn commit(&mut self, current: &T) {
+        // edits that end where they started, like a slider dragged back, aren't kept
+        let Some(previous) = self.pending.take().filter(|previous| previous != current) else {
+            return;
+        };
+
+        if self.undo.len() >= MAX_EDITS {
+            self.undo.remove(0);
+        }
+        self.undo.push(previous);
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_drag_is_a_single_edit() {
+        let mut history = EditHistory::new();
+        history.observe(&1, &2, true);
+        history.observe(&2, &3, true);
+        history.observe(&3, &3, false);
+
+        assert_eq!(history.undo(&3), Some(1));
+        assert_eq!(history.undo(&1), None);
+        assert_eq!(history.redo(&1), Some(3));
+        assert_eq!(history.redo(&3), None);
+    }
+
+    #[test]
+    fn a_new_edit_drops_the_redo_steps() {
+        let mut history = EditHistory::new();
+        history.observe(&1, &2, false);
+        history.observe(&2, &3, false);
+        assert_eq!(history.undo(&3), Some(2));
+
+        history.observe(&2, &5, false);
+        assert!(!history.can_redo());
+        assert_eq!(history.undo(&5), Some(2));
+        assert_eq!(history.undo(&2), Some(1));
+    }
+
+    #[test]
+    fn unfinished_edits_can_be_undone() {
+        let mut history = EditHistory::new();
+        history.observe(&1, &2, true);
+        assert_eq!(history.undo(&2), Some(1));
+
+        let mut history = EditHistory::new();
+        history.observe(&1, &2, true);
+        history.observe(&2, &1, false);
+        assert!(!history.can_undo());
+    }
+}
@@ -0,0 +1,167 @@
+use std::{fmt, sync::mpsc::RecvError};
+
+/// Everything that can go wrong in sploosh, from device creation to writing exported frames.
+#[derive(Debug)]
+pub enum SplooshError {
+    /// No adapter matches the configuration
+    Adapter(String),
+    CreateSurface(wgpu::CreateSurfaceError),
+    RequestDevice(wgpu::RequestDeviceError),
+    /// The device was lost or destroyed and has to be recreated
+    DeviceLost,
+    /// The surface texture could not be acquired, even after reconfiguring the surface
+    Surface(wgpu::SurfaceError),
+    BufferMap(wgpu::BufferAsyncError),
+    /// The device was dropped before a buffer mapping finished
+    MapCancelled,
+    /// A frame could not be captured or read back
+    Capture(String),
+    /// A config or settings file could not be parsed
+    Config(String),
+    /// A particle snapshot doesn't fit the simulation it is restored into
+    Snapshot(String),
+    /// A soak run found a broken particle state
+    Diverged(String),
+    /// A validation case missed the tolerance of its reference
+    Validation(String),
+    /// A scenario script failed to compile
+    Script(String),
+    /// The frame graph passes can't be ordered or use a resource that doesn't exist
+    FrameGraph(String),
+    EventLoop(winit::error::EventLoopError),
+    Image(image::ImageError),
+    Io(std::io::Error),
+}
+
+impl SplooshError {
+    /// Errors after which the next frame can be attempted as usual.
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self,
+            SplooshError::Surface(wgpu::SurfaceError::Timeout)
+                | SplooshError::Capture(_)
+                | SplooshError::Image(_)
+                | SplooshError::Io(_)
+        )
+    }
+}
+
+impl fmt::Display for SplooshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplooshError::Adapter(message) => write!(f, "{message}"),
+            SplooshError::CreateSurface(err) => write!(f, "Failed to create the surface: {err}"),
+            SplooshError::RequestDevice(err) => write!(f, "Failed to request a device: {err}"),
+            SplooshError::DeviceLost => write!(f, "The device was lost"),
+            SplooshError::Surface(err) => write!(f, "Failed to acquire the surface texture: {err}"),
+            SplooshError::BufferMap(err) => write!(f, "Failed to map a buffer: {err}"),
+            SplooshError::MapCancelled => write!(f, "The device was lost while mapping a buffer"),
+            SplooshError::Capture(message) => write!(f, "{message}"),
+            SplooshError::Config(message) => write!(f, "{message}"),
+            SplooshError::Snapshot(message) => write!(f, "{message}"),
+            SplooshError::Diverged(message) => write!(f, "{message}"),
+            SplooshError::Validation(message) => write!(f, "{message}"),
+            SplooshError::Script(message) => write!(f, "Script error: {message}"),
+            SplooshError::FrameGraph(message) => write!(f, "{message}"),
+            SplooshError::EventLoop(err) => write!(f, "Event loop error: {err}"),
+            SplooshError::Image(err) => write!(f, "Image error: {err}"),
+            SplooshError::Io(err) => write!(f, "IO error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SplooshError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SplooshError::CreateSurface(err) => Some(err),
+            SplooshError::RequestDevice(err) => Some(err),
+            SplooshError::Surface(err) => Some(err),
+            SplooshError::BufferMap(err) => Some(err),
+            SplooshError::EventLoop(err) => Some(err),
+            SplooshError::Image(err) => Some(err),
+            SplooshError::Io(err) => Some(err),
+            SplooshError::Adapter(_)
+            | SplooshError::DeviceLost
+            | SplooshError::MapCancelled
+            | SplooshError::Capture(_)
+            | SplooshError::Config(_)
+            | SplooshError::Snapshot(_)
+            | SplooshError::Diverged(_)
+            | SplooshError::Validation(_)
+            | SplooshError::Script(_)
+            | SplooshError::FrameGraph(_) => None,
+        }
+    }
+}
+
+impl From<wgpu::CreateSurfaceError> for SplooshError {
+    fn from(err: wgpu::CreateSurfaceError) -> Self {
+        SplooshError::CreateSurface(err)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for SplooshError {
+    fn from(err: wgpu::RequestDeviceError) -> Self {
+        SplooshError::RequestDevice(err)
+    }
+}
+
+impl From<wgpu::SurfaceError> for SplooshError {
+    fn from(err: wgpu::SurfaceError) -> Self {
+        SplooshError::Surface(err)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for SplooshError {
+    fn from(err: wgpu::BufferAsyncError) -> Self {
+        SplooshError::BufferMap(err)
+    }
+}
+
+impl From<RecvError> for SplooshError {
+    fn from(_: RecvError) -> Self {
+        SplooshError::MapCancelled
+    }
+}
+
+impl From<winit::error::EventLoopError> for SplooshError {
+    fn from(err: winit::error::EventLoopError) -> Self {
+        SplooshError::EventLoop(err)
+    }
+}
+
+impl From<image::ImageError> for SplooshError {
+    fn from(err: image::ImageError) -> Self {
+        SplooshError::Image(err)
+    }
+}
+
+impl From<std::io::Error> for SplooshError {
+    fn from(err: std::io::Error) -> Self {
+        SplooshError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for SplooshError {
+    fn from(err: toml::de::Error) -> Self {
+        SplooshError::Config(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for SplooshError {
+    fn from(err: serde_json::Error) -> Self {
+        SplooshError::Config(err.to_string())
+    }
+}
+
+impl From<ron::error::SpannedError> for SplooshError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        SplooshError::Config(err.to_string())
+    }
+}
+
+impl From<ron::Error> for SplooshError {
+    fn from(err: ron::Error) -> Self {
+        SplooshError::Config(err.to_string())
+    }
+}
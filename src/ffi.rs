@@ -0,0 +1,259 @@
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+};
+
+use pollster::FutureExt;
+
+use crate::{
+    fluid_simulation::{FluidSimulationConfig, SimDim},
+    FluidSimulation, SplooshError, WgpuDevice,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// A simulation with its own device, handed to C as an opaque pointer.
+pub struct SplooshSimulation {
+    wgpu_device: WgpuDevice,
+    fluid_sim: FluidSimulation,
+}
+
+impl SplooshSimulation {
+    fn new(config: FluidSimulationConfig) -> Result<Self, SplooshError> {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on()?;
+        config.max_particle_cnt(&wgpu_device.device.limits())?;
+        let fluid_sim = FluidSimulation::new(config, &wgpu_device);
+
+        Ok(Self {
+            wgpu_device,
+            fluid_sim,
+        })
+    }
+}
+
+fn set_last_error(message: String) {
+    // messages with a nul byte are cut off there
+    let message = CString::new(message).unwrap_or_else(|err| {
+        let nul = err.nul_position();
+        CString::new(&err.into_vec()[..nul]).unwrap_or_default()
+    });
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, storing its error or panic for `sploosh_last_error` and returning `fallback`
+/// instead. Panics must not unwind into the C caller.
+fn guarded<T>(fallback: T, f: impl FnOnce() -> Result<T, SplooshError>) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => {
+            set_last_error(err.to_string());
+            fallback
+        }
+        Err(_) => {
+            set_last_error("sploosh panicked".to_string());
+            fallback
+        }
+    }
+}
+
+fn null_error() -> SplooshError {
+    SplooshError::Config("The simulation pointer is null".to_string())
+}
+
+/// Creates a simulation with the default parameters and `particle_cnt` fluid particles, the
+/// boundary particles are added on top. `two_d` runs it in the xy plane. Returns null on
+/// failure.
+#[no_mangle]
+pub extern "C" fn sploosh_create(particle_cnt: u32, two_d: bool) -> *mut SplooshSimulation {
+    let config = FluidSimulationConfig {
+        particle_cnt: particle_cnt as usize,
+        dimensions: if two_d { SimDim::Two } else { SimDim::Three },
+        ..Default::default()
+    };
+
+    guarded(ptr::null_mut(), || {
+        Ok(Box::into_raw(Box::new(SplooshSimulation::new(config)?)))
+    })
+}
+
+/// Creates a simulation from a TOML or RON file with the fields of the `[simulation]` section.
+/// Returns null on failure.
+///
+/// # Safety
+///
+/// `path` has to be a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sploosh_create_from_file(path: *const c_char) -> *mut SplooshSimulation {
+    guarded(ptr::null_mut(), || {
+        if path.is_null() {
+            return Err(SplooshError::Config("The path is null".to_string()));
+        }
+        let path = CStr::from_ptr(path)
+            .to_str()
+            .map_err(|err| SplooshError::Config(err.to_string()))?;
+        let config = FluidSimulationConfig::from_file(path)?;
+
+        Ok(Box::into_raw(Box::new(SplooshSimulation::new(config)?)))
+    })
+}
+
+/// # Safety
+///
+/// `sim` has to come from `sploosh_create` and must not be used afterwards. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn sploosh_destroy(sim: *mut SplooshSimulation) {
+    if !sim.is_null() {
+        drop(Box::from_raw(sim));
+    }
+}
+
+/// Advances the simulation by `steps` steps of `dt` seconds and waits for the GPU. Returns 0,
+/// or -1 on failure.
+///
+/// # Safety
+///
+/// `sim` has to be a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn sploosh_step(sim: *mut SplooshSimulation, dt: f32, steps: u32) -> i32 {
+    guarded(-1, || {
+        let sim = sim.as_ref().ok_or_else(null_error)?;
+        if sim.wgpu_device.is_lost() {
+            return Err(SplooshError::DeviceLost);
+        }
+
+        let mut encoder =
+            sim.wgpu_device
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("FFI step encoder"),
+                });
+        for _ in 0..steps {
+            sim.fluid_sim.step_fn(dt)(&mut encoder, &sim.wgpu_device.queue);
+        }
+        sim.wgpu_device.submit(encoder);
+        sim.wgpu_device.device.poll(wgpu::Maintain::Wait);
+
+        Ok(0)
+    })
+}
+
+/// Number of particles including the static boundary particles, which come first. The
+/// fluid particles given to `sploosh_create` come after them.
+///
+/// # Safety
+///
+/// `sim` has to be a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn sploosh_particle_count(sim: *const SplooshSimulation) -> u32 {
+    sim.as_ref()
+        .map_or(0, |sim| sim.fluid_sim.particle_cnt() as u32)
+}
+
+/// # Safety
+///
+/// `sim` has to be a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn sploosh_ghost_particle_count(sim: *const SplooshSimulation) -> u32 {
+    sim.as_ref()
+        .map_or(0, |sim| sim.fluid_sim.ghost_particle_cnt() as u32)
+}
+
+/// Simulated time in seconds.
+///
+/// # Safety
+///
+/// `sim` has to be a live simulation.
+#[no_mangle]
+pub unsafe extern "C" fn sploosh_time(sim: *const SplooshSimulation) -> f32 {
+    sim.as_ref().map_or(0.0, |sim| sim.fluid_sim.sim_time())
+}
+
+/// Copies the particle state into the given arrays, positions and velocities as 4 floats per
+/// particle and densities as one. Any of the arrays may be null. At most `capacity` particles
+/// are written, the number written is returned, or -1 on failure.
+///
+/// # Safety
+///
+/// `sim` has to be a live simulation and every array that isn't null has to hold `capacity`
+/// particles.
+#[no_mangle]
+pub unsafe extern "C" fn sploosh_read_particles(
+    sim: *const SplooshSimulation,
+    positions: *mut f32,
+    velocities: *mut f32,
+    densities: *mut f32,
+    capacity: u32,
+) -> i32 {
+    guarded(-1, || {
+        let sim = sim.as_ref().ok_or_else(null_error)?;
+        let snapshot = sim.fluid_sim.read_snapshot(&sim.wgpu_device)?;
+        let cnt = snapshot.positions.len().min(capacity as usize);
+
+        let copy = |source: &[f32], destination: *mut f32, components: usize| {
+            if !destination.is_null() {
+                ptr::copy_nonoverlapping(source.as_ptr(), destination, cnt * components);
+            }
+        };
+        copy(bytemuck::cast_slice(&snapshot.positions), positions, 4);
+        copy(bytemuck::cast_slice(&snapshot.velocities), velocities, 4);
+        copy(&snapshot.densities, densities, 1);
+
+        Ok(cnt as i32)
+    })
+}
+
+/// Message of the last failure on this thread, null if nothing failed yet. Valid until the
+/// next failing call.
+#[no_mangle]
+pub extern "C" fn sploosh_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_and_read_back() {
+        let sim = sploosh_create(2048, true);
+        assert!(!sim.is_null());
+
+        unsafe {
+            assert_eq!(sploosh_step(sim, 1.0 / 60.0, 10), 0);
+            assert!(sploosh_time(sim) > 0.1);
+
+            let particle_cnt = sploosh_particle_count(sim);
+            let mut positions = vec![0.0; 4 * particle_cnt as usize];
+            let mut densities = vec![0.0; particle_cnt as usize];
+            let read = sploosh_read_particles(
+                sim,
+                positions.as_mut_ptr(),
+                ptr::null_mut(),
+                densities.as_mut_ptr(),
+                particle_cnt,
+            );
+            assert_eq!(read, particle_cnt as i32);
+            assert!(positions.iter().all(|x| x.is_finite()));
+
+            sploosh_destroy(sim);
+        }
+    }
+
+    #[test]
+    fn errors_are_kept_for_the_caller() {
+        unsafe {
+            assert_eq!(sploosh_step(ptr::null_mut(), 0.01, 1), -1);
+            let message = CStr::from_ptr(sploosh_last_error());
+            assert!(message.to_str().unwrap().contains("null"));
+        }
+    }
+}
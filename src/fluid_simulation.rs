@@ -1,180 +1,1769 @@
-use std::rc::Rc;
+use std::{
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+};
 
-use nalgebra::{Point4, Vector3};
+use nalgebra::{Point3, Point4, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    anisotropy::{Anisotropy, AnisotropyConfig, ParticleShape, PARTICLE_SHAPE_SHADER},
+    colormap::{ColorRange, Colormap, ColormapTexture, COLORMAP_SHADER},
+    compute_task::{tune_workgroup_size, WORKGROUP_SIZES},
+    config,
+    debris::{Debris, DebrisConfig},
+    density_filter::{DensityFilter, DensityRenormalization},
+    density_slice::{DensitySlice, DensitySliceConfig},
+    depth_sort::DepthSort,
+    diagnostics::{Diagnostics, ParticleDiagnostics},
+    dye::{Dye, DyeConfig},
     graphics::{
+        camera::Camera,
         geometry::Geometry,
         materials::{ColoredVertex, MaterialType},
-        render_engine::{RenderEngine, RenderRequest},
+        render_engine::{FrameStage, GenericRequest, RenderEngine, RenderRequest},
     },
-    ComputeTask, SpatialLookup, WgpuDevice,
+    minimap::{Minimap, MinimapConfig},
+    neighbor_count::{NeighborCount, HISTOGRAM_BINS},
+    particle_inspector::{ParticleInspector, ParticleSample},
+    particle_trails::{ParticleTrailConfig, ParticleTrails, TRAIL_BUFFER_SIZE},
+    pass_validation,
+    playback::{packed_frame_size, PlaybackConfig, PlaybackFrame, PlaybackRange, PlaybackRecorder},
+    probes::{ProbeConfig, ProbeSample, Probes},
+    project::{check_version, migrate_simulation, unversioned, WrittenSimulation},
+    readback::READBACK_SLOTS,
+    rest_state::{config_fingerprint, RestState, RestStateConfig},
+    screen_density::{ScreenDensity, ScreenDensityConfig},
+    soft_body::{FluidCoupling, SoftBody, SoftBodyConfig},
+    spatial_lookup::{NeighborCaching, SpatialGrid, SpatialLookupBackend},
+    velocity_lines::{VelocityLineConfig, VelocityLines, MAX_STREAMLINE_STEPS},
+    wave_gauges::{WaveGaugeConfig, WaveGaugeSample, WaveGauges},
+    wgpu_device::{read_staging, Uploader},
+    ComputeTask, SpatialLookup, SplooshError, WgpuDevice,
 };
 
+/// Spacing of the initial particle lattice relative to the smoothing radius.
+pub const PARTICLE_SPACING: f32 = 0.55;
+
+/// Rest density of water in kg/m³.
+pub const WATER_DENSITY: f32 = 1000.0;
+
+/// Dynamic viscosity of water at 20 °C in Pa s.
+pub const WATER_VISCOSITY: f32 = 1.0e-3;
+
+/// Surface tension of water against air at 20 °C in N/m.
+pub const WATER_SURFACE_TENSION: f32 = 0.072;
+
+/// In m/s².
+pub const STANDARD_GRAVITY: f32 = 9.81;
+
+/// Steps submitted at once while settling the rest state.
+const SETTLE_BATCH_SIZE: usize = 64;
+
+/// Bytes per particle of the largest single buffer, the velocity lines with two vertices per
+/// particle.
+const LARGEST_BUFFER_BYTES_PER_PARTICLE: u64 = 2 * std::mem::size_of::<ColoredVertex>() as u64;
+
+/// Quantities are in meters, kilograms and seconds. The defaults are a scaled down scene
+/// that looks right at 60 steps per second, `FluidSimulationConfig::water` derives a
+/// physically consistent set from the domain size.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct FluidSimulationConfig {
+    /// Fluid particles, the boundary ghost particles of `ghost_particle_cnt` come on top
     pub particle_cnt: usize,
+    /// In m
     pub smoothing_radius: f32,
+    /// In kg
     pub mass: f32,
+    /// Factor applied to the velocity into a wall on collision
     pub damping: f32,
+    /// Stiffness of the equation of state `p = gas_const * (density - rest_density)`, in
+    /// m²/s², the square of the speed of sound
     pub gas_const: f32,
+    /// In kg/m³, per m² in 2D
     pub rest_density: f32,
+    /// Dynamic viscosity in Pa s. Newtonian viscosity, the consistency of the power law or the
+    /// zero shear viscosity of the Cross model
     pub viscosity: f32,
+    pub viscosity_model: ViscosityModel,
+    /// In N/m, pulls the fluid particles together at the surface, zero disables it
+    pub surface_tension: f32,
+    /// Replaces the mass, rest density, gas constant, viscosity and surface tension with
+    /// values derived from the preset when the simulation is created
+    pub preset: Option<FluidPreset>,
+    /// Springs between neighboring particles, for gel-like fluids
+    pub elastic_springs: Option<ElasticSprings>,
+    /// In m/s²
     pub gravity: Vector3<f32>,
+    /// In m
     pub bbox_dimensions: Vector3<f32>,
+    pub initial_layout: InitialLayout,
+    /// Settles the initial layout before the first step
+    pub relaxation: Option<Relaxation>,
+    /// Starts from settled particles saved in a file, settled and saved on the first start
+    pub rest_state: Option<RestStateConfig>,
+    pub dimensions: SimDim,
+    pub wave_paddle: Option<WavePaddle>,
+    /// Steady channel flow along the x axis
+    pub river: Option<RiverFlow>,
+    pub boundary: DomainBoundary,
+    pub material: Material,
+    /// Corrects the underestimated densities near the free surface every step, which keeps
+    /// the surface from clumping
+    pub free_surface_correction: bool,
+    /// Smooths the densities every few steps against noise and drift
+    pub density_renormalization: Option<DensityRenormalization>,
+    /// Without walls, particles further than this from the center of the bounding box are
+    /// respawned above it
+    pub kill_radius: f32,
+    pub spatial_lookup: SpatialLookupBackend,
+    /// Number of hash table slots, defaults to twice the particle count rounded up to a power
+    /// of two
+    pub hash_table_size: Option<u32>,
+    /// Reuses the dense grid for several steps, each rebuild sorts all particles
+    pub neighbor_caching: Option<NeighborCaching>,
+    /// Caps `particle_cnt` so that the buffers listed by `buffer_allocations` fit, in MiB. The
+    /// device limits on buffer sizes cap it either way.
+    pub memory_budget_mib: Option<u64>,
+    /// Threads per workgroup of the density and force kernels, limited by the device. Unset, the
+    /// fastest of `compute_task::WORKGROUP_SIZES` is measured on the first simulation of a device
+    pub workgroup_size: Option<u32>,
+    /// Shares the neighbor loads of the density and force kernels across subgroups where the
+    /// adapter supports subgroup operations, off runs the per-particle kernels everywhere
+    pub subgroup_kernels: bool,
+    /// Precomputes p / ρ² of every particle in a pass before the forces, off recomputes it in
+    /// the force kernel for every pair like older builds, to compare the two
+    pub pressure_pass: bool,
+    pub integrator: Integrator,
+    pub culling: ParticleCulling,
+    /// Alpha blends the particles, which sorts them by depth every frame
+    pub translucent_particles: bool,
+    /// Draws the particles as ellipsoids shaped by their neighbors, always opaque
+    pub anisotropy: Option<AnisotropyConfig>,
+    /// Draws the boundary ghost particles along with the fluid, for debugging the boundary
+    pub show_ghost_particles: bool,
+    /// Draws debug lines along the particle velocities
+    pub velocity_lines: Option<VelocityLineConfig>,
+    /// Draws the density on a plane through the bounding box
+    pub density_slice: Option<DensitySliceConfig>,
+    /// Draws the recent paths of a subset of the particles
+    pub particle_trails: Option<ParticleTrailConfig>,
+    /// Shows a top-down map of the particle distribution in a corner of the window
+    pub minimap: Option<MinimapConfig>,
+    /// Draws a heatmap of the particles projected onto the screen over the main viewport
+    pub screen_density: Option<ScreenDensityConfig>,
+    /// Carries a dye with the particles that can be painted and diffuses between neighbors
+    pub dye: Option<DyeConfig>,
+    /// Passive debris carried by the fluid, drawn as small sprites
+    pub debris: Option<DebrisConfig>,
+    /// Computes the velocity divergence and pressure of every particle each frame
+    pub diagnostics: bool,
+    /// Virtual sensors sampling the fluid every frame, up to `MAX_PROBES`
+    pub probes: Vec<ProbeConfig>,
+    /// Columns measuring the height of the fluid every frame, up to `MAX_WAVE_GAUGES`
+    pub wave_gauges: Vec<WaveGaugeConfig>,
+    /// Records the particles every few steps for replaying the recent history
+    pub playback: Option<PlaybackConfig>,
+    /// A deformable body pushed around by the fluid, drawn as its lattice
+    pub soft_body: Option<SoftBodyConfig>,
+    pub color_mode: ParticleColorMode,
+    /// Colors of the density and neighbor count modes
+    pub colormap: Colormap,
+    /// Values mapped to the ends of the colormap, or from water to dye, `None` uses the
+    /// default range of the color mode
+    pub color_range: Option<ColorRange>,
+    /// Steps per second of a simulation thread running independently of the frame rate,
+    /// `None` steps once per frame
+    pub background_step_rate: Option<f32>,
+}
+
+/// Decides which particles are written to the display buffer each frame.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParticleCulling {
+    /// Skips particles outside of the view frustum
+    pub frustum: bool,
+    /// Particles further away from the camera are not drawn
+    pub max_distance: Option<f32>,
+    /// Beyond this distance only a random subset of the particles is drawn, with sprites
+    /// enlarged to cover the same screen area
+    pub lod_distance: Option<f32>,
+    /// Beyond this distance the particles of a spatial lookup cell are drawn as one sprite at
+    /// their center, which bounds the drawn sprites by the cells in view. Takes over from
+    /// `lod_distance` where both apply
+    pub merge_distance: Option<f32>,
+}
+
+impl ParticleCulling {
+    pub const DISABLED: ParticleCulling = ParticleCulling {
+        frustum: false,
+        max_distance: None,
+        lod_distance: None,
+        merge_distance: None,
+    };
+}
+
+impl Default for ParticleCulling {
+    fn default() -> Self {
+        Self {
+            frustum: true,
+            max_distance: None,
+            lod_distance: None,
+            merge_distance: None,
+        }
+    }
+}
+
+/// Walls the particles collide with. Without the full box the bounding box only sets the
+/// initial layout and the respawn point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainBoundary {
+    #[default]
+    Box,
+    Floor,
+    Open,
+}
+
+/// How the particles respond to compression and shear. It is built into the force pass, so
+/// changing it needs a new simulation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Material {
+    /// Pressure from the equation of state and viscosity
+    #[default]
+    Fluid,
+    /// Sand, the pressure only pushes the particles apart and the shear between neighbors is
+    /// capped by a Drucker-Prager yield criterion
+    Granular {
+        /// In degrees, the slope of the cone of internal friction
+        friction_angle: f32,
+        /// Shear stress the grains resist without any pressure, zero for dry sand
+        cohesion: f32,
+        /// Replaces the viscosity, shear below the yield limit is damped this strongly
+        shear_viscosity: f32,
+    },
+}
+
+impl Material {
+    pub const ALL: [Material; 2] = [Material::Fluid, Material::GRANULAR];
+
+    /// Dry sand
+    pub const GRANULAR: Material = Material::Granular {
+        friction_angle: 35.0,
+        cohesion: 0.0,
+        shear_viscosity: 20.0,
+    };
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Material::Fluid => "Fluid",
+            Material::Granular { .. } => "Granular",
+        }
+    }
+}
+
+/// Dependence of the viscosity on the local shear rate, which is estimated from the velocity
+/// gradient over the neighbors. Not used by granular materials.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ViscosityModel {
+    #[default]
+    Newtonian,
+    /// `viscosity * shear_rate^(flow_index - 1)`, shear thinning like blood below a flow index
+    /// of one and thickening like cornstarch above
+    PowerLaw {
+        flow_index: f32,
+        /// Bounds the thinning fluids at rest, where the power law diverges
+        max_viscosity: f32,
+    },
+    /// Falls from `viscosity` towards `infinite_shear_viscosity` once the shear rate passes
+    /// `1 / time_constant`, for example honey or paint
+    Cross {
+        infinite_shear_viscosity: f32,
+        time_constant: f32,
+        rate_index: f32,
+    },
+}
+
+impl ViscosityModel {
+    fn shader_constants(&self) -> String {
+        let (id, flow_index, max_viscosity, infinite_shear_viscosity, time_constant) = match *self {
+            ViscosityModel::Newtonian => (0, 1.0, 0.0, 0.0, 0.0),
+            ViscosityModel::PowerLaw {
+                flow_index,
+                max_viscosity,
+            } => (1, flow_index, max_viscosity, 0.0, 0.0),
+            ViscosityModel::Cross {
+                infinite_shear_viscosity,
+                time_constant,
+                rate_index,
+            } => (2, rate_index, 0.0, infinite_shear_viscosity, time_constant),
+        };
+
+        format!(
+            "const VISCOSITY_MODEL: u32 = {id}u;
+             const FLOW_INDEX: f32 = {flow_index};
+             const MAX_VISCOSITY: f32 = {max_viscosity};
+             const INFINITE_SHEAR_VISCOSITY: f32 = {infinite_shear_viscosity};
+             const TIME_CONSTANT: f32 = {time_constant};"
+        )
+    }
+}
+
+/// Springs towards a rest length between every pair of neighboring fluid particles. They are
+/// formed from the current neighbors each step, so the fluid resists fast deformation
+/// elastically but still flows.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ElasticSprings {
+    pub stiffness: f32,
+    /// Fraction of the smoothing radius
+    pub rest_length: f32,
+}
+
+impl Default for ElasticSprings {
+    fn default() -> Self {
+        Self {
+            stiffness: 50.0,
+            rest_length: 0.5,
+        }
+    }
+}
+
+/// Physical constants of a liquid.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FluidProperties {
+    /// In kg/m³
+    pub rest_density: f32,
+    /// Dynamic viscosity in Pa s
+    pub viscosity: f32,
+    /// In N/m
+    pub surface_tension: f32,
+}
+
+/// Common liquids at room temperature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FluidPreset {
+    Water,
+    /// Olive oil
+    Oil,
+    Honey,
+    /// Mercury
+    LiquidMetal,
+}
+
+impl FluidPreset {
+    pub const ALL: [FluidPreset; 4] = [
+        FluidPreset::Water,
+        FluidPreset::Oil,
+        FluidPreset::Honey,
+        FluidPreset::LiquidMetal,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FluidPreset::Water => "Water",
+            FluidPreset::Oil => "Oil",
+            FluidPreset::Honey => "Honey",
+            FluidPreset::LiquidMetal => "Liquid metal",
+        }
+    }
+
+    pub fn properties(&self) -> FluidProperties {
+        let (rest_density, viscosity, surface_tension) = match self {
+            FluidPreset::Water => (WATER_DENSITY, WATER_VISCOSITY, WATER_SURFACE_TENSION),
+            FluidPreset::Oil => (910.0, 0.08, 0.032),
+            FluidPreset::Honey => (1420.0, 10.0, 0.05),
+            FluidPreset::LiquidMetal => (13_534.0, 1.5e-3, 0.485),
+        };
+
+        FluidProperties {
+            rest_density,
+            viscosity,
+            surface_tension,
+        }
+    }
+}
+
+/// Moves the -x wall back and forth to generate surface waves. The wall oscillates between
+/// its rest position and `amplitude` units into the box.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WavePaddle {
+    pub amplitude: f32,
+    pub frequency: f32,
+}
+
+impl WavePaddle {
+    fn position(&self, time: f32) -> f32 {
+        let omega = 2.0 * std::f32::consts::PI * self.frequency;
+        self.amplitude * 0.5 * (1.0 - (omega * time).cos())
+    }
+
+    fn velocity(&self, time: f32) -> f32 {
+        let omega = 2.0 * std::f32::consts::PI * self.frequency;
+        self.amplitude * 0.5 * omega * (omega * time).sin()
+    }
+}
+
+/// Inflow and outflow planes for a steady channel flow along +x. Particles that pass the
+/// outflow plane are recycled into the inflow zone at the -x end, where their velocity is held
+/// at `velocity`, so the particle count never changes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiverFlow {
+    /// Width of the inflow zone, the inflow plane is this far from the -x end of the box
+    pub inflow_width: f32,
+    /// Height of the inflow zone above the floor
+    pub inflow_depth: f32,
+    pub velocity: Vector3<f32>,
+    /// Distance of the outflow plane from the +x end of the box
+    pub outflow_distance: f32,
+}
+
+impl Default for RiverFlow {
+    fn default() -> Self {
+        Self {
+            inflow_width: 1.0,
+            inflow_depth: 2.0,
+            velocity: Vector3::new(2.0, 0.0, 0.0),
+            outflow_distance: 1.0,
+        }
+    }
+}
+
+/// Time integration scheme used to advance the particles, all of them evaluate the forces once
+/// per step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    SymplecticEuler,
+    /// Kick-drift-kick with both half kicks using the forces from the start of the step
+    #[default]
+    Leapfrog,
+    /// Stormer-Verlet, advances the positions from the previous two steps
+    Verlet,
+}
+
+impl Integrator {
+    pub const ALL: [Integrator; 3] = [
+        Integrator::SymplecticEuler,
+        Integrator::Leapfrog,
+        Integrator::Verlet,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Integrator::SymplecticEuler => "Symplectic Euler",
+            Integrator::Leapfrog => "Leapfrog",
+            Integrator::Verlet => "Verlet",
+        }
+    }
+
+    fn shader_id(&self) -> u32 {
+        match self {
+            Integrator::SymplecticEuler => 0,
+            Integrator::Leapfrog => 1,
+            Integrator::Verlet => 2,
+        }
+    }
+}
+
+/// What the particle colors show.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticleColorMode {
+    #[default]
+    Density,
+    /// Heatmap of the particles within the smoothing radius, counted every frame
+    NeighborCount,
+    /// Dye concentration, blends from the water color to the dye color
+    Dye,
+}
+
+impl ParticleColorMode {
+    pub const ALL: [ParticleColorMode; 3] = [
+        ParticleColorMode::Density,
+        ParticleColorMode::NeighborCount,
+        ParticleColorMode::Dye,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParticleColorMode::Density => "Density",
+            ParticleColorMode::NeighborCount => "Neighbor count",
+            ParticleColorMode::Dye => "Dye",
+        }
+    }
+
+    /// Range the colors are spread over unless the config overrides it.
+    pub fn default_range(&self) -> ColorRange {
+        match self {
+            ParticleColorMode::Density => ColorRange {
+                min: 150.0,
+                max: 250.0,
+            },
+            ParticleColorMode::NeighborCount => ColorRange {
+                min: 0.0,
+                max: (HISTOGRAM_BINS - 1) as f32,
+            },
+            ParticleColorMode::Dye => ColorRange { min: 0.0, max: 1.0 },
+        }
+    }
+
+    fn shader_id(&self) -> u32 {
+        match self {
+            ParticleColorMode::Density => 0,
+            ParticleColorMode::NeighborCount => 1,
+            ParticleColorMode::Dye => 2,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct StepUniform {
+    paddle_position: f32,
+    paddle_velocity: f32,
+    integrator: u32,
+    first_step: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimDim {
+    Two,
+    #[default]
+    Three,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct DisplayConstants {
+    color_mode: u32,
+    /// `u32::MAX` without a selection
+    selected_particle: u32,
+    interpolation: f32,
+    /// Ghost particle count to hide the boundary, zero to draw it
+    first_particle: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullUniform {
+    frustum_planes: [Vector4<f32>; 6],
+    camera_position: Point3<f32>,
+    lod_distance: f32,
+    camera_forward: Vector3<f32>,
+    max_distance: f32,
+    frustum: u32,
+    /// Longest axis of the particle shapes relative to the spheres
+    max_stretch: f32,
+    /// Copies the particle shapes along with the kept particles
+    anisotropic: u32,
+    merge_distance: f32,
+}
+
+impl CullUniform {
+    fn new(
+        culling: ParticleCulling,
+        anisotropy: Option<AnisotropyConfig>,
+        camera: &Camera,
+        aspect: f32,
+    ) -> Self {
+        let view_projection = camera.get_projection_matrix(aspect) * camera.get_view_matrix();
+        let row = |i| view_projection.row(i).transpose();
+
+        // planes of the clip volume in world space, wgpu clips depth to [0, w]
+        let frustum_planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ]
+        .map(|plane| plane / plane.xyz().norm());
+
+        Self {
+            frustum_planes,
+            camera_position: camera.position,
+            lod_distance: culling.lod_distance.unwrap_or(0.0),
+            camera_forward: (camera.target - camera.position).normalize(),
+            max_distance: culling.max_distance.unwrap_or(0.0),
+            frustum: culling.frustum as u32,
+            max_stretch: anisotropy.map_or(1.0, |anisotropy| anisotropy.max_stretch()),
+            anisotropic: anisotropy.is_some() as u32,
+            merge_distance: culling.merge_distance.unwrap_or(0.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InitialLayout {
+    #[default]
+    Cube,
+    DamBreak,
+}
+
+/// Damped SPH steps run without gravity when the simulation is created, so the particles
+/// spread out of the lattice they are placed on into an even packing. The velocities are
+/// reset after every iteration, which moves the particles down the pressure gradient.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Relaxation {
+    pub iterations: u32,
+    /// Pseudo time step of an iteration
+    pub dt: f32,
+}
+
+impl Default for Relaxation {
+    fn default() -> Self {
+        Self {
+            iterations: 200,
+            dt: 0.01,
+        }
+    }
+}
+
+impl Default for FluidSimulationConfig {
+    fn default() -> Self {
+        Self {
+            particle_cnt: 100_000,
+            smoothing_radius: 0.15,
+            mass: 0.12,
+            damping: -0.7,
+            gas_const: 350.0,
+            rest_density: 200.0,
+            viscosity: 1.15,
+            gravity: Vector3::new(0.0, -1.0, 0.0),
+            bbox_dimensions: Vector3::new(14.0, 6.0, 4.0),
+            initial_layout: InitialLayout::Cube,
+            relaxation: None,
+            rest_state: None,
+            dimensions: SimDim::Three,
+            wave_paddle: None,
+            river: None,
+            boundary: DomainBoundary::Box,
+            material: Material::Fluid,
+            free_surface_correction: false,
+            density_renormalization: None,
+            viscosity_model: ViscosityModel::Newtonian,
+            surface_tension: 0.0,
+            preset: None,
+            elastic_springs: None,
+            kill_radius: 50.0,
+            spatial_lookup: SpatialLookupBackend::Auto,
+            neighbor_caching: None,
+            memory_budget_mib: None,
+            hash_table_size: None,
+            workgroup_size: None,
+            subgroup_kernels: true,
+            pressure_pass: true,
+            integrator: Integrator::Leapfrog,
+            culling: ParticleCulling::default(),
+            translucent_particles: false,
+            anisotropy: None,
+            show_ghost_particles: false,
+            velocity_lines: None,
+            density_slice: None,
+            particle_trails: None,
+            minimap: None,
+            screen_density: None,
+            dye: None,
+            debris: None,
+            diagnostics: false,
+            probes: Vec::new(),
+            wave_gauges: Vec::new(),
+            playback: None,
+            soft_body: None,
+            color_mode: ParticleColorMode::Density,
+            colormap: Colormap::Heatmap,
+            color_range: None,
+            background_step_rate: None,
+        }
+    }
+}
+
+impl FluidSimulationConfig {
+    /// Reads a simulation config file. Like the other config files it can have a top level
+    /// `version`, files without one are from version 1 and are migrated.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SplooshError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+        let mut simulation: Self = config::parse_contents(path, &contents)?;
+        let written: WrittenSimulation = config::parse_contents(path, &contents)?;
+
+        let version = written.version.unwrap_or_else(unversioned);
+        check_version(version, "simulation config")?;
+        migrate_simulation(&mut simulation, version, &written);
+
+        Ok(simulation)
+    }
+
+    /// This config with the fields that can change at runtime taken from `other`, see
+    /// `FluidSimulation::apply_runtime_config`.
+    pub fn with_runtime_fields_of(&self, other: &FluidSimulationConfig) -> Self {
+        FluidSimulationConfig {
+            free_surface_correction: other.free_surface_correction,
+            density_renormalization: other.density_renormalization,
+            integrator: other.integrator,
+            culling: other.culling,
+            translucent_particles: other.translucent_particles,
+            anisotropy: other.anisotropy,
+            show_ghost_particles: other.show_ghost_particles,
+            velocity_lines: other.velocity_lines,
+            density_slice: other.density_slice,
+            particle_trails: other.particle_trails,
+            minimap: other.minimap,
+            screen_density: other.screen_density,
+            dye: other.dye,
+            debris: other.debris,
+            diagnostics: other.diagnostics,
+            probes: other.probes.clone(),
+            wave_gauges: other.wave_gauges.clone(),
+            playback: other.playback,
+            color_mode: other.color_mode,
+            colormap: other.colormap,
+            color_range: other.color_range,
+            background_step_rate: other.background_step_rate,
+            ..self.clone()
+        }
+    }
+
+    /// Water in a `domain_size` box in meters, starting as a dam break column of half the box
+    /// height. `resolution` is the number of particles across the shortest side of the box.
+    pub fn water(domain_size: Vector3<f32>, resolution: u32) -> Self {
+        Self::liquid(domain_size, resolution, FluidPreset::Water.properties())
+    }
+
+    /// Like `water` for any liquid, with the physical constants applied by `apply_fluid` under
+    /// standard gravity. The background step rate is set to the time step limit, see
+    /// `max_time_step`.
+    pub fn liquid(domain_size: Vector3<f32>, resolution: u32, fluid: FluidProperties) -> Self {
+        let spacing = domain_size.min() / resolution.max(1) as f32;
+        let smoothing_radius = spacing / PARTICLE_SPACING;
+        let column_height = domain_size.y / 2.0;
+
+        // the same lattice `particle_start_positions` lays out for a dam break
+        let lattice_cnt = |length: f32| usize::max((length / spacing) as usize, 1);
+        let fluid_particle_cnt = lattice_cnt(domain_size.x * 0.4 - 2.0 * smoothing_radius)
+            * lattice_cnt(domain_size.z - 2.0 * smoothing_radius)
+            * lattice_cnt(column_height);
+
+        let mut config = Self {
+            particle_cnt: fluid_particle_cnt,
+            smoothing_radius,
+            gravity: Vector3::new(0.0, -STANDARD_GRAVITY, 0.0),
+            bbox_dimensions: domain_size,
+            initial_layout: InitialLayout::DamBreak,
+            kill_radius: 10.0 * domain_size.norm(),
+            ..Default::default()
+        };
+        config.apply_fluid(fluid);
+        config.background_step_rate = Some((1.0 / config.max_time_step()).ceil());
+        config
+    }
+
+    /// Derives the simulation constants of a weakly compressible liquid for the current
+    /// smoothing radius, bounding box and gravity. The particle mass fills the lattice spacing
+    /// at the rest density, and the speed of sound is ten times the fastest velocity of a
+    /// column of half the box height collapsing, which keeps the density within about one
+    /// percent of the rest density. The viscosity is raised to the artificial viscosity the
+    /// resolution needs to stay stable.
+    pub fn apply_fluid(&mut self, fluid: FluidProperties) {
+        let spacing = self.smoothing_radius * PARTICLE_SPACING;
+        let dim = match self.dimensions {
+            SimDim::Two => 2,
+            SimDim::Three => 3,
+        };
+        let column_height = self.bbox_dimensions.y / 2.0;
+        let speed_of_sound = 10.0 * (2.0 * self.gravity.norm() * column_height).sqrt();
+        let artificial_viscosity =
+            0.02 * fluid.rest_density * speed_of_sound * self.smoothing_radius / 8.0;
+
+        self.mass = fluid.rest_density * spacing.powi(dim);
+        self.rest_density = fluid.rest_density;
+        self.gas_const = speed_of_sound * speed_of_sound;
+        self.viscosity = fluid.viscosity.max(artificial_viscosity);
+        self.surface_tension = fluid.surface_tension;
+    }
+
+    /// In m/s, follows from the stiffness of the equation of state.
+    pub fn speed_of_sound(&self) -> f32 {
+        self.gas_const.max(0.0).sqrt()
+    }
+
+    /// Longest stable time step in seconds by the CFL condition on the speed of sound.
+    pub fn max_time_step(&self) -> f32 {
+        0.4 * self.smoothing_radius / self.speed_of_sound().max(f32::EPSILON)
+    }
+
+    pub fn spatial_grid(&self) -> SpatialGrid {
+        let grid = self.grid_for(self.particle_cnt + self.ghost_particle_cnt());
+        if self.spatial_lookup == SpatialLookupBackend::DenseGrid
+            && self.boundary != DomainBoundary::Box
+        {
+            tracing::warn!("The dense grid requires a closed box, using the hash table instead");
+        }
+
+        grid
+    }
+
+    /// Grid of the spatial lookup for `particle_cnt` particles, ghosts included.
+    fn grid_for(&self, particle_cnt: usize) -> SpatialGrid {
+        let bbox_dimensions = self.simulation_bbox();
+        let cell_size = self.cell_size();
+        let dense = SpatialGrid::Dense {
+            cell_cnt: Vector3::new(
+                (bbox_dimensions.x / cell_size).ceil() as u32,
+                (bbox_dimensions.y / cell_size).ceil() as u32,
+                (bbox_dimensions.z / cell_size).ceil() as u32,
+            ),
+        };
+        let hashed = SpatialGrid::Hashed {
+            table_size: self
+                .hash_table_size
+                .unwrap_or_else(|| (2 * particle_cnt as u32).next_power_of_two()),
+        };
+
+        match (self.spatial_lookup, self.boundary) {
+            (SpatialLookupBackend::Auto | SpatialLookupBackend::DenseGrid, DomainBoundary::Box) => {
+                dense
+            }
+            _ => hashed,
+        }
+    }
+
+    /// Distance the particles may move before the spatial lookup has to be rebuilt. Zero
+    /// unless `neighbor_caching` is set for a dense grid, the hash table rejects particles that
+    /// left their cell and the river teleports particles from the outflow to the inflow.
+    pub fn neighbor_skin(&self) -> f32 {
+        let dense = self.boundary == DomainBoundary::Box
+            && self.spatial_lookup != SpatialLookupBackend::HashTable;
+        match self.neighbor_caching {
+            Some(caching) if dense && self.river.is_none() => {
+                caching.skin.max(0.0) * self.smoothing_radius
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Edge length of the spatial lookup cells in m.
+    pub fn cell_size(&self) -> f32 {
+        self.smoothing_radius + self.neighbor_skin()
+    }
+
+    /// Steps between two rebuilds of the spatial lookup for time steps of `dt`. Limited so
+    /// that a particle at the speed of sound, which bounds the flow speed like in the CFL
+    /// condition, can't cross the skin in between.
+    pub fn neighbor_rebuild_interval(&self, dt: f32) -> u64 {
+        let Some(caching) = self.neighbor_caching else {
+            return 1;
+        };
+        let skin_steps = self.neighbor_skin() / (self.speed_of_sound() * dt).max(f32::EPSILON);
+        (caching.rebuild_interval as u64)
+            .min(skin_steps.min(u32::MAX as f32) as u64)
+            .max(1)
+    }
+
+    /// Most fluid particles whose buffers fit into the device limits and the memory budget,
+    /// the ghost particles taken into account. Fails if not even the ghosts and a single fluid
+    /// particle fit.
+    pub fn max_particle_cnt(&self, limits: &wgpu::Limits) -> Result<usize, SplooshError> {
+        let ghost_particle_cnt = self.ghost_particle_cnt();
+        let largest_buffer =
+            (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        // the smallest workgroups of the tuned kernels need the most workgroups
+        let dispatch =
+            limits.max_compute_workgroups_per_dimension as u64 * WORKGROUP_SIZES[0] as u64;
+
+        let total = (largest_buffer / LARGEST_BUFFER_BYTES_PER_PARTICLE)
+            .saturating_sub(MAX_STREAMLINE_STEPS as u64)
+            .min(dispatch)
+            .min(u32::MAX as u64);
+        let mut max_particle_cnt = (total as usize).saturating_sub(ghost_particle_cnt);
+
+        if let Some(mib) = self.memory_budget_mib {
+            let budget = mib * 1024 * 1024;
+            let fits = |fluid_particle_cnt: usize| {
+                self.allocations(fluid_particle_cnt, ghost_particle_cnt)
+                    .iter()
+                    .map(|(_, size)| size)
+                    .sum::<u64>()
+                    <= budget
+            };
+            // the allocations grow with the particle count, bisect for the last one that fits
+            if !fits(max_particle_cnt) {
+                let (mut fitting, mut exceeding) = (0, max_particle_cnt);
+                while exceeding - fitting > 1 {
+                    let middle = fitting + (exceeding - fitting) / 2;
+                    match fits(middle) {
+                        true => fitting = middle,
+                        false => exceeding = middle,
+                    }
+                }
+                max_particle_cnt = fitting;
+            }
+        }
+
+        match max_particle_cnt {
+            0 => Err(SplooshError::Config(format!(
+                "The {ghost_particle_cnt} ghost particles and a fluid particle don't fit into the device limits and the memory budget"
+            ))),
+            max_particle_cnt => Ok(max_particle_cnt),
+        }
+    }
+
+    /// Size in bytes of the GPU buffers of a simulation of `fluid_particle_cnt` particles, the
+    /// ghost particles come on top.
+    pub fn buffer_allocations(&self, fluid_particle_cnt: usize) -> Vec<(&'static str, u64)> {
+        self.allocations(fluid_particle_cnt, self.ghost_particle_cnt())
+    }
+
+    fn allocations(
+        &self,
+        fluid_particle_cnt: usize,
+        ghost_particle_cnt: usize,
+    ) -> Vec<(&'static str, u64)> {
+        let fluid = fluid_particle_cnt as u64;
+        let total = fluid + ghost_particle_cnt as u64;
+        let vertex = std::mem::size_of::<ColoredVertex>() as u64;
+        let readback_copies = 1 + READBACK_SLOTS as u64;
+
+        let mut allocations = vec![
+            // current, previous and interpolated positions
+            ("Positions", 3 * 16 * total),
+            ("Velocities", 16 * total),
+            // raw and filtered densities
+            ("Densities", 2 * 4 * total),
+            ("Pressures", 4 * total),
+            ("Forces", 16 * total),
+            // display buffer and its depth sorted copy
+            ("Display", 2 * vertex * total),
+            (
+                "Particle shapes",
+                2 * std::mem::size_of::<ParticleShape>() as u64 * total,
+            ),
+            (
+                "Spatial lookup",
+                2 * 4 * total + self.grid_for(total as usize).index_size(),
+            ),
+            ("Depth sort", 2 * 4 * total),
+            ("Neighbor counts", 4 * total),
+            ("Dye", 2 * 4 * total),
+            (
+                "Diagnostics",
+                readback_copies * std::mem::size_of::<ParticleDiagnostics>() as u64 * fluid.max(1),
+            ),
+            (
+                "Velocity lines",
+                2 * vertex * (fluid + MAX_STREAMLINE_STEPS as u64),
+            ),
+            // the packed frame and its staging copies
+            (
+                "Playback",
+                readback_copies * packed_frame_size(fluid_particle_cnt),
+            ),
+            ("Particle trails", TRAIL_BUFFER_SIZE),
+        ];
+        if let Some(soft_body) = &self.soft_body {
+            allocations.push((
+                "Soft body",
+                SoftBody::buffer_size(soft_body, self.dimensions),
+            ));
+        }
+
+        allocations
+    }
+
+    /// Dimensions of the simulated volume. In 2D the box is squashed into a slab one
+    /// smoothing radius deep on each side of the particle plane, so the grid has two layers of
+    /// cells with the particles on the boundary between them.
+    pub fn simulation_bbox(&self) -> Vector3<f32> {
+        match self.dimensions {
+            SimDim::Two => Vector3::new(
+                self.bbox_dimensions.x,
+                self.bbox_dimensions.y,
+                2.0 * self.smoothing_radius,
+            ),
+            SimDim::Three => self.bbox_dimensions,
+        }
+    }
+
+    /// Boundary particles the simulation adds to the `particle_cnt` fluid particles.
+    pub fn ghost_particle_cnt(&self) -> usize {
+        FluidSimulation::ghost_positions(
+            self.smoothing_radius,
+            self.simulation_bbox(),
+            self.dimensions,
+            self.boundary,
+        )
+        .len()
+    }
+}
+
+/// Points in the simulation step where custom compute passes can be injected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimulationStage {
+    /// Before the particles are sorted into the spatial lookup
+    PreSort,
+    /// After the densities have been computed
+    PostDensity,
+    /// After the pressure and viscosity forces have been computed
+    PostForce,
+    /// After the positions and velocities have been integrated
+    PostIntegrate,
+}
+
+/// Normalization constants of the SPH smoothing kernels, which depend on the number of
+/// dimensions.
+struct SphKernels {
+    poly6: f32,
+    spiky_grad: f32,
+    visc_lap: f32,
+}
+
+impl SphKernels {
+    fn new(dimensions: SimDim, h: f32) -> Self {
+        use std::f32::consts::PI;
+
+        match dimensions {
+            SimDim::Two => Self {
+                poly6: 4.0 / (PI * h.powi(8)),
+                spiky_grad: 10.0 / (PI * h.powi(5)),
+                visc_lap: 40.0 / (PI * h.powi(5)),
+            },
+            SimDim::Three => Self {
+                poly6: 315.0 / (64.0 * PI * h.powi(9)),
+                spiky_grad: 15.0 / (PI * h.powi(6)),
+                visc_lap: 45.0 / (PI * h.powi(6)),
+            },
+        }
+    }
+}
+
+/// CPU copy of the particle state, used to continue the simulation on a new device.
+#[derive(Clone, Debug)]
+pub struct ParticleSnapshot {
+    pub positions: Vec<Point4<f32>>,
+    pub velocities: Vec<Vector4<f32>>,
+    pub densities: Vec<f32>,
+    pub time: f32,
+    pub step_cnt: u64,
 }
 
 pub struct FluidSimulation {
     config: FluidSimulationConfig,
     bbox_geometry: Geometry,
-    _position_buffer: Rc<wgpu::Buffer>,
-    _velocity_buffer: Rc<wgpu::Buffer>,
-    _density_buffer: Rc<wgpu::Buffer>,
-    _force_buffer: Rc<wgpu::Buffer>,
+    particle_cnt: usize,
+    ghost_particle_cnt: usize,
+    workgroup_size: u32,
+    subgroup_kernels: bool,
+    position_buffer: Arc<wgpu::Buffer>,
+    velocity_buffer: Arc<wgpu::Buffer>,
+    density_buffer: Arc<wgpu::Buffer>,
+    pressure_buffer: Arc<wgpu::Buffer>,
+    force_buffer: Arc<wgpu::Buffer>,
+    step_buffer: Arc<wgpu::Buffer>,
+    uploader: Uploader,
+    /// Buffers read by the step before it writes them, checked by `pass_validation`
+    state_buffers: Vec<wgpu::Id<wgpu::Buffer>>,
+    /// Bits of the simulated time in seconds, there is no atomic f32
+    time: Arc<AtomicU32>,
+    step_cnt: Arc<AtomicU64>,
+    restart_history: Arc<AtomicBool>,
+    /// Steps since the spatial lookup was rebuilt, `u64::MAX` forces a rebuild
+    steps_since_rebuild: Arc<AtomicU64>,
 
     spatial_lookup: SpatialLookup,
-    compute_density_task: Rc<ComputeTask>,
+    compute_density_task: Arc<ComputeTask>,
+    density_filter: DensityFilter,
 
-    particle_display_buffer: Rc<wgpu::Buffer>,
-    display_density_task: Rc<ComputeTask>,
-    update_particle_task: Rc<ComputeTask>,
-    compute_force_task: Rc<ComputeTask>,
+    particle_display_buffer: Arc<wgpu::Buffer>,
+    /// Positions before the last background step, blended with the current ones for display
+    interpolation_buffer: Arc<wgpu::Buffer>,
+    interpolation: f32,
+    draw_args_buffer: Arc<wgpu::Buffer>,
+    cull_buffer: Arc<wgpu::Buffer>,
+    colormap_texture: ColormapTexture,
+    color_range_buffer: Arc<wgpu::Buffer>,
+    /// `None` without particles
+    depth_sort: Option<DepthSort>,
+    velocity_lines: VelocityLines,
+    density_slice: DensitySlice,
+    particle_trails: ParticleTrails,
+    minimap: Minimap,
+    screen_density: ScreenDensity,
+    dye: Dye,
+    debris: Debris,
+    diagnostics: Diagnostics,
+    playback: PlaybackRecorder,
+    probes: Probes,
+    wave_gauges: WaveGauges,
+    soft_body: Option<SoftBody>,
+    neighbor_count: NeighborCount,
+    anisotropy: Anisotropy,
+    particle_inspector: ParticleInspector,
+    selected_particle: Option<u32>,
+    display_density_task: Arc<ComputeTask>,
+    update_particle_task: Arc<ComputeTask>,
+    compute_pressure_task: Arc<ComputeTask>,
+    compute_force_task: Arc<ComputeTask>,
+
+    custom_passes: Vec<(SimulationStage, Arc<ComputeTask>)>,
 }
 
 impl FluidSimulation {
-    pub fn new(
-        config: FluidSimulationConfig,
-        render_engine: &RenderEngine,
-        wgpu_device: &WgpuDevice,
-    ) -> Self {
-        let bbox_geometry = render_engine
-            .create_geometry_array(&FluidSimulation::create_bbox_geometry(&config.bbox_dimensions));
+    /// Particle counts beyond `FluidSimulationConfig::max_particle_cnt` are clamped to it.
+    /// Panics if it fails, configs from outside are checked with it first.
+    pub fn new(mut config: FluidSimulationConfig, wgpu_device: &WgpuDevice) -> Self {
+        let _span =
+            tracing::info_span!("create_simulation", particles = config.particle_cnt).entered();
+        if let Some(preset) = config.preset {
+            config.apply_fluid(preset.properties());
+        }
+        // buffer creation would fail deep inside the constructor otherwise
+        let max_particle_cnt = config
+            .max_particle_cnt(&wgpu_device.device.limits())
+            .unwrap_or_else(|err| panic!("{err}"));
+        if config.particle_cnt > max_particle_cnt {
+            tracing::warn!(
+                "{} particles don't fit into the device limits and the memory budget, using {max_particle_cnt}",
+                config.particle_cnt
+            );
+            config.particle_cnt = max_particle_cnt;
+        }
+        let bbox_dimensions = config.simulation_bbox();
+        let kernels = SphKernels::new(config.dimensions, config.smoothing_radius);
+
+        let bbox_vertices = FluidSimulation::create_bbox_geometry(&bbox_dimensions)
+            .map(|position| ColoredVertex::new(position.into(), Vector4::new(0.1, 0.1, 0.1, 1.0)));
+        let bbox_geometry = Geometry::Array {
+            vertex_buffer: wgpu_device.create_buffer_init(
+                &bbox_vertices,
+                wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            ),
+            vertex_cnt: bbox_vertices.len(),
+        };
 
         let (positions, ghost_particle_cnt) = FluidSimulation::particle_start_positions(
             config.particle_cnt,
             config.smoothing_radius,
-            config.bbox_dimensions,
+            bbox_dimensions,
+            config.initial_layout,
+            config.dimensions,
+            config.boundary,
         );
+        let particle_cnt = positions.len();
 
         let position_buffer = wgpu_device.create_buffer_init(
+            &positions,
+            wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC,
+        );
+        let previous_position_buffer = wgpu_device.create_buffer_init(
             &positions,
             wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
         );
 
-        let densities = vec![config.rest_density; config.particle_cnt];
+        let densities = vec![config.rest_density; particle_cnt];
         let density_buffer = wgpu_device.create_buffer_init(
             &densities,
-            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
         );
 
-        let force_buffer = Rc::new(wgpu_device.device.create_buffer(&wgpu::BufferDescriptor {
+        let pressure_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pressure buffer"),
+            size: (particle_cnt * std::mem::size_of::<f32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let force_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Force buffer"),
-            size: (config.particle_cnt * std::mem::size_of::<nalgebra::Vector4<f32>>()) as u64,
+            size: (particle_cnt * std::mem::size_of::<nalgebra::Vector4<f32>>()) as u64,
             usage: wgpu::BufferUsages::STORAGE,
             mapped_at_creation: false,
-        }));
+        });
 
-        let velocity = vec![nalgebra::Vector4::<f32>::new(0.0, 0.0, 0.0, 1.0); config.particle_cnt];
+        let velocity = vec![nalgebra::Vector4::<f32>::new(0.0, 0.0, 0.0, 1.0); particle_cnt];
         let velocity_buffer = wgpu_device.create_buffer_init(
             &velocity,
-            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+            wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC,
         );
 
-        let particle_display_buffer =
-            Rc::new(wgpu_device.device.create_buffer(&wgpu::BufferDescriptor {
-                label: Some("Display buffer"),
-                size: (config.particle_cnt * std::mem::size_of::<ColoredVertex>()) as u64,
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
-                mapped_at_creation: false,
-            }));
+        let step_buffer = wgpu_device.create_buffer_init(
+            &[StepUniform::default()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let particle_display_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Display buffer"),
+            size: (particle_cnt * std::mem::size_of::<ColoredVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        // vertex count, instance count, first vertex, first instance
+        let draw_args_buffer = wgpu_device.create_buffer_init(
+            &[4u32, 0, 0, 0],
+            wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+        );
 
-        let cell_cnt = Vector3::new(
-            (config.bbox_dimensions.x / config.smoothing_radius).ceil() as u32,
-            (config.bbox_dimensions.y / config.smoothing_radius).ceil() as u32,
-            (config.bbox_dimensions.z / config.smoothing_radius).ceil() as u32,
+        let cull_buffer = wgpu_device.create_buffer_init(
+            &[CullUniform::default()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         );
 
+        let colormap_texture = ColormapTexture::new(wgpu_device);
+        let color_range_buffer = wgpu_device.create_buffer_init(
+            &[config.color_mode.default_range()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let grid = config.spatial_grid();
+
         let spatial_lookup = SpatialLookup::new(
             wgpu_device,
-            config.particle_cnt,
+            particle_cnt,
             config.smoothing_radius,
-            cell_cnt,
+            config.cell_size(),
+            grid,
             &position_buffer,
         );
 
-        let compute_density_task = FluidSimulation::create_compute_density_task(
+        let subgroup_kernels = config.subgroup_kernels && wgpu_device.supports_subgroups();
+        let create_sph_tasks = |workgroup_size| {
+            let compute_density_task = FluidSimulation::create_compute_density_task(
+                wgpu_device,
+                particle_cnt,
+                ghost_particle_cnt,
+                workgroup_size,
+                subgroup_kernels,
+                config.mass,
+                &kernels,
+                &spatial_lookup,
+                &position_buffer,
+                &density_buffer,
+            );
+            let compute_pressure_task = FluidSimulation::create_compute_pressure_task(
+                wgpu_device,
+                particle_cnt,
+                workgroup_size,
+                config.gas_const,
+                config.rest_density,
+                config.material,
+                &density_buffer,
+                &pressure_buffer,
+            );
+            let compute_force_task = FluidSimulation::create_compute_force_task(
+                wgpu_device,
+                particle_cnt,
+                ghost_particle_cnt,
+                workgroup_size,
+                subgroup_kernels,
+                config.pressure_pass,
+                config.mass,
+                config.gas_const,
+                config.rest_density,
+                config.viscosity,
+                config.viscosity_model,
+                config.surface_tension,
+                config.elastic_springs,
+                config.smoothing_radius,
+                config.material,
+                &kernels,
+                &spatial_lookup,
+                &position_buffer,
+                &velocity_buffer,
+                &density_buffer,
+                &pressure_buffer,
+                &force_buffer,
+            );
+            [
+                compute_density_task,
+                compute_pressure_task,
+                compute_force_task,
+            ]
+        };
+        let max_workgroup_size = wgpu_device
+            .device
+            .limits()
+            .max_compute_invocations_per_workgroup;
+        let workgroup_size = match config.workgroup_size {
+            Some(size) => size.clamp(1, max_workgroup_size),
+            None => wgpu_device.tuned_workgroup_size(|| {
+                tune_workgroup_size(wgpu_device, spatial_lookup.update_fn(), |size| {
+                    create_sph_tasks(size).to_vec()
+                })
+            }),
+        };
+        let [compute_density_task, compute_pressure_task, compute_force_task] =
+            create_sph_tasks(workgroup_size);
+
+        let density_filter = DensityFilter::new(
             wgpu_device,
-            config.particle_cnt,
+            particle_cnt,
             ghost_particle_cnt,
-            config.smoothing_radius,
             config.mass,
-            cell_cnt,
+            kernels.poly6,
+            config.dimensions,
+            &spatial_lookup,
             &position_buffer,
-            spatial_lookup.keys(),
-            spatial_lookup.vals(),
-            spatial_lookup.index(),
             &density_buffer,
         );
 
-        let display_density_task = FluidSimulation::create_display_density_task(
+        let neighbor_count = NeighborCount::new(
             wgpu_device,
-            config.particle_cnt,
-            config.bbox_dimensions,
+            particle_cnt,
+            ghost_particle_cnt,
+            &spatial_lookup,
             &position_buffer,
-            &density_buffer,
-            &particle_display_buffer,
         );
 
-        let update_particle_task = FluidSimulation::create_update_particles_task(
+        let anisotropy = Anisotropy::new(
             wgpu_device,
-            config.particle_cnt,
+            particle_cnt,
             ghost_particle_cnt,
-            config.smoothing_radius,
-            config.damping,
-            config.mass,
-            config.gravity,
-            config.bbox_dimensions,
+            &spatial_lookup,
             &position_buffer,
-            &velocity_buffer,
-            &density_buffer,
-            &force_buffer,
         );
 
-        let compute_force_task = FluidSimulation::create_compute_force_task(
-            wgpu_device,
-            config.particle_cnt,
-            ghost_particle_cnt,
-            config.smoothing_radius,
+        let interpolation_buffer = wgpu_device.create_buffer_init(
+            &positions,
+            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+        );
+
+        let dye = Dye::new(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            bbox_dimensions,
+            &spatial_lookup,
+            &position_buffer,
+            &density_buffer,
+        );
+
+        let debris = Debris::new(
+            wgpu_device,
+            ghost_particle_cnt,
+            config.mass,
+            config.rest_density,
+            kernels.poly6,
+            config.gravity,
+            bbox_dimensions,
+            config.dimensions,
+            config.boundary == DomainBoundary::Box,
+            config.boundary != DomainBoundary::Open,
+            config.kill_radius,
+            &spatial_lookup,
+            &position_buffer,
+            &velocity_buffer,
+            &density_buffer,
+        );
+
+        let display_density_task = FluidSimulation::create_display_density_task(
+            wgpu_device,
+            particle_cnt,
+            bbox_dimensions,
+            &position_buffer,
+            &interpolation_buffer,
+            &density_buffer,
+            neighbor_count.counts(),
+            dye.concentrations(),
+            &particle_display_buffer,
+            &draw_args_buffer,
+            &cull_buffer,
+            &colormap_texture,
+            &color_range_buffer,
+            &anisotropy,
+            &spatial_lookup,
+        );
+
+        // without particles there is nothing to sort, the clamp above keeps the count in a u32
+        let depth_sort = (particle_cnt > 0).then(|| {
+            DepthSort::new(
+                wgpu_device,
+                spatial_lookup.sorter(),
+                particle_cnt,
+                &particle_display_buffer,
+                &draw_args_buffer,
+                &cull_buffer,
+            )
+            .unwrap_or_else(|err| panic!("{err}"))
+        });
+
+        let velocity_lines = VelocityLines::new(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            bbox_dimensions,
+            &spatial_lookup,
+            &position_buffer,
+            &velocity_buffer,
+            &density_buffer,
+        );
+
+        let density_slice = DensitySlice::new(
+            wgpu_device,
+            bbox_dimensions,
+            config.mass,
+            kernels.poly6,
+            &spatial_lookup,
+            &position_buffer,
+        );
+
+        let particle_trails = ParticleTrails::new(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            bbox_dimensions,
+            &position_buffer,
+        );
+
+        let minimap = Minimap::new(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            bbox_dimensions,
+            &position_buffer,
+        );
+
+        let screen_density = ScreenDensity::new(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            bbox_dimensions,
+            &position_buffer,
+        );
+
+        let diagnostics = Diagnostics::new(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            config.mass,
+            config.gas_const,
+            config.rest_density,
+            kernels.spiky_grad,
+            &spatial_lookup,
+            &position_buffer,
+            &velocity_buffer,
+            &density_buffer,
+        );
+
+        // the walls keep the particles in the box, without them they go up to the kill radius
+        let (playback_origin, playback_extent) = match config.boundary {
+            DomainBoundary::Box => (Vector3::zeros(), bbox_dimensions),
+            DomainBoundary::Floor | DomainBoundary::Open => (
+                bbox_dimensions / 2.0 - Vector3::repeat(config.kill_radius),
+                Vector3::repeat(2.0 * config.kill_radius),
+            ),
+        };
+        let playback = PlaybackRecorder::new(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            PlaybackRange {
+                origin: playback_origin,
+                extent: playback_extent,
+                max_density: 3.0 * config.rest_density,
+            },
+            &position_buffer,
+            &velocity_buffer,
+            &density_buffer,
+        );
+
+        let probes = Probes::new(
+            wgpu_device,
+            ghost_particle_cnt,
+            bbox_dimensions,
             config.mass,
+            kernels.poly6,
+            config.gas_const,
+            config.rest_density,
+            &spatial_lookup,
+            &position_buffer,
+            &velocity_buffer,
+            &density_buffer,
+        );
+
+        let wave_gauges = WaveGauges::new(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            bbox_dimensions,
+            &position_buffer,
+        );
+
+        let particle_inspector = ParticleInspector::new(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            bbox_dimensions,
             config.gas_const,
             config.rest_density,
-            config.viscosity,
-            cell_cnt,
+            position_buffer.clone(),
+            velocity_buffer.clone(),
+            density_buffer.clone(),
+            neighbor_count.counts().clone(),
+        );
+
+        let update_particle_task = FluidSimulation::create_update_particles_task(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            config.smoothing_radius,
+            config.damping,
+            config.mass,
+            config.gravity,
+            bbox_dimensions,
+            config.boundary,
+            config.kill_radius,
+            config.river,
+            false,
+            config.dimensions,
             &position_buffer,
             &velocity_buffer,
-            spatial_lookup.keys(),
-            spatial_lookup.vals(),
-            spatial_lookup.index(),
             &density_buffer,
             &force_buffer,
+            &previous_position_buffer,
+            &step_buffer,
         );
 
-        Self {
-            config, 
+        let soft_body = config.soft_body.map(|soft_body| {
+            SoftBody::new(
+                wgpu_device,
+                &soft_body,
+                config.dimensions,
+                particle_cnt,
+                &FluidCoupling {
+                    ghost_particle_cnt,
+                    smoothing_radius: config.smoothing_radius,
+                    mass: config.mass,
+                    gas_const: config.gas_const,
+                    rest_density: config.rest_density,
+                    viscosity: config.viscosity,
+                    spiky_grad: kernels.spiky_grad,
+                    visc_lap: kernels.visc_lap,
+                },
+                bbox_dimensions,
+                config.gravity,
+                config.damping,
+                config.boundary == DomainBoundary::Box,
+                config.boundary != DomainBoundary::Open,
+                &spatial_lookup,
+                &position_buffer,
+                &velocity_buffer,
+                &density_buffer,
+                &force_buffer,
+            )
+        });
+
+        if let Some(relaxation) = config.relaxation {
+            let relax_task = FluidSimulation::create_update_particles_task(
+                wgpu_device,
+                particle_cnt,
+                ghost_particle_cnt,
+                config.smoothing_radius,
+                config.damping,
+                config.mass,
+                Vector3::zeros(),
+                bbox_dimensions,
+                config.boundary,
+                config.kill_radius,
+                None,
+                true,
+                config.dimensions,
+                &position_buffer,
+                &velocity_buffer,
+                &density_buffer,
+                &force_buffer,
+                &previous_position_buffer,
+                &step_buffer,
+            );
+
+            let step = StepUniform {
+                integrator: Integrator::SymplecticEuler.shader_id(),
+                first_step: 1,
+                ..Default::default()
+            };
+            wgpu_device
+                .queue
+                .write_buffer(&step_buffer, 0, bytemuck::bytes_of(&step));
+
+            let spatial_lookup_update = spatial_lookup.update_fn();
+            let mut encoder = wgpu_device
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            for _ in 0..relaxation.iterations {
+                spatial_lookup_update(&mut encoder, &wgpu_device.queue);
+                compute_density_task.execute(&mut encoder, &[]);
+                compute_pressure_task.execute(&mut encoder, &[]);
+                compute_force_task.execute(&mut encoder, &[]);
+                relax_task.execute(&mut encoder, bytemuck::bytes_of(&relaxation.dt));
+            }
+            wgpu_device.submit(encoder);
+        }
+
+        let mut state_buffers = vec![
+            position_buffer.global_id(),
+            velocity_buffer.global_id(),
+            previous_position_buffer.global_id(),
+            dye.concentrations().global_id(),
+        ];
+        if let Some(soft_body) = &soft_body {
+            state_buffers.extend(soft_body.state_buffers());
+        }
+
+        let fluid_sim = Self {
+            config,
 
             bbox_geometry,
-            _position_buffer: position_buffer,
-            _velocity_buffer: velocity_buffer,
-            _density_buffer: density_buffer,
-            _force_buffer: force_buffer,
+            particle_cnt,
+            ghost_particle_cnt,
+            workgroup_size,
+            subgroup_kernels,
+            position_buffer,
+            velocity_buffer,
+            density_buffer,
+            pressure_buffer,
+            force_buffer,
+            step_buffer,
+            uploader: wgpu_device.uploader.clone(),
+            state_buffers,
+            time: Arc::new(AtomicU32::new(0.0f32.to_bits())),
+            step_cnt: Arc::new(AtomicU64::new(0)),
+            restart_history: Arc::new(AtomicBool::new(false)),
+            steps_since_rebuild: Arc::new(AtomicU64::new(u64::MAX)),
 
             spatial_lookup,
             compute_density_task,
+            density_filter,
 
             particle_display_buffer,
+            interpolation_buffer,
+            interpolation: 1.0,
+            draw_args_buffer,
+            cull_buffer,
+            colormap_texture,
+            color_range_buffer,
+            depth_sort,
+            velocity_lines,
+            density_slice,
+            particle_trails,
+            minimap,
+            screen_density,
+            dye,
+            debris,
+            diagnostics,
+            playback,
+            probes,
+            wave_gauges,
+            soft_body,
+            neighbor_count,
+            anisotropy,
+            particle_inspector,
+            selected_particle: None,
             display_density_task,
             update_particle_task,
+            compute_pressure_task,
             compute_force_task,
+
+            custom_passes: Vec::new(),
+        };
+
+        if let Some(rest_state) = &fluid_sim.config.rest_state {
+            if let Err(err) = fluid_sim.warm_start(wgpu_device, rest_state) {
+                tracing::error!(
+                    "Failed to start from the rest state in {}: {err}",
+                    rest_state.path.display()
+                );
+            }
+        }
+
+        fluid_sim
+    }
+
+    /// Restores the rest state saved for this config, or steps the simulation until it has
+    /// settled and saves it if the file is missing or was saved with a different config.
+    fn warm_start(
+        &self,
+        wgpu_device: &WgpuDevice,
+        rest_state: &RestStateConfig,
+    ) -> Result<(), SplooshError> {
+        let _span = tracing::info_span!("warm_start").entered();
+        let path = &rest_state.path;
+        let fingerprint = config_fingerprint(&self.config)?;
+        match RestState::load(path) {
+            Ok(saved) if saved.fingerprint == fingerprint => {
+                tracing::info!("Starting from the rest state in {}", path.display());
+                return self.restore_snapshot(&wgpu_device.queue, &saved.snapshot());
+            }
+            Ok(_) => tracing::info!(
+                "The rest state in {} was saved with a different config, settling again",
+                path.display()
+            ),
+            Err(SplooshError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+                tracing::info!("No rest state in {} yet, settling", path.display())
+            }
+            Err(err) => tracing::warn!(
+                "Failed to read the rest state in {}, settling again: {err}",
+                path.display()
+            ),
+        }
+
+        let step_cnt = (rest_state.settle_time / rest_state.dt).ceil().max(0.0) as u64;
+        let step = self.step_fn(rest_state.dt);
+        for batch_start in (0..step_cnt).step_by(SETTLE_BATCH_SIZE) {
+            let mut encoder =
+                wgpu_device
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("Settle encoder"),
+                    });
+            for _ in batch_start..step_cnt.min(batch_start + SETTLE_BATCH_SIZE as u64) {
+                step(&mut encoder, &wgpu_device.queue);
+            }
+            wgpu_device.submit(encoder);
+            wgpu_device.device.poll(wgpu::Maintain::Wait);
         }
+
+        let saved = self.save_rest_state(wgpu_device)?;
+        self.restore_snapshot(&wgpu_device.queue, &saved.snapshot())
+    }
+
+    /// Saves the current particles to the rest state file of the config, the simulation
+    /// starts from them whenever it is created with the same config.
+    pub fn save_rest_state(&self, wgpu_device: &WgpuDevice) -> Result<RestState, SplooshError> {
+        let Some(rest_state) = &self.config.rest_state else {
+            return Err(SplooshError::Config(
+                "The config has no rest state file".to_string(),
+            ));
+        };
+
+        let saved = RestState::new(
+            &self.read_snapshot(wgpu_device)?,
+            config_fingerprint(&self.config)?,
+        );
+        saved.save(&rest_state.path)?;
+        tracing::info!("Saved the rest state to {}", rest_state.path.display());
+
+        Ok(saved)
     }
 
     fn create_bbox_geometry(dimensions: &Vector3<f32>) -> [Vector3<f32>; 24] {
@@ -218,59 +1807,113 @@ impl FluidSimulation {
         ]
     }
 
-    fn particle_start_positions(
-        particle_cnt: usize,
+    /// Two layers of boundary particles `PARTICLE_SPACING` apart on the floor of the bounding
+    /// box, none with an open boundary.
+    pub(crate) fn ghost_positions(
         smoothing_radius: f32,
         bbox_dimensions: Vector3<f32>,
-    ) -> (Vec<Point4<f32>>, usize) {
-        let mut positions = Vec::with_capacity(particle_cnt);
-
-        let squeeze_const = 0.55;
-        let num_ghost_layers = 2;
+        dimensions: SimDim,
+        boundary: DomainBoundary,
+    ) -> Vec<Point4<f32>> {
+        let num_ghost_layers = if boundary == DomainBoundary::Open {
+            0
+        } else {
+            2
+        };
+        let is_2d = dimensions == SimDim::Two;
+        let mut positions = Vec::new();
 
         for i in 0..num_ghost_layers {
             let mut x = 0.0;
             while x < bbox_dimensions.x {
-                let mut z = 0.0;
+                let mut z = if is_2d { bbox_dimensions.z / 2.0 } else { 0.0 };
                 while z < bbox_dimensions.z {
                     positions.push(Point4::new(
                         x,
-                        i as f32 * smoothing_radius * squeeze_const,
+                        i as f32 * smoothing_radius * PARTICLE_SPACING,
                         z,
                         1.0,
                     ));
-                    z += smoothing_radius * squeeze_const;
+                    z += smoothing_radius * PARTICLE_SPACING;
+                    if is_2d {
+                        break;
+                    }
                 }
-                x += smoothing_radius * squeeze_const;
+                x += smoothing_radius * PARTICLE_SPACING;
             }
         }
 
+        positions
+    }
+
+    /// The ghost particles followed by `fluid_particle_cnt` fluid particles in the initial
+    /// layout, and the number of ghost particles.
+    pub(crate) fn particle_start_positions(
+        fluid_particle_cnt: usize,
+        smoothing_radius: f32,
+        bbox_dimensions: Vector3<f32>,
+        initial_layout: InitialLayout,
+        dimensions: SimDim,
+        boundary: DomainBoundary,
+    ) -> (Vec<Point4<f32>>, usize) {
+        let mut positions =
+            Self::ghost_positions(smoothing_radius, bbox_dimensions, dimensions, boundary);
+        let is_2d = dimensions == SimDim::Two;
         let ghost_particle_cnt = positions.len();
-        let n = f32::ceil(f32::powf(
-            (particle_cnt - ghost_particle_cnt) as f32,
-            1.0 / 3.0,
-        )) as usize;
-        let half = ((n - 1) as f32 * smoothing_radius * squeeze_const) / 2.0;
-
-        'outer: for i in 0..n {
-            for j in 0..n {
-                for k in 0..n {
-                    let jitter_x = (rand::random::<f32>() - 0.5) * smoothing_radius / 6.0;
-                    let jitter_y = (rand::random::<f32>() - 0.5) * smoothing_radius / 6.0;
-                    let jitter_z = (rand::random::<f32>() - 0.5) * smoothing_radius / 6.0;
+        let particle_cnt = ghost_particle_cnt + fluid_particle_cnt;
+        positions.reserve(fluid_particle_cnt);
+        let spacing = smoothing_radius * PARTICLE_SPACING;
+        let jitter = || (rand::random::<f32>() - 0.5) * smoothing_radius / 6.0;
+        // particles stay on the z = bbox.z / 2 plane in 2D
+        let jitter_z = || if is_2d { 0.0 } else { jitter() };
 
-                    positions.push(Point4::new(
-                        j as f32 * smoothing_radius * squeeze_const + jitter_x - half
-                            + bbox_dimensions.x / 2.0,
-                        i as f32 * smoothing_radius * squeeze_const + jitter_y - half
-                            + bbox_dimensions.y / 2.0,
-                        k as f32 * smoothing_radius * squeeze_const + jitter_z - half
-                            + bbox_dimensions.z / 2.0,
-                        1.0,
-                    ));
-                    if positions.len() >= particle_cnt {
-                        break 'outer;
+        match initial_layout {
+            InitialLayout::Cube => {
+                let dim = if is_2d { 2.0 } else { 3.0 };
+                let n = f32::ceil(f32::powf(fluid_particle_cnt as f32, 1.0 / dim)) as usize;
+                let nz = if is_2d { 1 } else { n };
+                let half = ((n - 1) as f32 * spacing) / 2.0;
+                let half_z = ((nz - 1) as f32 * spacing) / 2.0;
+
+                'outer: for i in 0..n {
+                    for j in 0..n {
+                        for k in 0..nz {
+                            positions.push(Point4::new(
+                                j as f32 * spacing + jitter() - half + bbox_dimensions.x / 2.0,
+                                i as f32 * spacing + jitter() - half + bbox_dimensions.y / 2.0,
+                                k as f32 * spacing + jitter_z() - half_z + bbox_dimensions.z / 2.0,
+                                1.0,
+                            ));
+                            if positions.len() >= particle_cnt {
+                                break 'outer;
+                            }
+                        }
+                    }
+                }
+            }
+            InitialLayout::DamBreak => {
+                // a column of fluid against the -x wall, stacked upwards until the count is reached
+                let column_width = bbox_dimensions.x * 0.4 - 2.0 * smoothing_radius;
+                let column_depth = bbox_dimensions.z - 2.0 * smoothing_radius;
+                let nx = usize::max((column_width / spacing) as usize, 1);
+                let nz = usize::max((column_depth / spacing) as usize, 1);
+
+                let mut i = 0;
+                'layers: loop {
+                    for j in 0..nx {
+                        for k in 0..nz {
+                            if positions.len() >= particle_cnt {
+                                break 'layers;
+                            }
+                            positions.push(Point4::new(
+                                smoothing_radius + j as f32 * spacing + jitter(),
+                                smoothing_radius + i as f32 * spacing + jitter(),
+                                smoothing_radius + k as f32 * spacing + jitter_z(),
+                                1.0,
+                            ));
+                        }
                     }
+                    i += 1;
                 }
             }
         }
@@ -278,38 +1921,46 @@ impl FluidSimulation {
         (positions, ghost_particle_cnt)
     }
 
+    /// The subgroup kernel runs one invocation per sorted particle, ghosts included, the
+    /// per-particle kernel one per fluid particle.
+    #[allow(clippy::too_many_arguments)]
     fn create_compute_density_task(
         wgpu_device: &WgpuDevice,
         particle_cnt: usize,
         ghost_particle_cnt: usize,
-        smoothing_radius: f32,
+        workgroup_size: u32,
+        subgroup_kernels: bool,
         mass: f32,
-        cell_cnt: Vector3<u32>,
+        kernels: &SphKernels,
+        spatial_lookup: &SpatialLookup,
         positions: &wgpu::Buffer,
-        spatial_lookup_keys: &wgpu::Buffer,
-        spatial_lookup_vals: &wgpu::Buffer,
-        spatial_lookup_index: &wgpu::Buffer,
         density: &wgpu::Buffer,
-    ) -> Rc<ComputeTask> {
-        let mut workgroup_cnt = (particle_cnt - ghost_particle_cnt) as u32 / 256;
-        if (particle_cnt - ghost_particle_cnt) % 256 != 0 {
-            workgroup_cnt += 1;
-        }
-
+    ) -> Arc<ComputeTask> {
         let shader_source = format!(
             "
              const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
-             const SMOOTHING_RADIUS: f32 = {smoothing_radius};\n
-             const CELL_CNT: vec3<u32> = vec3<u32>({}, {}, {});\n 
+             const POLY6: f32 = {};\n
              const MASS: f32 = {mass};\n 
+             {}
+             {}
              {}",
-            cell_cnt.x,
-            cell_cnt.y,
-            cell_cnt.z,
-            include_str!("shaders/compute_density.wgsl")
+            kernels.poly6,
+            spatial_lookup.shader_source(),
+            include_str!("shaders/compute_density.wgsl"),
+            match subgroup_kernels {
+                true => concat!(
+                    include_str!("shaders/subgroup_rows.wgsl"),
+                    include_str!("shaders/compute_density_subgroup.wgsl")
+                ),
+                false => include_str!("shaders/compute_density_main.wgsl"),
+            }
         );
+        let invocations = match subgroup_kernels {
+            true => particle_cnt,
+            false => particle_cnt - ghost_particle_cnt,
+        };
 
-        Rc::new(ComputeTask::new(
+        Arc::new(ComputeTask::with_workgroup_size(
             wgpu_device,
             "Compute density",
             &[
@@ -371,15 +2022,15 @@ impl FluidSimulation {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: spatial_lookup_keys.as_entire_binding(),
+                    resource: spatial_lookup.keys().as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
-                    resource: spatial_lookup_vals.as_entire_binding(),
+                    resource: spatial_lookup.vals().as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
-                    resource: spatial_lookup_index.as_entire_binding(),
+                    resource: spatial_lookup.index().as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 4,
@@ -387,53 +2038,111 @@ impl FluidSimulation {
                 },
             ],
             &[],
-            shader_source.into(),
-            (workgroup_cnt, 1, 1),
+            &shader_source,
+            workgroup_size,
+            invocations as u32,
         ))
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn create_compute_force_task(
         wgpu_device: &WgpuDevice,
         particle_cnt: usize,
         ghost_particle_cnt: usize,
-        smoothing_radius: f32,
+        workgroup_size: u32,
+        subgroup_kernels: bool,
+        pressure_pass: bool,
         mass: f32,
         gas_const: f32,
         rest_density: f32,
         viscosity: f32,
-        cell_cnt: Vector3<u32>,
+        viscosity_model: ViscosityModel,
+        surface_tension: f32,
+        elastic_springs: Option<ElasticSprings>,
+        smoothing_radius: f32,
+        material: Material,
+        kernels: &SphKernels,
+        spatial_lookup: &SpatialLookup,
         positions: &wgpu::Buffer,
         velocities: &wgpu::Buffer,
-        spatial_lookup_keys: &wgpu::Buffer,
-        spatial_lookup_vals: &wgpu::Buffer,
-        spatial_lookup_index: &wgpu::Buffer,
         density: &wgpu::Buffer,
+        pressure: &wgpu::Buffer,
         force: &wgpu::Buffer,
-    ) -> Rc<ComputeTask> {
-        let mut workgroup_cnt = (particle_cnt - ghost_particle_cnt) as u32 / 256;
-        if (particle_cnt - ghost_particle_cnt) % 256 != 0 {
-            workgroup_cnt += 1;
-        }
+    ) -> Arc<ComputeTask> {
+        let (granular, friction, cohesion, viscosity) = match material {
+            Material::Fluid => (false, 0.0, 0.0, viscosity),
+            Material::Granular {
+                friction_angle,
+                cohesion,
+                shear_viscosity,
+            } => (
+                true,
+                friction_angle.to_radians().tan(),
+                cohesion,
+                shear_viscosity,
+            ),
+        };
+        let (spring_stiffness, spring_rest_length) = elastic_springs
+            .map_or((0.0, 0.0), |springs| {
+                (springs.stiffness, springs.rest_length * smoothing_radius)
+            });
+
+        // Akinci cohesion, the coefficient is a dimensional estimate that gives roughly the
+        // surface tension in N/m
+        let cohesion_coefficient =
+            surface_tension / (rest_density.powi(2) * smoothing_radius.powi(4));
+        let cohesion_kernel = 32.0 / (std::f32::consts::PI * smoothing_radius.powi(9));
 
         let shader_source = format!(
             "
              const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
-             const REST_DENSITY: f32 = {rest_density};\n
-             const GAS_CONST: f32 = {gas_const};\n
-             const SMOOTHING_RADIUS: f32 = {smoothing_radius};\n
-             const CELL_CNT: vec3<u32> = vec3<u32>({}, {}, {});\n 
+             const SURFACE_TENSION: f32 = {cohesion_coefficient};\n
+             const COHESION_KERNEL: f32 = {cohesion_kernel};\n
+             const SPIKY_GRAD: f32 = {};\n
+             const VISC_LAP: f32 = {};\n
              const MASS: f32 = {mass};\n 
              const VISCOSITY: f32 = {viscosity};\n 
+             const GRANULAR: bool = {granular};\n
+             const FRICTION: f32 = {friction};\n
+             const COHESION: f32 = {cohesion};\n
+             const SPIKY_DERIVATIVE: f32 = {};\n
+             const SPRING_STIFFNESS: f32 = {spring_stiffness};\n
+             const SPRING_REST_LENGTH: f32 = {spring_rest_length};\n
+             const GAS_CONST: f32 = {gas_const};\n
+             const REST_DENSITY: f32 = {rest_density};\n
+             {}
+             {}
+             {}
+             {}
+             {}
              {}",
-            cell_cnt.x,
-            cell_cnt.y,
-            cell_cnt.z,
-            include_str!("shaders/compute_force.wgsl")
+            kernels.spiky_grad,
+            kernels.visc_lap,
+            3.0 * kernels.spiky_grad,
+            viscosity_model.shader_constants(),
+            spatial_lookup.shader_source(),
+            include_str!("shaders/pressure_term.wgsl"),
+            include_str!("shaders/compute_force.wgsl"),
+            match pressure_pass {
+                true => include_str!("shaders/force_pressure_precomputed.wgsl"),
+                false => include_str!("shaders/force_pressure_inline.wgsl"),
+            },
+            match subgroup_kernels {
+                true => concat!(
+                    include_str!("shaders/subgroup_rows.wgsl"),
+                    include_str!("shaders/compute_force_subgroup.wgsl")
+                ),
+                false => include_str!("shaders/compute_force_main.wgsl"),
+            }
         );
+        let invocations = match subgroup_kernels {
+            true => particle_cnt,
+            false => particle_cnt - ghost_particle_cnt,
+        };
 
-        Rc::new(ComputeTask::new(
+        Arc::new(ComputeTask::with_workgroup_size(
             wgpu_device,
-            "Compute pressure",
+            "Compute force",
             &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
@@ -498,6 +2207,16 @@ impl FluidSimulation {
                 wgpu::BindGroupLayoutEntry {
                     binding: 6,
                     visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Storage { read_only: false },
                         has_dynamic_offset: false,
@@ -517,15 +2236,15 @@ impl FluidSimulation {
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
-                    resource: spatial_lookup_keys.as_entire_binding(),
+                    resource: spatial_lookup.keys().as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
-                    resource: spatial_lookup_vals.as_entire_binding(),
+                    resource: spatial_lookup.vals().as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 4,
-                    resource: spatial_lookup_index.as_entire_binding(),
+                    resource: spatial_lookup.index().as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 5,
@@ -533,15 +2252,77 @@ impl FluidSimulation {
                 },
                 wgpu::BindGroupEntry {
                     binding: 6,
+                    resource: pressure.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
                     resource: force.as_entire_binding(),
                 },
             ],
             &[],
-            shader_source.into(),
-            (workgroup_cnt, 1, 1),
+            &shader_source,
+            workgroup_size,
+            invocations as u32,
+        ))
+    }
+
+    /// p / ρ² of every particle from its density, read by the force pass for each pair.
+    #[allow(clippy::too_many_arguments)]
+    fn create_compute_pressure_task(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        workgroup_size: u32,
+        gas_const: f32,
+        rest_density: f32,
+        material: Material,
+        density: &wgpu::Buffer,
+        pressure: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let granular = matches!(material, Material::Granular { .. });
+        let shader_source = format!(
+            "
+             const REST_DENSITY: f32 = {rest_density};\n
+             const GAS_CONST: f32 = {gas_const};\n
+             const GRANULAR: bool = {granular};\n
+             {}
+             {}",
+            include_str!("shaders/pressure_term.wgsl"),
+            include_str!("shaders/compute_pressure.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        Arc::new(ComputeTask::with_workgroup_size(
+            wgpu_device,
+            "Compute pressure",
+            &[storage_entry(0, true), storage_entry(1, false)],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: density.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: pressure.as_entire_binding(),
+                },
+            ],
+            &[],
+            &shader_source,
+            workgroup_size,
+            particle_cnt as u32,
         ))
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn create_update_particles_task(
         wgpu_device: &WgpuDevice,
         particle_cnt: usize,
@@ -551,16 +2332,56 @@ impl FluidSimulation {
         mass: f32,
         gravity: Vector3<f32>,
         bbox_dimensions: Vector3<f32>,
+        domain_boundary: DomainBoundary,
+        kill_radius: f32,
+        river: Option<RiverFlow>,
+        relax: bool,
+        dimensions: SimDim,
         positions: &wgpu::Buffer,
         velocities: &wgpu::Buffer,
         densities: &wgpu::Buffer,
         forces: &wgpu::Buffer,
-    ) -> Rc<ComputeTask> {
-        let mut workgroup_cnt = (particle_cnt - ghost_particle_cnt) as u32 / 256;
-        if (particle_cnt - ghost_particle_cnt) % 256 != 0 {
-            workgroup_cnt += 1;
+        previous_positions: &wgpu::Buffer,
+        step: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = ((particle_cnt - ghost_particle_cnt) as u32).div_ceil(256);
+
+        // respawned particles are spread out a bit so they don't overlap, but stay on the plane in 2D
+        let mut spawn_extent = bbox_dimensions * 0.2;
+        if dimensions == SimDim::Two {
+            spawn_extent.z = 0.0;
         }
 
+        // recycled particles keep clear of the walls across the channel
+        let river_span = if dimensions == SimDim::Two {
+            0.0
+        } else {
+            bbox_dimensions.z - 2.0 * smoothing_radius
+        };
+        let river_constants = match river {
+            Some(river) => format!(
+                "const RIVER: bool = true;
+                 const INFLOW_WIDTH: f32 = {};
+                 const INFLOW_DEPTH: f32 = {};
+                 const INFLOW_VELOCITY: vec3<f32> = vec3<f32>({}, {}, {});
+                 const OUTFLOW_X: f32 = {};
+                 const RIVER_SPAN: f32 = {river_span};",
+                river.inflow_width,
+                river.inflow_depth,
+                river.velocity.x,
+                river.velocity.y,
+                river.velocity.z,
+                bbox_dimensions.x - river.outflow_distance,
+            ),
+            None => "const RIVER: bool = false;
+                 const INFLOW_WIDTH: f32 = 0.0;
+                 const INFLOW_DEPTH: f32 = 0.0;
+                 const INFLOW_VELOCITY: vec3<f32> = vec3<f32>(0.0);
+                 const OUTFLOW_X: f32 = 0.0;
+                 const RIVER_SPAN: f32 = 0.0;"
+                .to_owned(),
+        };
+
         let shader_source = format!(
             "
              const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
@@ -569,6 +2390,12 @@ impl FluidSimulation {
              const BBOX: vec3<f32> = vec3<f32>({}, {}, {});\n 
              const G: vec3<f32> = vec3<f32>({}, {}, {});\n 
              const DAMPING: f32 = {damping};\n 
+             const WALLS: bool = {};\n
+             const FLOOR: bool = {};\n
+             const KILL_RADIUS: f32 = {kill_radius};\n
+             const SPAWN_EXTENT: vec3<f32> = vec3<f32>({}, {}, {});\n
+             {river_constants}\n
+             const RELAX: bool = {relax};\n
              {}",
             bbox_dimensions.x,
             bbox_dimensions.y,
@@ -576,10 +2403,15 @@ impl FluidSimulation {
             gravity.x,
             gravity.y,
             gravity.z,
+            domain_boundary == DomainBoundary::Box,
+            domain_boundary != DomainBoundary::Open,
+            spawn_extent.x,
+            spawn_extent.y,
+            spawn_extent.z,
             include_str!("shaders/update_particles.wgsl")
         );
 
-        Rc::new(ComputeTask::new(
+        Arc::new(ComputeTask::new(
             wgpu_device,
             "Update particles",
             &[
@@ -623,6 +2455,26 @@ impl FluidSimulation {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
             &[
                 wgpu::BindGroupEntry {
@@ -641,6 +2493,14 @@ impl FluidSimulation {
                     binding: 3,
                     resource: forces.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: step.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: previous_positions.as_entire_binding(),
+                },
             ],
             &[wgpu::PushConstantRange {
                 stages: wgpu::ShaderStages::COMPUTE,
@@ -651,30 +2511,50 @@ impl FluidSimulation {
         ))
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn create_display_density_task(
         wgpu_device: &WgpuDevice,
         particle_cnt: usize,
         bbox_dimensions: Vector3<f32>,
         positions: &wgpu::Buffer,
+        previous_positions: &wgpu::Buffer,
         density: &wgpu::Buffer,
+        neighbor_count: &wgpu::Buffer,
+        dye: &wgpu::Buffer,
         display_buffer: &wgpu::Buffer,
-    ) -> Rc<ComputeTask> {
-        let mut workgroup_cnt = particle_cnt as u32 / 256;
-        if particle_cnt % 256 != 0 {
-            workgroup_cnt += 1;
-        }
+        draw_args: &wgpu::Buffer,
+        cull: &wgpu::Buffer,
+        colormap: &ColormapTexture,
+        color_range: &wgpu::Buffer,
+        anisotropy: &Anisotropy,
+        spatial_lookup: &SpatialLookup,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = (particle_cnt as u32).div_ceil(256);
 
         let shader_source = format!(
             "
              const OFFSET: vec3<f32> = vec3<f32>({}, {}, {});\n 
+             {}
+             {}
+             {}
+             {}
+             {}
              {}",
             -bbox_dimensions.x / 2.0,
             -bbox_dimensions.y / 2.0,
             -bbox_dimensions.z / 2.0,
-            include_str!("shaders/fill_display_buffer.wgsl")
+            COLORMAP_SHADER,
+            PARTICLE_SHAPE_SHADER,
+            spatial_lookup.shader_source(),
+            include_str!("shaders/display_particle.wgsl"),
+            include_str!("shaders/fill_display_buffer.wgsl"),
+            match wgpu_device.supports_subgroups() {
+                true => include_str!("shaders/display_append_subgroup.wgsl"),
+                false => include_str!("shaders/display_append.wgsl"),
+            }
         );
 
-        Rc::new(ComputeTask::new(
+        Arc::new(ComputeTask::new(
             wgpu_device,
             "Display density",
             &[
@@ -708,64 +2588,1634 @@ impl FluidSimulation {
                     },
                     count: None,
                 },
-            ],
-            &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: positions.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: density.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: display_buffer.as_entire_binding(),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                ColormapTexture::layout_entry(8, wgpu::ShaderStages::COMPUTE),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 14,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
             ],
-            &[],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: density.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: display_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: draw_args.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: cull.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: neighbor_count.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: previous_positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: dye.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: wgpu::BindingResource::TextureView(colormap.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: color_range.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: anisotropy.shapes().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: anisotropy.display_shapes().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 14,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+            ],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..16,
+            }],
             shader_source.into(),
             (workgroup_cnt, 1, 1),
         ))
     }
 
-    pub fn update(&self, render_engine: &mut RenderEngine, dt: f32, simulation_paused: bool) {
+    pub fn config(&self) -> &FluidSimulationConfig {
+        &self.config
+    }
+
+    /// Fluid and ghost particles, the length of the particle buffers.
+    pub fn particle_cnt(&self) -> usize {
+        self.particle_cnt
+    }
+
+    pub fn fluid_particle_cnt(&self) -> usize {
+        self.particle_cnt - self.ghost_particle_cnt
+    }
+
+    pub fn bbox_dimensions(&self) -> Vector3<f32> {
+        self.config.simulation_bbox()
+    }
+
+    /// Edges of the bounding box as a line list, drawn as a scene object by the application.
+    pub fn bbox_geometry(&self) -> Geometry {
+        self.bbox_geometry.clone()
+    }
+
+    /// Runs `task` every simulation step at the given stage, after the passes already
+    /// registered for that stage. Custom passes are executed without push constants.
+    pub fn add_custom_pass(&mut self, stage: SimulationStage, task: Arc<ComputeTask>) {
+        self.custom_passes.push((stage, task));
+    }
+
+    pub fn step_fn(&self, dt: f32) -> GenericRequest {
+        let stages = self.step_stages(dt);
+        let state_buffers = self.state_buffers.clone();
+
+        Box::new(move |encoder, queue| {
+            // debug builds check that no pass reads what a later pass of the step writes
+            pass_validation::begin_step(&state_buffers);
+            for (_, stage) in &stages {
+                stage(encoder, queue);
+            }
+            pass_validation::end_step();
+        })
+    }
+
+    /// The passes of a single step grouped into named stages, run in order they are the same
+    /// as `step_fn`. Custom passes belong to the stage before them.
+    pub fn step_stages(&self, dt: f32) -> Vec<(&'static str, GenericRequest)> {
+        let spatial_lookup_update = self.spatial_lookup.update_fn();
+        let compute_density_task = self.compute_density_task.clone();
+        let compute_pressure_task = self.compute_pressure_task.clone();
+        let compute_force_task = self.compute_force_task.clone();
+        let update_particles_task = self.update_particle_task.clone();
+        let custom_passes = self.custom_passes.clone();
+        let wave_paddle = self.config.wave_paddle;
+        let integrator = self.config.integrator;
+        let uploader = self.uploader.clone();
+        let step_buffer = self.step_buffer.clone();
+        let time = self.time.clone();
+        let step_cnt = self.step_cnt.clone();
+        let restart_history = self.restart_history.clone();
+        let steps_since_rebuild = self.steps_since_rebuild.clone();
+        let rebuild_interval = self.config.neighbor_rebuild_interval(dt);
+
+        let run_custom_passes = move |encoder: &mut wgpu::CommandEncoder, stage| {
+            for (_, task) in custom_passes.iter().filter(|(s, _)| *s == stage) {
+                task.execute(encoder, &[]);
+            }
+        };
+        let run_custom_passes = Arc::new(run_custom_passes);
+
+        let custom_passes = run_custom_passes.clone();
+        let sort: GenericRequest = Box::new(move |encoder, queue| {
+            let current_time = f32::from_bits(time.load(Ordering::Relaxed)) + dt;
+            time.store(current_time.to_bits(), Ordering::Relaxed);
+            let mut step = StepUniform {
+                integrator: integrator.shader_id(),
+                first_step: (step_cnt.load(Ordering::Relaxed) == 0
+                    || restart_history.swap(false, Ordering::Relaxed))
+                    as u32,
+                ..Default::default()
+            };
+            if let Some(paddle) = wave_paddle {
+                step.paddle_position = paddle.position(current_time);
+                step.paddle_velocity = paddle.velocity(current_time);
+            }
+            // recorded into the encoder, several steps in one submission each see their own
+            uploader.write(encoder, &step_buffer, 0, bytemuck::bytes_of(&step));
+            step_cnt.fetch_add(1, Ordering::Relaxed);
+
+            custom_passes(encoder, SimulationStage::PreSort);
+            let steps = steps_since_rebuild.load(Ordering::Relaxed);
+            if steps >= rebuild_interval {
+                spatial_lookup_update(encoder, queue);
+                steps_since_rebuild.store(1, Ordering::Relaxed);
+            } else {
+                steps_since_rebuild.store(steps + 1, Ordering::Relaxed);
+            }
+        });
+
+        let custom_passes = run_custom_passes.clone();
+        let density: GenericRequest = Box::new(move |encoder, _| {
+            compute_density_task.execute(encoder, &[]);
+            custom_passes(encoder, SimulationStage::PostDensity);
+        });
+
+        let pressure: GenericRequest = Box::new(move |encoder, _| {
+            compute_pressure_task.execute(encoder, &[]);
+        });
+
+        let custom_passes = run_custom_passes.clone();
+        let force: GenericRequest = Box::new(move |encoder, _| {
+            compute_force_task.execute(encoder, &[]);
+            custom_passes(encoder, SimulationStage::PostForce);
+        });
+
+        let integrate: GenericRequest = Box::new(move |encoder, _| {
+            update_particles_task.execute(encoder, bytemuck::bytes_of(&dt));
+            run_custom_passes(encoder, SimulationStage::PostIntegrate);
+        });
+
+        let mut stages = vec![("sort", sort), ("density", density)];
+        if self.config.free_surface_correction {
+            stages.push(("free surface", self.density_filter.free_surface_fn()));
+        }
+        if let Some(renormalization) = self.config.density_renormalization {
+            let renormalize = self.density_filter.renormalize_fn(renormalization.method);
+            let interval = renormalization.interval.max(1) as u64;
+            let step_cnt = self.step_cnt.clone();
+            let renormalize: GenericRequest = Box::new(move |encoder, queue| {
+                // the sort already counted this step
+                if step_cnt.load(Ordering::Relaxed) % interval == 0 {
+                    renormalize(encoder, queue);
+                }
+            });
+            stages.push(("renormalize", renormalize));
+        }
+        // the dye diffuses between the neighbors found for the density
+        if let Some(dye) = self.config.dye {
+            stages.push(("dye", self.dye.step_fn(dye, dt)));
+        }
+        if let Some(debris) = self.config.debris {
+            stages.push(("debris", self.debris.step_fn(debris, dt)));
+        }
+        // after every pass that changes the densities
+        if self.config.pressure_pass {
+            stages.push(("pressure", pressure));
+        }
+        stages.push(("force", force));
+        // adds the coupling to the fluid forces before they are integrated
+        if let Some(soft_body) = &self.soft_body {
+            stages.push(("soft body", soft_body.step_fn(dt)));
+        }
+        stages.push(("integrate", integrate));
+        stages
+    }
+
+    /// Same as `step_fn`, but keeps the positions before the step so the display can blend
+    /// between the last two steps while they are submitted independently of the frames.
+    pub fn background_step_fn(&self, dt: f32) -> GenericRequest {
+        let step = self.step_fn(dt);
+        let position_buffer = self.position_buffer.clone();
+        let interpolation_buffer = self.interpolation_buffer.clone();
+
+        Box::new(move |encoder, queue| {
+            encoder.copy_buffer_to_buffer(
+                &position_buffer,
+                0,
+                &interpolation_buffer,
+                0,
+                position_buffer.size(),
+            );
+            step(encoder, queue);
+        })
+    }
+
+    /// Blend factor between the positions before and after the last background step, 1 draws
+    /// the current positions.
+    pub fn set_interpolation(&mut self, interpolation: f32) {
+        self.interpolation = interpolation.clamp(0.0, 1.0);
+    }
+
+    /// Switching to Verlet restarts its position history with a Taylor step.
+    pub fn set_integrator(&mut self, integrator: Integrator) {
+        if integrator == Integrator::Verlet && self.config.integrator != Integrator::Verlet {
+            self.step_cnt.store(0, Ordering::Relaxed);
+        }
+        self.config.integrator = integrator;
+    }
+
+    /// Simulated seconds since the start.
+    pub fn sim_time(&self) -> f32 {
+        f32::from_bits(self.time.load(Ordering::Relaxed))
+    }
+
+    pub fn step_cnt(&self) -> u64 {
+        self.step_cnt.load(Ordering::Relaxed)
+    }
+
+    /// Size in bytes of the GPU buffers, by the same table that caps the particle count.
+    pub fn buffer_memory(&self) -> Vec<(&'static str, u64)> {
+        self.config
+            .allocations(self.fluid_particle_cnt(), self.ghost_particle_cnt)
+    }
+
+    /// Blocks until the submitted steps have finished and copies the particle state to the CPU.
+    pub fn read_snapshot(
+        &self,
+        wgpu_device: &WgpuDevice,
+    ) -> Result<ParticleSnapshot, SplooshError> {
+        self.read_particles(wgpu_device, 0..self.particle_cnt)
+    }
+
+    /// Like `read_snapshot`, for the particles in `range` of the buffers.
+    pub fn read_particles(
+        &self,
+        wgpu_device: &WgpuDevice,
+        range: Range<usize>,
+    ) -> Result<ParticleSnapshot, SplooshError> {
+        if range.start > range.end || range.end > self.particle_cnt {
+            return Err(SplooshError::Snapshot(format!(
+                "Particles {range:?} are out of the {} particles of the simulation",
+                self.particle_cnt
+            )));
+        }
+        if range.is_empty() {
+            return Ok(ParticleSnapshot {
+                positions: Vec::new(),
+                velocities: Vec::new(),
+                densities: Vec::new(),
+                time: self.sim_time(),
+                step_cnt: self.step_cnt(),
+            });
+        }
+
+        let buffers = [
+            (&self.position_buffer, std::mem::size_of::<Point4<f32>>()),
+            (&self.velocity_buffer, std::mem::size_of::<Vector4<f32>>()),
+            (&self.density_buffer, std::mem::size_of::<f32>()),
+        ];
+        let staging_buffers = buffers.map(|(_, stride)| {
+            wgpu_device.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Snapshot staging buffer"),
+                size: (range.len() * stride) as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        });
+
+        let mut encoder =
+            wgpu_device
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Snapshot encoder"),
+                });
+        for ((buffer, stride), staging_buffer) in buffers.iter().zip(&staging_buffers) {
+            encoder.copy_buffer_to_buffer(
+                buffer,
+                (range.start * stride) as u64,
+                staging_buffer,
+                0,
+                staging_buffer.size(),
+            );
+        }
+        wgpu_device.submit(encoder);
+
+        let device = &wgpu_device.device;
+        Ok(ParticleSnapshot {
+            positions: read_staging(device, &staging_buffers[0])?,
+            velocities: read_staging(device, &staging_buffers[1])?,
+            densities: read_staging(device, &staging_buffers[2])?,
+            time: self.sim_time(),
+            step_cnt: self.step_cnt(),
+        })
+    }
+
+    /// Overwrites the particles from `first` on with those of `particles`, without touching
+    /// the time or the position history. Meant for particles whose next step is thrown away
+    /// anyway, like the halo of a split simulation.
+    pub fn write_particles(
+        &self,
+        queue: &wgpu::Queue,
+        first: usize,
+        particles: &ParticleSnapshot,
+    ) -> Result<(), SplooshError> {
+        let cnt = particles.positions.len();
+        if particles.velocities.len() != cnt
+            || particles.densities.len() != cnt
+            || first + cnt > self.particle_cnt
+        {
+            return Err(SplooshError::Snapshot(format!(
+                "{cnt} particles from {first} don't fit into the {} particles of the simulation",
+                self.particle_cnt
+            )));
+        }
+
+        queue.write_buffer(
+            &self.position_buffer,
+            (first * std::mem::size_of::<Point4<f32>>()) as u64,
+            bytemuck::cast_slice(&particles.positions),
+        );
+        queue.write_buffer(
+            &self.velocity_buffer,
+            (first * std::mem::size_of::<Vector4<f32>>()) as u64,
+            bytemuck::cast_slice(&particles.velocities),
+        );
+        queue.write_buffer(
+            &self.density_buffer,
+            (first * std::mem::size_of::<f32>()) as u64,
+            bytemuck::cast_slice(&particles.densities),
+        );
+
+        Ok(())
+    }
+
+    /// Continues from `snapshot`, which has to be taken from a simulation with the same
+    /// particle layout. The Verlet position history restarts with a Taylor step.
+    pub fn restore_snapshot(
+        &self,
+        queue: &wgpu::Queue,
+        snapshot: &ParticleSnapshot,
+    ) -> Result<(), SplooshError> {
+        let particle_cnt = self.particle_cnt;
+        if snapshot.positions.len() != particle_cnt
+            || snapshot.velocities.len() != particle_cnt
+            || snapshot.densities.len() != particle_cnt
+        {
+            return Err(SplooshError::Snapshot(format!(
+                "The snapshot has {} particles, the simulation {particle_cnt}",
+                snapshot.positions.len()
+            )));
+        }
+
+        queue.write_buffer(
+            &self.position_buffer,
+            0,
+            bytemuck::cast_slice(&snapshot.positions),
+        );
+        queue.write_buffer(
+            &self.velocity_buffer,
+            0,
+            bytemuck::cast_slice(&snapshot.velocities),
+        );
+        queue.write_buffer(
+            &self.density_buffer,
+            0,
+            bytemuck::cast_slice(&snapshot.densities),
+        );
+        self.restart_at(snapshot.time, snapshot.step_cnt);
+
+        Ok(())
+    }
+
+    /// Moves the fluid particles back to a frame recorded by this simulation, the frames
+    /// recorded from here on continue after it.
+    pub fn restore_playback_frame(
+        &self,
+        queue: &wgpu::Queue,
+        frame: &PlaybackFrame,
+    ) -> Result<(), SplooshError> {
+        if frame.particle_cnt() != self.fluid_particle_cnt() {
+            return Err(SplooshError::Snapshot(format!(
+                "The frame has {} particles, the simulation {}",
+                frame.particle_cnt(),
+                self.fluid_particle_cnt()
+            )));
+        }
+
+        // the ghost particles never move and aren't recorded
+        let (positions, velocities, densities) = self.playback.unpack(frame);
+        let ghost_particle_cnt = self.ghost_particle_cnt as u64;
+        queue.write_buffer(
+            &self.position_buffer,
+            ghost_particle_cnt * std::mem::size_of::<Point4<f32>>() as u64,
+            bytemuck::cast_slice(&positions),
+        );
+        queue.write_buffer(
+            &self.velocity_buffer,
+            ghost_particle_cnt * std::mem::size_of::<Vector4<f32>>() as u64,
+            bytemuck::cast_slice(&velocities),
+        );
+        queue.write_buffer(
+            &self.density_buffer,
+            ghost_particle_cnt * std::mem::size_of::<f32>() as u64,
+            bytemuck::cast_slice(&densities),
+        );
+        self.restart_at(frame.time, frame.step_cnt);
+
+        Ok(())
+    }
+
+    /// Continues from restored particles at `time`, the Verlet position history restarts
+    /// with a Taylor step.
+    fn restart_at(&self, time: f32, step_cnt: u64) {
+        self.time.store(time.to_bits(), Ordering::Relaxed);
+        self.step_cnt.store(step_cnt, Ordering::Relaxed);
+        self.restart_history.store(true, Ordering::Relaxed);
+        self.steps_since_rebuild.store(u64::MAX, Ordering::Relaxed);
+        self.particle_trails.reset();
+        self.playback.reset(step_cnt);
+    }
+
+    /// Number of static boundary particles stored at the start of every particle buffer.
+    pub fn ghost_particle_cnt(&self) -> usize {
+        self.ghost_particle_cnt
+    }
+
+    /// Threads per workgroup of the density and force kernels, tuned unless the config sets it.
+    pub fn workgroup_size(&self) -> u32 {
+        self.workgroup_size
+    }
+
+    /// Whether the density and force kernels share neighbor loads across subgroups.
+    pub fn subgroup_kernels(&self) -> bool {
+        self.subgroup_kernels
+    }
+
+    pub fn positions(&self) -> &wgpu::Buffer {
+        &self.position_buffer
+    }
+
+    pub fn velocities(&self) -> &wgpu::Buffer {
+        &self.velocity_buffer
+    }
+
+    pub fn densities(&self) -> &wgpu::Buffer {
+        &self.density_buffer
+    }
+
+    pub fn forces(&self) -> &wgpu::Buffer {
+        &self.force_buffer
+    }
+
+    /// `DrawIndirectArgs` of the particle draw. The instance count is reset before the display
+    /// pass and incremented for every particle written to the display buffer.
+    pub fn draw_args(&self) -> &wgpu::Buffer {
+        &self.draw_args_buffer
+    }
+
+    /// Particles kept by the last display pass as `ColoredVertex` instances, the sprite scale
+    /// is stored in the w of the position. Not sorted, see `display_fn`.
+    pub fn display_buffer(&self) -> &wgpu::Buffer {
+        &self.particle_display_buffer
+    }
+
+    /// Culls the particles against `camera` and writes the visible ones to the display buffer.
+    /// With `depth_sort` they are also sorted back-to-front into a second buffer.
+    pub fn display_fn(&self, camera: &Camera, aspect: f32, depth_sort: bool) -> GenericRequest {
+        self.culled_display_fn(self.config.culling, camera, aspect, depth_sort)
+    }
+
+    fn culled_display_fn(
+        &self,
+        culling: ParticleCulling,
+        camera: &Camera,
+        aspect: f32,
+        depth_sort: bool,
+    ) -> GenericRequest {
+        let display_density_task = self.display_density_task.clone();
+        let draw_args_buffer = self.draw_args_buffer.clone();
+        let uploader = self.uploader.clone();
+        let cull_buffer = self.cull_buffer.clone();
+        let cull = CullUniform::new(culling, self.config.anisotropy, camera, aspect);
+        let color_range_buffer = self.color_range_buffer.clone();
+        let color_range = self
+            .config
+            .color_range
+            .unwrap_or_else(|| self.config.color_mode.default_range())
+            .validated();
+        let upload_colormap = self.colormap_texture.upload_fn(self.config.colormap);
+        let sort_fn = self
+            .depth_sort
+            .as_ref()
+            .filter(|_| depth_sort)
+            .map(DepthSort::sort_fn);
+        let constants = DisplayConstants {
+            color_mode: self.config.color_mode.shader_id(),
+            selected_particle: self.selected_particle.unwrap_or(u32::MAX),
+            interpolation: self.interpolation,
+            first_particle: match self.config.show_ghost_particles {
+                true => 0,
+                false => self.ghost_particle_cnt as u32,
+            },
+        };
+
+        Box::new(move |encoder, queue| {
+            uploader.write(encoder, &cull_buffer, 0, bytemuck::bytes_of(&cull));
+            uploader.write(
+                encoder,
+                &color_range_buffer,
+                0,
+                bytemuck::bytes_of(&color_range),
+            );
+            upload_colormap(encoder, queue);
+            // the display pass appends the particles it keeps to the instance count
+            encoder.clear_buffer(&draw_args_buffer, 4, Some(4));
+            display_density_task.execute(encoder, bytemuck::bytes_of(&constants));
+            if let Some(sort_fn) = &sort_fn {
+                sort_fn(encoder, queue);
+            }
+        })
+    }
+
+    pub fn particle_material(&self) -> MaterialType {
+        if self.config.anisotropy.is_some() {
+            MaterialType::AnisotropicParticle
+        } else if self.config.translucent_particles {
+            MaterialType::TranslucentParticle
+        } else {
+            MaterialType::Particle
+        }
+    }
+
+    pub fn set_translucent_particles(&mut self, translucent: bool) {
+        self.config.translucent_particles = translucent;
+    }
+
+    /// The shapes are computed every frame while set, the ellipsoids ignore
+    /// `translucent_particles`.
+    pub fn set_anisotropy(&mut self, anisotropy: Option<AnisotropyConfig>) {
+        self.config.anisotropy = anisotropy;
+    }
+
+    pub fn set_show_ghost_particles(&mut self, show: bool) {
+        self.config.show_ghost_particles = show;
+    }
+
+    pub fn set_culling(&mut self, culling: ParticleCulling) {
+        self.config.culling = culling;
+    }
+
+    pub fn set_color_mode(&mut self, color_mode: ParticleColorMode) {
+        self.config.color_mode = color_mode;
+    }
+
+    pub fn set_colormap(&mut self, colormap: Colormap) {
+        self.config.colormap = colormap;
+    }
+
+    pub fn set_color_range(&mut self, color_range: Option<ColorRange>) {
+        self.config.color_range = color_range;
+    }
+
+    /// Number of particles per neighbor count, the last bin holds the particles with more
+    /// neighbors. Doesn't block, `None` until a newer histogram arrives. Only particles colored
+    /// by neighbor count keep the counts up to date.
+    pub fn neighbor_histogram(
+        &self,
+        device: &wgpu::Device,
+    ) -> Option<Result<Vec<u32>, SplooshError>> {
+        self.neighbor_count.poll_histogram(device)
+    }
+
+    /// Runs the diagnostics pass on the current state, read the result with
+    /// `read_diagnostics`.
+    pub fn diagnostics_fn(&self) -> GenericRequest {
+        self.diagnostics.update_fn()
+    }
+
+    /// Divergence and pressure of the fluid particles after the last `update`, `None` without
+    /// diagnostics enabled. Blocks until the GPU is done.
+    pub fn read_diagnostics(
+        &self,
+        device: &wgpu::Device,
+    ) -> Option<Result<Vec<ParticleDiagnostics>, SplooshError>> {
+        self.config
+            .diagnostics
+            .then(|| self.diagnostics.read(device))
+    }
+
+    /// Like `read_diagnostics` without blocking, `None` until newer diagnostics arrive.
+    pub fn poll_diagnostics(
+        &self,
+        device: &wgpu::Device,
+    ) -> Option<Result<Vec<ParticleDiagnostics>, SplooshError>> {
+        self.diagnostics.poll(device)
+    }
+
+    /// Samples the probes on the current state, read the result with `read_probes`.
+    pub fn probes_fn(&self) -> GenericRequest {
+        self.probes
+            .update_fn(&self.config.probes, self.time.clone())
+    }
+
+    /// A sample per probe of `config().probes` after the last `update`. Blocks until the GPU
+    /// is done.
+    pub fn read_probes(&self, device: &wgpu::Device) -> Result<Vec<ProbeSample>, SplooshError> {
+        self.probes.read(device, &self.config.probes)
+    }
+
+    /// Records the particles for playback if enough steps have passed since the last frame,
+    /// with the default settings if `config().playback` isn't set.
+    pub fn record_playback_fn(&self) -> GenericRequest {
+        self.playback.record_fn(
+            self.config.playback.unwrap_or_default(),
+            self.time.clone(),
+            self.step_cnt.clone(),
+        )
+    }
+
+    /// Blocks until the submitted recordings have finished and returns the newest frame.
+    pub fn read_playback(&self, device: &wgpu::Device) -> Result<PlaybackFrame, SplooshError> {
+        self.playback.read(device)
+    }
+
+    /// Like `read_playback` without blocking, `None` until a newer frame arrives.
+    pub fn poll_playback(
+        &self,
+        device: &wgpu::Device,
+    ) -> Option<Result<PlaybackFrame, SplooshError>> {
+        self.playback.poll(device)
+    }
+
+    pub fn playback(&self) -> &PlaybackRecorder {
+        &self.playback
+    }
+
+    /// Frames recorded before are kept, the history decides what to drop.
+    pub fn set_playback(&mut self, playback: Option<PlaybackConfig>) {
+        self.config.playback = playback;
+    }
+
+    /// Like `read_probes` without blocking, `None` until newer samples arrive.
+    pub fn poll_probes(
+        &self,
+        device: &wgpu::Device,
+    ) -> Option<Result<Vec<ProbeSample>, SplooshError>> {
+        self.probes.poll(device, &self.config.probes)
+    }
+
+    /// Probes after the first `MAX_PROBES` are kept in the config but not sampled.
+    pub fn set_probes(&mut self, probes: Vec<ProbeConfig>) {
+        self.config.probes = probes;
+    }
+
+    /// Measures the wave gauges on the current state, read the result with
+    /// `read_wave_gauges`.
+    pub fn wave_gauges_fn(&self) -> GenericRequest {
+        self.wave_gauges
+            .update_fn(&self.config.wave_gauges, self.time.clone())
+    }
+
+    /// A sample per wave gauge of `config().wave_gauges` after the last `update`. Blocks until
+    /// the GPU is done.
+    pub fn read_wave_gauges(
+        &self,
+        device: &wgpu::Device,
+    ) -> Result<Vec<WaveGaugeSample>, SplooshError> {
+        self.wave_gauges.read(device, self.config.wave_gauges.len())
+    }
+
+    /// Like `read_wave_gauges` without blocking, `None` until newer samples arrive.
+    pub fn poll_wave_gauges(
+        &self,
+        device: &wgpu::Device,
+    ) -> Option<Result<Vec<WaveGaugeSample>, SplooshError>> {
+        self.wave_gauges.poll(device, self.config.wave_gauges.len())
+    }
+
+    /// Gauges after the first `MAX_WAVE_GAUGES` are kept in the config but not measured.
+    pub fn set_wave_gauges(&mut self, wave_gauges: Vec<WaveGaugeConfig>) {
+        self.config.wave_gauges = wave_gauges;
+    }
+
+    pub fn set_free_surface_correction(&mut self, free_surface_correction: bool) {
+        self.config.free_surface_correction = free_surface_correction;
+    }
+
+    pub fn set_density_renormalization(
+        &mut self,
+        density_renormalization: Option<DensityRenormalization>,
+    ) {
+        self.config.density_renormalization = density_renormalization;
+    }
+
+    pub fn set_diagnostics(&mut self, diagnostics: bool) {
+        self.config.diagnostics = diagnostics;
+    }
+
+    /// Ray test against the fluid particles, read the result with `poll_pick`.
+    pub fn pick_fn(&self, origin: Point3<f32>, direction: Vector3<f32>) -> GenericRequest {
+        self.particle_inspector.pick_fn(origin, direction)
+    }
+
+    /// Result of the last submitted `pick_fn` once the GPU is done with it, the inner `None`
+    /// if no particle was hit.
+    pub fn poll_pick(&self, device: &wgpu::Device) -> Option<Result<Option<u32>, SplooshError>> {
+        self.particle_inspector.poll_pick(device)
+    }
+
+    pub fn selected_particle(&self) -> Option<u32> {
+        self.selected_particle
+    }
+
+    /// The selected particle is highlighted and sampled in every `update`.
+    pub fn select_particle(&mut self, particle: Option<u32>) {
+        self.selected_particle = particle;
+        self.particle_inspector.discard_samples();
+    }
+
+    /// Newest state of the selected particle sampled by `update`, `None` until a newer sample
+    /// arrives.
+    pub fn poll_selected_particle(
+        &self,
+        device: &wgpu::Device,
+    ) -> Option<Result<ParticleSample, SplooshError>> {
+        self.selected_particle
+            .and_then(|_| self.particle_inspector.poll_sample(device))
+    }
+
+    pub fn set_background_step_rate(&mut self, step_rate: Option<f32>) {
+        self.config.background_step_rate = step_rate;
+    }
+
+    pub fn set_velocity_lines(&mut self, velocity_lines: Option<VelocityLineConfig>) {
+        self.config.velocity_lines = velocity_lines;
+    }
+
+    pub fn set_density_slice(&mut self, density_slice: Option<DensitySliceConfig>) {
+        self.config.density_slice = density_slice;
+    }
+
+    /// Changing the trails starts them over from the current positions.
+    pub fn set_particle_trails(&mut self, particle_trails: Option<ParticleTrailConfig>) {
+        self.config.particle_trails = particle_trails;
+        self.particle_trails.reset();
+    }
+
+    pub fn set_minimap(&mut self, minimap: Option<MinimapConfig>) {
+        self.config.minimap = minimap;
+    }
+
+    /// Texture of the minimap, updated every frame while `config().minimap` is set.
+    pub fn minimap(&self) -> &Minimap {
+        &self.minimap
+    }
+
+    pub fn set_screen_density(&mut self, screen_density: Option<ScreenDensityConfig>) {
+        self.config.screen_density = screen_density;
+    }
+
+    /// Texture of the density heatmap, updated every frame while `config().screen_density`
+    /// is set.
+    pub fn screen_density(&self) -> &ScreenDensity {
+        &self.screen_density
+    }
+
+    /// Disabling the dye keeps the concentrations, they are only cleared by `clear_dye_fn`.
+    pub fn set_dye(&mut self, dye: Option<DyeConfig>) {
+        self.config.dye = dye;
+    }
+
+    /// Dyes the particles along a ray in world space, `None` without dye enabled.
+    pub fn paint_dye_fn(
+        &self,
+        origin: Point3<f32>,
+        direction: Vector3<f32>,
+    ) -> Option<GenericRequest> {
+        self.config
+            .dye
+            .map(|dye| self.dye.paint_fn(dye, origin, direction))
+    }
+
+    pub fn clear_dye_fn(&self) -> GenericRequest {
+        self.dye.clear_fn()
+    }
+
+    /// Disabling the debris stops spawning and hides the pieces, they keep their positions.
+    pub fn set_debris(&mut self, debris: Option<DebrisConfig>) {
+        self.config.debris = debris;
+    }
+
+    pub fn clear_debris_fn(&self) -> GenericRequest {
+        self.debris.clear_fn()
+    }
+
+    /// Switches to `config` through the setters if it only differs in the fields that can
+    /// change at runtime. Returns false without changing anything if it needs a rebuild.
+    pub fn apply_runtime_config(&mut self, config: &FluidSimulationConfig) -> bool {
+        if config.with_runtime_fields_of(&self.config) != self.config {
+            return false;
+        }
+
+        self.set_free_surface_correction(config.free_surface_correction);
+        self.set_density_renormalization(config.density_renormalization);
+        self.set_integrator(config.integrator);
+        self.set_culling(config.culling);
+        self.set_translucent_particles(config.translucent_particles);
+        self.set_anisotropy(config.anisotropy);
+        self.set_show_ghost_particles(config.show_ghost_particles);
+        self.set_velocity_lines(config.velocity_lines);
+        self.set_density_slice(config.density_slice);
+        if config.particle_trails != self.config.particle_trails {
+            self.set_particle_trails(config.particle_trails);
+        }
+        self.set_minimap(config.minimap);
+        self.set_screen_density(config.screen_density);
+        self.set_dye(config.dye);
+        self.set_debris(config.debris);
+        self.set_diagnostics(config.diagnostics);
+        self.set_probes(config.probes.clone());
+        self.set_wave_gauges(config.wave_gauges.clone());
+        self.set_playback(config.playback);
+        self.set_color_mode(config.color_mode);
+        self.set_colormap(config.colormap);
+        self.set_color_range(config.color_range);
+        self.set_background_step_rate(config.background_step_rate);
+
+        true
+    }
+
+    /// Encodes the step and the work on the simulation state into an encoder of its own and
+    /// submits it right away, so the GPU computes while the frame and the gui are prepared.
+    /// wgpu has a single queue, the overlap comes from the earlier submission. What turns the
+    /// state into the frame goes to the `PreRender` stage of `render_engine`.
+    pub fn update(
+        &self,
+        wgpu_device: &WgpuDevice,
+        render_engine: &mut RenderEngine,
+        camera: &Camera,
+        dt: f32,
+        simulation_paused: bool,
+    ) {
+        let mut requests = Vec::new();
         if !simulation_paused {
-            self.spatial_lookup.update(render_engine);
+            requests.push(self.step_fn(dt));
+        }
+        if self.config.color_mode == ParticleColorMode::NeighborCount
+            || self.selected_particle.is_some()
+        {
+            requests.push(self.neighbor_count.update_fn());
+        }
+        if self.config.diagnostics {
+            requests.push(self.diagnostics.update_fn());
+        }
+        if !self.config.probes.is_empty() {
+            requests.push(self.probes_fn());
+        }
+        if !self.config.wave_gauges.is_empty() {
+            requests.push(self.wave_gauges_fn());
+        }
+        if let Some(particle) = self.selected_particle {
+            requests.push(self.particle_inspector.sample_fn(particle));
+        }
+        if let Some(anisotropy) = self.config.anisotropy {
+            requests.push(self.anisotropy.update_fn(anisotropy));
+        }
+        if self.config.playback.is_some() {
+            requests.push(self.record_playback_fn());
+        }
+        if !requests.is_empty() {
+            let _span = tracing::debug_span!("simulation submit").entered();
+            let mut encoder =
+                wgpu_device
+                    .device
+                    .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                        label: Some("Simulation encoder"),
+                    });
+            render_engine.time_simulation(&mut encoder, |encoder| {
+                for request in &requests {
+                    request(encoder, &wgpu_device.queue);
+                }
+            });
+            wgpu_device.submit(encoder);
+        }
+
+        let material_type = self.particle_material();
+        let depth_sorted = render_engine.is_depth_sorted(material_type);
+        // culling only knows the main camera, the other viewports need every particle
+        let culling = if render_engine.viewport_cnt() > 1 {
+            ParticleCulling::DISABLED
+        } else {
+            self.config.culling
+        };
+        render_engine.submit_generic_request(
+            FrameStage::PreRender,
+            self.culled_display_fn(culling, camera, render_engine.aspect_ratio(), depth_sorted),
+        );
+
+        if let Some(velocity_lines) = self.config.velocity_lines {
+            render_engine.submit_generic_request(
+                FrameStage::PreRender,
+                self.velocity_lines.update_fn(velocity_lines),
+            );
+            render_engine.submit_render_request(RenderRequest {
+                material_type: MaterialType::Line,
+                geometry: self.velocity_lines.geometry(velocity_lines),
+            });
+        }
+
+        if let Some(soft_body) = &self.soft_body {
+            render_engine.submit_generic_request(FrameStage::PreRender, soft_body.update_fn());
+            render_engine.submit_render_request(RenderRequest {
+                material_type: MaterialType::Line,
+                geometry: soft_body.geometry(),
+            });
+        }
 
-            let compute_density_task = self.compute_density_task.clone();
-            render_engine.submit_generic_request(Box::new(move |encoder, _| {
-                compute_density_task.execute(encoder, &[]);
-            }));
+        if let Some(particle_trails) = self.config.particle_trails {
+            render_engine.submit_generic_request(
+                FrameStage::PreRender,
+                self.particle_trails.update_fn(particle_trails),
+            );
+            render_engine.submit_render_request(RenderRequest {
+                material_type: MaterialType::Line,
+                geometry: self.particle_trails.geometry(particle_trails),
+            });
+        }
 
-            let compute_force_task = self.compute_force_task.clone();
-            render_engine.submit_generic_request(Box::new(move |encoder, _| {
-                compute_force_task.execute(encoder, &[]);
-            }));
+        if let Some(minimap) = self.config.minimap {
+            render_engine
+                .submit_generic_request(FrameStage::PreRender, self.minimap.update_fn(minimap));
+        }
 
-            let update_particles_task = self.update_particle_task.clone();
-            render_engine.submit_generic_request(Box::new(move |encoder, _| {
-                update_particles_task.execute(encoder, bytemuck::bytes_of(&dt));
-            }));
+        if let Some(screen_density) = self.config.screen_density {
+            render_engine.submit_generic_request(
+                FrameStage::PreRender,
+                self.screen_density
+                    .update_fn(screen_density, camera, render_engine.aspect_ratio()),
+            );
+        }
 
-            let display_density_task = self.display_density_task.clone();
-            render_engine.submit_generic_request(Box::new(move |encoder, _| {
-                display_density_task.execute(encoder, &[]);
-            }));
+        if let Some(debris) = self.config.debris {
+            render_engine.submit_render_request(RenderRequest {
+                material_type: MaterialType::InstancedMesh,
+                geometry: self.debris.geometry(debris),
+            });
         }
 
+        let instance_buffer = match &self.depth_sort {
+            Some(depth_sort) if depth_sorted => depth_sort.sorted_display(),
+            _ => self.particle_display_buffer.clone(),
+        };
+        let geometry = match self.config.anisotropy {
+            Some(_) => Geometry::IndirectInstancedSplit {
+                instance_buffer,
+                extra_instance_buffer: self.anisotropy.display_shapes().clone(),
+                indirect_buffer: self.draw_args_buffer.clone(),
+            },
+            None => Geometry::IndirectInstanced {
+                instance_buffer,
+                indirect_buffer: self.draw_args_buffer.clone(),
+            },
+        };
         render_engine.submit_render_request(RenderRequest {
-            material_type: MaterialType::Line,
-            geometry: self.bbox_geometry.clone(),
+            material_type,
+            geometry,
         });
 
-        render_engine.submit_render_request(RenderRequest {
-            material_type: MaterialType::Particle,
-            geometry: Geometry::Instanced {
-                vertex_cnt: 4,
-                instance_buffer: self.particle_display_buffer.clone(),
-                instance_cnt: self.config.particle_cnt,
-            },
+        // blended over the particles, so it is drawn after them
+        if let Some(density_slice) = self.config.density_slice {
+            render_engine.submit_generic_request(
+                FrameStage::PreRender,
+                self.density_slice.update_fn(density_slice),
+            );
+            render_engine.submit_render_request(RenderRequest {
+                material_type: MaterialType::DensitySlice,
+                geometry: self.density_slice.geometry(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use pollster::FutureExt as _;
+
+    use crate::{
+        density_filter::RenormalizationMethod,
+        test_utils::{read_buffer, step},
+    };
+
+    use super::*;
+
+    fn copy_to_staging(wgpu_device: &WgpuDevice, buffer: &wgpu::Buffer) -> wgpu::Buffer {
+        let staging_buffer = wgpu_device.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging buffer"),
+            size: buffer.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
         });
+
+        let mut encoder = wgpu_device
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+        wgpu_device.submit(encoder);
+
+        staging_buffer
+    }
+
+    fn energy(wgpu_device: &WgpuDevice, fluid_sim: &FluidSimulation) -> f32 {
+        let positions = copy_to_staging(wgpu_device, fluid_sim.positions());
+        let velocities = copy_to_staging(wgpu_device, fluid_sim.velocities());
+        let positions = read_buffer::<Point4<f32>>(wgpu_device, &positions);
+        let velocities = read_buffer::<Vector4<f32>>(wgpu_device, &velocities);
+
+        let gravity = fluid_sim.config().gravity;
+        positions
+            .iter()
+            .zip(velocities.iter())
+            .skip(fluid_sim.ghost_particle_cnt())
+            .map(|(p, v)| 0.5 * v.xyz().norm_squared() - gravity.dot(&p.coords.xyz()))
+            .sum::<f32>()
+            / fluid_sim.fluid_particle_cnt() as f32
+    }
+
+    fn energy_drift(wgpu_device: &WgpuDevice, integrator: Integrator) -> f32 {
+        // pressure and viscosity are disabled, so the particles are in free fall
+        let config = FluidSimulationConfig {
+            particle_cnt: 512,
+            gas_const: 0.0,
+            viscosity: 0.0,
+            boundary: DomainBoundary::Open,
+            bbox_dimensions: Vector3::new(2.0, 2.0, 2.0),
+            integrator,
+            ..Default::default()
+        };
+        let fluid_sim = FluidSimulation::new(config, wgpu_device);
+
+        let start_energy = energy(wgpu_device, &fluid_sim);
+        step(wgpu_device, &fluid_sim, 0.01, 200);
+
+        (energy(wgpu_device, &fluid_sim) - start_energy).abs()
+    }
+
+    #[test]
+    fn integrator_energy_drift() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+
+        let euler_drift = energy_drift(&wgpu_device, Integrator::SymplecticEuler);
+        let leapfrog_drift = energy_drift(&wgpu_device, Integrator::Leapfrog);
+        let verlet_drift = energy_drift(&wgpu_device, Integrator::Verlet);
+
+        // symplectic Euler loses g^2 dt^2 / 2 per step in free fall, the second order
+        // integrators are exact up to rounding
+        assert!(euler_drift > 5e-3, "euler drift {euler_drift}");
+        assert!(leapfrog_drift < 1e-3, "leapfrog drift {leapfrog_drift}");
+        assert!(verlet_drift < 1e-3, "verlet drift {verlet_drift}");
+    }
+
+    #[test]
+    fn batched_steps_match_single_steps() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        // the paddle moves every step, so each step reads its own step uniform
+        let config = FluidSimulationConfig {
+            particle_cnt: 512,
+            wave_paddle: Some(WavePaddle {
+                amplitude: 0.5,
+                frequency: 2.0,
+            }),
+            integrator: Integrator::Verlet,
+            ..Default::default()
+        };
+        let fluid_sim = FluidSimulation::new(config, &wgpu_device);
+        let start = fluid_sim.read_snapshot(&wgpu_device).unwrap();
+
+        step(&wgpu_device, &fluid_sim, 0.01, 8);
+        let single = fluid_sim.read_snapshot(&wgpu_device).unwrap();
+
+        fluid_sim
+            .restore_snapshot(&wgpu_device.queue, &start)
+            .unwrap();
+        let mut encoder = wgpu_device
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for _ in 0..8 {
+            fluid_sim.step_fn(0.01)(&mut encoder, &wgpu_device.queue);
+        }
+        wgpu_device.submit(encoder);
+        let batched = fluid_sim.read_snapshot(&wgpu_device).unwrap();
+
+        assert_eq!(batched.step_cnt, single.step_cnt);
+        for (single, batched) in single.positions.iter().zip(&batched.positions) {
+            assert!(
+                (single - batched).norm() < 1e-4,
+                "{single:?} stepped alone, {batched:?} batched"
+            );
+        }
+    }
+
+    #[test]
+    fn pressure_pass_matches_inline_pressure() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let config = FluidSimulationConfig {
+            particle_cnt: 512,
+            ..Default::default()
+        };
+        let precomputed = FluidSimulation::new(config.clone(), &wgpu_device);
+        let inline = FluidSimulation::new(
+            FluidSimulationConfig {
+                pressure_pass: false,
+                ..config
+            },
+            &wgpu_device,
+        );
+        // the jitter of the start positions differs between simulations
+        inline
+            .restore_snapshot(
+                &wgpu_device.queue,
+                &precomputed.read_snapshot(&wgpu_device).unwrap(),
+            )
+            .unwrap();
+
+        for fluid_sim in [&precomputed, &inline] {
+            step(&wgpu_device, fluid_sim, 0.01, 8);
+        }
+
+        let precomputed = precomputed.read_snapshot(&wgpu_device).unwrap();
+        let inline = inline.read_snapshot(&wgpu_device).unwrap();
+        for (precomputed, inline) in precomputed.positions.iter().zip(&inline.positions) {
+            assert!(
+                (precomputed - inline).norm() < 1e-4,
+                "{precomputed:?} with the pressure pass, {inline:?} without"
+            );
+        }
+    }
+
+    /// Kinetic energy and the internal energy of the linear equation of state,
+    /// e = k (ln(ρ / ρ0) + ρ0 / ρ - 1), per fluid particle
+    fn energies(config: &FluidSimulationConfig, snapshot: &ParticleSnapshot) -> (f32, f32) {
+        let rest_density = config.rest_density;
+        let (kinetic, internal) = snapshot.velocities.iter().zip(&snapshot.densities).fold(
+            (0.0, 0.0),
+            |(kinetic, internal), (v, &density)| {
+                (
+                    kinetic + 0.5 * v.xyz().norm_squared(),
+                    internal
+                        + config.gas_const
+                            * ((density / rest_density).ln() + rest_density / density - 1.0),
+                )
+            },
+        );
+
+        let particle_cnt = snapshot.velocities.len() as f32;
+        (kinetic / particle_cnt, internal / particle_cnt)
+    }
+
+    fn pressure_energy_drift(wgpu_device: &WgpuDevice, integrator: Integrator) -> f32 {
+        // without gravity, viscosity and walls only the pressure forces act. The block starts at
+        // rest, packed to about twice the rest density, and its pressure energy turns into the
+        // kinetic energy of the expanding particles
+        let config = FluidSimulationConfig {
+            particle_cnt: 512,
+            rest_density: 100.0,
+            gas_const: 1.0,
+            viscosity: 0.0,
+            gravity: Vector3::zeros(),
+            boundary: DomainBoundary::Open,
+            bbox_dimensions: Vector3::new(2.0, 2.0, 2.0),
+            integrator,
+            ..Default::default()
+        };
+        let fluid_sim = FluidSimulation::new(config.clone(), wgpu_device);
+
+        // the densities are only known after the first step
+        step(wgpu_device, &fluid_sim, 0.005, 1);
+        let (start_kinetic, start_internal) =
+            energies(&config, &fluid_sim.read_snapshot(wgpu_device).unwrap());
+        step(wgpu_device, &fluid_sim, 0.005, 200);
+        let (end_kinetic, end_internal) =
+            energies(&config, &fluid_sim.read_snapshot(wgpu_device).unwrap());
+
+        let name = integrator.name();
+        assert!(start_internal > 0.05, "{name} starts with {start_internal}");
+        assert!(
+            end_kinetic > 0.1 * start_internal,
+            "{name} only reached a kinetic energy of {end_kinetic}"
+        );
+
+        (end_kinetic + end_internal - start_kinetic - start_internal).abs()
+    }
+
+    #[test]
+    fn pressure_forces_conserve_energy() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+
+        for integrator in Integrator::ALL {
+            let drift = pressure_energy_drift(&wgpu_device, integrator);
+            assert!(drift < 5e-3, "{} drift {drift}", integrator.name());
+        }
+    }
+
+    fn dam_break_densities(wgpu_device: &WgpuDevice, free_surface_correction: bool) -> Vec<f32> {
+        let config = FluidSimulationConfig {
+            particle_cnt: 4096,
+            dimensions: SimDim::Two,
+            initial_layout: InitialLayout::DamBreak,
+            free_surface_correction,
+            ..Default::default()
+        };
+        let fluid_sim = FluidSimulation::new(config, wgpu_device);
+        step(wgpu_device, &fluid_sim, 0.01, 100);
+
+        let snapshot = fluid_sim.read_snapshot(wgpu_device).unwrap();
+        snapshot.densities[fluid_sim.ghost_particle_cnt()..].to_vec()
+    }
+
+    #[test]
+    fn free_surface_correction_on_dam_break() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let rest_density = FluidSimulationConfig::default().rest_density;
+        let underestimated = |densities: &[f32]| {
+            densities
+                .iter()
+                .filter(|&&density| density < 0.8 * rest_density)
+                .count()
+        };
+
+        let uncorrected = underestimated(&dam_break_densities(&wgpu_device, false));
+        let corrected_densities = dam_break_densities(&wgpu_device, true);
+        let corrected = underestimated(&corrected_densities);
+
+        assert!(corrected_densities
+            .iter()
+            .all(|density| density.is_finite()));
+        assert!(
+            corrected < uncorrected,
+            "{corrected} corrected and {uncorrected} uncorrected particles underestimated"
+        );
+    }
+
+    #[test]
+    fn granular_material_from_toml() {
+        let config: FluidSimulationConfig = toml::from_str(
+            "material = { type = \"granular\", friction_angle = 30.0, cohesion = 2.0, shear_viscosity = 10.0 }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.material,
+            Material::Granular {
+                friction_angle: 30.0,
+                cohesion: 2.0,
+                shear_viscosity: 10.0
+            }
+        );
+    }
+
+    #[test]
+    fn non_newtonian_fluid_from_toml() {
+        let config: FluidSimulationConfig = toml::from_str(
+            "viscosity_model = { type = \"power_law\", flow_index = 0.5, max_viscosity = 20.0 }
+             elastic_springs = { stiffness = 10.0 }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.viscosity_model,
+            ViscosityModel::PowerLaw {
+                flow_index: 0.5,
+                max_viscosity: 20.0
+            }
+        );
+        assert_eq!(
+            config.elastic_springs,
+            Some(ElasticSprings {
+                stiffness: 10.0,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn density_renormalization_from_toml() {
+        let config: FluidSimulationConfig = toml::from_str(
+            "density_renormalization = { method = \"moving_least_squares\", interval = 10 }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.density_renormalization,
+            Some(DensityRenormalization {
+                method: RenormalizationMethod::MovingLeastSquares,
+                interval: 10
+            })
+        );
+    }
+
+    #[test]
+    fn neighbor_rebuild_interval_is_limited_by_the_skin() {
+        let config = FluidSimulationConfig {
+            smoothing_radius: 0.1,
+            gas_const: 100.0,
+            neighbor_caching: Some(NeighborCaching {
+                rebuild_interval: 8,
+                skin: 0.5,
+            }),
+            ..Default::default()
+        };
+        // crossing the skin of 0.05 m at 10 m/s takes a bit over 4 steps of 0.0012 s
+        assert_eq!(config.neighbor_rebuild_interval(0.0012), 4);
+        assert_eq!(config.neighbor_rebuild_interval(0.0001), 8);
+        assert_eq!(config.neighbor_rebuild_interval(0.1), 1);
+
+        let hashed = FluidSimulationConfig {
+            spatial_lookup: SpatialLookupBackend::HashTable,
+            ..config
+        };
+        assert_eq!(hashed.neighbor_skin(), 0.0);
+        assert_eq!(hashed.neighbor_rebuild_interval(0.0001), 1);
+    }
+
+    #[test]
+    fn memory_budget_caps_the_particle_count() {
+        let config = FluidSimulationConfig {
+            memory_budget_mib: Some(64),
+            soft_body: Some(SoftBodyConfig::default()),
+            ..Default::default()
+        };
+        let limits = wgpu::Limits::default();
+        let max_particle_cnt = config.max_particle_cnt(&limits).unwrap();
+
+        let allocated = |fluid_particle_cnt| {
+            config
+                .buffer_allocations(fluid_particle_cnt)
+                .iter()
+                .map(|(_, size)| size)
+                .sum::<u64>()
+        };
+        assert!(allocated(max_particle_cnt) <= 64 * 1024 * 1024);
+        assert!(allocated(max_particle_cnt + 1) > 64 * 1024 * 1024);
+
+        // two velocity line vertices per particle fill the 128 MiB binding first
+        let unbounded = FluidSimulationConfig::default()
+            .max_particle_cnt(&limits)
+            .unwrap();
+        assert_eq!(
+            unbounded + config.ghost_particle_cnt(),
+            128 * 1024 * 1024 / 64 - MAX_STREAMLINE_STEPS as usize
+        );
+
+        let starved = FluidSimulationConfig {
+            memory_budget_mib: Some(0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            starved.max_particle_cnt(&limits),
+            Err(SplooshError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn river_recycles_outflowing_particles() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+
+        let river = RiverFlow::default();
+        let config = FluidSimulationConfig {
+            particle_cnt: 4096,
+            dimensions: SimDim::Two,
+            initial_layout: InitialLayout::DamBreak,
+            river: Some(river),
+            ..Default::default()
+        };
+        let fluid_sim = FluidSimulation::new(config.clone(), &wgpu_device);
+        step(&wgpu_device, &fluid_sim, 0.01, 300);
+
+        let positions = copy_to_staging(&wgpu_device, fluid_sim.positions());
+        let velocities = copy_to_staging(&wgpu_device, fluid_sim.velocities());
+        let positions = read_buffer::<Point4<f32>>(&wgpu_device, &positions);
+        let velocities = read_buffer::<Vector4<f32>>(&wgpu_device, &velocities);
+        let fluid = fluid_sim.ghost_particle_cnt()..positions.len();
+
+        let outflow_x = config.bbox_dimensions.x - river.outflow_distance;
+        assert!(positions[fluid.clone()].iter().all(|p| p.x <= outflow_x));
+        let mean_velocity =
+            velocities[fluid.clone()].iter().map(|v| v.x).sum::<f32>() / fluid.len() as f32;
+        assert!(mean_velocity > 0.1, "mean velocity {mean_velocity}");
+    }
+
+    #[test]
+    fn water_is_physically_scaled() {
+        let domain_size = Vector3::new(2.0, 1.0, 0.5);
+        let config = FluidSimulationConfig::water(domain_size, 20);
+        let spacing = config.smoothing_radius * PARTICLE_SPACING;
+        assert!((spacing - 0.025).abs() < 1e-6);
+        assert!((config.mass / spacing.powi(3) - WATER_DENSITY).abs() < 1e-2);
+        assert!((config.speed_of_sound() - 10.0 * 9.81f32.sqrt()).abs() < 1e-3);
+
+        let (positions, ghost_particle_cnt) = FluidSimulation::particle_start_positions(
+            config.particle_cnt,
+            config.smoothing_radius,
+            config.simulation_bbox(),
+            config.initial_layout,
+            config.dimensions,
+            config.boundary,
+        );
+        assert_eq!(positions.len(), ghost_particle_cnt + config.particle_cnt);
+        assert_eq!(config.ghost_particle_cnt(), ghost_particle_cnt);
+
+        // the estimated count fills the column to half the box height
+        let top = positions[ghost_particle_cnt..]
+            .iter()
+            .map(|p| p.y)
+            .fold(0.0, f32::max);
+        assert!(
+            (top - domain_size.y / 2.0).abs() < 2.0 * spacing,
+            "top {top}"
+        );
+    }
+
+    #[test]
+    fn fluid_preset_from_toml() {
+        let config: FluidSimulationConfig = toml::from_str("preset = \"honey\"").unwrap();
+        assert_eq!(config.preset, Some(FluidPreset::Honey));
+
+        let mut config = config;
+        config.apply_fluid(FluidPreset::Honey.properties());
+        let spacing = config.smoothing_radius * PARTICLE_SPACING;
+        assert!((config.mass / spacing.powi(3) - 1420.0).abs() < 1e-1);
+        // the default resolution needs more viscosity than honey to stay stable
+        assert!(config.viscosity >= 10.0);
+        assert_eq!(config.surface_tension, 0.05);
+    }
+
+    #[test]
+    fn gpu_resources_are_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<WgpuDevice>();
+        assert_send_sync::<ComputeTask>();
+        assert_send_sync::<GenericRequest>();
+        assert_send_sync::<FluidSimulation>();
+        assert_send_sync::<RenderEngine>();
+    }
+
+    #[test]
+    fn snapshot_restores_particle_state() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+
+        let config = FluidSimulationConfig {
+            particle_cnt: 512,
+            ..Default::default()
+        };
+        let fluid_sim = FluidSimulation::new(config.clone(), &wgpu_device);
+        step(&wgpu_device, &fluid_sim, 0.01, 20);
+        let snapshot = fluid_sim.read_snapshot(&wgpu_device).unwrap();
+
+        let restored_sim = FluidSimulation::new(config, &wgpu_device);
+        restored_sim
+            .restore_snapshot(&wgpu_device.queue, &snapshot)
+            .unwrap();
+        let restored = restored_sim.read_snapshot(&wgpu_device).unwrap();
+
+        assert_eq!(restored.positions, snapshot.positions);
+        assert_eq!(restored.velocities, snapshot.velocities);
+        assert_eq!(restored.step_cnt, 20);
+        assert_eq!(restored.time, snapshot.time);
     }
 }
@@ -0,0 +1,112 @@
+use crate::{readback::Readback, wgpu_device::read_staging, SplooshError, WgpuDevice};
+
+/// Features needed to write timestamps between the passes of an encoder.
+pub const TIMESTAMP_FEATURES: wgpu::Features =
+    wgpu::Features::TIMESTAMP_QUERY.union(wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS);
+
+/// Measures the GPU time between timestamps written into a command encoder.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    staging_buffer: wgpu::Buffer,
+    capacity: u32,
+    /// Nanoseconds per timestamp tick
+    period: f64,
+}
+
+impl GpuTimer {
+    /// `None` if the device was created without timestamp support.
+    pub fn new(wgpu_device: &WgpuDevice, capacity: u32) -> Option<Self> {
+        if !wgpu_device.device.features().contains(TIMESTAMP_FEATURES) {
+            return None;
+        }
+
+        let device = &wgpu_device.device;
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("GPU timer query set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: capacity,
+        });
+
+        let size = capacity as u64 * std::mem::size_of::<u64>() as u64;
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU timer resolve buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("GPU timer staging buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            staging_buffer,
+            capacity,
+            period: wgpu_device.queue.get_timestamp_period() as f64,
+        })
+    }
+
+    pub fn write_timestamp(&self, encoder: &mut wgpu::CommandEncoder, index: u32) {
+        encoder.write_timestamp(&self.query_set, index);
+    }
+
+    /// Copies the first `count` timestamps to the CPU once the encoder is submitted.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder, count: u32) {
+        let count = count.min(self.capacity);
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.staging_buffer,
+            0,
+            count as u64 * std::mem::size_of::<u64>() as u64,
+        );
+    }
+
+    /// Same as `resolve`, but copies into a staging buffer of `readback`, so the timestamps can
+    /// be read without waiting for the GPU. Returns false if every staging buffer is in flight.
+    pub fn resolve_into(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        count: u32,
+        readback: &Readback<u64>,
+    ) -> bool {
+        let count = count.min(self.capacity);
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        readback.copy(encoder, |encoder, staging_buffer| {
+            encoder.copy_buffer_to_buffer(
+                &self.resolve_buffer,
+                0,
+                staging_buffer,
+                0,
+                count as u64 * std::mem::size_of::<u64>() as u64,
+            );
+        })
+    }
+
+    /// Milliseconds between each pair of consecutive timestamps.
+    pub fn intervals(&self, timestamps: &[u64]) -> Vec<f64> {
+        timestamps
+            .windows(2)
+            .map(|pair| pair[1].wrapping_sub(pair[0]) as f64 * self.period / 1e6)
+            .collect()
+    }
+
+    /// Blocks until the last resolve has finished and returns the milliseconds between each
+    /// pair of consecutive timestamps.
+    pub fn read_intervals(
+        &self,
+        device: &wgpu::Device,
+        count: u32,
+    ) -> Result<Vec<f64>, SplooshError> {
+        let timestamps: Vec<u64> = read_staging(device, &self.staging_buffer)?;
+        let count = (count.min(self.capacity) as usize).min(timestamps.len());
+
+        Ok(self.intervals(&timestamps[..count]))
+    }
+}
@@ -1,9 +1,15 @@
-pub mod render_engine;
-pub mod geometry;
+pub mod background;
 pub mod camera;
+pub mod capture;
+pub mod frame_graph;
+pub mod geometry;
 pub mod materials;
+pub mod post_process;
+pub mod render_engine;
+pub mod scene_objects;
 pub mod texture;
+pub mod uniform_buffer;
 
-pub use render_engine::RenderEngine;
 pub use camera::Camera;
-pub use texture::Texture;
\ No newline at end of file
+pub use render_engine::RenderEngine;
+pub use texture::Texture;
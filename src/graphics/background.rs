@@ -0,0 +1,273 @@
+use std::path::PathBuf;
+
+use image::RgbaImage;
+use nalgebra::Matrix4;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    materials::LineStyle, post_process::HDR_FORMAT, texture::Texture, uniform_buffer::UniformBuffer,
+};
+
+use crate::SplooshError;
+
+/// What is visible behind the particles. Colors are in sRGB.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Background {
+    Solid {
+        color: [f32; 3],
+    },
+    /// Blends from `bottom` to `top` with the height of the view direction
+    Gradient {
+        top: [f32; 3],
+        bottom: [f32; 3],
+    },
+    /// Equirectangular image around the scene
+    Environment {
+        path: PathBuf,
+    },
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Solid {
+            color: [0.0, 0.0, 0.0],
+        }
+    }
+}
+
+impl Background {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Background::Solid { .. } => "Solid",
+            Background::Gradient { .. } => "Gradient",
+            Background::Environment { .. } => "Environment map",
+        }
+    }
+
+    /// Color the frame is cleared to, `None` when the background has to be drawn.
+    pub fn clear_color(&self) -> Option<wgpu::Color> {
+        match self {
+            Background::Solid { color } => {
+                let [r, g, b] = color.map(|c| srgb_to_linear(c) as f64);
+                Some(wgpu::Color { r, g, b, a: 1.0 })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderSettings {
+    pub background: Background,
+    pub lines: LineStyle,
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct BackgroundUniform {
+    inverse_view_proj: [[f32; 4]; 4],
+    top: [f32; 4],
+    bottom: [f32; 4],
+    mode: u32,
+    _padding: [u32; 3],
+}
+
+/// Fullscreen pass drawing gradient and environment map backgrounds into the HDR texture.
+pub struct BackgroundPass {
+    uniform_buffer: UniformBuffer<BackgroundUniform>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl BackgroundPass {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Background shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!(
+                    "{}\n{}",
+                    include_str!("../shaders/fullscreen.wgsl"),
+                    include_str!("../shaders/background.wgsl")
+                )
+                .into(),
+            ),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Background bind group layout"),
+            entries: &[
+                UniformBuffer::<BackgroundUniform>::layout_entry(
+                    0,
+                    wgpu::ShaderStages::FRAGMENT,
+                    false,
+                ),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Background pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Background pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_fullscreen"),
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_background"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let uniform_buffer = UniformBuffer::new(device, "Background buffer");
+
+        let placeholder = Texture::from_image(device, queue, &RgbaImage::new(1, 1));
+        let bind_group = BackgroundPass::create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            &placeholder,
+        );
+
+        Self {
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+        }
+    }
+
+    /// Loads the environment map of `background`, if it has one.
+    pub fn set_background(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        background: &Background,
+    ) -> Result<(), SplooshError> {
+        if let Background::Environment { path } = background {
+            let image = image::open(path)?.to_rgba8();
+            let environment = Texture::from_image(device, queue, &image);
+            self.bind_group = BackgroundPass::create_bind_group(
+                device,
+                &self.bind_group_layout,
+                &self.uniform_buffer,
+                &environment,
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn write_uniform(
+        &self,
+        queue: &wgpu::Queue,
+        background: &Background,
+        view_proj: &Matrix4<f32>,
+    ) {
+        let (top, bottom, mode) = match background {
+            Background::Gradient { top, bottom } => (*top, *bottom, 0),
+            _ => ([0.0; 3], [0.0; 3], 1),
+        };
+        let linear = |[r, g, b]: [f32; 3]| [r, g, b, 1.0].map(srgb_to_linear);
+
+        let uniform = BackgroundUniform {
+            inverse_view_proj: view_proj.try_inverse().unwrap_or_default().into(),
+            top: linear(top),
+            bottom: linear(bottom),
+            mode,
+            _padding: [0; 3],
+        };
+        self.uniform_buffer.write(queue, &uniform);
+    }
+
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Background pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &UniformBuffer<BackgroundUniform>,
+        environment: &Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Background bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(environment.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(environment.sampler()),
+                },
+            ],
+        })
+    }
+}
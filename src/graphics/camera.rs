@@ -1,6 +1,13 @@
 use core::f32;
 
-use nalgebra::{Matrix4, Perspective3, Point3, Vector3};
+use nalgebra::{Matrix4, Orthographic3, Perspective3, Point3, Vector3};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Projection {
+    Perspective,
+    /// Shows the same extent as the perspective projection at the target distance
+    Orthographic,
+}
 
 pub struct Camera {
     pub position: Point3<f32>,
@@ -9,6 +16,13 @@ pub struct Camera {
     pub z_near: f32,
     pub z_far: f32,
     pub fov: f32,
+    pub projection: Projection,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Camera {
@@ -19,6 +33,7 @@ impl Camera {
             z_near: 0.01,
             z_far: 100.0,
             fov: std::f32::consts::FRAC_PI_4,
+            projection: Projection::Perspective,
         }
     }
 
@@ -28,6 +43,37 @@ impl Camera {
     }
 
     pub fn get_projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
-        Perspective3::new(aspect, self.fov, self.z_near, self.z_far).to_homogeneous()
+        match self.projection {
+            Projection::Perspective => {
+                Perspective3::new(aspect, self.fov, self.z_near, self.z_far).to_homogeneous()
+            }
+            Projection::Orthographic => {
+                let distance = (self.target - self.position).norm();
+                let half_height = distance * (self.fov / 2.0).tan();
+                let half_width = half_height * aspect;
+
+                Orthographic3::new(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.z_near,
+                    self.z_far,
+                )
+                .to_homogeneous()
+            }
+        }
+    }
+
+    /// World space ray through the point at normalized device coordinates `ndc`, starting on
+    /// the near plane.
+    pub fn view_ray(&self, ndc: (f32, f32), aspect: f32) -> (Point3<f32>, Vector3<f32>) {
+        let view_proj = self.get_projection_matrix(aspect) * self.get_view_matrix();
+        let inverse = view_proj.try_inverse().unwrap_or_default();
+
+        let near = inverse.transform_point(&Point3::new(ndc.0, ndc.1, -1.0));
+        let far = inverse.transform_point(&Point3::new(ndc.0, ndc.1, 1.0));
+
+        (near, (far - near).normalize())
     }
 }
@@ -0,0 +1,130 @@
+use image::RgbaImage;
+
+use crate::{readback::Readback, SplooshError, WgpuDevice};
+
+/// Copies rendered textures back to the CPU through a ring of staging buffers, with rows
+/// padded to `COPY_BYTES_PER_ROW_ALIGNMENT`. Like any `Readback`, a frame can be polled for
+/// without stalling, usually a frame or two after it was copied.
+pub struct FrameCapture {
+    readback: Readback<u8>,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    format: wgpu::TextureFormat,
+}
+
+impl FrameCapture {
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let unpadded_bytes_per_row = width * 4;
+        let padded_bytes_per_row = unpadded_bytes_per_row
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        Self {
+            readback: Readback::new(
+                wgpu_device,
+                "Frame capture buffer",
+                (padded_bytes_per_row * height) as u64,
+            ),
+            width,
+            height,
+            padded_bytes_per_row,
+            format,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Whether frames of `texture` can be copied into this capture.
+    pub fn matches(&self, texture: &wgpu::Texture) -> bool {
+        self.width == texture.width()
+            && self.height == texture.height()
+            && self.format == texture.format()
+    }
+
+    /// Records a copy of `texture`. Returns false without copying if all staging buffers are
+    /// still in flight.
+    pub fn copy_from_texture(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) -> bool {
+        self.readback.copy(encoder, |encoder, buffer| {
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(self.padded_bytes_per_row),
+                        rows_per_image: Some(self.height),
+                    },
+                },
+                wgpu::Extent3d {
+                    width: self.width,
+                    height: self.height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        })
+    }
+
+    /// The newest frame copied since the last call that has finished, without blocking.
+    pub fn poll(&self, device: &wgpu::Device) -> Option<Result<RgbaImage, SplooshError>> {
+        self.readback
+            .poll(device)
+            .map(|data| data.and_then(|data| self.to_image(data)))
+    }
+
+    /// Every frame that finished since the last call, oldest first, for captures that must not
+    /// drop frames.
+    pub fn poll_all(&self, device: &wgpu::Device) -> Vec<Result<RgbaImage, SplooshError>> {
+        self.readback
+            .poll_all(device)
+            .into_iter()
+            .map(|data| data.and_then(|data| self.to_image(data)))
+            .collect()
+    }
+
+    /// Blocks until the copies recorded so far have finished and returns the newest frame.
+    pub fn read(&self, device: &wgpu::Device) -> Result<RgbaImage, SplooshError> {
+        self.to_image(self.readback.wait(device)?)
+    }
+
+    fn to_image(&self, data: Vec<u8>) -> Result<RgbaImage, SplooshError> {
+        let row_len = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_len * self.height as usize);
+        for row in data.chunks(self.padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..row_len]);
+        }
+
+        if matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        RgbaImage::from_raw(self.width, self.height, pixels).ok_or_else(|| {
+            SplooshError::Capture("Captured frame has an unexpected size".to_string())
+        })
+    }
+}
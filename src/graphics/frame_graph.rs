@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+
+use crate::SplooshError;
+
+pub type PassFn<'a> =
+    Box<dyn FnOnce(&mut wgpu::CommandEncoder, &FrameResources) -> Result<(), SplooshError> + 'a>;
+
+/// Texture the frame graph allocates itself. It is kept between frames while the description
+/// stays the same.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransientTexture {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+#[derive(Default)]
+pub struct TransientTextures {
+    textures: HashMap<&'static str, (TransientTexture, wgpu::Texture, wgpu::TextureView)>,
+}
+
+impl TransientTextures {
+    fn allocate(&mut self, device: &wgpu::Device, name: &'static str, desc: TransientTexture) {
+        if matches!(self.textures.get(name), Some((cached, _, _)) if *cached == desc) {
+            return;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(name),
+            size: wgpu::Extent3d {
+                width: desc.width.max(1),
+                height: desc.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: desc.format,
+            usage: desc.usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        self.textures.insert(name, (desc, texture, view));
+    }
+}
+
+/// Textures and buffers a pass can look up by name while it is recorded.
+pub struct FrameResources<'a> {
+    textures: HashMap<&'static str, (&'a wgpu::Texture, &'a wgpu::TextureView)>,
+    buffers: HashMap<&'static str, &'a wgpu::Buffer>,
+}
+
+impl FrameResources<'_> {
+    pub fn texture(&self, name: &str) -> Result<&wgpu::Texture, SplooshError> {
+        Ok(self.texture_entry(name)?.0)
+    }
+
+    pub fn texture_view(&self, name: &str) -> Result<&wgpu::TextureView, SplooshError> {
+        Ok(self.texture_entry(name)?.1)
+    }
+
+    pub fn buffer(&self, name: &str) -> Result<&wgpu::Buffer, SplooshError> {
+        self.buffers
+            .get(name)
+            .copied()
+            .ok_or_else(|| SplooshError::FrameGraph(format!("Unknown frame graph buffer {name}")))
+    }
+
+    fn texture_entry(
+        &self,
+        name: &str,
+    ) -> Result<(&wgpu::Texture, &wgpu::TextureView), SplooshError> {
+        self.textures
+            .get(name)
+            .copied()
+            .ok_or_else(|| SplooshError::FrameGraph(format!("Unknown frame graph texture {name}")))
+    }
+}
+
+struct Pass<'a> {
+    name: &'static str,
+    reads: Vec<&'static str>,
+    writes: Vec<&'static str>,
+    run: PassFn<'a>,
+}
+
+/// Records the passes of a single frame. Passes declare the named resources they read and
+/// write and are sorted by them, the order they are added in only breaks ties:
+/// - a pass reading a name runs after the passes writing it, and a pass changing it in place,
+///   which reads and writes it, after the passes writing it without reading it
+/// - a name made with `alias` is the next version of another one, its writers run after every
+///   pass using the earlier versions and its readers see their writes as well
+///
+/// Passes whose writes never reach an output are dropped. Names that are neither imported
+/// nor transient only express dependencies, e.g. buffers written by the simulation.
+#[derive(Default)]
+pub struct FrameGraph<'a> {
+    passes: Vec<Pass<'a>>,
+    textures: HashMap<&'static str, (&'a wgpu::Texture, &'a wgpu::TextureView)>,
+    buffers: HashMap<&'static str, &'a wgpu::Buffer>,
+    transient_textures: Vec<(&'static str, TransientTexture)>,
+    /// New names and the names they are the next version of, in the order they were made
+    aliases: Vec<(&'static str, &'static str)>,
+    outputs: Vec<&'static str>,
+}
+
+impl<'a> FrameGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn import_texture(
+        &mut self,
+        name: &'static str,
+        texture: &'a wgpu::Texture,
+        view: &'a wgpu::TextureView,
+    ) {
+        self.textures.insert(name, (texture, view));
+    }
+
+    pub fn import_buffer(&mut self, name: &'static str, buffer: &'a wgpu::Buffer) {
+        self.buffers.insert(name, buffer);
+    }
+
+    pub fn create_texture(&mut self, name: &'static str, desc: TransientTexture) {
+        self.transient_textures.push((name, desc));
+    }
+
+    /// Makes `name` the next version of the resource behind `of`. Passes writing `name` run
+    /// after everything that uses `of`, e.g. the gui drawn into the frame after it was captured.
+    pub fn alias(&mut self, name: &'static str, of: &'static str) {
+        self.aliases.push((name, of));
+    }
+
+    /// Passes writing `name` are always executed.
+    pub fn mark_output(&mut self, name: &'static str) {
+        self.outputs.push(name);
+    }
+
+    pub fn add_pass<F>(
+        &mut self,
+        name: &'static str,
+        reads: &[&'static str],
+        writes: &[&'static str],
+        run: F,
+    ) where
+        F: FnOnce(&mut wgpu::CommandEncoder, &FrameResources) -> Result<(), SplooshError> + 'a,
+    {
+        self.passes.push(Pass {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            run: Box::new(run),
+        });
+    }
+
+    /// Names of the passes in execution order.
+    pub fn pass_order(&self) -> Result<Vec<&'static str>, SplooshError> {
+        Ok(self
+            .schedule()?
+            .into_iter()
+            .map(|pass| self.passes[pass].name)
+            .collect())
+    }
+
+    /// Records the passes into `encoder`. Fails without recording anything if the passes
+    /// depend on each other in a cycle, or stops at the first pass that fails.
+    pub fn execute(
+        self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        transient_pool: &mut TransientTextures,
+    ) -> Result<(), SplooshError> {
+        let order = self.schedule()?;
+
+        for (name, desc) in &self.transient_textures {
+            transient_pool.allocate(device, name, *desc);
+        }
+
+        let mut textures = self.textures;
+        for (name, _) in &self.transient_textures {
+            let (_, texture, view) = &transient_pool.textures[name];
+            textures.insert(name, (texture, view));
+        }
+        let mut buffers = self.buffers;
+        for &(name, of) in &self.aliases {
+            if let Some(&texture) = textures.get(of) {
+                textures.insert(name, texture);
+            }
+            if let Some(&buffer) = buffers.get(of) {
+                buffers.insert(name, buffer);
+            }
+        }
+        let resources = FrameResources { textures, buffers };
+
+        let mut passes: Vec<Option<Pass>> = self.passes.into_iter().map(Some).collect();
+        for pass in order {
+            let pass = passes[pass].take().unwrap();
+            let _span = tracing::trace_span!("frame_pass", name = pass.name).entered();
+            (pass.run)(encoder, &resources)?;
+        }
+
+        Ok(())
+    }
+
+    /// The versions of the resource behind `name` before it, newest first.
+    fn earlier_versions(&self, name: &str) -> Vec<&'static str> {
+        let mut versions = Vec::new();
+        let mut name = name;
+        while let Some(&(_, of)) = self.aliases.iter().find(|(alias, _)| *alias == name) {
+            if versions.contains(&of) {
+                break;
+            }
+            versions.push(of);
+            name = of;
+        }
+
+        versions
+    }
+
+    fn schedule(&self) -> Result<Vec<usize>, SplooshError> {
+        let pass_cnt = self.passes.len();
+
+        // inputs are the passes whose writes a pass reads, `after` adds the passes that only
+        // have to finish before it overwrites what they use
+        let mut inputs = vec![Vec::new(); pass_cnt];
+        let mut after = vec![Vec::new(); pass_cnt];
+        for (i, pass) in self.passes.iter().enumerate() {
+            for (j, other) in self.passes.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+
+                let is_input = pass.reads.iter().any(|name| {
+                    let in_place = pass.writes.contains(name) && other.reads.contains(name);
+                    (other.writes.contains(name) && !in_place)
+                        || self
+                            .earlier_versions(name)
+                            .iter()
+                            .any(|earlier| other.writes.contains(earlier))
+                });
+                let overwrites = pass.writes.iter().any(|name| {
+                    self.earlier_versions(name).iter().any(|earlier| {
+                        other.reads.contains(earlier) || other.writes.contains(earlier)
+                    })
+                });
+
+                if is_input {
+                    inputs[i].push(j);
+                }
+                if is_input || overwrites {
+                    after[i].push(j);
+                }
+            }
+        }
+
+        // keep the passes the outputs depend on
+        let mut needed = vec![false; pass_cnt];
+        let mut stack: Vec<usize> = (0..pass_cnt)
+            .filter(|&i| {
+                self.passes[i]
+                    .writes
+                    .iter()
+                    .any(|resource| self.outputs.contains(resource))
+            })
+            .collect();
+        while let Some(pass) = stack.pop() {
+            if !std::mem::replace(&mut needed[pass], true) {
+                stack.extend(&inputs[pass]);
+            }
+        }
+
+        // the earliest added pass whose dependencies are done runs next
+        let mut order = Vec::new();
+        let mut scheduled = vec![false; pass_cnt];
+        while order.len() < needed.iter().filter(|&&needed| needed).count() {
+            let ready = (0..pass_cnt).find(|&i| {
+                needed[i] && !scheduled[i] && after[i].iter().all(|&j| !needed[j] || scheduled[j])
+            });
+            let Some(pass) = ready else {
+                let blocked: Vec<&str> = (0..pass_cnt)
+                    .filter(|&i| needed[i] && !scheduled[i])
+                    .map(|i| self.passes[i].name)
+                    .collect();
+                return Err(SplooshError::FrameGraph(format!(
+                    "The frame graph passes {} depend on each other in a cycle",
+                    blocked.join(", ")
+                )));
+            };
+            scheduled[pass] = true;
+            order.push(pass);
+        }
+
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_without_outputs_are_culled() {
+        let mut graph = FrameGraph::new();
+        graph.add_pass("simulation", &[], &["particles"], |_, _| Ok(()));
+        graph.add_pass("blur", &["color"], &["blurred"], |_, _| Ok(()));
+        graph.add_pass("scene", &["particles"], &["color"], |_, _| Ok(()));
+        graph.add_pass("unused", &["particles"], &["histogram"], |_, _| Ok(()));
+        graph.add_pass("gui", &["color"], &["color"], |_, _| Ok(()));
+        graph.mark_output("color");
+
+        assert_eq!(graph.pass_order().unwrap(), ["simulation", "scene", "gui"]);
+    }
+
+    #[test]
+    fn passes_run_after_their_inputs() {
+        let mut graph = FrameGraph::new();
+        graph.alias("gui_color", "color");
+        graph.add_pass("screenshot", &["gui_color"], &["screenshot"], |_, _| Ok(()));
+        graph.add_pass("gui", &["color"], &["gui_color"], |_, _| Ok(()));
+        graph.add_pass("capture", &["color"], &["capture"], |_, _| Ok(()));
+        graph.add_pass("post_process", &["hdr"], &["color"], |_, _| Ok(()));
+        graph.add_pass("scene", &["hdr"], &["hdr"], |_, _| Ok(()));
+        graph.add_pass("background", &[], &["hdr"], |_, _| Ok(()));
+        graph.mark_output("gui_color");
+        graph.mark_output("capture");
+        graph.mark_output("screenshot");
+
+        assert_eq!(
+            graph.pass_order().unwrap(),
+            [
+                "background",
+                "scene",
+                "post_process",
+                "capture",
+                "gui",
+                "screenshot"
+            ]
+        );
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let mut graph = FrameGraph::new();
+        graph.add_pass("a", &["b"], &["a"], |_, _| Ok(()));
+        graph.add_pass("b", &["a"], &["b"], |_, _| Ok(()));
+        graph.mark_output("a");
+
+        assert!(matches!(
+            graph.pass_order(),
+            Err(SplooshError::FrameGraph(_))
+        ));
+    }
+}
@@ -1,14 +1,166 @@
-use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::WgpuDevice;
 
 #[derive(Clone)]
 pub enum Geometry {
     Array {
-        vertex_buffer: Rc<wgpu::Buffer>,
+        vertex_buffer: Arc<wgpu::Buffer>,
         vertex_cnt: usize,
     },
     Instanced {
         vertex_cnt: usize,
-        instance_buffer: Rc<wgpu::Buffer>,
-        instance_cnt: usize
+        instance_buffer: Arc<wgpu::Buffer>,
+        instance_cnt: usize,
+    },
+    /// Instanced draw whose vertex and instance counts are read from a `DrawIndirectArgs`
+    /// buffer, so compute passes can change them without a round trip to the CPU
+    IndirectInstanced {
+        instance_buffer: Arc<wgpu::Buffer>,
+        indirect_buffer: Arc<wgpu::Buffer>,
+    },
+    /// Like `IndirectInstanced` with a second instance buffer in the same order, for per
+    /// instance data only some materials read, e.g. the particle shapes
+    IndirectInstancedSplit {
+        instance_buffer: Arc<wgpu::Buffer>,
+        extra_instance_buffer: Arc<wgpu::Buffer>,
+        indirect_buffer: Arc<wgpu::Buffer>,
+    },
+    /// Vertex array drawn with an extra bind group for the material, e.g. its textures
+    Textured {
+        vertex_buffer: Arc<wgpu::Buffer>,
+        vertex_cnt: usize,
+        bind_group: Arc<wgpu::BindGroup>,
+    },
+    /// Indexed mesh drawn once per `MeshInstance` in the instance buffer
+    InstancedMesh {
+        mesh: Arc<Mesh>,
+        instance_buffer: Arc<wgpu::Buffer>,
+        instance_cnt: usize,
+    },
+}
+
+/// Indexed triangle list of `MeshVertex`, drawn once per instance by the instanced mesh
+/// material.
+pub struct Mesh {
+    pub vertex_buffer: Arc<wgpu::Buffer>,
+    pub index_buffer: Arc<wgpu::Buffer>,
+    pub index_cnt: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+}
+
+impl Mesh {
+    pub fn new(wgpu_device: &WgpuDevice, vertices: &[MeshVertex], indices: &[u32]) -> Self {
+        Self {
+            vertex_buffer: wgpu_device.create_buffer_init(vertices, wgpu::BufferUsages::VERTEX),
+            index_buffer: wgpu_device.create_buffer_init(indices, wgpu::BufferUsages::INDEX),
+            index_cnt: indices.len() as u32,
+        }
+    }
+
+    /// Sphere of radius one around the origin.
+    pub fn sphere(wgpu_device: &WgpuDevice, rings: u32, segments: u32) -> Self {
+        let (vertices, indices) = sphere_data(rings, segments);
+        Mesh::new(wgpu_device, &vertices, &indices)
+    }
+
+    /// Cube from -1 to 1 with flat faces.
+    pub fn cube(wgpu_device: &WgpuDevice) -> Self {
+        let (vertices, indices) = cube_data();
+        Mesh::new(wgpu_device, &vertices, &indices)
+    }
+}
+
+/// Latitude rings from pole to pole, every ring has `segments + 1` vertices so the seam can be
+/// closed without wrapping the indices.
+fn sphere_data(rings: u32, segments: u32) -> (Vec<MeshVertex>, Vec<u32>) {
+    let (rings, segments) = (rings.max(2), segments.max(3));
+
+    let mut vertices = Vec::new();
+    for ring in 0..=rings {
+        let theta = std::f32::consts::PI * ring as f32 / rings as f32;
+        for segment in 0..=segments {
+            let phi = std::f32::consts::TAU * segment as f32 / segments as f32;
+            let position = [
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            ];
+            vertices.push(MeshVertex {
+                position,
+                normal: position,
+            });
+        }
+    }
+
+    let mut indices = Vec::new();
+    let row = segments + 1;
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let top = ring * row + segment;
+            let bottom = top + row;
+            indices.extend([top, top + 1, bottom, top + 1, bottom + 1, bottom]);
+        }
+    }
+
+    (vertices, indices)
+}
+
+fn cube_data() -> (Vec<MeshVertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    for axis in 0..3 {
+        for sign in [-1.0f32, 1.0] {
+            let mut normal = [0.0; 3];
+            normal[axis] = sign;
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+            let first = vertices.len() as u32;
+            for (a, b) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let mut position = normal;
+                position[u] = a;
+                position[v] = b;
+                vertices.push(MeshVertex { position, normal });
+            }
+
+            // counter clockwise seen from outside
+            if sign > 0.0 {
+                indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+            } else {
+                indices.extend([first, first + 2, first + 1, first, first + 3, first + 2]);
+            }
+        }
+    }
+
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sphere_triangles_face_outwards() {
+        let (vertices, indices) = sphere_data(8, 12);
+        assert!(indices
+            .iter()
+            .all(|&index| (index as usize) < vertices.len()));
+
+        for triangle in indices.chunks(3) {
+            let [a, b, c] =
+                [0, 1, 2].map(|i| nalgebra::Vector3::from(vertices[triangle[i] as usize].position));
+            let normal = (b - a).cross(&(c - a));
+            // the triangles at the poles have two vertices in the same place
+            if normal.norm() > 1e-6 {
+                assert!(normal.dot(&(a + b + c)) > 0.0);
+            }
+        }
     }
 }
@@ -1,6 +1,20 @@
-use crate::WgpuRenderDevice;
+use std::collections::HashMap;
 
-pub trait Material {
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    anisotropy::ParticleShape,
+    colormap::{ColormapTexture, COLORMAP_SHADER},
+    WgpuRenderDevice,
+};
+
+use super::{
+    geometry::{Mesh, MeshVertex},
+    post_process::HDR_FORMAT,
+    uniform_buffer::UniformBuffer,
+};
+
+pub trait Material: Send + Sync {
     fn material_type(&self) -> MaterialType;
     fn bind_pipeline(&self, render_pass: &mut wgpu::RenderPass);
     fn draw_geometry_array(
@@ -16,16 +30,62 @@ pub trait Material {
         instance_cnt: usize,
         render_pass: &mut wgpu::RenderPass,
     );
+    fn draw_indirect_instanced(
+        &self,
+        instance_buffer: &wgpu::Buffer,
+        indirect_buffer: &wgpu::Buffer,
+        render_pass: &mut wgpu::RenderPass,
+    );
+    fn draw_indirect_instanced_split(
+        &self,
+        instance_buffer: &wgpu::Buffer,
+        extra_instance_buffer: &wgpu::Buffer,
+        indirect_buffer: &wgpu::Buffer,
+        render_pass: &mut wgpu::RenderPass,
+    );
+    fn draw_textured(
+        &self,
+        vertex_buffer: &wgpu::Buffer,
+        vertex_cnt: usize,
+        bind_group: &wgpu::BindGroup,
+        render_pass: &mut wgpu::RenderPass,
+    );
+    fn draw_mesh_instanced(
+        &self,
+        mesh: &Mesh,
+        instance_buffer: &wgpu::Buffer,
+        instance_cnt: usize,
+        render_pass: &mut wgpu::RenderPass,
+    );
+
+    /// Instances drawn with a blending material have to be sorted back-to-front.
+    fn depth_sorted(&self) -> bool {
+        false
+    }
 }
 
-#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum MaterialType {
+    /// Line list of `ColoredVertex`
     Line,
+    /// Line list of `ColoredVertex` drawn with the dash pattern of the `LineStyle`
+    DashedLine,
     Particle,
+    TranslucentParticle,
+    /// Particles drawn as ellipsoids, the extra instance buffer of the split geometry holds a
+    /// `ParticleShape` per particle
+    AnisotropicParticle,
+    /// Color mapped density slice, vertices are `TexturedVertex` and the bind group follows
+    /// `DENSITY_SLICE_LAYOUT_ENTRIES`
+    DensitySlice,
+    /// Indexed `Mesh` drawn once per `MeshInstance`
+    InstancedMesh,
 }
 
 pub struct LineMaterial {
     pipeline: wgpu::RenderPipeline,
+    style_bind_group: wgpu::BindGroup,
+    dashed: bool,
 }
 
 #[repr(C)]
@@ -35,10 +95,90 @@ pub struct ColoredVertex {
     color: nalgebra::Vector4<f32>,
 }
 
+impl ColoredVertex {
+    pub fn new(position: nalgebra::Point3<f32>, color: nalgebra::Vector4<f32>) -> Self {
+        Self {
+            position,
+            _padding: 0.0,
+            color,
+        }
+    }
+}
+
+/// Width and dash pattern of every line, in points so they keep their size on high DPI
+/// screens.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LineStyle {
+    pub width: f32,
+    pub dash_length: f32,
+    pub gap_length: f32,
+}
+
+impl Default for LineStyle {
+    fn default() -> Self {
+        Self {
+            width: 1.5,
+            dash_length: 6.0,
+            gap_length: 4.0,
+        }
+    }
+}
+
+/// Matches `LineStyle` in the line shader.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineStyleUniform {
+    width: f32,
+    dash_length: f32,
+    gap_length: f32,
+    _padding: f32,
+}
+
+impl From<&LineStyle> for LineStyleUniform {
+    fn from(style: &LineStyle) -> Self {
+        Self {
+            width: style.width,
+            dash_length: style.dash_length,
+            gap_length: style.gap_length,
+            _padding: 0.0,
+        }
+    }
+}
+
 impl LineMaterial {
+    // every segment is an instance reading both ColoredVertex ends of the line list, the color
+    // of a vertex starts after the position padding
+    const SEGMENT_ATTRIBUTES: [wgpu::VertexAttribute; 4] = [
+        wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32x3,
+            offset: 0,
+            shader_location: 0,
+        },
+        wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32x4,
+            offset: 16,
+            shader_location: 1,
+        },
+        wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32x3,
+            offset: 32,
+            shader_location: 2,
+        },
+        wgpu::VertexAttribute {
+            format: wgpu::VertexFormat::Float32x4,
+            offset: 48,
+            shader_location: 3,
+        },
+    ];
+
+    /// Lines are line lists of `ColoredVertex`, drawn as quads `LineStyle::width` wide. Dashed
+    /// lines leave gaps after every `dash_length` of the style.
     pub fn new(
         render_device: &WgpuRenderDevice,
         model_view_bind_group_layout: &wgpu::BindGroupLayout,
+        style_buffer: &UniformBuffer<LineStyleUniform>,
+        dashed: bool,
     ) -> Self {
         let shader = render_device
             .device()
@@ -49,15 +189,49 @@ impl LineMaterial {
                 ),
             });
 
+        let style_bind_group_layout =
+            render_device
+                .device()
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Line style bind group layout"),
+                    entries: &[UniformBuffer::<LineStyleUniform>::layout_entry(
+                        0,
+                        wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        false,
+                    )],
+                });
+        let style_bind_group =
+            render_device
+                .device()
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Line style bind group"),
+                    layout: &style_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: style_buffer.binding(),
+                    }],
+                });
+
         let render_pipeline_layout =
             render_device
                 .device()
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                     label: Some("Line render pipeline layout"),
-                    bind_group_layouts: &[&model_view_bind_group_layout],
+                    bind_group_layouts: &[model_view_bind_group_layout, &style_bind_group_layout],
                     push_constant_ranges: &[],
                 });
 
+        let segment_layout = wgpu::VertexBufferLayout {
+            array_stride: 2 * std::mem::size_of::<ColoredVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::SEGMENT_ATTRIBUTES,
+        };
+        let constants = HashMap::from([("DASHED".to_string(), f64::from(dashed as u8))]);
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            constants: &constants,
+            ..Default::default()
+        };
+
         let pipeline =
             render_device
                 .device()
@@ -67,29 +241,25 @@ impl LineMaterial {
                     vertex: wgpu::VertexState {
                         module: &shader,
                         entry_point: Some("vs_main"),
-                        buffers: &[wgpu::VertexBufferLayout {
-                            array_stride: std::mem::size_of::<nalgebra::Vector3<f32>>()
-                                as wgpu::BufferAddress,
-                            step_mode: wgpu::VertexStepMode::Vertex,
-                            attributes: &wgpu::vertex_attr_array![0 => Float32x3],
-                        }],
-                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        buffers: &[segment_layout],
+                        compilation_options: compilation_options.clone(),
                     },
                     fragment: Some(wgpu::FragmentState {
                         module: &shader,
                         entry_point: Some("fs_main"),
                         targets: &[Some(wgpu::ColorTargetState {
-                            format: render_device.config.format,
+                            format: HDR_FORMAT,
                             blend: Some(wgpu::BlendState::REPLACE),
                             write_mask: wgpu::ColorWrites::ALL,
                         })],
-                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        compilation_options,
                     }),
                     primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::LineList,
+                        topology: wgpu::PrimitiveTopology::TriangleList,
                         strip_index_format: None,
                         front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: Some(wgpu::Face::Back),
+                        // the winding of a quad depends on the direction of its segment
+                        cull_mode: None,
                         polygon_mode: wgpu::PolygonMode::Fill,
                         unclipped_depth: false,
                         conservative: false,
@@ -110,17 +280,26 @@ impl LineMaterial {
                     cache: None,
                 });
 
-        Self { pipeline }
+        Self {
+            pipeline,
+            style_bind_group,
+            dashed,
+        }
     }
 }
 
 impl Material for LineMaterial {
     fn material_type(&self) -> MaterialType {
-        MaterialType::Line
+        if self.dashed {
+            MaterialType::DashedLine
+        } else {
+            MaterialType::Line
+        }
     }
 
     fn bind_pipeline(&self, render_pass: &mut wgpu::RenderPass) {
         render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(1, &self.style_bind_group, &[]);
     }
 
     fn draw_geometry_array(
@@ -129,8 +308,9 @@ impl Material for LineMaterial {
         vertex_cnt: usize,
         render_pass: &mut wgpu::RenderPass,
     ) {
+        // six vertices for the quad of every segment
         render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
-        render_pass.draw(0..vertex_cnt as u32, 0..1);
+        render_pass.draw(0..6, 0..(vertex_cnt / 2) as u32);
     }
 
     fn draw_instanced(
@@ -142,16 +322,58 @@ impl Material for LineMaterial {
     ) {
         panic!("Instanced rendering is not currently supported for the line pipeline");
     }
+
+    fn draw_indirect_instanced(
+        &self,
+        _instance_buffer: &wgpu::Buffer,
+        _indirect_buffer: &wgpu::Buffer,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Instanced rendering is not currently supported for the line pipeline");
+    }
+
+    fn draw_indirect_instanced_split(
+        &self,
+        _instance_buffer: &wgpu::Buffer,
+        _extra_instance_buffer: &wgpu::Buffer,
+        _indirect_buffer: &wgpu::Buffer,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Instanced rendering is not currently supported for the line pipeline");
+    }
+
+    fn draw_textured(
+        &self,
+        _vertex_buffer: &wgpu::Buffer,
+        _vertex_cnt: usize,
+        _bind_group: &wgpu::BindGroup,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Textured rendering is not supported for the line pipeline");
+    }
+
+    fn draw_mesh_instanced(
+        &self,
+        _mesh: &Mesh,
+        _instance_buffer: &wgpu::Buffer,
+        _instance_cnt: usize,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Meshes are not supported for the line pipeline");
+    }
 }
 
 pub struct ParticleMaterial {
     pipeline: wgpu::RenderPipeline,
+    translucent: bool,
 }
 
 impl ParticleMaterial {
+    /// Translucent particles are alpha blended and don't write depth.
     pub fn new(
         render_device: &WgpuRenderDevice,
         model_view_bind_group_layout: &wgpu::BindGroupLayout,
+        translucent: bool,
     ) -> Self {
         let shader = render_device
             .device()
@@ -167,7 +389,7 @@ impl ParticleMaterial {
                 .device()
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                     label: Some("Particle render pipeline layout"),
-                    bind_group_layouts: &[&model_view_bind_group_layout],
+                    bind_group_layouts: &[model_view_bind_group_layout],
                     push_constant_ranges: &[],
                 });
 
@@ -190,10 +412,18 @@ impl ParticleMaterial {
                     },
                     fragment: Some(wgpu::FragmentState {
                         module: &shader,
-                        entry_point: Some("fs_main"),
+                        entry_point: Some(if translucent {
+                            "fs_translucent"
+                        } else {
+                            "fs_main"
+                        }),
                         targets: &[Some(wgpu::ColorTargetState {
-                            format: render_device.config.format,
-                            blend: Some(wgpu::BlendState::REPLACE),
+                            format: HDR_FORMAT,
+                            blend: Some(if translucent {
+                                wgpu::BlendState::ALPHA_BLENDING
+                            } else {
+                                wgpu::BlendState::REPLACE
+                            }),
                             write_mask: wgpu::ColorWrites::ALL,
                         })],
                         compilation_options: wgpu::PipelineCompilationOptions::default(),
@@ -209,7 +439,7 @@ impl ParticleMaterial {
                     },
                     depth_stencil: Some(wgpu::DepthStencilState {
                         format: render_device.depth_texture.format(),
-                        depth_write_enabled: true,
+                        depth_write_enabled: !translucent,
                         depth_compare: wgpu::CompareFunction::Less,
                         stencil: wgpu::StencilState::default(),
                         bias: wgpu::DepthBiasState::default(),
@@ -223,13 +453,24 @@ impl ParticleMaterial {
                     cache: None,
                 });
 
-        Self { pipeline }
+        Self {
+            pipeline,
+            translucent,
+        }
     }
 }
 
 impl Material for ParticleMaterial {
     fn material_type(&self) -> MaterialType {
-        MaterialType::Particle
+        if self.translucent {
+            MaterialType::TranslucentParticle
+        } else {
+            MaterialType::Particle
+        }
+    }
+
+    fn depth_sorted(&self) -> bool {
+        self.translucent
     }
 
     fn bind_pipeline(&self, render_pass: &mut wgpu::RenderPass) {
@@ -255,4 +496,595 @@ impl Material for ParticleMaterial {
         render_pass.set_vertex_buffer(0, instance_buffer.slice(..));
         render_pass.draw(0..vertex_cnt as u32, 0..instance_cnt as u32);
     }
+
+    fn draw_indirect_instanced(
+        &self,
+        instance_buffer: &wgpu::Buffer,
+        indirect_buffer: &wgpu::Buffer,
+        render_pass: &mut wgpu::RenderPass,
+    ) {
+        render_pass.set_vertex_buffer(0, instance_buffer.slice(..));
+        render_pass.draw_indirect(indirect_buffer, 0);
+    }
+
+    /// The spheres ignore the extra instance data.
+    fn draw_indirect_instanced_split(
+        &self,
+        instance_buffer: &wgpu::Buffer,
+        _extra_instance_buffer: &wgpu::Buffer,
+        indirect_buffer: &wgpu::Buffer,
+        render_pass: &mut wgpu::RenderPass,
+    ) {
+        self.draw_indirect_instanced(instance_buffer, indirect_buffer, render_pass);
+    }
+
+    fn draw_textured(
+        &self,
+        _vertex_buffer: &wgpu::Buffer,
+        _vertex_cnt: usize,
+        _bind_group: &wgpu::BindGroup,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Textured rendering is not supported for the particle pipeline");
+    }
+
+    fn draw_mesh_instanced(
+        &self,
+        _mesh: &Mesh,
+        _instance_buffer: &wgpu::Buffer,
+        _instance_cnt: usize,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Meshes are not supported for the particle pipeline");
+    }
+}
+
+/// Opaque ellipsoid impostors, every particle is a camera facing quad around its ellipsoid
+/// and the fragments intersect the view ray with it.
+pub struct AnisotropicParticleMaterial {
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl AnisotropicParticleMaterial {
+    pub fn new(
+        render_device: &WgpuRenderDevice,
+        model_view_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = render_device
+            .device()
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Anisotropic particle shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    include_str!("../shaders/anisotropic_particle.wgsl").into(),
+                ),
+            });
+
+        let render_pipeline_layout =
+            render_device
+                .device()
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Anisotropic particle render pipeline layout"),
+                    bind_group_layouts: &[model_view_bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+        let pipeline = render_device.device().create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label: Some("Anisotropic particle render pipeline"),
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[
+                        wgpu::VertexBufferLayout {
+                            array_stride: std::mem::size_of::<ColoredVertex>()
+                                as wgpu::BufferAddress,
+                            step_mode: wgpu::VertexStepMode::Instance,
+                            attributes: &wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4],
+                        },
+                        wgpu::VertexBufferLayout {
+                            array_stride: std::mem::size_of::<ParticleShape>()
+                                as wgpu::BufferAddress,
+                            step_mode: wgpu::VertexStepMode::Instance,
+                            attributes: &wgpu::vertex_attr_array![2 => Float32x4, 3 => Float32x4],
+                        },
+                    ],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: HDR_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    unclipped_depth: false,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: render_device.depth_texture.format(),
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+                cache: None,
+            },
+        );
+
+        Self { pipeline }
+    }
+}
+
+impl Material for AnisotropicParticleMaterial {
+    fn material_type(&self) -> MaterialType {
+        MaterialType::AnisotropicParticle
+    }
+
+    fn bind_pipeline(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_pipeline(&self.pipeline);
+    }
+
+    fn draw_geometry_array(
+        &self,
+        _vertex_buffer: &wgpu::Buffer,
+        _vertex_cnt: usize,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("The anisotropic particle pipeline needs the particle shapes");
+    }
+
+    fn draw_instanced(
+        &self,
+        _vertex_cnt: usize,
+        _instance_buffer: &wgpu::Buffer,
+        _instance_cnt: usize,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("The anisotropic particle pipeline needs the particle shapes");
+    }
+
+    fn draw_indirect_instanced(
+        &self,
+        _instance_buffer: &wgpu::Buffer,
+        _indirect_buffer: &wgpu::Buffer,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("The anisotropic particle pipeline needs the particle shapes");
+    }
+
+    fn draw_indirect_instanced_split(
+        &self,
+        instance_buffer: &wgpu::Buffer,
+        extra_instance_buffer: &wgpu::Buffer,
+        indirect_buffer: &wgpu::Buffer,
+        render_pass: &mut wgpu::RenderPass,
+    ) {
+        render_pass.set_vertex_buffer(0, instance_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, extra_instance_buffer.slice(..));
+        render_pass.draw_indirect(indirect_buffer, 0);
+    }
+
+    fn draw_textured(
+        &self,
+        _vertex_buffer: &wgpu::Buffer,
+        _vertex_cnt: usize,
+        _bind_group: &wgpu::BindGroup,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Textured rendering is not supported for the anisotropic particle pipeline");
+    }
+
+    fn draw_mesh_instanced(
+        &self,
+        _mesh: &Mesh,
+        _instance_buffer: &wgpu::Buffer,
+        _instance_cnt: usize,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Meshes are not supported for the anisotropic particle pipeline");
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TexturedVertex {
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+/// Slice texture with the raw densities in the red channel, its sampler, the `SliceUniform`
+/// holding the color map range and the colormap texture.
+pub const DENSITY_SLICE_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 4] = [
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    ColormapTexture::layout_entry(3, wgpu::ShaderStages::FRAGMENT),
+];
+
+/// Alpha blended, double sided quad showing a density slice, regions without fluid are drawn
+/// faintly.
+pub struct DensitySliceMaterial {
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl DensitySliceMaterial {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+
+    pub fn new(
+        render_device: &WgpuRenderDevice,
+        model_view_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = render_device
+            .device()
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Density slice shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    format!(
+                        "{}{}",
+                        COLORMAP_SHADER,
+                        include_str!("../shaders/density_slice_material.wgsl")
+                    )
+                    .into(),
+                ),
+            });
+
+        // shared with the bind group of `DensitySlice`
+        let slice_bind_group_layout = render_device
+            .wgpu_device
+            .bind_group_layout(&DENSITY_SLICE_LAYOUT_ENTRIES);
+
+        let render_pipeline_layout =
+            render_device
+                .device()
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Density slice render pipeline layout"),
+                    bind_group_layouts: &[model_view_bind_group_layout, &slice_bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+        let pipeline =
+            render_device
+                .device()
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Density slice render pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: Some("vs_main"),
+                        buffers: &[wgpu::VertexBufferLayout {
+                            array_stride: std::mem::size_of::<TexturedVertex>()
+                                as wgpu::BufferAddress,
+                            step_mode: wgpu::VertexStepMode::Vertex,
+                            attributes: &Self::ATTRIBUTES,
+                        }],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: render_device.depth_texture.format(),
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::Less,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState {
+                        count: 1,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                    multiview: None,
+                    cache: None,
+                });
+
+        Self { pipeline }
+    }
+}
+
+impl Material for DensitySliceMaterial {
+    fn material_type(&self) -> MaterialType {
+        MaterialType::DensitySlice
+    }
+
+    fn bind_pipeline(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_pipeline(&self.pipeline);
+    }
+
+    fn draw_geometry_array(
+        &self,
+        _vertex_buffer: &wgpu::Buffer,
+        _vertex_cnt: usize,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("The density slice pipeline needs a textured geometry");
+    }
+
+    fn draw_instanced(
+        &self,
+        _vertex_cnt: usize,
+        _instance_buffer: &wgpu::Buffer,
+        _instance_cnt: usize,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Instanced rendering is not supported for the density slice pipeline");
+    }
+
+    fn draw_indirect_instanced(
+        &self,
+        _instance_buffer: &wgpu::Buffer,
+        _indirect_buffer: &wgpu::Buffer,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Instanced rendering is not supported for the density slice pipeline");
+    }
+
+    fn draw_indirect_instanced_split(
+        &self,
+        _instance_buffer: &wgpu::Buffer,
+        _extra_instance_buffer: &wgpu::Buffer,
+        _indirect_buffer: &wgpu::Buffer,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Instanced rendering is not supported for the density slice pipeline");
+    }
+
+    fn draw_textured(
+        &self,
+        vertex_buffer: &wgpu::Buffer,
+        vertex_cnt: usize,
+        bind_group: &wgpu::BindGroup,
+        render_pass: &mut wgpu::RenderPass,
+    ) {
+        render_pass.set_bind_group(1, bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..vertex_cnt as u32, 0..1);
+    }
+
+    fn draw_mesh_instanced(
+        &self,
+        _mesh: &Mesh,
+        _instance_buffer: &wgpu::Buffer,
+        _instance_cnt: usize,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Meshes are not supported for the density slice pipeline");
+    }
+}
+
+/// Placement and color of one copy of a mesh.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshInstance {
+    pub transform: nalgebra::Matrix4<f32>,
+    pub color: nalgebra::Vector4<f32>,
+}
+
+pub struct InstancedMeshMaterial {
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl InstancedMeshMaterial {
+    const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+    // the columns of the transform and the color
+    const INSTANCE_ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        2 => Float32x4,
+        3 => Float32x4,
+        4 => Float32x4,
+        5 => Float32x4,
+        6 => Float32x4
+    ];
+
+    pub fn new(
+        render_device: &WgpuRenderDevice,
+        model_view_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = render_device
+            .device()
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Instanced mesh shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    include_str!("../shaders/instanced_mesh.wgsl").into(),
+                ),
+            });
+
+        let render_pipeline_layout =
+            render_device
+                .device()
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Instanced mesh render pipeline layout"),
+                    bind_group_layouts: &[model_view_bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+
+        let pipeline =
+            render_device
+                .device()
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Instanced mesh render pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: Some("vs_main"),
+                        buffers: &[
+                            wgpu::VertexBufferLayout {
+                                array_stride: std::mem::size_of::<MeshVertex>()
+                                    as wgpu::BufferAddress,
+                                step_mode: wgpu::VertexStepMode::Vertex,
+                                attributes: &Self::VERTEX_ATTRIBUTES,
+                            },
+                            wgpu::VertexBufferLayout {
+                                array_stride: std::mem::size_of::<MeshInstance>()
+                                    as wgpu::BufferAddress,
+                                step_mode: wgpu::VertexStepMode::Instance,
+                                attributes: &Self::INSTANCE_ATTRIBUTES,
+                            },
+                        ],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: Some("fs_main"),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: HDR_FORMAT,
+                            blend: Some(wgpu::BlendState::REPLACE),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::TriangleList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: Some(wgpu::Face::Back),
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: render_device.depth_texture.format(),
+                        depth_write_enabled: true,
+                        depth_compare: wgpu::CompareFunction::Less,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState {
+                        count: 1,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                    multiview: None,
+                    cache: None,
+                });
+
+        Self { pipeline }
+    }
+}
+
+impl Material for InstancedMeshMaterial {
+    fn material_type(&self) -> MaterialType {
+        MaterialType::InstancedMesh
+    }
+
+    fn bind_pipeline(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_pipeline(&self.pipeline);
+    }
+
+    fn draw_geometry_array(
+        &self,
+        _vertex_buffer: &wgpu::Buffer,
+        _vertex_cnt: usize,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Vertex arrays are not supported for the instanced mesh pipeline");
+    }
+
+    fn draw_instanced(
+        &self,
+        _vertex_cnt: usize,
+        _instance_buffer: &wgpu::Buffer,
+        _instance_cnt: usize,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("The instanced mesh pipeline needs a mesh");
+    }
+
+    fn draw_indirect_instanced(
+        &self,
+        _instance_buffer: &wgpu::Buffer,
+        _indirect_buffer: &wgpu::Buffer,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("The instanced mesh pipeline needs a mesh");
+    }
+
+    fn draw_indirect_instanced_split(
+        &self,
+        _instance_buffer: &wgpu::Buffer,
+        _extra_instance_buffer: &wgpu::Buffer,
+        _indirect_buffer: &wgpu::Buffer,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("The instanced mesh pipeline needs a mesh");
+    }
+
+    fn draw_textured(
+        &self,
+        _vertex_buffer: &wgpu::Buffer,
+        _vertex_cnt: usize,
+        _bind_group: &wgpu::BindGroup,
+        _render_pass: &mut wgpu::RenderPass,
+    ) {
+        panic!("Textured rendering is not supported for the instanced mesh pipeline");
+    }
+
+    fn draw_mesh_instanced(
+        &self,
+        mesh: &Mesh,
+        instance_buffer: &wgpu::Buffer,
+        instance_cnt: usize,
+        render_pass: &mut wgpu::RenderPass,
+    ) {
+        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..mesh.index_cnt, 0, 0..instance_cnt as u32);
+    }
 }
@@ -0,0 +1,448 @@
+use nalgebra::Matrix4;
+use serde::{Deserialize, Serialize};
+
+use super::uniform_buffer::UniformBuffer;
+
+/// Format of the texture the scene is rendered to before post processing.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const AO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tonemapping {
+    /// Colors above one are clipped
+    #[default]
+    None,
+    Aces,
+}
+
+impl Tonemapping {
+    pub const ALL: [Tonemapping; 2] = [Tonemapping::None, Tonemapping::Aces];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tonemapping::None => "None",
+            Tonemapping::Aces => "ACES",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bloom {
+    /// Brightness above which pixels start to glow
+    pub threshold: f32,
+    pub intensity: f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            intensity: 0.5,
+        }
+    }
+}
+
+/// Screen space ambient occlusion computed from the scene depth buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ssao {
+    /// View space distance searched for occluders
+    pub radius: f32,
+    /// How strongly occluded pixels are darkened, between 0 and 1
+    pub intensity: f32,
+}
+
+impl Default for Ssao {
+    fn default() -> Self {
+        Self {
+            radius: 0.1,
+            intensity: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcessSettings {
+    pub exposure: f32,
+    pub tonemapping: Tonemapping,
+    pub bloom: Option<Bloom>,
+    /// How much the corners are darkened, between 0 and 1
+    pub vignette: f32,
+    pub ssao: Option<Ssao>,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            tonemapping: Tonemapping::None,
+            bloom: None,
+            vignette: 0.0,
+            ssao: None,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostProcessUniform {
+    exposure: f32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    vignette: f32,
+    tonemapping: u32,
+    ssao_intensity: f32,
+    _padding: [u32; 2],
+}
+
+impl From<PostProcessSettings> for PostProcessUniform {
+    fn from(settings: PostProcessSettings) -> Self {
+        let bloom = settings.bloom.unwrap_or(Bloom {
+            threshold: 0.0,
+            intensity: 0.0,
+        });
+
+        Self {
+            exposure: settings.exposure,
+            bloom_threshold: bloom.threshold,
+            bloom_intensity: bloom.intensity,
+            vignette: settings.vignette,
+            tonemapping: match settings.tonemapping {
+                Tonemapping::None => 0,
+                Tonemapping::Aces => 1,
+            },
+            ssao_intensity: settings.ssao.map_or(0.0, |ssao| ssao.intensity),
+            _padding: [0; 2],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    projection: [[f32; 4]; 4],
+    inverse_projection: [[f32; 4]; 4],
+    radius: f32,
+    _padding: [u32; 3],
+}
+
+/// Fullscreen passes turning the HDR scene into the displayed image: an optional bloom made
+/// of a bright pass and a separable blur at half resolution and optional ambient occlusion
+/// from the depth buffer, followed by exposure, tonemapping and vignette.
+pub struct PostProcess {
+    settings: PostProcessSettings,
+    uniform_buffer: UniformBuffer<PostProcessUniform>,
+    ssao_buffer: UniformBuffer<SsaoUniform>,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    ssao_bind_group_layout: wgpu::BindGroupLayout,
+    ssao_pipeline: wgpu::RenderPipeline,
+    bright_pipeline: wgpu::RenderPipeline,
+    blur_horizontal_pipeline: wgpu::RenderPipeline,
+    blur_vertical_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+}
+
+impl PostProcess {
+    pub fn new(device: &wgpu::Device, output_format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Post process shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!(
+                    "{}\n{}",
+                    include_str!("../shaders/fullscreen.wgsl"),
+                    include_str!("../shaders/post_process.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let ssao_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("SSAO shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!(
+                    "{}\n{}",
+                    include_str!("../shaders/fullscreen.wgsl"),
+                    include_str!("../shaders/ssao.wgsl")
+                )
+                .into(),
+            ),
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post process bind group layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                texture_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                UniformBuffer::<PostProcessUniform>::layout_entry(
+                    4,
+                    wgpu::ShaderStages::FRAGMENT,
+                    false,
+                ),
+            ],
+        });
+
+        let ssao_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("SSAO bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    UniformBuffer::<SsaoUniform>::layout_entry(
+                        1,
+                        wgpu::ShaderStages::FRAGMENT,
+                        false,
+                    ),
+                ],
+            });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post process pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let ssao_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSAO pipeline layout"),
+            bind_group_layouts: &[&ssao_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |entry_point: &str, format: wgpu::TextureFormat| {
+            let (layout, shader) = match entry_point {
+                "fs_ssao" => (&ssao_layout, &ssao_shader),
+                _ => (&layout, &shader),
+            };
+
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: shader,
+                    entry_point: Some("vs_fullscreen"),
+                    buffers: &[],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: shader,
+                    entry_point: Some(entry_point),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        let uniform_buffer = UniformBuffer::new(device, "Post process buffer");
+        let ssao_buffer = UniformBuffer::new(device, "SSAO buffer");
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post process sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            settings: PostProcessSettings::default(),
+            uniform_buffer,
+            ssao_buffer,
+            sampler,
+            ssao_pipeline: create_pipeline("fs_ssao", AO_FORMAT),
+            bright_pipeline: create_pipeline("fs_bright", HDR_FORMAT),
+            blur_horizontal_pipeline: create_pipeline("fs_blur_horizontal", HDR_FORMAT),
+            blur_vertical_pipeline: create_pipeline("fs_blur_vertical", HDR_FORMAT),
+            composite_pipeline: create_pipeline("fs_composite", output_format),
+            bind_group_layout,
+            ssao_bind_group_layout,
+        }
+    }
+
+    pub fn settings(&self) -> PostProcessSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: PostProcessSettings) {
+        self.settings = settings;
+    }
+
+    /// `projection` is the camera projection the depth buffer was rendered with.
+    pub fn write_uniform(&self, queue: &wgpu::Queue, projection: &Matrix4<f32>) {
+        let uniform = PostProcessUniform::from(self.settings);
+        self.uniform_buffer.write(queue, &uniform);
+
+        if let Some(ssao) = self.settings.ssao {
+            let uniform = SsaoUniform {
+                projection: (*projection).into(),
+                inverse_projection: projection.try_inverse().unwrap_or_default().into(),
+                radius: ssao.radius,
+                _padding: [0; 3],
+            };
+            self.ssao_buffer.write(queue, &uniform);
+        }
+    }
+
+    /// Writes the ambient occlusion of `depth` to `target`, which has the `AO_FORMAT` and the
+    /// size of `depth`.
+    pub fn ssao_pass(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        depth: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO bind group"),
+            layout: &self.ssao_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(depth),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.ssao_buffer.binding(),
+                },
+            ],
+        });
+
+        self.draw_fullscreen(encoder, &self.ssao_pipeline, &bind_group, target);
+    }
+
+    pub fn bright_pass(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+    ) {
+        self.fullscreen_pass(device, encoder, &self.bright_pipeline, [hdr; 3], target);
+    }
+
+    /// Blurs `bloom` in place, `scratch` has to be of the same size.
+    pub fn blur_pass(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        bloom: &wgpu::TextureView,
+        scratch: &wgpu::TextureView,
+    ) {
+        let pipeline = &self.blur_horizontal_pipeline;
+        self.fullscreen_pass(device, encoder, pipeline, [bloom; 3], scratch);
+        let pipeline = &self.blur_vertical_pipeline;
+        self.fullscreen_pass(device, encoder, pipeline, [scratch; 3], bloom);
+    }
+
+    /// Without bloom or ambient occlusion, `bloom` and `occlusion` are not sampled and may be
+    /// any texture.
+    pub fn composite_pass(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        hdr: &wgpu::TextureView,
+        bloom: &wgpu::TextureView,
+        occlusion: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+    ) {
+        let pipeline = &self.composite_pipeline;
+        self.fullscreen_pass(device, encoder, pipeline, [hdr, bloom, occlusion], target);
+    }
+
+    fn fullscreen_pass(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        [source, bloom, occlusion]: [&wgpu::TextureView; 3],
+        target: &wgpu::TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post process bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(bloom),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(occlusion),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.uniform_buffer.binding(),
+                },
+            ],
+        });
+
+        self.draw_fullscreen(encoder, pipeline, &bind_group, target);
+    }
+
+    fn draw_fullscreen(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup,
+        target: &wgpu::TextureView,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post process pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -1,15 +1,30 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Instant};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, RwLock},
+    time::Instant,
+};
 
 use egui::{ClippedPrimitive, TexturesDelta};
 use egui_wgpu::Renderer;
-use nalgebra::{Matrix4, Point3};
+use image::RgbaImage;
+use nalgebra::Matrix4;
 
-use crate::WgpuRenderDevice;
+use crate::{gpu_timer::GpuTimer, readback::Readback, SplooshError, WgpuDevice, WgpuRenderDevice};
 
 use super::{
+    background::{BackgroundPass, RenderSettings},
     camera::Camera,
+    capture::FrameCapture,
+    frame_graph::{FrameGraph, TransientTexture, TransientTextures},
     geometry::Geometry,
-    materials::{LineMaterial, Material, MaterialType, ParticleMaterial},
+    materials::{
+        AnisotropicParticleMaterial, DensitySliceMaterial, InstancedMeshMaterial, LineMaterial,
+        LineStyle, LineStyleUniform, Material, MaterialType, ParticleMaterial,
+    },
+    post_process::{PostProcess, PostProcessSettings, AO_FORMAT, HDR_FORMAT},
+    scene_objects::{ObjectHandle, SceneObjects, MAX_SCENE_OBJECTS},
+    texture::Texture,
+    uniform_buffer::UniformBuffer,
 };
 
 pub struct RenderRequest {
@@ -17,62 +32,228 @@ pub struct RenderRequest {
     pub geometry: Geometry,
 }
 
+pub type GenericRequest = Box<dyn Fn(&mut wgpu::CommandEncoder, &wgpu::Queue) + Send + Sync>;
+
+/// Where in a frame a generic request is encoded. The stages run in this order, the requests of
+/// a stage in the order they were submitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FrameStage {
+    /// Steps of the simulation and work on its state, like picking or painting
+    Simulation,
+    /// Turns the simulation state into what is drawn, e.g. the display buffers
+    PreRender,
+    /// After the scene is drawn and before the post processing
+    Render,
+    /// After the gui is drawn, the last work of the frame
+    Gui,
+}
+
+impl FrameStage {
+    pub const ALL: [FrameStage; 4] = [
+        FrameStage::Simulation,
+        FrameStage::PreRender,
+        FrameStage::Render,
+        FrameStage::Gui,
+    ];
+}
+
 pub struct GuiRenderRequest {
     pub textures_delta: TexturesDelta,
     pub tris: Vec<ClippedPrimitive>,
     pub scale_factor: f32,
 }
 
+/// Cameras drawn in a single frame, the main camera and the extra viewports.
+pub const MAX_VIEWPORTS: usize = 4;
+
+/// Frames submitted before `render` waits for the oldest one, so the next frame is encoded
+/// while the GPU still works on the last.
+pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+
+/// Start and end of the simulation submission, then of the render submission.
+const FRAME_TIMESTAMPS: u32 = 4;
+const SIMULATION_TIMESTAMP: u32 = 0;
+const RENDER_TIMESTAMP: u32 = 2;
+
+/// Matches `CameraUniform` in the material shaders.
 #[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 struct CameraUniform {
     pub view_proj: Matrix4<f32>,
     pub view_inv: Matrix4<f32>,
-    pub position: Point3<f32>,
+    pub position: [f32; 3],
     pub _padding: f32,
+    /// In pixels, lines are widened in screen space
+    pub viewport_size: [f32; 2],
+    pub pixels_per_point: f32,
+    pub _viewport_padding: f32,
+}
+
+// the vec3 is aligned to 16 bytes in WGSL and the padding fills it up to a vec4
+const _: () = {
+    assert!(std::mem::offset_of!(CameraUniform, view_inv) == 64);
+    assert!(std::mem::offset_of!(CameraUniform, position) == 128);
+    assert!(std::mem::offset_of!(CameraUniform, viewport_size) == 144);
+    assert!(std::mem::size_of::<CameraUniform>() == 160);
+};
+
+impl CameraUniform {
+    fn new(camera: &Camera, viewport_size: [u32; 2], pixels_per_point: f32) -> Self {
+        let view_mat = camera.get_view_matrix();
+        let aspect = viewport_size[0] as f32 / viewport_size[1] as f32;
+
+        Self {
+            view_proj: camera.get_projection_matrix(aspect) * view_mat,
+            view_inv: view_mat.try_inverse().unwrap(),
+            position: camera.position.into(),
+            _padding: 0.0,
+            viewport_size: viewport_size.map(|size| size as f32),
+            pixels_per_point,
+            _viewport_padding: 0.0,
+        }
+    }
+
+    /// Camera of an object placed with `model`.
+    fn with_model(&self, model: &Matrix4<f32>) -> Self {
+        Self {
+            view_proj: self.view_proj * model,
+            ..*self
+        }
+    }
+}
+
+/// Camera uniforms of a viewport, one for the submitted requests and one per scene object.
+const CAMERA_SLOTS: usize = MAX_SCENE_OBJECTS + 1;
+
+fn camera_slot(viewport: usize, object: Option<usize>) -> usize {
+    viewport * CAMERA_SLOTS + object.map_or(0, |object| object + 1)
+}
+
+/// Part of the render target, as fractions of its size from the top left corner.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewportRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl ViewportRect {
+    pub const FULL: ViewportRect = ViewportRect {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// x, y, width and height in pixels of a target with the given size, at least a pixel
+    /// large and inside the target.
+    pub fn pixels(&self, target_width: u32, target_height: u32) -> [u32; 4] {
+        let (target_width, target_height) = (target_width.max(1), target_height.max(1));
+        let x = ((self.x * target_width as f32) as u32).min(target_width - 1);
+        let y = ((self.y * target_height as f32) as u32).min(target_height - 1);
+        let width = ((self.width * target_width as f32).round() as u32).clamp(1, target_width - x);
+        let height =
+            ((self.height * target_height as f32).round() as u32).clamp(1, target_height - y);
+
+        [x, y, width, height]
+    }
+}
+
+/// An additional camera drawing the scene into part of the render target.
+pub struct Viewport {
+    pub rect: ViewportRect,
+    pub camera: Camera,
+}
+
+struct OffscreenTarget {
+    color: Texture,
+    depth: Texture,
+    capture: FrameCapture,
+}
+
+/// Scene texture shown by the gui as an image instead of filling the window.
+struct ViewportTexture {
+    color: Texture,
+    depth: Texture,
+    texture_id: egui::TextureId,
 }
 
 pub struct RenderEngine {
-    render_device: Rc<RefCell<WgpuRenderDevice>>,
+    render_device: Arc<RwLock<WgpuRenderDevice>>,
     gui_renderer: Renderer,
 
-    camera_buffer: wgpu::Buffer,
+    /// `CAMERA_SLOTS` cameras per viewport, selected with a dynamic offset
+    camera_buffer: UniformBuffer<CameraUniform>,
     camera_bind_group: wgpu::BindGroup,
+    main_viewport: ViewportRect,
+    extra_viewports: Vec<Viewport>,
 
     materials: HashMap<MaterialType, Box<dyn Material>>,
+    line_style: UniformBuffer<LineStyleUniform>,
+    /// Of the last gui frame, scales the line widths
+    pixels_per_point: f32,
+    post_process: PostProcess,
+    render_settings: RenderSettings,
+    background: BackgroundPass,
     render_queue: Vec<RenderRequest>,
+    scene_objects: SceneObjects,
     gui_request: Option<GuiRenderRequest>,
-    generic_queue: Vec<Box<dyn Fn(&mut wgpu::CommandEncoder, &wgpu::Queue) -> ()>>,
+    /// One queue per `FrameStage`
+    generic_queues: [Vec<GenericRequest>; FrameStage::ALL.len()],
+    offscreen_target: Option<OffscreenTarget>,
+    viewport_texture: Option<ViewportTexture>,
+    transient_textures: TransientTextures,
+    screenshot_requested: bool,
+    screenshot_readback: Option<FrameCapture>,
+    screenshot: Option<Result<RgbaImage, SplooshError>>,
+    scene_capture_requested: bool,
+    /// Whether the last frame queued the requested scene capture
+    scene_capture_queued: bool,
+    scene_readback: Option<FrameCapture>,
+    /// Read back frames in the order they were captured
+    scene_captures: Vec<Result<RgbaImage, SplooshError>>,
 
+    /// Submissions of the frames the GPU may still be working on, oldest first
+    frames_in_flight: VecDeque<wgpu::SubmissionIndex>,
+    /// `None` if the device has no timestamp queries
+    frame_timer: Option<GpuTimer>,
+    frame_timestamps: Readback<u64>,
+    /// Whether the simulation of this frame was submitted through `time_simulation`
+    simulation_timed: bool,
     last_frame_time: f32,
+    last_gpu_frame_time: Option<f32>,
 }
 
-impl<'a> RenderEngine {
-    pub fn new(render_device: Rc<RefCell<WgpuRenderDevice>>) -> Self {
-        let rd = render_device.borrow();
+impl RenderEngine {
+    pub fn new(render_device: Arc<RwLock<WgpuRenderDevice>>) -> Self {
+        let _span = tracing::info_span!("create_render_engine").entered();
+        let rd = render_device.read().unwrap();
 
         // Model view buffer initialization
 
-        let camera_buffer = rd.device().create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Camera buffer"),
-            size: std::mem::size_of::<CameraUniform>() as u64,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        // every viewport and scene object has its own camera, selected with a dynamic offset
+        let camera_buffer =
+            UniformBuffer::with_count(rd.device(), "Camera buffer", MAX_VIEWPORTS * CAMERA_SLOTS);
 
         let camera_bind_group_layout =
             rd.device()
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                     label: Some("Camera bind group layout"),
-                    entries: &[wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }],
+                    entries: &[UniformBuffer::<CameraUniform>::layout_entry(
+                        0,
+                        wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        true,
+                    )],
                 });
 
         let camera_bind_group = rd.device().create_bind_group(&wgpu::BindGroupDescriptor {
@@ -80,31 +261,76 @@ impl<'a> RenderEngine {
             layout: &camera_bind_group_layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
-                resource: camera_buffer.as_entire_binding(),
+                resource: camera_buffer.binding(),
             }],
         });
 
         // Material initialization
 
         let mut materials: HashMap<MaterialType, Box<dyn Material>> = HashMap::new();
+        let line_style = UniformBuffer::new(rd.device(), "Line style buffer");
+        line_style.write(rd.queue(), &LineStyleUniform::from(&LineStyle::default()));
         materials.insert(
             MaterialType::Line,
-            Box::new(LineMaterial::new(&rd, &camera_bind_group_layout)),
+            Box::new(LineMaterial::new(
+                &rd,
+                &camera_bind_group_layout,
+                &line_style,
+                false,
+            )),
+        );
+        materials.insert(
+            MaterialType::DashedLine,
+            Box::new(LineMaterial::new(
+                &rd,
+                &camera_bind_group_layout,
+                &line_style,
+                true,
+            )),
         );
         materials.insert(
             MaterialType::Particle,
-            Box::new(ParticleMaterial::new(&rd, &camera_bind_group_layout)),
+            Box::new(ParticleMaterial::new(&rd, &camera_bind_group_layout, false)),
+        );
+        materials.insert(
+            MaterialType::TranslucentParticle,
+            Box::new(ParticleMaterial::new(&rd, &camera_bind_group_layout, true)),
+        );
+        materials.insert(
+            MaterialType::AnisotropicParticle,
+            Box::new(AnisotropicParticleMaterial::new(
+                &rd,
+                &camera_bind_group_layout,
+            )),
+        );
+        materials.insert(
+            MaterialType::InstancedMesh,
+            Box::new(InstancedMeshMaterial::new(&rd, &camera_bind_group_layout)),
         );
+        materials.insert(
+            MaterialType::DensitySlice,
+            Box::new(DensitySliceMaterial::new(&rd, &camera_bind_group_layout)),
+        );
+
+        let post_process = PostProcess::new(rd.device(), rd.config.format);
+        let background = BackgroundPass::new(rd.device(), rd.queue());
 
         // gui
         let gui_renderer = Renderer::new(
-            &rd.device(),
+            rd.device(),
             rd.config.format,
             Some(rd.depth_texture.format()),
             1,
             true,
         );
 
+        let frame_timer = GpuTimer::new(&rd.wgpu_device, FRAME_TIMESTAMPS);
+        let frame_timestamps = Readback::new(
+            &rd.wgpu_device,
+            "Frame timestamp staging buffer",
+            FRAME_TIMESTAMPS as u64 * std::mem::size_of::<u64>() as u64,
+        );
+
         drop(rd);
 
         Self {
@@ -112,17 +338,40 @@ impl<'a> RenderEngine {
             gui_renderer,
             camera_buffer,
             camera_bind_group,
+            main_viewport: ViewportRect::FULL,
+            extra_viewports: Vec::new(),
             materials,
+            line_style,
+            pixels_per_point: 1.0,
+            post_process,
+            render_settings: RenderSettings::default(),
+            background,
             render_queue: Vec::new(),
-            generic_queue: Vec::new(),
+            scene_objects: SceneObjects::default(),
+            generic_queues: Default::default(),
             gui_request: None,
+            offscreen_target: None,
+            viewport_texture: None,
+            transient_textures: TransientTextures::default(),
+            screenshot_requested: false,
+            screenshot_readback: None,
+            screenshot: None,
+            scene_capture_requested: false,
+            scene_capture_queued: false,
+            scene_readback: None,
+            scene_captures: Vec::new(),
+            frames_in_flight: VecDeque::new(),
+            frame_timer,
+            frame_timestamps,
+            simulation_timed: false,
             last_frame_time: 0.0,
+            last_gpu_frame_time: None,
         }
     }
 
     pub fn create_geometry_array<T>(&self, vertices: &[T]) -> Geometry {
         Geometry::Array {
-            vertex_buffer: self.render_device.borrow().create_buffer_init(
+            vertex_buffer: self.render_device.read().unwrap().create_buffer_init(
                 vertices,
                 wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
             ),
@@ -136,164 +385,778 @@ impl<'a> RenderEngine {
         self.render_queue.push(render_request);
     }
 
+    /// Adds geometry drawn every frame before the submitted requests, `None` if there are
+    /// already `MAX_SCENE_OBJECTS`.
+    pub fn add_object(&mut self, name: &str, request: RenderRequest) -> Option<ObjectHandle> {
+        self.scene_objects.add(name, request)
+    }
+
+    pub fn remove_object(&mut self, handle: ObjectHandle) {
+        self.scene_objects.remove(handle);
+    }
+
+    pub fn set_object_transform(&mut self, handle: ObjectHandle, transform: Matrix4<f32>) {
+        if let Some(object) = self.scene_objects.get_mut(handle) {
+            object.transform = transform;
+        }
+    }
+
+    pub fn set_object_visible(&mut self, handle: ObjectHandle, visible: bool) {
+        if let Some(object) = self.scene_objects.get_mut(handle) {
+            object.visible = visible;
+        }
+    }
+
+    pub fn set_object_geometry(&mut self, handle: ObjectHandle, geometry: Geometry) {
+        if let Some(object) = self.scene_objects.get_mut(handle) {
+            object.request.geometry = geometry;
+        }
+    }
+
+    pub fn scene_objects(&self) -> &SceneObjects {
+        &self.scene_objects
+    }
+
     pub fn submit_gui_render_request(&mut self, request: GuiRenderRequest) {
+        self.pixels_per_point = request.scale_factor;
         self.gui_request = Some(request);
     }
 
-    pub fn submit_generic_request(
+    /// Encodes `request` in `stage` of the next frame. Render requests are drawn in the
+    /// `Render` stage before its generic requests, the gui in the `Gui` stage.
+    pub fn submit_generic_request(&mut self, stage: FrameStage, request: GenericRequest) {
+        self.generic_queues[stage as usize].push(request);
+    }
+
+    /// Redirects rendering into an offscreen texture of the given size instead of the window
+    /// surface. Every rendered frame can then be read back with `read_offscreen_frame`.
+    pub fn set_offscreen_target(&mut self, width: u32, height: u32) {
+        let rd = self.render_device.read().unwrap();
+        let format = rd.config.format;
+
+        self.offscreen_target = Some(OffscreenTarget {
+            color: Texture::render_target(rd.device(), width, height, format),
+            depth: Texture::depth_texture_with_size(rd.device(), width, height),
+            capture: FrameCapture::new(&rd.wgpu_device, width, height, format),
+        });
+    }
+
+    pub fn clear_offscreen_target(&mut self) {
+        self.offscreen_target = None;
+    }
+
+    /// Blocks until the last rendered frame is copied, offline renders need every frame.
+    pub fn read_offscreen_frame(&self) -> Option<Result<RgbaImage, SplooshError>> {
+        let target = self.offscreen_target.as_ref()?;
+        Some(
+            target
+                .capture
+                .read(self.render_device.read().unwrap().device()),
+        )
+    }
+
+    /// Renders the scene into a texture of the given size in pixels, which the gui draws with
+    /// the returned id, e.g. in an `egui::Image`. The window is then only covered by the gui.
+    /// Calling it every frame is cheap, the texture is only recreated when the size changes.
+    pub fn set_viewport_texture(&mut self, width: u32, height: u32) -> egui::TextureId {
+        let (width, height) = (width.max(1), height.max(1));
+        if let Some(viewport) = &self.viewport_texture {
+            if viewport.color.texture().width() == width
+                && viewport.color.texture().height() == height
+            {
+                return viewport.texture_id;
+            }
+        }
+
+        let rd = self.render_device.read().unwrap();
+        let color = Texture::render_target(rd.device(), width, height, rd.config.format);
+        let depth = Texture::depth_texture_with_size(rd.device(), width, height);
+        let texture_id = match &self.viewport_texture {
+            Some(viewport) => {
+                self.gui_renderer.update_egui_texture_from_wgpu_texture(
+                    rd.device(),
+                    color.view(),
+                    wgpu::FilterMode::Linear,
+                    viewport.texture_id,
+                );
+                viewport.texture_id
+            }
+            None => self.gui_renderer.register_native_texture(
+                rd.device(),
+                color.view(),
+                wgpu::FilterMode::Linear,
+            ),
+        };
+
+        self.viewport_texture = Some(ViewportTexture {
+            color,
+            depth,
+            texture_id,
+        });
+        texture_id
+    }
+
+    /// Goes back to rendering the scene behind the gui.
+    pub fn clear_viewport_texture(&mut self) {
+        if let Some(viewport) = self.viewport_texture.take() {
+            self.gui_renderer.free_texture(&viewport.texture_id);
+        }
+    }
+
+    /// Makes a texture of the simulation drawable by the gui, e.g. in an `egui::Image`. Passing
+    /// the id of an earlier registration points it at the new texture instead.
+    pub fn register_gui_texture(
         &mut self,
-        request: Box<dyn Fn(&mut wgpu::CommandEncoder, &wgpu::Queue) -> ()>,
-    ) {
-        self.generic_queue.push(request);
+        view: &wgpu::TextureView,
+        id: Option<egui::TextureId>,
+    ) -> egui::TextureId {
+        let rd = self.render_device.read().unwrap();
+        match id {
+            Some(id) => {
+                self.gui_renderer.update_egui_texture_from_wgpu_texture(
+                    rd.device(),
+                    view,
+                    wgpu::FilterMode::Nearest,
+                    id,
+                );
+                id
+            }
+            None => self.gui_renderer.register_native_texture(
+                rd.device(),
+                view,
+                wgpu::FilterMode::Nearest,
+            ),
+        }
     }
 
-    pub fn render(&mut self, camera: &Camera) -> Result<(), wgpu::SurfaceError> {
-        let start_time = Instant::now();
+    pub fn free_gui_texture(&mut self, id: egui::TextureId) {
+        self.gui_renderer.free_texture(&id);
+    }
 
-        let rd = self.render_device.borrow();
-        let output = rd.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+    pub fn is_depth_sorted(&self, material_type: MaterialType) -> bool {
+        self.materials
+            .get(&material_type)
+            .is_some_and(|material| material.depth_sorted())
+    }
 
-        let view_mat = camera.get_view_matrix();
-        let projection_mat =
-            camera.get_projection_matrix(rd.config.width as f32 / rd.config.height as f32);
+    pub fn post_process_settings(&self) -> PostProcessSettings {
+        self.post_process.settings()
+    }
 
-        let camera_data = CameraUniform {
-            view_proj: projection_mat * view_mat,
-            view_inv: view_mat.try_inverse().unwrap(),
-            position: camera.position,
-            _padding: 0.0,
+    pub fn set_post_process_settings(&mut self, settings: PostProcessSettings) {
+        self.post_process.set_settings(settings);
+    }
+
+    pub fn render_settings(&self) -> &RenderSettings {
+        &self.render_settings
+    }
+
+    /// Keeps the previous settings if the environment map can't be loaded.
+    pub fn set_render_settings(&mut self, settings: RenderSettings) -> Result<(), SplooshError> {
+        let rd = self.render_device.read().unwrap();
+        // only environment maps are loaded, again when the path changes
+        if settings.background != self.render_settings.background {
+            self.background
+                .set_background(rd.device(), rd.queue(), &settings.background)?;
+        }
+        self.line_style
+            .write(rd.queue(), &LineStyleUniform::from(&settings.lines));
+        drop(rd);
+
+        self.render_settings = settings;
+        Ok(())
+    }
+
+    /// Splits the frame into the viewport of the camera passed to `render` and additional
+    /// cameras, e.g. a top view next to the main one. Viewports beyond `MAX_VIEWPORTS` are
+    /// dropped. The background and the post processing follow the main camera.
+    pub fn set_viewports(&mut self, main: ViewportRect, mut extra: Vec<Viewport>) {
+        extra.truncate(MAX_VIEWPORTS - 1);
+        self.main_viewport = main;
+        self.extra_viewports = extra;
+    }
+
+    pub fn main_viewport(&self) -> ViewportRect {
+        self.main_viewport
+    }
+
+    pub fn viewport_cnt(&self) -> usize {
+        1 + self.extra_viewports.len()
+    }
+
+    /// Aspect ratio of the main viewport in the texture the next frame is rendered to.
+    pub fn aspect_ratio(&self) -> f32 {
+        self.target_aspect_ratio() * self.main_viewport.width / self.main_viewport.height
+    }
+
+    fn target_aspect_ratio(&self) -> f32 {
+        let (width, height) = match (&self.offscreen_target, &self.viewport_texture) {
+            (Some(target), _) => (target.capture.width(), target.capture.height()),
+            (None, Some(viewport)) => (
+                viewport.color.texture().width(),
+                viewport.color.texture().height(),
+            ),
+            (None, None) => {
+                let rd = self.render_device.read().unwrap();
+                (rd.config.width, rd.config.height)
+            }
         };
 
-        let len = std::mem::size_of::<CameraUniform>();
-        let ptr = camera_data.view_proj.as_ptr() as *const u8;
-        let data = unsafe { std::slice::from_raw_parts(ptr, len) };
+        width as f32 / height as f32
+    }
+
+    /// Captures the next rendered frame, including the gui. The result is available through
+    /// `take_screenshot` once the copy has finished, usually a frame or two later.
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    pub fn take_screenshot(&mut self) -> Option<Result<RgbaImage, SplooshError>> {
+        self.screenshot.take()
+    }
+
+    /// Same as `request_screenshot`, but the frame is captured before the gui is drawn.
+    pub fn request_scene_capture(&mut self) {
+        self.scene_capture_requested = true;
+    }
+
+    /// Whether the last `render` copied the scene for a requested capture. All staging buffers
+    /// can still be in flight, then the frame is skipped.
+    pub fn scene_capture_queued(&self) -> bool {
+        self.scene_capture_queued
+    }
 
-        rd.queue().write_buffer(&self.camera_buffer, 0, data);
+    /// The captured frames read back since the last call, oldest first.
+    pub fn take_scene_captures(&mut self) -> Vec<Result<RgbaImage, SplooshError>> {
+        std::mem::take(&mut self.scene_captures)
+    }
+
+    /// Draws the submitted requests. If the surface texture can't be acquired, the requests of
+    /// this frame are dropped so they don't pile up until the next one.
+    #[tracing::instrument(skip_all)]
+    pub fn render(&mut self, camera: &Camera) -> Result<(), SplooshError> {
+        let rd = self.render_device.read().unwrap();
+        while self.frames_in_flight.len() >= MAX_FRAMES_IN_FLIGHT {
+            let _span = tracing::debug_span!("wait_for_frame").entered();
+            let submission = self.frames_in_flight.pop_front().unwrap();
+            rd.device()
+                .poll(wgpu::Maintain::WaitForSubmissionIndex(submission));
+        }
+        let start_time = Instant::now();
+
+        let output = match self.offscreen_target {
+            Some(_) => None,
+            None => match rd.acquire_frame() {
+                Ok(output) => Some(output),
+                Err(err) => {
+                    self.render_queue.clear();
+                    self.generic_queues.iter_mut().for_each(Vec::clear);
+                    self.gui_request = None;
+                    self.simulation_timed = false;
+                    self.scene_capture_queued = false;
+                    return Err(err);
+                }
+            },
+        };
+        let surface_view = output.as_ref().map(|output| {
+            output
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+
+        // offline renders never show the gui, so the offscreen target wins over the viewport
+        let viewport_texture = self
+            .viewport_texture
+            .as_ref()
+            .filter(|_| self.offscreen_target.is_none());
+        let (view, depth_view, width, height) =
+            match (&self.offscreen_target, viewport_texture, &surface_view) {
+                (Some(target), _, _) => (
+                    target.color.view(),
+                    target.depth.view(),
+                    target.capture.width(),
+                    target.capture.height(),
+                ),
+                (None, Some(viewport), _) => (
+                    viewport.color.view(),
+                    viewport.depth.view(),
+                    viewport.color.texture().width(),
+                    viewport.color.texture().height(),
+                ),
+                (None, None, Some(view)) => (
+                    view,
+                    rd.depth_texture.view(),
+                    rd.config.width,
+                    rd.config.height,
+                ),
+                (None, None, None) => {
+                    unreachable!("the surface texture is acquired when no offscreen target is set")
+                }
+            };
+
+        let viewports: Vec<([u32; 4], CameraUniform)> =
+            std::iter::once((self.main_viewport, camera))
+                .chain(
+                    self.extra_viewports
+                        .iter()
+                        .map(|viewport| (viewport.rect, &viewport.camera)),
+                )
+                .enumerate()
+                .map(|(i, (rect, camera))| {
+                    let pixels = rect.pixels(width, height);
+                    let camera_data =
+                        CameraUniform::new(camera, [pixels[2], pixels[3]], self.pixels_per_point);
+                    self.camera_buffer
+                        .write_at(rd.queue(), camera_slot(i, None), &camera_data);
+                    for (j, object) in self.scene_objects.visible().enumerate() {
+                        let object_camera = camera_data.with_model(&object.transform);
+                        self.camera_buffer.write_at(
+                            rd.queue(),
+                            camera_slot(i, Some(j)),
+                            &object_camera,
+                        );
+                    }
+                    (pixels, camera_data)
+                })
+                .collect();
+        let [_, _, main_width, main_height] = viewports[0].0;
+        let camera_data = &viewports[0].1;
+        let projection_mat = camera.get_projection_matrix(main_width as f32 / main_height as f32);
 
         let mut encoder = rd
             .device()
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
-
-        {
-            for request in &self.generic_queue {
-                request(&mut encoder, rd.queue());
+        if let Some(timer) = &self.frame_timer {
+            // an empty interval for frames without a timed simulation
+            if !std::mem::take(&mut self.simulation_timed) {
+                timer.write_timestamp(&mut encoder, SIMULATION_TIMESTAMP);
+                timer.write_timestamp(&mut encoder, SIMULATION_TIMESTAMP + 1);
             }
+            timer.write_timestamp(&mut encoder, RENDER_TIMESTAMP);
+        }
+
+        let (target_texture, depth_texture) =
+            match (&self.offscreen_target, viewport_texture, &output) {
+                (Some(target), _, _) => (target.color.texture(), target.depth.texture()),
+                (None, Some(viewport), _) => (viewport.color.texture(), viewport.depth.texture()),
+                (None, None, Some(output)) => (&output.texture, rd.depth_texture.texture()),
+                (None, None, None) => unreachable!(),
+            };
+
+        let mut scene_capture_failed = None;
+        let mut scene_capture_queued = false;
+        let mut screenshot_failed = None;
+        let [simulation_queue, pre_render_queue, render_stage_queue, gui_stage_queue] =
+            std::mem::take(&mut self.generic_queues);
+        let render_queue = std::mem::take(&mut self.render_queue);
+        let gui_request = self.gui_request.take();
+
+        let mut graph = FrameGraph::new();
+        graph.import_texture("color", target_texture, view);
+        graph.import_texture("depth", depth_texture, depth_view);
+        graph.mark_output("color");
+
+        // the scene goes to the viewport texture and the gui covers the window around it
+        let (gui_color, gui_depth, gui_size, gui_load) =
+            match (viewport_texture, &output, &surface_view) {
+                (Some(_), Some(output), Some(surface_view)) => {
+                    graph.import_texture("surface", &output.texture, surface_view);
+                    graph.import_texture(
+                        "surface_depth",
+                        rd.depth_texture.texture(),
+                        rd.depth_texture.view(),
+                    );
+                    graph.mark_output("surface");
+                    let size = [rd.config.width, rd.config.height];
+                    let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+                    ("surface", "surface_depth", size, clear)
+                }
+                // the gui is drawn into the frame once the captures of the scene are done
+                _ => {
+                    graph.alias("gui_color", "color");
+                    graph.alias("gui_depth", "depth");
+                    graph.mark_output("gui_color");
+                    (
+                        "gui_color",
+                        "gui_depth",
+                        [width, height],
+                        wgpu::LoadOp::Load,
+                    )
+                }
+            };
+
+        let hdr_usage =
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+        graph.create_texture(
+            "hdr",
+            TransientTexture {
+                width,
+                height,
+                format: HDR_FORMAT,
+                usage: hdr_usage,
+            },
+        );
 
-            self.generic_queue.clear();
+        // the stages only depend on each other through the names they read and write, the
+        // frame graph sorts them by those
+        let queue = rd.queue();
+        if !simulation_queue.is_empty() {
+            graph.add_pass("simulation", &[], &["simulation"], |encoder, _| {
+                for request in &simulation_queue {
+                    request(encoder, queue);
+                }
+                Ok(())
+            });
+        }
+        if !pre_render_queue.is_empty() {
+            graph.add_pass(
+                "pre_render",
+                &["simulation"],
+                &["simulation"],
+                |encoder, _| {
+                    for request in &pre_render_queue {
+                        request(encoder, queue);
+                    }
+                    Ok(())
+                },
+            );
         }
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: rd.depth_texture.view(),
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                occlusion_query_set: None,
-                timestamp_writes: None,
+        let background = &self.render_settings.background;
+        let clear_color = background.clear_color();
+        if clear_color.is_none() {
+            self.background
+                .write_uniform(rd.queue(), background, &camera_data.view_proj);
+
+            let background_pass = &self.background;
+            graph.add_pass("background", &[], &["hdr"], |encoder, resources| {
+                background_pass.draw(encoder, resources.texture_view("hdr")?);
+                Ok(())
             });
+        }
 
-            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        let materials = &self.materials;
+        let camera_bind_group = &self.camera_bind_group;
+        let camera_buffer = &self.camera_buffer;
+        let scene_objects = &self.scene_objects;
+        let viewports = &viewports;
+        graph.add_pass(
+            "scene",
+            &["simulation", "hdr"],
+            &["hdr", "depth"],
+            |encoder, resources| {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Render Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: resources.texture_view("hdr")?,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: clear_color.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: resources.texture_view("depth")?,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                });
 
-            for request in &self.render_queue {
-                let material = self.materials.get(&request.material_type).unwrap();
-                material.bind_pipeline(&mut render_pass);
+                for (i, (pixels, _)) in viewports.iter().enumerate() {
+                    let [x, y, width, height] = *pixels;
+                    render_pass.set_viewport(
+                        x as f32,
+                        y as f32,
+                        width as f32,
+                        height as f32,
+                        0.0,
+                        1.0,
+                    );
+                    render_pass.set_scissor_rect(x, y, width, height);
 
-                match &request.geometry {
-                    Geometry::Array {
-                        vertex_buffer,
-                        vertex_cnt,
-                    } => {
-                        material.draw_geometry_array(&vertex_buffer, *vertex_cnt, &mut render_pass)
-                    }
-                    Geometry::Instanced {
-                        vertex_cnt,
-                        instance_buffer,
-                        instance_cnt,
-                    } => {
-                        material.draw_instanced(
-                            *vertex_cnt,
-                            &instance_buffer,
-                            *instance_cnt,
-                            &mut render_pass,
+                    for (j, object) in scene_objects.visible().enumerate() {
+                        let slot = camera_slot(i, Some(j));
+                        render_pass.set_bind_group(
+                            0,
+                            camera_bind_group,
+                            &[camera_buffer.offset(slot)],
                         );
+                        draw_request(materials, &object.request, &mut render_pass);
+                    }
+
+                    render_pass.set_bind_group(
+                        0,
+                        camera_bind_group,
+                        &[camera_buffer.offset(camera_slot(i, None))],
+                    );
+                    for request in &render_queue {
+                        draw_request(materials, request, &mut render_pass);
                     }
                 }
-            }
 
-            self.render_queue.clear();
-        }
+                Ok(())
+            },
+        );
 
-        if let Some(request) = self.gui_request.take() {
-            for (id, image_delta) in &request.textures_delta.set {
-                self.gui_renderer
-                    .update_texture(&rd.device(), &rd.queue(), *id, image_delta);
-            }
+        if !render_stage_queue.is_empty() {
+            graph.add_pass("render_stage", &["hdr", "depth"], &["hdr"], |encoder, _| {
+                for request in &render_stage_queue {
+                    request(encoder, queue);
+                }
+                Ok(())
+            });
+        }
 
-            let screen_descriptor = egui_wgpu::ScreenDescriptor {
-                size_in_pixels: [rd.config.width, rd.config.height],
-                pixels_per_point: request.scale_factor,
+        let post_process = &self.post_process;
+        post_process.write_uniform(rd.queue(), &projection_mat);
+        let bloom_texture = if post_process.settings().bloom.is_some() {
+            let bloom = TransientTexture {
+                width: width / 2,
+                height: height / 2,
+                format: HDR_FORMAT,
+                usage: hdr_usage,
             };
+            graph.create_texture("bloom", bloom);
+            graph.create_texture("bloom_scratch", bloom);
+
+            graph.add_pass("bloom", &["hdr"], &["bloom"], |encoder, resources| {
+                let bloom = resources.texture_view("bloom")?;
+                post_process.bright_pass(
+                    rd.device(),
+                    encoder,
+                    resources.texture_view("hdr")?,
+                    bloom,
+                );
+                post_process.blur_pass(
+                    rd.device(),
+                    encoder,
+                    bloom,
+                    resources.texture_view("bloom_scratch")?,
+                );
+                Ok(())
+            });
+            "bloom"
+        } else {
+            "hdr"
+        };
 
-            self.gui_renderer.update_buffers(
-                &rd.device(),
-                &rd.queue(),
-                &mut encoder,
-                &request.tris,
-                &screen_descriptor,
+        let ao_texture = if post_process.settings().ssao.is_some() {
+            graph.create_texture(
+                "ao",
+                TransientTexture {
+                    width,
+                    height,
+                    format: AO_FORMAT,
+                    usage: hdr_usage,
+                },
             );
 
-            let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Gui render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: rd.depth_texture.view(),
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                occlusion_query_set: None,
-                timestamp_writes: None,
+            graph.add_pass("ssao", &["depth"], &["ao"], |encoder, resources| {
+                post_process.ssao_pass(
+                    rd.device(),
+                    encoder,
+                    resources.texture_view("depth")?,
+                    resources.texture_view("ao")?,
+                );
+                Ok(())
             });
+            "ao"
+        } else {
+            "hdr"
+        };
+
+        graph.add_pass(
+            "post_process",
+            &["hdr", bloom_texture, ao_texture],
+            &["color"],
+            |encoder, resources| {
+                post_process.composite_pass(
+                    rd.device(),
+                    encoder,
+                    resources.texture_view("hdr")?,
+                    resources.texture_view(bloom_texture)?,
+                    resources.texture_view(ao_texture)?,
+                    resources.texture_view("color")?,
+                );
+                Ok(())
+            },
+        );
 
-            self.gui_renderer.render(
-                &mut render_pass.forget_lifetime(),
-                &request.tris,
-                &screen_descriptor,
+        if std::mem::take(&mut self.scene_capture_requested) {
+            graph.mark_output("scene_capture");
+            graph.add_pass(
+                "scene_capture",
+                &["color"],
+                &["scene_capture"],
+                |encoder, resources| {
+                    let texture = resources.texture("color")?;
+                    match capture_texture(
+                        &rd.wgpu_device,
+                        &mut self.scene_readback,
+                        encoder,
+                        texture,
+                    ) {
+                        Ok(queued) => scene_capture_queued = queued,
+                        Err(err) => scene_capture_failed = Some(err),
+                    }
+                    Ok(())
+                },
             );
-            for x in &request.textures_delta.free {
-                self.gui_renderer.free_texture(x);
+        }
+
+        if let Some(request) = gui_request {
+            let gui_renderer = &mut self.gui_renderer;
+            let (device, queue) = (rd.device(), rd.queue());
+            graph.add_pass(
+                "gui",
+                &["color"],
+                &[gui_color, gui_depth],
+                move |encoder, resources| {
+                    for (id, image_delta) in &request.textures_delta.set {
+                        gui_renderer.update_texture(device, queue, *id, image_delta);
+                    }
+
+                    let screen_descriptor = egui_wgpu::ScreenDescriptor {
+                        size_in_pixels: gui_size,
+                        pixels_per_point: request.scale_factor,
+                    };
+
+                    gui_renderer.update_buffers(
+                        device,
+                        queue,
+                        encoder,
+                        &request.tris,
+                        &screen_descriptor,
+                    );
+
+                    let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("Gui render Pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: resources.texture_view(gui_color)?,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: gui_load,
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                            view: resources.texture_view(gui_depth)?,
+                            depth_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(1.0),
+                                store: wgpu::StoreOp::Store,
+                            }),
+                            stencil_ops: None,
+                        }),
+                        occlusion_query_set: None,
+                        timestamp_writes: None,
+                    });
+
+                    gui_renderer.render(
+                        &mut render_pass.forget_lifetime(),
+                        &request.tris,
+                        &screen_descriptor,
+                    );
+                    for x in &request.textures_delta.free {
+                        gui_renderer.free_texture(x);
+                    }
+                    Ok(())
+                },
+            );
+        }
+
+        if !gui_stage_queue.is_empty() {
+            graph.add_pass("gui_stage", &[gui_color], &[gui_color], |encoder, _| {
+                for request in &gui_stage_queue {
+                    request(encoder, queue);
+                }
+                Ok(())
+            });
+        }
+
+        if let Some(target) = &self.offscreen_target {
+            graph.mark_output("offscreen_capture");
+            graph.add_pass(
+                "offscreen_capture",
+                &["color"],
+                &["offscreen_capture"],
+                |encoder, resources| {
+                    target
+                        .capture
+                        .copy_from_texture(encoder, resources.texture("color")?);
+                    Ok(())
+                },
+            );
+        }
+
+        if std::mem::take(&mut self.screenshot_requested) {
+            graph.mark_output("screenshot");
+            graph.add_pass(
+                "screenshot",
+                &[gui_color],
+                &["screenshot"],
+                |encoder, resources| {
+                    let texture = resources.texture(gui_color)?;
+                    if let Err(err) = capture_texture(
+                        &rd.wgpu_device,
+                        &mut self.screenshot_readback,
+                        encoder,
+                        texture,
+                    ) {
+                        screenshot_failed = Some(err);
+                    }
+                    Ok(())
+                },
+            );
+        }
+
+        graph.execute(rd.device(), &mut encoder, &mut self.transient_textures)?;
+
+        if let Some(timer) = &self.frame_timer {
+            timer.write_timestamp(&mut encoder, RENDER_TIMESTAMP + 1);
+            timer.resolve_into(&mut encoder, FRAME_TIMESTAMPS, &self.frame_timestamps);
+        }
+        let submission = rd.wgpu_device.submit(encoder);
+        self.frames_in_flight.push_back(submission);
+
+        if let (Some(timer), Some(Ok(timestamps))) =
+            (&self.frame_timer, self.frame_timestamps.poll(rd.device()))
+        {
+            let intervals = timer.intervals(&timestamps);
+            self.last_gpu_frame_time = Some(
+                (intervals[SIMULATION_TIMESTAMP as usize] + intervals[RENDER_TIMESTAMP as usize])
+                    as f32,
+            );
+        }
+
+        // captures are read once their copies finish instead of waiting for them here
+        let poll = |failed: Option<SplooshError>, readback: &Option<FrameCapture>| match failed {
+            Some(err) => Some(Err(err)),
+            None => readback.as_ref()?.poll(rd.device()),
+        };
+        self.scene_capture_queued = scene_capture_queued;
+        // scene captures make up clips and keep every frame, screenshots only need the newest
+        match scene_capture_failed {
+            Some(err) => self.scene_captures.push(Err(err)),
+            None => {
+                if let Some(readback) = &self.scene_readback {
+                    self.scene_captures.extend(readback.poll_all(rd.device()));
+                }
             }
         }
+        if let Some(frame) = poll(screenshot_failed, &self.screenshot_readback) {
+            self.screenshot = Some(frame);
+        }
 
-        rd.queue().submit(std::iter::once(encoder.finish()));
-        output.present();
+        if let Some(output) = output {
+            output.present();
+        }
 
         let end_time = Instant::now();
         self.last_frame_time = (end_time - start_time).as_secs_f32() * 1000.0;
@@ -301,7 +1164,108 @@ impl<'a> RenderEngine {
         Ok(())
     }
 
+    /// Milliseconds the CPU spent encoding, submitting and presenting the last frame, without
+    /// waiting for earlier frames.
     pub fn last_frame_time(&self) -> f32 {
         self.last_frame_time
     }
+
+    /// Milliseconds the GPU spent on the simulation and the render submission of a recent
+    /// frame, `None` without timestamp queries or before the first frame finished.
+    pub fn last_gpu_frame_time(&self) -> Option<f32> {
+        self.last_gpu_frame_time
+    }
+
+    /// Records work submitted separately ahead of `render` between timestamps, so it counts
+    /// towards `last_gpu_frame_time`. Used for the simulation of the frame.
+    pub fn time_simulation(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        record: impl FnOnce(&mut wgpu::CommandEncoder),
+    ) {
+        let Some(timer) = &self.frame_timer else {
+            return record(encoder);
+        };
+        timer.write_timestamp(encoder, SIMULATION_TIMESTAMP);
+        record(encoder);
+        timer.write_timestamp(encoder, SIMULATION_TIMESTAMP + 1);
+        self.simulation_timed = true;
+    }
+}
+
+/// Draws `request` with its material, the camera has to be bound already.
+fn draw_request(
+    materials: &HashMap<MaterialType, Box<dyn Material>>,
+    request: &RenderRequest,
+    render_pass: &mut wgpu::RenderPass,
+) {
+    let material = materials.get(&request.material_type).unwrap();
+    material.bind_pipeline(render_pass);
+
+    match &request.geometry {
+        Geometry::Array {
+            vertex_buffer,
+            vertex_cnt,
+        } => material.draw_geometry_array(vertex_buffer, *vertex_cnt, render_pass),
+        Geometry::Instanced {
+            vertex_cnt,
+            instance_buffer,
+            instance_cnt,
+        } => {
+            material.draw_instanced(*vertex_cnt, instance_buffer, *instance_cnt, render_pass);
+        }
+        Geometry::IndirectInstanced {
+            instance_buffer,
+            indirect_buffer,
+        } => {
+            material.draw_indirect_instanced(instance_buffer, indirect_buffer, render_pass);
+        }
+        Geometry::IndirectInstancedSplit {
+            instance_buffer,
+            extra_instance_buffer,
+            indirect_buffer,
+        } => material.draw_indirect_instanced_split(
+            instance_buffer,
+            extra_instance_buffer,
+            indirect_buffer,
+            render_pass,
+        ),
+        Geometry::Textured {
+            vertex_buffer,
+            vertex_cnt,
+            bind_group,
+        } => material.draw_textured(vertex_buffer, *vertex_cnt, bind_group, render_pass),
+        Geometry::InstancedMesh {
+            mesh,
+            instance_buffer,
+            instance_cnt,
+        } => material.draw_mesh_instanced(mesh, instance_buffer, *instance_cnt, render_pass),
+    }
+}
+
+/// Records a copy of `texture` into `readback`, which is recreated when the size or the format
+/// of the texture changed. Returns false when the copy was skipped because the staging buffers
+/// are in flight.
+fn capture_texture(
+    wgpu_device: &WgpuDevice,
+    readback: &mut Option<FrameCapture>,
+    encoder: &mut wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+) -> Result<bool, SplooshError> {
+    if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+        return Err(SplooshError::Capture(
+            "The render target does not support copies".to_string(),
+        ));
+    }
+
+    let readback = match readback {
+        Some(readback) if readback.matches(texture) => readback,
+        _ => readback.insert(FrameCapture::new(
+            wgpu_device,
+            texture.width(),
+            texture.height(),
+            texture.format(),
+        )),
+    };
+    Ok(readback.copy_from_texture(encoder, texture))
 }
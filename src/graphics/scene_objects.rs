@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use nalgebra::Matrix4;
+
+use super::render_engine::RenderRequest;
+
+/// Objects drawn at once, each takes a camera uniform per viewport.
+pub const MAX_SCENE_OBJECTS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectHandle(u64);
+
+/// Geometry kept by the render engine and drawn every frame until it's removed, unlike a
+/// `RenderRequest` that is submitted again for every frame.
+pub struct SceneObject {
+    pub name: String,
+    pub request: RenderRequest,
+    /// Applied to the vertex positions before the camera
+    pub transform: Matrix4<f32>,
+    pub visible: bool,
+}
+
+#[derive(Default)]
+pub struct SceneObjects {
+    objects: BTreeMap<ObjectHandle, SceneObject>,
+    next_handle: u64,
+}
+
+impl SceneObjects {
+    /// Adds a visible object, `None` if there are already `MAX_SCENE_OBJECTS`.
+    pub fn add(&mut self, name: &str, request: RenderRequest) -> Option<ObjectHandle> {
+        if self.objects.len() >= MAX_SCENE_OBJECTS {
+            return None;
+        }
+
+        let handle = ObjectHandle(self.next_handle);
+        self.next_handle += 1;
+        self.objects.insert(
+            handle,
+            SceneObject {
+                name: name.to_string(),
+                request,
+                transform: Matrix4::identity(),
+                visible: true,
+            },
+        );
+        Some(handle)
+    }
+
+    pub fn remove(&mut self, handle: ObjectHandle) -> Option<SceneObject> {
+        self.objects.remove(&handle)
+    }
+
+    pub fn get_mut(&mut self, handle: ObjectHandle) -> Option<&mut SceneObject> {
+        self.objects.get_mut(&handle)
+    }
+
+    /// Every object in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (ObjectHandle, &SceneObject)> {
+        self.objects
+            .iter()
+            .map(|(handle, object)| (*handle, object))
+    }
+
+    pub fn visible(&self) -> impl Iterator<Item = &SceneObject> {
+        self.objects.values().filter(|object| object.visible)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::FutureExt as _;
+
+    use super::*;
+    use crate::{
+        graphics::{geometry::Geometry, materials::MaterialType},
+        WgpuDevice,
+    };
+
+    #[test]
+    fn removed_handles_are_not_reused() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let vertex_buffer =
+            wgpu_device.create_buffer_init(&[0.0f32; 6], wgpu::BufferUsages::VERTEX);
+        let request = || RenderRequest {
+            material_type: MaterialType::Line,
+            geometry: Geometry::Array {
+                vertex_buffer: vertex_buffer.clone(),
+                vertex_cnt: 2,
+            },
+        };
+
+        let mut objects = SceneObjects::default();
+        let first = objects.add("First", request()).unwrap();
+        objects.remove(first);
+        let second = objects.add("Second", request()).unwrap();
+        assert_ne!(first, second);
+        assert!(objects.get_mut(first).is_none());
+
+        objects.get_mut(second).unwrap().visible = false;
+        assert_eq!(objects.visible().count(), 0);
+
+        for i in 1..MAX_SCENE_OBJECTS {
+            assert!(objects.add(&format!("Object {i}"), request()).is_some());
+        }
+        assert!(objects.add("One too many", request()).is_none());
+    }
+}
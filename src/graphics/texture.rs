@@ -1,3 +1,5 @@
+use image::RgbaImage;
+
 pub struct Texture {
     texture: wgpu::Texture,
     view: wgpu::TextureView,
@@ -7,11 +9,15 @@ pub struct Texture {
 
 impl Texture {
     pub fn depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        Texture::depth_texture_with_size(device, config.width, config.height)
+    }
+
+    pub fn depth_texture_with_size(device: &wgpu::Device, width: u32, height: u32) -> Self {
         let format = wgpu::TextureFormat::Depth32Float;
 
         let size = wgpu::Extent3d {
-            width: config.width.max(1),
-            height: config.height.max(1),
+            width: width.max(1),
+            height: height.max(1),
             depth_or_array_layers: 1,
         };
 
@@ -46,22 +52,114 @@ impl Texture {
             texture,
             view,
             sampler,
-            format
+            format,
+        }
+    }
+
+    pub fn render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Render target texture"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Render target sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+            format,
         }
     }
-    
+
+    /// Sampled texture holding an sRGB image, repeating horizontally.
+    pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, image: &RgbaImage) -> Self {
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Image texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            image.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * image.width()),
+                rows_per_image: Some(image.height()),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Image sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+            format,
+        }
+    }
+
     pub fn sampler(&self) -> &wgpu::Sampler {
         &self.sampler
     }
-    
+
     pub fn view(&self) -> &wgpu::TextureView {
         &self.view
     }
-    
+
     pub fn texture(&self) -> &wgpu::Texture {
         &self.texture
     }
-    
+
     pub fn format(&self) -> wgpu::TextureFormat {
         self.format
     }
@@ -0,0 +1,95 @@
+use std::marker::PhantomData;
+
+/// Uniform buffer holding `count` values of `T`, each at a multiple of `stride` so that one can
+/// be selected with a dynamic offset. `T` has to match the WGSL struct byte for byte, its size
+/// is checked to be a multiple of 16 like WGSL rounds uniform structs.
+pub struct UniformBuffer<T> {
+    buffer: wgpu::Buffer,
+    stride: u64,
+    count: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> UniformBuffer<T> {
+    const SIZE: u64 = {
+        assert!(
+            std::mem::size_of::<T>() % 16 == 0,
+            "uniforms have to be padded to a multiple of 16 bytes"
+        );
+        std::mem::size_of::<T>() as u64
+    };
+
+    pub fn new(device: &wgpu::Device, label: &str) -> Self {
+        Self::with_count(device, label, 1)
+    }
+
+    pub fn with_count(device: &wgpu::Device, label: &str, count: usize) -> Self {
+        let stride =
+            Self::SIZE.next_multiple_of(device.limits().min_uniform_buffer_offset_alignment as u64);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: count as u64 * stride,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+            stride,
+            count,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, value: &T) {
+        self.write_at(queue, 0, value);
+    }
+
+    pub fn write_at(&self, queue: &wgpu::Queue, index: usize, value: &T) {
+        assert!(index < self.count, "uniform {index} is out of bounds");
+        queue.write_buffer(
+            &self.buffer,
+            index as u64 * self.stride,
+            bytemuck::bytes_of(value),
+        );
+    }
+
+    /// Dynamic offset of the value at `index`.
+    pub fn offset(&self, index: usize) -> u32 {
+        (index as u64 * self.stride) as u32
+    }
+
+    pub fn binding_size() -> wgpu::BufferSize {
+        wgpu::BufferSize::new(Self::SIZE).unwrap()
+    }
+
+    pub fn layout_entry(
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        has_dynamic_offset: bool,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset,
+                min_binding_size: Some(Self::binding_size()),
+            },
+            count: None,
+        }
+    }
+
+    /// Binds a single value, the one at the dynamic offset if there is one.
+    pub fn binding(&self) -> wgpu::BindingResource {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: Some(Self::binding_size()),
+        })
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
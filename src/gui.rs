@@ -1,8 +1,14 @@
+use std::collections::BTreeMap;
+
 use egui::Context;
 use egui_winit::State;
+use serde::{Deserialize, Serialize};
 use winit::{event::WindowEvent, window::Window};
 
-use crate::graphics::{render_engine::GuiRenderRequest, RenderEngine};
+use crate::{
+    graphics::{render_engine::GuiRenderRequest, RenderEngine},
+    input_helper::InputFocus,
+};
 
 pub struct Egui {
     state: State,
@@ -31,21 +37,27 @@ impl Egui {
         self.state.egui_ctx()
     }
 
+    /// Input the gui claimed during the last pass.
+    pub fn input_focus(&self) -> InputFocus {
+        InputFocus {
+            pointer: self.context().wants_pointer_input(),
+            keyboard: self.context().wants_keyboard_input(),
+        }
+    }
+
     pub fn ppp(&self) -> f32 {
         self.context().pixels_per_point()
     }
 
-    pub fn render(&mut self, window: &Window, render_engine: &mut RenderEngine, title: &str, add_contents: impl FnOnce(&mut egui::Ui) -> ()) {
+    pub fn begin_pass(&mut self, window: &Window) -> Context {
         let raw_input = self.state.take_egui_input(window);
         self.state.egui_ctx().begin_pass(raw_input);
 
-        let scale_factor = window.scale_factor() as f32;
+        self.state.egui_ctx().clone()
+    }
 
-        egui::Window::new(title)
-            .resizable(true)
-            .vscroll(true)
-            .default_open(false)
-            .show(self.context(), add_contents);
+    pub fn end_pass(&mut self, window: &Window, render_engine: &mut RenderEngine) {
+        let scale_factor = window.scale_factor() as f32;
 
         self.state.egui_ctx().set_pixels_per_point(scale_factor);
         let full_output = self.state.egui_ctx().end_pass();
@@ -64,3 +76,268 @@ impl Egui {
         });
     }
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum GuiPanel {
+    Stats,
+    Parameters,
+    Scene,
+    Profiler,
+    Inspector,
+    System,
+    Console,
+    KernelEditor,
+    Probes,
+}
+
+impl GuiPanel {
+    pub const ALL: [GuiPanel; 9] = [
+        GuiPanel::Stats,
+        GuiPanel::Parameters,
+        GuiPanel::Scene,
+        GuiPanel::Profiler,
+        GuiPanel::Inspector,
+        GuiPanel::System,
+        GuiPanel::Console,
+        GuiPanel::KernelEditor,
+        GuiPanel::Probes,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            GuiPanel::Stats => "Stats",
+            GuiPanel::Parameters => "Parameters",
+            GuiPanel::Scene => "Scene",
+            GuiPanel::Profiler => "Profiler",
+            GuiPanel::Inspector => "Inspector",
+            GuiPanel::System => "System",
+            GuiPanel::Console => "Console",
+            GuiPanel::KernelEditor => "Kernel editor",
+            GuiPanel::Probes => "Probes",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DockArea {
+    Left,
+    Right,
+    Bottom,
+    Floating,
+}
+
+impl DockArea {
+    pub const ALL: [DockArea; 4] = [
+        DockArea::Left,
+        DockArea::Right,
+        DockArea::Bottom,
+        DockArea::Floating,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DockArea::Left => "Left",
+            DockArea::Right => "Right",
+            DockArea::Bottom => "Bottom",
+            DockArea::Floating => "Floating",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PanelState {
+    pub visible: bool,
+    pub dock: DockArea,
+}
+
+/// Arranges the gui panels into dock areas around the viewport. Panels sharing an area are
+/// stacked as collapsible sections, floating panels get their own window.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DockLayout {
+    panels: BTreeMap<GuiPanel, PanelState>,
+    /// The scene is drawn as an image between the docked panels instead of behind them
+    #[serde(default)]
+    embedded_viewport: bool,
+}
+
+impl Default for DockLayout {
+    fn default() -> Self {
+        let mut panels = BTreeMap::new();
+        panels.insert(
+            GuiPanel::Stats,
+            PanelState {
+                visible: true,
+                dock: DockArea::Left,
+            },
+        );
+        panels.insert(
+            GuiPanel::Parameters,
+            PanelState {
+                visible: true,
+                dock: DockArea::Left,
+            },
+        );
+        panels.insert(
+            GuiPanel::Scene,
+            PanelState {
+                visible: true,
+                dock: DockArea::Right,
+            },
+        );
+        panels.insert(
+            GuiPanel::Profiler,
+            PanelState {
+                visible: false,
+                dock: DockArea::Bottom,
+            },
+        );
+        panels.insert(
+            GuiPanel::Inspector,
+            PanelState {
+                visible: true,
+                dock: DockArea::Right,
+            },
+        );
+        panels.insert(
+            GuiPanel::System,
+            PanelState {
+                visible: false,
+                dock: DockArea::Floating,
+            },
+        );
+        panels.insert(
+            GuiPanel::Console,
+            PanelState {
+                visible: false,
+                dock: DockArea::Bottom,
+            },
+        );
+        panels.insert(
+            GuiPanel::KernelEditor,
+            PanelState {
+                visible: false,
+                dock: DockArea::Floating,
+            },
+        );
+        panels.insert(
+            GuiPanel::Probes,
+            PanelState {
+                visible: false,
+                dock: DockArea::Bottom,
+            },
+        );
+
+        Self {
+            panels,
+            embedded_viewport: false,
+        }
+    }
+}
+
+impl DockLayout {
+    pub fn add_missing_panels(&mut self) {
+        // panels added after the layout was saved fall back to their default placement
+        for (panel, state) in DockLayout::default().panels {
+            self.panels.entry(panel).or_insert(state);
+        }
+    }
+
+    pub fn embedded_viewport(&self) -> bool {
+        self.embedded_viewport
+    }
+
+    pub fn show(&mut self, ctx: &Context, mut add_contents: impl FnMut(GuiPanel, &mut egui::Ui)) {
+        egui::TopBottomPanel::top("dock_menu_bar").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("View", |ui| self.view_menu(ui));
+            });
+        });
+
+        self.show_docked(ctx, DockArea::Left, &mut add_contents);
+        self.show_docked(ctx, DockArea::Right, &mut add_contents);
+        self.show_docked(ctx, DockArea::Bottom, &mut add_contents);
+
+        for (panel, state) in self.panels.iter_mut() {
+            if state.dock != DockArea::Floating || !state.visible {
+                continue;
+            }
+
+            egui::Window::new(panel.title())
+                .open(&mut state.visible)
+                .resizable(true)
+                .vscroll(true)
+                .show(ctx, |ui| add_contents(*panel, ui));
+        }
+    }
+
+    fn view_menu(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("dock_view_menu").show(ui, |ui| {
+            for (panel, state) in self.panels.iter_mut() {
+                ui.checkbox(&mut state.visible, panel.title());
+                egui::ComboBox::from_id_salt(("dock_area", *panel))
+                    .selected_text(state.dock.name())
+                    .show_ui(ui, |ui| {
+                        for dock in DockArea::ALL {
+                            ui.selectable_value(&mut state.dock, dock, dock.name());
+                        }
+                    });
+                ui.end_row();
+            }
+        });
+
+        ui.checkbox(&mut self.embedded_viewport, "Embed viewport")
+            .on_hover_text("Draws the scene between the panels, which then no longer cover it");
+
+        if ui.button("Reset layout").clicked() {
+            *self = DockLayout::default();
+            ui.close_menu();
+        }
+    }
+
+    fn show_docked(
+        &self,
+        ctx: &Context,
+        dock: DockArea,
+        add_contents: &mut impl FnMut(GuiPanel, &mut egui::Ui),
+    ) {
+        let panels: Vec<GuiPanel> = self
+            .panels
+            .iter()
+            .filter(|(_, state)| state.visible && state.dock == dock)
+            .map(|(panel, _)| *panel)
+            .collect();
+
+        if panels.is_empty() {
+            return;
+        }
+
+        let add_panels = |ui: &mut egui::Ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for panel in &panels {
+                    egui::CollapsingHeader::new(panel.title())
+                        .default_open(true)
+                        .show(ui, |ui| add_contents(*panel, ui));
+                }
+            });
+        };
+
+        match dock {
+            DockArea::Left => {
+                egui::SidePanel::left("dock_left")
+                    .resizable(true)
+                    .show(ctx, add_panels);
+            }
+            DockArea::Right => {
+                egui::SidePanel::right("dock_right")
+                    .resizable(true)
+                    .show(ctx, add_panels);
+            }
+            DockArea::Bottom => {
+                egui::TopBottomPanel::bottom("dock_bottom")
+                    .resizable(true)
+                    .show(ctx, add_panels);
+            }
+            DockArea::Floating => {}
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::{collections::BTreeMap, fs::File, io::Write, path::PathBuf, time::Instant};
+
+use nalgebra::Point4;
+use serde::Serialize;
+
+use crate::{
+    config::AdapterConfig,
+    diagnostics::export_diagnostics,
+    domain_decomposition::{adapter_pair, SplitSimulation},
+    fluid_simulation::FluidSimulationConfig,
+    gpu_timer::GpuTimer,
+    probes::{export_probes, ProbeHistory},
+    test_utils::read_buffer,
+    wave_gauges::{export_wave_gauges, WaveGaugeHistory},
+    FluidSimulation, SplooshError, WgpuDevice,
+};
+
+pub struct HeadlessOptions {
+    pub frames: u64,
+    pub dt: f32,
+    pub export_dir: Option<PathBuf>,
+    /// Writes the divergence and pressure of the fluid particles next to the positions, also
+    /// done with diagnostics enabled in the config
+    pub export_diagnostics: bool,
+    /// Particle counts to benchmark, an empty list runs the simulation normally
+    pub benchmark_particles: Vec<usize>,
+    /// Splits the domain between two adapters with a skin of this many smoothing radii
+    pub split_skin: Option<f32>,
+}
+
+/// One line of the benchmark output.
+#[derive(Serialize)]
+struct BenchmarkResult {
+    particles: usize,
+    workgroup_size: u32,
+    /// Whether p / ρ² came from the pressure pass instead of the force kernel
+    pressure_pass: bool,
+    /// Whether the density and force kernels ran with subgroup operations
+    subgroups: bool,
+    frames: u64,
+    total_s: f64,
+    mean_frame_ms: f64,
+    fps: f64,
+    /// Mean GPU time of each step stage, null without timestamp support
+    mean_pass_ms: Option<BTreeMap<&'static str, f64>>,
+}
+
+pub async fn run_headless(
+    config: FluidSimulationConfig,
+    adapter: &AdapterConfig,
+    options: HeadlessOptions,
+) -> Result<(), SplooshError> {
+    if let Some(skin) = options.split_skin {
+        return run_split(config, adapter, skin, &options).await;
+    }
+
+    let wgpu_device = WgpuDevice::with_adapter_config(adapter).await?;
+    config.max_particle_cnt(&wgpu_device.device.limits())?;
+    if !options.benchmark_particles.is_empty() {
+        return run_benchmark(config, &wgpu_device, &options);
+    }
+
+    let mut fluid_sim = FluidSimulation::new(config, &wgpu_device);
+    if options.export_diagnostics {
+        fluid_sim.set_diagnostics(true);
+    }
+
+    if let Some(export_dir) = &options.export_dir {
+        std::fs::create_dir_all(export_dir)?;
+    }
+    let sample_probes = options.export_dir.is_some() && !fluid_sim.config().probes.is_empty();
+    let mut probe_history = ProbeHistory::default();
+    let measure_waves = options.export_dir.is_some() && !fluid_sim.config().wave_gauges.is_empty();
+    let mut wave_gauge_history = WaveGaugeHistory::default();
+
+    let staging_buffer = wgpu_device.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Export staging buffer"),
+        size: fluid_sim.positions().size(),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    for frame in 0..options.frames {
+        let mut encoder =
+            wgpu_device
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Headless encoder"),
+                });
+
+        fluid_sim.step_fn(options.dt)(&mut encoder, &wgpu_device.queue);
+        if fluid_sim.config().diagnostics {
+            fluid_sim.diagnostics_fn()(&mut encoder, &wgpu_device.queue);
+        }
+        if sample_probes {
+            fluid_sim.probes_fn()(&mut encoder, &wgpu_device.queue);
+        }
+        if measure_waves {
+            fluid_sim.wave_gauges_fn()(&mut encoder, &wgpu_device.queue);
+        }
+
+        if options.export_dir.is_some() {
+            encoder.copy_buffer_to_buffer(
+                fluid_sim.positions(),
+                0,
+                &staging_buffer,
+                0,
+                staging_buffer.size(),
+            );
+        }
+
+        wgpu_device.submit(encoder);
+        wgpu_device.device.poll(wgpu::Maintain::Wait);
+
+        if let Some(export_dir) = &options.export_dir {
+            let positions = read_buffer::<Point4<f32>>(&wgpu_device, &staging_buffer);
+            export_positions(
+                &export_dir.join(format!("frame_{frame:05}.csv")),
+                &positions,
+            )?;
+
+            if let Some(diagnostics) = fluid_sim.read_diagnostics(&wgpu_device.device) {
+                export_diagnostics(
+                    &export_dir.join(format!("diagnostics_{frame:05}.csv")),
+                    fluid_sim.ghost_particle_cnt(),
+                    &diagnostics?,
+                )?;
+            }
+        }
+        if sample_probes {
+            probe_history.push(&fluid_sim.read_probes(&wgpu_device.device)?);
+        }
+        if measure_waves {
+            wave_gauge_history.push(&fluid_sim.read_wave_gauges(&wgpu_device.device)?);
+        }
+    }
+
+    if let Some(export_dir) = options.export_dir.as_ref().filter(|_| sample_probes) {
+        export_probes(
+            &export_dir.join("probes.csv"),
+            &fluid_sim.config().probes,
+            &probe_history,
+        )?;
+    }
+    if let Some(export_dir) = options.export_dir.as_ref().filter(|_| measure_waves) {
+        export_wave_gauges(
+            &export_dir.join("wave_gauges.csv"),
+            &fluid_sim.config().wave_gauges,
+            &wave_gauge_history,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Steps the simulation split between two adapters, see `SplitSimulation`, and exports the
+/// fluid particles of every frame.
+async fn run_split(
+    config: FluidSimulationConfig,
+    adapter: &AdapterConfig,
+    skin: f32,
+    options: &HeadlessOptions,
+) -> Result<(), SplooshError> {
+    let devices = adapter_pair(adapter).await?;
+    let mut split_sim = SplitSimulation::new(config, devices, skin)?;
+    if let Some(export_dir) = &options.export_dir {
+        std::fs::create_dir_all(export_dir)?;
+    }
+
+    for frame in 0..options.frames {
+        split_sim.step(options.dt)?;
+
+        if let Some(export_dir) = &options.export_dir {
+            export_positions(
+                &export_dir.join(format!("frame_{frame:05}.csv")),
+                &split_sim.read_fluid_particles()?.positions,
+            )?;
+        }
+    }
+    let [below, above] = split_sim.owned_particle_cnts();
+    tracing::info!(below, above, "Split simulation finished");
+
+    Ok(())
+}
+
+/// Steps a fresh simulation for every particle count and prints one line of JSON per count,
+/// with the GPU time of each step stage if the adapter supports timestamps. Every count runs
+/// with the pressure recomputed for every pair first, then with the pressure pass, and then
+/// with the subgroup kernels on adapters with subgroup operations, one line each.
+fn run_benchmark(
+    config: FluidSimulationConfig,
+    wgpu_device: &WgpuDevice,
+    options: &HeadlessOptions,
+) -> Result<(), SplooshError> {
+    // each variant adds one optimization to the one before
+    let mut variants = vec![(false, false), (true, false)];
+    if wgpu_device.supports_subgroups() {
+        variants.push((true, true));
+    }
+
+    for &particle_cnt in &options.benchmark_particles {
+        for &(pressure_pass, subgroup_kernels) in &variants {
+            let fluid_sim = FluidSimulation::new(
+                FluidSimulationConfig {
+                    particle_cnt,
+                    pressure_pass,
+                    subgroup_kernels,
+                    ..config.clone()
+                },
+                wgpu_device,
+            );
+            let result = benchmark_simulation(&fluid_sim, wgpu_device, options)?;
+            println!("{}", serde_json::to_string(&result)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn benchmark_simulation(
+    fluid_sim: &FluidSimulation,
+    wgpu_device: &WgpuDevice,
+    options: &HeadlessOptions,
+) -> Result<BenchmarkResult, SplooshError> {
+    let stages = fluid_sim.step_stages(options.dt);
+    let timestamp_cnt = stages.len() as u32 + 1;
+    let timer = GpuTimer::new(wgpu_device, timestamp_cnt);
+    let mut stage_times = vec![0.0; stages.len()];
+
+    let mut frame_times = Vec::with_capacity(options.frames as usize);
+    let start_time = Instant::now();
+
+    for _ in 0..options.frames {
+        let frame_start = Instant::now();
+        let mut encoder =
+            wgpu_device
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Benchmark encoder"),
+                });
+
+        for (i, (_, stage)) in stages.iter().enumerate() {
+            if let Some(timer) = &timer {
+                timer.write_timestamp(&mut encoder, i as u32);
+            }
+            stage(&mut encoder, &wgpu_device.queue);
+        }
+        if let Some(timer) = &timer {
+            timer.write_timestamp(&mut encoder, timestamp_cnt - 1);
+            timer.resolve(&mut encoder, timestamp_cnt);
+        }
+
+        wgpu_device.submit(encoder);
+        match &timer {
+            Some(timer) => {
+                let intervals = timer.read_intervals(&wgpu_device.device, timestamp_cnt)?;
+                for (total, interval) in stage_times.iter_mut().zip(intervals) {
+                    *total += interval;
+                }
+            }
+            None => {
+                wgpu_device.device.poll(wgpu::Maintain::Wait);
+            }
+        }
+
+        frame_times.push((Instant::now() - frame_start).as_secs_f64() * 1000.0);
+    }
+
+    let total_time = (Instant::now() - start_time).as_secs_f64();
+    let frames = frame_times.len().max(1) as f64;
+    let mean = frame_times.iter().sum::<f64>() / frames;
+
+    Ok(BenchmarkResult {
+        particles: fluid_sim.fluid_particle_cnt(),
+        workgroup_size: fluid_sim.workgroup_size(),
+        pressure_pass: fluid_sim.config().pressure_pass,
+        subgroups: fluid_sim.subgroup_kernels(),
+        frames: options.frames,
+        total_s: total_time,
+        mean_frame_ms: mean,
+        fps: options.frames as f64 / total_time,
+        mean_pass_ms: timer.map(|_| {
+            stages
+                .iter()
+                .zip(&stage_times)
+                .map(|((name, _), total)| (*name, total / frames))
+                .collect()
+        }),
+    })
+}
+
+fn export_positions(path: &PathBuf, positions: &[Point4<f32>]) -> Result<(), SplooshError> {
+    let mut file = std::io::BufWriter::new(File::create(path)?);
+    writeln!(file, "x,y,z")?;
+    for p in positions {
+        writeln!(file, "{},{},{}", p.x, p.y, p.z)?;
+    }
+
+    Ok(())
+}
@@ -1,37 +1,101 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use winit::{
-    event::{ElementState, KeyEvent, MouseButton},
+    event::{ElementState, KeyEvent, MouseButton, TouchPhase},
     keyboard::PhysicalKey,
 };
 
+/// Cursor movement in pixels up to which a press and release still count as a click.
+const CLICK_DISTANCE: f32 = 4.0;
+
+/// Input taken by the gui. While it has the focus, the input is hidden from everything else that
+/// reads the `InputHelper`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InputFocus {
+    /// The pointer is over a gui area or dragging a widget
+    pub pointer: bool,
+    /// A text field has the keyboard focus
+    pub keyboard: bool,
+}
+
 pub struct InputHelper {
+    gui_focus: InputFocus,
     mouse_button_map: HashMap<MouseButton, bool>,
-    keyboard_button_map: HashMap<PhysicalKey, bool>,
+    held_keys: HashSet<PhysicalKey>,
+    /// Edges since the last `reset`, key repeats of a held key are not counted
+    just_pressed_keys: HashSet<PhysicalKey>,
+    just_released_keys: HashSet<PhysicalKey>,
+    press_positions: HashMap<MouseButton, (f32, f32)>,
+    clicked_buttons: HashSet<MouseButton>,
+    cursor_position: Option<(f32, f32)>,
+    /// Last position of every finger on the screen, by touch id
+    touches: HashMap<u64, (f32, f32)>,
 
     mouse_dx: f32,
     mouse_dy: f32,
     mouse_dw: f32,
+
+    touch_drag: (f32, f32),
+    touch_pan: (f32, f32),
+    pinch_scale: f32,
+}
+
+impl Default for InputHelper {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl InputHelper {
     pub fn new() -> Self {
         Self {
+            gui_focus: InputFocus::default(),
             mouse_button_map: HashMap::new(),
-            keyboard_button_map: HashMap::new(),
+            held_keys: HashSet::new(),
+            just_pressed_keys: HashSet::new(),
+            just_released_keys: HashSet::new(),
+            press_positions: HashMap::new(),
+            clicked_buttons: HashSet::new(),
+            cursor_position: None,
+            touches: HashMap::new(),
             mouse_dx: 0.0,
             mouse_dy: 0.0,
             mouse_dw: 0.0,
+            touch_drag: (0.0, 0.0),
+            touch_pan: (0.0, 0.0),
+            pinch_scale: 1.0,
         }
     }
 
     pub fn key_event(&mut self, event: &KeyEvent) {
-        self.keyboard_button_map
-            .insert(event.physical_key, event.state.is_pressed());
+        if event.state.is_pressed() {
+            if self.held_keys.insert(event.physical_key) {
+                self.just_pressed_keys.insert(event.physical_key);
+            }
+        } else if self.held_keys.remove(&event.physical_key) {
+            self.just_released_keys.insert(event.physical_key);
+        }
     }
 
     pub fn mouse_key_event(&mut self, state: &ElementState, button: MouseButton) {
         self.mouse_button_map.insert(button, state.is_pressed());
+
+        let Some(cursor) = self.cursor_position else {
+            return;
+        };
+        if state.is_pressed() {
+            self.press_positions.insert(button, cursor);
+        } else if let Some(pressed) = self.press_positions.remove(&button) {
+            let distance = (cursor.0 - pressed.0).hypot(cursor.1 - pressed.1);
+            if distance < CLICK_DISTANCE {
+                self.clicked_buttons.insert(button);
+            }
+        }
+    }
+
+    /// Position in physical pixels from the top left corner of the window.
+    pub fn cursor_moved(&mut self, position: (f32, f32)) {
+        self.cursor_position = Some(position);
     }
 
     pub fn mouse_moved(&mut self, delta: (f32, f32)) {
@@ -43,27 +107,187 @@ impl InputHelper {
         self.mouse_dw += delta;
     }
 
+    /// Position in physical pixels, like the cursor. One finger drags, two fingers pan and
+    /// pinch, the gestures of more fingers are ignored.
+    pub fn touch_event(&mut self, id: u64, phase: TouchPhase, position: (f32, f32)) {
+        match phase {
+            TouchPhase::Started => {
+                self.touches.insert(id, position);
+            }
+            TouchPhase::Moved => {
+                let before = self.two_finger_gesture();
+                let Some(last) = self.touches.insert(id, position) else {
+                    return;
+                };
+
+                match (self.touches.len(), before, self.two_finger_gesture()) {
+                    (1, _, _) => {
+                        self.touch_drag.0 += position.0 - last.0;
+                        self.touch_drag.1 += position.1 - last.1;
+                    }
+                    (2, Some((center, distance)), Some((new_center, new_distance))) => {
+                        self.touch_pan.0 += new_center.0 - center.0;
+                        self.touch_pan.1 += new_center.1 - center.1;
+                        if distance > 0.0 {
+                            self.pinch_scale *= new_distance / distance;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.remove(&id);
+            }
+        }
+    }
+
+    /// Center of and distance between the fingers while exactly two are down.
+    fn two_finger_gesture(&self) -> Option<((f32, f32), f32)> {
+        let mut touches = self.touches.values();
+        let (Some(a), Some(b), None) = (touches.next(), touches.next(), touches.next()) else {
+            return None;
+        };
+
+        let center = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+        Some((center, (a.0 - b.0).hypot(a.1 - b.1)))
+    }
+
+    /// Releases everything, keys let go while the window was unfocused never report it.
+    pub fn focus_lost(&mut self) {
+        self.just_released_keys.extend(self.held_keys.drain());
+        self.mouse_button_map.clear();
+        self.press_positions.clear();
+        self.touches.clear();
+    }
+
     pub fn reset(&mut self) {
         self.mouse_dx = 0.0;
         self.mouse_dy = 0.0;
         self.mouse_dw = 0.0;
-        self.keyboard_button_map.clear();
+        self.touch_drag = (0.0, 0.0);
+        self.touch_pan = (0.0, 0.0);
+        self.pinch_scale = 1.0;
+        self.just_pressed_keys.clear();
+        self.just_released_keys.clear();
+        self.clicked_buttons.clear();
+    }
+
+    /// Decides which of the following frames' input is hidden, the events are tracked either way
+    /// so nothing is stuck once the gui lets go.
+    pub fn set_gui_focus(&mut self, focus: InputFocus) {
+        self.gui_focus = focus;
+    }
+
+    pub fn gui_focus(&self) -> InputFocus {
+        self.gui_focus
+    }
+
+    /// Went down this frame, holding the key doesn't report it again.
+    pub fn was_just_pressed(&self, key: PhysicalKey) -> bool {
+        !self.gui_focus.keyboard && self.just_pressed_keys.contains(&key)
+    }
+
+    pub fn was_just_released(&self, key: PhysicalKey) -> bool {
+        !self.gui_focus.keyboard && self.just_released_keys.contains(&key)
     }
 
-    pub fn is_key_pressed(&self, key: PhysicalKey) -> bool {
-        *self.keyboard_button_map.get(&key).unwrap_or(&false)
+    pub fn just_pressed_keys(&self) -> impl Iterator<Item = PhysicalKey> + '_ {
+        let keys = (!self.gui_focus.keyboard).then_some(&self.just_pressed_keys);
+        keys.into_iter().flatten().copied()
+    }
+
+    pub fn is_key_held(&self, key: PhysicalKey) -> bool {
+        !self.gui_focus.keyboard && self.held_keys.contains(&key)
     }
 
     pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
-        *self.mouse_button_map.get(&button).unwrap_or(&false)
+        !self.gui_focus.pointer && *self.mouse_button_map.get(&button).unwrap_or(&false)
+    }
+
+    /// Released this frame without dragging the cursor away from where it was pressed.
+    pub fn is_mouse_button_clicked(&self, button: MouseButton) -> bool {
+        !self.gui_focus.pointer && self.clicked_buttons.contains(&button)
+    }
+
+    pub fn cursor_position(&self) -> Option<(f32, f32)> {
+        self.cursor_position
     }
 
     pub fn mouse_delta(&self) -> (f32, f32) {
-        (self.mouse_dx, self.mouse_dy)
+        self.unless_gui_pointer((self.mouse_dx, self.mouse_dy), (0.0, 0.0))
     }
-    
+
     pub fn mouse_wheel_delta(&self) -> f32 {
-        self.mouse_dw
+        self.unless_gui_pointer(self.mouse_dw, 0.0)
+    }
+
+    pub fn touch_cnt(&self) -> usize {
+        self.unless_gui_pointer(self.touches.len(), 0)
+    }
+
+    /// Movement of a single finger this frame.
+    pub fn touch_drag(&self) -> (f32, f32) {
+        self.unless_gui_pointer(self.touch_drag, (0.0, 0.0))
+    }
+
+    /// Movement of the center between two fingers this frame.
+    pub fn touch_pan(&self) -> (f32, f32) {
+        self.unless_gui_pointer(self.touch_pan, (0.0, 0.0))
+    }
+
+    /// How much the distance between two fingers grew this frame, 1 without a pinch.
+    pub fn pinch_scale(&self) -> f32 {
+        self.unless_gui_pointer(self.pinch_scale, 1.0)
+    }
+
+    fn unless_gui_pointer<T>(&self, value: T, idle: T) -> T {
+        if self.gui_focus.pointer {
+            idle
+        } else {
+            value
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_fingers_pinch_and_pan() {
+        let mut input_helper = InputHelper::new();
+        input_helper.touch_event(0, TouchPhase::Started, (100.0, 100.0));
+        input_helper.touch_event(1, TouchPhase::Started, (200.0, 100.0));
+        input_helper.touch_event(1, TouchPhase::Moved, (300.0, 100.0));
+
+        assert_eq!(input_helper.pinch_scale(), 2.0);
+        assert_eq!(input_helper.touch_pan(), (50.0, 0.0));
+        assert_eq!(input_helper.touch_drag(), (0.0, 0.0));
+
+        input_helper.reset();
+        input_helper.touch_event(1, TouchPhase::Ended, (300.0, 100.0));
+        input_helper.touch_event(0, TouchPhase::Moved, (110.0, 90.0));
+
+        assert_eq!(input_helper.pinch_scale(), 1.0);
+        assert_eq!(input_helper.touch_drag(), (10.0, -10.0));
+    }
+
+    #[test]
+    fn gui_focus_hides_pointer_input() {
+        let mut input_helper = InputHelper::new();
+        input_helper.cursor_moved((10.0, 10.0));
+        input_helper.mouse_key_event(&ElementState::Pressed, MouseButton::Left);
+        input_helper.mouse_moved((5.0, 0.0));
+
+        input_helper.set_gui_focus(InputFocus {
+            pointer: true,
+            keyboard: false,
+        });
+        assert!(!input_helper.is_mouse_button_pressed(MouseButton::Left));
+        assert_eq!(input_helper.mouse_delta(), (0.0, 0.0));
+
+        input_helper.set_gui_focus(InputFocus::default());
+        assert!(input_helper.is_mouse_button_pressed(MouseButton::Left));
+        assert_eq!(input_helper.mouse_delta(), (5.0, 0.0));
     }
-    
 }
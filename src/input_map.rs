@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+use crate::input_helper::InputHelper;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Pause,
+    Reset,
+    /// Advances a paused simulation by a single step
+    Step,
+    ToggleCamera,
+    FrameBounds,
+    Screenshot,
+    Fullscreen,
+    /// Reverts the last edit of the scene or its parameters, triggered together with Ctrl
+    Undo,
+    /// Triggered together with Ctrl
+    Redo,
+}
+
+impl Action {
+    pub const ALL: [Action; 9] = [
+        Action::Pause,
+        Action::Reset,
+        Action::Step,
+        Action::ToggleCamera,
+        Action::FrameBounds,
+        Action::Screenshot,
+        Action::Fullscreen,
+        Action::Undo,
+        Action::Redo,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Pause => "Pause",
+            Action::Reset => "Reset",
+            Action::Step => "Step",
+            Action::ToggleCamera => "Toggle camera",
+            Action::FrameBounds => "Frame bounds",
+            Action::Screenshot => "Screenshot",
+            Action::Fullscreen => "Fullscreen",
+            Action::Undo => "Undo (Ctrl)",
+            Action::Redo => "Redo (Ctrl)",
+        }
+    }
+}
+
+/// Keys bound to the named actions, saved with the settings. An action can have several keys,
+/// or none to disable it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputMap {
+    bindings: BTreeMap<Action, Vec<KeyCode>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let bindings = BTreeMap::from([
+            (Action::Pause, vec![KeyCode::Space]),
+            (Action::Reset, vec![KeyCode::KeyR]),
+            (Action::Step, vec![KeyCode::Period]),
+            (Action::ToggleCamera, vec![KeyCode::KeyF]),
+            (Action::FrameBounds, vec![KeyCode::Home]),
+            (Action::Screenshot, vec![KeyCode::F12]),
+            (Action::Fullscreen, vec![KeyCode::F11]),
+            (Action::Undo, vec![KeyCode::KeyZ]),
+            (Action::Redo, vec![KeyCode::KeyY]),
+        ]);
+
+        Self { bindings }
+    }
+}
+
+impl InputMap {
+    pub fn add_missing_actions(&mut self) {
+        // actions added after the settings were saved get their default keys
+        for (action, keys) in InputMap::default().bindings {
+            self.bindings.entry(action).or_insert(keys);
+        }
+    }
+
+    /// True if a key bound to `action` was pressed this frame.
+    pub fn is_triggered(&self, input_helper: &InputHelper, action: Action) -> bool {
+        self.keys(action)
+            .iter()
+            .any(|&key| input_helper.was_just_pressed(PhysicalKey::Code(key)))
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Replaces the keys of `action`. A key can only trigger one action, so it is removed from
+    /// the others.
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        for keys in self.bindings.values_mut() {
+            keys.retain(|&k| k != key);
+        }
+        self.bindings.insert(action, vec![key]);
+    }
+
+    pub fn unbind(&mut self, action: Action) {
+        self.bindings.insert(action, Vec::new());
+    }
+
+    /// Keys of `action` for button labels, e.g. "F12".
+    pub fn key_label(&self, action: Action) -> String {
+        let keys: Vec<String> = self
+            .keys(action)
+            .iter()
+            .map(|key| format!("{key:?}"))
+            .collect();
+
+        if keys.is_empty() {
+            "unbound".to_string()
+        } else {
+            keys.join(", ")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_a_key_moves_it_between_actions() {
+        let mut input_map = InputMap::default();
+        input_map.bind(Action::Screenshot, KeyCode::Space);
+
+        assert_eq!(input_map.keys(Action::Screenshot), &[KeyCode::Space]);
+        assert!(input_map.keys(Action::Pause).is_empty());
+        assert_eq!(input_map.key_label(Action::Pause), "unbound");
+    }
+
+    #[test]
+    fn missing_actions_get_default_keys() {
+        let mut input_map: InputMap = ron::from_str("(bindings: {Pause: [KeyP]})").unwrap();
+        input_map.add_missing_actions();
+
+        assert_eq!(input_map.keys(Action::Pause), &[KeyCode::KeyP]);
+        assert_eq!(input_map.keys(Action::Screenshot), &[KeyCode::F12]);
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, Weak};
+
+use pollster::FutureExt as _;
+
+/// Compiled WGSL of a compute task. The pipeline can be replaced while the task is in use, the
+/// next dispatch runs the new one.
+pub struct Kernel {
+    name: String,
+    /// Source the task was created with
+    generated_source: String,
+    source: Mutex<String>,
+    layout: Arc<wgpu::PipelineLayout>,
+    pipeline: RwLock<wgpu::ComputePipeline>,
+}
+
+impl Kernel {
+    /// Compiles `source`, errors are logged and leave an invalid pipeline behind.
+    pub fn new(
+        device: &wgpu::Device,
+        name: &str,
+        layout: Arc<wgpu::PipelineLayout>,
+        source: String,
+    ) -> Self {
+        let (pipeline, error) = create_pipeline(device, name, &layout, &source);
+        if let Some(err) = error {
+            tracing::error!("Failed to create the {name} pipeline: {err}");
+        }
+
+        Self {
+            name: name.to_string(),
+            generated_source: source.clone(),
+            source: Mutex::new(source),
+            layout,
+            pipeline: RwLock::new(pipeline),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Source of the pipeline in use.
+    pub fn source(&self) -> String {
+        self.source.lock().unwrap().clone()
+    }
+
+    pub fn generated_source(&self) -> &str {
+        &self.generated_source
+    }
+
+    pub fn is_edited(&self) -> bool {
+        *self.source.lock().unwrap() != self.generated_source
+    }
+
+    /// Compiles `source` and swaps it in. On an error the previous pipeline stays in use.
+    pub fn recompile(&self, device: &wgpu::Device, source: &str) -> Result<(), String> {
+        let _span = tracing::debug_span!("recompile_kernel", name = self.name).entered();
+        let (pipeline, error) = create_pipeline(device, &self.name, &self.layout, source);
+        if let Some(err) = error {
+            return Err(err.to_string());
+        }
+
+        *self.pipeline.write().unwrap() = pipeline;
+        *self.source.lock().unwrap() = source.to_string();
+        Ok(())
+    }
+
+    pub(crate) fn pipeline(&self) -> RwLockReadGuard<'_, wgpu::ComputePipeline> {
+        self.pipeline.read().unwrap()
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    name: &str,
+    layout: &wgpu::PipelineLayout,
+    source: &str,
+) -> (wgpu::ComputePipeline, Option<wgpu::Error>) {
+    // shader errors are reported with the name of the task instead of as uncaptured errors
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(&format!("{name} shader")),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(&format!("{name} pipeline")),
+        layout: Some(layout),
+        module: &shader,
+        entry_point: Some("main"),
+        compilation_options: wgpu::PipelineCompilationOptions::default(),
+        cache: None,
+    });
+    let error = device.pop_error_scope().block_on();
+
+    (pipeline, error)
+}
+
+/// Kernels of the compute tasks created through `WgpuDevice`, for the kernel editor. Like the
+/// resource registry it only keeps them until their task is dropped, edits are lost when the
+/// simulation is rebuilt.
+#[derive(Clone, Default)]
+pub struct KernelRegistry {
+    kernels: Arc<Mutex<Vec<Weak<Kernel>>>>,
+}
+
+impl KernelRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track(&self, kernel: &Arc<Kernel>) {
+        self.kernels.lock().unwrap().push(Arc::downgrade(kernel));
+    }
+
+    /// The kernels still in use, in the order they were created.
+    pub fn kernels(&self) -> Vec<Arc<Kernel>> {
+        let mut kernels = self.kernels.lock().unwrap();
+        kernels.retain(|kernel| kernel.strong_count() > 0);
+        kernels.iter().filter_map(Weak::upgrade).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::FutureExt as _;
+
+    use crate::{compute_task::ComputeTask, WgpuDevice};
+
+    #[test]
+    fn invalid_edits_keep_the_previous_pipeline() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let source = "@compute @workgroup_size(1) fn main() {}";
+        let task = ComputeTask::new(
+            &wgpu_device,
+            "Test",
+            &[],
+            &[],
+            &[],
+            source.into(),
+            (1, 1, 1),
+        );
+
+        let kernels = wgpu_device.kernels.kernels();
+        assert_eq!(kernels.len(), 1);
+        let kernel = &kernels[0];
+        assert_eq!(kernel.name(), "Test");
+
+        assert!(kernel
+            .recompile(&wgpu_device.device, "fn main() { let x = ; }")
+            .is_err());
+        assert_eq!(kernel.source(), source);
+        assert!(!kernel.is_edited());
+
+        let edited = "@compute @workgroup_size(2) fn main() {}";
+        assert!(kernel.recompile(&wgpu_device.device, edited).is_ok());
+        assert_eq!(kernel.source(), edited);
+        assert!(kernel.is_edited());
+
+        drop(kernels);
+        drop(task);
+        assert!(wgpu_device.kernels.kernels().is_empty());
+    }
+}
@@ -1,35 +1,154 @@
-use std::error::Error;
 use application::Application;
-use winit;
+use clap::Parser;
+use cli::Cli;
+use pollster::FutureExt;
 
+pub mod anisotropy;
+pub mod annotations;
 pub mod application;
-pub mod graphics;
-pub mod input_helper;
-pub mod fluid_simulation;
-pub mod gui;
-pub mod wgpu_render_device;
 pub mod application_state;
+#[cfg(feature = "bevy")]
+pub mod bevy_plugin;
+pub mod camera_animation;
 pub mod camera_controller;
+pub mod cli;
+pub mod clip_recorder;
+pub mod colormap;
 pub mod compute_task;
-pub mod wgpu_device;
-pub mod test_utils;
+pub mod config;
+pub mod console;
+pub mod debris;
+pub mod density_filter;
+pub mod density_slice;
+pub mod depth_sort;
+pub mod diagnostics;
+pub mod domain_decomposition;
+pub mod dye;
+pub mod edit_history;
+pub mod error;
+#[cfg(feature = "sploosh-ffi")]
+pub mod ffi;
+pub mod fluid_simulation;
+pub mod gpu_timer;
+pub mod graphics;
+pub mod gui;
+pub mod headless;
+pub mod input_helper;
+pub mod input_map;
+pub mod kernel_registry;
+pub mod logging;
+pub mod minimap;
+pub mod neighbor_count;
+pub mod offline_render;
+pub mod particle_inspector;
+pub mod particle_trails;
+pub mod pass_validation;
+pub mod playback;
+pub mod probes;
+pub mod project;
+pub mod readback;
+pub mod remote;
+pub mod resource_registry;
+pub mod rest_state;
+pub mod scene;
+pub mod screen_density;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod settings;
+pub mod simulation_worker;
+pub mod soak;
+pub mod soft_body;
 pub mod spatial_lookup;
+pub mod test_utils;
+pub mod timeline;
+pub mod validation;
+pub mod velocity_lines;
+pub mod wave_gauges;
+pub mod wgpu_device;
+pub mod wgpu_render_device;
 
-
-pub use wgpu_render_device::WgpuRenderDevice;
-pub use wgpu_device::WgpuDevice;
-pub use fluid_simulation::FluidSimulation;
 pub use application_state::ApplicationState;
 pub use camera_controller::CameraController;
 pub use compute_task::ComputeTask;
+pub use error::SplooshError;
+pub use fluid_simulation::FluidSimulation;
+pub use scene::{Scene, SceneContext};
 pub use spatial_lookup::SpatialLookup;
+pub use wgpu_device::WgpuDevice;
+pub use wgpu_render_device::WgpuRenderDevice;
+
+pub fn run() -> Result<(), SplooshError> {
+    let cli = Cli::parse();
+    let _trace_guard = logging::init(cli.log_level, cli.trace.as_deref())?;
+    let config = cli.load_config()?;
+
+    if let Some(options) = cli.soak_options() {
+        let report = soak::run_soak(
+            config.simulation.unwrap_or_default(),
+            &config.adapter,
+            &options,
+        )
+        .block_on()?;
+        tracing::info!(
+            frames = report.frames,
+            min_density = report.min_density,
+            max_density = report.max_density,
+            diverged_frame = ?report.divergence.as_ref().map(|(frame, _)| frame),
+            "Soak finished"
+        );
+
+        return match report.divergence {
+            Some((frame, divergence)) => Err(SplooshError::Diverged(format!(
+                "The simulation diverged at frame {frame}: {divergence}"
+            ))),
+            None => Ok(()),
+        };
+    }
+
+    if let Some(options) = cli.validation_options() {
+        let results = validation::run_validation(&config.adapter, &options).block_on()?;
+        for result in &results {
+            let (case, metric) = (result.case.name(), result.metric);
+            let (error, tolerance) = (result.error, result.tolerance);
+            if result.passed() {
+                tracing::info!(case, metric, error, tolerance, "Validation passed");
+            } else {
+                tracing::warn!(case, metric, error, tolerance, "Validation failed");
+            }
+        }
+
+        let failed: Vec<_> = results
+            .iter()
+            .filter(|result| !result.passed())
+            .map(|result| result.case.name())
+            .collect();
+        return match failed.is_empty() {
+            true => Ok(()),
+            false => Err(SplooshError::Validation(format!(
+                "Validation failed for {}",
+                failed.join(", ")
+            ))),
+        };
+    }
+
+    if cli.is_headless() {
+        return headless::run_headless(
+            config.simulation.unwrap_or_default(),
+            &config.adapter,
+            cli.headless_options(),
+        )
+        .block_on();
+    }
 
-pub fn run() -> Result<(), Box<dyn Error>> {
-    let event_loop = winit::event_loop::EventLoop::new()?;
-    event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
+    let application = Application::with_config(config)
+        .with_frame_limit(cli.frame_limit())
+        .with_offline_options(cli.offline_options());
 
-    let mut app = Application::new();
-    event_loop.run_app(&mut app)?;    
+    #[cfg(feature = "scripting")]
+    let application = match &cli.script {
+        Some(path) => application.with_scene(scripting::ScriptScene::from_file(path)?),
+        None => application,
+    };
 
-    Ok(())
-}
\ No newline at end of file
+    application.run()
+}
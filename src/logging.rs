@@ -0,0 +1,78 @@
+use std::{fs::File, path::Path};
+
+use clap::ValueEnum;
+use tracing_subscriber::{
+    filter::{EnvFilter, Targets},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    Layer,
+};
+
+use crate::{console::ConsoleLayer, SplooshError};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    Error,
+    #[default]
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn directive(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+/// Writes the Chrome trace when it's dropped, has to live until the application exits.
+pub struct TraceGuard {
+    _chrome: Option<tracing_chrome::FlushGuard>,
+}
+
+/// Logs to stderr at `level`, `RUST_LOG` overrides it, and collects warnings and errors for the
+/// console panel. wgpu only logs warnings unless `RUST_LOG` asks for more. With `chrome_trace`
+/// the spans of sploosh are also written to that file, it can be opened in `chrome://tracing` or
+/// Perfetto.
+pub fn init(level: LogLevel, chrome_trace: Option<&Path>) -> Result<TraceGuard, SplooshError> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(format!(
+            "{},wgpu_core=warn,wgpu_hal=warn,naga=warn",
+            level.directive()
+        ))
+    });
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_filter(filter);
+
+    let (chrome_layer, chrome_guard) = match chrome_trace {
+        Some(path) => {
+            let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+                .writer(File::create(path)?)
+                .include_args(true)
+                .build();
+            let layer =
+                layer.with_filter(Targets::new().with_target("sploosh", tracing::Level::TRACE));
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    // an application embedding sploosh may have set up its own subscriber, which is kept
+    let _ = tracing_subscriber::registry()
+        .with(fmt_layer)
+        .with(ConsoleLayer)
+        .with(chrome_layer)
+        .try_init();
+
+    Ok(TraceGuard {
+        _chrome: chrome_guard,
+    })
+}
@@ -1,5 +1,8 @@
 use sploosh::run;
 
 fn main() {
-    run().unwrap();
+    if let Err(err) = run() {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
 }
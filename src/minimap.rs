@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    colormap::{Colormap, ColormapTexture, COLORMAP_SHADER},
+    graphics::render_engine::GenericRequest,
+    ComputeTask, WgpuDevice,
+};
+
+/// Texels along the longer horizontal side of the bounding box.
+pub const MINIMAP_RESOLUTION: u32 = 128;
+
+/// Top-down view of the whole domain in a corner of the window, colored by the number of
+/// particles above each point.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MinimapConfig {
+    pub colormap: Colormap,
+    /// Width of the inset in points
+    pub width: f32,
+}
+
+impl Default for MinimapConfig {
+    fn default() -> Self {
+        Self {
+            colormap: Colormap::Viridis,
+            width: 200.0,
+        }
+    }
+}
+
+/// Splats the fluid particles onto a grid over the xz plane of the bounding box and color maps
+/// the counts into a texture. The counts are normalized by the fullest column, so the map
+/// adapts to the particle count.
+pub struct Minimap {
+    size: (u32, u32),
+    grid_buffer: Arc<wgpu::Buffer>,
+    view: wgpu::TextureView,
+    colormap_texture: ColormapTexture,
+    splat_task: Arc<ComputeTask>,
+    color_task: Arc<ComputeTask>,
+}
+
+impl Minimap {
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        bbox_dimensions: Vector3<f32>,
+        positions: &wgpu::Buffer,
+    ) -> Self {
+        let scale = MINIMAP_RESOLUTION as f32 / bbox_dimensions.x.max(bbox_dimensions.z);
+        let size = (
+            ((bbox_dimensions.x * scale).ceil() as u32).max(1),
+            ((bbox_dimensions.z * scale).ceil() as u32).max(1),
+        );
+        let texel_cnt = size.0 * size.1;
+
+        // the counts of the texels followed by the largest one
+        let grid_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Minimap grid buffer"),
+            size: (texel_cnt as u64 + 1) * 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let texture = wgpu_device.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Minimap texture"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let colormap_texture = ColormapTexture::new(wgpu_device);
+
+        let constants = format!(
+            "
+             const BBOX: vec3<f32> = vec3<f32>({}, {}, {});\n
+             const WIDTH: u32 = {}u;\n
+             const HEIGHT: u32 = {}u;\n
+             const GHOST_PARTICLES: u32 = {ghost_particle_cnt}u;\n",
+            bbox_dimensions.x, bbox_dimensions.y, bbox_dimensions.z, size.0, size.1,
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let fluid_particle_cnt = particle_cnt.saturating_sub(ghost_particle_cnt) as u32;
+        let splat_task = Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Minimap splat",
+            &[storage_entry(0, true), storage_entry(1, false)],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: grid_buffer.as_entire_binding(),
+                },
+            ],
+            &[],
+            format!("{constants}{}", include_str!("shaders/minimap_splat.wgsl")).into(),
+            (fluid_particle_cnt.div_ceil(256).max(1), 1, 1),
+        ));
+
+        let color_task = Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Minimap color",
+            &[
+                storage_entry(0, true),
+                ColormapTexture::layout_entry(1, wgpu::ShaderStages::COMPUTE),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: grid_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(colormap_texture.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+            ],
+            &[],
+            format!(
+                "{constants}{COLORMAP_SHADER}{}",
+                include_str!("shaders/minimap_color.wgsl")
+            )
+            .into(),
+            (size.0.div_ceil(16), size.1.div_ceil(16), 1),
+        ));
+
+        Self {
+            size,
+            grid_buffer,
+            view,
+            colormap_texture,
+            splat_task,
+            color_task,
+        }
+    }
+
+    pub fn update_fn(&self, config: MinimapConfig) -> GenericRequest {
+        let grid_buffer = self.grid_buffer.clone();
+        let upload_colormap = self.colormap_texture.upload_fn(config.colormap);
+        let splat_task = self.splat_task.clone();
+        let color_task = self.color_task.clone();
+
+        Box::new(move |encoder, queue| {
+            upload_colormap(encoder, queue);
+            encoder.clear_buffer(&grid_buffer, 0, None);
+            splat_task.execute(encoder, &[]);
+            color_task.execute(encoder, &[]);
+        })
+    }
+
+    /// Width and height of the texture, x runs along the width and z along the height.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::Arc;
+
+use crate::{
+    graphics::render_engine::GenericRequest, readback::Readback, ComputeTask, SpatialLookup,
+    SplooshError, WgpuDevice,
+};
+
+/// The last bin also counts every particle with more neighbors.
+pub const HISTOGRAM_BINS: usize = 64;
+
+/// Counts the particles within the smoothing radius of every fluid particle and gathers a
+/// histogram of the counts.
+pub struct NeighborCount {
+    count_buffer: Arc<wgpu::Buffer>,
+    histogram_buffer: Arc<wgpu::Buffer>,
+    readback: Readback<u32>,
+    count_task: Arc<ComputeTask>,
+}
+
+impl NeighborCount {
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+    ) -> Self {
+        let count_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Neighbor count buffer"),
+            size: (particle_cnt * std::mem::size_of::<u32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let histogram_size = (HISTOGRAM_BINS * std::mem::size_of::<u32>()) as u64;
+        let histogram_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Neighbor histogram buffer"),
+            size: histogram_size,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let readback = Readback::new(wgpu_device, "Neighbor histogram readback", histogram_size);
+
+        let count_task = NeighborCount::create_count_task(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            spatial_lookup,
+            positions,
+            &count_buffer,
+            &histogram_buffer,
+        );
+
+        Self {
+            count_buffer,
+            histogram_buffer,
+            readback,
+            count_task,
+        }
+    }
+
+    /// Neighbor count of every particle, zero for the ghost particles.
+    pub fn counts(&self) -> &Arc<wgpu::Buffer> {
+        &self.count_buffer
+    }
+
+    pub fn update_fn(&self) -> GenericRequest {
+        let count_task = self.count_task.clone();
+        let histogram_buffer = self.histogram_buffer.clone();
+        let readback = self.readback.clone();
+
+        Box::new(move |encoder, _| {
+            encoder.clear_buffer(&histogram_buffer, 0, None);
+            count_task.execute(encoder, &[]);
+            readback.copy(encoder, |encoder, staging_buffer| {
+                encoder.copy_buffer_to_buffer(
+                    &histogram_buffer,
+                    0,
+                    staging_buffer,
+                    0,
+                    histogram_buffer.size(),
+                );
+            });
+        })
+    }
+
+    /// Blocks until the submitted passes of `update_fn` have finished and returns the number of
+    /// particles per neighbor count.
+    pub fn read_histogram(&self, device: &wgpu::Device) -> Result<Vec<u32>, SplooshError> {
+        self.readback.wait(device)
+    }
+
+    /// Like `read_histogram` without blocking, `None` until a newer histogram arrives.
+    pub fn poll_histogram(&self, device: &wgpu::Device) -> Option<Result<Vec<u32>, SplooshError>> {
+        self.readback.poll(device)
+    }
+
+    fn create_count_task(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        count_buffer: &wgpu::Buffer,
+        histogram_buffer: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = ((particle_cnt - ghost_particle_cnt) as u32).div_ceil(256);
+
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             const HISTOGRAM_BINS: u32 = {HISTOGRAM_BINS}u;\n
+             {}
+             {}",
+            spatial_lookup.shader_source(),
+            include_str!("shaders/neighbor_count.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Neighbor count",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, false),
+                storage_entry(5, false),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: count_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: histogram_buffer.as_entire_binding(),
+                },
+            ],
+            &[],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point4, Vector3};
+    use pollster::FutureExt as _;
+    use rand::Rng;
+
+    use crate::spatial_lookup::SpatialGrid;
+
+    use super::*;
+
+    #[test]
+    fn neighbor_histogram_matches_brute_force() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+
+        let particle_cnt = 500;
+        let smoothing_radius = 0.5;
+        let bbox_dimensions = Vector3::new(3.0, 3.0, 3.0);
+
+        let mut rng = rand::thread_rng();
+        let positions: Vec<Point4<f32>> = (0..particle_cnt)
+            .map(|_| {
+                let x = rng.gen_range(0.0..bbox_dimensions.x);
+                let y = rng.gen_range(0.0..bbox_dimensions.y);
+                let z = rng.gen_range(0.0..bbox_dimensions.z);
+                Point4::new(x, y, z, 1.0)
+            })
+            .collect();
+
+        let position_buffer = wgpu_device.create_buffer_init(
+            &positions,
+            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+        );
+
+        let cell_cnt = Vector3::new(6, 6, 6);
+        let spatial_lookup = SpatialLookup::new(
+            &wgpu_device,
+            particle_cnt,
+            smoothing_radius,
+            smoothing_radius,
+            SpatialGrid::Dense { cell_cnt },
+            &position_buffer,
+        );
+        let neighbor_count = NeighborCount::new(
+            &wgpu_device,
+            particle_cnt,
+            0,
+            &spatial_lookup,
+            &position_buffer,
+        );
+
+        let mut encoder = wgpu_device
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        spatial_lookup.update_fn()(&mut encoder, &wgpu_device.queue);
+        neighbor_count.update_fn()(&mut encoder, &wgpu_device.queue);
+        wgpu_device.submit(encoder);
+
+        let histogram = neighbor_count.read_histogram(&wgpu_device.device).unwrap();
+
+        let mut expected = vec![0; HISTOGRAM_BINS];
+        for (i, p) in positions.iter().enumerate() {
+            let count = positions
+                .iter()
+                .enumerate()
+                .filter(|&(j, q)| i != j && (p - q).xyz().norm() < smoothing_radius)
+                .count();
+            expected[count.min(HISTOGRAM_BINS - 1)] += 1;
+        }
+
+        assert_eq!(histogram, expected);
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+};
+
+use image::RgbaImage;
+
+use crate::SplooshError;
+
+#[derive(Clone, Debug)]
+pub struct OfflineOptions {
+    pub width: u32,
+    pub height: u32,
+    pub dt: f32,
+    pub fps: u32,
+    pub export_dir: PathBuf,
+    pub video: Option<PathBuf>,
+}
+
+pub enum FrameSink {
+    PngSequence { dir: PathBuf, next_frame: u64 },
+    Ffmpeg { process: Child },
+}
+
+impl FrameSink {
+    pub fn new(options: &OfflineOptions) -> Result<Self, SplooshError> {
+        match &options.video {
+            Some(video) => {
+                let process = Command::new("ffmpeg")
+                    .args(["-y", "-f", "rawvideo", "-pix_fmt", "rgba", "-s"])
+                    .arg(format!("{}x{}", options.width, options.height))
+                    .arg("-r")
+                    .arg(options.fps.to_string())
+                    .args(["-i", "-", "-c:v", "libx264", "-pix_fmt", "yuv420p"])
+                    .arg(video)
+                    .stdin(Stdio::piped())
+                    .spawn()?;
+
+                Ok(FrameSink::Ffmpeg { process })
+            }
+            None => {
+                std::fs::create_dir_all(&options.export_dir)?;
+
+                Ok(FrameSink::PngSequence {
+                    dir: options.export_dir.clone(),
+                    next_frame: 0,
+                })
+            }
+        }
+    }
+
+    pub fn write_frame(&mut self, frame: &RgbaImage) -> Result<(), SplooshError> {
+        match self {
+            FrameSink::PngSequence { dir, next_frame } => {
+                frame.save(dir.join(format!("frame_{next_frame:05}.png")))?;
+                *next_frame += 1;
+            }
+            FrameSink::Ffmpeg { process } => {
+                process
+                    .stdin
+                    .as_mut()
+                    .ok_or_else(|| {
+                        std::io::Error::new(
+                            std::io::ErrorKind::BrokenPipe,
+                            "ffmpeg stdin is closed",
+                        )
+                    })?
+                    .write_all(frame.as_raw())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for FrameSink {
+    fn drop(&mut self) {
+        if let FrameSink::Ffmpeg { process } = self {
+            drop(process.stdin.take());
+            let _ = process.wait();
+        }
+    }
+}
+
+pub struct OfflineRenderer {
+    options: OfflineOptions,
+    sink: FrameSink,
+}
+
+impl OfflineRenderer {
+    pub fn new(options: OfflineOptions) -> Result<Self, SplooshError> {
+        let sink = FrameSink::new(&options)?;
+        Ok(Self { options, sink })
+    }
+
+    pub fn options(&self) -> &OfflineOptions {
+        &self.options
+    }
+
+    pub fn write_frame(&mut self, frame: &RgbaImage) -> Result<(), SplooshError> {
+        self.sink.write_frame(frame)
+    }
+}
@@ -0,0 +1,341 @@
+use std::sync::Arc;
+
+use nalgebra::{Point3, Vector3};
+
+use crate::{
+    graphics::render_engine::GenericRequest, readback::Readback, wgpu_device::Uploader,
+    ComputeTask, SplooshError, WgpuDevice,
+};
+
+/// Radius of the sphere a pick ray is tested against, matches the particle sprites.
+const PICK_RADIUS: f32 = 0.05;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct RayUniform {
+    origin: [f32; 3],
+    radius: f32,
+    direction: [f32; 3],
+    _padding: f32,
+}
+
+/// State of a single particle, positions are in world space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParticleSample {
+    pub position: Point3<f32>,
+    pub velocity: Vector3<f32>,
+    pub density: f32,
+    pub pressure: f32,
+    pub neighbor_count: u32,
+}
+
+/// Picks the fluid particle under a ray and reads back the state of single particles.
+pub struct ParticleInspector {
+    bbox_dimensions: Vector3<f32>,
+    gas_const: f32,
+    rest_density: f32,
+
+    positions: Arc<wgpu::Buffer>,
+    velocities: Arc<wgpu::Buffer>,
+    densities: Arc<wgpu::Buffer>,
+    neighbor_counts: Arc<wgpu::Buffer>,
+
+    ray_buffer: Arc<wgpu::Buffer>,
+    pick_buffer: Arc<wgpu::Buffer>,
+    pick_readback: Readback<u32>,
+    sample_readback: Readback<f32>,
+    pick_task: Arc<ComputeTask>,
+    uploader: Uploader,
+}
+
+impl ParticleInspector {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        bbox_dimensions: Vector3<f32>,
+        gas_const: f32,
+        rest_density: f32,
+        positions: Arc<wgpu::Buffer>,
+        velocities: Arc<wgpu::Buffer>,
+        densities: Arc<wgpu::Buffer>,
+        neighbor_counts: Arc<wgpu::Buffer>,
+    ) -> Self {
+        let ray_buffer = wgpu_device.create_buffer_init(
+            &[RayUniform::default()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        let pick_buffer = wgpu_device.create_buffer_init(
+            &[u32::MAX; 2],
+            wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        );
+        let pick_readback = Readback::new(wgpu_device, "Pick readback", pick_buffer.size());
+        // position, velocity, density and neighbor count
+        let sample_readback =
+            Readback::new(wgpu_device, "Particle sample readback", 16 + 16 + 4 + 4);
+
+        let pick_task = ParticleInspector::create_pick_task(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            bbox_dimensions,
+            &positions,
+            &ray_buffer,
+            &pick_buffer,
+        );
+
+        Self {
+            uploader: wgpu_device.uploader.clone(),
+            bbox_dimensions,
+            gas_const,
+            rest_density,
+
+            positions,
+            velocities,
+            densities,
+            neighbor_counts,
+
+            ray_buffer,
+            pick_buffer,
+            pick_readback,
+            sample_readback,
+            pick_task,
+        }
+    }
+
+    /// Finds the fluid particle closest to `origin` whose sprite is hit by the world space ray.
+    pub fn pick_fn(&self, origin: Point3<f32>, direction: Vector3<f32>) -> GenericRequest {
+        let ray = RayUniform {
+            origin: origin.into(),
+            radius: PICK_RADIUS,
+            direction: direction.normalize().into(),
+            _padding: 0.0,
+        };
+        let uploader = self.uploader.clone();
+        let ray_buffer = self.ray_buffer.clone();
+        let pick_buffer = self.pick_buffer.clone();
+        let pick_readback = self.pick_readback.clone();
+        let pick_task = self.pick_task.clone();
+
+        Box::new(move |encoder, _| {
+            uploader.write(encoder, &ray_buffer, 0, bytemuck::bytes_of(&ray));
+            uploader.write(
+                encoder,
+                &pick_buffer,
+                0,
+                bytemuck::cast_slice(&[u32::MAX; 2]),
+            );
+            pick_task.execute(encoder, bytemuck::bytes_of(&0u32));
+            pick_task.execute(encoder, bytemuck::bytes_of(&1u32));
+            pick_readback.copy(encoder, |encoder, staging_buffer| {
+                encoder.copy_buffer_to_buffer(
+                    &pick_buffer,
+                    0,
+                    staging_buffer,
+                    0,
+                    pick_buffer.size(),
+                );
+            });
+        })
+    }
+
+    /// Blocks until the submitted passes of `pick_fn` have finished and returns the index of
+    /// the picked particle, `None` if the ray missed.
+    pub fn read_pick(&self, device: &wgpu::Device) -> Result<Option<u32>, SplooshError> {
+        self.pick_readback.wait(device).map(Self::picked)
+    }
+
+    /// Like `read_pick` without blocking, `None` until the pick arrives.
+    pub fn poll_pick(&self, device: &wgpu::Device) -> Option<Result<Option<u32>, SplooshError>> {
+        self.pick_readback
+            .poll(device)
+            .map(|pick| pick.map(Self::picked))
+    }
+
+    fn picked(pick: Vec<u32>) -> Option<u32> {
+        (pick[1] != u32::MAX).then_some(pick[1])
+    }
+
+    /// Copies the state of particle `index` to the staging buffer. The neighbor count is only
+    /// current if the neighbor count pass ran before.
+    pub fn sample_fn(&self, index: u32) -> GenericRequest {
+        let index = index as u64;
+        let positions = self.positions.clone();
+        let velocities = self.velocities.clone();
+        let densities = self.densities.clone();
+        let neighbor_counts = self.neighbor_counts.clone();
+        let sample_readback = self.sample_readback.clone();
+
+        Box::new(move |encoder, _| {
+            sample_readback.copy(encoder, |encoder, staging_buffer| {
+                encoder.copy_buffer_to_buffer(&positions, index * 16, staging_buffer, 0, 16);
+                encoder.copy_buffer_to_buffer(&velocities, index * 16, staging_buffer, 16, 16);
+                encoder.copy_buffer_to_buffer(&densities, index * 4, staging_buffer, 32, 4);
+                encoder.copy_buffer_to_buffer(&neighbor_counts, index * 4, staging_buffer, 36, 4);
+            });
+        })
+    }
+
+    /// The newest sample of `sample_fn` that arrived since the last call, without blocking.
+    pub fn poll_sample(
+        &self,
+        device: &wgpu::Device,
+    ) -> Option<Result<ParticleSample, SplooshError>> {
+        self.sample_readback
+            .poll(device)
+            .map(|data| data.map(|data| self.sample(&data)))
+    }
+
+    /// Drops the samples still in flight, so a new selection doesn't get the state of the
+    /// previous one.
+    pub fn discard_samples(&self) {
+        self.sample_readback.discard();
+    }
+
+    fn sample(&self, data: &[f32]) -> ParticleSample {
+        let density = data[8];
+
+        ParticleSample {
+            position: Point3::new(data[0], data[1], data[2]) - self.bbox_dimensions / 2.0,
+            velocity: Vector3::new(data[4], data[5], data[6]),
+            density,
+            pressure: self.gas_const * (density - self.rest_density),
+            neighbor_count: data[9].to_bits(),
+        }
+    }
+
+    fn create_pick_task(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        bbox_dimensions: Vector3<f32>,
+        positions: &wgpu::Buffer,
+        ray_buffer: &wgpu::Buffer,
+        pick_buffer: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = ((particle_cnt - ghost_particle_cnt) as u32).div_ceil(256);
+
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             const OFFSET: vec3<f32> = vec3<f32>({}, {}, {});\n
+             {}",
+            -bbox_dimensions.x / 2.0,
+            -bbox_dimensions.y / 2.0,
+            -bbox_dimensions.z / 2.0,
+            include_str!("shaders/particle_pick.wgsl")
+        );
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Particle pick",
+            &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: ray_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: pick_buffer.as_entire_binding(),
+                },
+            ],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..4,
+            }],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point4;
+    use pollster::FutureExt as _;
+
+    use super::*;
+
+    #[test]
+    fn pick_returns_closest_particle_on_ray() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+
+        let bbox_dimensions = Vector3::new(4.0, 4.0, 4.0);
+        // centered in the box, so the world positions are the simulation positions minus 2
+        let positions = [
+            Point4::new(2.0, 2.0, 1.0, 1.0),
+            Point4::new(2.0, 2.0, 3.0, 1.0),
+            Point4::new(2.01, 2.0, 2.0, 1.0),
+            Point4::new(3.0, 2.0, 3.5, 1.0),
+        ];
+        let position_buffer = wgpu_device.create_buffer_init(
+            &positions,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        );
+
+        let inspector = ParticleInspector::new(
+            &wgpu_device,
+            positions.len(),
+            1,
+            bbox_dimensions,
+            1.0,
+            0.0,
+            position_buffer.clone(),
+            position_buffer.clone(),
+            position_buffer.clone(),
+            position_buffer,
+        );
+
+        let mut encoder = wgpu_device
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        // looks down the z axis, the first particle is a ghost and is skipped
+        inspector.pick_fn(Point3::new(0.0, 0.0, -5.0), Vector3::z())(
+            &mut encoder,
+            &wgpu_device.queue,
+        );
+        wgpu_device.submit(encoder);
+
+        let picked = inspector.read_pick(&wgpu_device.device).unwrap();
+        assert_eq!(picked, Some(2));
+    }
+}
@@ -0,0 +1,253 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graphics::{geometry::Geometry, materials::ColoredVertex, render_engine::GenericRequest},
+    wgpu_device::Uploader,
+    ComputeTask, WgpuDevice,
+};
+
+pub const MAX_TRAILS: u32 = 2048;
+pub const MAX_TRAIL_LENGTH: u32 = 64;
+
+/// Bytes of the GPU buffers of the trails, sized for the most trails whether enabled or not.
+pub const TRAIL_BUFFER_SIZE: u64 = (MAX_TRAILS * MAX_TRAIL_LENGTH) as u64 * 16
+    + MAX_TRAILS as u64 * 8
+    + 2 * (MAX_TRAILS * (MAX_TRAIL_LENGTH - 1)) as u64
+        * std::mem::size_of::<ColoredVertex>() as u64;
+
+/// Fading paths behind a subset of the fluid particles, showing mixing and circulation.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParticleTrailConfig {
+    /// Particles followed, spread evenly over the fluid particles
+    pub trail_cnt: u32,
+    /// Positions kept per particle, one is recorded every frame the particles moved
+    pub length: u32,
+}
+
+impl Default for ParticleTrailConfig {
+    fn default() -> Self {
+        Self {
+            trail_cnt: 256,
+            length: 32,
+        }
+    }
+}
+
+impl ParticleTrailConfig {
+    fn clamped(self, fluid_particle_cnt: usize) -> Self {
+        let max_trails = MAX_TRAILS.min(fluid_particle_cnt as u32).max(1);
+        Self {
+            trail_cnt: self.trail_cnt.clamp(1, max_trails),
+            length: self.length.clamp(2, MAX_TRAIL_LENGTH),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParticleTrailUniform {
+    trail_cnt: u32,
+    length: u32,
+    stride: u32,
+    reset: u32,
+}
+
+/// Keeps a ring buffer of recent positions per followed particle on the GPU and writes them
+/// out as a line list.
+pub struct ParticleTrails {
+    fluid_particle_cnt: usize,
+    line_buffer: Arc<wgpu::Buffer>,
+    uniform_buffer: Arc<wgpu::Buffer>,
+    trail_task: Arc<ComputeTask>,
+    uploader: Uploader,
+    reset: Arc<AtomicBool>,
+}
+
+impl ParticleTrails {
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        bbox_dimensions: Vector3<f32>,
+        positions: &wgpu::Buffer,
+    ) -> Self {
+        let device = &wgpu_device.device;
+        let fluid_particle_cnt = particle_cnt - ghost_particle_cnt;
+
+        let history_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle trail history buffer"),
+            size: (MAX_TRAILS * MAX_TRAIL_LENGTH) as u64 * 4 * std::mem::size_of::<f32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let state_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle trail state buffer"),
+            size: MAX_TRAILS as u64 * 2 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let segment_cnt = (MAX_TRAILS * (MAX_TRAIL_LENGTH - 1)) as usize;
+        let line_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle trail line buffer"),
+            size: (2 * segment_cnt * std::mem::size_of::<ColoredVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let uniform_buffer = wgpu_device.create_buffer_init(
+            &[ParticleTrailUniform::default()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let trail_task = ParticleTrails::create_trail_task(
+            wgpu_device,
+            ghost_particle_cnt,
+            bbox_dimensions,
+            positions,
+            &history_buffer,
+            &state_buffer,
+            &line_buffer,
+            &uniform_buffer,
+        );
+
+        Self {
+            uploader: wgpu_device.uploader.clone(),
+            fluid_particle_cnt,
+            line_buffer,
+            uniform_buffer,
+            trail_task,
+            // the state buffer starts out zeroed, which is an empty trail
+            reset: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Forgets the recorded positions, for example after the particles jumped.
+    pub fn reset(&self) {
+        self.reset.store(true, Ordering::Relaxed);
+    }
+
+    /// Records the current positions and rewrites the lines, run once per frame.
+    pub fn update_fn(&self, config: ParticleTrailConfig) -> GenericRequest {
+        let config = config.clamped(self.fluid_particle_cnt);
+        let uniform = ParticleTrailUniform {
+            trail_cnt: config.trail_cnt,
+            length: config.length,
+            stride: self.fluid_particle_cnt as u32 / config.trail_cnt,
+            reset: 0,
+        };
+        let uploader = self.uploader.clone();
+        let uniform_buffer = self.uniform_buffer.clone();
+        let trail_task = self.trail_task.clone();
+        let reset = self.reset.clone();
+
+        Box::new(move |encoder, _| {
+            let uniform = ParticleTrailUniform {
+                reset: reset.swap(false, Ordering::Relaxed) as u32,
+                ..uniform
+            };
+            uploader.write(encoder, &uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+            trail_task.execute(encoder, &[]);
+        })
+    }
+
+    /// Line list written by the pass returned from `update_fn` with the same config.
+    pub fn geometry(&self, config: ParticleTrailConfig) -> Geometry {
+        let config = config.clamped(self.fluid_particle_cnt);
+
+        Geometry::Array {
+            vertex_buffer: self.line_buffer.clone(),
+            vertex_cnt: 2 * (config.trail_cnt * (config.length - 1)) as usize,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_trail_task(
+        wgpu_device: &WgpuDevice,
+        ghost_particle_cnt: usize,
+        bbox_dimensions: Vector3<f32>,
+        positions: &wgpu::Buffer,
+        history_buffer: &wgpu::Buffer,
+        state_buffer: &wgpu::Buffer,
+        line_buffer: &wgpu::Buffer,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        // an invocation per trail, the shader skips the ones past the trail count
+        let workgroup_cnt = MAX_TRAILS.div_ceil(64);
+
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             const MAX_TRAIL_LENGTH: u32 = {MAX_TRAIL_LENGTH}u;\n
+             const OFFSET: vec3<f32> = vec3<f32>({}, {}, {});\n
+             {}",
+            -bbox_dimensions.x / 2.0,
+            -bbox_dimensions.y / 2.0,
+            -bbox_dimensions.z / 2.0,
+            include_str!("shaders/particle_trails.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Particle trails",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, false),
+                storage_entry(2, false),
+                storage_entry(3, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: history_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: state_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: line_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            &[],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+}
@@ -0,0 +1,171 @@
+use std::cell::RefCell;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    /// Read-write storage, the pass may read the buffer as well
+    Write,
+}
+
+/// Buffers bound by a compute task and how it accesses them, taken from its layout entries.
+#[derive(Clone, Debug, Default)]
+pub struct PassAccesses {
+    name: String,
+    buffers: Vec<(wgpu::Id<wgpu::Buffer>, Access)>,
+}
+
+impl PassAccesses {
+    pub fn new(
+        name: &str,
+        entries: &[wgpu::BindGroupLayoutEntry],
+        resources: &[wgpu::BindGroupEntry],
+    ) -> Self {
+        let buffers = resources
+            .iter()
+            .filter_map(|resource| {
+                let wgpu::BindingResource::Buffer(binding) = &resource.resource else {
+                    return None;
+                };
+                let entry = entries
+                    .iter()
+                    .find(|entry| entry.binding == resource.binding)?;
+                let access = match entry.ty {
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        ..
+                    } => Access::Write,
+                    _ => Access::Read,
+                };
+
+                Some((binding.buffer.global_id(), access))
+            })
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            buffers,
+        }
+    }
+}
+
+struct StepLog {
+    carried: Vec<wgpu::Id<wgpu::Buffer>>,
+    passes: Vec<PassAccesses>,
+}
+
+thread_local! {
+    static STEP_LOG: RefCell<Option<StepLog>> = const { RefCell::new(None) };
+}
+
+/// Starts recording the compute passes executed on this thread, only in debug builds. The
+/// `carried` buffers hold state from one step to the next, so they are read before the step
+/// writes them on purpose.
+pub fn begin_step(carried: &[wgpu::Id<wgpu::Buffer>]) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    STEP_LOG.with_borrow_mut(|log| {
+        *log = Some(StepLog {
+            carried: carried.to_vec(),
+            passes: Vec::new(),
+        })
+    });
+}
+
+/// Called by `ComputeTask::execute`.
+pub(crate) fn record(accesses: &PassAccesses) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    STEP_LOG.with_borrow_mut(|log| {
+        if let Some(log) = log {
+            log.passes.push(accesses.clone());
+        }
+    });
+}
+
+/// Stops recording and panics if a pass read a buffer that a later pass of the step writes,
+/// it would see the value of the previous step. Copies and clears aren't recorded.
+pub fn end_step() {
+    let Some(log) = STEP_LOG.with_borrow_mut(Option::take) else {
+        return;
+    };
+
+    if let Err(message) = check_order(&log.passes, &log.carried) {
+        panic!("{message}");
+    }
+}
+
+fn check_order(passes: &[PassAccesses], carried: &[wgpu::Id<wgpu::Buffer>]) -> Result<(), String> {
+    for (i, writer) in passes.iter().enumerate() {
+        for (buffer, access) in &writer.buffers {
+            if *access != Access::Write || carried.contains(buffer) {
+                continue;
+            }
+            // only the first write matters, later ones are preceded by it
+            let written_before = passes[..i]
+                .iter()
+                .any(|pass| pass.buffers.contains(&(*buffer, Access::Write)));
+            if written_before {
+                continue;
+            }
+
+            if let Some(reader) = passes[..i]
+                .iter()
+                .find(|pass| pass.buffers.iter().any(|(read, _)| read == buffer))
+            {
+                return Err(format!(
+                    "The pass \"{}\" reads a buffer before \"{}\" writes it in the same step",
+                    reader.name, writer.name
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::FutureExt as _;
+
+    use super::*;
+    use crate::WgpuDevice;
+
+    #[test]
+    fn reads_before_the_first_write_are_reported() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let buffers =
+            [0; 3].map(|_| wgpu_device.create_buffer_init(&[0u32], wgpu::BufferUsages::STORAGE));
+        let [state, density, force] = buffers.each_ref().map(|buffer| buffer.global_id());
+        let pass = |name: &str, buffers: &[(wgpu::Id<wgpu::Buffer>, Access)]| PassAccesses {
+            name: name.to_string(),
+            buffers: buffers.to_vec(),
+        };
+
+        let density_pass = pass(
+            "density",
+            &[(state, Access::Read), (density, Access::Write)],
+        );
+        let force_pass = pass("force", &[(density, Access::Read), (force, Access::Write)]);
+        let integrate_pass = pass(
+            "integrate",
+            &[(force, Access::Read), (state, Access::Write)],
+        );
+        let carried = [state];
+
+        let ordered = [
+            density_pass.clone(),
+            force_pass.clone(),
+            integrate_pass.clone(),
+        ];
+        assert!(check_order(&ordered, &carried).is_ok());
+        // the state is read before the integration on purpose, but only if it's carried
+        assert!(check_order(&ordered, &[]).is_err());
+
+        let swapped = [force_pass, density_pass, integrate_pass];
+        assert!(check_order(&swapped, &carried).is_err());
+    }
+}
@@ -0,0 +1,432 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use nalgebra::{Point4, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graphics::render_engine::GenericRequest, readback::Readback, wgpu_device::Uploader,
+    ComputeTask, SplooshError, WgpuDevice,
+};
+
+/// Records the fluid particles every few steps so the recent history can be replayed, see
+/// `PlaybackHistory`. A recorded frame takes 14 bytes per fluid particle.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaybackConfig {
+    /// Steps between recorded frames
+    pub interval: u32,
+    /// Recorded frames kept, the oldest are dropped first
+    pub capacity: usize,
+    /// In m/s, faster particles are recorded at this speed
+    pub max_speed: f32,
+}
+
+impl Default for PlaybackConfig {
+    fn default() -> Self {
+        Self {
+            interval: 4,
+            capacity: 150,
+            max_speed: 20.0,
+        }
+    }
+}
+
+/// Particle state quantized to 16 bits per value, matches the layout written by
+/// `playback_pack.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PackedParticle {
+    /// unorm relative to the recorded volume
+    position: [u16; 3],
+    /// unorm relative to the max density
+    density: u16,
+    /// snorm relative to the max speed
+    velocity: [u16; 3],
+}
+
+/// Bytes of a recorded frame of `fluid_particle_cnt` particles on the GPU. The header comes
+/// first, then the particles in pairs of seven words, the last pair padded.
+pub fn packed_frame_size(fluid_particle_cnt: usize) -> u64 {
+    (std::mem::size_of::<FrameHeader>()
+        + fluid_particle_cnt.div_ceil(2) * 2 * std::mem::size_of::<PackedParticle>()) as u64
+}
+
+/// Fluid particles of one recorded step.
+#[derive(Clone, Debug)]
+pub struct PlaybackFrame {
+    pub time: f32,
+    pub step_cnt: u64,
+    max_speed: f32,
+    particles: Vec<PackedParticle>,
+}
+
+impl PlaybackFrame {
+    pub fn particle_cnt(&self) -> usize {
+        self.particles.len()
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct FrameHeader {
+    time: f32,
+    step_cnt: [u32; 2],
+    max_speed: f32,
+}
+
+/// Range the positions and densities are quantized to, the velocities use the max speed
+/// of the config at the time of recording.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaybackRange {
+    pub origin: Vector3<f32>,
+    pub extent: Vector3<f32>,
+    pub max_density: f32,
+}
+
+/// Packs the fluid particles on the GPU and copies them back without stalling, a step
+/// recorded while the previous copies are still in flight is skipped.
+pub struct PlaybackRecorder {
+    fluid_particle_cnt: usize,
+    packed_buffer: Arc<wgpu::Buffer>,
+    readback: Readback<u32>,
+    uploader: Uploader,
+    pack_task: Arc<ComputeTask>,
+    range: PlaybackRange,
+    /// Step count of the last recorded frame
+    last_recorded: Arc<AtomicU64>,
+}
+
+impl PlaybackRecorder {
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        range: PlaybackRange,
+        positions: &wgpu::Buffer,
+        velocities: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+    ) -> Self {
+        let fluid_particle_cnt = particle_cnt - ghost_particle_cnt;
+        let size = packed_frame_size(fluid_particle_cnt);
+
+        let packed_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Playback buffer"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let readback = Readback::new(wgpu_device, "Playback readback", size);
+
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt}u;\n
+             const FLUID_PARTICLE_CNT: u32 = {fluid_particle_cnt}u;\n
+             const ORIGIN: vec3<f32> = vec3<f32>({}, {}, {});\n
+             const EXTENT: vec3<f32> = vec3<f32>({}, {}, {});\n
+             const MAX_DENSITY: f32 = {};\n
+             {}",
+            range.origin.x,
+            range.origin.y,
+            range.origin.z,
+            range.extent.x,
+            range.extent.y,
+            range.extent.z,
+            range.max_density,
+            include_str!("shaders/playback_pack.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let pack_task = Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Playback pack",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, false),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: velocities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: densities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: packed_buffer.as_entire_binding(),
+                },
+            ],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..4,
+            }],
+            shader_source.into(),
+            // one invocation per pair of particles
+            ((fluid_particle_cnt as u32).div_ceil(2 * 256).max(1), 1, 1),
+        ));
+
+        Self {
+            fluid_particle_cnt,
+            packed_buffer,
+            readback,
+            uploader: wgpu_device.uploader.clone(),
+            pack_task,
+            range,
+            last_recorded: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Records the particles if `config.interval` steps have passed since the last frame.
+    /// `time` and `step_cnt` are read when the request runs, after the step before it.
+    pub fn record_fn(
+        &self,
+        config: PlaybackConfig,
+        time: Arc<AtomicU32>,
+        step_cnt: Arc<AtomicU64>,
+    ) -> GenericRequest {
+        let packed_buffer = self.packed_buffer.clone();
+        let readback = self.readback.clone();
+        let uploader = self.uploader.clone();
+        let pack_task = self.pack_task.clone();
+        let last_recorded = self.last_recorded.clone();
+        let max_speed = config.max_speed.max(f32::EPSILON);
+
+        Box::new(move |encoder, _| {
+            let step_cnt = step_cnt.load(Ordering::Relaxed);
+            let last = last_recorded.load(Ordering::Relaxed);
+            if step_cnt < last + config.interval.max(1) as u64 {
+                return;
+            }
+
+            let header = FrameHeader {
+                time: f32::from_bits(time.load(Ordering::Relaxed)),
+                step_cnt: [step_cnt as u32, (step_cnt >> 32) as u32],
+                max_speed,
+            };
+            uploader.write(encoder, &packed_buffer, 0, bytemuck::bytes_of(&header));
+            pack_task.execute(encoder, bytemuck::bytes_of(&max_speed));
+            let copied = readback.copy(encoder, |encoder, staging_buffer| {
+                encoder.copy_buffer_to_buffer(
+                    &packed_buffer,
+                    0,
+                    staging_buffer,
+                    0,
+                    packed_buffer.size(),
+                );
+            });
+            if copied {
+                last_recorded.store(step_cnt, Ordering::Relaxed);
+            }
+        })
+    }
+
+    /// Like `poll` but blocks until the submitted recordings have finished.
+    pub fn read(&self, device: &wgpu::Device) -> Result<PlaybackFrame, SplooshError> {
+        self.readback
+            .wait(device)
+            .map(|packed| frame_from_packed(&packed, self.fluid_particle_cnt))
+    }
+
+    /// The newest recorded frame that arrived since the last call.
+    pub fn poll(&self, device: &wgpu::Device) -> Option<Result<PlaybackFrame, SplooshError>> {
+        self.readback
+            .poll(device)
+            .map(|packed| packed.map(|packed| frame_from_packed(&packed, self.fluid_particle_cnt)))
+    }
+
+    /// Continues recording after `step_cnt`, the copies still in flight are dropped.
+    pub fn reset(&self, step_cnt: u64) {
+        self.readback.discard();
+        self.last_recorded.store(step_cnt, Ordering::Relaxed);
+    }
+
+    /// Positions, velocities and densities of the fluid particles of `frame`.
+    pub fn unpack(&self, frame: &PlaybackFrame) -> (Vec<Point4<f32>>, Vec<Vector4<f32>>, Vec<f32>) {
+        unpack(&self.range, frame)
+    }
+}
+
+/// Splits the words of a recorded frame into its header and `fluid_particle_cnt` particles.
+fn frame_from_packed(packed: &[u32], fluid_particle_cnt: usize) -> PlaybackFrame {
+    let (header, particles) = packed.split_at(std::mem::size_of::<FrameHeader>() / 4);
+    let header: FrameHeader = bytemuck::pod_read_unaligned(bytemuck::cast_slice(header));
+    let particle_size = std::mem::size_of::<PackedParticle>();
+    let particles: &[u8] = bytemuck::cast_slice(particles);
+
+    PlaybackFrame {
+        time: header.time,
+        step_cnt: header.step_cnt[0] as u64 | (header.step_cnt[1] as u64) << 32,
+        max_speed: header.max_speed,
+        particles: bytemuck::pod_collect_to_vec(&particles[..fluid_particle_cnt * particle_size]),
+    }
+}
+
+fn unpack(
+    range: &PlaybackRange,
+    frame: &PlaybackFrame,
+) -> (Vec<Point4<f32>>, Vec<Vector4<f32>>, Vec<f32>) {
+    // the inverses of pack2x16unorm and pack2x16snorm
+    let unorm = |bits: u16| bits as f32 / 65535.0;
+    let snorm = |bits: u16| (bits as i16 as f32 / 32767.0).max(-1.0);
+
+    let mut positions = Vec::with_capacity(frame.particles.len());
+    let mut velocities = Vec::with_capacity(frame.particles.len());
+    let mut densities = Vec::with_capacity(frame.particles.len());
+    for particle in &frame.particles {
+        let position = Vector3::from(particle.position.map(unorm));
+        let position = range.origin + position.component_mul(&range.extent);
+        positions.push(Point4::new(position.x, position.y, position.z, 1.0));
+
+        let velocity = frame.max_speed * Vector3::from(particle.velocity.map(snorm));
+        velocities.push(velocity.push(0.0));
+
+        densities.push(range.max_density * unorm(particle.density));
+    }
+
+    (positions, velocities, densities)
+}
+
+/// Recorded frames on the CPU, oldest first.
+#[derive(Default)]
+pub struct PlaybackHistory {
+    frames: VecDeque<PlaybackFrame>,
+}
+
+impl PlaybackHistory {
+    /// Appends `frame` and drops the oldest frames beyond `capacity`. Frames not newer than
+    /// the last one were recorded before a restore and are dropped.
+    pub fn push(&mut self, frame: PlaybackFrame, capacity: usize) {
+        if self
+            .frames
+            .back()
+            .is_some_and(|last| frame.step_cnt <= last.step_cnt)
+        {
+            return;
+        }
+
+        self.frames.push_back(frame);
+        while self.frames.len() > capacity.max(1) {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Drops the frames after `index`, they don't happen once the simulation continues from
+    /// it.
+    pub fn truncate_after(&mut self, index: usize) {
+        self.frames.truncate(index + 1);
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn get(&self, index: usize) -> Option<&PlaybackFrame> {
+        self.frames.get(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Simulated seconds between the oldest and the newest frame.
+    pub fn duration(&self) -> f32 {
+        match (self.frames.front(), self.frames.back()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    pub fn memory(&self) -> u64 {
+        self.frames
+            .iter()
+            .map(|frame| (frame.particles.len() * std::mem::size_of::<PackedParticle>()) as u64)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::FutureExt as _;
+
+    use crate::{
+        fluid_simulation::FluidSimulationConfig,
+        test_utils::{step, submit},
+        FluidSimulation,
+    };
+
+    use super::*;
+
+    #[test]
+    fn recorded_frame_restores_the_particles() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let config = FluidSimulationConfig {
+            // an odd count leaves the second half of the last pair empty
+            particle_cnt: 511,
+            bbox_dimensions: Vector3::new(2.0, 2.0, 2.0),
+            playback: Some(PlaybackConfig {
+                interval: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let fluid_sim = FluidSimulation::new(config, &wgpu_device);
+
+        step(&wgpu_device, &fluid_sim, 0.01, 10);
+        submit(&wgpu_device, |encoder| {
+            fluid_sim.record_playback_fn()(encoder, &wgpu_device.queue)
+        });
+
+        let frame = fluid_sim.read_playback(&wgpu_device.device).unwrap();
+        assert_eq!(frame.step_cnt, 10);
+        assert_eq!(frame.particle_cnt(), fluid_sim.fluid_particle_cnt());
+
+        let snapshot = fluid_sim.read_snapshot(&wgpu_device).unwrap();
+        let (positions, velocities, _) = fluid_sim.playback().unpack(&frame);
+        let ghosts = fluid_sim.ghost_particle_cnt();
+        for (recorded, actual) in positions.iter().zip(&snapshot.positions[ghosts..]) {
+            assert!((recorded - actual).norm() < 1e-3);
+        }
+        for (recorded, actual) in velocities.iter().zip(&snapshot.velocities[ghosts..]) {
+            assert!((recorded - actual).norm() < 1e-2);
+        }
+
+        // steps after the recorded frame are undone
+        step(&wgpu_device, &fluid_sim, 0.01, 1);
+        fluid_sim
+            .restore_playback_frame(&wgpu_device.queue, &frame)
+            .unwrap();
+
+        let restored = fluid_sim.read_snapshot(&wgpu_device).unwrap();
+        assert_eq!(restored.step_cnt, 10);
+        assert_eq!(&restored.positions[ghosts..], &positions[..]);
+    }
+}
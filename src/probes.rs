@@ -0,0 +1,504 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use nalgebra::{Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    density_slice::SliceAxis, graphics::render_engine::GenericRequest, readback::Readback,
+    wgpu_device::Uploader, ComputeTask, SpatialLookup, SplooshError, WgpuDevice,
+};
+
+pub const MAX_PROBES: usize = 16;
+/// Sample points along each side of a plane probe, the plane reports their mean.
+pub const PLANE_RESOLUTION: usize = 8;
+/// Samples kept per probe, the oldest are dropped first.
+pub const MAX_PROBE_HISTORY: usize = 100_000;
+
+const POINTS_PER_PROBE: usize = PLANE_RESOLUTION * PLANE_RESOLUTION;
+const MAX_PROBE_POINTS: usize = MAX_PROBES * POINTS_PER_PROBE;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProbeShape {
+    #[default]
+    Point,
+    /// Square perpendicular to the axis, averaging the values over its area
+    Plane { axis: SliceAxis, size: f32 },
+}
+
+/// Virtual sensor sampling the SPH interpolated fluid state every frame.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProbeConfig {
+    pub name: String,
+    /// In world space, the center of a plane
+    pub position: Point3<f32>,
+    pub shape: ProbeShape,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            name: "Probe".to_string(),
+            position: Point3::origin(),
+            shape: ProbeShape::Point,
+        }
+    }
+}
+
+impl ProbeConfig {
+    /// World space points the probe averages over.
+    pub fn sample_points(&self) -> Vec<Point3<f32>> {
+        match self.shape {
+            ProbeShape::Point => vec![self.position],
+            ProbeShape::Plane { axis, size } => {
+                let (u, v) = match axis {
+                    SliceAxis::X => (Vector3::z(), Vector3::y()),
+                    SliceAxis::Y => (Vector3::x(), Vector3::z()),
+                    SliceAxis::Z => (Vector3::x(), Vector3::y()),
+                };
+                let offset = |i: usize| ((i as f32 + 0.5) / PLANE_RESOLUTION as f32 - 0.5) * size;
+
+                (0..POINTS_PER_PROBE)
+                    .map(|i| {
+                        let (x, y) = (i % PLANE_RESOLUTION, i / PLANE_RESOLUTION);
+                        self.position + u * offset(x) + v * offset(y)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProbeSample {
+    /// Simulated time in seconds
+    pub time: f32,
+    pub density: f32,
+    /// Pressure from the equation of state
+    pub pressure: f32,
+    pub velocity: Vector3<f32>,
+}
+
+/// Value of a probe sample shown in the time series plot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProbeQuantity {
+    #[default]
+    Density,
+    Pressure,
+    Speed,
+}
+
+impl ProbeQuantity {
+    pub const ALL: [ProbeQuantity; 3] = [
+        ProbeQuantity::Density,
+        ProbeQuantity::Pressure,
+        ProbeQuantity::Speed,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProbeQuantity::Density => "Density",
+            ProbeQuantity::Pressure => "Pressure",
+            ProbeQuantity::Speed => "Speed",
+        }
+    }
+
+    pub fn value(&self, sample: &ProbeSample) -> f32 {
+        match self {
+            ProbeQuantity::Density => sample.density,
+            ProbeQuantity::Pressure => sample.pressure,
+            ProbeQuantity::Speed => sample.velocity.norm(),
+        }
+    }
+}
+
+/// Time series of every probe, restarted when the number of probes changes.
+#[derive(Clone, Debug, Default)]
+pub struct ProbeHistory {
+    series: Vec<VecDeque<ProbeSample>>,
+}
+
+impl ProbeHistory {
+    /// Appends a sample per probe. Samples from before a reset of the simulation time are
+    /// dropped.
+    pub fn push(&mut self, samples: &[ProbeSample]) {
+        let restarted = self
+            .series
+            .first()
+            .and_then(VecDeque::back)
+            .zip(samples.first())
+            .is_some_and(|(last, sample)| sample.time < last.time);
+        if self.series.len() != samples.len() || restarted {
+            self.series = vec![VecDeque::new(); samples.len()];
+        }
+
+        for (series, sample) in self.series.iter_mut().zip(samples) {
+            if series.len() == MAX_PROBE_HISTORY {
+                series.pop_front();
+            }
+            series.push_back(*sample);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.series.clear();
+    }
+
+    /// Samples of probe `index`, oldest first.
+    pub fn series(&self, index: usize) -> Option<&VecDeque<ProbeSample>> {
+        self.series.get(index)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.series.iter().all(VecDeque::is_empty)
+    }
+}
+
+/// Writes a row per probe and sample, `probes` names the series of `history`.
+pub fn export_probes(
+    path: &Path,
+    probes: &[ProbeConfig],
+    history: &ProbeHistory,
+) -> Result<(), SplooshError> {
+    let mut file = std::io::BufWriter::new(File::create(path)?);
+    writeln!(
+        file,
+        "probe,time,density,pressure,velocity_x,velocity_y,velocity_z"
+    )?;
+    for (i, probe) in probes.iter().enumerate() {
+        let Some(series) = history.series(i) else {
+            continue;
+        };
+        // quoted, names may contain commas
+        let name = format!("\"{}\"", probe.name.replace('"', "\"\""));
+        for s in series {
+            writeln!(
+                file,
+                "{name},{},{},{},{},{},{}",
+                s.time, s.density, s.pressure, s.velocity.x, s.velocity.y, s.velocity.z
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Interpolates the density and velocity at the sample points of the probes. Every probe has
+/// the same number of slots in the sample buffers, a point probe only uses the first one.
+pub struct Probes {
+    bbox_dimensions: Vector3<f32>,
+    gas_const: f32,
+    rest_density: f32,
+    point_buffer: Arc<wgpu::Buffer>,
+    sample_buffer: Arc<wgpu::Buffer>,
+    time_buffer: Arc<wgpu::Buffer>,
+    readback: Readback<[f32; 4]>,
+    probe_task: Arc<ComputeTask>,
+    uploader: Uploader,
+}
+
+impl Probes {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        ghost_particle_cnt: usize,
+        bbox_dimensions: Vector3<f32>,
+        mass: f32,
+        poly6: f32,
+        gas_const: f32,
+        rest_density: f32,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        velocities: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+    ) -> Self {
+        let point_buffer = wgpu_device.create_buffer_init(
+            &[[0.0f32; 4]; MAX_PROBE_POINTS],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let sample_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Probe sample buffer"),
+            size: (MAX_PROBE_POINTS * 16) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let time_buffer = wgpu_device.create_buffer_init(
+            &[0.0f32; 4],
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        );
+        // the time of the samples followed by the samples
+        let readback = Readback::new(
+            wgpu_device,
+            "Probe readback",
+            time_buffer.size() + sample_buffer.size(),
+        );
+
+        let probe_task = Probes::create_probe_task(
+            wgpu_device,
+            ghost_particle_cnt,
+            mass,
+            poly6,
+            spatial_lookup,
+            positions,
+            velocities,
+            densities,
+            &point_buffer,
+            &sample_buffer,
+        );
+
+        Self {
+            uploader: wgpu_device.uploader.clone(),
+            bbox_dimensions,
+            gas_const,
+            rest_density,
+            point_buffer,
+            sample_buffer,
+            time_buffer,
+            readback,
+            probe_task,
+        }
+    }
+
+    /// Samples the first `MAX_PROBES` probes. `time` holds the bits of the simulated time,
+    /// read when the passes are recorded. Needs an up to date spatial lookup and densities, so
+    /// it runs after a step.
+    pub fn update_fn(&self, probes: &[ProbeConfig], time: Arc<AtomicU32>) -> GenericRequest {
+        let probes = &probes[..probes.len().min(MAX_PROBES)];
+        let mut points = vec![[0.0f32; 4]; probes.len() * POINTS_PER_PROBE];
+        for (slots, probe) in points.chunks_mut(POINTS_PER_PROBE).zip(probes) {
+            for (slot, point) in slots.iter_mut().zip(probe.sample_points()) {
+                let point = point + self.bbox_dimensions / 2.0;
+                *slot = [point.x, point.y, point.z, 1.0];
+            }
+        }
+
+        let point_cnt = points.len() as u32;
+        let uploader = self.uploader.clone();
+        let point_buffer = self.point_buffer.clone();
+        let sample_buffer = self.sample_buffer.clone();
+        let time_buffer = self.time_buffer.clone();
+        let readback = self.readback.clone();
+        let probe_task = self.probe_task.clone();
+
+        Box::new(move |encoder, _| {
+            if point_cnt == 0 {
+                return;
+            }
+
+            uploader.write(encoder, &point_buffer, 0, bytemuck::cast_slice(&points));
+            let time = f32::from_bits(time.load(Ordering::Relaxed));
+            uploader.write(encoder, &time_buffer, 0, bytemuck::cast_slice(&[time; 4]));
+            probe_task.execute(encoder, bytemuck::bytes_of(&point_cnt));
+            readback.copy(encoder, |encoder, staging_buffer| {
+                encoder.copy_buffer_to_buffer(&time_buffer, 0, staging_buffer, 0, 16);
+                encoder.copy_buffer_to_buffer(
+                    &sample_buffer,
+                    0,
+                    staging_buffer,
+                    16,
+                    point_cnt as u64 * 16,
+                );
+            });
+        })
+    }
+
+    /// Blocks until the submitted passes of `update_fn` have finished. The samples are matched
+    /// to `probes`, which has to be the list the passes were submitted with.
+    pub fn read(
+        &self,
+        device: &wgpu::Device,
+        probes: &[ProbeConfig],
+    ) -> Result<Vec<ProbeSample>, SplooshError> {
+        self.readback
+            .wait(device)
+            .map(|data| self.samples(&data, probes))
+    }
+
+    /// Like `read` without blocking, `None` until newer samples arrive. Samples submitted
+    /// before `probes` changed are reported for the new list once.
+    pub fn poll(
+        &self,
+        device: &wgpu::Device,
+        probes: &[ProbeConfig],
+    ) -> Option<Result<Vec<ProbeSample>, SplooshError>> {
+        self.readback
+            .poll(device)
+            .map(|data| data.map(|data| self.samples(&data, probes)))
+    }
+
+    fn samples(&self, data: &[[f32; 4]], probes: &[ProbeConfig]) -> Vec<ProbeSample> {
+        let time = data[0][0];
+
+        data[1..]
+            .chunks(POINTS_PER_PROBE)
+            .zip(probes)
+            .map(|(slots, probe)| {
+                let used = &slots[..probe.sample_points().len()];
+                let cnt = used.len() as f32;
+                let density = used.iter().map(|s| s[3]).sum::<f32>() / cnt;
+                let velocity = used
+                    .iter()
+                    .map(|s| Vector3::new(s[0], s[1], s[2]))
+                    .sum::<Vector3<f32>>()
+                    / cnt;
+
+                ProbeSample {
+                    time,
+                    density,
+                    pressure: self.gas_const * (density - self.rest_density),
+                    velocity,
+                }
+            })
+            .collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_probe_task(
+        wgpu_device: &WgpuDevice,
+        ghost_particle_cnt: usize,
+        mass: f32,
+        poly6: f32,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        velocities: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+        point_buffer: &wgpu::Buffer,
+        sample_buffer: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = (MAX_PROBE_POINTS as u32).div_ceil(64);
+
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             const POLY6: f32 = {poly6};\n
+             const MASS: f32 = {mass};\n
+             {}
+             {}",
+            spatial_lookup.shader_source(),
+            include_str!("shaders/probes.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Probes",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, true),
+                storage_entry(5, true),
+                storage_entry(6, true),
+                storage_entry(7, false),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: velocities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: densities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: point_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: sample_buffer.as_entire_binding(),
+                },
+            ],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..4,
+            }],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_points_span_the_plane() {
+        let probe = ProbeConfig {
+            position: Point3::new(1.0, 2.0, 3.0),
+            shape: ProbeShape::Plane {
+                axis: SliceAxis::Y,
+                size: 2.0,
+            },
+            ..Default::default()
+        };
+
+        let points = probe.sample_points();
+        assert_eq!(points.len(), PLANE_RESOLUTION * PLANE_RESOLUTION);
+        assert!(points.iter().all(|p| p.y == 2.0));
+        assert!(points
+            .iter()
+            .all(|p| (p.x - 1.0).abs() < 1.0 && (p.z - 3.0).abs() < 1.0));
+        let mean = points.iter().map(|p| p.coords).sum::<Vector3<f32>>() / points.len() as f32;
+        assert!((mean - probe.position.coords).norm() < 1e-5);
+    }
+
+    #[test]
+    fn history_restarts_with_the_simulation_time() {
+        let sample = |time| ProbeSample {
+            time,
+            density: 1.0,
+            pressure: 0.0,
+            velocity: Vector3::zeros(),
+        };
+
+        let mut history = ProbeHistory::default();
+        history.push(&[sample(0.1), sample(0.1)]);
+        history.push(&[sample(0.2), sample(0.2)]);
+        assert_eq!(history.series(1).unwrap().len(), 2);
+
+        history.push(&[sample(0.0), sample(0.0)]);
+        assert_eq!(history.series(0).unwrap().len(), 1);
+
+        history.push(&[sample(0.1)]);
+        assert!(history.series(1).is_none());
+    }
+}
@@ -0,0 +1,199 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    annotations::Annotation,
+    camera_animation::CameraKeyframes,
+    camera_controller::OrbitState,
+    fluid_simulation::FluidSimulationConfig,
+    graphics::{background::RenderSettings, post_process::PostProcessSettings},
+    timeline::Timeline,
+    SplooshError,
+};
+
+pub const PROJECT_EXTENSION: &str = "sploosh";
+
+/// Increased whenever a field changes its meaning. Files from newer versions are rejected
+/// instead of being read wrong, older files are migrated when they are loaded.
+///
+/// Version 2 stopped counting the boundary ghost particles in `particle_cnt`.
+pub const PROJECT_VERSION: u32 = 2;
+
+/// Everything authored for a scene, saved as a `.sploosh` file. The obstacle and the emitters
+/// are part of the simulation config. Files are written as RON, JSON is read as well.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Project {
+    pub version: u32,
+    pub simulation: FluidSimulationConfig,
+    pub camera: OrbitState,
+    pub camera_keyframes: CameraKeyframes,
+    pub timeline: Timeline,
+    pub post_process: PostProcessSettings,
+    pub render: RenderSettings,
+    pub annotations: Vec<Annotation>,
+}
+
+impl Default for Project {
+    fn default() -> Self {
+        Self {
+            version: PROJECT_VERSION,
+            simulation: FluidSimulationConfig::default(),
+            camera: OrbitState::default(),
+            camera_keyframes: CameraKeyframes::default(),
+            timeline: Timeline::default(),
+            post_process: PostProcessSettings::default(),
+            render: RenderSettings::default(),
+            annotations: Vec::new(),
+        }
+    }
+}
+
+impl Project {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SplooshError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SplooshError> {
+        std::fs::write(path, self.to_ron()?)?;
+
+        Ok(())
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, SplooshError> {
+        // RON writes structs in parentheses, so a brace can only start a JSON object
+        let (mut project, written): (Project, WrittenProject) =
+            if contents.trim_start().starts_with('{') {
+                (
+                    serde_json::from_str(contents)?,
+                    serde_json::from_str(contents)?,
+                )
+            } else {
+                (ron::from_str(contents)?, ron::from_str(contents)?)
+            };
+
+        check_version(project.version, "project")?;
+        migrate_simulation(
+            &mut project.simulation,
+            project.version,
+            &written.simulation,
+        );
+        project.version = PROJECT_VERSION;
+
+        Ok(project)
+    }
+
+    pub fn to_ron(&self) -> Result<String, SplooshError> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+}
+
+/// The fields of a simulation config as they are written in the file. Serde fills in the
+/// defaults of missing fields, which already have the current meaning and must not be migrated.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename = "FluidSimulationConfig")]
+pub(crate) struct WrittenSimulation {
+    /// Only used by simulation config files, the other files keep it next to the simulation
+    pub(crate) version: Option<u32>,
+    particle_cnt: Option<usize>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename = "Project")]
+struct WrittenProject {
+    simulation: WrittenSimulation,
+}
+
+/// Files without a version are from before the versions were introduced.
+pub(crate) fn unversioned() -> u32 {
+    1
+}
+
+/// Rejects files from newer versions instead of reading their fields wrong.
+pub(crate) fn check_version(version: u32, file: &str) -> Result<(), SplooshError> {
+    if version > PROJECT_VERSION {
+        return Err(SplooshError::Config(format!(
+            "The {file} is from version {version}, this build reads up to version {PROJECT_VERSION}"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Updates a simulation config saved with `version` to the current meaning of its fields.
+/// Settings and config files store the simulation config as well and share the project
+/// versions.
+pub(crate) fn migrate_simulation(
+    config: &mut FluidSimulationConfig,
+    version: u32,
+    written: &WrittenSimulation,
+) {
+    if version < 2 && written.particle_cnt.is_some() {
+        config.particle_cnt = config
+            .particle_cnt
+            .saturating_sub(config.ghost_particle_cnt());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let project = Project {
+            simulation: FluidSimulationConfig {
+                viscosity: 2.5,
+                gravity: Vector3::new(0.0, -3.0, 0.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let loaded = Project::parse(&project.to_ron().unwrap()).unwrap();
+        assert_eq!(loaded.simulation, project.simulation);
+        assert_eq!(loaded.version, PROJECT_VERSION);
+    }
+
+    #[test]
+    fn reads_json_and_rejects_newer_versions() {
+        let project =
+            Project::parse(r#"{"version": 2, "simulation": {"viscosity": 2.0}}"#).unwrap();
+        assert_eq!(project.simulation.viscosity, 2.0);
+
+        assert!(Project::parse(r#"{"version": 99}"#).is_err());
+    }
+
+    #[test]
+    fn version_1_counts_without_ghosts() {
+        let config = FluidSimulationConfig::default();
+        let ghost_particle_cnt = config.ghost_particle_cnt();
+        assert!(ghost_particle_cnt > 0);
+
+        let project = Project::parse(&format!(
+            r#"{{"version": 1, "simulation": {{"particle_cnt": {}}}}}"#,
+            config.particle_cnt + ghost_particle_cnt
+        ))
+        .unwrap();
+        assert_eq!(project.simulation.particle_cnt, config.particle_cnt);
+        assert_eq!(project.version, PROJECT_VERSION);
+    }
+
+    #[test]
+    fn version_1_keeps_default_particle_cnt() {
+        let default = FluidSimulationConfig::default();
+
+        let project =
+            Project::parse(r#"{"version": 1, "simulation": {"viscosity": 2.0}}"#).unwrap();
+        assert_eq!(project.simulation.particle_cnt, default.particle_cnt);
+
+        let project = Project::parse("(version: 1, simulation: (viscosity: 2.0))").unwrap();
+        assert_eq!(project.simulation.particle_cnt, default.particle_cnt);
+    }
+}
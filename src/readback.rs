@@ -0,0 +1,280 @@
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{SplooshError, WgpuDevice};
+
+/// Staging buffers per readback, a copy is skipped while all of them are in flight.
+pub const READBACK_SLOTS: usize = 3;
+
+enum SlotState {
+    Free,
+    /// Copied into by an encoder, mapped once the encoder is submitted
+    Copied(u64),
+    Mapping(u64),
+    Mapped(u64),
+    Failed(u64, wgpu::BufferAsyncError),
+}
+
+struct Slot {
+    buffer: wgpu::Buffer,
+    state: Arc<Mutex<SlotState>>,
+}
+
+/// Reads a GPU buffer back without stalling the frame. Copies go into a ring of staging
+/// buffers, which are mapped by `poll` after the copies are submitted and read by a later
+/// `poll` once the GPU is done with them, usually a frame or two later. `poll` and `wait` only
+/// return the newest copy and drop older ones, `poll_all` returns every copy in order.
+pub struct Readback<T> {
+    slots: Arc<[Slot]>,
+    copy_cnt: Arc<AtomicU64>,
+    /// Sequence number after the last returned copy, older copies are dropped
+    returned: Arc<AtomicU64>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Readback<T> {
+    fn clone(&self) -> Self {
+        Self {
+            slots: self.slots.clone(),
+            copy_cnt: self.copy_cnt.clone(),
+            returned: self.returned.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: bytemuck::Pod> Readback<T> {
+    /// Staging buffers of `size` bytes.
+    pub fn new(wgpu_device: &WgpuDevice, label: &str, size: u64) -> Self {
+        let slots = (0..READBACK_SLOTS)
+            .map(|_| Slot {
+                buffer: wgpu_device.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(label),
+                    size,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                state: Arc::new(Mutex::new(SlotState::Free)),
+            })
+            .collect();
+
+        Self {
+            slots,
+            copy_cnt: Arc::new(AtomicU64::new(0)),
+            returned: Arc::new(AtomicU64::new(0)),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Records the copies made by `copy` into a free staging buffer. Returns false without
+    /// calling `copy` if every staging buffer is still in flight.
+    pub fn copy(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        copy: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::Buffer),
+    ) -> bool {
+        for slot in self.slots.iter() {
+            let mut state = slot.state.lock().unwrap();
+            if matches!(*state, SlotState::Free) {
+                copy(encoder, &slot.buffer);
+                *state = SlotState::Copied(self.copy_cnt.fetch_add(1, Ordering::Relaxed));
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// The newest copy that finished since the last call, `None` if none did. Has to be
+    /// called after the copies are submitted.
+    pub fn poll(&self, device: &wgpu::Device) -> Option<Result<Vec<T>, SplooshError>> {
+        let _span = tracing::trace_span!("readback_poll").entered();
+        self.map_copied();
+        device.poll(wgpu::Maintain::Poll);
+        self.take_newest()
+    }
+
+    /// Every copy that finished since the last call, oldest first. Copies finishing behind one
+    /// that is still in flight are held back for a later call to keep the order. Has to be
+    /// called after the copies are submitted.
+    pub fn poll_all(&self, device: &wgpu::Device) -> Vec<Result<Vec<T>, SplooshError>> {
+        let _span = tracing::trace_span!("readback_poll_all").entered();
+        self.map_copied();
+        device.poll(wgpu::Maintain::Poll);
+        self.take_in_order()
+    }
+
+    /// Blocks until the copies submitted so far have finished and returns the newest one.
+    pub fn wait(&self, device: &wgpu::Device) -> Result<Vec<T>, SplooshError> {
+        let _span = tracing::debug_span!("readback_wait").entered();
+        self.map_copied();
+        device.poll(wgpu::Maintain::Wait);
+        self.take_newest().unwrap_or_else(|| {
+            Err(SplooshError::Capture(
+                "Nothing was copied for the readback".to_string(),
+            ))
+        })
+    }
+
+    /// Drops the copies recorded so far, e.g. after their source changed meaning.
+    pub fn discard(&self) {
+        self.returned
+            .fetch_max(self.copy_cnt.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    fn map_copied(&self) {
+        for slot in self.slots.iter() {
+            let sequence = {
+                let mut state = slot.state.lock().unwrap();
+                let SlotState::Copied(sequence) = *state else {
+                    continue;
+                };
+                *state = SlotState::Mapping(sequence);
+                sequence
+            };
+
+            // the callback may run right away, so the lock is released before
+            let state = slot.state.clone();
+            slot.buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    *state.lock().unwrap() = match result {
+                        Ok(()) => SlotState::Mapped(sequence),
+                        Err(err) => SlotState::Failed(sequence, err),
+                    };
+                });
+        }
+    }
+
+    fn take_newest(&self) -> Option<Result<Vec<T>, SplooshError>> {
+        let mut newest: Option<(u64, Result<Vec<T>, SplooshError>)> = None;
+        for slot in self.slots.iter() {
+            let Some((sequence, result)) = take_finished(slot, |_| true) else {
+                continue;
+            };
+
+            if newest.as_ref().is_none_or(|(newest, _)| sequence > *newest) {
+                newest = Some((sequence, result));
+            }
+        }
+
+        let (sequence, result) = newest?;
+        // a slow copy finishing after a newer one was returned is out of date
+        let returned = self.returned.fetch_max(sequence + 1, Ordering::Relaxed);
+        (sequence >= returned).then_some(result)
+    }
+
+    fn take_in_order(&self) -> Vec<Result<Vec<T>, SplooshError>> {
+        let in_flight = self
+            .slots
+            .iter()
+            .filter_map(|slot| match *slot.state.lock().unwrap() {
+                SlotState::Copied(sequence) | SlotState::Mapping(sequence) => Some(sequence),
+                _ => None,
+            })
+            .min()
+            .unwrap_or(u64::MAX);
+
+        let mut finished: Vec<(u64, Result<Vec<T>, SplooshError>)> = self
+            .slots
+            .iter()
+            .filter_map(|slot| take_finished(slot, |sequence| sequence < in_flight))
+            .collect();
+        finished.sort_by_key(|(sequence, _)| *sequence);
+
+        let Some((newest, _)) = finished.last() else {
+            return Vec::new();
+        };
+        let returned = self.returned.fetch_max(newest + 1, Ordering::Relaxed);
+        finished
+            .into_iter()
+            .filter(|(sequence, _)| *sequence >= returned)
+            .map(|(_, result)| result)
+            .collect()
+    }
+}
+
+/// Reads and frees the slot if its copy finished and `take` accepts its sequence number.
+fn take_finished<T: bytemuck::Pod>(
+    slot: &Slot,
+    take: impl FnOnce(u64) -> bool,
+) -> Option<(u64, Result<Vec<T>, SplooshError>)> {
+    let mut state = slot.state.lock().unwrap();
+    match *state {
+        SlotState::Mapped(sequence) | SlotState::Failed(sequence, _) if take(sequence) => {}
+        _ => return None,
+    }
+
+    match std::mem::replace(&mut *state, SlotState::Free) {
+        SlotState::Mapped(sequence) => {
+            let data = bytemuck::cast_slice(&slot.buffer.slice(..).get_mapped_range()).to_vec();
+            slot.buffer.unmap();
+            Some((sequence, Ok(data)))
+        }
+        SlotState::Failed(sequence, err) => Some((sequence, Err(err.into()))),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::FutureExt as _;
+
+    use super::*;
+
+    #[test]
+    fn returns_the_newest_copy() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let readback = Readback::<u32>::new(&wgpu_device, "Test readback", 8);
+
+        let mut encoder = wgpu_device
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for value in [1u32, 2] {
+            let source = wgpu_device.create_buffer_init(&[value; 2], wgpu::BufferUsages::COPY_SRC);
+            assert!(readback.copy(&mut encoder, |encoder, staging| {
+                encoder.copy_buffer_to_buffer(&source, 0, staging, 0, 8);
+            }));
+        }
+        wgpu_device.submit(encoder);
+
+        assert_eq!(readback.wait(&wgpu_device.device).unwrap(), vec![2, 2]);
+        // both staging buffers are free again and nothing is left to read
+        assert!(readback.poll(&wgpu_device.device).is_none());
+    }
+
+    #[test]
+    fn returns_every_copy_in_order() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let readback = Readback::<u32>::new(&wgpu_device, "Test readback", 4);
+
+        let mut encoder = wgpu_device
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for value in 1u32..=READBACK_SLOTS as u32 {
+            let source = wgpu_device.create_buffer_init(&[value], wgpu::BufferUsages::COPY_SRC);
+            assert!(readback.copy(&mut encoder, |encoder, staging| {
+                encoder.copy_buffer_to_buffer(&source, 0, staging, 0, 4);
+            }));
+        }
+        wgpu_device.submit(encoder);
+
+        // the first poll maps the staging buffers, the second one reads what is left
+        let read = |values: &mut Vec<u32>| {
+            for data in readback.poll_all(&wgpu_device.device) {
+                values.push(data.unwrap()[0]);
+            }
+        };
+        let mut values = Vec::new();
+        read(&mut values);
+        wgpu_device.device.poll(wgpu::Maintain::Wait);
+        read(&mut values);
+        assert_eq!(values, (1..=READBACK_SLOTS as u32).collect::<Vec<_>>());
+        assert!(readback.poll_all(&wgpu_device.device).is_empty());
+    }
+}
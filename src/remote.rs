@@ -0,0 +1,288 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fluid_simulation::{FluidSimulationConfig, ParticleSnapshot},
+    SplooshError,
+};
+
+/// Telemetry clients that don't read for this long are dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteConfig {
+    pub address: String,
+    /// Seconds between two telemetry messages, each one reads the particles back
+    pub telemetry_interval: f32,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:7878".to_string(),
+            telemetry_interval: 0.5,
+        }
+    }
+}
+
+/// A line of JSON sent by a client, e.g. `{"command": "set", "parameters": {"viscosity": 2.0}}`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum RemoteCommand {
+    Pause,
+    Resume,
+    /// Single step of a paused simulation
+    Step,
+    Reset,
+    /// Replaces fields of the simulation config, baked in fields rebuild the simulation
+    Set {
+        parameters: serde_json::Map<String, serde_json::Value>,
+    },
+}
+
+impl RemoteCommand {
+    /// `config` with the fields of a `Set` command replaced. Nested values like the wave
+    /// paddle are replaced as a whole.
+    pub fn apply_parameters(
+        config: &FluidSimulationConfig,
+        parameters: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<FluidSimulationConfig, SplooshError> {
+        let mut value = serde_json::to_value(config)?;
+        let fields = value
+            .as_object_mut()
+            .ok_or_else(|| SplooshError::Config("The config is not an object".to_string()))?;
+        for (name, parameter) in parameters {
+            if !fields.contains_key(name) {
+                return Err(SplooshError::Config(format!("Unknown parameter {name}")));
+            }
+            fields.insert(name.clone(), parameter.clone());
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// A command together with the client that sent it.
+pub struct RemoteRequest {
+    pub command: RemoteCommand,
+    client: TcpStream,
+}
+
+impl RemoteRequest {
+    /// Answers the client with `{"ok": true}` or `{"error": "..."}`.
+    pub fn respond(mut self, result: Result<(), SplooshError>) {
+        let response = match result {
+            Ok(()) => serde_json::json!({ "ok": true }),
+            Err(err) => serde_json::json!({ "error": err.to_string() }),
+        };
+        // the client may have disconnected in the meantime
+        let _ = writeln!(self.client, "{response}");
+    }
+}
+
+/// Statistics streamed to every client.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Telemetry {
+    pub time: f32,
+    pub step_cnt: u64,
+    pub fps: f32,
+    pub paused: bool,
+    pub particle_cnt: usize,
+    pub kinetic_energy: f32,
+    /// Relative to the corner of the bounding box
+    pub potential_energy: f32,
+    /// Mean of `|density / rest_density - 1|` over the fluid particles
+    pub mean_density_error: f32,
+    pub max_density_error: f32,
+}
+
+impl Telemetry {
+    pub fn from_snapshot(
+        snapshot: &ParticleSnapshot,
+        config: &FluidSimulationConfig,
+        ghost_particle_cnt: usize,
+    ) -> Self {
+        let fluid = ghost_particle_cnt.min(snapshot.positions.len())..snapshot.positions.len();
+        let mut telemetry = Telemetry {
+            time: snapshot.time,
+            step_cnt: snapshot.step_cnt,
+            particle_cnt: fluid.len(),
+            ..Default::default()
+        };
+
+        for i in fluid.clone() {
+            let position: Vector3<f32> = snapshot.positions[i].xyz().coords;
+            let velocity = snapshot.velocities[i].xyz();
+            telemetry.kinetic_energy += 0.5 * config.mass * velocity.norm_squared();
+            telemetry.potential_energy -= config.mass * config.gravity.dot(&position);
+
+            let density_error = (snapshot.densities[i] / config.rest_density - 1.0).abs();
+            telemetry.mean_density_error += density_error;
+            telemetry.max_density_error = telemetry.max_density_error.max(density_error);
+        }
+        telemetry.mean_density_error /= fluid.len().max(1) as f32;
+
+        telemetry
+    }
+}
+
+/// Accepts TCP connections that send commands and receive telemetry as newline separated
+/// JSON. Every client runs on its own thread, the application polls the commands once per
+/// frame.
+pub struct RemoteServer {
+    address: SocketAddr,
+    requests: Receiver<RemoteRequest>,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl RemoteServer {
+    pub fn start(config: &RemoteConfig) -> Result<Self, SplooshError> {
+        let listener = TcpListener::bind(&config.address)?;
+        let address = listener.local_addr()?;
+        let (sender, requests) = mpsc::channel();
+        let clients = Arc::new(Mutex::new(Vec::new()));
+
+        let accepted = clients.clone();
+        std::thread::spawn(move || {
+            for client in listener.incoming().flatten() {
+                let (Ok(reader), Ok(writer)) = (client.try_clone(), client.try_clone()) else {
+                    continue;
+                };
+                if client.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+                    continue;
+                }
+                accepted.lock().unwrap().push(writer);
+
+                let sender = sender.clone();
+                std::thread::spawn(move || serve_client(reader, client, sender));
+            }
+        });
+
+        Ok(Self {
+            address,
+            requests,
+            clients,
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    pub fn client_cnt(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /// Commands received since the last call.
+    pub fn poll(&self) -> Vec<RemoteRequest> {
+        self.requests.try_iter().collect()
+    }
+
+    /// Sends the telemetry to every client and drops the ones that disconnected.
+    pub fn broadcast(&self, telemetry: &Telemetry) -> Result<(), SplooshError> {
+        let line = serde_json::to_string(telemetry)?;
+        self.clients
+            .lock()
+            .unwrap()
+            .retain_mut(|client| writeln!(client, "{line}").is_ok());
+
+        Ok(())
+    }
+}
+
+fn serve_client(reader: TcpStream, mut client: TcpStream, requests: Sender<RemoteRequest>) {
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str(&line) {
+            Ok(command) => {
+                let Ok(client) = client.try_clone() else {
+                    return;
+                };
+                if requests.send(RemoteRequest { command, client }).is_err() {
+                    return;
+                }
+            }
+            Err(err) => {
+                let response = serde_json::json!({ "error": err.to_string() });
+                if writeln!(client, "{response}").is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point4, Vector4};
+
+    use super::*;
+
+    #[test]
+    fn set_replaces_config_fields() {
+        let command: RemoteCommand = serde_json::from_str(
+            r#"{"command": "set", "parameters": {"viscosity": 2.0, "gravity": [0.0, -2.0, 0.0]}}"#,
+        )
+        .unwrap();
+        let RemoteCommand::Set { parameters } = command else {
+            panic!("expected a set command");
+        };
+
+        let config = FluidSimulationConfig::default();
+        let changed = RemoteCommand::apply_parameters(&config, &parameters).unwrap();
+        assert_eq!(changed.viscosity, 2.0);
+        assert_eq!(changed.gravity, Vector3::new(0.0, -2.0, 0.0));
+        assert_eq!(changed.particle_cnt, config.particle_cnt);
+
+        let mut unknown = serde_json::Map::new();
+        unknown.insert("viscocity".to_string(), 2.0.into());
+        assert!(RemoteCommand::apply_parameters(&config, &unknown).is_err());
+    }
+
+    #[test]
+    fn telemetry_skips_ghost_particles() {
+        let config = FluidSimulationConfig {
+            mass: 2.0,
+            rest_density: 100.0,
+            gravity: Vector3::new(0.0, -1.0, 0.0),
+            ..Default::default()
+        };
+        let snapshot = ParticleSnapshot {
+            positions: vec![
+                Point4::new(0.0, 0.0, 0.0, 1.0),
+                Point4::new(0.0, 3.0, 0.0, 1.0),
+            ],
+            velocities: vec![
+                Vector4::new(5.0, 0.0, 0.0, 0.0),
+                Vector4::new(1.0, 0.0, 0.0, 0.0),
+            ],
+            densities: vec![0.0, 110.0],
+            time: 1.5,
+            step_cnt: 90,
+        };
+
+        let telemetry = Telemetry::from_snapshot(&snapshot, &config, 1);
+        assert_eq!(telemetry.particle_cnt, 1);
+        assert_eq!(telemetry.kinetic_energy, 1.0);
+        assert_eq!(telemetry.potential_energy, 6.0);
+        assert!((telemetry.mean_density_error - 0.1).abs() < 1e-6);
+        assert_eq!(telemetry.step_cnt, 90);
+    }
+}
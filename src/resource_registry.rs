@@ -0,0 +1,114 @@
+use std::sync::{Arc, Mutex, Weak};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResourceKind {
+    Buffer(wgpu::BufferUsages),
+    Texture(wgpu::TextureUsages),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceInfo {
+    pub label: String,
+    /// Bytes, estimated from the format for textures
+    pub size: u64,
+    pub kind: ResourceKind,
+}
+
+enum Handle {
+    Buffer(Weak<wgpu::Buffer>),
+    Texture(Weak<wgpu::Texture>),
+}
+
+impl Handle {
+    fn is_alive(&self) -> bool {
+        match self {
+            Handle::Buffer(buffer) => buffer.strong_count() > 0,
+            Handle::Texture(texture) => texture.strong_count() > 0,
+        }
+    }
+}
+
+/// Buffers and textures created through `WgpuDevice`, listed in the gui to see where the GPU
+/// memory goes. A resource is listed until the last `Arc` to it is dropped, resources only
+/// kept alive by a bind group aren't tracked.
+#[derive(Clone, Default)]
+pub struct ResourceRegistry {
+    resources: Arc<Mutex<Vec<(Handle, ResourceInfo)>>>,
+}
+
+impl ResourceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track_buffer(&self, buffer: &Arc<wgpu::Buffer>, label: Option<&str>) {
+        let info = ResourceInfo {
+            label: label.unwrap_or("Unnamed buffer").to_string(),
+            size: buffer.size(),
+            kind: ResourceKind::Buffer(buffer.usage()),
+        };
+        self.track(Handle::Buffer(Arc::downgrade(buffer)), info);
+    }
+
+    pub fn track_texture(&self, texture: &Arc<wgpu::Texture>, label: Option<&str>) {
+        let format = texture.format();
+        let size = (0..texture.mip_level_count())
+            .map(|level| {
+                let extent = texture.size().mip_level_size(level, texture.dimension());
+                format.theoretical_memory_footprint(extent)
+            })
+            .sum::<u64>()
+            * texture.sample_count() as u64;
+
+        let info = ResourceInfo {
+            label: label.unwrap_or("Unnamed texture").to_string(),
+            size,
+            kind: ResourceKind::Texture(texture.usage()),
+        };
+        self.track(Handle::Texture(Arc::downgrade(texture)), info);
+    }
+
+    /// The resources still alive, largest first.
+    pub fn resources(&self) -> Vec<ResourceInfo> {
+        let mut resources = self.resources.lock().unwrap();
+        resources.retain(|(handle, _)| handle.is_alive());
+
+        let mut infos: Vec<_> = resources.iter().map(|(_, info)| info.clone()).collect();
+        infos.sort_by(|a, b| b.size.cmp(&a.size));
+        infos
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.resources().iter().map(|info| info.size).sum()
+    }
+
+    fn track(&self, handle: Handle, info: ResourceInfo) {
+        self.resources.lock().unwrap().push((handle, info));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::FutureExt as _;
+
+    use crate::WgpuDevice;
+
+    #[test]
+    fn dropped_buffers_are_forgotten() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Test buffer"),
+            size: 256,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let resources = wgpu_device.resources.resources();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].label, "Test buffer");
+        assert_eq!(wgpu_device.resources.total_size(), 256);
+
+        drop(buffer);
+        assert!(wgpu_device.resources.resources().is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+//! Rest states cache the settled particles of a scene. Letting a fresh layout come to rest
+//! takes seconds of simulated time, the rest state is settled once and loaded instantly on
+//! every later start with the same config.
+
+use std::path::{Path, PathBuf};
+
+use nalgebra::{Point4, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fluid_simulation::{FluidSimulationConfig, ParticleSnapshot},
+    SplooshError,
+};
+
+const REST_STATE_MAGIC: &[u8; 8] = b"SPLREST\0";
+
+/// Increased whenever the layout of the file changes, other versions are settled again.
+/// Version 2 stores the body in little endian instead of the native byte order.
+pub const REST_STATE_VERSION: u32 = 2;
+
+/// Starts the simulation from the particles saved in `path`. A missing file, or one saved
+/// with a different config, is replaced by stepping the new simulation for `settle_time`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RestStateConfig {
+    pub path: PathBuf,
+    /// Simulated seconds the particles settle for before they are saved
+    pub settle_time: f32,
+    /// Time step of the settling steps
+    pub dt: f32,
+}
+
+impl Default for RestStateConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("rest_state.bin"),
+            settle_time: 5.0,
+            dt: 1.0 / 120.0,
+        }
+    }
+}
+
+/// Settled particles, including the ghost particles, with the fingerprint of the config
+/// they were settled with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RestState {
+    pub fingerprint: u64,
+    pub positions: Vec<Point4<f32>>,
+    pub velocities: Vec<Vector4<f32>>,
+    pub densities: Vec<f32>,
+}
+
+impl RestState {
+    pub fn new(snapshot: &ParticleSnapshot, fingerprint: u64) -> Self {
+        Self {
+            fingerprint,
+            positions: snapshot.positions.clone(),
+            velocities: snapshot.velocities.clone(),
+            densities: snapshot.densities.clone(),
+        }
+    }
+
+    /// Snapshot at the start of the simulation.
+    pub fn snapshot(&self) -> ParticleSnapshot {
+        ParticleSnapshot {
+            positions: self.positions.clone(),
+            velocities: self.velocities.clone(),
+            densities: self.densities.clone(),
+            time: 0.0,
+            step_cnt: 0,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SplooshError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SplooshError> {
+        std::fs::write(path, self.to_bytes())?;
+
+        Ok(())
+    }
+
+    /// The magic, the version, the fingerprint and the particle count as a header, followed
+    /// by the positions, velocities and densities, all little endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            32 + self.positions.len() * (2 * std::mem::size_of::<Point4<f32>>() + 4),
+        );
+        bytes.extend_from_slice(REST_STATE_MAGIC);
+        bytes.extend_from_slice(&REST_STATE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&self.fingerprint.to_le_bytes());
+        bytes.extend_from_slice(&(self.positions.len() as u64).to_le_bytes());
+        let floats = self
+            .positions
+            .iter()
+            .flat_map(|position| position.coords.iter())
+            .chain(self.velocities.iter().flat_map(|velocity| velocity.iter()))
+            .chain(&self.densities);
+        for float in floats {
+            bytes.extend_from_slice(&float.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SplooshError> {
+        let invalid =
+            |message: &str| SplooshError::Snapshot(format!("Invalid rest state, {message}"));
+        if bytes.len() < 32 || &bytes[..8] != REST_STATE_MAGIC {
+            return Err(invalid("the header is missing"));
+        }
+
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != REST_STATE_VERSION {
+            return Err(invalid(&format!(
+                "version {version}, this build reads version {REST_STATE_VERSION}"
+            )));
+        }
+        let fingerprint = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+        let particle_cnt = u64::from_le_bytes(bytes[24..32].try_into().unwrap()) as usize;
+
+        let point_size = std::mem::size_of::<Point4<f32>>();
+        let body = &bytes[32..];
+        if body.len() != particle_cnt * (2 * point_size + 4) {
+            return Err(invalid(&format!(
+                "{} bytes don't hold {particle_cnt} particles",
+                body.len()
+            )));
+        }
+        let floats: Vec<f32> = body
+            .chunks_exact(4)
+            .map(|float| f32::from_le_bytes(float.try_into().unwrap()))
+            .collect();
+        let (positions, rest) = floats.split_at(4 * particle_cnt);
+        let (velocities, densities) = rest.split_at(4 * particle_cnt);
+
+        Ok(Self {
+            fingerprint,
+            positions: positions
+                .chunks_exact(4)
+                .map(|p| Point4::new(p[0], p[1], p[2], p[3]))
+                .collect(),
+            velocities: velocities
+                .chunks_exact(4)
+                .map(Vector4::from_column_slice)
+                .collect(),
+            densities: densities.to_vec(),
+        })
+    }
+}
+
+/// Hash of everything in `config` that changes how the particles settle. The fields that can
+/// change at runtime without touching the solver and the path of the rest state are left out.
+pub fn config_fingerprint(config: &FluidSimulationConfig) -> Result<u64, SplooshError> {
+    let settled = FluidSimulationConfig {
+        rest_state: config
+            .rest_state
+            .as_ref()
+            .map(|rest_state| RestStateConfig {
+                path: PathBuf::new(),
+                ..rest_state.clone()
+            }),
+        // changeable at runtime, but the particles settle differently with them
+        integrator: config.integrator,
+        free_surface_correction: config.free_surface_correction,
+        density_renormalization: config.density_renormalization,
+        ..config.with_runtime_fields_of(&FluidSimulationConfig::default())
+    };
+    let json = serde_json::to_string(&settled)?;
+
+    // FNV-1a, unlike the std hasher it doesn't change between builds
+    Ok(json.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fluid_simulation::Integrator;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let rest_state = RestState {
+            fingerprint: 42,
+            positions: vec![
+                Point4::new(1.0, 2.0, 3.0, 1.0),
+                Point4::new(4.0, 5.0, 6.0, 1.0),
+            ],
+            velocities: vec![Vector4::new(0.0, -1.0, 0.0, 0.0), Vector4::zeros()],
+            densities: vec![200.0, 201.5],
+        };
+
+        let bytes = rest_state.to_bytes();
+        let loaded = RestState::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded, rest_state);
+        // the x of the first position starts the body on every platform
+        assert_eq!(bytes[32..36], 1.0f32.to_le_bytes());
+
+        assert!(RestState::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn fingerprint_ignores_display_fields_and_path() {
+        let config = FluidSimulationConfig {
+            rest_state: Some(RestStateConfig::default()),
+            ..Default::default()
+        };
+        let fingerprint = config_fingerprint(&config).unwrap();
+
+        let moved = FluidSimulationConfig {
+            rest_state: Some(RestStateConfig {
+                path: PathBuf::from("elsewhere.bin"),
+                ..RestStateConfig::default()
+            }),
+            diagnostics: true,
+            ..config.clone()
+        };
+        assert_eq!(config_fingerprint(&moved).unwrap(), fingerprint);
+
+        let heavier = FluidSimulationConfig {
+            mass: config.mass * 2.0,
+            ..config.clone()
+        };
+        assert_ne!(config_fingerprint(&heavier).unwrap(), fingerprint);
+
+        let corrected = FluidSimulationConfig {
+            free_surface_correction: !config.free_surface_correction,
+            ..config.clone()
+        };
+        assert_ne!(config_fingerprint(&corrected).unwrap(), fingerprint);
+
+        let verlet = FluidSimulationConfig {
+            integrator: Integrator::Verlet,
+            ..config.clone()
+        };
+        assert_ne!(config_fingerprint(&verlet).unwrap(), fingerprint);
+    }
+}
@@ -0,0 +1,32 @@
+use std::sync::{Arc, RwLock};
+
+use crate::{
+    graphics::{Camera, RenderEngine},
+    input_helper::InputHelper,
+    CameraController, FluidSimulation, WgpuRenderDevice,
+};
+
+/// Everything a scene is allowed to touch while the application is running.
+pub struct SceneContext<'a> {
+    pub render_device: &'a Arc<RwLock<WgpuRenderDevice>>,
+    pub render_engine: &'a mut RenderEngine,
+    pub fluid_sim: &'a mut FluidSimulation,
+    pub camera: &'a mut Camera,
+    pub camera_controller: &'a mut CameraController,
+    pub input_helper: Option<&'a InputHelper>,
+}
+
+/// Hooks for embedding custom logic into the application. Every method has an empty default,
+/// so a scene only implements the ones it needs.
+pub trait Scene {
+    /// Called once after the window and the fluid simulation have been created, and again
+    /// whenever the simulation is reset or recreated on a new device.
+    fn setup(&mut self, _ctx: &mut SceneContext) {}
+
+    /// Called every frame after the camera has been updated and before the simulation step
+    /// is submitted. Render requests submitted here are drawn along with the simulation.
+    fn update(&mut self, _ctx: &mut SceneContext, _dt: f32) {}
+
+    /// Adds widgets to the bottom of the scene panel.
+    fn gui(&mut self, _ctx: &mut SceneContext, _ui: &mut egui::Ui) {}
+}
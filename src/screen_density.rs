@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use nalgebra::{Matrix4, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    colormap::{Colormap, ColormapTexture, COLORMAP_SHADER},
+    graphics::{camera::Camera, render_engine::GenericRequest},
+    wgpu_device::Uploader,
+    ComputeTask, WgpuDevice,
+};
+
+/// Texels along each side of the screen space grid, stretched over the main viewport.
+pub const SCREEN_DENSITY_RESOLUTION: u32 = 256;
+
+/// Heatmap of the projected particle density drawn over the main viewport, which shows where
+/// the particles pile up along the view direction without the volume renderer.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenDensityConfig {
+    pub colormap: Colormap,
+    /// Of the heatmap over the scene, empty texels are transparent
+    pub opacity: f32,
+}
+
+impl Default for ScreenDensityConfig {
+    fn default() -> Self {
+        Self {
+            colormap: Colormap::Heatmap,
+            opacity: 0.8,
+        }
+    }
+}
+
+/// Splats the fluid particles onto a grid over the screen with the camera of the main
+/// viewport and color maps the counts into a texture. Like the minimap, the counts are
+/// normalized by the fullest texel.
+pub struct ScreenDensity {
+    grid_buffer: Arc<wgpu::Buffer>,
+    view_projection_buffer: Arc<wgpu::Buffer>,
+    uploader: Uploader,
+    view: wgpu::TextureView,
+    colormap_texture: ColormapTexture,
+    splat_task: Arc<ComputeTask>,
+    color_task: Arc<ComputeTask>,
+}
+
+impl ScreenDensity {
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        bbox_dimensions: Vector3<f32>,
+        positions: &wgpu::Buffer,
+    ) -> Self {
+        let size = SCREEN_DENSITY_RESOLUTION;
+
+        // the counts of the texels followed by the largest one
+        let grid_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screen density grid buffer"),
+            size: (size as u64 * size as u64 + 1) * 4,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let view_projection_buffer = wgpu_device.create_buffer_init(
+            &[Matrix4::<f32>::identity()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let texture = wgpu_device.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Screen density texture"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let colormap_texture = ColormapTexture::new(wgpu_device);
+
+        // the particles are drawn centered at the origin
+        let constants = format!(
+            "
+             const OFFSET: vec3<f32> = vec3<f32>({}, {}, {});\n
+             const WIDTH: u32 = {size}u;\n
+             const HEIGHT: u32 = {size}u;\n
+             const GHOST_PARTICLES: u32 = {ghost_particle_cnt}u;\n",
+            -bbox_dimensions.x / 2.0,
+            -bbox_dimensions.y / 2.0,
+            -bbox_dimensions.z / 2.0,
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let fluid_particle_cnt = particle_cnt.saturating_sub(ghost_particle_cnt) as u32;
+        let splat_task = Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Screen density splat",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: grid_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: view_projection_buffer.as_entire_binding(),
+                },
+            ],
+            &[],
+            format!(
+                "{constants}{}",
+                include_str!("shaders/screen_density_splat.wgsl")
+            )
+            .into(),
+            (fluid_particle_cnt.div_ceil(256).max(1), 1, 1),
+        ));
+
+        let color_task = Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Screen density color",
+            &[
+                storage_entry(0, true),
+                ColormapTexture::layout_entry(1, wgpu::ShaderStages::COMPUTE),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba8Unorm,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: grid_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(colormap_texture.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+            ],
+            &[],
+            format!(
+                "{constants}{COLORMAP_SHADER}{}",
+                include_str!("shaders/screen_density_color.wgsl")
+            )
+            .into(),
+            (size.div_ceil(16), size.div_ceil(16), 1),
+        ));
+
+        Self {
+            grid_buffer,
+            view_projection_buffer,
+            uploader: wgpu_device.uploader.clone(),
+            view,
+            colormap_texture,
+            splat_task,
+            color_task,
+        }
+    }
+
+    /// Projects the particles with `camera` at the `aspect` ratio of the main viewport.
+    pub fn update_fn(
+        &self,
+        config: ScreenDensityConfig,
+        camera: &Camera,
+        aspect: f32,
+    ) -> GenericRequest {
+        let grid_buffer = self.grid_buffer.clone();
+        let view_projection_buffer = self.view_projection_buffer.clone();
+        let uploader = self.uploader.clone();
+        let upload_colormap = self.colormap_texture.upload_fn(config.colormap);
+        let splat_task = self.splat_task.clone();
+        let color_task = self.color_task.clone();
+        let view_projection = camera.get_projection_matrix(aspect) * camera.get_view_matrix();
+
+        Box::new(move |encoder, queue| {
+            uploader.write(
+                encoder,
+                &view_projection_buffer,
+                0,
+                bytemuck::bytes_of(&view_projection),
+            );
+            upload_colormap(encoder, queue);
+            encoder.clear_buffer(&grid_buffer, 0, None);
+            splat_task.execute(encoder, &[]);
+            color_task.execute(encoder, &[]);
+        })
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
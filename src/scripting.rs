@@ -0,0 +1,452 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use nalgebra::{Point3, Vector3};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, FnPtr, Scope, AST};
+
+use crate::{
+    debris::DebrisConfig,
+    dye::{DyeConfig, DyeEmitter},
+    fluid_simulation::{DomainBoundary, FluidSimulationConfig, InitialLayout, WavePaddle},
+    soft_body::SoftBodyConfig,
+    FluidSimulation, Scene, SceneContext, SplooshError,
+};
+
+/// Keeps endless loops in a script from freezing the application.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Values the script reads and the changes it made during one call.
+struct ScriptState {
+    config: FluidSimulationConfig,
+    time: f32,
+    step_cnt: u64,
+    /// The change is baked into the shaders and needs a new simulation
+    rebuild: bool,
+    /// Callbacks scheduled with `at`, sorted by their simulation time
+    events: Vec<(f32, FnPtr)>,
+}
+
+type SharedState = Rc<RefCell<ScriptState>>;
+
+/// A scene defined by a Rhai script. The top level of the script runs on setup and again on
+/// every reset, it configures the simulation and schedules events with `at`. An optional
+/// `fn update(t)` is called every frame with the simulation time.
+///
+/// Scripts only see a copy of the config and the statistics of the simulation. Their changes
+/// are applied after the script returns, changes baked into the shaders rebuild the simulation
+/// and keep the particles where they are.
+pub struct ScriptScene {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: SharedState,
+    has_update: bool,
+    /// Last runtime error, `update` isn't called again until the next setup
+    error: Option<String>,
+}
+
+impl ScriptScene {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SplooshError> {
+        let path = path.as_ref().to_path_buf();
+        let source = std::fs::read_to_string(&path)?;
+        let mut scene = Self::new(&source)?;
+        scene.path = path;
+
+        Ok(scene)
+    }
+
+    pub fn new(source: &str) -> Result<Self, SplooshError> {
+        let state = Rc::new(RefCell::new(ScriptState {
+            config: FluidSimulationConfig::default(),
+            time: 0.0,
+            step_cnt: 0,
+            rebuild: false,
+            events: Vec::new(),
+        }));
+        let engine = create_engine(&state);
+        let ast = engine
+            .compile(source)
+            .map_err(|err| SplooshError::Script(err.to_string()))?;
+        let has_update = ast
+            .iter_functions()
+            .any(|function| function.name == "update");
+
+        Ok(Self {
+            path: PathBuf::new(),
+            engine,
+            ast,
+            scope: Scope::new(),
+            state,
+            has_update,
+            error: None,
+        })
+    }
+
+    /// Compiles the script file again, the new script runs from the next setup.
+    pub fn reload(&mut self) -> Result<(), SplooshError> {
+        let source = std::fs::read_to_string(&self.path)?;
+        self.ast = self
+            .engine
+            .compile(&source)
+            .map_err(|err| SplooshError::Script(err.to_string()))?;
+        self.has_update = self
+            .ast
+            .iter_functions()
+            .any(|function| function.name == "update");
+
+        Ok(())
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    fn report(&mut self, result: Result<(), Box<EvalAltResult>>) {
+        if let Err(err) = result {
+            tracing::error!("Script error: {err}");
+            self.error = Some(err.to_string());
+        }
+    }
+
+    fn read_simulation(&self, fluid_sim: &FluidSimulation) {
+        let mut state = self.state.borrow_mut();
+        state.config = fluid_sim.config().clone();
+        state.time = fluid_sim.sim_time();
+        state.step_cnt = fluid_sim.step_cnt();
+        state.rebuild = false;
+    }
+
+    /// Hands the changes of the script to the simulation. Setup starts the particles over,
+    /// later rebuilds continue from the current particles if the layout allows it.
+    fn apply_changes(&self, ctx: &mut SceneContext, keep_particles: bool) {
+        let (config, rebuild) = {
+            let state = self.state.borrow();
+            (state.config.clone(), state.rebuild)
+        };
+
+        if !rebuild {
+            ctx.fluid_sim.set_dye(config.dye);
+            ctx.fluid_sim.set_debris(config.debris);
+            return;
+        }
+
+        let render_device = ctx.render_device.read().unwrap();
+        let wgpu_device = &render_device.wgpu_device;
+        if let Err(err) = config.max_particle_cnt(&wgpu_device.device.limits()) {
+            tracing::warn!("The script change was not applied: {err}");
+            return;
+        }
+        let snapshot = keep_particles
+            .then(|| ctx.fluid_sim.read_snapshot(wgpu_device))
+            .transpose();
+        let ghost_particle_cnt = ctx.fluid_sim.ghost_particle_cnt();
+
+        *ctx.fluid_sim = FluidSimulation::new(config, wgpu_device);
+
+        let restored = snapshot.and_then(|snapshot| match snapshot {
+            // the ghost particles sit at the start of the buffers, with a different count the
+            // fluid particles would land in their slots
+            Some(snapshot) if ctx.fluid_sim.ghost_particle_cnt() == ghost_particle_cnt => ctx
+                .fluid_sim
+                .restore_snapshot(render_device.queue(), &snapshot),
+            _ => Ok(()),
+        });
+        if let Err(err) = restored {
+            tracing::warn!("The script change restarted the particles: {err}");
+        }
+    }
+
+    /// Calls the events that are due, in the order they were scheduled for.
+    fn run_due_events(&mut self) {
+        let due = {
+            let mut state = self.state.borrow_mut();
+            let time = state.time;
+            let split = state.events.partition_point(|(at, _)| *at <= time);
+            state.events.drain(..split).collect::<Vec<_>>()
+        };
+
+        for (_, callback) in due {
+            let result = callback.call::<Dynamic>(&self.engine, &self.ast, ());
+            self.report(result.map(|_| ()));
+        }
+    }
+}
+
+impl Scene for ScriptScene {
+    fn setup(&mut self, ctx: &mut SceneContext) {
+        self.read_simulation(ctx.fluid_sim);
+        self.state.borrow_mut().events.clear();
+        self.scope.clear();
+        self.error = None;
+
+        let result = self.engine.run_ast_with_scope(&mut self.scope, &self.ast);
+        self.report(result);
+        self.apply_changes(ctx, false);
+    }
+
+    fn update(&mut self, ctx: &mut SceneContext, _dt: f32) {
+        self.read_simulation(ctx.fluid_sim);
+        self.run_due_events();
+
+        if self.has_update && self.error.is_none() {
+            let time = self.state.borrow().time as f64;
+            let result = self.engine.call_fn_with_options::<Dynamic>(
+                CallFnOptions::new().eval_ast(false),
+                &mut self.scope,
+                &self.ast,
+                "update",
+                (time,),
+            );
+            self.report(result.map(|_| ()));
+        }
+
+        self.apply_changes(ctx, true);
+    }
+
+    fn gui(&mut self, _ctx: &mut SceneContext, ui: &mut egui::Ui) {
+        ui.separator();
+        ui.label(format!("Script: {}", self.path.display()));
+        ui.label(format!(
+            "Pending events: {}",
+            self.state.borrow().events.len()
+        ));
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        if ui
+            .add_enabled(
+                !self.path.as_os_str().is_empty(),
+                egui::Button::new("Reload script"),
+            )
+            .on_hover_text("Takes effect on the next reset")
+            .clicked()
+        {
+            if let Err(err) = self.reload() {
+                self.error = Some(err.to_string());
+            }
+        }
+    }
+}
+
+fn create_engine(state: &SharedState) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    // statistics
+    let s = state.clone();
+    engine.register_fn("time", move || s.borrow().time as f64);
+    let s = state.clone();
+    engine.register_fn("steps", move || s.borrow().step_cnt as i64);
+    let s = state.clone();
+    engine.register_fn("particle_count", move || {
+        s.borrow().config.particle_cnt as i64
+    });
+    let s = state.clone();
+    engine.register_fn("gravity", move || {
+        let gravity = s.borrow().config.gravity;
+        vec![
+            Dynamic::from(gravity.x as f64),
+            Dynamic::from(gravity.y as f64),
+            Dynamic::from(gravity.z as f64),
+        ]
+    });
+
+    // events
+    let s = state.clone();
+    engine.register_fn("at", move |time: f64, callback: FnPtr| {
+        let mut state = s.borrow_mut();
+        let time = time as f32;
+        // stable, events at the same time run in the order they were scheduled
+        let index = state.events.partition_point(|(at, _)| *at <= time);
+        state.events.insert(index, (time, callback));
+    });
+
+    // forces and fluid properties
+    let s = state.clone();
+    engine.register_fn("set_gravity", move |x: f64, y: f64, z: f64| {
+        let mut state = s.borrow_mut();
+        state.config.gravity = Vector3::new(x as f32, y as f32, z as f32);
+        state.rebuild = true;
+    });
+    let s = state.clone();
+    engine.register_fn("set_viscosity", move |viscosity: f64| {
+        let mut state = s.borrow_mut();
+        state.config.viscosity = viscosity as f32;
+        state.rebuild = true;
+    });
+    let s = state.clone();
+    engine.register_fn("set_wave_paddle", move |amplitude: f64, frequency: f64| {
+        let mut state = s.borrow_mut();
+        state.config.wave_paddle = Some(WavePaddle {
+            amplitude: amplitude as f32,
+            frequency: frequency as f32,
+        });
+        state.rebuild = true;
+    });
+    let s = state.clone();
+    engine.register_fn("stop_wave_paddle", move || {
+        let mut state = s.borrow_mut();
+        state.config.wave_paddle = None;
+        state.rebuild = true;
+    });
+
+    // scene layout
+    let s = state.clone();
+    engine.register_fn("set_particle_count", move |particle_cnt: i64| {
+        let mut state = s.borrow_mut();
+        state.config.particle_cnt = particle_cnt.max(1) as usize;
+        state.rebuild = true;
+    });
+    let s = state.clone();
+    engine.register_fn(
+        "set_layout",
+        move |layout: &str| -> Result<(), Box<EvalAltResult>> {
+            let layout = match layout {
+                "cube" => InitialLayout::Cube,
+                "dam_break" => InitialLayout::DamBreak,
+                _ => return Err(format!("Unknown layout {layout}").into()),
+            };
+            let mut state = s.borrow_mut();
+            state.config.initial_layout = layout;
+            state.rebuild = true;
+            Ok(())
+        },
+    );
+    let s = state.clone();
+    engine.register_fn(
+        "set_boundary",
+        move |boundary: &str| -> Result<(), Box<EvalAltResult>> {
+            let boundary = match boundary {
+                "box" => DomainBoundary::Box,
+                "floor" => DomainBoundary::Floor,
+                "open" => DomainBoundary::Open,
+                _ => return Err(format!("Unknown boundary {boundary}").into()),
+            };
+            let mut state = s.borrow_mut();
+            state.config.boundary = boundary;
+            state.rebuild = true;
+            Ok(())
+        },
+    );
+
+    // obstacles
+    let s = state.clone();
+    engine.register_fn("soft_body", move |x: f64, y: f64, z: f64, size: f64| {
+        let mut state = s.borrow_mut();
+        let size = size as f32;
+        state.config.soft_body = Some(SoftBodyConfig {
+            center: Point3::new(x as f32, y as f32, z as f32),
+            size: Vector3::new(size, size, size),
+            ..Default::default()
+        });
+        state.rebuild = true;
+    });
+    let s = state.clone();
+    engine.register_fn("remove_soft_body", move || {
+        let mut state = s.borrow_mut();
+        state.config.soft_body = None;
+        state.rebuild = true;
+    });
+
+    // emitters, applied without a rebuild
+    let s = state.clone();
+    engine.register_fn("dye_emitter", move |x: f64, y: f64, z: f64, radius: f64| {
+        let mut state = s.borrow_mut();
+        let dye = state.config.dye.get_or_insert_with(DyeConfig::default);
+        dye.emitter = Some(DyeEmitter {
+            center: Point3::new(x as f32, y as f32, z as f32),
+            radius: radius as f32,
+        });
+    });
+    let s = state.clone();
+    engine.register_fn("stop_dye_emitter", move || {
+        if let Some(dye) = &mut s.borrow_mut().config.dye {
+            dye.emitter = None;
+        }
+    });
+    let s = state.clone();
+    engine.register_fn(
+        "debris_emitter",
+        move |x: f64, y: f64, z: f64, spawn_rate: f64| {
+            let mut state = s.borrow_mut();
+            let debris = state
+                .config
+                .debris
+                .get_or_insert_with(DebrisConfig::default);
+            debris.spawn_center = Point3::new(x as f32, y as f32, z as f32);
+            debris.spawn_rate = spawn_rate as f32;
+        },
+    );
+    let s = state.clone();
+    engine.register_fn("stop_debris", move || {
+        s.borrow_mut().config.debris = None;
+    });
+
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str) -> ScriptScene {
+        let mut scene = ScriptScene::new(source).unwrap();
+        let result = scene
+            .engine
+            .run_ast_with_scope(&mut scene.scope, &scene.ast);
+        scene.report(result);
+        scene
+    }
+
+    #[test]
+    fn events_run_in_time_order() {
+        let mut scene = run("
+            at(5.0, || set_gravity(0.0, -2.0, 0.0));
+            at(2.0, || set_boundary(\"floor\"));
+        ");
+        assert_eq!(scene.error(), None);
+        assert_eq!(scene.state.borrow().events.len(), 2);
+
+        scene.state.borrow_mut().time = 3.0;
+        scene.run_due_events();
+        {
+            let state = scene.state.borrow();
+            assert_eq!(state.config.boundary, DomainBoundary::Floor);
+            assert_eq!(
+                state.config.gravity,
+                FluidSimulationConfig::default().gravity
+            );
+            assert_eq!(state.events.len(), 1);
+            assert!(state.rebuild);
+        }
+
+        scene.state.borrow_mut().time = 5.0;
+        scene.run_due_events();
+        assert_eq!(scene.state.borrow().config.gravity.y, -2.0);
+        assert!(scene.state.borrow().events.is_empty());
+    }
+
+    #[test]
+    fn emitters_do_not_rebuild() {
+        let scene = run("dye_emitter(0.0, 1.0, 0.0, 0.5); debris_emitter(1.0, 2.0, 0.0, 50.0);");
+        let state = scene.state.borrow();
+        assert!(!state.rebuild);
+        assert_eq!(state.config.dye.unwrap().emitter.unwrap().radius, 0.5);
+        assert_eq!(state.config.debris.unwrap().spawn_rate, 50.0);
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let scene = run("set_boundary(\"glass\");");
+        assert!(scene.error().unwrap().contains("Unknown boundary glass"));
+
+        assert!(matches!(
+            ScriptScene::new("at(1.0, "),
+            Err(SplooshError::Script(_))
+        ));
+    }
+}
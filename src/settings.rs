@@ -0,0 +1,79 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera_controller::OrbitState,
+    fluid_simulation::FluidSimulationConfig,
+    graphics::{background::RenderSettings, post_process::PostProcessSettings},
+    gui::DockLayout,
+    input_map::InputMap,
+    project::{migrate_simulation, unversioned, WrittenSimulation, PROJECT_VERSION},
+    SplooshError,
+};
+
+pub const SETTINGS_PATH: &str = "settings.ron";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Project version of the simulation config, files without one are from version 1
+    #[serde(default = "unversioned")]
+    pub version: u32,
+    /// Size of the window when it isn't fullscreen
+    pub window_size: Option<(u32, u32)>,
+    pub fullscreen: bool,
+    pub camera: OrbitState,
+    pub gui_layout: DockLayout,
+    pub simulation: FluidSimulationConfig,
+    pub post_process: PostProcessSettings,
+    pub render: RenderSettings,
+    pub input_map: InputMap,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename = "Settings")]
+struct WrittenSettings {
+    simulation: WrittenSimulation,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: PROJECT_VERSION,
+            window_size: None,
+            fullscreen: false,
+            camera: OrbitState::default(),
+            gui_layout: DockLayout::default(),
+            simulation: FluidSimulationConfig::default(),
+            post_process: PostProcessSettings::default(),
+            render: RenderSettings::default(),
+            input_map: InputMap::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SplooshError> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut settings: Settings = ron::from_str(&contents)?;
+        let written: WrittenSettings = ron::from_str(&contents)?;
+        settings.gui_layout.add_missing_panels();
+        settings.input_map.add_missing_actions();
+        migrate_simulation(
+            &mut settings.simulation,
+            settings.version,
+            &written.simulation,
+        );
+        settings.version = PROJECT_VERSION;
+
+        Ok(settings)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SplooshError> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(path, contents)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+    view_inv: mat4x4<f32>,
+    position: vec3<f32>,
+    _padding: f32,
+    viewport_size: vec2<f32>,
+    pixels_per_point: f32,
+    _viewport_padding: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    // w holds the sprite scale written by the culling pass
+    @location(0) particle: vec4<f32>,
+    @location(1) color: vec4<f32>,
+    // symmetric transform from the sphere to the ellipsoid, xx yy zz and xy xz yz
+    @location(2) diagonal: vec4<f32>,
+    @location(3) off_diagonal: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) center: vec3<f32>,
+    // columns of the transform from the ellipsoid to the unit sphere
+    @location(3) @interpolate(flat) inverse_0: vec3<f32>,
+    @location(4) @interpolate(flat) inverse_1: vec3<f32>,
+    @location(5) @interpolate(flat) inverse_2: vec3<f32>,
+};
+
+// sprite radius of the particle shader
+const SIZE: f32 = 0.05;
+
+fn invert(m: mat3x3<f32>) -> mat3x3<f32> {
+    let adjugate = transpose(mat3x3<f32>(cross(m[1], m[2]), cross(m[2], m[0]), cross(m[0], m[1])));
+    return adjugate * (1.0 / determinant(m));
+}
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+    vertex_input: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    let particle_pos = vertex_input.particle.xyz;
+    let radius = SIZE * vertex_input.particle.w;
+
+    let d = vertex_input.diagonal.xyz;
+    let o = vertex_input.off_diagonal.xyz;
+    let shape = mat3x3<f32>(
+        vec3<f32>(d.x, o.x, o.y),
+        vec3<f32>(o.x, d.y, o.z),
+        vec3<f32>(o.y, o.z, d.z),
+    ) * radius;
+    // the Frobenius norm bounds the longest axis, so the quad covers the ellipsoid
+    let bound = sqrt(dot(d, d) + 2.0 * dot(o, o)) * radius;
+
+    var quad_vertices: array<vec3<f32>, 4> = array(
+        vec3f(-1.0, -1.0, 0.0),
+        vec3f( 1.0, -1.0, 0.0),
+        vec3f(-1.0,  1.0, 0.0),
+        vec3f( 1.0,  1.0, 0.0),
+    );
+
+    let camera_forward = normalize(camera.position - particle_pos);
+    let up = vec3(0.0, 1.0, 0.0);
+    let right = normalize(cross(up, camera_forward));
+    let billboard_up = cross(camera_forward, right);
+
+    let world_position = particle_pos +
+        quad_vertices[in_vertex_index].x * right * bound +
+        quad_vertices[in_vertex_index].y * billboard_up * bound;
+
+    let shape_inverse = invert(shape);
+    out.clip_position = camera.view_projection * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.color = vertex_input.color;
+    out.center = particle_pos;
+    out.inverse_0 = shape_inverse[0];
+    out.inverse_1 = shape_inverse[1];
+    out.inverse_2 = shape_inverse[2];
+    return out;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+// the last row of an orthographic view projection is (0, 0, 0, 1), its rays are parallel
+fn view_direction(world_position: vec3<f32>) -> vec3<f32> {
+    let w_row = vec3<f32>(
+        camera.view_projection[0][3],
+        camera.view_projection[1][3],
+        camera.view_projection[2][3],
+    );
+    if (all(w_row == vec3<f32>(0.0))) {
+        return -normalize(camera.view_inv[2].xyz);
+    }
+
+    return normalize(world_position - camera.position);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let shape_inverse = mat3x3<f32>(in.inverse_0, in.inverse_1, in.inverse_2);
+
+    // the view ray through the quad, moved into the frame where the ellipsoid is the unit
+    // sphere, the quad passes through the center so the front hit lies before the origin
+    let direction = view_direction(in.world_position);
+    let origin = shape_inverse * (in.world_position - in.center);
+    let local_direction = shape_inverse * direction;
+
+    let a = dot(local_direction, local_direction);
+    let b = dot(origin, local_direction);
+    let c = dot(origin, origin) - 1.0;
+    let discriminant = b * b - a * c;
+    if (discriminant < 0.0) {
+        discard;
+    }
+
+    let t = (-b - sqrt(discriminant)) / a;
+    let hit = in.world_position + t * direction;
+    let local_hit = origin + t * local_direction;
+
+    // gradient of |shape_inverse * (x - center)|^2, lit from above like the spheres
+    let normal = normalize(transpose(shape_inverse) * local_hit);
+    let brightness = max(normal.y, 0.0) + 0.05;
+
+    let clip = camera.view_projection * vec4<f32>(hit, 1.0);
+
+    var ret: FragmentOutput;
+    ret.color = vec4<f32>(in.color.xyz * brightness, 1.0);
+    ret.depth = clip.z / clip.w;
+
+    return ret;
+}
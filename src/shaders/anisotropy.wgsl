@@ -0,0 +1,146 @@
+@group(0) @binding(0) var<storage, read> particle_positions: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(2) var<storage, read> spatial_lookup_vals: array<u32>;
+@group(0) @binding(3) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+@group(0) @binding(4) var<storage, read_write> shapes: array<ParticleShape>;
+
+struct AnisotropyConstants {
+    max_ratio: f32,
+    min_neighbors: u32,
+}
+
+var<push_constant> constants: AnisotropyConstants;
+
+const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
+const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
+const dz = array(-1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+
+// the rotations between the axis pairs converge quadratically, a few sweeps suffice in 3D
+const JACOBI_SWEEPS: i32 = 4;
+const PAIR_P = array(0, 0, 1);
+const PAIR_Q = array(1, 2, 2);
+
+struct Eigen {
+    values: vec3<f32>,
+    // the eigenvectors are the columns
+    vectors: mat3x3<f32>,
+}
+
+// cyclic Jacobi eigenvalue algorithm for a symmetric matrix
+fn symmetric_eigen(m: mat3x3<f32>) -> Eigen {
+    var a = m;
+    var v = mat3x3<f32>(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, 1.0));
+
+    for (var sweep = 0; sweep < JACOBI_SWEEPS; sweep += 1) {
+        for (var pair = 0; pair < 3; pair += 1) {
+            let p = PAIR_P[pair];
+            let q = PAIR_Q[pair];
+            let apq = a[q][p];
+            if (abs(apq) < 1e-12) {
+                continue;
+            }
+
+            // rotation zeroing a[p][q], written column by column
+            let theta = (a[q][q] - a[p][p]) / (2.0 * apq);
+            let t = select(-1.0, 1.0, theta >= 0.0) / (abs(theta) + sqrt(theta * theta + 1.0));
+            let c = 1.0 / sqrt(t * t + 1.0);
+            let s = t * c;
+
+            var rotation = mat3x3<f32>(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, 1.0));
+            rotation[p][p] = c;
+            rotation[q][q] = c;
+            rotation[q][p] = s;
+            rotation[p][q] = -s;
+
+            a = transpose(rotation) * a * rotation;
+            v = v * rotation;
+        }
+    }
+
+    return Eigen(vec3<f32>(a[0][0], a[1][1], a[2][2]), v);
+}
+
+// weight of Yu and Turk, falls to zero at the smoothing radius
+fn weight(r: f32) -> f32 {
+    let q = r / SMOOTHING_RADIUS;
+    return 1.0 - q * q * q;
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let gid = global_id.x + GHOST_PARTICLE_CNT;
+
+    if (gid >= arrayLength(&particle_positions)) {
+        return;
+    }
+
+    let particle_pos = particle_positions[gid];
+    let particle_cell = cell_of(particle_pos);
+
+    // weighted moments of the neighbor offsets, the particle itself included
+    var weight_sum = 1.0;
+    var mean = vec3<f32>(0.0);
+    var second_moment = mat3x3<f32>(vec3<f32>(0.0), vec3<f32>(0.0), vec3<f32>(0.0));
+    var count = 0u;
+
+    for (var i = 0; i < 27; i += 1) {
+        let neighbor_cell = particle_cell + vec3<i32>(dx[i], dy[i], dz[i]);
+
+        if (!is_valid_cell(neighbor_cell)) {
+            continue;
+        }
+
+        let neighbor_cell_key = cell_key(neighbor_cell);
+        for (var l = cell_start(neighbor_cell_key); l < arrayLength(&particle_positions) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+            let ind = spatial_lookup_vals[l];
+
+            // the walls would flatten the particles next to them
+            if (ind == gid || ind < GHOST_PARTICLE_CNT) {
+                continue;
+            }
+
+            if (HASHED && any(cell_of(particle_positions[ind]) != neighbor_cell)) {
+                continue;
+            }
+
+            let offset = particle_positions[ind] - particle_pos;
+            let r = length(offset);
+            if (r < SMOOTHING_RADIUS) {
+                let w = weight(r);
+                weight_sum += w;
+                mean += w * offset;
+                second_moment += w * mat3x3<f32>(offset * offset.x, offset * offset.y, offset * offset.z);
+                count += 1u;
+            }
+        }
+    }
+
+    if (count < constants.min_neighbors) {
+        shapes[gid] = SPHERE_SHAPE;
+        return;
+    }
+
+    mean /= weight_sum;
+    let covariance = second_moment * (1.0 / weight_sum)
+        - mat3x3<f32>(mean * mean.x, mean * mean.y, mean * mean.z);
+    let eigen = symmetric_eigen(covariance);
+
+    let largest = max(max(eigen.values.x, eigen.values.y), eigen.values.z);
+    if (largest <= 0.0) {
+        shapes[gid] = SPHERE_SHAPE;
+        return;
+    }
+
+    // the short axes are clamped to the largest ratio, then scaled to the volume of a sphere
+    let clamped = max(eigen.values, vec3<f32>(largest / constants.max_ratio));
+    let stretch = clamped / pow(clamped.x * clamped.y * clamped.z, 1.0 / 3.0);
+
+    let r = eigen.vectors;
+    let scaled = mat3x3<f32>(r[0] * stretch.x, r[1] * stretch.y, r[2] * stretch.z);
+    let transform = scaled * transpose(r);
+
+    shapes[gid] = ParticleShape(
+        vec4<f32>(transform[0][0], transform[1][1], transform[2][2], 0.0),
+        vec4<f32>(transform[1][0], transform[2][0], transform[2][1], 0.0),
+    );
+}
@@ -0,0 +1,33 @@
+struct Background {
+    inverse_view_proj: mat4x4<f32>,
+    top: vec4<f32>,
+    bottom: vec4<f32>,
+    // 0 for the gradient, 1 for the environment map
+    mode: u32,
+}
+
+@group(0) @binding(0) var<uniform> background: Background;
+@group(0) @binding(1) var environment: texture_2d<f32>;
+@group(0) @binding(2) var environment_sampler: sampler;
+
+const PI: f32 = 3.14159265;
+
+@fragment
+fn fs_background(in: VertexOutput) -> @location(0) vec4<f32> {
+    let ndc = vec2<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+    let near = background.inverse_view_proj * vec4<f32>(ndc, -1.0, 1.0);
+    let far = background.inverse_view_proj * vec4<f32>(ndc, 1.0, 1.0);
+    let direction = normalize(far.xyz / far.w - near.xyz / near.w);
+
+    if background.mode == 0u {
+        let t = direction.y * 0.5 + 0.5;
+        return vec4<f32>(mix(background.bottom.rgb, background.top.rgb, t), 1.0);
+    }
+
+    // equirectangular projection
+    let uv = vec2<f32>(
+        atan2(direction.z, direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / PI,
+    );
+    return vec4<f32>(textureSampleLevel(environment, environment_sampler, uv, 0.0).rgb, 1.0);
+}
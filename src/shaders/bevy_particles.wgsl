@@ -0,0 +1,57 @@
+#import bevy_pbr::mesh_view_bindings::view
+
+struct VertexInput {
+    // w holds the sprite scale written by the culling pass
+    @location(0) particle: vec4<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normalized_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+const SIZE: f32 = 0.05;
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+    vertex_input: VertexInput
+) -> VertexOutput {
+    var out: VertexOutput;
+    let particle_pos = vertex_input.particle.xyz;
+    let size = SIZE * vertex_input.particle.w;
+
+    var quad_vertices: array<vec2<f32>, 4> = array(
+        vec2f(-1.0, -1.0),
+        vec2f( 1.0, -1.0),
+        vec2f(-1.0,  1.0),
+        vec2f( 1.0,  1.0),
+    );
+    let corner = quad_vertices[in_vertex_index];
+
+    // billboards face the camera plane
+    let right = view.world_from_view[0].xyz;
+    let up = view.world_from_view[1].xyz;
+    let world_position = particle_pos + (corner.x * right + corner.y * up) * size;
+
+    out.clip_position = view.clip_from_world * vec4<f32>(world_position, 1.0);
+    out.normalized_coords = corner;
+    out.color = vertex_input.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dist_sq = dot(in.normalized_coords, in.normalized_coords);
+    if (dist_sq > 1.0) {
+        discard;
+    }
+
+    let normal = vec3f(in.normalized_coords, sqrt(max(0.0, 1.0 - dist_sq)));
+    let light_direction = normalize((vec4f(0.0, 1.0, 0.0, 0.0) * view.world_from_view).xyz);
+    let brightness = max(dot(normal, light_direction), 0.0) + 0.05;
+
+    return vec4<f32>(in.color.xyz * brightness, 1.0);
+}
@@ -0,0 +1,9 @@
+// linear interpolation between the texels of the colormap, t is clamped to [0, 1]
+fn colormap(t: f32) -> vec3<f32> {
+    let size = textureDimensions(colormap_texture);
+    let x = clamp(t, 0.0, 1.0) * f32(size - 1u);
+    let i = min(u32(x), size - 2u);
+    let low = textureLoad(colormap_texture, i, 0).rgb;
+    let high = textureLoad(colormap_texture, i + 1u, 0).rgb;
+    return mix(low, high, x - f32(i));
+}
@@ -0,0 +1,10 @@
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let gid = global_id.x + GHOST_PARTICLE_CNT;
+
+    if (gid >= arrayLength(&particle_positions)) {
+        return;
+    }
+
+    density[gid] = particle_density(particle_positions[gid]);
+}
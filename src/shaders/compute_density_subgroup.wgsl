@@ -0,0 +1,45 @@
+// density with the rows of neighbor cells shared by the subgroup, see `subgroup_rows.wgsl`
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(subgroup_invocation_id) lane: u32,
+    @builtin(subgroup_size) lane_cnt: u32,
+) {
+    let particle_cnt = arrayLength(&particle_positions);
+    let gid = sorted_particle(global_id.x);
+    let active = global_id.x < particle_cnt && gid >= GHOST_PARTICLE_CNT;
+
+    let particle_pos = particle_positions[gid];
+    let particle_cell = cell_of(particle_pos);
+    let first_cell = subgroupBroadcastFirst(particle_cell);
+    let min_z = subgroupMin(particle_cell.z);
+    let max_z = subgroupMax(particle_cell.z);
+
+    var d: f32 = 0.0;
+    if (shares_rows(particle_cell, first_cell, min_z, max_z)) {
+        for (var i = 0; i < 9; i += 1) {
+            let range = row_range(first_cell.xy + vec2<i32>(i / 3 - 1, i % 3 - 1), min_z, max_z);
+
+            for (var base = range.x; base < particle_cnt; base += lane_cnt) {
+                let l = base + lane;
+                let in_row = l < particle_cnt && spatial_lookup_keys[l] <= range.y;
+                let neighbor_pos = particle_positions[sorted_particle(l)];
+
+                // the particles of the row are a prefix of the loaded ones
+                let loaded = subgroupAdd(select(0u, 1u, in_row));
+                for (var j = 0u; j < loaded; j += 1u) {
+                    d += density_term(particle_pos, subgroupShuffle(neighbor_pos, j));
+                }
+                if (loaded < lane_cnt) {
+                    break;
+                }
+            }
+        }
+    } else {
+        d = particle_density(particle_pos);
+    }
+
+    if (active) {
+        density[gid] = d;
+    }
+}
@@ -2,85 +2,196 @@
 @group(0) @binding(1) var<storage, read> particle_velocities: array<vec3<f32>>; 
 @group(0) @binding(2) var<storage, read> spatial_lookup_keys: array<u32>;
 @group(0) @binding(3) var<storage, read> spatial_lookup_vals: array<u32>;
-@group(0) @binding(4) var<storage, read> spatial_lookup_index: array<u32>;
+@group(0) @binding(4) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
 @group(0) @binding(5) var<storage, read> particle_density: array<f32>;
-@group(0) @binding(6) var<storage, read_write> particle_force: array<vec3<f32>>;
+@group(0) @binding(6) var<storage, read> particle_pressure_terms: array<f32>;
+@group(0) @binding(7) var<storage, read_write> particle_force: array<vec3<f32>>;
 
-const PI = 3.14159;
-const SPIKY_GRAD = 15.0 / (PI * pow(SMOOTHING_RADIUS, 6.0));
-const VISC_LAP = 45.0 / (PI * pow(SMOOTHING_RADIUS, 6.0));
+const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
+const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
+const dz = array(1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+
+// magnitude of the strain rate tensor from the velocity gradient over the neighbors
+fn shear_rate(gid: u32, particle_pos: vec3<f32>, particle_velocity: vec3<f32>) -> f32 {
+    let particle_cell = cell_of(particle_pos);
+    var gradient = mat3x3<f32>(vec3<f32>(0.0), vec3<f32>(0.0), vec3<f32>(0.0));
+
+    for (var i = 0; i < 27; i += 1) {
+        let neighbor_cell = particle_cell + vec3<i32>(dx[i], dy[i], dz[i]);
+
+        if (!is_valid_cell(neighbor_cell)) {
+            continue;
+        }
+
+        let neighbor_cell_key = cell_key(neighbor_cell);
+        for (var l = cell_start(neighbor_cell_key); l < arrayLength(&particle_positions) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+            let ind = spatial_lookup_vals[l];
+            let neighbor_pos = particle_positions[ind];
+
+            if (ind == gid || (HASHED && any(cell_of(neighbor_pos) != neighbor_cell))) {
+                continue;
+            }
 
-fn cell_key(cell: vec3<u32>) -> u32 {
-    return cell.z + cell.y * CELL_CNT.z + cell.x * CELL_CNT.y * CELL_CNT.z;
+            let dir = particle_pos - neighbor_pos;
+            let dist = length(dir);
+            if (dist >= SMOOTHING_RADIUS || dist == 0.0) {
+                continue;
+            }
+
+            let diff = SMOOTHING_RADIUS - dist;
+            let grad = -SPIKY_DERIVATIVE * diff * diff * dir / dist;
+            let dv = (particle_velocities[ind] - particle_velocity) * MASS / particle_density[ind];
+            gradient += mat3x3<f32>(dv * grad.x, dv * grad.y, dv * grad.z);
+        }
+    }
+
+    let strain = 0.5 * (gradient + transpose(gradient));
+    let contraction = dot(strain[0], strain[0]) + dot(strain[1], strain[1]) + dot(strain[2], strain[2]);
+    return sqrt(2.0 * contraction);
 }
 
-fn calculate_pressure(density: f32) -> f32 {
-    return GAS_CONST * (density - REST_DENSITY);
+fn cohesion(r: f32) -> f32 {
+    let h = SMOOTHING_RADIUS;
+    let spline = (h - r) * (h - r) * (h - r) * r * r * r;
+    if (2.0 * r > h) {
+        return COHESION_KERNEL * spline;
+    }
+    return COHESION_KERNEL * (2.0 * spline - pow(h, 6.0) / 64.0);
 }
 
-const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
-const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
-const dz = array(1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+fn viscosity_at(rate: f32) -> f32 {
+    switch VISCOSITY_MODEL {
+        // power law, thins below a flow index of one and thickens above
+        case 1u: {
+            return min(VISCOSITY * pow(max(rate, 1e-6), FLOW_INDEX - 1.0), MAX_VISCOSITY);
+        }
+        // Cross, falls from the zero shear to the infinite shear viscosity
+        case 2u: {
+            return INFINITE_SHEAR_VISCOSITY + (VISCOSITY - INFINITE_SHEAR_VISCOSITY) / (1.0 + pow(TIME_CONSTANT * rate, FLOW_INDEX));
+        }
+        default: {
+            return VISCOSITY;
+        }
+    }
+}
+
+fn particle_viscosity(gid: u32, particle_pos: vec3<f32>, particle_velocity: vec3<f32>) -> f32 {
+    if (VISCOSITY_MODEL != 0u && !GRANULAR) {
+        return viscosity_at(shear_rate(gid, particle_pos, particle_velocity));
+    }
+    return VISCOSITY;
+}
 
-@compute @workgroup_size(256)
-fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    let gid = global_id.x + GHOST_PARTICLE_CNT;
+// force of the neighbor `ind` on the particle `gid`, zero outside the smoothing radius
+fn pair_force(
+    gid: u32,
+    particle_pos: vec3<f32>,
+    particle_velocity: vec3<f32>,
+    particle_den: f32,
+    particle_pressure_term: f32,
+    viscosity: f32,
+    ind: u32,
+    neighbor_pos: vec3<f32>,
+    neighbor_velocity: vec3<f32>,
+    neighbor_density: f32,
+    neighbor_pressure_term: f32,
+) -> vec3<f32> {
+    var force: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
+    if (ind == gid) {
+        return force;
+    }
 
-    if (gid >= arrayLength(&particle_positions)) {
-        return;
+    var dir: vec3<f32> = particle_pos - neighbor_pos;
+    let dist = length(dir);
+
+    if (dist < SMOOTHING_RADIUS) {
+        // hit
+        if (dist == 0) {
+            dir = vec3<f32>(1.0, 0.0, 0.0);
+        }
+
+        let diff = (SMOOTHING_RADIUS - dist);
+        let norm_dir = normalize(dir);
+        // symmetric in the pair, so the pressure forces conserve momentum
+        let pressure_force = norm_dir * MASS * particle_den * (particle_pressure_term + neighbor_pressure_term) * SPIKY_GRAD * diff * diff * diff;
+        let viscous_force = viscosity * MASS * (neighbor_velocity - particle_velocity) * VISC_LAP * diff / neighbor_density;
+        force += pressure_force;
+
+        if (GRANULAR) {
+            // Drucker-Prager yield between two grains, the shear force can't exceed
+            // the friction from the normal force plus the cohesion
+            let normal_force = dot(viscous_force, norm_dir) * norm_dir;
+            let shear_force = viscous_force - normal_force;
+            let cohesion = MASS * COHESION * SPIKY_GRAD * diff * diff * diff / neighbor_density;
+            let yield_force = FRICTION * length(pressure_force) + cohesion;
+            let shear = length(shear_force);
+            force += normal_force;
+            if (shear > yield_force) {
+                force += shear_force * (yield_force / shear);
+            } else {
+                force += shear_force;
+            }
+        } else {
+            force += viscous_force;
+        }
+
+        // attracts fluid neighbors at medium range and repels them up close, which
+        // minimizes the surface
+        if (SURFACE_TENSION > 0.0 && ind >= GHOST_PARTICLE_CNT) {
+            force -= norm_dir * SURFACE_TENSION * particle_den * MASS * cohesion(dist);
+        }
+
+        // pulls neighbors together and pushes them apart towards the rest length, the
+        // walls don't take part
+        if (SPRING_STIFFNESS > 0.0 && ind >= GHOST_PARTICLE_CNT) {
+            force += norm_dir * SPRING_STIFFNESS * MASS * (SPRING_REST_LENGTH - dist) * diff / neighbor_density;
+        }
     }
 
+    return force;
+}
+
+// force on one particle, walking its neighbor cells on its own
+fn neighbor_force(gid: u32, viscosity: f32) -> vec3<f32> {
     let particle_velocity = particle_velocities[gid];
     let particle_pos = particle_positions[gid];
     let particle_den = particle_density[gid];
-    let particle_pressure = calculate_pressure(particle_den);
-    let particle_cell = vec3<i32>(particle_pos / SMOOTHING_RADIUS);
+    let particle_pressure_term = pressure_term_of(gid);
+    let particle_cell = cell_of(particle_pos);
     var force: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
 
     for (var i = 0; i < 27; i += 1) {
-        let neighbor_cell = vec3<i32>(
-            particle_cell.x + dx[i], 
-            particle_cell.y + dy[i], 
-            particle_cell.z + dz[i]
-        );
+        let neighbor_cell = particle_cell + vec3<i32>(dx[i], dy[i], dz[i]);
 
-        let is_valid_cell = all(neighbor_cell >= vec3<i32>(0)) && 
-                            all(vec3<u32>(neighbor_cell) < CELL_CNT);
-
-        if (!is_valid_cell) {
+        if (!is_valid_cell(neighbor_cell)) {
             continue;
         }
 
-        let neighbor_cell_key = cell_key(vec3<u32>(neighbor_cell));
-        for (var l = spatial_lookup_index[neighbor_cell_key]; spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+        let neighbor_cell_key = cell_key(neighbor_cell);
+        for (var l = cell_start(neighbor_cell_key); l < arrayLength(&particle_positions) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
             let ind = spatial_lookup_vals[l];
-            if (ind == gid) {
-                continue;
-            }
-
             let neighbor_pos = particle_positions[ind];
 
-            var dir: vec3<f32> = particle_pos - neighbor_pos;
-            let dist = length(dir);
-
-            if (dist < SMOOTHING_RADIUS) {
-                // hit
-                if (dist == 0) {
-                    dir = vec3<f32>(1.0, 0.0, 0.0);
-                }
-
-                let neighbor_density = particle_density[ind];
-                let neighbor_pressure = calculate_pressure(neighbor_density);
-                let neighbor_velocity = particle_velocities[ind];
-
-                let diff = (SMOOTHING_RADIUS - dist);
-                let norm_dir = normalize(dir);
-                force += norm_dir * MASS * (particle_pressure + neighbor_pressure)  * SPIKY_GRAD * diff * diff * diff / (2.0 * neighbor_density);
-                force += VISCOSITY * MASS * (neighbor_velocity - particle_velocity) * VISC_LAP * diff / neighbor_density;
+            // several cells can share a hash key
+            if (ind == gid || (HASHED && any(cell_of(neighbor_pos) != neighbor_cell))) {
+                continue;
             }
-        }
 
+            force += pair_force(
+                gid,
+                particle_pos,
+                particle_velocity,
+                particle_den,
+                particle_pressure_term,
+                viscosity,
+                ind,
+                neighbor_pos,
+                particle_velocities[ind],
+                particle_density[ind],
+                pressure_term_of(ind),
+            );
+        }
     }
 
-    particle_force[gid] = force;
-}
\ No newline at end of file
+    return force;
+}
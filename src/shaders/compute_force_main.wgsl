@@ -0,0 +1,11 @@
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let gid = global_id.x + GHOST_PARTICLE_CNT;
+
+    if (gid >= arrayLength(&particle_positions)) {
+        return;
+    }
+
+    let viscosity = particle_viscosity(gid, particle_positions[gid], particle_velocities[gid]);
+    particle_force[gid] = neighbor_force(gid, viscosity);
+}
@@ -0,0 +1,14 @@
+@group(0) @binding(0) var<storage, read> particle_density: array<f32>;
+@group(0) @binding(1) var<storage, read_write> particle_pressure_terms: array<f32>;
+
+// the ghost particles get one too, they are neighbors in the force pass
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let gid = global_id.x;
+
+    if (gid >= arrayLength(&particle_density)) {
+        return;
+    }
+
+    particle_pressure_terms[gid] = pressure_term(particle_density[gid]);
+}
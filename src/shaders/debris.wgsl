@@ -0,0 +1,156 @@
+@group(0) @binding(0) var<storage, read> position: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read> velocity: array<vec3<f32>>;
+@group(0) @binding(2) var<storage, read> density: array<f32>;
+@group(0) @binding(3) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(4) var<storage, read> spatial_lookup_vals: array<u32>;
+@group(0) @binding(5) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+// w is one for pieces that are alive
+@group(0) @binding(6) var<storage, read_write> debris_position: array<vec4<f32>>;
+@group(0) @binding(7) var<storage, read_write> debris_velocity: array<vec4<f32>>;
+
+struct MeshInstance {
+    transform: mat4x4<f32>,
+    color: vec4<f32>,
+}
+
+@group(0) @binding(8) var<storage, read_write> display: array<MeshInstance>;
+
+struct Debris {
+    spawn_center: vec3<f32>,
+    spawn_radius: f32,
+    count: u32,
+    drag: f32,
+    buoyancy: f32,
+    size: f32,
+}
+
+@group(0) @binding(9) var<uniform> params: Debris;
+
+struct DebrisStep {
+    first_spawn: u32,
+    spawn_cnt: u32,
+    seed: u32,
+    dt: f32,
+}
+
+var<push_constant> step: DebrisStep;
+
+const HSQ = SMOOTHING_RADIUS * SMOOTHING_RADIUS;
+const COLOR = vec4<f32>(0.45, 0.3, 0.1, 1.0);
+// the radius of a fluid particle sprite of size one
+const PARTICLE_RADIUS = 0.05;
+
+const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
+const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
+const dz = array(-1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+
+fn random(seed: u32) -> f32 {
+    let h = seed * 747796405u + 2891336453u;
+    return f32(h >> 8u) / 16777216.0;
+}
+
+// uniform point in the unit ball, a disk in 2D
+fn random_in_ball(seed: u32) -> vec3<f32> {
+    for (var i = 0u; i < 16u; i += 1u) {
+        var p = vec3<f32>(random(seed + 3u * i), random(seed + 3u * i + 1u), random(seed + 3u * i + 2u)) * 2.0 - 1.0;
+        if (FLAT) {
+            p.z = 0.0;
+        }
+        if (dot(p, p) <= 1.0) {
+            return p;
+        }
+    }
+    return vec3<f32>(0.0);
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let piece = global_id.x;
+
+    if (piece >= params.count) {
+        return;
+    }
+
+    var pos = debris_position[piece].xyz;
+    var v = debris_velocity[piece].xyz;
+    var alive = debris_position[piece].w > 0.0;
+
+    // the ring of pieces respawned this step can wrap around the end
+    if ((piece + params.count - step.first_spawn) % params.count < step.spawn_cnt) {
+        pos = params.spawn_center + random_in_ball(step.seed ^ (piece * 2246822519u)) * params.spawn_radius;
+        v = vec3<f32>(0.0);
+        alive = true;
+    }
+
+    if (!alive) {
+        display[piece].transform = mat4x4<f32>();
+        return;
+    }
+
+    // fluid velocity normalized by the kernel sum, and the fluid density for the buoyancy
+    let cell = cell_of(pos);
+    var fluid_velocity = vec3<f32>(0.0);
+    var weight_sum = 0.0;
+    var fluid_density = 0.0;
+
+    for (var i = 0; i < 27; i += 1) {
+        let neighbor_cell = cell + vec3<i32>(dx[i], dy[i], dz[i]);
+
+        if (!is_valid_cell(neighbor_cell)) {
+            continue;
+        }
+
+        let neighbor_cell_key = cell_key(neighbor_cell);
+        for (var l = cell_start(neighbor_cell_key); l < arrayLength(&position) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+            let ind = spatial_lookup_vals[l];
+
+            if (ind < GHOST_PARTICLE_CNT || (HASHED && any(cell_of(position[ind]) != neighbor_cell))) {
+                continue;
+            }
+
+            let diff = pos - position[ind];
+            let dist_sq = dot(diff, diff);
+            if (dist_sq >= HSQ) {
+                continue;
+            }
+
+            let w = HSQ - dist_sq;
+            let weight = w * w * w / density[ind];
+            fluid_velocity += weight * velocity[ind];
+            weight_sum += weight;
+            fluid_density += MASS * POLY6 * w * w * w;
+        }
+    }
+
+    // partly submerged pieces float at the surface where the buoyancy balances gravity
+    let submerged = clamp(fluid_density / REST_DENSITY, 0.0, 1.0);
+    var acceleration = G * (1.0 - params.buoyancy * submerged);
+    if (weight_sum > 0.0) {
+        acceleration += (fluid_velocity / weight_sum - v) * min(params.drag * submerged, 1.0 / step.dt);
+    }
+
+    v += acceleration * step.dt;
+    pos += v * step.dt;
+
+    if (WALLS) {
+        pos = clamp(pos, vec3<f32>(SMOOTHING_RADIUS), BBOX - SMOOTHING_RADIUS);
+    }
+    if (FLOOR && pos.y < SMOOTHING_RADIUS) {
+        pos.y = SMOOTHING_RADIUS;
+        v.y = max(v.y, 0.0);
+    }
+    if (!WALLS && distance(pos, BBOX / 2.0) > KILL_RADIUS) {
+        alive = false;
+    }
+
+    debris_position[piece] = vec4<f32>(pos, select(0.0, 1.0, alive));
+    debris_velocity[piece] = vec4<f32>(v, 0.0);
+    let radius = select(0.0, PARTICLE_RADIUS * params.size, alive);
+    let transform = mat4x4<f32>(
+        vec4<f32>(radius, 0.0, 0.0, 0.0),
+        vec4<f32>(0.0, radius, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, radius, 0.0),
+        vec4<f32>(pos + OFFSET, 1.0),
+    );
+    display[piece] = MeshInstance(transform, COLOR);
+}
@@ -0,0 +1,100 @@
+@group(0) @binding(0) var<storage, read> position: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read> density: array<f32>;
+@group(0) @binding(2) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(3) var<storage, read> spatial_lookup_vals: array<u32>;
+@group(0) @binding(4) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+@group(0) @binding(5) var<storage, read_write> filtered_density: array<f32>;
+
+var<push_constant> mode: u32;
+
+const HSQ = SMOOTHING_RADIUS * SMOOTHING_RADIUS;
+
+const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
+const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
+const dz = array(-1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let particle = GHOST_PARTICLE_CNT + global_id.x;
+
+    if (particle >= arrayLength(&position)) {
+        return;
+    }
+
+    let pos = position[particle];
+    let cell = cell_of(pos);
+    // the kernel sum over the neighbor volumes, one for a complete neighborhood
+    var volume_sum = 0.0;
+    // first and second moments of the volume weighted kernel for the linear fit
+    var first_moment = vec3<f32>(0.0);
+    var second_moment = mat3x3<f32>(vec3<f32>(0.0), vec3<f32>(0.0), vec3<f32>(0.0));
+    // the SPH density sum and its first moment
+    var mass_sum = 0.0;
+    var mass_moment = vec3<f32>(0.0);
+
+    for (var i = 0; i < 27; i += 1) {
+        let neighbor_cell = cell + vec3<i32>(dx[i], dy[i], dz[i]);
+
+        if (!is_valid_cell(neighbor_cell)) {
+            continue;
+        }
+
+        let neighbor_cell_key = cell_key(neighbor_cell);
+        for (var l = cell_start(neighbor_cell_key); l < arrayLength(&position) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+            let ind = spatial_lookup_vals[l];
+
+            if (HASHED && any(cell_of(position[ind]) != neighbor_cell)) {
+                continue;
+            }
+
+            let diff = pos - position[ind];
+            let dist_sq = dot(diff, diff);
+            if (dist_sq >= HSQ) {
+                continue;
+            }
+
+            let w = HSQ - dist_sq;
+            let kernel = POLY6 * w * w * w;
+            let volume = MASS / density[ind];
+            volume_sum += volume * kernel;
+            first_moment += volume * kernel * diff;
+            second_moment += volume * kernel * mat3x3<f32>(diff * diff.x, diff * diff.y, diff * diff.z);
+            mass_sum += MASS * kernel;
+            mass_moment += MASS * kernel * diff;
+        }
+    }
+
+    var filtered = density[particle];
+    // only incomplete neighborhoods are corrected, so the interior keeps its compression
+    if (mode == FREE_SURFACE && volume_sum > 0.0) {
+        filtered /= min(volume_sum, 1.0);
+    }
+
+    if (mode == SHEPARD && volume_sum > 0.0) {
+        filtered = mass_sum / volume_sum;
+    }
+
+    if (mode == MOVING_LEAST_SQUARES && volume_sum > 0.0) {
+        filtered = mass_sum / volume_sum;
+
+        // the kernel correction solves the moment matrix [[a, b^T], [b, C]] for its first
+        // column, the flat axis of a 2D simulation has no spread and is left out
+        var c = second_moment;
+        if (FLAT) {
+            c[2][2] = volume_sum * HSQ;
+        }
+        let det = determinant(c);
+        if (abs(det) > 1e-6 * pow(volume_sum * HSQ, 3.0)) {
+            let c_inv = transpose(mat3x3<f32>(cross(c[1], c[2]), cross(c[2], c[0]), cross(c[0], c[1]))) * (1.0 / det);
+            let c_inv_b = c_inv * first_moment;
+            let schur = volume_sum - dot(first_moment, c_inv_b);
+            if (schur > 1e-6 * volume_sum) {
+                let beta0 = 1.0 / schur;
+                let beta = -c_inv_b * beta0;
+                filtered = beta0 * mass_sum + dot(beta, mass_moment);
+            }
+        }
+    }
+
+    filtered_density[particle] = filtered;
+}
@@ -0,0 +1,76 @@
+@group(0) @binding(0) var<storage, read> particle_positions: array<vec3<f32>>; 
+@group(0) @binding(1) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(2) var<storage, read> spatial_lookup_vals: array<u32>;
+@group(0) @binding(3) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+
+struct Slice {
+    axis: u32,
+    position: f32,
+    min_density: f32,
+    max_density: f32,
+}
+
+@group(0) @binding(4) var<uniform> slice: Slice;
+@group(0) @binding(5) var slice_texture: texture_storage_2d<rgba16float, write>;
+
+const HSQ = SMOOTHING_RADIUS * SMOOTHING_RADIUS;
+
+const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
+const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
+const dz = array(-1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+
+// must match the quad built by DensitySlice
+fn slice_point(uv: vec2<f32>) -> vec3<f32> {
+    switch (slice.axis) {
+        case 0u: {
+            return vec3<f32>(slice.position, uv.y * BBOX.y, uv.x * BBOX.z);
+        }
+        case 1u: {
+            return vec3<f32>(uv.x * BBOX.x, slice.position, uv.y * BBOX.z);
+        }
+        default: {
+            return vec3<f32>(uv.x * BBOX.x, uv.y * BBOX.y, slice.position);
+        }
+    }
+}
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let size = textureDimensions(slice_texture);
+
+    if (any(global_id.xy >= size)) {
+        return;
+    }
+
+    let pos = slice_point((vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(size));
+    let cell = cell_of(pos);
+    var d: f32 = 0.0;
+
+    for (var i = 0; i < 27; i += 1) {
+        let neighbor_cell = cell + vec3<i32>(dx[i], dy[i], dz[i]);
+
+        if (!is_valid_cell(neighbor_cell)) {
+            continue;
+        }
+
+        let neighbor_cell_key = cell_key(neighbor_cell);
+        for (var l = cell_start(neighbor_cell_key); l < arrayLength(&particle_positions) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+            let ind = spatial_lookup_vals[l];
+
+            if (HASHED && any(cell_of(particle_positions[ind]) != neighbor_cell)) {
+                continue;
+            }
+
+            let diff = pos - particle_positions[ind];
+            let dist_sq = dot(diff, diff);
+            if (dist_sq >= HSQ) {
+                continue;
+            }
+
+            let w = HSQ - dist_sq;
+            d += MASS * POLY6 * w * w * w;
+        }
+    }
+
+    textureStore(slice_texture, global_id.xy, vec4<f32>(d, 0.0, 0.0, 1.0));
+}
@@ -0,0 +1,55 @@
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+    view_inv: mat4x4<f32>,
+    position: vec3<f32>,
+    _padding: f32,
+    viewport_size: vec2<f32>,
+    pixels_per_point: f32,
+    _viewport_padding: f32,
+}
+
+@group(0) @binding(0) 
+var<uniform> camera: CameraUniform;
+
+struct Slice {
+    axis: u32,
+    position: f32,
+    min_density: f32,
+    max_density: f32,
+}
+
+@group(1) @binding(0) var slice_texture: texture_2d<f32>;
+@group(1) @binding(1) var slice_sampler: sampler;
+@group(1) @binding(2) var<uniform> slice: Slice;
+@group(1) @binding(3) var colormap_texture: texture_1d<f32>;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+    input: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_projection * vec4<f32>(input.position, 1.0);
+    out.uv = input.uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let density = textureSample(slice_texture, slice_sampler, in.uv).r;
+    let t = clamp((density - slice.min_density) / (slice.max_density - slice.min_density), 0.0, 1.0);
+    let color = colormap(t);
+
+    // keeps the outline of the slice visible where there is no fluid
+    let alpha = select(0.2, 0.9, density > 0.0);
+    return vec4<f32>(color, alpha);
+}
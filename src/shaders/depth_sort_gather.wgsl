@@ -0,0 +1,22 @@
+struct DrawArgs {
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0) var<storage, read> display: array<ColoredParticle>;
+@group(0) @binding(1) var<storage, read> draw_args: DrawArgs;
+@group(0) @binding(2) var<storage, read> depth_vals: array<u32>;
+@group(0) @binding(3) var<storage, read_write> sorted_display: array<ColoredParticle>;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let gid = global_id.x;
+
+    if (gid >= draw_args.instance_count) {
+        return;
+    }
+
+    sorted_display[gid] = display[depth_vals[gid]];
+}
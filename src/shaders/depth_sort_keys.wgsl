@@ -0,0 +1,37 @@
+struct DrawArgs {
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0) var<storage, read> display: array<ColoredParticle>;
+@group(0) @binding(1) var<storage, read> draw_args: DrawArgs;
+@group(0) @binding(2) var<uniform> cull: Cull;
+@group(0) @binding(3) var<storage, read_write> depth_keys: array<u32>;
+@group(0) @binding(4) var<storage, read_write> depth_vals: array<u32>;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let gid = global_id.x;
+
+    if (gid >= PARTICLE_CNT) {
+        return;
+    }
+
+    depth_vals[gid] = gid;
+
+    // slots past the drawn particles sort to the end
+    if (gid >= draw_args.instance_count) {
+        depth_keys[gid] = 0xffffffffu;
+        return;
+    }
+
+    let depth = dot(display[gid].position - cull.camera_position, cull.camera_forward);
+
+    // map the float to an ascending unsigned key and invert it, so the farthest particle
+    // comes first
+    let bits = bitcast<u32>(depth);
+    let ascending = select(bits | 0x80000000u, ~bits, (bits >> 31u) == 1u);
+    depth_keys[gid] = ~ascending;
+}
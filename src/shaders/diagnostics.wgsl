@@ -0,0 +1,66 @@
+@group(0) @binding(0) var<storage, read> position: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read> velocity: array<vec3<f32>>;
+@group(0) @binding(2) var<storage, read> density: array<f32>;
+@group(0) @binding(3) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(4) var<storage, read> spatial_lookup_vals: array<u32>;
+@group(0) @binding(5) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+
+struct ParticleDiagnostics {
+    divergence: f32,
+    pressure: f32,
+}
+
+// fluid particles only, the first entry belongs to the first fluid particle
+@group(0) @binding(6) var<storage, read_write> diagnostics: array<ParticleDiagnostics>;
+
+const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
+const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
+const dz = array(-1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let particle = GHOST_PARTICLE_CNT + global_id.x;
+
+    if (particle >= arrayLength(&position)) {
+        return;
+    }
+
+    let pos = position[particle];
+    let vel = velocity[particle];
+    let cell = cell_of(pos);
+    var divergence = 0.0;
+
+    for (var i = 0; i < 27; i += 1) {
+        let neighbor_cell = cell + vec3<i32>(dx[i], dy[i], dz[i]);
+
+        if (!is_valid_cell(neighbor_cell)) {
+            continue;
+        }
+
+        let neighbor_cell_key = cell_key(neighbor_cell);
+        for (var l = cell_start(neighbor_cell_key); l < arrayLength(&position) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+            let ind = spatial_lookup_vals[l];
+
+            if (ind == particle || (HASHED && any(cell_of(position[ind]) != neighbor_cell))) {
+                continue;
+            }
+
+            let dir = pos - position[ind];
+            let dist = length(dir);
+            if (dist >= SMOOTHING_RADIUS || dist == 0.0) {
+                continue;
+            }
+
+            // gradient of the spiky kernel with respect to this particle
+            let diff = SMOOTHING_RADIUS - dist;
+            let grad = -SPIKY_DERIVATIVE * diff * diff * dir / dist;
+            divergence += MASS * dot(velocity[ind] - vel, grad);
+        }
+    }
+
+    let den = density[particle];
+    diagnostics[global_id.x] = ParticleDiagnostics(
+        divergence / den,
+        GAS_CONST * (den - REST_DENSITY),
+    );
+}
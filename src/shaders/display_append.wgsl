@@ -0,0 +1,7 @@
+// slot of a kept particle in the display buffer, one atomic per kept particle
+fn append_slot(keep: bool) -> u32 {
+    if (!keep) {
+        return 0u;
+    }
+    return atomicAdd(&draw_args.instance_count, 1u);
+}
@@ -0,0 +1,13 @@
+// slot of a kept particle in the display buffer. The kept particles of a subgroup take
+// consecutive slots reserved with a single atomic by its first invocation.
+fn append_slot(keep: bool) -> u32 {
+    let kept = select(0u, 1u, keep);
+    let offset = subgroupExclusiveAdd(kept);
+    let total = subgroupAdd(kept);
+
+    var base = 0u;
+    if (subgroupExclusiveAdd(1u) == 0u && total > 0u) {
+        base = atomicAdd(&draw_args.instance_count, total);
+    }
+    return subgroupBroadcastFirst(base) + offset;
+}
@@ -0,0 +1,20 @@
+struct ColoredParticle {
+    position: vec3<f32>,
+    size: f32,
+    color: vec4<f32>
+}
+
+struct Cull {
+    frustum_planes: array<vec4<f32>, 6>,
+    camera_position: vec3<f32>,
+    lod_distance: f32,
+    camera_forward: vec3<f32>,
+    max_distance: f32,
+    frustum: u32,
+    // longest axis of the particle shapes, one for spheres
+    max_stretch: f32,
+    // copies the shapes of the kept particles next to them
+    anisotropic: u32,
+    // the particles of a cell are merged beyond it, zero to never merge
+    merge_distance: f32,
+}
@@ -0,0 +1,62 @@
+@group(0) @binding(0) var<storage, read> position: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read> density: array<f32>;
+@group(0) @binding(2) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(3) var<storage, read> spatial_lookup_vals: array<u32>;
+@group(0) @binding(4) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+@group(0) @binding(5) var<storage, read> dye: array<f32>;
+@group(0) @binding(6) var<storage, read_write> next_dye: array<f32>;
+
+// fraction of the way to the neighborhood average covered this step
+var<push_constant> amount: f32;
+
+const HSQ = SMOOTHING_RADIUS * SMOOTHING_RADIUS;
+
+const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
+const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
+const dz = array(-1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let particle = GHOST_PARTICLE_CNT + global_id.x;
+
+    if (particle >= arrayLength(&position)) {
+        return;
+    }
+
+    let pos = position[particle];
+    let cell = cell_of(pos);
+    var dye_sum = 0.0;
+    var weight_sum = 0.0;
+
+    for (var i = 0; i < 27; i += 1) {
+        let neighbor_cell = cell + vec3<i32>(dx[i], dy[i], dz[i]);
+
+        if (!is_valid_cell(neighbor_cell)) {
+            continue;
+        }
+
+        let neighbor_cell_key = cell_key(neighbor_cell);
+        for (var l = cell_start(neighbor_cell_key); l < arrayLength(&position) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+            let ind = spatial_lookup_vals[l];
+
+            // the walls neither hold nor absorb dye
+            if (ind < GHOST_PARTICLE_CNT || (HASHED && any(cell_of(position[ind]) != neighbor_cell))) {
+                continue;
+            }
+
+            let diff = pos - position[ind];
+            let dist_sq = dot(diff, diff);
+            if (dist_sq >= HSQ) {
+                continue;
+            }
+
+            let w = HSQ - dist_sq;
+            let weight = w * w * w / density[ind];
+            dye_sum += weight * dye[ind];
+            weight_sum += weight;
+        }
+    }
+
+    let average = select(dye[particle], dye_sum / weight_sum, weight_sum > 0.0);
+    next_dye[particle] = mix(dye[particle], average, amount);
+}
@@ -0,0 +1,28 @@
+@group(0) @binding(0) var<storage, read> position: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read_write> dye: array<f32>;
+
+struct Region {
+    start: vec3<f32>,
+    radius: f32,
+    end: vec3<f32>,
+}
+
+@group(0) @binding(2) var<uniform> region: Region;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let particle = GHOST_PARTICLE_CNT + global_id.x;
+
+    if (particle >= arrayLength(&position)) {
+        return;
+    }
+
+    // distance to the segment from start to end, a sphere if they are the same point
+    let p = position[particle];
+    let axis = region.end - region.start;
+    let t = clamp(dot(p - region.start, axis) / max(dot(axis, axis), 1e-12), 0.0, 1.0);
+
+    if (distance(p, region.start + t * axis) < region.radius) {
+        dye[particle] = 1.0;
+    }
+}
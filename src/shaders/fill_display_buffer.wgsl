@@ -1,29 +1,221 @@
-struct ColoredParticle {
-    position: vec3<f32>,
-    color: vec4<f32>
-}
-
 @group(0) @binding(0) var<storage, read> position: array<vec3<f32>>; 
 @group(0) @binding(1) var<storage, read> density: array<f32>;
 @group(0) @binding(2) var<storage, read_write> display: array<ColoredParticle>;
 
-@compute @workgroup_size(256)
-fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    let gid = global_id.x;
+struct DrawArgs {
+    vertex_count: u32,
+    instance_count: atomic<u32>,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+@group(0) @binding(3) var<storage, read_write> draw_args: DrawArgs;
+
+@group(0) @binding(4) var<uniform> cull: Cull;
+
+@group(0) @binding(5) var<storage, read> neighbor_count: array<u32>;
+
+@group(0) @binding(6) var<storage, read> previous_position: array<vec3<f32>>;
+
+@group(0) @binding(7) var<storage, read> dye: array<f32>;
+
+@group(0) @binding(8) var colormap_texture: texture_1d<f32>;
+
+struct ColorRange {
+    min: f32,
+    max: f32,
+}
+
+@group(0) @binding(9) var<uniform> color_range: ColorRange;
+
+@group(0) @binding(10) var<storage, read> shapes: array<ParticleShape>;
+@group(0) @binding(11) var<storage, read_write> display_shapes: array<ParticleShape>;
 
-    if (gid >= arrayLength(&position)) {
-        return;
+@group(0) @binding(12) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(13) var<storage, read> spatial_lookup_vals: array<u32>;
+@group(0) @binding(14) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+
+struct DisplayConstants {
+    // 0 colors the particles by density, 1 by neighbor count, 2 by dye concentration
+    color_mode: u32,
+    // highlighted particle, 0xffffffff without a selection
+    selected_particle: u32,
+    // blends from the positions before the last step to the current ones
+    interpolation: f32,
+    // the ghost particles before it are hidden
+    first_particle: u32,
+}
+
+var<push_constant> constants: DisplayConstants;
+
+// sprite radius of the particle shader
+const SPRITE_SIZE: f32 = 0.05;
+
+fn random(gid: u32) -> f32 {
+    let h = gid * 747796405u + 2891336453u;
+    return f32(h >> 8u) / 16777216.0;
+}
+
+fn range_fraction(value: f32) -> f32 {
+    return clamp((value - color_range.min) / (color_range.max - color_range.min), 0.0, 1.0);
+}
+
+// the quantity of the color mode
+fn color_value(gid: u32) -> f32 {
+    if (constants.color_mode == 1u) {
+        return f32(neighbor_count[gid]);
+    } else if (constants.color_mode == 2u) {
+        return dye[gid];
     }
+    return density[gid];
+}
 
-    let cmin = vec4<f32>(0.0, 0.0, 1.0, 1.0);
-    let cmax = vec4<f32>(1.0, 0.0, 0.0, 1.0);
+fn value_color(value: f32) -> vec4<f32> {
+    if (constants.color_mode == 2u) {
+        let water = vec4<f32>(0.1, 0.3, 0.8, 1.0);
+        let ink = vec4<f32>(1.0, 0.2, 0.6, 1.0);
+        return mix(water, ink, range_fraction(value));
+    }
+    return vec4<f32>(colormap(range_fraction(value)), 1.0);
+}
 
-    var particle: ColoredParticle;
+struct MergedCell {
+    // mean of the interpolated world positions
+    center: vec3<f32>,
+    count: u32,
+    // mean of the color values
+    value: f32,
+}
+
+// set by `display_particle` for sprites standing in for a whole cell
+var<private> merged: bool;
 
-    let alpha = clamp((density[gid] - 150.0) / 100.0, 0.0, 1.0);
+fn world_position_of(ind: u32) -> vec3<f32> {
+    return mix(previous_position[ind], position[ind], constants.interpolation) + OFFSET;
+}
+
+// whether the fluid particle `ind` is drawn as part of the sprite of its cell. The selected
+// particle and the ones closer than the merge distance are drawn on their own, the ones beyond
+// the max distance not at all.
+fn is_merged(ind: u32) -> bool {
+    if (ind < constants.first_particle || ind == constants.selected_particle) {
+        return false;
+    }
+    let camera_distance = distance(world_position_of(ind), cull.camera_position);
+    return camera_distance > cull.merge_distance
+        && (cull.max_distance <= 0.0 || camera_distance <= cull.max_distance);
+}
+
+// sums up the merged particles of the spatial lookup cell of `gid` if it is the first of them,
+// false otherwise. The cells are taken from the current positions, particles listed in
+// another cell of a cached grid are left out until the next rebuild.
+fn merge_cell(gid: u32, cell: ptr<function, MergedCell>) -> bool {
+    let particle_cell = cell_of(position[gid]);
+    let key = cell_key(particle_cell);
+    var count = 0u;
+    var center = vec3<f32>(0.0);
+    var value = 0.0;
+
+    for (var l = cell_start(key); l < arrayLength(&position) && spatial_lookup_keys[l] == key; l += 1u) {
+        let ind = spatial_lookup_vals[l];
+        if (any(cell_of(position[ind]) != particle_cell) || !is_merged(ind)) {
+            continue;
+        }
+        if (count == 0u && ind != gid) {
+            return false;
+        }
+
+        count += 1u;
+        center += world_position_of(ind);
+        value += color_value(ind);
+    }
+
+    if (count == 0u) {
+        return false;
+    }
+
+    (*cell).center = center / f32(count);
+    (*cell).count = count;
+    (*cell).value = value / f32(count);
+    return true;
+}
+
+// writes the displayed particle gid to `particle`, false if it is culled
+fn display_particle(gid: u32, particle: ptr<function, ColoredParticle>) -> bool {
+    if (gid >= arrayLength(&position) || gid < constants.first_particle) {
+        return false;
+    }
+
+    var world_position = world_position_of(gid);
+    let camera_distance = distance(world_position, cull.camera_position);
 
-    particle.position = position[gid] + OFFSET;
-    particle.color = mix(cmin, cmax, alpha);
+    if (cull.max_distance > 0.0 && camera_distance > cull.max_distance) {
+        return false;
+    }
+
+    let selected = gid == constants.selected_particle;
+    var size = 1.0;
+    var value = 0.0;
+    if (cull.merge_distance > 0.0 && is_merged(gid)) {
+        // one sprite with the volume of the merged particles of the cell
+        var cell: MergedCell;
+        if (!merge_cell(gid, &cell)) {
+            return false;
+        }
+        world_position = cell.center;
+        size = pow(f32(cell.count), 1.0 / 3.0);
+        value = cell.value;
+        merged = true;
+    } else {
+        // distant particles are thinned out so that the kept sprites stay at the size they
+        // have at the lod distance, which preserves the covered screen area
+        if (cull.lod_distance > 0.0 && camera_distance > cull.lod_distance) {
+            let keep = cull.lod_distance / camera_distance;
+            if (random(gid) > keep * keep && !selected) {
+                return false;
+            }
+            size = camera_distance / cull.lod_distance;
+        }
+        value = color_value(gid);
+    }
+
+    if (cull.frustum != 0u) {
+        for (var i = 0; i < 6; i++) {
+            let plane = cull.frustum_planes[i];
+            if (dot(plane.xyz, world_position) + plane.w < -SPRITE_SIZE * size * cull.max_stretch) {
+                return false;
+            }
+        }
+    }
+
+    (*particle).position = world_position;
+    (*particle).size = size;
+
+    if (selected) {
+        (*particle).color = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+        (*particle).size = 2.0 * size;
+    } else {
+        (*particle).color = value_color(value);
+    }
 
-    display[gid] = particle;
-}
\ No newline at end of file
+    return true;
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    var particle: ColoredParticle;
+    // every invocation takes part in `append_slot`, culled or not
+    let keep = display_particle(global_id.x, &particle);
+    let slot = append_slot(keep);
+    if (keep) {
+        display[slot] = particle;
+        if (cull.anisotropic != 0u) {
+            // the highlighted particle and the merged cells stay spheres
+            var shape = shapes[global_id.x];
+            if (global_id.x == constants.selected_particle || merged) {
+                shape = SPHERE_SHAPE;
+            }
+            display_shapes[slot] = shape;
+        }
+    }
+}
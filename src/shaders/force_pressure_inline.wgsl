@@ -0,0 +1,4 @@
+// recomputes the pressure term for every pair instead of reading the pressure pass
+fn pressure_term_of(ind: u32) -> f32 {
+    return pressure_term(particle_density[ind]);
+}
@@ -0,0 +1,3 @@
+fn pressure_term_of(ind: u32) -> f32 {
+    return particle_pressure_terms[ind];
+}
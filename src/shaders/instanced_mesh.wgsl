@@ -0,0 +1,57 @@
+struct CameraUniform {
+    view_projection: mat4x4<f32>,
+    view_inv: mat4x4<f32>,
+    position: vec3<f32>,
+    _padding: f32,
+    viewport_size: vec2<f32>,
+    pixels_per_point: f32,
+    _viewport_padding: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+};
+
+// a MeshInstance, the columns of the transform one after another
+struct InstanceInput {
+    @location(2) transform_0: vec4<f32>,
+    @location(3) transform_1: vec4<f32>,
+    @location(4) transform_2: vec4<f32>,
+    @location(5) transform_3: vec4<f32>,
+    @location(6) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let transform = mat4x4<f32>(
+        instance.transform_0,
+        instance.transform_1,
+        instance.transform_2,
+        instance.transform_3,
+    );
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_projection * transform * vec4<f32>(vertex.position, 1.0);
+    // not exact for non uniform scales, good enough for the shading
+    out.normal = (transform * vec4<f32>(vertex.normal, 0.0)).xyz;
+    out.color = instance.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // lit from above the camera like the particles
+    let light_direction = (camera.view_inv * vec4<f32>(0.0, 1.0, 0.0, 0.0)).xyz;
+    let brightness = max(dot(normalize(in.normal), normalize(light_direction)), 0.0) + 0.05;
+    return vec4<f32>(in.color.rgb * brightness, 1.0);
+}
@@ -2,30 +2,106 @@ struct CameraUniform {
     view_projection: mat4x4<f32>,
     view_inv: mat4x4<f32>,
     position: vec3<f32>,
-    _padding: f32
+    _padding: f32,
+    viewport_size: vec2<f32>,
+    pixels_per_point: f32,
+    _viewport_padding: f32,
 }
 
-@group(0) @binding(0) 
+// in points, scaled by the pixels per point of the camera
+struct LineStyle {
+    width: f32,
+    dash_length: f32,
+    gap_length: f32,
+    _padding: f32,
+}
+
+// set by the dashed line pipeline
+override DASHED: bool = false;
+
+@group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
-struct VertexInput {
-    @location(0) position: vec3<f32>,
+@group(1) @binding(0)
+var<uniform> style: LineStyle;
+
+// a segment is an instance made of the two ColoredVertex ends of the line list
+struct SegmentInput {
+    @location(0) start: vec3<f32>,
+    @location(1) start_color: vec4<f32>,
+    @location(2) end: vec3<f32>,
+    @location(3) end_color: vec4<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    // pixels from the start of the segment
+    @location(1) @interpolate(linear) distance: f32,
 };
 
+const NEAR_W: f32 = 1e-4;
+
+// moves `a` along the segment until it's in front of the camera
+fn clip_to_near(a: vec4<f32>, b: vec4<f32>) -> vec4<f32> {
+    if a.w >= NEAR_W {
+        return a;
+    }
+    let t = (NEAR_W - a.w) / (b.w - a.w);
+    return mix(a, b, t);
+}
+
 @vertex
 fn vs_main(
-    input: VertexInput,
+    @builtin(vertex_index) vertex_index: u32,
+    input: SegmentInput,
 ) -> VertexOutput {
+    var start = camera.view_projection * vec4<f32>(input.start, 1.0);
+    var end = camera.view_projection * vec4<f32>(input.end, 1.0);
+    let clipped_start = clip_to_near(start, end);
+    end = clip_to_near(end, start);
+    start = clipped_start;
+
+    let half_size = 0.5 * camera.viewport_size;
+    let start_px = start.xy / start.w * half_size;
+    let end_px = end.xy / end.w * half_size;
+    let length_px = length(end_px - start_px);
+    var direction = vec2<f32>(1.0, 0.0);
+    if length_px > 1e-6 {
+        direction = (end_px - start_px) / length_px;
+    }
+    let normal = vec2<f32>(-direction.y, direction.x);
+
+    // two triangles spanning the segment, x along it and y across it
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    let half_width = 0.5 * style.width * camera.pixels_per_point;
+    let end_point = select(start, end, corner.x > 0.5);
+    let offset = normal * corner.y * half_width / half_size * end_point.w;
+
     var out: VertexOutput;
-    out.clip_position = camera.view_projection * vec4<f32>(input.position, 1.0);
+    out.clip_position = vec4<f32>(end_point.xy + offset, end_point.zw);
+    out.color = select(input.start_color, input.end_color, corner.x > 0.5);
+    out.distance = corner.x * length_px;
     return out;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(0.1, 0.1, 0.1, 1.0);
-}
\ No newline at end of file
+    if DASHED {
+        let scale = camera.pixels_per_point;
+        let period = (style.dash_length + style.gap_length) * scale;
+        if in.distance % period > style.dash_length * scale {
+            discard;
+        }
+    }
+    return in.color;
+}
@@ -0,0 +1,23 @@
+@group(0) @binding(0) var<storage, read> grid: array<u32>;
+@group(0) @binding(1) var colormap_texture: texture_1d<f32>;
+@group(0) @binding(2) var minimap_texture: texture_storage_2d<rgba8unorm, write>;
+
+const EMPTY: vec3<f32> = vec3<f32>(0.02, 0.02, 0.03);
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if (global_id.x >= WIDTH || global_id.y >= HEIGHT) {
+        return;
+    }
+
+    let count = grid[global_id.y * WIDTH + global_id.x];
+    let largest = max(grid[WIDTH * HEIGHT], 1u);
+
+    // the square root keeps thin sheets of fluid visible next to deep pools
+    var color = EMPTY;
+    if (count > 0u) {
+        color = colormap(sqrt(f32(count) / f32(largest)));
+    }
+
+    textureStore(minimap_texture, global_id.xy, vec4<f32>(color, 1.0));
+}
@@ -0,0 +1,36 @@
+@group(0) @binding(0) var<storage, read> position: array<vec3<f32>>;
+// texel counts in fixed point, the last element holds the largest one
+@group(0) @binding(1) var<storage, read_write> grid: array<atomic<u32>>;
+
+const WEIGHT_SCALE: f32 = 256.0;
+
+fn splat(texel: vec2<i32>, weight: f32) {
+    if (any(texel < vec2<i32>(0)) || texel.x >= i32(WIDTH) || texel.y >= i32(HEIGHT)) {
+        return;
+    }
+
+    let amount = u32(weight * WEIGHT_SCALE);
+    let total = atomicAdd(&grid[u32(texel.y) * WIDTH + u32(texel.x)], amount) + amount;
+    atomicMax(&grid[WIDTH * HEIGHT], total);
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    // the ghost particles of the walls would outline the box
+    let gid = global_id.x + GHOST_PARTICLES;
+
+    if (gid >= arrayLength(&position)) {
+        return;
+    }
+
+    let p = position[gid];
+    let texel = p.xz / BBOX.xz * vec2<f32>(f32(WIDTH), f32(HEIGHT)) - 0.5;
+    let base = vec2<i32>(floor(texel));
+    let f = fract(texel);
+
+    // bilinear weights keep the map smooth while the particles move between texels
+    splat(base, (1.0 - f.x) * (1.0 - f.y));
+    splat(base + vec2<i32>(1, 0), f.x * (1.0 - f.y));
+    splat(base + vec2<i32>(0, 1), (1.0 - f.x) * f.y);
+    splat(base + vec2<i32>(1, 1), f.x * f.y);
+}
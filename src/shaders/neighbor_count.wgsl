@@ -0,0 +1,47 @@
+@group(0) @binding(0) var<storage, read> particle_positions: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(2) var<storage, read> spatial_lookup_vals: array<u32>;
+@group(0) @binding(3) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+@group(0) @binding(4) var<storage, read_write> neighbor_count: array<u32>;
+@group(0) @binding(5) var<storage, read_write> histogram: array<atomic<u32>, HISTOGRAM_BINS>;
+
+const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
+const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
+const dz = array(-1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let gid = global_id.x + GHOST_PARTICLE_CNT;
+
+    if (gid >= arrayLength(&particle_positions)) {
+        return;
+    }
+
+    let particle_pos = particle_positions[gid];
+    let particle_cell = cell_of(particle_pos);
+    var count = 0u;
+
+    for (var i = 0; i < 27; i += 1) {
+        let neighbor_cell = particle_cell + vec3<i32>(dx[i], dy[i], dz[i]);
+
+        if (!is_valid_cell(neighbor_cell)) {
+            continue;
+        }
+
+        let neighbor_cell_key = cell_key(neighbor_cell);
+        for (var l = cell_start(neighbor_cell_key); l < arrayLength(&particle_positions) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+            let ind = spatial_lookup_vals[l];
+
+            if (HASHED && any(cell_of(particle_positions[ind]) != neighbor_cell)) {
+                continue;
+            }
+
+            if (ind != gid && distance(particle_pos, particle_positions[ind]) < SMOOTHING_RADIUS) {
+                count += 1u;
+            }
+        }
+    }
+
+    neighbor_count[gid] = count;
+    atomicAdd(&histogram[min(count, HISTOGRAM_BINS - 1u)], 1u);
+}
@@ -0,0 +1,41 @@
+struct Ray {
+    origin: vec3<f32>,
+    radius: f32,
+    direction: vec3<f32>,
+    _padding: f32,
+}
+
+@group(0) @binding(0) var<storage, read> position: array<vec3<f32>>;
+@group(0) @binding(1) var<uniform> ray: Ray;
+// distance bits of the closest hit, index of the closest particle
+@group(0) @binding(2) var<storage, read_write> pick: array<atomic<u32>, 2>;
+
+// 0 finds the closest hit, 1 the particle it belongs to
+var<push_constant> phase: u32;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let gid = global_id.x + GHOST_PARTICLE_CNT;
+
+    if (gid >= arrayLength(&position)) {
+        return;
+    }
+
+    let to_center = position[gid] + OFFSET - ray.origin;
+    let t = dot(to_center, ray.direction);
+    let distance_sq = dot(to_center, to_center) - t * t;
+    let radius_sq = ray.radius * ray.radius;
+
+    if (t < 0.0 || distance_sq > radius_sq) {
+        return;
+    }
+
+    // non negative floats order the same as their bits
+    let hit = bitcast<u32>(max(t - sqrt(radius_sq - distance_sq), 0.0));
+
+    if (phase == 0u) {
+        atomicMin(&pick[0], hit);
+    } else if (atomicLoad(&pick[0]) == hit) {
+        atomicMin(&pick[1], gid);
+    }
+}
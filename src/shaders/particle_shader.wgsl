@@ -2,14 +2,18 @@ struct CameraUniform {
     view_projection: mat4x4<f32>,
     view_inv: mat4x4<f32>,
     position: vec3<f32>,
-    _padding: f32
+    _padding: f32,
+    viewport_size: vec2<f32>,
+    pixels_per_point: f32,
+    _viewport_padding: f32,
 }
 
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
 struct VertexInput {
-    @location(0) particle_pos: vec3<f32>,
+    // w holds the sprite scale written by the culling pass
+    @location(0) particle: vec4<f32>,
     @location(1) color: vec4<f32>,
 };
 
@@ -28,6 +32,8 @@ fn vs_main(
     vertex_input: VertexInput
 ) -> VertexOutput {
     var out: VertexOutput;
+    let particle_pos = vertex_input.particle.xyz;
+    let size = SIZE * vertex_input.particle.w;
 
     var quad_vertices: array<vec3<f32>, 4> = array(
         vec3f(-1.0, -1.0, 0.0),
@@ -36,14 +42,14 @@ fn vs_main(
         vec3f( 1.0,  1.0, 0.0),
     );
 
-    let camera_forward = normalize(camera.position - vertex_input.particle_pos);
+    let camera_forward = normalize(camera.position - particle_pos);
     let up = vec3(0.0, 1.0, 0.0);
     let right = normalize(cross(up, camera_forward));
     let billboard_up = cross(camera_forward, right);
 
-    let world_position = vertex_input.particle_pos +
-        quad_vertices[in_vertex_index].x * right * SIZE +
-        quad_vertices[in_vertex_index].y * billboard_up * SIZE;
+    let world_position = particle_pos +
+        quad_vertices[in_vertex_index].x * right * size +
+        quad_vertices[in_vertex_index].y * billboard_up * size;
 
     out.clip_position = camera.view_projection * vec4<f32>(world_position, 1.0);
     out.normalized_coords = quad_vertices[in_vertex_index].xy;
@@ -55,6 +61,14 @@ struct FragmentOutput {
     @location(0) color: vec4<f32>,
 }
 
+fn shade(in: VertexOutput, dist_sq: f32) -> vec3<f32> {
+    let normal = normalize(vec3f(in.normalized_coords, sqrt(max(0.0, 1.0 - dist_sq))));
+    var light_direction: vec4f = transpose(camera.view_inv) * vec4f(0.0, 1.0, 0.0, 0.0);
+    let brightness = max(dot(normal, light_direction.xyz), 0.0) + 0.05;
+
+    return in.color.xyz * brightness;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> FragmentOutput {
     let dist_sq = dot(in.normalized_coords, in.normalized_coords);
@@ -62,12 +76,24 @@ fn fs_main(in: VertexOutput) -> FragmentOutput {
         discard;
     }
 
-    let normal = normalize(vec3f(in.normalized_coords, sqrt(max(0.0, 1.0 - dist_sq))));
-    var light_direction: vec4f = transpose(camera.view_inv) * vec4f(0.0, 1.0, 0.0, 0.0);
-    let brightness = max(dot(normal, light_direction.xyz), 0.0) + 0.05;
+    var ret: FragmentOutput;
+    ret.color = vec4<f32>(shade(in, dist_sq), 1.0);
+
+    return ret;
+}
+
+const OPACITY: f32 = 0.4;
+
+@fragment
+fn fs_translucent(in: VertexOutput) -> FragmentOutput {
+    let dist_sq = dot(in.normalized_coords, in.normalized_coords);
+    if (dist_sq > 1.0) {
+        discard;
+    }
 
+    // fade out towards the edge of the sprite
     var ret: FragmentOutput;
-    ret.color = vec4<f32>(in.color.xyz * brightness, 1.0);
+    ret.color = vec4<f32>(shade(in, dist_sq), in.color.a * OPACITY * (1.0 - dist_sq));
 
     return ret;
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+// symmetric transform from the sphere of a particle to its ellipsoid
+struct ParticleShape {
+    // xx, yy and zz
+    diagonal: vec4<f32>,
+    // xy, xz and yz
+    off_diagonal: vec4<f32>,
+}
+
+const SPHERE_SHAPE: ParticleShape = ParticleShape(vec4<f32>(1.0, 1.0, 1.0, 0.0), vec4<f32>(0.0));
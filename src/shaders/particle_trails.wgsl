@@ -0,0 +1,79 @@
+@group(0) @binding(0) var<storage, read> position: array<vec3<f32>>;
+
+struct TrailState {
+    // index of the newest recorded position
+    head: u32,
+    len: u32,
+}
+
+@group(0) @binding(1) var<storage, read_write> history: array<vec3<f32>>;
+@group(0) @binding(2) var<storage, read_write> trail_state: array<TrailState>;
+
+struct LineVertex {
+    position: vec3<f32>,
+    color: vec4<f32>,
+}
+
+@group(0) @binding(3) var<storage, read_write> lines: array<LineVertex>;
+
+struct ParticleTrails {
+    trail_cnt: u32,
+    length: u32,
+    stride: u32,
+    reset: u32,
+}
+
+@group(0) @binding(4) var<uniform> params: ParticleTrails;
+
+fn hue(h: f32) -> vec3<f32> {
+    let k = vec3<f32>(0.0, 2.0 / 3.0, 1.0 / 3.0);
+    return clamp(abs(fract(h + k) * 6.0 - 3.0) - 1.0, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let trail = global_id.x;
+
+    if (trail >= params.trail_cnt) {
+        return;
+    }
+
+    let particle = GHOST_PARTICLE_CNT + trail * params.stride;
+    let base = trail * MAX_TRAIL_LENGTH;
+    let p = position[particle];
+
+    var state = trail_state[trail];
+    if (params.reset != 0u) {
+        state = TrailState(0u, 0u);
+    }
+
+    // while the simulation is paused the trail keeps its shape
+    if (state.len == 0u || any(history[base + state.head] != p)) {
+        state.head = (state.head + 1u) % params.length;
+        state.len = min(state.len + 1u, params.length);
+        history[base + state.head] = p;
+    }
+    trail_state[trail] = state;
+
+    // neighboring trails get very different hues, so it is visible where they mix
+    let color = hue(fract(f32(trail) * 0.618034));
+    let segment_cnt = params.length - 1u;
+
+    for (var age = 0u; age < segment_cnt; age++) {
+        let vertex = 2u * (trail * segment_cnt + age);
+
+        // segments the trail doesn't reach yet collapse onto the particle
+        if (age + 1u >= state.len) {
+            lines[vertex] = LineVertex(p + OFFSET, vec4<f32>(color, 0.0));
+            lines[vertex + 1u] = LineVertex(p + OFFSET, vec4<f32>(color, 0.0));
+            continue;
+        }
+
+        let newer = history[base + (state.head + params.length - age) % params.length];
+        let older = history[base + (state.head + params.length - age - 1u) % params.length];
+        let alpha_newer = 1.0 - f32(age) / f32(segment_cnt);
+        let alpha_older = 1.0 - f32(age + 1u) / f32(segment_cnt);
+        lines[vertex] = LineVertex(older + OFFSET, vec4<f32>(color, alpha_older));
+        lines[vertex + 1u] = LineVertex(newer + OFFSET, vec4<f32>(color, alpha_newer));
+    }
+}
@@ -0,0 +1,60 @@
+@group(0) @binding(0) var<storage, read> positions: array<vec4<f32>>;
+@group(0) @binding(1) var<storage, read> velocities: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read> densities: array<f32>;
+// the header of the frame takes the first four words, the particles follow in pairs of seven
+// words with 16 bits per value
+@group(0) @binding(3) var<storage, read_write> packed: array<u32>;
+
+var<push_constant> max_speed: f32;
+
+const HEADER_WORDS: u32 = 4u;
+
+// the 16 bit values of fluid particle i in the low bits: position, density and velocity. Zero
+// for the missing second particle of an odd count.
+fn particle_values(i: u32) -> array<u32, 7> {
+    var values: array<u32, 7>;
+    if (i >= FLUID_PARTICLE_CNT) {
+        return values;
+    }
+
+    let particle = GHOST_PARTICLE_CNT + i;
+    // the pack functions clamp what is out of range
+    let position = (positions[particle].xyz - ORIGIN) / EXTENT;
+    let velocity = velocities[particle].xyz / max_speed;
+    let density = densities[particle] / MAX_DENSITY;
+    let position_xy = pack2x16unorm(position.xy);
+    let position_z_density = pack2x16unorm(vec2<f32>(position.z, density));
+    let velocity_xy = pack2x16snorm(velocity.xy);
+    let velocity_z = pack2x16snorm(vec2<f32>(velocity.z, 0.0));
+
+    values[0] = position_xy & 0xffffu;
+    values[1] = position_xy >> 16u;
+    values[2] = position_z_density & 0xffffu;
+    values[3] = position_z_density >> 16u;
+    values[4] = velocity_xy & 0xffffu;
+    values[5] = velocity_xy >> 16u;
+    values[6] = velocity_z & 0xffffu;
+    return values;
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let pair = id.x;
+    if (2u * pair >= FLUID_PARTICLE_CNT) {
+        return;
+    }
+
+    let first = particle_values(2u * pair);
+    let second = particle_values(2u * pair + 1u);
+    var values: array<u32, 14>;
+    for (var k = 0u; k < 7u; k++) {
+        values[k] = first[k];
+        values[k + 7u] = second[k];
+    }
+
+    // the earlier value of each word in its low half, like the u16 fields of `PackedParticle`
+    let start = HEADER_WORDS + 7u * pair;
+    for (var w = 0u; w < 7u; w++) {
+        packed[start + w] = values[2u * w] | (values[2u * w + 1u] << 16u);
+    }
+}
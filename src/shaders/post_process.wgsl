@@ -0,0 +1,86 @@
+struct Settings {
+    exposure: f32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    vignette: f32,
+    tonemapping: u32,
+    ssao_intensity: f32,
+}
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var bloom: texture_2d<f32>;
+@group(0) @binding(2) var occlusion: texture_2d<f32>;
+@group(0) @binding(3) var linear_sampler: sampler;
+@group(0) @binding(4) var<uniform> settings: Settings;
+
+const TONEMAP_NONE = 0u;
+const TONEMAP_ACES = 1u;
+
+@fragment
+fn fs_bright(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, linear_sampler, in.uv).rgb * settings.exposure;
+    let brightness = max(color.r, max(color.g, color.b));
+    let weight = max(brightness - settings.bloom_threshold, 0.0) / max(brightness, 1e-4);
+
+    return vec4<f32>(color * weight, 1.0);
+}
+
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    let weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    let texel = direction / vec2<f32>(textureDimensions(source));
+
+    var color = textureSample(source, linear_sampler, uv).rgb * weights[0];
+    for (var i = 1; i < 5; i++) {
+        let offset = texel * f32(i);
+        color += textureSample(source, linear_sampler, uv + offset).rgb * weights[i];
+        color += textureSample(source, linear_sampler, uv - offset).rgb * weights[i];
+    }
+
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_blur_horizontal(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_vertical(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(0.0, 1.0));
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// the raw occlusion is noisy, the bilinear taps between texels average a 4x4 block
+fn blurred_occlusion(uv: vec2<f32>) -> f32 {
+    let texel = 1.0 / vec2<f32>(textureDimensions(occlusion));
+
+    var ao = 0.0;
+    for (var i = 0; i < 4; i++) {
+        let offset = vec2<f32>(f32(i & 1) - 0.5, f32(i >> 1u) - 0.5) * 2.0 * texel;
+        ao += textureSample(occlusion, linear_sampler, uv + offset).r;
+    }
+
+    return ao / 4.0;
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = textureSample(source, linear_sampler, in.uv).rgb * settings.exposure;
+    if settings.ssao_intensity > 0.0 {
+        color *= mix(1.0, blurred_occlusion(in.uv), settings.ssao_intensity);
+    }
+    color += textureSample(bloom, linear_sampler, in.uv).rgb * settings.bloom_intensity;
+
+    if (settings.tonemapping == TONEMAP_ACES) {
+        color = aces(color);
+    }
+
+    let edge = distance(in.uv, vec2<f32>(0.5));
+    color *= 1.0 - settings.vignette * smoothstep(0.3, 0.75, edge);
+
+    return vec4<f32>(color, 1.0);
+}
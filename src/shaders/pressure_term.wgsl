@@ -0,0 +1,9 @@
+// p / ρ² from the equation of state, the pressure force of a pair sums it over both particles
+fn pressure_term(density: f32) -> f32 {
+    var pressure = GAS_CONST * (density - REST_DENSITY);
+    // grains push each other apart but don't pull
+    if (GRANULAR) {
+        pressure = max(pressure, 0.0);
+    }
+    return pressure / (density * density);
+}
@@ -0,0 +1,78 @@
+@group(0) @binding(0) var<storage, read> position: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read> velocity: array<vec3<f32>>;
+@group(0) @binding(2) var<storage, read> density: array<f32>;
+@group(0) @binding(3) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(4) var<storage, read> spatial_lookup_vals: array<u32>;
+@group(0) @binding(5) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+// in simulation space, w is zero for the unused points of a probe
+@group(0) @binding(6) var<storage, read> sample_points: array<vec4<f32>>;
+// interpolated velocity and density at each sample point
+@group(0) @binding(7) var<storage, read_write> samples: array<vec4<f32>>;
+
+var<push_constant> point_cnt: u32;
+
+const HSQ = SMOOTHING_RADIUS * SMOOTHING_RADIUS;
+
+const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
+const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
+const dz = array(-1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+
+    if (index >= point_cnt) {
+        return;
+    }
+
+    let sample_point = sample_points[index];
+    if (sample_point.w == 0.0) {
+        samples[index] = vec4<f32>(0.0);
+        return;
+    }
+
+    // the density like the density slice, the velocity normalized by the kernel sum of the
+    // fluid particles like the debris
+    let pos = sample_point.xyz;
+    let cell = cell_of(pos);
+    var d = 0.0;
+    var v = vec3<f32>(0.0);
+    var weight_sum = 0.0;
+
+    for (var i = 0; i < 27; i += 1) {
+        let neighbor_cell = cell + vec3<i32>(dx[i], dy[i], dz[i]);
+
+        if (!is_valid_cell(neighbor_cell)) {
+            continue;
+        }
+
+        let neighbor_cell_key = cell_key(neighbor_cell);
+        for (var l = cell_start(neighbor_cell_key); l < arrayLength(&position) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+            let ind = spatial_lookup_vals[l];
+
+            if (HASHED && any(cell_of(position[ind]) != neighbor_cell)) {
+                continue;
+            }
+
+            let diff = pos - position[ind];
+            let dist_sq = dot(diff, diff);
+            if (dist_sq >= HSQ) {
+                continue;
+            }
+
+            let w = HSQ - dist_sq;
+            d += MASS * POLY6 * w * w * w;
+
+            if (ind >= GHOST_PARTICLE_CNT) {
+                let weight = w * w * w / density[ind];
+                v += weight * velocity[ind];
+                weight_sum += weight;
+            }
+        }
+    }
+
+    if (weight_sum > 0.0) {
+        v /= weight_sum;
+    }
+    samples[index] = vec4<f32>(v, d);
+}
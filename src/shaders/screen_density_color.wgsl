@@ -0,0 +1,21 @@
+@group(0) @binding(0) var<storage, read> grid: array<u32>;
+@group(0) @binding(1) var colormap_texture: texture_1d<f32>;
+@group(0) @binding(2) var density_texture: texture_storage_2d<rgba8unorm, write>;
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    if (global_id.x >= WIDTH || global_id.y >= HEIGHT) {
+        return;
+    }
+
+    let count = grid[global_id.y * WIDTH + global_id.x];
+    let largest = max(grid[WIDTH * HEIGHT], 1u);
+
+    // empty texels let the scene through
+    var color = vec4<f32>(0.0);
+    if (count > 0u) {
+        color = vec4<f32>(colormap(sqrt(f32(count) / f32(largest))), 1.0);
+    }
+
+    textureStore(density_texture, global_id.xy, color);
+}
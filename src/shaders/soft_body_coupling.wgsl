@@ -0,0 +1,19 @@
+// force per unit volume a surface point of the soft body exerts on a fluid particle, the point
+// is pushed back by the same force times the volume of the particle
+fn coupling_force(particle_pos: vec3<f32>, particle_velocity: vec3<f32>, particle_density: f32, node_pos: vec3<f32>, node_velocity: vec3<f32>) -> vec3<f32> {
+    let dir = particle_pos - node_pos;
+    let dist = length(dir);
+    if (dist >= SMOOTHING_RADIUS || dist == 0.0) {
+        return vec3<f32>(0.0);
+    }
+
+    let diff = SMOOTHING_RADIUS - dist;
+    let norm_dir = dir / dist;
+    // the point mirrors the pressure of the particle, but never pulls it in
+    let pressure = max(GAS_CONST * (particle_density - REST_DENSITY), 0.0);
+    let pressure_force = norm_dir * MASS * pressure * SPIKY_GRAD * diff * diff * diff / particle_density;
+    let penalty_force = norm_dir * COUPLING_STIFFNESS * diff;
+    let viscous_force = VISCOSITY * MASS * (node_velocity - particle_velocity) * VISC_LAP * diff / particle_density;
+
+    return pressure_force + penalty_force + viscous_force;
+}
@@ -0,0 +1,29 @@
+@group(0) @binding(0) var<storage, read> particle_positions: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read> particle_velocities: array<vec3<f32>>;
+@group(0) @binding(2) var<storage, read> particle_density: array<f32>;
+@group(0) @binding(3) var<storage, read> node_positions: array<vec3<f32>>;
+@group(0) @binding(4) var<storage, read> node_velocities: array<vec3<f32>>;
+@group(0) @binding(5) var<storage, read> surface_nodes: array<u32>;
+@group(0) @binding(6) var<storage, read_write> particle_force: array<vec3<f32>>;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let gid = global_id.x + GHOST_PARTICLE_CNT;
+
+    if (gid >= arrayLength(&particle_positions)) {
+        return;
+    }
+
+    let particle_pos = particle_positions[gid];
+    let particle_velocity = particle_velocities[gid];
+    let density = particle_density[gid];
+    var force = vec3<f32>(0.0);
+
+    // the body is small, so all of its surface is checked instead of the spatial lookup
+    for (var i = 0u; i < arrayLength(&surface_nodes); i += 1u) {
+        let node = surface_nodes[i];
+        force += coupling_force(particle_pos, particle_velocity, density, node_positions[node], node_velocities[node]);
+    }
+
+    particle_force[gid] += force;
+}
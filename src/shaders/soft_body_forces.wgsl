@@ -0,0 +1,87 @@
+@group(0) @binding(0) var<storage, read> particle_positions: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read> particle_velocities: array<vec3<f32>>;
+@group(0) @binding(2) var<storage, read> particle_density: array<f32>;
+@group(0) @binding(3) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(4) var<storage, read> spatial_lookup_vals: array<u32>;
+@group(0) @binding(5) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+@group(0) @binding(6) var<storage, read> node_positions: array<vec3<f32>>;
+@group(0) @binding(7) var<storage, read> node_velocities: array<vec3<f32>>;
+@group(0) @binding(8) var<storage, read_write> node_acceleration: array<vec3<f32>>;
+
+const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
+const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
+const dz = array(-1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+
+fn node_index(lattice: vec3<u32>) -> u32 {
+    return lattice.x + RESOLUTION.x * (lattice.y + RESOLUTION.y * lattice.z);
+}
+
+// flat axes of a 2D body have no faces
+fn is_surface(lattice: vec3<u32>) -> bool {
+    let on_face = (lattice == vec3<u32>(0u)) | (lattice == RESOLUTION - 1u);
+    return any(on_face & (RESOLUTION > vec3<u32>(1u)));
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let node = global_id.x;
+
+    if (node >= arrayLength(&node_positions)) {
+        return;
+    }
+
+    let lattice = vec3<u32>(node % RESOLUTION.x, (node / RESOLUTION.x) % RESOLUTION.y, node / (RESOLUTION.x * RESOLUTION.y));
+    let pos = node_positions[node];
+    let velocity = node_velocities[node];
+    var force = G * NODE_MASS;
+
+    // springs to the lattice neighbors along the edges and the face and space diagonals
+    for (var i = 0; i < 27; i += 1) {
+        let offset = vec3<i32>(dx[i], dy[i], dz[i]);
+        let neighbor = vec3<i32>(lattice) + offset;
+
+        if (all(offset == vec3<i32>(0)) || any(neighbor < vec3<i32>(0)) || any(neighbor >= vec3<i32>(RESOLUTION))) {
+            continue;
+        }
+
+        let other = node_index(vec3<u32>(neighbor));
+        let rest_length = length(vec3<f32>(offset) * SPACING);
+        let dir = node_positions[other] - pos;
+        let len = length(dir);
+        if (len == 0.0) {
+            continue;
+        }
+
+        let axis = dir / len;
+        let relative_speed = dot(node_velocities[other] - velocity, axis);
+        force += axis * (STIFFNESS * (len - rest_length) + SPRING_DAMPING * relative_speed);
+    }
+
+    // reaction to the coupling force the fluid particles feel from this point
+    if (is_surface(lattice)) {
+        let cell = cell_of(pos);
+
+        for (var i = 0; i < 27; i += 1) {
+            let neighbor_cell = cell + vec3<i32>(dx[i], dy[i], dz[i]);
+
+            if (!is_valid_cell(neighbor_cell)) {
+                continue;
+            }
+
+            let neighbor_cell_key = cell_key(neighbor_cell);
+            for (var l = cell_start(neighbor_cell_key); l < arrayLength(&particle_positions) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+                let ind = spatial_lookup_vals[l];
+                let particle_pos = particle_positions[ind];
+
+                if (ind < GHOST_PARTICLE_CNT || (HASHED && any(cell_of(particle_pos) != neighbor_cell))) {
+                    continue;
+                }
+
+                let density = particle_density[ind];
+                force -= coupling_force(particle_pos, particle_velocities[ind], density, pos, velocity) * MASS / density;
+            }
+        }
+    }
+
+    node_acceleration[node] = force / NODE_MASS;
+}
@@ -0,0 +1,55 @@
+@group(0) @binding(0) var<storage, read_write> node_positions: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read_write> node_velocities: array<vec3<f32>>;
+@group(0) @binding(2) var<storage, read> node_acceleration: array<vec3<f32>>;
+
+var<push_constant> dt: f32;
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let node = global_id.x;
+
+    if (node >= arrayLength(&node_positions)) {
+        return;
+    }
+
+    var velocity = node_velocities[node] + node_acceleration[node] * dt;
+    var position = node_positions[node] + velocity * dt;
+
+    // the body stays as far from the walls as the fluid, the wave paddle is not felt
+    if (WALLS) {
+        if position.x - SMOOTHING_RADIUS < 0.0 {
+            velocity.x *= DAMPING;
+            position.x = 0.0 + SMOOTHING_RADIUS;
+        }
+
+        if position.x + SMOOTHING_RADIUS > BBOX.x {
+            velocity.x *= DAMPING;
+            position.x = BBOX.x - SMOOTHING_RADIUS;
+        }
+
+        if position.y + SMOOTHING_RADIUS > BBOX.y {
+            velocity.y *= DAMPING;
+            position.y = BBOX.y - SMOOTHING_RADIUS;
+        }
+
+        if position.z - SMOOTHING_RADIUS < 0.0 {
+            velocity.z *= DAMPING;
+            position.z = 0.0 + SMOOTHING_RADIUS;
+        }
+
+        if position.z + SMOOTHING_RADIUS > BBOX.z {
+            velocity.z *= DAMPING;
+            position.z = BBOX.z - SMOOTHING_RADIUS;
+        }
+    }
+
+    if (FLOOR) {
+        if position.y - SMOOTHING_RADIUS < 0.0 {
+            velocity.y *= DAMPING;
+            position.y = 0.0 + SMOOTHING_RADIUS;
+        }
+    }
+
+    node_positions[node] = position;
+    node_velocities[node] = velocity;
+}
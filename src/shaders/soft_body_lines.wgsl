@@ -0,0 +1,40 @@
+@group(0) @binding(0) var<storage, read> node_positions: array<vec3<f32>>;
+
+struct LineVertex {
+    position: vec3<f32>,
+    color: vec4<f32>,
+}
+
+@group(0) @binding(1) var<storage, read_write> lines: array<LineVertex>;
+
+const COLOR = vec4<f32>(1.0, 0.55, 0.15, 1.0);
+
+fn node_index(lattice: vec3<u32>) -> u32 {
+    return lattice.x + RESOLUTION.x * (lattice.y + RESOLUTION.y * lattice.z);
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let node = global_id.x;
+
+    if (node >= arrayLength(&node_positions)) {
+        return;
+    }
+
+    let lattice = vec3<u32>(node % RESOLUTION.x, (node / RESOLUTION.x) % RESOLUTION.y, node / (RESOLUTION.x * RESOLUTION.y));
+    let start = node_positions[node] + OFFSET;
+
+    // an edge to the next point along every axis, collapsed to a point on the last layer
+    for (var axis = 0u; axis < 3u; axis += 1u) {
+        var next = lattice;
+        next[axis] += 1u;
+
+        var end = start;
+        if (next[axis] < RESOLUTION[axis]) {
+            end = node_positions[node_index(next)] + OFFSET;
+        }
+
+        lines[6u * node + 2u * axis] = LineVertex(start, COLOR);
+        lines[6u * node + 2u * axis + 1u] = LineVertex(end, COLOR);
+    }
+}
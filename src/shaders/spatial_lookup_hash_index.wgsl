@@ -0,0 +1,33 @@
+@group(0) @binding(0) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(1) var<storage, read_write> spatial_lookup_index: array<atomic<u32>>;
+
+// every table slot is a (key + 1, start) pair, a zero key marks an empty slot
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let gid = global_id.x;
+
+    if (gid >= PARTICLE_CNT) {
+        return;
+    }
+
+    let key = spatial_lookup_keys[gid];
+    if (gid != 0 && key == spatial_lookup_keys[gid - 1]) {
+        return;
+    }
+
+    var slot = key % TABLE_SIZE;
+    var probes = 0u;
+    while (probes < TABLE_SIZE) {
+        let result = atomicCompareExchangeWeak(&spatial_lookup_index[2u * slot], 0u, key + 1u);
+        if (result.exchanged) {
+            atomicStore(&spatial_lookup_index[2u * slot + 1u], gid);
+            return;
+        }
+
+        // weak exchanges can fail spuriously, only move on if the slot is taken
+        if (result.old_value != 0u) {
+            slot = (slot + 1u) % TABLE_SIZE;
+            probes += 1u;
+        }
+    }
+}
@@ -0,0 +1,86 @@
+struct Ssao {
+    projection: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    radius: f32,
+}
+
+@group(0) @binding(0) var depth: texture_depth_2d;
+@group(0) @binding(1) var<uniform> ssao: Ssao;
+
+const SAMPLE_CNT: u32 = 16u;
+
+fn view_position(pixel: vec2<i32>) -> vec3<f32> {
+    let uv = (vec2<f32>(pixel) + 0.5) / vec2<f32>(textureDimensions(depth));
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, textureLoad(depth, pixel, 0), 1.0);
+    let position = ssao.inverse_projection * ndc;
+    return position.xyz / position.w;
+}
+
+fn hash(seed: u32) -> u32 {
+    let state = seed * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random(seed: u32) -> f32 {
+    return f32(hash(seed)) / 4294967295.0;
+}
+
+@fragment
+fn fs_ssao(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(depth));
+    let pixel = vec2<i32>(in.clip_position.xy);
+
+    if textureLoad(depth, pixel, 0) >= 1.0 {
+        return vec4<f32>(1.0);
+    }
+
+    let position = view_position(pixel);
+
+    // take the neighbour closer in depth on each axis so normals don't bend over silhouettes
+    let right = view_position(min(pixel + vec2<i32>(1, 0), size - 1)) - position;
+    let left = position - view_position(max(pixel - vec2<i32>(1, 0), vec2<i32>(0)));
+    let down = view_position(min(pixel + vec2<i32>(0, 1), size - 1)) - position;
+    let up = position - view_position(max(pixel - vec2<i32>(0, 1), vec2<i32>(0)));
+    let dx = select(left, right, abs(right.z) < abs(left.z));
+    let dy = select(up, down, abs(down.z) < abs(up.z));
+
+    var normal = normalize(cross(dy, dx));
+    if dot(normal, position) > 0.0 {
+        normal = -normal;
+    }
+
+    let seed = hash(u32(pixel.x) + u32(pixel.y) * u32(size.x)) * SAMPLE_CNT;
+    let bias = 0.05 * ssao.radius;
+
+    var occlusion = 0.0;
+    for (var i = 0u; i < SAMPLE_CNT; i++) {
+        let u = random(3u * (seed + i));
+        let v = random(3u * (seed + i) + 1u);
+        let z = u * 2.0 - 1.0;
+        let phi = v * 6.2831853;
+        var direction = vec3<f32>(sqrt(1.0 - z * z) * vec2<f32>(cos(phi), sin(phi)), z);
+        if dot(direction, normal) < 0.0 {
+            direction = -direction;
+        }
+
+        // more samples close to the surface
+        let t = f32(i + 1u) / f32(SAMPLE_CNT);
+        let scale = mix(0.1, 1.0, t * t) * random(3u * (seed + i) + 2u);
+        let sample = position + direction * scale * ssao.radius;
+
+        let clip = ssao.projection * vec4<f32>(sample, 1.0);
+        let uv = vec2<f32>(clip.x / clip.w * 0.5 + 0.5, 0.5 - clip.y / clip.w * 0.5);
+        if any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0)) {
+            continue;
+        }
+
+        let scene = view_position(vec2<i32>(uv * vec2<f32>(size)));
+        let range = smoothstep(0.0, 1.0, ssao.radius / abs(position.z - scene.z));
+        if scene.z >= sample.z + bias {
+            occlusion += range;
+        }
+    }
+
+    return vec4<f32>(vec3<f32>(1.0 - occlusion / f32(SAMPLE_CNT)), 1.0);
+}
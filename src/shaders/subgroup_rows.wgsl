@@ -0,0 +1,47 @@
+// The subgroup kernels visit the particles in their sorted order, so the invocations of a
+// subgroup mostly sit in a few neighboring cells along z. If they share their x and y cell,
+// the neighbors of the whole subgroup lie in the nine rows of cells along z around them. The
+// keys of a dense row are consecutive, which makes its particles one range of the sorted
+// particles, loaded together one particle per invocation.
+
+// widest span of cells along z a subgroup shares its rows for
+const MAX_ROW_SPAN: i32 = 4;
+
+// sorted particle of an invocation, the invocations past the end stand in for the last one
+// because every invocation takes part in the shuffles
+fn sorted_particle(sorted: u32) -> u32 {
+    return spatial_lookup_vals[min(sorted, arrayLength(&particle_positions) - 1u)];
+}
+
+// whether all invocations of the subgroup share the rows around `first_cell`
+fn shares_rows(particle_cell: vec3<i32>, first_cell: vec3<i32>, min_z: i32, max_z: i32) -> bool {
+    if (HASHED) {
+        return false;
+    }
+    return subgroupAll(all(particle_cell.xy == first_cell.xy)) && max_z - min_z < MAX_ROW_SPAN;
+}
+
+// first sorted particle and last key of the row through `row` from `min_z - 1` to `max_z + 1`,
+// the first particle is past the end for an empty row
+fn row_range(row: vec2<i32>, min_z: i32, max_z: i32) -> vec2<u32> {
+    let particle_cnt = arrayLength(&particle_positions);
+    var start = particle_cnt;
+    var last_key = 0u;
+
+    for (var z = min_z - 1; z <= max_z + 1; z += 1) {
+        let cell = vec3<i32>(row, z);
+        if (!is_valid_cell(cell)) {
+            continue;
+        }
+
+        let key = cell_key(cell);
+        let first = cell_start(key);
+        // stale index entries point at particles of other cells
+        if (start == particle_cnt && first < particle_cnt && spatial_lookup_keys[first] == key) {
+            start = first;
+        }
+        last_key = key;
+    }
+
+    return vec2<u32>(start, last_key);
+}
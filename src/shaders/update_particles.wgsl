@@ -3,8 +3,28 @@
 @group(0) @binding(2) var<storage, read> particle_density: array<f32>; 
 @group(0) @binding(3) var<storage, read> particle_force: array<vec3<f32>>; 
 
+struct Step {
+    paddle_position: f32,
+    paddle_velocity: f32,
+    integrator: u32,
+    first_step: u32,
+}
+
+@group(0) @binding(4) var<uniform> step: Step;
+@group(0) @binding(5) var<storage, read_write> previous_positions: array<vec3<f32>>;
+
+const SYMPLECTIC_EULER = 0u;
+const LEAPFROG = 1u;
+const VERLET = 2u;
+
 var<push_constant> dt: f32;
 
+fn spawn_offset(gid: u32) -> vec3<f32> {
+    let h = gid * 747796405u + 2891336453u;
+    let bits = vec3<u32>(h & 1023u, (h >> 10u) & 1023u, (h >> 20u) & 1023u);
+    return vec3<f32>(bits) / 1023.0 - 0.5;
+}
+
 @compute @workgroup_size(256)
 fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let gid = global_id.x + GHOST_PARTICLE_CNT;
@@ -13,43 +33,101 @@ fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
         return;
     }
 
-    //var velocity: vec3<f32> = particle_velocity[gid] + ( particle_force[gid] + G ) * (dt / MASS);
-    // var velocity: vec3<f32> = particle_velocity[gid] /*+ G * dt*/ + particle_force[gid] * (dt / particle_density[gid]);
-    // var position: vec3<f32> = particle_positions[gid] + velocity * dt;
+    let acceleration = G + particle_force[gid] / particle_density[gid];
+    let current_position = particle_positions[gid];
+    var position: vec3<f32>;
+    var velocity: vec3<f32>;
+
+    switch step.integrator {
+        case SYMPLECTIC_EULER: {
+            velocity = particle_velocity[gid] + acceleration * dt;
+            position = current_position + velocity * dt;
+        }
+        case VERLET: {
+            if (step.first_step != 0u) {
+                position = current_position + particle_velocity[gid] * dt + acceleration * (dt * dt / 2.0);
+            } else {
+                position = 2.0 * current_position - previous_positions[gid] + acceleration * (dt * dt);
+            }
+            velocity = (position - current_position) / dt + acceleration * (dt / 2.0);
+        }
+        default: {
+            let dv = acceleration * (dt / 2.0);
+            let half_velocity = particle_velocity[gid] + dv;
+            position = current_position + half_velocity * dt;
+            velocity = half_velocity + dv;
+        }
+    }
+
+    // the inflow zone drives the channel, particles in it are carried at the inflow velocity
+    if (RIVER && current_position.x < INFLOW_WIDTH && current_position.y < INFLOW_DEPTH) {
+        velocity = INFLOW_VELOCITY;
+        position = current_position + velocity * dt;
+    }
+
+    if (WALLS) {
+        // the -x wall can move, reflect the velocity relative to it
+        if position.x - SMOOTHING_RADIUS < step.paddle_position {
+            velocity.x = step.paddle_velocity + (velocity.x - step.paddle_velocity) * DAMPING;
+            position.x = step.paddle_position + SMOOTHING_RADIUS;
+        }
+
+        if position.x + SMOOTHING_RADIUS > BBOX.x {
+            velocity.x *= DAMPING;
+            position.x = BBOX.x - SMOOTHING_RADIUS;
+        }
+
+        if position.y + SMOOTHING_RADIUS > BBOX.y {
+            velocity.y *= DAMPING;
+            position.y = BBOX.y - SMOOTHING_RADIUS;
+        }
 
-    let dv = G * dt / 2.0 + particle_force[gid] * (dt / (2.0 * particle_density[gid]));
-    var half_velocity: vec3<f32> = particle_velocity[gid] + dv;
-    var position: vec3<f32> = particle_positions[gid] + half_velocity * dt;
-    var velocity: vec3<f32> = half_velocity + dv;
+        if position.z - SMOOTHING_RADIUS < 0.0 {
+            velocity.z *= DAMPING;
+            position.z = 0.0 + SMOOTHING_RADIUS;
+        }
 
-    if position.x - SMOOTHING_RADIUS < 0.0 {
-        velocity.x *= DAMPING;
-        position.x = 0.0 + SMOOTHING_RADIUS;
+        if position.z + SMOOTHING_RADIUS > BBOX.z {
+            velocity.z *= DAMPING;
+            position.z = BBOX.z - SMOOTHING_RADIUS;
+        }
     }
 
-    if position.x + SMOOTHING_RADIUS > BBOX.x {
-        velocity.x *= DAMPING;
-        position.x = BBOX.x - SMOOTHING_RADIUS;
+    if (FLOOR) {
+        if position.y - SMOOTHING_RADIUS < 0.0 {
+            velocity.y *= DAMPING;
+            position.y = 0.0 + SMOOTHING_RADIUS;
+        }
     }
 
-    if position.y - SMOOTHING_RADIUS < 0.0 {
-        velocity.y *= DAMPING;
-        position.y = 0.0 + SMOOTHING_RADIUS;
+    // without walls, particles that drift too far are recycled above the center of the box
+    if (!WALLS && distance(position, BBOX / 2.0) > KILL_RADIUS) {
+        position = vec3<f32>(BBOX.x / 2.0, BBOX.y, BBOX.z / 2.0) + spawn_offset(gid) * SPAWN_EXTENT;
+        velocity = vec3<f32>(0.0);
     }
 
-    if position.y + SMOOTHING_RADIUS > BBOX.y {
-        velocity.y *= DAMPING;
-        position.y = BBOX.y - SMOOTHING_RADIUS;
+    // particles leaving through the outflow plane are recycled into the inflow zone, the
+    // position is mixed into the seed so a particle doesn't land on the same spot every time
+    if (RIVER && position.x > OUTFLOW_X) {
+        let offset = spawn_offset(gid ^ bitcast<u32>(position.y)) + 0.5;
+        position = vec3<f32>(
+            SMOOTHING_RADIUS + offset.x * (INFLOW_WIDTH - SMOOTHING_RADIUS),
+            SMOOTHING_RADIUS + offset.y * (INFLOW_DEPTH - SMOOTHING_RADIUS),
+            BBOX.z / 2.0 + (offset.z - 0.5) * RIVER_SPAN,
+        );
+        velocity = INFLOW_VELOCITY;
     }
 
-    if position.z - SMOOTHING_RADIUS < 0.0 {
-        velocity.z *= DAMPING;
-        position.z = 0.0 + SMOOTHING_RADIUS;
+    // relaxation only moves the particles along the forces, nothing carries over to the next
+    // iteration
+    if (RELAX) {
+        velocity = vec3<f32>(0.0);
     }
 
-    if position.z + SMOOTHING_RADIUS > BBOX.z {
-        velocity.z *= DAMPING;
-        position.z = BBOX.z - SMOOTHING_RADIUS;
+    // collisions change the velocity, so the history is rebuilt from it instead of storing the
+    // current position
+    if (step.integrator == VERLET) {
+        previous_positions[gid] = position - (velocity - acceleration * (dt / 2.0)) * dt;
     }
 
     particle_positions[gid] = position;
@@ -0,0 +1,98 @@
+@group(0) @binding(0) var<storage, read> position: array<vec3<f32>>;
+@group(0) @binding(1) var<storage, read> velocity: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read> density: array<f32>;
+@group(0) @binding(3) var<storage, read> spatial_lookup_keys: array<u32>;
+@group(0) @binding(4) var<storage, read> spatial_lookup_vals: array<u32>;
+@group(0) @binding(5) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+
+struct LineVertex {
+    position: vec3<f32>,
+    color: vec4<f32>,
+}
+
+@group(0) @binding(6) var<storage, read_write> lines: array<LineVertex>;
+
+struct VelocityLines {
+    scale: f32,
+    subsampling: u32,
+    streamline_steps: u32,
+}
+
+@group(0) @binding(7) var<uniform> params: VelocityLines;
+
+const HSQ = SMOOTHING_RADIUS * SMOOTHING_RADIUS;
+
+const dx = array(-1, -1, -1, -1, -1, -1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1);
+const dy = array(-1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1, -1, -1, -1, 0, 0, 0, 1, 1, 1);
+const dz = array(-1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1, -1, 0, 1);
+
+// SPH interpolation normalized by the kernel sum, so the constant factors of the kernel cancel
+fn sample_velocity(pos: vec3<f32>) -> vec3<f32> {
+    let cell = cell_of(pos);
+    var v = vec3<f32>(0.0);
+    var weight_sum = 0.0;
+
+    for (var i = 0; i < 27; i += 1) {
+        let neighbor_cell = cell + vec3<i32>(dx[i], dy[i], dz[i]);
+
+        if (!is_valid_cell(neighbor_cell)) {
+            continue;
+        }
+
+        let neighbor_cell_key = cell_key(neighbor_cell);
+        for (var l = cell_start(neighbor_cell_key); l < arrayLength(&position) && spatial_lookup_keys[l] == neighbor_cell_key; l += 1u) {
+            let ind = spatial_lookup_vals[l];
+
+            if (HASHED && any(cell_of(position[ind]) != neighbor_cell)) {
+                continue;
+            }
+
+            let diff = pos - position[ind];
+            let dist_sq = dot(diff, diff);
+            if (dist_sq >= HSQ) {
+                continue;
+            }
+
+            let w = HSQ - dist_sq;
+            let weight = w * w * w / density[ind];
+            v += weight * velocity[ind].xyz;
+            weight_sum += weight;
+        }
+    }
+
+    return select(vec3<f32>(0.0), v / weight_sum, weight_sum > 0.0);
+}
+
+fn speed_color(v: vec3<f32>) -> vec4<f32> {
+    // lines longer than two smoothing radii are fully red
+    let t = clamp(length(v) * params.scale / (2.0 * SMOOTHING_RADIUS), 0.0, 1.0);
+    return mix(vec4<f32>(0.9, 0.9, 0.9, 1.0), vec4<f32>(1.0, 0.1, 0.0, 1.0), t);
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let line = global_id.x;
+    let particle = GHOST_PARTICLE_CNT + line * params.subsampling;
+
+    if (particle >= arrayLength(&position)) {
+        return;
+    }
+
+    let dt = params.scale / f32(params.streamline_steps);
+    var pos = position[particle];
+    var v = velocity[particle].xyz;
+
+    for (var step = 0u; step < params.streamline_steps; step++) {
+        // the first segment follows the particle itself, the rest the interpolated field
+        if (step > 0u) {
+            v = sample_velocity(pos);
+        }
+
+        let next = pos + v * dt;
+        let color = speed_color(v);
+        let vertex = 2u * (line * params.streamline_steps + step);
+        lines[vertex] = LineVertex(pos + OFFSET, color);
+        lines[vertex + 1u] = LineVertex(next + OFFSET, color);
+        pos = next;
+    }
+}
@@ -0,0 +1,42 @@
+@group(0) @binding(0) var<storage, read> position: array<vec3<f32>>;
+// x and z of the column in simulation space, then its radius
+@group(0) @binding(1) var<storage, read> gauges: array<vec4<f32>, MAX_WAVE_GAUGES>;
+// highest particle of each column as an ordered key, zero for an empty column
+@group(0) @binding(2) var<storage, read_write> heights: array<atomic<u32>, MAX_WAVE_GAUGES>;
+
+var<push_constant> gauge_cnt: u32;
+
+var<workgroup> local_heights: array<atomic<u32>, MAX_WAVE_GAUGES>;
+
+// flips the bits so that the keys order like the floats, negative heights included
+fn ordered_key(x: f32) -> u32 {
+    let bits = bitcast<u32>(x);
+    return select(bits | 0x80000000u, ~bits, (bits & 0x80000000u) != 0u);
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_index) local_index: u32) {
+    if (local_index < MAX_WAVE_GAUGES) {
+        atomicStore(&local_heights[local_index], 0u);
+    }
+    workgroupBarrier();
+
+    // every invocation takes part in `reduce_height`, the ones past the end with a zero key
+    let particle = GHOST_PARTICLE_CNT + global_id.x;
+    let valid = particle < arrayLength(&position);
+    let pos = position[min(particle, arrayLength(&position) - 1u)];
+    for (var i = 0u; i < gauge_cnt; i += 1u) {
+        let gauge = gauges[i];
+        let inside = valid && distance(pos.xz, gauge.xy) <= gauge.z;
+        reduce_height(i, select(0u, ordered_key(pos.y), inside));
+    }
+
+    // one global atomic per workgroup and gauge
+    workgroupBarrier();
+    if (local_index < gauge_cnt) {
+        let height = atomicLoad(&local_heights[local_index]);
+        if (height != 0u) {
+            atomicMax(&heights[local_index], height);
+        }
+    }
+}
@@ -0,0 +1,5 @@
+fn reduce_height(gauge: u32, key: u32) {
+    if (key != 0u) {
+        atomicMax(&local_heights[gauge], key);
+    }
+}
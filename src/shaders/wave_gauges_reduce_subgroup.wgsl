@@ -0,0 +1,7 @@
+// one workgroup atomic per subgroup, from its first invocation
+fn reduce_height(gauge: u32, key: u32) {
+    let highest = subgroupMax(key);
+    if (subgroupExclusiveAdd(1u) == 0u && highest != 0u) {
+        atomicMax(&local_heights[gauge], highest);
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{graphics::render_engine::GenericRequest, WgpuRenderDevice};
+
+struct SharedState {
+    /// Request submitted every step, `None` while the simulation is paused
+    step: Mutex<Option<GenericRequest>>,
+    /// When the GPU finished the last step
+    last_step: Mutex<Instant>,
+    stop: AtomicBool,
+}
+
+/// Steps the simulation on its own thread at a fixed rate, independent of the frame rate. The
+/// steps go through the same queue as the frames, so a frame always sees a completed step.
+pub struct SimulationWorker {
+    step_rate: f32,
+    shared: Arc<SharedState>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SimulationWorker {
+    pub fn new(render_device: Arc<RwLock<WgpuRenderDevice>>, step_rate: f32) -> Self {
+        let step_rate = step_rate.max(1.0);
+        let shared = Arc::new(SharedState {
+            step: Mutex::new(None),
+            last_step: Mutex::new(Instant::now()),
+            stop: AtomicBool::new(false),
+        });
+
+        let step_interval = Duration::from_secs_f32(1.0 / step_rate);
+        let thread_state = shared.clone();
+        let thread = std::thread::Builder::new()
+            .name("simulation".to_string())
+            .spawn(move || run_steps(&render_device, &thread_state, step_interval))
+            .ok();
+        if thread.is_none() {
+            tracing::error!("Failed to spawn the simulation thread");
+        }
+
+        Self {
+            step_rate,
+            shared,
+            thread,
+        }
+    }
+
+    pub fn step_rate(&self) -> f32 {
+        self.step_rate
+    }
+
+    /// Simulated time of a single step.
+    pub fn dt(&self) -> f32 {
+        1.0 / self.step_rate
+    }
+
+    /// Replaces the request run every step, `None` pauses the simulation.
+    pub fn set_step(&self, step: Option<GenericRequest>) {
+        *self.shared.step.lock().unwrap() = step;
+    }
+
+    /// Fraction of a step interval that has passed since the last step finished, from 0 to 1.
+    pub fn interpolation(&self) -> f32 {
+        let elapsed = self.shared.last_step.lock().unwrap().elapsed();
+        (elapsed.as_secs_f32() * self.step_rate).min(1.0)
+    }
+}
+
+impl Drop for SimulationWorker {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run_steps(
+    render_device: &RwLock<WgpuRenderDevice>,
+    shared: &SharedState,
+    step_interval: Duration,
+) {
+    let mut next_step = Instant::now();
+
+    while !shared.stop.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        if now < next_step {
+            std::thread::sleep(next_step - now);
+            continue;
+        }
+        // a slow GPU drops steps instead of piling them up
+        next_step = (next_step + step_interval).max(now);
+
+        let rd = render_device.read().unwrap();
+        let submission = {
+            let step = shared.step.lock().unwrap();
+            let Some(step) = step.as_ref() else {
+                continue;
+            };
+
+            let _span = tracing::debug_span!("background_step").entered();
+            let mut encoder = rd
+                .device()
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Simulation step encoder"),
+                });
+            step(&mut encoder, rd.queue());
+            rd.wgpu_device.submit(encoder)
+        };
+
+        rd.device()
+            .poll(wgpu::Maintain::WaitForSubmissionIndex(submission));
+        drop(rd);
+
+        *shared.last_step.lock().unwrap() = Instant::now();
+    }
+}
@@ -0,0 +1,222 @@
+use std::fmt;
+
+use crate::{
+    config::AdapterConfig,
+    fluid_simulation::{FluidSimulationConfig, ParticleSnapshot},
+    FluidSimulation, SplooshError, WgpuDevice,
+};
+
+pub struct SoakOptions {
+    pub frames: u64,
+    pub dt: f32,
+    /// Frames between two checks of the particle state
+    pub check_interval: u64,
+    /// Densities above this multiple of the rest density count as a divergence
+    pub max_density_ratio: f32,
+}
+
+impl Default for SoakOptions {
+    fn default() -> Self {
+        Self {
+            frames: 20_000,
+            dt: 1.0 / 120.0,
+            check_interval: 100,
+            max_density_ratio: 4.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Divergence {
+    /// A position, velocity or density is NaN or infinite
+    NonFinite { particle: usize },
+    /// The density left the range between zero and the allowed maximum
+    Density { particle: usize, density: f32 },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::NonFinite { particle } => {
+                write!(f, "particle {particle} has a non-finite state")
+            }
+            Divergence::Density { particle, density } => {
+                write!(f, "particle {particle} has a density of {density}")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SoakReport {
+    /// Frames stepped, up to and including the diverged one
+    pub frames: u64,
+    /// Density range of the fluid particles over all checks
+    pub min_density: f32,
+    pub max_density: f32,
+    /// First frame after which the state was broken
+    pub divergence: Option<(u64, Divergence)>,
+}
+
+/// Fluid particles only, the ghost particles at the start of the buffers never move.
+pub fn find_divergence(
+    snapshot: &ParticleSnapshot,
+    ghost_particle_cnt: usize,
+    max_density: f32,
+) -> Option<Divergence> {
+    (ghost_particle_cnt..snapshot.positions.len()).find_map(|particle| {
+        let position = snapshot.positions[particle];
+        let velocity = snapshot.velocities[particle];
+        let density = snapshot.densities[particle];
+
+        let finite = position
+            .iter()
+            .chain(velocity.iter())
+            .all(|x| x.is_finite());
+        if !finite || !density.is_finite() {
+            Some(Divergence::NonFinite { particle })
+        } else if density <= 0.0 || density > max_density {
+            Some(Divergence::Density { particle, density })
+        } else {
+            None
+        }
+    })
+}
+
+pub async fn run_soak(
+    config: FluidSimulationConfig,
+    adapter: &AdapterConfig,
+    options: &SoakOptions,
+) -> Result<SoakReport, SplooshError> {
+    let wgpu_device = WgpuDevice::with_adapter_config(adapter).await?;
+    config.max_particle_cnt(&wgpu_device.device.limits())?;
+    let fluid_sim = FluidSimulation::new(config, &wgpu_device);
+
+    soak(&fluid_sim, &wgpu_device, options)
+}
+
+/// Steps the simulation and checks it every `check_interval` frames. After a failed check the
+/// frames since the last good one are stepped again one at a time to find where it diverged.
+pub fn soak(
+    fluid_sim: &FluidSimulation,
+    wgpu_device: &WgpuDevice,
+    options: &SoakOptions,
+) -> Result<SoakReport, SplooshError> {
+    let max_density = fluid_sim.config().rest_density * options.max_density_ratio;
+    let ghost_particle_cnt = fluid_sim.ghost_particle_cnt();
+    let check_interval = options.check_interval.max(1);
+    let step = fluid_sim.step_fn(options.dt);
+
+    // the step uniform is written once per submission, so every step is submitted alone
+    let submit_step = || {
+        let mut encoder =
+            wgpu_device
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Soak encoder"),
+                });
+        step(&mut encoder, &wgpu_device.queue);
+        wgpu_device.submit(encoder);
+    };
+
+    let mut report = SoakReport {
+        frames: 0,
+        min_density: f32::INFINITY,
+        max_density: 0.0,
+        divergence: None,
+    };
+    let mut last_good = fluid_sim.read_snapshot(wgpu_device)?;
+
+    while report.frames < options.frames {
+        let steps = check_interval.min(options.frames - report.frames);
+        for _ in 0..steps {
+            submit_step();
+        }
+
+        let snapshot = fluid_sim.read_snapshot(wgpu_device)?;
+        if find_divergence(&snapshot, ghost_particle_cnt, max_density).is_none() {
+            report.frames += steps;
+            for &density in &snapshot.densities[ghost_particle_cnt..] {
+                report.min_density = report.min_density.min(density);
+                report.max_density = report.max_density.max(density);
+            }
+            last_good = snapshot;
+            continue;
+        }
+
+        fluid_sim.restore_snapshot(&wgpu_device.queue, &last_good)?;
+        for _ in 0..steps {
+            submit_step();
+            report.frames += 1;
+
+            let snapshot = fluid_sim.read_snapshot(wgpu_device)?;
+            if let Some(divergence) = find_divergence(&snapshot, ghost_particle_cnt, max_density) {
+                report.divergence = Some((report.frames, divergence));
+                return Ok(report);
+            }
+        }
+
+        // stepping again from the snapshot restarted the integrator history, which was enough
+        // to avoid the divergence
+        tracing::warn!(
+            "The check at frame {} failed but stepping again from frame {} did not diverge",
+            report.frames,
+            report.frames - steps
+        );
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::{Point4, Vector4};
+    use pollster::FutureExt as _;
+
+    use super::*;
+
+    #[test]
+    fn divergence_skips_ghost_particles() {
+        let mut snapshot = ParticleSnapshot {
+            positions: vec![Point4::new(0.0, 0.0, 0.0, 1.0); 3],
+            velocities: vec![Vector4::zeros(); 3],
+            densities: vec![f32::NAN, 100.0, 100.0],
+            time: 0.0,
+            step_cnt: 0,
+        };
+        assert_eq!(find_divergence(&snapshot, 1, 400.0), None);
+
+        snapshot.velocities[2].y = f32::INFINITY;
+        assert_eq!(
+            find_divergence(&snapshot, 1, 400.0),
+            Some(Divergence::NonFinite { particle: 2 })
+        );
+
+        snapshot.densities[1] = 500.0;
+        assert_eq!(
+            find_divergence(&snapshot, 1, 400.0),
+            Some(Divergence::Density {
+                particle: 1,
+                density: 500.0
+            })
+        );
+    }
+
+    /// Takes minutes, run with `cargo test --release -- --ignored` after changing the solver.
+    #[test]
+    #[ignore]
+    fn dam_break_stays_stable() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let fluid_sim = FluidSimulation::new(
+            FluidSimulationConfig {
+                particle_cnt: 20_000,
+                initial_layout: crate::fluid_simulation::InitialLayout::DamBreak,
+                ..Default::default()
+            },
+            &wgpu_device,
+        );
+
+        let report = soak(&fluid_sim, &wgpu_device, &SoakOptions::default()).unwrap();
+        assert_eq!(report.divergence, None, "{report:?}");
+    }
+}
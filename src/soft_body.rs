@@ -0,0 +1,514 @@
+use std::sync::Arc;
+
+use nalgebra::{Point3, Point4, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fluid_simulation::SimDim,
+    graphics::{geometry::Geometry, materials::ColoredVertex, render_engine::GenericRequest},
+    ComputeTask, SpatialLookup, WgpuDevice,
+};
+
+/// A deformable box of mass points joined by springs to their lattice neighbors. The points on
+/// its surface act as moving boundary particles, they push the fluid out and are pushed back.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SoftBodyConfig {
+    /// In world space, the bounding box is centered at the origin
+    pub center: Point3<f32>,
+    pub size: Vector3<f32>,
+    /// Mass points along each axis, the z resolution is ignored in 2D. The surface should be
+    /// about a smoothing radius apart, otherwise the fluid leaks through.
+    pub resolution: [u32; 3],
+    /// Mass per volume, bodies lighter than the rest density float
+    pub density: f32,
+    /// Force per unit of stretch of every spring
+    pub stiffness: f32,
+    /// Damps the relative velocity along the springs
+    pub damping: f32,
+    /// Pushes fluid particles out of the surface on top of their pressure
+    pub coupling_stiffness: f32,
+}
+
+impl Default for SoftBodyConfig {
+    fn default() -> Self {
+        Self {
+            center: Point3::new(-4.0, 1.0, 0.0),
+            size: Vector3::new(1.2, 1.2, 1.2),
+            resolution: [9, 9, 9],
+            density: 120.0,
+            stiffness: 100.0,
+            damping: 1.0,
+            coupling_stiffness: 2000.0,
+        }
+    }
+}
+
+/// Mass points of the lattice in simulation space, x runs fastest. Also returns the indices of
+/// the points on the surface.
+fn lattice(
+    config: &SoftBodyConfig,
+    resolution: Vector3<u32>,
+    bbox_dimensions: Vector3<f32>,
+) -> (Vec<Point4<f32>>, Vec<u32>) {
+    let spacing = lattice_spacing(config.size, resolution);
+    let corner = config.center + bbox_dimensions / 2.0 - config.size / 2.0;
+
+    let mut nodes = Vec::new();
+    let mut surface = Vec::new();
+    for z in 0..resolution.z {
+        for y in 0..resolution.y {
+            for x in 0..resolution.x {
+                let index = Vector3::new(x, y, z);
+                let offset = index.cast::<f32>().component_mul(&spacing);
+                let is_surface = (0..3).any(|axis| {
+                    resolution[axis] > 1
+                        && (index[axis] == 0 || index[axis] == resolution[axis] - 1)
+                });
+
+                if is_surface {
+                    surface.push(nodes.len() as u32);
+                }
+                let position = corner + offset;
+                nodes.push(Point4::new(position.x, position.y, position.z, 1.0));
+            }
+        }
+    }
+
+    (nodes, surface)
+}
+
+/// Distance between neighboring points along each axis, zero along flat axes.
+fn lattice_spacing(size: Vector3<f32>, resolution: Vector3<u32>) -> Vector3<f32> {
+    Vector3::from_fn(|axis, _| {
+        if resolution[axis] > 1 {
+            size[axis] / (resolution[axis] - 1) as f32
+        } else {
+            0.0
+        }
+    })
+}
+
+/// Mass-spring soft body stepped together with the fluid. The coupling forces are added to the
+/// fluid forces, so its step has to run between the force and the integration stage.
+pub struct SoftBody {
+    node_cnt: usize,
+    node_buffer: Arc<wgpu::Buffer>,
+    /// Node positions and velocities, carried from one step to the next
+    state_buffers: [wgpu::Id<wgpu::Buffer>; 2],
+    line_buffer: Arc<wgpu::Buffer>,
+    fluid_task: Arc<ComputeTask>,
+    node_force_task: Arc<ComputeTask>,
+    integrate_task: Arc<ComputeTask>,
+    line_task: Arc<ComputeTask>,
+}
+
+/// Constants of the fluid shared by the coupling passes.
+pub struct FluidCoupling {
+    pub ghost_particle_cnt: usize,
+    pub smoothing_radius: f32,
+    pub mass: f32,
+    pub gas_const: f32,
+    pub rest_density: f32,
+    pub viscosity: f32,
+    pub spiky_grad: f32,
+    pub visc_lap: f32,
+}
+
+/// The config and lattice resolution as simulated, flat along z in 2D.
+fn flattened(config: &SoftBodyConfig, dimensions: SimDim) -> (SoftBodyConfig, Vector3<u32>) {
+    let mut config = *config;
+    let mut resolution = Vector3::from(config.resolution).map(|n| n.max(1));
+    if dimensions == SimDim::Two {
+        resolution.z = 1;
+        config.center.z = 0.0;
+    }
+
+    (config, resolution)
+}
+
+impl SoftBody {
+    /// Bytes of the GPU buffers of a body with `config`: the node positions, velocities and
+    /// accelerations, the surface indices and the spring lines.
+    pub fn buffer_size(config: &SoftBodyConfig, dimensions: SimDim) -> u64 {
+        let (config, resolution) = flattened(config, dimensions);
+        let (nodes, surface) = lattice(&config, resolution, Vector3::zeros());
+
+        (3 * nodes.len() * std::mem::size_of::<Vector4<f32>>()
+            + surface.len() * std::mem::size_of::<u32>()
+            + 6 * nodes.len() * std::mem::size_of::<ColoredVertex>()) as u64
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        config: &SoftBodyConfig,
+        dimensions: SimDim,
+        particle_cnt: usize,
+        fluid: &FluidCoupling,
+        bbox_dimensions: Vector3<f32>,
+        gravity: Vector3<f32>,
+        damping: f32,
+        walls: bool,
+        floor: bool,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        velocities: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+        forces: &wgpu::Buffer,
+    ) -> Self {
+        let (config, resolution) = flattened(config, dimensions);
+        let (nodes, surface) = lattice(&config, resolution, bbox_dimensions);
+        let node_cnt = nodes.len();
+        let node_mass = config.density * config.size.product() / node_cnt as f32;
+
+        let node_buffer = wgpu_device.create_buffer_init(
+            &nodes,
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let velocity_buffer = wgpu_device.create_buffer_init(
+            &vec![Vector4::<f32>::zeros(); node_cnt],
+            wgpu::BufferUsages::STORAGE,
+        );
+        let acceleration_buffer = wgpu_device.create_buffer_init(
+            &vec![Vector4::<f32>::zeros(); node_cnt],
+            wgpu::BufferUsages::STORAGE,
+        );
+        let surface_buffer = wgpu_device.create_buffer_init(&surface, wgpu::BufferUsages::STORAGE);
+        let line_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Soft body line buffer"),
+            size: (6 * node_cnt * std::mem::size_of::<ColoredVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let spacing = lattice_spacing(config.size, resolution);
+        let constants = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {};\n
+             const MASS: f32 = {};\n
+             const GAS_CONST: f32 = {};\n
+             const REST_DENSITY: f32 = {};\n
+             const VISCOSITY: f32 = {};\n
+             const SPIKY_GRAD: f32 = {};\n
+             const VISC_LAP: f32 = {};\n
+             const COUPLING_STIFFNESS: f32 = {};\n
+             const NODE_MASS: f32 = {node_mass};\n
+             const STIFFNESS: f32 = {};\n
+             const SPRING_DAMPING: f32 = {};\n
+             const RESOLUTION: vec3<u32> = vec3<u32>({}u, {}u, {}u);\n
+             const SPACING: vec3<f32> = vec3<f32>({}, {}, {});\n
+             const BBOX: vec3<f32> = vec3<f32>({}, {}, {});\n
+             const G: vec3<f32> = vec3<f32>({}, {}, {});\n
+             const DAMPING: f32 = {damping};\n
+             const WALLS: bool = {walls};\n
+             const FLOOR: bool = {floor};\n",
+            fluid.ghost_particle_cnt,
+            fluid.mass,
+            fluid.gas_const,
+            fluid.rest_density,
+            fluid.viscosity,
+            fluid.spiky_grad,
+            fluid.visc_lap,
+            config.coupling_stiffness,
+            config.stiffness,
+            config.damping,
+            resolution.x,
+            resolution.y,
+            resolution.z,
+            spacing.x,
+            spacing.y,
+            spacing.z,
+            bbox_dimensions.x,
+            bbox_dimensions.y,
+            bbox_dimensions.z,
+            gravity.x,
+            gravity.y,
+            gravity.z,
+        );
+        // the force pass gets the smoothing radius from the spatial lookup
+        let smoothing_radius = format!(
+            "const SMOOTHING_RADIUS: f32 = {};\n",
+            fluid.smoothing_radius
+        );
+        let coupling = include_str!("shaders/soft_body_coupling.wgsl");
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let node_workgroup_cnt = (node_cnt as u32).div_ceil(256);
+
+        let fluid_task = Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Soft body fluid coupling",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, true),
+                storage_entry(5, true),
+                storage_entry(6, false),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: velocities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: densities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: node_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: velocity_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: surface_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: forces.as_entire_binding(),
+                },
+            ],
+            &[],
+            format!(
+                "{constants}{smoothing_radius}{coupling}{}",
+                include_str!("shaders/soft_body_fluid.wgsl")
+            )
+            .into(),
+            (
+                ((particle_cnt - fluid.ghost_particle_cnt) as u32).div_ceil(256),
+                1,
+                1,
+            ),
+        ));
+
+        let node_force_task = Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Soft body forces",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, true),
+                storage_entry(5, true),
+                storage_entry(6, true),
+                storage_entry(7, true),
+                storage_entry(8, false),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: velocities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: densities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: node_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: velocity_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: acceleration_buffer.as_entire_binding(),
+                },
+            ],
+            &[],
+            format!(
+                "{constants}{}{coupling}{}",
+                spatial_lookup.shader_source(),
+                include_str!("shaders/soft_body_forces.wgsl")
+            )
+            .into(),
+            (node_workgroup_cnt, 1, 1),
+        ));
+
+        let integrate_task = Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Soft body integration",
+            &[
+                storage_entry(0, false),
+                storage_entry(1, false),
+                storage_entry(2, true),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: node_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: velocity_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: acceleration_buffer.as_entire_binding(),
+                },
+            ],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..4,
+            }],
+            format!(
+                "{constants}{smoothing_radius}{}",
+                include_str!("shaders/soft_body_integrate.wgsl")
+            )
+            .into(),
+            (node_workgroup_cnt, 1, 1),
+        ));
+
+        let line_task = Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Soft body lines",
+            &[storage_entry(0, true), storage_entry(1, false)],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: node_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: line_buffer.as_entire_binding(),
+                },
+            ],
+            &[],
+            format!(
+                "
+                 const RESOLUTION: vec3<u32> = vec3<u32>({}u, {}u, {}u);\n
+                 const OFFSET: vec3<f32> = vec3<f32>({}, {}, {});\n
+                 {}",
+                resolution.x,
+                resolution.y,
+                resolution.z,
+                -bbox_dimensions.x / 2.0,
+                -bbox_dimensions.y / 2.0,
+                -bbox_dimensions.z / 2.0,
+                include_str!("shaders/soft_body_lines.wgsl")
+            )
+            .into(),
+            (node_workgroup_cnt, 1, 1),
+        ));
+
+        Self {
+            node_cnt,
+            state_buffers: [node_buffer.global_id(), velocity_buffer.global_id()],
+            node_buffer,
+            line_buffer,
+            fluid_task,
+            node_force_task,
+            integrate_task,
+            line_task,
+        }
+    }
+
+    /// Adds the coupling forces to the fluid and advances the body over `dt`. Both sides of the
+    /// coupling are computed from the state before the step.
+    pub fn step_fn(&self, dt: f32) -> GenericRequest {
+        let fluid_task = self.fluid_task.clone();
+        let node_force_task = self.node_force_task.clone();
+        let integrate_task = self.integrate_task.clone();
+
+        Box::new(move |encoder, _| {
+            node_force_task.execute(encoder, &[]);
+            fluid_task.execute(encoder, &[]);
+            integrate_task.execute(encoder, bytemuck::bytes_of(&dt));
+        })
+    }
+
+    pub fn update_fn(&self) -> GenericRequest {
+        let line_task = self.line_task.clone();
+
+        Box::new(move |encoder, _| {
+            line_task.execute(encoder, &[]);
+        })
+    }
+
+    /// Edges of the lattice along the axes as a line list, written by `update_fn`.
+    pub fn geometry(&self) -> Geometry {
+        Geometry::Array {
+            vertex_buffer: self.line_buffer.clone(),
+            vertex_cnt: 6 * self.node_cnt,
+        }
+    }
+
+    pub fn state_buffers(&self) -> [wgpu::Id<wgpu::Buffer>; 2] {
+        self.state_buffers
+    }
+
+    /// Positions of the mass points in simulation space.
+    pub fn nodes(&self) -> &wgpu::Buffer {
+        &self.node_buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lattice_surface() {
+        let config = SoftBodyConfig {
+            center: Point3::origin(),
+            size: Vector3::new(3.0, 3.0, 3.0),
+            ..Default::default()
+        };
+
+        let (nodes, surface) = lattice(&config, Vector3::new(4, 4, 4), Vector3::new(4.0, 4.0, 4.0));
+
+        assert_eq!(nodes.len(), 64);
+        // all but the 2 x 2 x 2 interior points
+        assert_eq!(surface.len(), 56);
+        assert_eq!(nodes[0], Point4::new(0.5, 0.5, 0.5, 1.0));
+        assert_eq!(nodes[63], Point4::new(3.5, 3.5, 3.5, 1.0));
+    }
+
+    #[test]
+    fn flat_lattice_surface_is_the_outline() {
+        let config = SoftBodyConfig::default();
+
+        let (nodes, surface) =
+            lattice(&config, Vector3::new(5, 3, 1), Vector3::new(14.0, 6.0, 4.0));
+
+        assert_eq!(nodes.len(), 15);
+        assert_eq!(surface.len(), 12);
+    }
+}
@@ -1,40 +1,181 @@
-use std::{num::NonZeroU32, rc::Rc};
+use std::{num::NonZeroU32, sync::Arc};
 
 use nalgebra::Vector3;
 use pollster::FutureExt;
+use serde::{Deserialize, Serialize};
 use wgpu_sort::{utils::guess_workgroup_size, GPUSorter, SortBuffers};
 
-use crate::{graphics::RenderEngine, ComputeTask, WgpuDevice};
+use crate::{
+    graphics::{
+        render_engine::{FrameStage, GenericRequest},
+        RenderEngine,
+    },
+    ComputeTask, WgpuDevice,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpatialLookupBackend {
+    /// Dense grid for simulations inside of a box, hash table otherwise
+    #[default]
+    Auto,
+    DenseGrid,
+    HashTable,
+}
+
+/// Keeps the spatial lookup for several steps instead of sorting the particles every step.
+/// The cells grow by the skin, so neighbors are still found while no particle has moved
+/// further than the skin since the last rebuild.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NeighborCaching {
+    /// Steps between two rebuilds at most
+    pub rebuild_interval: u32,
+    /// Added to the cell size, in smoothing radii
+    pub skin: f32,
+}
+
+impl Default for NeighborCaching {
+    fn default() -> Self {
+        Self {
+            rebuild_interval: 4,
+            skin: 0.2,
+        }
+    }
+}
+
+/// How cells are mapped to entries of the spatial lookup index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpatialGrid {
+    /// One index entry per cell of a grid starting at the origin. Particles have to stay
+    /// inside of the grid.
+    Dense { cell_cnt: Vector3<u32> },
+    /// Cells are hashed into an open addressing table, so the domain is unbounded.
+    Hashed { table_size: u32 },
+}
+
+impl SpatialGrid {
+    /// Bytes of the buffer that maps cells to their first sorted particle.
+    pub fn index_size(&self) -> u64 {
+        match self {
+            SpatialGrid::Dense { cell_cnt } => {
+                (cell_cnt.x * cell_cnt.y * cell_cnt.z) as u64 * std::mem::size_of::<u32>() as u64
+            }
+            SpatialGrid::Hashed { table_size } => {
+                *table_size as u64 * 2 * std::mem::size_of::<u32>() as u64
+            }
+        }
+    }
+
+    /// WGSL constants and functions for mapping positions to cells and cells to keys.
+    fn cell_source(&self, smoothing_radius: f32, cell_size: f32) -> String {
+        let common = format!(
+            "const SMOOTHING_RADIUS: f32 = {smoothing_radius};\n
+             const CELL_SIZE: f32 = {cell_size};\n
+             fn cell_of(pos: vec3<f32>) -> vec3<i32> {{
+                 return vec3<i32>(floor(pos / CELL_SIZE));
+             }}\n"
+        );
+
+        match self {
+            SpatialGrid::Dense { cell_cnt } => format!(
+                "{common}
+                 const HASHED: bool = false;\n
+                 const CELL_CNT: vec3<u32> = vec3<u32>({}, {}, {});\n
+                 alias SpatialIndexEntry = u32;\n
+                 fn is_valid_cell(cell: vec3<i32>) -> bool {{
+                     return all(cell >= vec3<i32>(0)) && all(vec3<u32>(cell) < CELL_CNT);
+                 }}
+                 fn cell_key(cell: vec3<i32>) -> u32 {{
+                     let c = vec3<u32>(cell);
+                     return c.z + c.y * CELL_CNT.z + c.x * CELL_CNT.y * CELL_CNT.z;
+                 }}\n",
+                cell_cnt.x, cell_cnt.y, cell_cnt.z,
+            ),
+            SpatialGrid::Hashed { table_size } => format!(
+                "{common}
+                 const HASHED: bool = true;\n
+                 const TABLE_SIZE: u32 = {table_size}u;\n
+                 alias SpatialIndexEntry = vec2<u32>;\n
+                 fn is_valid_cell(cell: vec3<i32>) -> bool {{
+                     return true;
+                 }}
+                 // keys stay below u32::MAX so that key + 1 can mark occupied table slots
+                 fn cell_key(cell: vec3<i32>) -> u32 {{
+                     let c = bitcast<vec3<u32>>(cell);
+                     return ((c.x * 73856093u) ^ (c.y * 19349663u) ^ (c.z * 83492791u)) % 4294967295u;
+                 }}\n"
+            ),
+        }
+    }
+
+    /// WGSL function returning the first sorted particle of the cell with the given key,
+    /// expects `spatial_lookup_index` to be bound as `array<SpatialIndexEntry>`.
+    fn index_source(&self) -> &'static str {
+        match self {
+            SpatialGrid::Dense { .. } => {
+                "fn cell_start(key: u32) -> u32 {
+                     return spatial_lookup_index[key];
+                 }\n"
+            }
+            SpatialGrid::Hashed { .. } => {
+                "fn cell_start(key: u32) -> u32 {
+                     var slot = key % TABLE_SIZE;
+                     for (var i = 0u; i < TABLE_SIZE; i += 1u) {
+                         let entry = spatial_lookup_index[slot];
+                         if (entry.x == key + 1u) {
+                             return entry.y;
+                         }
+                         if (entry.x == 0u) {
+                             break;
+                         }
+                         slot = (slot + 1u) % TABLE_SIZE;
+                     }
+                     return 0xffffffffu;
+                 }\n"
+            }
+        }
+    }
+}
 
 pub struct SpatialLookup {
-    sort: Rc<GPUSorter>,
-    sort_buffers: Rc<SortBuffers>,
+    grid: SpatialGrid,
+    smoothing_radius: f32,
+    cell_size: f32,
+
+    sort: Arc<GPUSorter>,
+    sort_buffers: Arc<SortBuffers>,
 
-    spatial_lookup_task: Rc<ComputeTask>,
-    spatial_lookup_index: wgpu::Buffer,
-    spatial_lookup_index_task: Rc<ComputeTask>,
+    spatial_lookup_task: Arc<ComputeTask>,
+    spatial_lookup_index: Arc<wgpu::Buffer>,
+    spatial_lookup_index_task: Arc<ComputeTask>,
 }
 
 impl SpatialLookup {
+    /// `cell_size` is at least the smoothing radius, larger cells keep finding the neighbors
+    /// of particles that moved since the last update.
     pub fn new(
         wgpu_device: &WgpuDevice,
         particle_cnt: usize,
         smoothing_radius: f32,
-        cell_cnt: Vector3<u32>,
+        cell_size: f32,
+        grid: SpatialGrid,
         position_buffer: &wgpu::Buffer,
     ) -> Self {
-        let spatial_lookup_index = wgpu_device.device.create_buffer(&wgpu::BufferDescriptor {
+        let spatial_lookup_index = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Spatial index buffer"),
-            size: (cell_cnt.x * cell_cnt.y * cell_cnt.z * std::mem::size_of::<u32>() as u32) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            size: grid.index_size(),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
 
         let subgroup_size = guess_workgroup_size(&wgpu_device.device, &wgpu_device.queue)
             .block_on()
             .unwrap();
-        let sort = Rc::new(GPUSorter::new(&wgpu_device.device, subgroup_size));
-        let sort_buffers = Rc::new(sort.create_sort_buffers(
+        let sort = Arc::new(GPUSorter::new(&wgpu_device.device, subgroup_size));
+        let sort_buffers = Arc::new(sort.create_sort_buffers(
             &wgpu_device.device,
             NonZeroU32::new(particle_cnt as u32).unwrap(),
         ));
@@ -42,21 +183,26 @@ impl SpatialLookup {
         let spatial_lookup_task = SpatialLookup::create_spatial_lookup_fill_task(
             particle_cnt,
             smoothing_radius,
-            cell_cnt,
-            &position_buffer,
-            &sort_buffers.keys(),
-            &sort_buffers.values(),
+            cell_size,
+            grid,
+            position_buffer,
+            sort_buffers.keys(),
+            sort_buffers.values(),
             wgpu_device,
         );
 
         let spatial_lookup_index_task = SpatialLookup::create_spatial_lookup_index_task(
             wgpu_device,
-            &sort_buffers.keys(),
+            grid,
+            sort_buffers.keys(),
             &spatial_lookup_index,
             particle_cnt,
         );
 
         Self {
+            grid,
+            smoothing_radius,
+            cell_size,
             sort,
             sort_buffers,
             spatial_lookup_task,
@@ -66,30 +212,57 @@ impl SpatialLookup {
     }
 
     pub fn update(&self, render_engine: &mut RenderEngine) {
-        render_engine.submit_generic_request(self.update_fn());
+        render_engine.submit_generic_request(FrameStage::Simulation, self.update_fn());
     }
 
     pub fn keys(&self) -> &wgpu::Buffer {
-        &self.sort_buffers.keys()
+        self.sort_buffers.keys()
     }
 
     pub fn vals(&self) -> &wgpu::Buffer {
-        &self.sort_buffers.values()
+        self.sort_buffers.values()
     }
 
     pub fn index(&self) -> &wgpu::Buffer {
         &self.spatial_lookup_index
     }
 
-    fn update_fn(&self) -> Box<dyn Fn(&mut wgpu::CommandEncoder, &wgpu::Queue) -> ()> {
+    pub fn grid(&self) -> SpatialGrid {
+        self.grid
+    }
+
+    /// The radix sorter is independent of the buffers it sorts and can be shared.
+    pub fn sorter(&self) -> Arc<GPUSorter> {
+        self.sort.clone()
+    }
+
+    /// WGSL prelude for shaders iterating over neighboring cells. Defines `SMOOTHING_RADIUS`,
+    /// `CELL_SIZE`, `HASHED`, `SpatialIndexEntry`, `cell_of`, `is_valid_cell`, `cell_key` and `cell_start`.
+    /// The shader has to bind the index buffer as `spatial_lookup_index`.
+    pub fn shader_source(&self) -> String {
+        format!(
+            "{}{}",
+            self.grid.cell_source(self.smoothing_radius, self.cell_size),
+            self.grid.index_source()
+        )
+    }
+
+    pub fn update_fn(&self) -> GenericRequest {
         let spatial_lookup_task = self.spatial_lookup_task.clone();
         let sort = self.sort.clone();
         let sort_buffers = self.sort_buffers.clone();
         let spatial_lookup_index_task = self.spatial_lookup_index_task.clone();
+        let spatial_lookup_index = self.spatial_lookup_index.clone();
+        let grid = self.grid;
 
         Box::new(move |encoder, queue| {
             spatial_lookup_task.execute(encoder, &[]);
             sort.sort(encoder, queue, &sort_buffers, None);
+            // stale dense entries are harmless because the keys they point to no longer
+            // match, stale hash table slots would fill up the table
+            if let SpatialGrid::Hashed { .. } = grid {
+                encoder.clear_buffer(&spatial_lookup_index, 0, None);
+            }
             spatial_lookup_index_task.execute(encoder, &[]);
         })
     }
@@ -97,29 +270,24 @@ impl SpatialLookup {
     fn create_spatial_lookup_fill_task(
         particle_cnt: usize,
         smoothing_radius: f32,
-        cell_cnt: Vector3<u32>,
+        cell_size: f32,
+        grid: SpatialGrid,
         position_buffer: &wgpu::Buffer,
         spatial_lookup_keys: &wgpu::Buffer,
         spatial_lookup_vals: &wgpu::Buffer,
         wgpu_device: &WgpuDevice,
-    ) -> Rc<ComputeTask> {
-        let mut workgroup_cnt = particle_cnt as u32 / 256;
-        if particle_cnt % 256 != 0 {
-            workgroup_cnt += 1;
-        }
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = (particle_cnt as u32).div_ceil(256);
 
         let shader_source = format!(
             "const PARTICLE_CNT: u32 = {particle_cnt};\n
-             const SMOOTHING_RADIUS: f32 = {smoothing_radius};\n
-             const CELL_CNT: vec3<u32> = vec3<u32>({}, {}, {});\n 
+             {}
              {}",
-            cell_cnt.x,
-            cell_cnt.y,
-            cell_cnt.z,
+            grid.cell_source(smoothing_radius, cell_size),
             include_str!("shaders/fill_spatial_lookup.wgsl")
         );
 
-        let spatial_lookup_task = Rc::new(ComputeTask::new(
+        let spatial_lookup_task = Arc::new(ComputeTask::new(
             wgpu_device,
             "Spatial lookup",
             &[
@@ -178,22 +346,28 @@ impl SpatialLookup {
 
     fn create_spatial_lookup_index_task(
         wgpu_device: &WgpuDevice,
+        grid: SpatialGrid,
         spatial_lookup_keys: &wgpu::Buffer,
         spatial_lookup_index: &wgpu::Buffer,
         particle_cnt: usize,
-    ) -> Rc<ComputeTask> {
-        let mut workgroup_cnt = particle_cnt as u32 / 256;
-        if particle_cnt % 256 != 0 {
-            workgroup_cnt += 1;
-        }
-
-        let shader_source = format!(
-            "const PARTICLE_CNT: u32 = {particle_cnt};\n
-             {}",
-            include_str!("shaders/spatial_lookup_index.wgsl")
-        );
-
-        let spatial_lookup_index_task = Rc::new(ComputeTask::new(
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = (particle_cnt as u32).div_ceil(256);
+
+        let shader_source = match grid {
+            SpatialGrid::Dense { .. } => format!(
+                "const PARTICLE_CNT: u32 = {particle_cnt};\n
+                 {}",
+                include_str!("shaders/spatial_lookup_index.wgsl")
+            ),
+            SpatialGrid::Hashed { table_size } => format!(
+                "const PARTICLE_CNT: u32 = {particle_cnt};\n
+                 const TABLE_SIZE: u32 = {table_size}u;\n
+                 {}",
+                include_str!("shaders/spatial_lookup_hash_index.wgsl")
+            ),
+        };
+
+        let spatial_lookup_index_task = Arc::new(ComputeTask::new(
             wgpu_device,
             "Spatial lookup index",
             &[
@@ -299,7 +473,8 @@ mod tests {
         let spatial_lookup_task = SpatialLookup::create_spatial_lookup_fill_task(
             particle_cnt,
             smoothing_radius,
-            cell_cnt,
+            smoothing_radius,
+            SpatialGrid::Dense { cell_cnt },
             &position_buffer,
             &spatial_lookup_keys,
             &spatial_lookup_vals,
@@ -345,7 +520,7 @@ mod tests {
             0,
             spatial_lookup_keys.size(),
         );
-        wgpu_device.queue.submit(Some(encoder.finish()));
+        wgpu_device.submit(encoder);
 
         let keys = read_buffer::<u32>(&wgpu_device, &staging_buffer_keys);
         let vals = read_buffer::<u32>(&wgpu_device, &staging_buffer_vals);
@@ -403,7 +578,8 @@ mod tests {
             &wgpu_device,
             particle_cnt,
             smoothing_radius,
-            cell_cnt,
+            smoothing_radius,
+            SpatialGrid::Dense { cell_cnt },
             &position_buffer,
         );
 
@@ -470,7 +646,7 @@ mod tests {
             staging_buffer_c.size(),
         );
 
-        wgpu_device.queue.submit(Some(encoder.finish()));
+        wgpu_device.submit(encoder);
         wgpu_device.device.poll(wgpu::Maintain::Wait);
 
         let a = read_buffer::<u32>(&wgpu_device, &staging_buffer_a);
@@ -482,4 +658,326 @@ mod tests {
         println!("{:?}", b);
         println!("{:?}", c);
     }
+
+    const NEIGHBOR_SHADER: &str = "
+        @group(0) @binding(0) var<storage, read> particle_positions: array<vec3<f32>>;
+        @group(0) @binding(1) var<storage, read> spatial_lookup_keys: array<u32>;
+        @group(0) @binding(2) var<storage, read> spatial_lookup_vals: array<u32>;
+        @group(0) @binding(3) var<storage, read> spatial_lookup_index: array<SpatialIndexEntry>;
+        @group(0) @binding(4) var<storage, read_write> neighbors: array<vec2<u32>>;
+
+        @compute @workgroup_size(256)
+        fn main(@builtin(global_invocation_id) global_id: vec3<u32>) {
+            let gid = global_id.x;
+            if (gid >= arrayLength(&particle_positions)) {
+                return;
+            }
+
+            let pos = particle_positions[gid];
+            let cell = cell_of(pos);
+            var cnt = 0u;
+            var sum = 0u;
+
+            for (var x = -1; x <= 1; x += 1) {
+                for (var y = -1; y <= 1; y += 1) {
+                    for (var z = -1; z <= 1; z += 1) {
+                        let neighbor_cell = cell + vec3<i32>(x, y, z);
+                        if (!is_valid_cell(neighbor_cell)) {
+                            continue;
+                        }
+
+                        let key = cell_key(neighbor_cell);
+                        for (var l = cell_start(key); l < arrayLength(&particle_positions) && spatial_lookup_keys[l] == key; l += 1u) {
+                            let ind = spatial_lookup_vals[l];
+                            let neighbor_pos = particle_positions[ind];
+                            if (HASHED && any(cell_of(neighbor_pos) != neighbor_cell)) {
+                                continue;
+                            }
+
+                            if (distance(pos, neighbor_pos) <= SMOOTHING_RADIUS) {
+                                cnt += 1u;
+                                sum += ind;
+                            }
+                        }
+                    }
+                }
+            }
+
+            neighbors[gid] = vec2<u32>(cnt, sum);
+        }
+    ";
+
+    /// Returns the number of neighbors and the sum of their indices for every particle at
+    /// `moved_positions`, as found through the spatial lookup updated with `positions`.
+    fn gpu_neighbors(
+        wgpu_device: &WgpuDevice,
+        grid: SpatialGrid,
+        smoothing_radius: f32,
+        cell_size: f32,
+        positions: &[Point4<f32>],
+        moved_positions: &[Point4<f32>],
+    ) -> Vec<[u32; 2]> {
+        let particle_cnt = positions.len();
+        let position_buffer = wgpu_device.create_buffer_init(
+            positions,
+            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+        );
+        let moved_position_buffer =
+            wgpu_device.create_buffer_init(moved_positions, wgpu::BufferUsages::COPY_SRC);
+
+        let spatial_lookup = SpatialLookup::new(
+            wgpu_device,
+            particle_cnt,
+            smoothing_radius,
+            cell_size,
+            grid,
+            &position_buffer,
+        );
+
+        let neighbor_buffer = wgpu_device.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Neighbor buffer"),
+            size: (particle_cnt * 2 * std::mem::size_of::<u32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let staging_buffer = wgpu_device.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer"),
+            size: neighbor_buffer.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let neighbor_task = ComputeTask::new(
+            wgpu_device,
+            "Neighbors",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, false),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: position_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: neighbor_buffer.as_entire_binding(),
+                },
+            ],
+            &[],
+            format!("{}{}", spatial_lookup.shader_source(), NEIGHBOR_SHADER).into(),
+            ((particle_cnt as u32).div_ceil(256), 1, 1),
+        );
+
+        let mut encoder =
+            wgpu_device
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Command Encoder"),
+                });
+
+        spatial_lookup.update_fn()(&mut encoder, &wgpu_device.queue);
+        encoder.copy_buffer_to_buffer(
+            &moved_position_buffer,
+            0,
+            &position_buffer,
+            0,
+            position_buffer.size(),
+        );
+        neighbor_task.execute(&mut encoder, &[]);
+        encoder.copy_buffer_to_buffer(
+            &neighbor_buffer,
+            0,
+            &staging_buffer,
+            0,
+            neighbor_buffer.size(),
+        );
+
+        wgpu_device.submit(encoder);
+        wgpu_device.device.poll(wgpu::Maintain::Wait);
+
+        read_buffer::<[u32; 2]>(wgpu_device, &staging_buffer)
+    }
+
+    #[test]
+    fn hash_table_matches_dense_grid() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+
+        let particle_cnt = 2000;
+        let smoothing_radius = 0.1;
+        let bbox_dimensions = Vector3::new(1.0, 1.0, 1.0);
+        let cell_cnt = Vector3::new(
+            (bbox_dimensions.x / smoothing_radius).ceil() as u32,
+            (bbox_dimensions.y / smoothing_radius).ceil() as u32,
+            (bbox_dimensions.z / smoothing_radius).ceil() as u32,
+        );
+
+        let mut rng = rand::thread_rng();
+        let positions: Vec<Point4<f32>> = (0..particle_cnt)
+            .map(|_| {
+                Point4::new(
+                    rng.gen_range(0.0..bbox_dimensions.x),
+                    rng.gen_range(0.0..bbox_dimensions.y),
+                    rng.gen_range(0.0..bbox_dimensions.z),
+                    1.0,
+                )
+            })
+            .collect();
+
+        let expected: Vec<[u32; 2]> = positions
+            .iter()
+            .map(|a| {
+                positions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| (a.xyz() - b.xyz()).norm() <= smoothing_radius)
+                    .fold([0, 0], |[cnt, sum], (j, _)| [cnt + 1, sum + j as u32])
+            })
+            .collect();
+
+        let dense = gpu_neighbors(
+            &wgpu_device,
+            SpatialGrid::Dense { cell_cnt },
+            smoothing_radius,
+            smoothing_radius,
+            &positions,
+            &positions,
+        );
+        // a small table forces plenty of collisions and long probe sequences
+        let hashed = gpu_neighbors(
+            &wgpu_device,
+            SpatialGrid::Hashed { table_size: 1024 },
+            smoothing_radius,
+            smoothing_radius,
+            &positions,
+            &positions,
+        );
+
+        assert_eq!(dense, expected);
+        assert_eq!(hashed, expected);
+    }
+
+    #[test]
+    fn hash_table_handles_negative_coordinates() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+
+        let particle_cnt = 1000;
+        let smoothing_radius = 0.25;
+
+        let mut rng = rand::thread_rng();
+        let positions: Vec<Point4<f32>> = (0..particle_cnt)
+            .map(|_| {
+                Point4::new(
+                    rng.gen_range(-2.0..2.0),
+                    rng.gen_range(-2.0..2.0),
+                    rng.gen_range(-2.0..2.0),
+                    1.0,
+                )
+            })
+            .collect();
+
+        let expected: Vec<[u32; 2]> = positions
+            .iter()
+            .map(|a| {
+                positions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| (a.xyz() - b.xyz()).norm() <= smoothing_radius)
+                    .fold([0, 0], |[cnt, sum], (j, _)| [cnt + 1, sum + j as u32])
+            })
+            .collect();
+
+        let hashed = gpu_neighbors(
+            &wgpu_device,
+            SpatialGrid::Hashed { table_size: 2048 },
+            smoothing_radius,
+            smoothing_radius,
+            &positions,
+            &positions,
+        );
+
+        assert_eq!(hashed, expected);
+    }
+
+    #[test]
+    fn stale_dense_grid_finds_neighbors_within_skin() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+
+        let particle_cnt = 2000;
+        let smoothing_radius = 0.1;
+        let skin = 0.05;
+        let cell_size = smoothing_radius + skin;
+        let cell_cnt = Vector3::repeat((1.0 / cell_size).ceil() as u32);
+
+        let mut rng = rand::thread_rng();
+        let positions: Vec<Point4<f32>> = (0..particle_cnt)
+            .map(|_| {
+                Point4::new(
+                    rng.gen_range(0.1..0.9),
+                    rng.gen_range(0.1..0.9),
+                    rng.gen_range(0.1..0.9),
+                    1.0,
+                )
+            })
+            .collect();
+        // every particle moves by less than the skin after the lookup was updated
+        let moved_positions: Vec<Point4<f32>> = positions
+            .iter()
+            .map(|p| {
+                let direction =
+                    Vector3::new(rng.gen::<f32>(), rng.gen(), rng.gen()).add_scalar(-0.5);
+                let offset = direction.normalize() * rng.gen_range(0.0..skin * 0.99);
+                Point4::new(p.x + offset.x, p.y + offset.y, p.z + offset.z, 1.0)
+            })
+            .collect();
+
+        let expected: Vec<[u32; 2]> = moved_positions
+            .iter()
+            .map(|a| {
+                moved_positions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| (a.xyz() - b.xyz()).norm() <= smoothing_radius)
+                    .fold([0, 0], |[cnt, sum], (j, _)| [cnt + 1, sum + j as u32])
+            })
+            .collect();
+
+        let dense = gpu_neighbors(
+            &wgpu_device,
+            SpatialGrid::Dense { cell_cnt },
+            smoothing_radius,
+            cell_size,
+            &positions,
+            &moved_positions,
+        );
+
+        assert_eq!(dense, expected);
+    }
 }
@@ -1,4 +1,4 @@
-use crate::WgpuDevice;
+use crate::{FluidSimulation, WgpuDevice};
 
 pub fn read_buffer<T: bytemuck::Pod>(wgpu_device: &WgpuDevice, buffer: &wgpu::Buffer) -> Vec<T> {
     let buffer_slice = buffer.slice(..);
@@ -18,4 +18,22 @@ pub fn read_buffer<T: bytemuck::Pod>(wgpu_device: &WgpuDevice, buffer: &wgpu::Bu
     buffer.unmap();
 
     result
-}
\ No newline at end of file
+}
+
+/// Records commands into a new encoder and submits it.
+pub fn submit(wgpu_device: &WgpuDevice, record: impl FnOnce(&mut wgpu::CommandEncoder)) {
+    let mut encoder = wgpu_device
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    record(&mut encoder);
+    wgpu_device.submit(encoder);
+}
+
+/// Steps `fluid_sim` `step_cnt` times by `dt`, every step submitted on its own.
+pub fn step(wgpu_device: &WgpuDevice, fluid_sim: &FluidSimulation, dt: f32, step_cnt: usize) {
+    for _ in 0..step_cnt {
+        submit(wgpu_device, |encoder| {
+            fluid_sim.step_fn(dt)(encoder, &wgpu_device.queue)
+        });
+    }
+}
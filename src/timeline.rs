@@ -0,0 +1,174 @@
+use nalgebra::{Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{debris::DebrisConfig, dye::DyeEmitter};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TimelineAction {
+    /// Starts or moves the dye emitter, `None` stops it
+    DyeEmitter {
+        emitter: Option<DyeEmitter>,
+    },
+    /// Starts or changes the debris spawning, `None` stops it
+    Debris {
+        debris: Option<DebrisConfig>,
+    },
+    /// Rebuilds the simulation, the particles keep moving where they are
+    Gravity {
+        gravity: Vector3<f32>,
+    },
+    /// Places the soft body at a new center, it restarts from its rest shape
+    MoveObstacle {
+        center: Point3<f32>,
+    },
+    /// Records a clip of this many seconds
+    Record {
+        duration: f32,
+    },
+    Pause,
+}
+
+impl TimelineAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimelineAction::DyeEmitter { emitter: Some(_) } => "Start dye emitter",
+            TimelineAction::DyeEmitter { emitter: None } => "Stop dye emitter",
+            TimelineAction::Debris { debris: Some(_) } => "Start debris",
+            TimelineAction::Debris { debris: None } => "Stop debris",
+            TimelineAction::Gravity { .. } => "Change gravity",
+            TimelineAction::MoveObstacle { .. } => "Move obstacle",
+            TimelineAction::Record { .. } => "Record",
+            TimelineAction::Pause => "Pause",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    /// Simulation time in seconds
+    pub time: f32,
+    pub action: TimelineAction,
+}
+
+/// Events run once the simulation time passes them. They are sorted when the timeline is
+/// started, so the config file can list them in any order.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timeline {
+    pub events: Vec<TimelineEvent>,
+}
+
+impl Timeline {
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Time of the last event.
+    pub fn duration(&self) -> f32 {
+        self.events
+            .iter()
+            .map(|event| event.time)
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Position of the simulation on a timeline.
+pub struct TimelinePlayer {
+    timeline: Timeline,
+    /// Index of the first event that hasn't run yet
+    next: usize,
+    last_time: f32,
+}
+
+impl TimelinePlayer {
+    pub fn new(mut timeline: Timeline) -> Self {
+        timeline.events.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Self {
+            timeline,
+            next: 0,
+            last_time: 0.0,
+        }
+    }
+
+    pub fn timeline(&self) -> &Timeline {
+        &self.timeline
+    }
+
+    /// Whether the event at `index` of the sorted timeline already ran.
+    pub fn is_done(&self, index: usize) -> bool {
+        index < self.next
+    }
+
+    /// Actions of the events passed since the last call. When the time jumps back, e.g. after
+    /// a reset, the events after it run again.
+    pub fn advance(&mut self, time: f32) -> Vec<TimelineAction> {
+        if time < self.last_time {
+            self.next = self
+                .timeline
+                .events
+                .partition_point(|event| event.time < time);
+        }
+        self.last_time = time;
+
+        let first = self.next;
+        while self
+            .timeline
+            .events
+            .get(self.next)
+            .is_some_and(|event| event.time <= time)
+        {
+            self.next += 1;
+        }
+
+        self.timeline.events[first..self.next]
+            .iter()
+            .map(|event| event.action.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_run_once_in_order_and_again_after_a_reset() {
+        let timeline: Timeline = toml::from_str(
+            "
+            [[events]]
+            time = 5.0
+            action = { type = \"gravity\", gravity = [0.0, -2.0, 0.0] }
+
+            [[events]]
+            time = 2.0
+            action = { type = \"dye_emitter\", emitter = { center = [0.0, 1.0, 0.0], radius = 0.5 } }
+
+            [[events]]
+            time = 8.0
+            action = { type = \"pause\" }
+            ",
+        )
+        .unwrap();
+        assert_eq!(timeline.duration(), 8.0);
+
+        let mut player = TimelinePlayer::new(timeline);
+        assert!(player.advance(1.0).is_empty());
+        let actions = player.advance(6.0);
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[0].name(), "Start dye emitter");
+        assert_eq!(
+            actions[1],
+            TimelineAction::Gravity {
+                gravity: Vector3::new(0.0, -2.0, 0.0)
+            }
+        );
+        assert!(player.advance(7.0).is_empty());
+        assert!(player.is_done(1) && !player.is_done(2));
+
+        // reset
+        assert!(player.advance(0.0).is_empty());
+        assert_eq!(player.advance(2.0).len(), 1);
+    }
+}
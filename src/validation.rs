@@ -0,0 +1,556 @@
+use std::{
+    f32::consts::PI,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use nalgebra::{Point2, Point3, Point4, Vector3, Vector4};
+
+use crate::{
+    config::AdapterConfig,
+    fluid_simulation::{
+        DomainBoundary, FluidPreset, FluidSimulationConfig, InitialLayout, ParticleSnapshot,
+        SimDim, PARTICLE_SPACING, STANDARD_GRAVITY,
+    },
+    probes::{ProbeConfig, ProbeShape},
+    wave_gauges::WaveGaugeConfig,
+    FluidSimulation, SplooshError, WgpuDevice,
+};
+
+/// Surge front of a collapsing water column twice as high as it is wide, as (T, Z) with
+/// T = t sqrt(2g / a) and Z = x / a for a column of width a. Measured by Martin and Moyce
+/// (1952), as tabulated in many SPH papers.
+const MARTIN_MOYCE_FRONT: [(f32, f32); 14] = [
+    (0.41, 1.11),
+    (0.84, 1.22),
+    (1.19, 1.44),
+    (1.43, 1.67),
+    (1.63, 1.89),
+    (1.83, 2.11),
+    (1.98, 2.33),
+    (2.20, 2.56),
+    (2.32, 2.78),
+    (2.51, 3.00),
+    (2.65, 3.22),
+    (2.83, 3.44),
+    (2.98, 3.67),
+    (3.11, 3.89),
+];
+
+/// Fraction of the fluid particles allowed ahead of the measured dam break front, so that a
+/// few splashing particles don't count as the front.
+const FRONT_OUTLIERS: f32 = 0.002;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ValidationCase {
+    /// Pressure over depth in a tank of water at rest
+    Hydrostatic,
+    /// Surge front of a collapsing water column in 2D
+    DamBreak,
+    /// Frequency of the first sloshing mode of a rectangular tank
+    Sloshing,
+}
+
+impl ValidationCase {
+    pub const ALL: [ValidationCase; 3] = [
+        ValidationCase::Hydrostatic,
+        ValidationCase::DamBreak,
+        ValidationCase::Sloshing,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValidationCase::Hydrostatic => "hydrostatic",
+            ValidationCase::DamBreak => "dam_break",
+            ValidationCase::Sloshing => "sloshing",
+        }
+    }
+}
+
+pub struct ValidationOptions {
+    pub cases: Vec<ValidationCase>,
+    /// Particles across the water depth of every case
+    pub resolution: u32,
+    /// Directory the measured and reference curves are written to as CSV
+    pub export_dir: Option<PathBuf>,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        Self {
+            cases: ValidationCase::ALL.to_vec(),
+            resolution: 30,
+            export_dir: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationResult {
+    pub case: ValidationCase,
+    /// What `error` measures
+    pub metric: &'static str,
+    pub error: f32,
+    pub tolerance: f32,
+    /// Name of the first value of the `curve` points
+    pub abscissa: &'static str,
+    /// Measured and reference value at each point of the comparison
+    pub curve: Vec<(f32, f32, f32)>,
+}
+
+impl ValidationResult {
+    pub fn passed(&self) -> bool {
+        self.error <= self.tolerance
+    }
+}
+
+/// Writes the curve of `result` with a row of `abscissa,measured,reference` per point.
+pub fn export_curve(path: &Path, result: &ValidationResult) -> Result<(), SplooshError> {
+    let mut file = std::io::BufWriter::new(File::create(path)?);
+    writeln!(file, "{},measured,reference", result.abscissa)?;
+    for (x, measured, reference) in &result.curve {
+        writeln!(file, "{x},{measured},{reference}")?;
+    }
+
+    Ok(())
+}
+
+pub async fn run_validation(
+    adapter: &AdapterConfig,
+    options: &ValidationOptions,
+) -> Result<Vec<ValidationResult>, SplooshError> {
+    let wgpu_device = WgpuDevice::with_adapter_config(adapter).await?;
+    if let Some(export_dir) = &options.export_dir {
+        std::fs::create_dir_all(export_dir)?;
+    }
+
+    let mut results = Vec::with_capacity(options.cases.len());
+    for &case in &options.cases {
+        let _span = tracing::info_span!("validation", case = case.name()).entered();
+        let result = match case {
+            ValidationCase::Hydrostatic => hydrostatic(&wgpu_device, options.resolution)?,
+            ValidationCase::DamBreak => dam_break(&wgpu_device, options.resolution)?,
+            ValidationCase::Sloshing => sloshing(&wgpu_device, options.resolution)?,
+        };
+
+        if let Some(export_dir) = &options.export_dir {
+            export_curve(
+                &export_dir.join(format!("validation_{}.csv", case.name())),
+                &result,
+            )?;
+        }
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// 2D water in a closed `width` by `height` tank with particles `spacing` apart.
+fn tank_config(width: f32, height: f32, spacing: f32) -> FluidSimulationConfig {
+    let mut config = FluidSimulationConfig {
+        smoothing_radius: spacing / PARTICLE_SPACING,
+        gravity: Vector3::new(0.0, -STANDARD_GRAVITY, 0.0),
+        bbox_dimensions: Vector3::new(width, height, 1.0),
+        dimensions: SimDim::Two,
+        boundary: DomainBoundary::Box,
+        initial_layout: InitialLayout::DamBreak,
+        ..Default::default()
+    };
+    config.apply_fluid(FluidPreset::Water.properties());
+    config
+}
+
+/// Creates the simulation of `config` with columns of fluid particles at rest instead of its
+/// initial layout. Column `i` is `columns[i]` particles high, the first one is next to the -x
+/// wall, and the particles are placed like those of a dam break.
+fn start_tank(
+    wgpu_device: &WgpuDevice,
+    mut config: FluidSimulationConfig,
+    columns: &[usize],
+) -> Result<FluidSimulation, SplooshError> {
+    let bbox = config.simulation_bbox();
+    let smoothing_radius = config.smoothing_radius;
+    let spacing = smoothing_radius * PARTICLE_SPACING;
+    let mut positions = FluidSimulation::ghost_positions(
+        smoothing_radius,
+        bbox,
+        config.dimensions,
+        config.boundary,
+    );
+    let ghost_particle_cnt = positions.len();
+
+    for (j, &height) in columns.iter().enumerate() {
+        for i in 0..height {
+            positions.push(Point4::new(
+                smoothing_radius + j as f32 * spacing,
+                smoothing_radius + i as f32 * spacing,
+                bbox.z / 2.0,
+                1.0,
+            ));
+        }
+    }
+
+    let particle_cnt = positions.len();
+    config.particle_cnt = particle_cnt - ghost_particle_cnt;
+    let rest_density = config.rest_density;
+    let fluid_sim = FluidSimulation::new(config, wgpu_device);
+    fluid_sim.restore_snapshot(
+        &wgpu_device.queue,
+        &ParticleSnapshot {
+            positions,
+            velocities: vec![Vector4::zeros(); particle_cnt],
+            densities: vec![rest_density; particle_cnt],
+            time: 0.0,
+            step_cnt: 0,
+        },
+    )?;
+
+    Ok(fluid_sim)
+}
+
+/// Steps `fluid_sim` at its longest stable time step until `duration` and calls `sample` about
+/// every `sample_interval` of simulated time. Like in the soak run, every step is submitted
+/// alone.
+fn simulate(
+    fluid_sim: &FluidSimulation,
+    wgpu_device: &WgpuDevice,
+    duration: f32,
+    sample_interval: f32,
+    mut sample: impl FnMut(&FluidSimulation) -> Result<(), SplooshError>,
+) -> Result<(), SplooshError> {
+    let dt = fluid_sim.config().max_time_step();
+    let step = fluid_sim.step_fn(dt);
+    let steps = (duration / dt).ceil() as u64;
+    let steps_per_sample = ((sample_interval / dt).round() as u64).max(1);
+
+    for i in 1..=steps {
+        let mut encoder =
+            wgpu_device
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Validation encoder"),
+                });
+        step(&mut encoder, &wgpu_device.queue);
+        wgpu_device.submit(encoder);
+
+        if i % steps_per_sample == 0 {
+            sample(fluid_sim)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Samples the probes and wave gauges of `fluid_sim` on its current state.
+fn measure(fluid_sim: &FluidSimulation, wgpu_device: &WgpuDevice) -> Result<(), SplooshError> {
+    let mut encoder = wgpu_device
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Validation measurement encoder"),
+        });
+    if !fluid_sim.config().probes.is_empty() {
+        fluid_sim.probes_fn()(&mut encoder, &wgpu_device.queue);
+    }
+    if !fluid_sim.config().wave_gauges.is_empty() {
+        fluid_sim.wave_gauges_fn()(&mut encoder, &wgpu_device.queue);
+    }
+    wgpu_device.submit(encoder);
+
+    Ok(())
+}
+
+/// Settles a tank of water and compares the pressure at a quarter, half and three quarters of
+/// the depth with ρgh below the measured surface. The error is the largest deviation relative
+/// to the pressure at the bottom.
+fn hydrostatic(
+    wgpu_device: &WgpuDevice,
+    resolution: u32,
+) -> Result<ValidationResult, SplooshError> {
+    const WIDTH: f32 = 1.0;
+    const DEPTH: f32 = 1.0;
+    const SETTLE_TIME: f32 = 2.0;
+    const AVERAGE_TIME: f32 = 1.0;
+    const DEPTH_FRACTIONS: [f32; 3] = [0.25, 0.5, 0.75];
+
+    let spacing = DEPTH / resolution.max(1) as f32;
+    let mut config = tank_config(WIDTH, 2.0 * DEPTH, spacing);
+    let bbox = config.simulation_bbox();
+    let smoothing_radius = config.smoothing_radius;
+    let column_cnt = ((WIDTH - 2.0 * smoothing_radius) / spacing) as usize + 1;
+    let layer_cnt = resolution.max(1) as usize;
+    // lower face of the bottom particle layer, in simulation space
+    let bottom = smoothing_radius - spacing / 2.0;
+
+    config.probes = DEPTH_FRACTIONS
+        .iter()
+        .map(|fraction| ProbeConfig {
+            name: format!("{fraction} of the depth"),
+            position: Point3::new(0.0, bottom + (1.0 - fraction) * DEPTH - bbox.y / 2.0, 0.0),
+            shape: ProbeShape::Point,
+        })
+        .collect();
+    config.wave_gauges = vec![WaveGaugeConfig {
+        name: "Surface".to_string(),
+        position: Point2::origin(),
+        radius: 2.0 * spacing,
+    }];
+    let rest_density = config.rest_density;
+    let probe_heights: Vec<f32> = config
+        .probes
+        .iter()
+        .map(|probe| probe.position.y + bbox.y / 2.0)
+        .collect();
+
+    let fluid_sim = start_tank(wgpu_device, config, &vec![layer_cnt; column_cnt])?;
+    let mut pressure_sums = vec![0.0; DEPTH_FRACTIONS.len()];
+    let mut surface_sum = 0.0;
+    let mut sample_cnt = 0;
+    simulate(
+        &fluid_sim,
+        wgpu_device,
+        SETTLE_TIME + AVERAGE_TIME,
+        0.01,
+        |fluid_sim| {
+            if fluid_sim.sim_time() < SETTLE_TIME {
+                return Ok(());
+            }
+
+            measure(fluid_sim, wgpu_device)?;
+            let probes = fluid_sim.read_probes(&wgpu_device.device)?;
+            let gauges = fluid_sim.read_wave_gauges(&wgpu_device.device)?;
+            let Some(surface) = gauges.first().and_then(|gauge| gauge.height) else {
+                return Ok(());
+            };
+
+            for (sum, probe) in pressure_sums.iter_mut().zip(&probes) {
+                *sum += probe.pressure;
+            }
+            // the surface is half a spacing above the highest particle
+            surface_sum += surface + spacing / 2.0;
+            sample_cnt += 1;
+            Ok(())
+        },
+    )?;
+
+    let cnt = sample_cnt.max(1) as f32;
+    let surface = surface_sum / cnt;
+    let bottom_pressure = rest_density * STANDARD_GRAVITY * DEPTH;
+    let curve: Vec<_> = probe_heights
+        .iter()
+        .zip(&pressure_sums)
+        .map(|(height, sum)| {
+            let depth = surface - height;
+            (depth, sum / cnt, rest_density * STANDARD_GRAVITY * depth)
+        })
+        .collect();
+    let error = match sample_cnt {
+        0 => f32::INFINITY,
+        _ => curve
+            .iter()
+            .map(|(_, measured, reference)| (measured - reference).abs() / bottom_pressure)
+            .fold(0.0, f32::max),
+    };
+
+    Ok(ValidationResult {
+        case: ValidationCase::Hydrostatic,
+        metric: "max_pressure_error",
+        error,
+        tolerance: 0.1,
+        abscissa: "depth",
+        curve,
+    })
+}
+
+/// Collapses a water column twice as high as it is wide and compares the surge front with
+/// the measurements of Martin and Moyce. The error is the mean relative deviation of the
+/// front position.
+fn dam_break(wgpu_device: &WgpuDevice, resolution: u32) -> Result<ValidationResult, SplooshError> {
+    const COLUMN_WIDTH: f32 = 0.5;
+
+    let layer_cnt = resolution.max(1) as usize;
+    let spacing = 2.0 * COLUMN_WIDTH / layer_cnt as f32;
+    // long enough for the front to stay clear of the far wall until the last measurement
+    let config = tank_config(5.0 * COLUMN_WIDTH, 3.0 * COLUMN_WIDTH, spacing);
+    let smoothing_radius = config.smoothing_radius;
+    let column_cnt = ((COLUMN_WIDTH / spacing).round() as usize).max(1);
+    let width = column_cnt as f32 * spacing;
+    // left face of the column, where the front is measured from
+    let wall = smoothing_radius - spacing / 2.0;
+    let time_scale = (2.0 * STANDARD_GRAVITY / width).sqrt();
+    let end_time = (MARTIN_MOYCE_FRONT[MARTIN_MOYCE_FRONT.len() - 1].0 + 0.1) / time_scale;
+
+    let fluid_sim = start_tank(wgpu_device, config, &vec![layer_cnt; column_cnt])?;
+    let ghost_particle_cnt = fluid_sim.ghost_particle_cnt();
+    let mut fronts = vec![(0.0, 1.0)];
+    simulate(&fluid_sim, wgpu_device, end_time, 0.005, |fluid_sim| {
+        let snapshot = fluid_sim.read_snapshot(wgpu_device)?;
+        let xs = snapshot.positions[ghost_particle_cnt..]
+            .iter()
+            .map(|p| p.x)
+            .collect();
+        let front = front_position(xs) + spacing / 2.0;
+        fronts.push((snapshot.time * time_scale, (front - wall) / width));
+        Ok(())
+    })?;
+
+    let curve: Vec<_> = MARTIN_MOYCE_FRONT
+        .iter()
+        .map(|&(t, z)| (t, interpolate(&fronts, t), z))
+        .collect();
+    let error = curve
+        .iter()
+        .map(|(_, measured, reference)| (measured - reference).abs() / reference)
+        .sum::<f32>()
+        / curve.len() as f32;
+
+    Ok(ValidationResult {
+        case: ValidationCase::DamBreak,
+        metric: "mean_front_error",
+        error,
+        tolerance: 0.1,
+        abscissa: "t_sqrt_2g_over_a",
+        curve,
+    })
+}
+
+/// Releases a tank whose surface is tilted like the first sloshing mode and compares the
+/// frequency of the surface next to the wall with linear wave theory, ω² = gk tanh(kd). The
+/// error is the relative deviation of the frequency.
+fn sloshing(wgpu_device: &WgpuDevice, resolution: u32) -> Result<ValidationResult, SplooshError> {
+    const LENGTH: f32 = 1.0;
+    const DEPTH: f32 = 0.5;
+    const AMPLITUDE: f32 = 0.05 * DEPTH;
+    const PERIODS: f32 = 4.0;
+
+    let spacing = DEPTH / resolution.max(1) as f32;
+    let mut config = tank_config(LENGTH, 2.0 * DEPTH, spacing);
+    let bbox = config.simulation_bbox();
+    let smoothing_radius = config.smoothing_radius;
+    let column_cnt = ((LENGTH - 2.0 * smoothing_radius) / spacing) as usize + 1;
+    let length = column_cnt as f32 * spacing;
+    let k = PI / length;
+
+    let columns: Vec<usize> = (0..column_cnt)
+        .map(|j| {
+            let x = (j as f32 + 0.5) * spacing;
+            ((DEPTH + AMPLITUDE * (k * x).cos()) / spacing).round() as usize
+        })
+        .collect();
+    let depth = columns.iter().sum::<usize>() as f32 / column_cnt as f32 * spacing;
+    let omega = (STANDARD_GRAVITY * k * (k * depth).tanh()).sqrt();
+
+    // over the second column, where the surface moves about as much as at the wall
+    let gauge_x = smoothing_radius + spacing;
+    config.wave_gauges = vec![WaveGaugeConfig {
+        name: "Wall".to_string(),
+        position: Point2::new(gauge_x - bbox.x / 2.0, 0.0),
+        radius: spacing,
+    }];
+    // highest particle center of the linear solution
+    let center_height = smoothing_radius - spacing + depth;
+    let gauge_amplitude = AMPLITUDE * (k * (gauge_x - smoothing_radius + spacing / 2.0)).cos();
+
+    let fluid_sim = start_tank(wgpu_device, config, &columns)?;
+    let mut heights = Vec::new();
+    let duration = PERIODS * 2.0 * PI / omega;
+    simulate(&fluid_sim, wgpu_device, duration, 0.005, |fluid_sim| {
+        measure(fluid_sim, wgpu_device)?;
+        let gauges = fluid_sim.read_wave_gauges(&wgpu_device.device)?;
+        if let Some(height) = gauges.first().and_then(|gauge| gauge.height) {
+            heights.push((fluid_sim.sim_time(), height));
+        }
+        Ok(())
+    })?;
+
+    let reference_frequency = omega / (2.0 * PI);
+    let error = match oscillation_period(&heights) {
+        Some(period) => (1.0 / period - reference_frequency).abs() / reference_frequency,
+        None => f32::INFINITY,
+    };
+    let curve = heights
+        .iter()
+        .map(|&(t, height)| {
+            let reference = center_height + gauge_amplitude * (omega * t).cos();
+            (t, height, reference)
+        })
+        .collect();
+
+    Ok(ValidationResult {
+        case: ValidationCase::Sloshing,
+        metric: "frequency_error",
+        error,
+        tolerance: 0.05,
+        abscissa: "time",
+        curve,
+    })
+}
+
+/// Position exceeded by at most `FRONT_OUTLIERS` of the values.
+fn front_position(mut xs: Vec<f32>) -> f32 {
+    if xs.is_empty() {
+        return 0.0;
+    }
+
+    xs.sort_by(|a, b| b.total_cmp(a));
+    xs[((xs.len() as f32 * FRONT_OUTLIERS) as usize).min(xs.len() - 1)]
+}
+
+/// Linear interpolation of the (x, y) points sorted by x, clamped to the first and last one.
+fn interpolate(points: &[(f32, f32)], x: f32) -> f32 {
+    match points.iter().position(|&(px, _)| px >= x) {
+        Some(0) => points[0].1,
+        Some(i) => {
+            let (x0, y0) = points[i - 1];
+            let (x1, y1) = points[i];
+            y0 + (y1 - y0) * (x - x0) / (x1 - x0).max(f32::EPSILON)
+        }
+        None => points.last().map_or(0.0, |&(_, y)| y),
+    }
+}
+
+/// Mean time between the upward crossings of the mean of the (t, y) samples, `None` with less
+/// than two crossings.
+fn oscillation_period(samples: &[(f32, f32)]) -> Option<f32> {
+    let mean = samples.iter().map(|&(_, y)| y).sum::<f32>() / samples.len().max(1) as f32;
+    let crossings: Vec<f32> = samples
+        .windows(2)
+        .filter(|pair| pair[0].1 < mean && pair[1].1 >= mean)
+        .map(|pair| {
+            let ((t0, y0), (t1, y1)) = (pair[0], pair[1]);
+            t0 + (t1 - t0) * (mean - y0) / (y1 - y0)
+        })
+        .collect();
+
+    (crossings.len() >= 2)
+        .then(|| (crossings[crossings.len() - 1] - crossings[0]) / (crossings.len() - 1) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn period_of_a_sine() {
+        let samples: Vec<_> = (0..1000)
+            .map(|i| {
+                let t = i as f32 * 0.01;
+                (t, 2.0 + (2.0 * PI * t / 1.5).sin())
+            })
+            .collect();
+
+        let period = oscillation_period(&samples).unwrap();
+        assert!((period - 1.5).abs() < 1e-3, "{period}");
+        assert_eq!(oscillation_period(&samples[..100]), None);
+    }
+
+    #[test]
+    fn front_ignores_splashes() {
+        let mut xs: Vec<f32> = (0..1000).map(|i| i as f32 / 1000.0).collect();
+        xs.push(10.0);
+
+        assert_eq!(front_position(xs), 0.998);
+        assert_eq!(interpolate(&[(0.0, 1.0), (1.0, 3.0)], 0.25), 1.5);
+    }
+}
@@ -0,0 +1,250 @@
+use std::sync::Arc;
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graphics::{geometry::Geometry, materials::ColoredVertex, render_engine::GenericRequest},
+    wgpu_device::Uploader,
+    ComputeTask, SpatialLookup, WgpuDevice,
+};
+
+pub const MAX_STREAMLINE_STEPS: u32 = 16;
+
+/// Debug lines visualizing the flow field, colored by speed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VelocityLineConfig {
+    /// Seconds of motion a line covers
+    pub scale: f32,
+    /// Only every n-th particle gets a line
+    pub subsampling: u32,
+    /// Streamline segments integrated through the velocity field, a single step draws the
+    /// particle velocity
+    pub streamline_steps: u32,
+}
+
+impl Default for VelocityLineConfig {
+    fn default() -> Self {
+        Self {
+            scale: 0.1,
+            subsampling: 8,
+            streamline_steps: 1,
+        }
+    }
+}
+
+impl VelocityLineConfig {
+    /// Streamlines with more segments are spread over fewer particles, so the line buffer stays
+    /// the size of a line per particle.
+    fn clamped(self) -> Self {
+        let streamline_steps = self.streamline_steps.clamp(1, MAX_STREAMLINE_STEPS);
+        Self {
+            scale: self.scale,
+            subsampling: self.subsampling.max(streamline_steps),
+            streamline_steps,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct VelocityLineUniform {
+    scale: f32,
+    subsampling: u32,
+    streamline_steps: u32,
+    _padding: u32,
+}
+
+/// Writes a line list along the velocity of a subset of the fluid particles.
+pub struct VelocityLines {
+    fluid_particle_cnt: usize,
+    line_buffer: Arc<wgpu::Buffer>,
+    uniform_buffer: Arc<wgpu::Buffer>,
+    line_task: Arc<ComputeTask>,
+    uploader: Uploader,
+}
+
+impl VelocityLines {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        bbox_dimensions: Vector3<f32>,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        velocities: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+    ) -> Self {
+        let fluid_particle_cnt = particle_cnt - ghost_particle_cnt;
+
+        // rounding the line count up can add up to a line worth of segments
+        let segment_cnt = fluid_particle_cnt + MAX_STREAMLINE_STEPS as usize;
+        let line_buffer = wgpu_device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Velocity line buffer"),
+            size: (2 * segment_cnt * std::mem::size_of::<ColoredVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let uniform_buffer = wgpu_device.create_buffer_init(
+            &[VelocityLineUniform::default()],
+            wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let line_task = VelocityLines::create_line_task(
+            wgpu_device,
+            fluid_particle_cnt,
+            ghost_particle_cnt,
+            bbox_dimensions,
+            spatial_lookup,
+            positions,
+            velocities,
+            densities,
+            &line_buffer,
+            &uniform_buffer,
+        );
+
+        Self {
+            uploader: wgpu_device.uploader.clone(),
+            fluid_particle_cnt,
+            line_buffer,
+            uniform_buffer,
+            line_task,
+        }
+    }
+
+    pub fn update_fn(&self, config: VelocityLineConfig) -> GenericRequest {
+        let config = config.clamped();
+        let uniform = VelocityLineUniform {
+            scale: config.scale,
+            subsampling: config.subsampling,
+            streamline_steps: config.streamline_steps,
+            _padding: 0,
+        };
+        let uploader = self.uploader.clone();
+        let uniform_buffer = self.uniform_buffer.clone();
+        let line_task = self.line_task.clone();
+
+        Box::new(move |encoder, _| {
+            uploader.write(encoder, &uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+            line_task.execute(encoder, &[]);
+        })
+    }
+
+    /// Line list written by the pass returned from `update_fn` with the same config.
+    pub fn geometry(&self, config: VelocityLineConfig) -> Geometry {
+        let config = config.clamped();
+        let line_cnt = self
+            .fluid_particle_cnt
+            .div_ceil(config.subsampling as usize);
+
+        Geometry::Array {
+            vertex_buffer: self.line_buffer.clone(),
+            vertex_cnt: 2 * line_cnt * config.streamline_steps as usize,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_line_task(
+        wgpu_device: &WgpuDevice,
+        fluid_particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        bbox_dimensions: Vector3<f32>,
+        spatial_lookup: &SpatialLookup,
+        positions: &wgpu::Buffer,
+        velocities: &wgpu::Buffer,
+        densities: &wgpu::Buffer,
+        line_buffer: &wgpu::Buffer,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        // enough invocations for a line per particle, the shader skips the ones past the end
+        let workgroup_cnt = (fluid_particle_cnt as u32).div_ceil(256);
+
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             const OFFSET: vec3<f32> = vec3<f32>({}, {}, {});\n
+             {}
+             {}",
+            -bbox_dimensions.x / 2.0,
+            -bbox_dimensions.y / 2.0,
+            -bbox_dimensions.z / 2.0,
+            spatial_lookup.shader_source(),
+            include_str!("shaders/velocity_lines.wgsl")
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Velocity lines",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, true),
+                storage_entry(4, true),
+                storage_entry(5, true),
+                storage_entry(6, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: velocities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: densities.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: spatial_lookup.keys().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: spatial_lookup.vals().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: spatial_lookup.index().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: line_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            &[],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use nalgebra::{Point2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    graphics::render_engine::GenericRequest, readback::Readback, wgpu_device::Uploader,
+    ComputeTask, SplooshError, WgpuDevice,
+};
+
+pub const MAX_WAVE_GAUGES: usize = 16;
+/// Samples kept per gauge, the oldest are dropped first.
+pub const MAX_WAVE_GAUGE_HISTORY: usize = 100_000;
+
+/// Vertical column measuring the height of the highest fluid particle inside of it, like the
+/// wave gauges of a flume.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WaveGaugeConfig {
+    pub name: String,
+    /// x and z of the column axis in world space
+    pub position: Point2<f32>,
+    pub radius: f32,
+}
+
+impl Default for WaveGaugeConfig {
+    fn default() -> Self {
+        Self {
+            name: "Gauge".to_string(),
+            position: Point2::origin(),
+            radius: 0.1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WaveGaugeSample {
+    /// Simulated time in seconds
+    pub time: f32,
+    /// Above the bottom of the bounding box, `None` while the column is empty
+    pub height: Option<f32>,
+}
+
+/// Time series of every gauge, restarted when the number of gauges changes.
+#[derive(Clone, Debug, Default)]
+pub struct WaveGaugeHistory {
+    series: Vec<VecDeque<WaveGaugeSample>>,
+}
+
+impl WaveGaugeHistory {
+    /// Appends a sample per gauge. Samples from before a reset of the simulation time are
+    /// dropped.
+    pub fn push(&mut self, samples: &[WaveGaugeSample]) {
+        let restarted = self
+            .series
+            .first()
+            .and_then(VecDeque::back)
+            .zip(samples.first())
+            .is_some_and(|(last, sample)| sample.time < last.time);
+        if self.series.len() != samples.len() || restarted {
+            self.series = vec![VecDeque::new(); samples.len()];
+        }
+
+        for (series, sample) in self.series.iter_mut().zip(samples) {
+            if series.len() == MAX_WAVE_GAUGE_HISTORY {
+                series.pop_front();
+            }
+            series.push_back(*sample);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.series.clear();
+    }
+
+    /// Samples of gauge `index`, oldest first.
+    pub fn series(&self, index: usize) -> Option<&VecDeque<WaveGaugeSample>> {
+        self.series.get(index)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.series.iter().all(VecDeque::is_empty)
+    }
+}
+
+/// Writes a row per gauge and sample, the height is left empty while the column is empty.
+pub fn export_wave_gauges(
+    path: &Path,
+    gauges: &[WaveGaugeConfig],
+    history: &WaveGaugeHistory,
+) -> Result<(), SplooshError> {
+    let mut file = std::io::BufWriter::new(File::create(path)?);
+    writeln!(file, "gauge,time,height")?;
+    for (i, gauge) in gauges.iter().enumerate() {
+        let Some(series) = history.series(i) else {
+            continue;
+        };
+        let name = format!("\"{}\"", gauge.name.replace('"', "\"\""));
+        for s in series {
+            match s.height {
+                Some(height) => writeln!(file, "{name},{},{height}", s.time)?,
+                None => writeln!(file, "{name},{},", s.time)?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Inverse of `ordered_key` in the wave gauge shader.
+fn height_of_key(key: u32) -> Option<f32> {
+    if key == 0 {
+        return None;
+    }
+
+    let bits = if key & 0x8000_0000 != 0 {
+        key & !0x8000_0000
+    } else {
+        !key
+    };
+    Some(f32::from_bits(bits))
+}
+
+/// Finds the highest fluid particle in each gauge column with a reduction over the particles.
+pub struct WaveGauges {
+    bbox_dimensions: Vector3<f32>,
+    gauge_buffer: Arc<wgpu::Buffer>,
+    height_buffer: Arc<wgpu::Buffer>,
+    time_buffer: Arc<wgpu::Buffer>,
+    readback: Readback<u32>,
+    gauge_task: Arc<ComputeTask>,
+    uploader: Uploader,
+}
+
+impl WaveGauges {
+    pub fn new(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        bbox_dimensions: Vector3<f32>,
+        positions: &wgpu::Buffer,
+    ) -> Self {
+        let gauge_buffer = wgpu_device.create_buffer_init(
+            &[[0.0f32; 4]; MAX_WAVE_GAUGES],
+            wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        );
+        let height_buffer = wgpu_device.create_buffer_init(
+            &[0u32; MAX_WAVE_GAUGES],
+            wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        );
+        let time_buffer = wgpu_device.create_buffer_init(
+            &[0.0f32; 4],
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        );
+        // the bits of the time of the heights followed by the heights
+        let readback = Readback::new(
+            wgpu_device,
+            "Wave gauge readback",
+            time_buffer.size() + height_buffer.size(),
+        );
+
+        let gauge_task = WaveGauges::create_gauge_task(
+            wgpu_device,
+            particle_cnt,
+            ghost_particle_cnt,
+            positions,
+            &gauge_buffer,
+            &height_buffer,
+        );
+
+        Self {
+            uploader: wgpu_device.uploader.clone(),
+            bbox_dimensions,
+            gauge_buffer,
+            height_buffer,
+            time_buffer,
+            readback,
+            gauge_task,
+        }
+    }
+
+    /// Measures the first `MAX_WAVE_GAUGES` gauges. `time` holds the bits of the simulated
+    /// time, read when the passes are recorded.
+    pub fn update_fn(&self, gauges: &[WaveGaugeConfig], time: Arc<AtomicU32>) -> GenericRequest {
+        let gauges: Vec<[f32; 4]> = gauges
+            .iter()
+            .take(MAX_WAVE_GAUGES)
+            .map(|gauge| {
+                [
+                    gauge.position.x + self.bbox_dimensions.x / 2.0,
+                    gauge.position.y + self.bbox_dimensions.z / 2.0,
+                    gauge.radius,
+                    0.0,
+                ]
+            })
+            .collect();
+
+        let gauge_cnt = gauges.len() as u32;
+        let uploader = self.uploader.clone();
+        let gauge_buffer = self.gauge_buffer.clone();
+        let height_buffer = self.height_buffer.clone();
+        let time_buffer = self.time_buffer.clone();
+        let readback = self.readback.clone();
+        let gauge_task = self.gauge_task.clone();
+
+        Box::new(move |encoder, _| {
+            if gauge_cnt == 0 {
+                return;
+            }
+
+            let time = time.load(Ordering::Relaxed);
+            uploader.write(encoder, &time_buffer, 0, bytemuck::cast_slice(&[time; 4]));
+            uploader.write(encoder, &gauge_buffer, 0, bytemuck::cast_slice(&gauges));
+            uploader.write(
+                encoder,
+                &height_buffer,
+                0,
+                bytemuck::cast_slice(&[0u32; MAX_WAVE_GAUGES]),
+            );
+            gauge_task.execute(encoder, bytemuck::bytes_of(&gauge_cnt));
+            readback.copy(encoder, |encoder, staging_buffer| {
+                encoder.copy_buffer_to_buffer(&time_buffer, 0, staging_buffer, 0, 16);
+                encoder.copy_buffer_to_buffer(
+                    &height_buffer,
+                    0,
+                    staging_buffer,
+                    16,
+                    height_buffer.size(),
+                );
+            });
+        })
+    }
+
+    /// Blocks until the submitted passes of `update_fn` have finished. Returns a sample for
+    /// each of the first `gauge_cnt` gauges.
+    pub fn read(
+        &self,
+        device: &wgpu::Device,
+        gauge_cnt: usize,
+    ) -> Result<Vec<WaveGaugeSample>, SplooshError> {
+        self.readback
+            .wait(device)
+            .map(|data| Self::samples(&data, gauge_cnt))
+    }
+
+    /// Like `read` without blocking, `None` until newer samples arrive.
+    pub fn poll(
+        &self,
+        device: &wgpu::Device,
+        gauge_cnt: usize,
+    ) -> Option<Result<Vec<WaveGaugeSample>, SplooshError>> {
+        self.readback
+            .poll(device)
+            .map(|data| data.map(|data| Self::samples(&data, gauge_cnt)))
+    }
+
+    fn samples(data: &[u32], gauge_cnt: usize) -> Vec<WaveGaugeSample> {
+        let time = f32::from_bits(data[0]);
+
+        data[4..]
+            .iter()
+            .take(gauge_cnt.min(MAX_WAVE_GAUGES))
+            .map(|&key| WaveGaugeSample {
+                time,
+                height: height_of_key(key),
+            })
+            .collect()
+    }
+
+    fn create_gauge_task(
+        wgpu_device: &WgpuDevice,
+        particle_cnt: usize,
+        ghost_particle_cnt: usize,
+        positions: &wgpu::Buffer,
+        gauge_buffer: &wgpu::Buffer,
+        height_buffer: &wgpu::Buffer,
+    ) -> Arc<ComputeTask> {
+        let workgroup_cnt = ((particle_cnt - ghost_particle_cnt) as u32)
+            .div_ceil(256)
+            .max(1);
+
+        let shader_source = format!(
+            "
+             const GHOST_PARTICLE_CNT: u32 = {ghost_particle_cnt};\n
+             const MAX_WAVE_GAUGES: u32 = {MAX_WAVE_GAUGES};\n
+             {}
+             {}",
+            include_str!("shaders/wave_gauges.wgsl"),
+            match wgpu_device.supports_subgroups() {
+                true => include_str!("shaders/wave_gauges_reduce_subgroup.wgsl"),
+                false => include_str!("shaders/wave_gauges_reduce.wgsl"),
+            }
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        Arc::new(ComputeTask::new(
+            wgpu_device,
+            "Wave gauges",
+            &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, false),
+            ],
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: positions.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: gauge_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: height_buffer.as_entire_binding(),
+                },
+            ],
+            &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..4,
+            }],
+            shader_source.into(),
+            (workgroup_cnt, 1, 1),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Point4;
+    use pollster::FutureExt as _;
+
+    use super::*;
+
+    #[test]
+    fn gauges_find_the_highest_particle_in_their_column() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+
+        // centered in the box, so x and z in world space are the simulation ones minus 2
+        let bbox_dimensions = Vector3::new(4.0, 4.0, 4.0);
+        let positions = [
+            Point4::new(2.0, 3.5, 2.0, 1.0),
+            Point4::new(2.0, 1.5, 2.0, 1.0),
+            Point4::new(2.05, 2.5, 2.0, 1.0),
+            Point4::new(3.0, 0.5, 3.0, 1.0),
+        ];
+        let position_buffer =
+            wgpu_device.create_buffer_init(&positions, wgpu::BufferUsages::STORAGE);
+        let gauges = [
+            WaveGaugeConfig {
+                position: Point2::new(0.0, 0.0),
+                radius: 0.1,
+                ..Default::default()
+            },
+            WaveGaugeConfig {
+                position: Point2::new(1.0, 1.0),
+                radius: 0.1,
+                ..Default::default()
+            },
+            WaveGaugeConfig {
+                position: Point2::new(-1.0, 1.0),
+                radius: 0.1,
+                ..Default::default()
+            },
+        ];
+
+        // the first particle is a ghost and is skipped
+        let wave_gauges = WaveGauges::new(
+            &wgpu_device,
+            positions.len(),
+            1,
+            bbox_dimensions,
+            &position_buffer,
+        );
+        let time = Arc::new(AtomicU32::new(0.5f32.to_bits()));
+        let mut encoder = wgpu_device
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        wave_gauges.update_fn(&gauges, time)(&mut encoder, &wgpu_device.queue);
+        wgpu_device.submit(encoder);
+
+        let samples = wave_gauges.read(&wgpu_device.device, gauges.len()).unwrap();
+        let heights: Vec<_> = samples.iter().map(|s| s.height).collect();
+        assert_eq!(heights, vec![Some(2.5), Some(0.5), None]);
+        assert!(samples.iter().all(|s| s.time == 0.5));
+        assert_eq!(height_of_key(0), None);
+    }
+}
@@ -1,32 +1,164 @@
-use std::{error::Error, num::NonZero, rc::Rc};
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    thread::{self, ThreadId},
+};
+
+use crate::config::{AdapterConfig, PowerPreference};
+
+use crate::{
+    gpu_timer::TIMESTAMP_FEATURES, kernel_registry::KernelRegistry,
+    resource_registry::ResourceRegistry, SplooshError,
+};
+
+/// A wgpu object owned by sploosh or borrowed from an engine that created it, e.g. the render
+/// device of Bevy. Derefs to the object either way.
+pub struct Shared<T: 'static>(Arc<dyn Deref<Target = T> + Send + Sync>);
+
+impl<T: Send + Sync + 'static> Shared<T> {
+    pub fn owned(value: T) -> Self {
+        Self(Arc::new(Box::new(value)))
+    }
+}
+
+impl<T: 'static> Shared<T> {
+    pub fn new(handle: impl Deref<Target = T> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(handle))
+    }
+}
+
+impl<T: 'static> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: 'static> Deref for Shared<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &**self.0
+    }
+}
+
+/// Storage buffers bound by the largest compute passes, like the soft body forces, above the
+/// WebGPU default of 8 but within what desktop adapters support.
+pub const MAX_STORAGE_BUFFERS: u32 = 16;
+
+/// Size of the staging buffers the uploader allocates, larger uploads get one of their own.
+const UPLOAD_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Batches the small uploads made every frame, like uniforms, into shared staging buffers
+/// instead of a `Queue::write_buffer` each. The copies are recorded into the encoder, so unlike
+/// `write_buffer` every command sees the value written before it. Uploads are kept per thread,
+/// each thread submits its own with `WgpuDevice::submit`.
+#[derive(Clone)]
+pub struct Uploader {
+    device: Shared<wgpu::Device>,
+    belts: Arc<Mutex<HashMap<ThreadId, wgpu::util::StagingBelt>>>,
+}
+
+impl Uploader {
+    pub fn new(device: Shared<wgpu::Device>) -> Self {
+        Self {
+            device,
+            belts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Copies `data` to `target` at `offset` when the encoder gets there. The size and offset
+    /// have to be multiples of 4.
+    pub fn write(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::Buffer,
+        offset: u64,
+        data: &[u8],
+    ) {
+        let Some(size) = wgpu::BufferSize::new(data.len() as u64) else {
+            return;
+        };
+
+        let mut belts = self.belts.lock().unwrap();
+        belts
+            .entry(thread::current().id())
+            .or_insert_with(|| wgpu::util::StagingBelt::new(UPLOAD_CHUNK_SIZE))
+            .write_buffer(encoder, target, offset, size, &self.device)
+            .copy_from_slice(data);
+    }
+
+    /// Has to be called after the uploads of this thread are encoded and before they are
+    /// submitted.
+    pub fn finish(&self) {
+        if let Some(belt) = self.belts.lock().unwrap().get_mut(&thread::current().id()) {
+            belt.finish();
+        }
+    }
+
+    /// Reuses the staging buffers of this thread once the GPU is done with them, has to be
+    /// called after the uploads are submitted.
+    pub fn recall(&self) {
+        if let Some(belt) = self.belts.lock().unwrap().get_mut(&thread::current().id()) {
+            belt.recall();
+        }
+    }
+}
+
+type PipelineLayoutKey = (
+    Vec<wgpu::BindGroupLayoutEntry>,
+    Vec<wgpu::PushConstantRange>,
+);
+
+/// Layouts created so far, keyed by their description. Tasks binding the same kinds of
+/// resources share one layout, so their bind groups are interchangeable.
+#[derive(Default)]
+struct LayoutCache {
+    bind_group_layouts: Mutex<HashMap<Vec<wgpu::BindGroupLayoutEntry>, Arc<wgpu::BindGroupLayout>>>,
+    pipeline_layouts: Mutex<HashMap<PipelineLayoutKey, Arc<wgpu::PipelineLayout>>>,
+}
 
 pub struct WgpuDevice {
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
+    pub adapter: Shared<wgpu::Adapter>,
+    pub device: Shared<wgpu::Device>,
+    pub queue: Shared<wgpu::Queue>,
+    pub uploader: Uploader,
+    pub resources: ResourceRegistry,
+    pub kernels: KernelRegistry,
+    layouts: Arc<LayoutCache>,
+    lost: Arc<AtomicBool>,
+    tuned_workgroup_size: Arc<OnceLock<u32>>,
 }
 
 impl WgpuDevice {
-    pub async fn new_compute_device() -> Result<Self, Box<dyn Error>> {
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
-            ..Default::default()
-        });
+    pub async fn new_compute_device() -> Result<Self, SplooshError> {
+        WgpuDevice::with_adapter_config(&AdapterConfig::default()).await
+    }
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: None,
-                force_fallback_adapter: false,
-            })
-            .await
-            .ok_or("Failed to crate an adapter")?;
+    pub async fn with_adapter_config(config: &AdapterConfig) -> Result<Self, SplooshError> {
+        let instance = create_instance(config);
+        let adapter = select_adapter(&instance, config, None).await?;
+
+        WgpuDevice::from_adapter(adapter).await
+    }
 
+    /// Requests a device with the features and limits the simulation needs.
+    #[tracing::instrument(skip_all)]
+    pub async fn from_adapter(adapter: wgpu::Adapter) -> Result<Self, SplooshError> {
+        // timestamps are only needed for profiling and subgroups have fallbacks, so both are
+        // requested if available
+        let optional_features =
+            adapter.features() & (TIMESTAMP_FEATURES | wgpu::Features::SUBGROUP);
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::PUSH_CONSTANTS,
+                    required_features: wgpu::Features::PUSH_CONSTANTS | optional_features,
                     required_limits: wgpu::Limits {
-                        max_push_constant_size: 4,
+                        max_push_constant_size: 16,
+                        max_storage_buffers_per_shader_stage: MAX_STORAGE_BUFFERS,
                         ..Default::default()
                     },
                     label: None,
@@ -36,11 +168,147 @@ impl WgpuDevice {
             )
             .await?;
 
-        Ok(Self { device, queue })
+        let lost = Arc::new(AtomicBool::new(false));
+        let lost_flag = lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            if matches!(
+                reason,
+                wgpu::DeviceLostReason::Unknown | wgpu::DeviceLostReason::Destroyed
+            ) {
+                tracing::error!("The device was lost: {message}");
+                lost_flag.store(true, Ordering::Relaxed);
+            }
+        });
+
+        // once the device is gone every call reports an error, they are expected until the
+        // device has been recreated. Other errors end up in the console instead of a panic.
+        let lost_flag = lost.clone();
+        device.on_uncaptured_error(Box::new(move |err| {
+            if let wgpu::Error::OutOfMemory { .. } = err {
+                lost_flag.store(true, Ordering::Relaxed);
+            }
+            if lost_flag.load(Ordering::Relaxed) {
+                tracing::warn!("Error on a lost device: {err}");
+            } else {
+                tracing::error!("wgpu error: {err}");
+            }
+        }));
+
+        let device = Shared::owned(device);
+        Ok(Self {
+            adapter: Shared::owned(adapter),
+            uploader: Uploader::new(device.clone()),
+            device,
+            queue: Shared::owned(queue),
+            resources: ResourceRegistry::new(),
+            kernels: KernelRegistry::new(),
+            layouts: Arc::default(),
+            lost,
+            tuned_workgroup_size: Arc::default(),
+        })
+    }
+
+    /// Runs the simulation on a device created elsewhere. It needs push constants of at least
+    /// 16 bytes and [`MAX_STORAGE_BUFFERS`] storage buffers per shader stage. Losing the device
+    /// is left to its owner, `is_lost` stays false.
+    pub fn from_shared(
+        adapter: Shared<wgpu::Adapter>,
+        device: Shared<wgpu::Device>,
+        queue: Shared<wgpu::Queue>,
+    ) -> Self {
+        Self {
+            adapter,
+            uploader: Uploader::new(device.clone()),
+            device,
+            queue,
+            resources: ResourceRegistry::new(),
+            kernels: KernelRegistry::new(),
+            layouts: Arc::default(),
+            lost: Arc::new(AtomicBool::new(false)),
+            tuned_workgroup_size: Arc::default(),
+        }
     }
 
-    pub fn create_buffer_init<T>(&self, data: &[T], usage: wgpu::BufferUsages) -> Rc<wgpu::Buffer> {
-        let len = data.len() * std::mem::size_of::<T>();
+    /// Whether kernels can use the subgroup operations of WGSL instead of their fallbacks.
+    pub fn supports_subgroups(&self) -> bool {
+        self.device.features().contains(wgpu::Features::SUBGROUP)
+    }
+
+    /// Workgroup size of the density and force kernels, measured by `tune` on the first call
+    /// and shared by every later simulation on the device.
+    pub fn tuned_workgroup_size(&self, tune: impl FnOnce() -> u32) -> u32 {
+        *self.tuned_workgroup_size.get_or_init(tune)
+    }
+
+    /// Bind group layout with `entries`, shared with everything else created with the same
+    /// entries.
+    pub fn bind_group_layout(
+        &self,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Arc<wgpu::BindGroupLayout> {
+        let mut layouts = self.layouts.bind_group_layouts.lock().unwrap();
+        if let Some(layout) = layouts.get(entries) {
+            return layout.clone();
+        }
+
+        let layout = Arc::new(self.device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label: Some("Shared bind group layout"),
+                entries,
+            },
+        ));
+        layouts.insert(entries.to_vec(), layout.clone());
+        layout
+    }
+
+    /// Pipeline layout with a single bind group of `entries`, shared like `bind_group_layout`.
+    pub fn pipeline_layout(
+        &self,
+        entries: &[wgpu::BindGroupLayoutEntry],
+        push_constant_ranges: &[wgpu::PushConstantRange],
+    ) -> Arc<wgpu::PipelineLayout> {
+        let key = (entries.to_vec(), push_constant_ranges.to_vec());
+        if let Some(layout) = self.layouts.pipeline_layouts.lock().unwrap().get(&key) {
+            return layout.clone();
+        }
+
+        let bind_group_layout = self.bind_group_layout(entries);
+        let layout = Arc::new(self.device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label: Some("Shared pipeline layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges,
+            },
+        ));
+        self.layouts
+            .pipeline_layouts
+            .lock()
+            .unwrap()
+            .insert(key, layout.clone());
+        layout
+    }
+
+    /// Submits `encoder` together with the uploads this thread made through the uploader.
+    pub fn submit(&self, encoder: wgpu::CommandEncoder) -> wgpu::SubmissionIndex {
+        let _span = tracing::debug_span!("submit").entered();
+        self.uploader.finish();
+        let submission = self.queue.submit(Some(encoder.finish()));
+        self.uploader.recall();
+        submission
+    }
+
+    /// Set once the driver lost the device or it was destroyed, everything created on it has
+    /// to be recreated on a new device.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+
+    pub fn create_buffer_init<T>(
+        &self,
+        data: &[T],
+        usage: wgpu::BufferUsages,
+    ) -> Arc<wgpu::Buffer> {
+        let len = std::mem::size_of_val(data);
         let ptr = data.as_ptr() as *const u8;
 
         let data = unsafe { std::slice::from_raw_parts(ptr, len) };
@@ -52,11 +320,173 @@ impl WgpuDevice {
             mapped_at_creation: false,
         });
 
-        let view = self
-            .queue
-            .write_buffer_with(&buffer, 0, NonZero::new(len as u64).unwrap());
-        view.unwrap().copy_from_slice(data);
+        self.queue.write_buffer(&buffer, 0, data);
+
+        let buffer = Arc::new(buffer);
+        self.resources.track_buffer(&buffer, Some("Buffer"));
+        buffer
+    }
+
+    /// Creates a buffer listed in `resources`.
+    pub fn create_buffer(&self, descriptor: &wgpu::BufferDescriptor) -> Arc<wgpu::Buffer> {
+        let buffer = Arc::new(self.device.create_buffer(descriptor));
+        self.resources.track_buffer(&buffer, descriptor.label);
+        buffer
+    }
+
+    /// Creates a texture listed in `resources`.
+    pub fn create_texture(&self, descriptor: &wgpu::TextureDescriptor) -> Arc<wgpu::Texture> {
+        let texture = Arc::new(self.device.create_texture(descriptor));
+        self.resources.track_texture(&texture, descriptor.label);
+        texture
+    }
+}
+
+/// Blocks until `staging_buffer` is mapped and returns its contents. The copies into the buffer
+/// have to be submitted before.
+pub fn read_staging<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    staging_buffer: &wgpu::Buffer,
+) -> Result<Vec<T>, SplooshError> {
+    let _span = tracing::debug_span!("read_staging", size = staging_buffer.size()).entered();
+    let buffer_slice = staging_buffer.slice(..);
+    let (tx, rx) = std::sync::mpsc::sync_channel(1);
+    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = tx.send(result);
+    });
+
+    device.poll(wgpu::Maintain::Wait);
+    rx.recv()??;
+
+    let data = bytemuck::cast_slice(&buffer_slice.get_mapped_range()).to_vec();
+    staging_buffer.unmap();
+
+    Ok(data)
+}
+
+pub fn create_instance(config: &AdapterConfig) -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: config.backend.wgpu_backends(),
+        ..Default::default()
+    })
+}
+
+/// Picks the adapter matching `config` that can present to `surface`, if given. Among several
+/// candidates the device type decides, following the power preference.
+#[tracing::instrument(skip_all)]
+pub async fn select_adapter(
+    instance: &wgpu::Instance,
+    config: &AdapterConfig,
+    surface: Option<&wgpu::Surface<'_>>,
+) -> Result<wgpu::Adapter, SplooshError> {
+    let adapters: Vec<wgpu::Adapter> = instance
+        .enumerate_adapters(config.backend.wgpu_backends())
+        .into_iter()
+        .filter(|adapter| surface.is_none_or(|surface| adapter.is_surface_supported(surface)))
+        .collect();
+
+    let available: Vec<String> = adapters
+        .iter()
+        .map(|adapter| {
+            let info = adapter.get_info();
+            format!("{} ({:?})", info.name, info.backend)
+        })
+        .collect();
+
+    let candidates: Vec<wgpu::Adapter> = match &config.name {
+        Some(name) => {
+            let name = name.to_lowercase();
+            adapters
+                .into_iter()
+                .filter(|adapter| adapter.get_info().name.to_lowercase().contains(&name))
+                .collect()
+        }
+        None => adapters,
+    };
+
+    if let (true, Some(name)) = (candidates.is_empty(), &config.name) {
+        return Err(SplooshError::Adapter(format!(
+            "No adapter matches {name:?}, available adapters: {}",
+            available.join(", ")
+        )));
+    }
+
+    let rank = |device_type| {
+        let order = match config.power_preference {
+            PowerPreference::HighPerformance => [
+                wgpu::DeviceType::DiscreteGpu,
+                wgpu::DeviceType::IntegratedGpu,
+                wgpu::DeviceType::VirtualGpu,
+                wgpu::DeviceType::Other,
+                wgpu::DeviceType::Cpu,
+            ],
+            PowerPreference::LowPower => [
+                wgpu::DeviceType::IntegratedGpu,
+                wgpu::DeviceType::DiscreteGpu,
+                wgpu::DeviceType::VirtualGpu,
+                wgpu::DeviceType::Other,
+                wgpu::DeviceType::Cpu,
+            ],
+        };
+        order.iter().position(|&t| t == device_type)
+    };
+
+    if let Some(adapter) = candidates
+        .into_iter()
+        .min_by_key(|adapter| rank(adapter.get_info().device_type))
+    {
+        return Ok(adapter);
+    }
+
+    // backends that can't enumerate their adapters up front
+    let power_preference = match config.power_preference {
+        PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+    };
+    instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference,
+            compatible_surface: surface,
+            force_fallback_adapter: false,
+        })
+        .await
+        .ok_or_else(|| SplooshError::Adapter("Failed to create an adapter".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use pollster::FutureExt as _;
+
+    use super::*;
+
+    #[test]
+    fn identical_layouts_are_shared() {
+        let wgpu_device = WgpuDevice::new_compute_device().block_on().unwrap();
+        let entry = |read_only| wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let layout = wgpu_device.bind_group_layout(&[entry(true)]);
+        assert!(Arc::ptr_eq(
+            &layout,
+            &wgpu_device.bind_group_layout(&[entry(true)])
+        ));
+        assert!(!Arc::ptr_eq(
+            &layout,
+            &wgpu_device.bind_group_layout(&[entry(false)])
+        ));
 
-        Rc::new(buffer)
+        let pipeline_layout = wgpu_device.pipeline_layout(&[entry(true)], &[]);
+        assert!(Arc::ptr_eq(
+            &pipeline_layout,
+            &wgpu_device.pipeline_layout(&[entry(true)], &[])
+        ));
     }
 }
@@ -1,8 +1,13 @@
-use std::{error::Error, rc::Rc, sync::Arc};
+use std::sync::Arc;
 
 use winit::window::Window;
 
-use crate::{graphics::texture::Texture, WgpuDevice};
+use crate::{
+    config::{AdapterConfig, WindowConfig},
+    graphics::{render_engine::MAX_FRAMES_IN_FLIGHT, texture::Texture},
+    wgpu_device::{create_instance, select_adapter},
+    SplooshError, WgpuDevice,
+};
 
 pub struct WgpuRenderDevice {
     pub surface: wgpu::Surface<'static>,
@@ -12,40 +17,22 @@ pub struct WgpuRenderDevice {
 }
 
 impl WgpuRenderDevice {
-    pub async fn new(window: Arc<Window>) -> Result<Self, Box<dyn Error>> {
+    #[tracing::instrument(skip_all)]
+    pub async fn new(
+        window: Arc<Window>,
+        window_config: &WindowConfig,
+        adapter_config: &AdapterConfig,
+    ) -> Result<Self, SplooshError> {
         let size = window.inner_size();
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
-            ..Default::default()
-        });
+        let instance = create_instance(adapter_config);
 
         let surface = instance.create_surface(window)?;
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .ok_or("Failed to crate an adapter")?;
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::PUSH_CONSTANTS,
-                    required_limits: wgpu::Limits {
-                        max_push_constant_size: 4,
-                        ..Default::default()
-                    },
-                    label: None,
-                    memory_hints: Default::default(),
-                },
-                None,
-            )
-            .await?;
-
-        let surface_caps = surface.get_capabilities(&adapter);
+        let adapter = select_adapter(&instance, adapter_config, Some(&surface)).await?;
+        let wgpu_device = WgpuDevice::from_adapter(adapter).await?;
+        let (adapter, device) = (&wgpu_device.adapter, &wgpu_device.device);
+
+        let surface_caps = surface.get_capabilities(adapter);
         let surface_format = surface_caps
             .formats
             .iter()
@@ -53,23 +40,30 @@ impl WgpuRenderDevice {
             .copied()
             .unwrap_or(surface_caps.formats[0]);
 
+        // copying out of the surface is needed for screenshots, but not every platform allows it
+        let usage = if surface_caps.usages.contains(wgpu::TextureUsages::COPY_SRC) {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        };
+
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage,
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Immediate,
-            desired_maximum_frame_latency: 2,
+            present_mode: window_config.present_mode(),
+            desired_maximum_frame_latency: MAX_FRAMES_IN_FLIGHT as u32,
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
         };
 
-        surface.configure(&device, &config);
-        let depth_texture = Texture::depth_texture(&device, &config);
+        surface.configure(device, &config);
+        let depth_texture = Texture::depth_texture(device, &config);
 
         Ok(Self {
             surface,
-            wgpu_device: WgpuDevice { device, queue },
+            wgpu_device,
             config,
             depth_texture,
         })
@@ -92,7 +86,23 @@ impl WgpuRenderDevice {
         }
     }
 
-    pub fn create_buffer_init<T>(&self, data: &[T], usage: wgpu::BufferUsages) -> Rc<wgpu::Buffer> {
+    /// Reconfigures the surface and tries again if it was lost or became outdated, which
+    /// happens on some platforms when the window is moved between monitors or minimized.
+    pub fn acquire_frame(&self) -> Result<wgpu::SurfaceTexture, SplooshError> {
+        match self.surface.get_current_texture() {
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(self.device(), &self.config);
+                Ok(self.surface.get_current_texture()?)
+            }
+            output => Ok(output?),
+        }
+    }
+
+    pub fn create_buffer_init<T>(
+        &self,
+        data: &[T],
+        usage: wgpu::BufferUsages,
+    ) -> Arc<wgpu::Buffer> {
         self.wgpu_device.create_buffer_init(data, usage)
     }
 }